@@ -0,0 +1,37 @@
+//! Chunking the generated Go project's files.
+
+use criterion::{Criterion, Throughput, criterion_group, criterion_main};
+
+use claudevil::bench::generate;
+use claudevil::chunker::TreeSitterChunker;
+use claudevil::config::Config;
+
+/// Files in the generated project.
+const FILES: usize = 50;
+
+fn chunking(c: &mut Criterion) {
+    let dir = tempfile::TempDir::new().expect("failed to create a scratch directory");
+    generate(dir.path(), FILES).expect("failed to generate the project");
+    let sources: Vec<String> = walkdir::WalkDir::new(dir.path())
+        .into_iter()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_file())
+        .map(|entry| std::fs::read_to_string(entry.path()).expect("failed to read a source"))
+        .collect();
+    let config = Config::load().expect("failed to load config");
+    let chunker = TreeSitterChunker::new(&config).expect("failed to load grammars");
+
+    let mut group = c.benchmark_group("chunking");
+    group.throughput(Throughput::Elements(sources.len() as u64));
+    group.bench_function("go files", |b| {
+        b.iter(|| {
+            for source in &sources {
+                chunker.chunk_file(source, "go").expect("failed to chunk");
+            }
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, chunking);
+criterion_main!(benches);
@@ -0,0 +1,44 @@
+//! Embedding one batch of chunks, at the batch size indexing starts with.
+
+use criterion::{Criterion, Throughput, criterion_group, criterion_main};
+
+use claudevil::batching::INITIAL_BATCH_SIZE;
+use claudevil::bench::generate;
+use claudevil::chunker::TreeSitterChunker;
+use claudevil::config::Config;
+use claudevil::embed::Embedder;
+
+fn embedding(c: &mut Criterion) {
+    let dir = tempfile::TempDir::new().expect("failed to create a scratch directory");
+    generate(dir.path(), 20).expect("failed to generate the project");
+    let config = Config::load().expect("failed to load config");
+    let chunker = TreeSitterChunker::new(&config).expect("failed to load grammars");
+    let mut texts = Vec::new();
+    for entry in walkdir::WalkDir::new(dir.path()) {
+        let entry = entry.expect("failed to walk the project");
+        if entry.file_type().is_file() {
+            let source = std::fs::read_to_string(entry.path()).expect("failed to read a source");
+            let chunks = chunker.chunk_file(&source, "go").expect("failed to chunk");
+            texts.extend(chunks.into_iter().map(|c| c.content));
+        }
+    }
+    texts.truncate(INITIAL_BATCH_SIZE);
+
+    let runtime = tokio::runtime::Runtime::new().expect("failed to start a runtime");
+    let embedder = Embedder::new(&config.embedding).expect("failed to load the model");
+
+    let mut group = c.benchmark_group("embedding");
+    group.sample_size(10);
+    group.throughput(Throughput::Elements(texts.len() as u64));
+    group.bench_function("batch", |b| {
+        b.iter(|| {
+            runtime
+                .block_on(embedder.embed_passages(texts.clone()))
+                .expect("failed to embed")
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, embedding);
+criterion_main!(benches);
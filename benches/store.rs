@@ -0,0 +1,80 @@
+//! Store inserts and searches at several index sizes, on synthetic vectors.
+
+use criterion::{BatchSize, BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+
+use claudevil::bench::{INDEX_SIZES, INSERT_BATCH, Vectors, synthetic_row};
+use claudevil::config::{Backend, IndexConfig};
+use claudevil::store::{ChunkRow, Filters, VectorStore};
+
+/// A local store in a scratch directory holding `size` synthetic chunks.
+async fn filled(dir: &std::path::Path, size: usize, vectors: &mut Vectors) -> VectorStore {
+    let config = IndexConfig {
+        backend: Backend::Usearch,
+        ..Default::default()
+    };
+    let store = VectorStore::new(&dir.to_string_lossy(), &config)
+        .await
+        .expect("failed to open the store");
+    let mut rows = (0..size)
+        .map(|i| synthetic_row(i, vectors.next_vector()))
+        .peekable();
+    while rows.peek().is_some() {
+        store
+            .insert(rows.by_ref().take(INSERT_BATCH).collect())
+            .await
+            .expect("failed to fill the store");
+    }
+    store
+}
+
+fn store(c: &mut Criterion) {
+    let runtime = tokio::runtime::Runtime::new().expect("failed to start a runtime");
+    let mut vectors = Vectors::new(0x2545_f491_4f6c_dd1d);
+
+    let mut insert = c.benchmark_group("insert");
+    insert.sample_size(10);
+    insert.throughput(Throughput::Elements(INSERT_BATCH as u64));
+    for &size in INDEX_SIZES {
+        let dir = tempfile::TempDir::new().expect("failed to create a scratch directory");
+        let store = runtime.block_on(filled(dir.path(), size, &mut vectors));
+        // Each batch goes under new paths, so the index grows a little per
+        // iteration rather than replacing what is there
+        let mut next = size;
+        insert.bench_with_input(BenchmarkId::from_parameter(size), &size, |b, _| {
+            b.iter_batched(
+                || {
+                    let rows: Vec<ChunkRow> = (next..next + INSERT_BATCH)
+                        .map(|i| synthetic_row(i, vectors.next_vector()))
+                        .collect();
+                    next += INSERT_BATCH;
+                    rows
+                },
+                |rows| {
+                    runtime
+                        .block_on(store.insert(rows))
+                        .expect("failed to insert")
+                },
+                BatchSize::LargeInput,
+            )
+        });
+    }
+    insert.finish();
+
+    let mut search = c.benchmark_group("search");
+    for &size in INDEX_SIZES {
+        let dir = tempfile::TempDir::new().expect("failed to create a scratch directory");
+        let store = runtime.block_on(filled(dir.path(), size, &mut vectors));
+        let query = vectors.next_vector();
+        search.bench_with_input(BenchmarkId::from_parameter(size), &size, |b, _| {
+            b.iter(|| {
+                runtime
+                    .block_on(store.search(&query, 10, Filters::default(), None))
+                    .expect("failed to search")
+            })
+        });
+    }
+    search.finish();
+}
+
+criterion_group!(benches, store);
+criterion_main!(benches);
@@ -0,0 +1,451 @@
+//! Bundling an index into a tar archive and installing one, to share an
+//! index built elsewhere.
+//!
+//! The manifest records how the index stores its vectors and text, so an
+//! import can refuse one this install would search with the wrong metric
+//! or dimensions. The usage counters and query log kept next to an index
+//! refer to its chunk keys, so they aren't bundled, and an import drops
+//! the ones the replaced index left.
+
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::{ContentStorage, IndexConfig};
+use crate::error::{Error, Result};
+use crate::querylog::QUERY_LOG_FILE;
+use crate::store::{STORE_FILES, StoredLayout};
+use crate::usage::USAGE_FILE;
+
+/// Bump when the archive layout or the store file formats change.
+const FORMAT_VERSION: u32 = 2;
+const MANIFEST_FILE: &str = "manifest.json";
+
+/// Files next to an index keyed by its chunks, removed when it is replaced.
+const CHUNK_KEYED_FILES: [&str; 2] = [USAGE_FILE, QUERY_LOG_FILE];
+
+/// Describes what an index archive contains so imports can reject
+/// incompatible bundles up front.
+#[derive(Debug, Serialize, Deserialize)]
+struct Manifest {
+    format_version: u32,
+    claudevil_version: String,
+    #[serde(flatten)]
+    layout: StoredLayout,
+}
+
+/// Bundle the store under `db_path` into a single tar archive at `archive`.
+pub fn export(db_path: &Path, archive: &Path) -> Result<()> {
+    for name in STORE_FILES {
+        if !db_path.join(name).exists() {
+            return Err(Error::Archive(format!(
+                "no index found at {} -- run claudevil on the project first so it builds an index",
+                db_path.display()
+            )));
+        }
+    }
+
+    let file = File::create(archive).map_err(|e| Error::StoreIo {
+        context: format!("creating {}", archive.display()),
+        source: e,
+    })?;
+    let mut builder = tar::Builder::new(file);
+
+    let manifest = Manifest {
+        format_version: FORMAT_VERSION,
+        claudevil_version: env!("CARGO_PKG_VERSION").to_string(),
+        layout: StoredLayout::read(db_path)?,
+    };
+    let json = serde_json::to_vec_pretty(&manifest).map_err(Error::StoreSerde)?;
+    let mut header = tar::Header::new_gnu();
+    header.set_size(json.len() as u64);
+    header.set_mode(0o644);
+    header.set_cksum();
+    builder
+        .append_data(&mut header, MANIFEST_FILE, json.as_slice())
+        .map_err(|e| archive_io(archive, e))?;
+
+    for name in STORE_FILES {
+        builder
+            .append_path_with_name(db_path.join(name), name)
+            .map_err(|e| archive_io(archive, e))?;
+    }
+
+    builder.finish().map_err(|e| archive_io(archive, e))?;
+    Ok(())
+}
+
+/// Replace the store under `db_path`, to be opened with `config`, with the
+/// contents of `archive`.
+///
+/// Every file is unpacked next to its destination first and only renamed
+/// into place once the whole archive has been read, so a bad archive never
+/// leaves a half-imported index behind. If renaming fails partway, the
+/// files already moved are put back, leaving the old index as it was.
+pub fn import(archive: &Path, db_path: &Path, config: &IndexConfig) -> Result<()> {
+    let expected = StoredLayout::of(config)?;
+    let file = File::open(archive).map_err(|e| Error::StoreIo {
+        context: format!("opening {}", archive.display()),
+        source: e,
+    })?;
+    std::fs::create_dir_all(db_path).map_err(|e| Error::StoreIo {
+        context: format!("creating {}", db_path.display()),
+        source: e,
+    })?;
+
+    let mut manifest = None;
+    let mut staged = Vec::new();
+    let mut tar = tar::Archive::new(file);
+    for entry in tar.entries().map_err(|e| archive_io(archive, e))? {
+        let mut entry = entry.map_err(|e| archive_io(archive, e))?;
+        let name = entry
+            .path()
+            .map_err(|e| archive_io(archive, e))?
+            .to_string_lossy()
+            .into_owned();
+
+        if name == MANIFEST_FILE {
+            let mut json = String::new();
+            entry
+                .read_to_string(&mut json)
+                .map_err(|e| archive_io(archive, e))?;
+            let parsed: Manifest = serde_json::from_str(&json).map_err(Error::StoreSerde)?;
+            check_manifest(&parsed, &expected)?;
+            manifest = Some(parsed);
+        } else if let Some(&known) = STORE_FILES.iter().find(|&&f| f == name) {
+            let tmp = db_path.join(format!("{known}.import"));
+            entry.unpack(&tmp).map_err(|e| archive_io(archive, e))?;
+            staged.push((tmp, db_path.join(known)));
+        } else {
+            tracing::warn!("ignoring unexpected archive entry: {name}");
+        }
+    }
+
+    let result = match manifest {
+        None => Err(Error::Archive(format!(
+            "{} is not a claudevil index archive (no {MANIFEST_FILE}) -- create one with `claudevil export`",
+            archive.display()
+        ))),
+        Some(_) if staged.len() != STORE_FILES.len() => Err(Error::Archive(format!(
+            "{} is incomplete: expected {} -- re-export the index",
+            archive.display(),
+            STORE_FILES.join(", ")
+        ))),
+        Some(_) => Ok(()),
+    };
+    if let Err(e) = result {
+        for (tmp, _) in &staged {
+            let _ = std::fs::remove_file(tmp);
+        }
+        return Err(e);
+    }
+
+    swap_in(&staged)?;
+    for name in CHUNK_KEYED_FILES {
+        let path = db_path.join(name);
+        match std::fs::remove_file(&path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                return Err(Error::StoreIo {
+                    context: format!("removing {}", path.display()),
+                    source: e,
+                });
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Rename each staged file over its destination, all or none: the files
+/// they replace are set aside first, and if any rename fails, the ones
+/// done are undone and the old files put back. The staged files are gone
+/// either way.
+fn swap_in(staged: &[(PathBuf, PathBuf)]) -> Result<()> {
+    let aside = |dest: &Path| {
+        let mut name = dest.as_os_str().to_owned();
+        name.push(".replaced");
+        PathBuf::from(name)
+    };
+    let mut set_aside = Vec::new();
+    let mut moved = Vec::new();
+    let mut swap = || -> Result<()> {
+        for (_, dest) in staged {
+            if dest.exists() {
+                std::fs::rename(dest, aside(dest)).map_err(|e| Error::StoreIo {
+                    context: format!("setting {} aside", dest.display()),
+                    source: e,
+                })?;
+                set_aside.push(dest);
+            }
+        }
+        for (tmp, dest) in staged {
+            std::fs::rename(tmp, dest).map_err(|e| Error::StoreIo {
+                context: format!("moving {} into place", dest.display()),
+                source: e,
+            })?;
+            moved.push(dest);
+        }
+        Ok(())
+    };
+    let swapped = swap();
+
+    if swapped.is_err() {
+        for dest in moved {
+            let _ = std::fs::remove_file(dest);
+        }
+        for dest in set_aside {
+            if let Err(e) = std::fs::rename(aside(dest), dest) {
+                tracing::error!(
+                    "couldn't put {} back after a failed import ({e}); it is at {}",
+                    dest.display(),
+                    aside(dest).display()
+                );
+            }
+        }
+        for (tmp, _) in staged {
+            let _ = std::fs::remove_file(tmp);
+        }
+    } else {
+        for dest in set_aside {
+            let _ = std::fs::remove_file(aside(dest));
+        }
+    }
+    swapped
+}
+
+fn check_manifest(manifest: &Manifest, expected: &StoredLayout) -> Result<()> {
+    if manifest.format_version != FORMAT_VERSION {
+        return Err(Error::Archive(format!(
+            "archive format version {} is not supported (expected {FORMAT_VERSION}) -- \
+             re-export it with claudevil {}",
+            manifest.format_version,
+            env!("CARGO_PKG_VERSION")
+        )));
+    }
+    let found = &manifest.layout;
+    if found.metric != expected.metric {
+        return Err(Error::Archive(format!(
+            "archive was built with metric {} but [index] metric is {} -- \
+             set [index] metric = \"{}\" to import it",
+            found.metric, expected.metric, found.metric
+        )));
+    }
+    if found.dimensions != expected.dimensions {
+        return Err(Error::Archive(format!(
+            "archive stores {} dimensions per vector but [index] dimensions gives {} -- \
+             set [index] dimensions = {} to import it",
+            found.dimensions, expected.dimensions, found.dimensions
+        )));
+    }
+    if found.content != expected.content {
+        let content = |c: ContentStorage| match c {
+            ContentStorage::Inline => "inline",
+            ContentStorage::Offsets => "offsets",
+        };
+        return Err(Error::Archive(format!(
+            "archive stores chunk text as \"{}\" but [index] content is \"{}\" -- \
+             set [index] content = \"{}\" to import it",
+            content(found.content),
+            content(expected.content),
+            content(found.content)
+        )));
+    }
+    Ok(())
+}
+
+fn archive_io(archive: &Path, source: std::io::Error) -> Error {
+    Error::StoreIo {
+        context: format!("processing archive {}", archive.display()),
+        source,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Metric;
+    use crate::embed::EMBEDDING_DIM;
+    use crate::store::{ChunkRow, Filters, VectorStore};
+    use tempfile::TempDir;
+
+    fn unit_vector(idx: usize) -> Vec<f32> {
+        let mut v = vec![0.0f32; EMBEDDING_DIM];
+        v[idx] = 1.0;
+        v
+    }
+
+    async fn populated_store(path: &Path) -> VectorStore {
+        let store = VectorStore::new(path.to_str().unwrap(), &Default::default())
+            .await
+            .unwrap();
+        store
+            .insert(vec![ChunkRow {
+                file_path: "main.go".to_string(),
+                content: "func main() {}".to_string(),
+                symbol_name: Some("main".to_string()),
+                symbol_kind: Some("function_declaration".to_string()),
+                language: "go".to_string(),
+                start_line: 1,
+                end_line: 1,
+                last_modified: 1700000000,
+                vector: unit_vector(0),
+                ..Default::default()
+            }])
+            .await
+            .unwrap();
+        store
+    }
+
+    fn layout() -> StoredLayout {
+        StoredLayout::of(&IndexConfig::default()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn export_then_import_round_trips() {
+        let src = TempDir::new().unwrap();
+        let dest = TempDir::new().unwrap();
+        let out = TempDir::new().unwrap();
+        let archive = out.path().join("index.tar");
+
+        populated_store(src.path()).await;
+        export(src.path(), &archive).unwrap();
+        // Counters of the replaced index would credit unrelated chunks
+        std::fs::write(
+            dest.path().join(USAGE_FILE),
+            r#"{"0":{"returned":9,"read":9}}"#,
+        )
+        .unwrap();
+        std::fs::write(dest.path().join(QUERY_LOG_FILE), "{}").unwrap();
+        import(&archive, dest.path(), &IndexConfig::default()).unwrap();
+        assert!(!dest.path().join(USAGE_FILE).exists());
+        assert!(!dest.path().join(QUERY_LOG_FILE).exists());
+
+        let store = VectorStore::new(dest.path().to_str().unwrap(), &Default::default())
+            .await
+            .unwrap();
+        assert_eq!(store.chunk_count().await.unwrap(), 1);
+        let results = store
+            .search(&unit_vector(0), 1, Filters::default(), None)
+            .await
+            .unwrap();
+        assert_eq!(results[0].content, "func main() {}");
+    }
+
+    #[test]
+    fn failed_swap_puts_the_old_files_back() {
+        let dir = TempDir::new().unwrap();
+        let path = |name: &str| dir.path().join(name);
+        for name in STORE_FILES {
+            std::fs::write(path(name), "old").unwrap();
+        }
+        std::fs::write(path("a.import"), "new").unwrap();
+        // The second staged file never made it, so its rename fails
+        let staged = vec![
+            (path("a.import"), path(STORE_FILES[0])),
+            (path("b.import"), path(STORE_FILES[1])),
+        ];
+        assert!(swap_in(&staged).is_err());
+
+        for name in STORE_FILES {
+            assert_eq!(std::fs::read_to_string(path(name)).unwrap(), "old");
+        }
+        let mut left: Vec<String> = std::fs::read_dir(dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        left.sort();
+        let mut expected = STORE_FILES.map(str::to_string).to_vec();
+        expected.sort();
+        assert_eq!(left, expected);
+    }
+
+    #[test]
+    fn export_without_index_fails() {
+        let empty = TempDir::new().unwrap();
+        let out = TempDir::new().unwrap();
+        let err = export(empty.path(), &out.path().join("index.tar")).unwrap_err();
+        assert!(err.to_string().contains("no index found"), "{err}");
+    }
+
+    #[test]
+    fn import_rejects_non_archive() {
+        let dir = TempDir::new().unwrap();
+        let bogus = dir.path().join("bogus.tar");
+        let mut builder = tar::Builder::new(File::create(&bogus).unwrap());
+        let mut header = tar::Header::new_gnu();
+        header.set_size(5);
+        header.set_cksum();
+        builder
+            .append_data(&mut header, "hello.txt", "hello".as_bytes())
+            .unwrap();
+        builder.finish().unwrap();
+
+        let dest = TempDir::new().unwrap();
+        let err = import(&bogus, dest.path(), &IndexConfig::default()).unwrap_err();
+        assert!(err.to_string().contains("not a claudevil index"), "{err}");
+        assert!(std::fs::read_dir(dest.path()).unwrap().next().is_none());
+    }
+
+    #[tokio::test]
+    async fn manifest_records_the_store_layout() {
+        let src = TempDir::new().unwrap();
+        let config = IndexConfig {
+            metric: Metric::Ip,
+            dimensions: 128,
+            ..Default::default()
+        };
+        VectorStore::new(src.path().to_str().unwrap(), &config)
+            .await
+            .unwrap()
+            .insert(vec![ChunkRow {
+                file_path: "main.go".to_string(),
+                content: "func main() {}".to_string(),
+                language: "go".to_string(),
+                start_line: 1,
+                end_line: 1,
+                vector: unit_vector(0),
+                ..Default::default()
+            }])
+            .await
+            .unwrap();
+        let archive = src.path().join("index.tar");
+        export(src.path(), &archive).unwrap();
+
+        let dest = TempDir::new().unwrap();
+        let err = import(&archive, dest.path(), &IndexConfig::default()).unwrap_err();
+        assert!(err.to_string().contains("metric = \"ip\""), "{err}");
+        let full = IndexConfig {
+            metric: Metric::Ip,
+            ..Default::default()
+        };
+        let err = import(&archive, dest.path(), &full).unwrap_err();
+        assert!(err.to_string().contains("dimensions = 128"), "{err}");
+        import(&archive, dest.path(), &config).unwrap();
+    }
+
+    #[test]
+    fn manifest_with_other_content_storage_is_rejected() {
+        let manifest = Manifest {
+            format_version: FORMAT_VERSION,
+            claudevil_version: "0.0.0".to_string(),
+            layout: StoredLayout {
+                content: ContentStorage::Offsets,
+                ..layout()
+            },
+        };
+        let err = check_manifest(&manifest, &layout()).unwrap_err();
+        assert!(err.to_string().contains("content = \"offsets\""), "{err}");
+    }
+
+    #[test]
+    fn manifest_with_future_version_is_rejected() {
+        let manifest = Manifest {
+            format_version: FORMAT_VERSION + 1,
+            claudevil_version: "99.0.0".to_string(),
+            layout: layout(),
+        };
+        assert!(check_manifest(&manifest, &layout()).is_err());
+    }
+}
@@ -0,0 +1,37 @@
+//! Files replaced whole, so a crash mid-write never leaves one truncated.
+//!
+//! The new contents go to a temporary file next to the old one, which is
+//! then renamed over it. A rename within a directory is atomic, so a
+//! reader sees either the old file or the new one, never part of either.
+
+use std::path::{Path, PathBuf};
+
+/// The temporary file `path`'s new contents are written to first.
+pub fn temp_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".tmp");
+    path.with_file_name(name)
+}
+
+/// Replace the file at `path` with `contents`.
+pub async fn write(path: &Path, contents: impl AsRef<[u8]>) -> std::io::Result<()> {
+    let temp = temp_path(path);
+    tokio::fs::write(&temp, contents).await?;
+    tokio::fs::rename(&temp, path).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn replaces_the_file_and_leaves_no_temporary() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("usage.json");
+        std::fs::write(&path, "old").unwrap();
+        write(&path, "new").await.unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "new");
+        assert!(!temp_path(&path).exists());
+    }
+}
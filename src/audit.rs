@@ -0,0 +1,354 @@
+//! An append-only log of the tool calls a server answers.
+//!
+//! Each call is one JSON line in `audit.jsonl` next to the root's index:
+//! when it ran, the tool, its parameters, whether it failed, how many
+//! results it listed, and how long it took. Long string parameters, such
+//! as pasted stack traces, are cut to `[server] audit_param_chars`, so the
+//! log stays small enough to keep. Past `[server] audit_max_bytes` it is
+//! moved to `audit.jsonl.1`, so at most two logs' worth is kept.
+//! `claudevil audit tail` prints the end of it.
+
+use std::fmt;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use serde::{Deserialize, Serialize};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+
+use crate::config::ServerConfig;
+use crate::error::{Error, Result};
+use crate::timespec;
+
+pub const AUDIT_FILE: &str = "audit.jsonl";
+
+/// The previous log, moved aside when [`AUDIT_FILE`] reached its limit.
+pub const ROTATED_AUDIT_FILE: &str = "audit.jsonl.1";
+
+/// One tool call.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditEntry {
+    /// When the call finished, in UTC (`2024-05-01T12:00:00Z`).
+    pub time: String,
+    pub tool: String,
+    /// The call's arguments, with long strings cut short.
+    #[serde(default)]
+    pub params: serde_json::Value,
+    /// The error the call failed with, if it did.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Entries the result listed: results, files, or list items.
+    #[serde(default)]
+    pub results: usize,
+    #[serde(default)]
+    pub latency_ms: u64,
+}
+
+impl AuditEntry {
+    /// An entry for a call to `tool` with `params` that took `latency`,
+    /// stamped with the current time.
+    pub fn new(
+        tool: &str,
+        params: serde_json::Value,
+        error: Option<String>,
+        results: usize,
+        latency: Duration,
+    ) -> Self {
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs() as i64;
+        Self {
+            time: timespec::format_utc(now),
+            tool: tool.to_string(),
+            params,
+            error,
+            results,
+            latency_ms: latency.as_millis() as u64,
+        }
+    }
+}
+
+impl fmt::Display for AuditEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} {}ms {} results {}",
+            self.time, self.tool, self.latency_ms, self.results, self.params
+        )?;
+        if let Some(error) = &self.error {
+            write!(f, " -- failed: {error}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Appends tool calls to one root's audit log.
+#[derive(Clone)]
+pub struct AuditLog {
+    path: PathBuf,
+    max_param_chars: usize,
+    /// Size past which the log is rotated; `None` for no limit.
+    max_bytes: Option<u64>,
+    /// Held while a line is written, so concurrent calls never interleave.
+    lock: Arc<Mutex<()>>,
+}
+
+impl AuditLog {
+    /// The log in `db_path`, or `None` if `[server] audit_log` is off or
+    /// the index is read-only, since its data directory belongs to another
+    /// server.
+    pub fn open(db_path: &Path, read_only: bool, config: &ServerConfig) -> Option<Self> {
+        (config.audit_log && !read_only).then(|| Self {
+            path: db_path.join(AUDIT_FILE),
+            max_param_chars: config.audit_param_chars,
+            max_bytes: (config.audit_max_bytes > 0).then_some(config.audit_max_bytes),
+            lock: Arc::new(Mutex::new(())),
+        })
+    }
+
+    /// Append a call to `tool` with `params`. A failed write is logged
+    /// rather than failing the call it records.
+    pub async fn record(
+        &self,
+        tool: &str,
+        params: serde_json::Value,
+        error: Option<String>,
+        results: usize,
+        latency: Duration,
+    ) {
+        let params = cap_params(params, self.max_param_chars);
+        let entry = AuditEntry::new(tool, params, error, results, latency);
+        if let Err(e) = self.append(&entry).await {
+            tracing::warn!("{e} -- tool calls are not being audited");
+        }
+    }
+
+    async fn append(&self, entry: &AuditEntry) -> Result<()> {
+        let mut line = serde_json::to_string(entry).map_err(Error::StoreSerde)?;
+        line.push('\n');
+        let io_error = |e| Error::StoreIo {
+            context: format!("appending to {}", self.path.display()),
+            source: e,
+        };
+        let _guard = self.lock.lock().await;
+        self.make_room(line.len() as u64).await?;
+        let mut file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .await
+            .map_err(io_error)?;
+        file.write_all(line.as_bytes()).await.map_err(io_error)?;
+        // tokio finishes a write in the background; without this it may
+        // not have landed when the next call reads or appends
+        file.flush().await.map_err(io_error)
+    }
+
+    /// Move the log to [`ROTATED_AUDIT_FILE`] if another `bytes` would take
+    /// it past its limit, so they start a new one.
+    async fn make_room(&self, bytes: u64) -> Result<()> {
+        let Some(max_bytes) = self.max_bytes else {
+            return Ok(());
+        };
+        let len = match tokio::fs::metadata(&self.path).await {
+            Ok(metadata) => metadata.len(),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => {
+                return Err(Error::StoreIo {
+                    context: format!("reading {}", self.path.display()),
+                    source: e,
+                });
+            }
+        };
+        if len == 0 || len + bytes <= max_bytes {
+            return Ok(());
+        }
+        let rotated = self.path.with_file_name(ROTATED_AUDIT_FILE);
+        tokio::fs::rename(&self.path, &rotated)
+            .await
+            .map_err(|e| Error::StoreIo {
+                context: format!("moving {} to {}", self.path.display(), rotated.display()),
+                source: e,
+            })
+    }
+}
+
+/// `value` with every string longer than `max_chars` cut to that many
+/// characters, noting how long it was.
+fn cap_params(value: serde_json::Value, max_chars: usize) -> serde_json::Value {
+    use serde_json::Value;
+    match value {
+        Value::String(s) => {
+            let len = s.chars().count();
+            if len <= max_chars {
+                return Value::String(s);
+            }
+            let kept: String = s.chars().take(max_chars).collect();
+            Value::String(format!("{kept}... ({len} chars)"))
+        }
+        Value::Array(items) => Value::Array(
+            items
+                .into_iter()
+                .map(|item| cap_params(item, max_chars))
+                .collect(),
+        ),
+        Value::Object(fields) => Value::Object(
+            fields
+                .into_iter()
+                .map(|(key, item)| (key, cap_params(item, max_chars)))
+                .collect(),
+        ),
+        other => other,
+    }
+}
+
+/// The last `n` calls in the audit log in `db_path`, and the rotated log
+/// before it, oldest first; none if nothing was logged yet. Lines that
+/// don't parse, such as one cut short by a crash, are skipped.
+pub fn tail(db_path: &Path, n: usize) -> Result<Vec<AuditEntry>> {
+    let mut entries = std::collections::VecDeque::with_capacity(n);
+    for name in [ROTATED_AUDIT_FILE, AUDIT_FILE] {
+        let path = db_path.join(name);
+        let file = match std::fs::File::open(&path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(e) => {
+                return Err(Error::StoreIo {
+                    context: format!("reading {}", path.display()),
+                    source: e,
+                });
+            }
+        };
+        for line in BufReader::new(file).lines() {
+            let line = line.map_err(|e| Error::StoreIo {
+                context: format!("reading {}", path.display()),
+                source: e,
+            })?;
+            let Ok(entry) = serde_json::from_str::<AuditEntry>(&line) else {
+                continue;
+            };
+            if entries.len() == n {
+                entries.pop_front();
+            }
+            if n > 0 {
+                entries.push_back(entry);
+            }
+        }
+    }
+    Ok(entries.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use tempfile::TempDir;
+
+    #[test]
+    fn long_strings_are_capped_at_any_depth() {
+        let params = json!({
+            "query": "x".repeat(10),
+            "filters": {"paths": ["short", "y".repeat(8)]},
+            "limit": 5,
+        });
+        assert_eq!(
+            cap_params(params, 6),
+            json!({
+                "query": "xxxxxx... (10 chars)",
+                "filters": {"paths": ["short", "yyyyyy... (8 chars)"]},
+                "limit": 5,
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn calls_are_appended_and_tailed() {
+        let tmp = TempDir::new().unwrap();
+        let log = AuditLog::open(tmp.path(), false, &ServerConfig::default()).unwrap();
+        for i in 0..3 {
+            log.record(
+                "search",
+                json!({ "query": format!("query {i}") }),
+                None,
+                i,
+                Duration::from_millis(12),
+            )
+            .await;
+        }
+        log.record(
+            "get_file",
+            json!({ "path": "../etc/passwd" }),
+            Some("path escapes the root".to_string()),
+            0,
+            Duration::ZERO,
+        )
+        .await;
+
+        let entries = tail(tmp.path(), 2).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].params, json!({ "query": "query 2" }));
+        assert_eq!(entries[0].results, 2);
+        assert_eq!(entries[0].latency_ms, 12);
+        assert_eq!(entries[1].tool, "get_file");
+        assert!(
+            entries[1]
+                .to_string()
+                .ends_with("-- failed: path escapes the root")
+        );
+        assert_eq!(tail(tmp.path(), 10).unwrap().len(), 4);
+    }
+
+    #[tokio::test]
+    async fn full_logs_are_rotated_and_still_tailed() {
+        let tmp = TempDir::new().unwrap();
+        let config = ServerConfig {
+            audit_max_bytes: 300,
+            ..ServerConfig::default()
+        };
+        let log = AuditLog::open(tmp.path(), false, &config).unwrap();
+        for i in 0..10 {
+            log.record("search", json!({ "query": i }), None, 0, Duration::ZERO)
+                .await;
+        }
+        let current = std::fs::metadata(tmp.path().join(AUDIT_FILE)).unwrap();
+        let rotated = std::fs::metadata(tmp.path().join(ROTATED_AUDIT_FILE)).unwrap();
+        assert!(current.len() <= 300, "{}", current.len());
+        assert!(rotated.len() <= 300, "{}", rotated.len());
+
+        // The oldest calls are gone, the rest are read across both files
+        let entries = tail(tmp.path(), 10).unwrap();
+        assert!(entries.len() < 10);
+        let queries: Vec<_> = entries.iter().map(|e| e.params["query"].clone()).collect();
+        let newest: Vec<_> = (10 - queries.len()..10).map(|i| json!(i)).collect();
+        assert_eq!(queries, newest);
+    }
+
+    #[test]
+    fn tail_skips_torn_lines_and_missing_logs() {
+        let tmp = TempDir::new().unwrap();
+        assert!(tail(tmp.path(), 5).unwrap().is_empty());
+
+        std::fs::write(
+            tmp.path().join(AUDIT_FILE),
+            "{\"time\":\"2024-01-01T00:00:00Z\",\"tool\":\"search\"}\n{\"time\":\"2024-01",
+        )
+        .unwrap();
+        let entries = tail(tmp.path(), 5).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].tool, "search");
+    }
+
+    #[test]
+    fn disabled_or_read_only_logs_nothing() {
+        let tmp = TempDir::new().unwrap();
+        let config = ServerConfig {
+            audit_log: false,
+            ..ServerConfig::default()
+        };
+        assert!(AuditLog::open(tmp.path(), false, &config).is_none());
+        assert!(AuditLog::open(tmp.path(), true, &ServerConfig::default()).is_none());
+    }
+}
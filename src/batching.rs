@@ -0,0 +1,114 @@
+//! Embedding batch sizes tuned to how fast this machine embeds.
+//!
+//! A fixed batch size is wrong everywhere but where it was picked: on a slow
+//! CPU a batch can take minutes, holding up progress and memory, while a GPU
+//! finishes a small batch before the next one is even chunked. After each
+//! flush the indexer reports how long it took, and the next batch is sized
+//! to take about `[indexing] batch_target_ms`.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+use crate::config::IndexingConfig;
+
+/// Chunks per batch before any flush has been timed, and always when
+/// tuning is off.
+pub const INITIAL_BATCH_SIZE: usize = 64;
+
+/// Picks how many chunks to embed per batch.
+pub struct BatchSizer {
+    min: usize,
+    max: usize,
+    /// `None` keeps the size fixed.
+    target: Option<Duration>,
+    size: AtomicUsize,
+}
+
+impl BatchSizer {
+    pub fn new(config: &IndexingConfig) -> Self {
+        let (min, max) = (config.min_batch, config.max_batch);
+        Self {
+            min,
+            max,
+            target: (config.batch_target_ms > 0)
+                .then(|| Duration::from_millis(config.batch_target_ms)),
+            size: AtomicUsize::new(INITIAL_BATCH_SIZE.clamp(min, max)),
+        }
+    }
+
+    /// Chunks to put in the next batch.
+    pub fn size(&self) -> usize {
+        self.size.load(Ordering::Relaxed)
+    }
+
+    /// Record that a batch of `chunks` took `elapsed` to flush, and size the
+    /// next one to take the target time. The size moves halfway there each
+    /// time, so one batch of unusually long chunks doesn't swing it.
+    pub fn observe(&self, chunks: usize, elapsed: Duration) {
+        let Some(target) = self.target else {
+            return;
+        };
+        if chunks == 0 {
+            return;
+        }
+        let per_chunk = elapsed.as_secs_f64().max(f64::EPSILON) / chunks as f64;
+        let ideal = (target.as_secs_f64() / per_chunk).min(self.max as f64) as usize;
+        let current = self.size();
+        // Rounded toward the ideal, so the size can reach the bounds
+        let halfway = if ideal > current {
+            (current + ideal).div_ceil(2)
+        } else {
+            (current + ideal) / 2
+        };
+        let next = halfway.clamp(self.min, self.max);
+        if next != current {
+            tracing::debug!(
+                "batch of {chunks} chunks took {elapsed:.1?}; next batches hold {next} chunks"
+            );
+            self.size.store(next, Ordering::Relaxed);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sizer(target_ms: u64) -> BatchSizer {
+        BatchSizer::new(&IndexingConfig {
+            batch_target_ms: target_ms,
+            min_batch: 8,
+            max_batch: 256,
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn batches_grow_and_shrink_toward_the_target_within_bounds() {
+        let sizer = sizer(1000);
+        assert_eq!(sizer.size(), INITIAL_BATCH_SIZE);
+
+        // 10ms a chunk: 100 chunks hit the target, so move halfway there
+        sizer.observe(64, Duration::from_millis(640));
+        assert_eq!(sizer.size(), 82);
+
+        // A fast machine is capped at max_batch
+        for _ in 0..20 {
+            sizer.observe(sizer.size(), Duration::from_millis(1));
+        }
+        assert_eq!(sizer.size(), 256);
+
+        // A slow one bottoms out at min_batch
+        for _ in 0..20 {
+            sizer.observe(sizer.size(), Duration::from_secs(60));
+        }
+        assert_eq!(sizer.size(), 8);
+    }
+
+    #[test]
+    fn no_target_keeps_the_size_fixed() {
+        let sizer = sizer(0);
+        sizer.observe(64, Duration::from_secs(600));
+        assert_eq!(sizer.size(), INITIAL_BATCH_SIZE);
+    }
+}
@@ -0,0 +1,317 @@
+//! A synthetic benchmark of the indexing and search paths.
+//!
+//! `claudevil bench` generates a Go project in a scratch directory, then
+//! times chunking, embedding batches, store inserts and searches at several
+//! index sizes, and a full index of the project. Running it before and after
+//! a change turns "indexing feels slower" into numbers. The criterion
+//! benchmarks under `benches/` build on the same generated project, vectors
+//! and rows, for comparing a change against a saved baseline.
+
+use std::fmt::Write as _;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::batching::INITIAL_BATCH_SIZE;
+use crate::chunker::TreeSitterChunker;
+use crate::config::{Backend, Config, ContentStorage, IndexConfig};
+use crate::embed::{EMBEDDING_DIM, Embedder};
+use crate::error::Result;
+use crate::indexer::Indexer;
+use crate::store::{ChunkRow, Filters, VectorStore};
+
+/// Functions written to each generated file.
+pub const FUNCTIONS_PER_FILE: usize = 8;
+
+/// Embedding batches timed.
+const EMBED_BATCHES: usize = 4;
+
+/// Index sizes, in chunks, that inserts and searches are timed at.
+pub const INDEX_SIZES: &[usize] = &[1_000, 10_000, 50_000];
+
+/// Rows per store insert when building the synthetic indexes.
+pub const INSERT_BATCH: usize = 1_000;
+
+/// Searches timed per index size.
+const SEARCHES: usize = 200;
+
+/// How long one step took over `count` items.
+#[derive(Debug)]
+pub struct Timing {
+    pub name: String,
+    pub count: usize,
+    pub unit: &'static str,
+    pub elapsed: Duration,
+}
+
+impl Timing {
+    fn per_second(&self) -> f64 {
+        self.count as f64 / self.elapsed.as_secs_f64().max(f64::EPSILON)
+    }
+}
+
+/// The timings of one benchmark run, in the order they were taken.
+#[derive(Debug, Default)]
+pub struct BenchReport {
+    pub timings: Vec<Timing>,
+}
+
+impl BenchReport {
+    /// Run `step` and record how long it took to get through `count` units.
+    async fn time<T>(
+        &mut self,
+        name: impl Into<String>,
+        count: usize,
+        unit: &'static str,
+        step: impl Future<Output = Result<T>>,
+    ) -> Result<T> {
+        let started = Instant::now();
+        let output = step.await?;
+        self.timings.push(Timing {
+            name: name.into(),
+            count,
+            unit,
+            elapsed: started.elapsed(),
+        });
+        Ok(output)
+    }
+
+    /// One line per timing: total time, items, and rate.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        for timing in &self.timings {
+            let _ = writeln!(
+                out,
+                "{:<24} {:>10.1?} {:>8} {:<7} {:>10.1} {}/s",
+                timing.name,
+                timing.elapsed,
+                timing.count,
+                timing.unit,
+                timing.per_second(),
+                timing.unit
+            );
+        }
+        out
+    }
+}
+
+/// Write `files` Go source files of [`FUNCTIONS_PER_FILE`] functions each
+/// under `dir`, spread over a few packages.
+pub fn generate(dir: &Path, files: usize) -> Result<()> {
+    for file in 0..files {
+        let package = format!("pkg{}", file % 10);
+        let package_dir = dir.join(&package);
+        std::fs::create_dir_all(&package_dir)?;
+
+        let mut source = format!("package {package}\n\nimport \"fmt\"\n");
+        for function in 0..FUNCTIONS_PER_FILE {
+            let _ = write!(
+                source,
+                "\n// Handle{file}x{function} validates request {function} of \
+                 file {file} and reports what it found.\n\
+                 func Handle{file}x{function}(items []string, limit int) (int, error) {{\n\
+                 \tcount := 0\n\
+                 \tfor i, item := range items {{\n\
+                 \t\tif i >= limit {{\n\
+                 \t\t\treturn count, fmt.Errorf(\"limit %d reached at %q\", limit, item)\n\
+                 \t\t}}\n\
+                 \t\tcount += len(item) * {function}\n\
+                 \t}}\n\
+                 \treturn count, nil\n\
+                 }}\n"
+            );
+        }
+        std::fs::write(package_dir.join(format!("file{file}.go")), source)?;
+    }
+    Ok(())
+}
+
+/// Deterministic pseudo-random unit vectors (xorshift), for filling large
+/// indexes without embedding anything.
+pub struct Vectors(u64);
+
+impl Vectors {
+    /// Vectors starting from `seed`, which must not be zero.
+    pub fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    pub fn next_vector(&mut self) -> Vec<f32> {
+        let mut vector: Vec<f32> = (0..EMBEDDING_DIM)
+            .map(|_| {
+                self.0 ^= self.0 << 13;
+                self.0 ^= self.0 >> 7;
+                self.0 ^= self.0 << 17;
+                (self.0 >> 40) as f32 / (1u64 << 24) as f32 - 0.5
+            })
+            .collect();
+        let norm = vector.iter().map(|x| x * x).sum::<f32>().sqrt();
+        vector.iter_mut().for_each(|x| *x /= norm);
+        vector
+    }
+}
+
+/// The `i`th chunk row of the synthetic indexes.
+pub fn synthetic_row(i: usize, vector: Vec<f32>) -> ChunkRow {
+    ChunkRow {
+        file_path: format!("pkg{}/file{}.go", i % 10, i / FUNCTIONS_PER_FILE),
+        chunk_id: (i % FUNCTIONS_PER_FILE) as i64,
+        content: format!("func Handle{i}() {{}}"),
+        symbol_name: Some(format!("Handle{i}")),
+        symbol_kind: Some("function_declaration".to_string()),
+        language: "go".to_string(),
+        start_line: 1,
+        end_line: 1,
+        vector,
+        ..Default::default()
+    }
+}
+
+/// Benchmark a generated project of `files` files, working in `work_dir`.
+/// Indexes always use the local usearch backend, with the configured HNSW
+/// parameters, so a shared Qdrant collection is never touched.
+pub async fn run(
+    config: &Config,
+    embedder: &Embedder,
+    files: usize,
+    work_dir: &Path,
+) -> Result<BenchReport> {
+    let mut report = BenchReport::default();
+    let index_config = IndexConfig {
+        backend: Backend::Usearch,
+        content: ContentStorage::Inline,
+        ..config.index.clone()
+    };
+    let project = work_dir.join("project");
+    generate(&project, files)?;
+
+    // Chunking
+    let chunker = Arc::new(TreeSitterChunker::new(config)?);
+    let mut sources = Vec::with_capacity(files);
+    for entry in walkdir::WalkDir::new(&project) {
+        let entry = entry.map_err(std::io::Error::other)?;
+        if entry.file_type().is_file() {
+            sources.push(std::fs::read_to_string(entry.path())?);
+        }
+    }
+    let chunks = report
+        .time("chunking", sources.len(), "files", async {
+            let mut chunks = Vec::new();
+            for source in &sources {
+                chunks.extend(chunker.chunk_file(source, "go")?);
+            }
+            Ok(chunks)
+        })
+        .await?;
+
+    // Embedding
+    let texts: Vec<String> = chunks.into_iter().map(|c| c.content).collect();
+    for (i, batch) in texts
+        .chunks(INITIAL_BATCH_SIZE)
+        .take(EMBED_BATCHES)
+        .enumerate()
+    {
+        report
+            .time(
+                format!("embedding batch {}", i + 1),
+                batch.len(),
+                "chunks",
+                embedder.embed_passages(batch.to_vec()),
+            )
+            .await?;
+    }
+
+    // Store inserts and searches at growing sizes
+    let mut vectors = Vectors::new(0x2545_f491_4f6c_dd1d);
+    for &size in INDEX_SIZES {
+        let dir = work_dir.join(format!("index-{size}"));
+        std::fs::create_dir_all(&dir)?;
+        let store = VectorStore::new(&dir.to_string_lossy(), &index_config).await?;
+        let rows: Vec<ChunkRow> = (0..size)
+            .map(|i| synthetic_row(i, vectors.next_vector()))
+            .collect();
+        report
+            .time(format!("insert {size}"), size, "chunks", async {
+                let mut rows = rows.into_iter().peekable();
+                while rows.peek().is_some() {
+                    store
+                        .insert(rows.by_ref().take(INSERT_BATCH).collect())
+                        .await?;
+                }
+                Ok(())
+            })
+            .await?;
+
+        let queries: Vec<Vec<f32>> = (0..SEARCHES).map(|_| vectors.next_vector()).collect();
+        report
+            .time(format!("search {size}"), SEARCHES, "queries", async {
+                for query in &queries {
+                    store.search(query, 10, Filters::default(), None).await?;
+                }
+                Ok(())
+            })
+            .await?;
+    }
+
+    // End to end
+    let dir = work_dir.join("index-project");
+    std::fs::create_dir_all(&dir)?;
+    let store = VectorStore::new(&dir.to_string_lossy(), &index_config).await?;
+    let indexer = Indexer::new(embedder.clone(), store, chunker, config.clone());
+    report
+        .time(
+            "index project",
+            files,
+            "files",
+            indexer.index_directory(&project),
+        )
+        .await?;
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn generated_files_chunk_into_their_functions() {
+        let tmp = TempDir::new().unwrap();
+        generate(tmp.path(), 3).unwrap();
+        let source = std::fs::read_to_string(tmp.path().join("pkg2/file2.go")).unwrap();
+
+        let chunker = TreeSitterChunker::new(&Config::load().unwrap()).unwrap();
+        let chunks = chunker.chunk_file(&source, "go").unwrap();
+        let functions = chunks
+            .iter()
+            .filter(|c| c.symbol_kind.as_deref() == Some("function_declaration"))
+            .count();
+        assert_eq!(functions, FUNCTIONS_PER_FILE);
+    }
+
+    #[test]
+    fn synthetic_vectors_are_unit_length_and_distinct() {
+        let mut vectors = Vectors(1);
+        let (a, b) = (vectors.next_vector(), vectors.next_vector());
+        assert_eq!(a.len(), EMBEDDING_DIM);
+        let norm = a.iter().map(|x| x * x).sum::<f32>().sqrt();
+        assert!((norm - 1.0).abs() < 1e-4);
+        assert_ne!(a, b);
+    }
+
+    #[test]
+    fn report_lists_rates() {
+        let report = BenchReport {
+            timings: vec![Timing {
+                name: "search 1000".to_string(),
+                count: 200,
+                unit: "queries",
+                elapsed: Duration::from_millis(100),
+            }],
+        };
+        let rendered = report.render();
+        assert!(rendered.starts_with("search 1000"), "{rendered}");
+        assert!(rendered.contains("2000.0 queries/s"), "{rendered}");
+    }
+}
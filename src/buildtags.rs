@@ -0,0 +1,422 @@
+//! Build constraints: Go `//go:build` lines and `_linux.go` file names, and
+//! Rust `#[cfg(...)]` attributes.
+//!
+//! When a function exists once per operating system, a search finds every
+//! copy and nothing tells them apart. Each chunk records the constraint it
+//! was written under, and the operating systems that constraint rules out,
+//! so a search can ask for one platform's implementation.
+
+/// Operating systems a search can be limited to, by their Rust names.
+pub const PLATFORMS: &[&str] = &[
+    "android", "freebsd", "ios", "linux", "macos", "netbsd", "openbsd", "windows",
+];
+
+/// Every `GOOS`, for telling OS tags and file name suffixes from others.
+const GOOS: &[&str] = &[
+    "aix",
+    "android",
+    "darwin",
+    "dragonfly",
+    "freebsd",
+    "hurd",
+    "illumos",
+    "ios",
+    "js",
+    "linux",
+    "nacl",
+    "netbsd",
+    "openbsd",
+    "plan9",
+    "solaris",
+    "wasip1",
+    "windows",
+    "zos",
+];
+
+/// Every `GOARCH`, for recognizing `_amd64.go` and `_linux_arm64.go`.
+const GOARCH: &[&str] = &[
+    "386", "amd64", "arm", "arm64", "loong64", "mips", "mips64", "mips64le", "mipsle", "ppc64",
+    "ppc64le", "riscv64", "s390x", "wasm",
+];
+
+/// The constraint a whole file is built under: its `//go:build` line and
+/// name suffix in Go, its `#![cfg(...)]` attributes in Rust.
+pub fn file_constraint(language: &str, file_name: &str, source: &str) -> Option<String> {
+    match language {
+        "go" => {
+            let line = source
+                .lines()
+                .map(str::trim)
+                .take_while(|l| !l.starts_with("package "))
+                .find_map(|l| l.strip_prefix("//go:build "))
+                .map(|expr| expr.trim().to_string());
+            combine(language, line, go_file_suffix(file_name))
+        }
+        "rust" => source
+            .lines()
+            .map(str::trim)
+            .filter(|l| !l.is_empty() && !l.starts_with("//"))
+            .map_while(|l| l.strip_prefix("#!["))
+            .filter_map(|attr| cfg_predicate(&format!("#[{attr}")).map(str::to_string))
+            .reduce(|a, b| format!("all({a}, {b})")),
+        _ => None,
+    }
+}
+
+/// The constraint implied by a Go file name: `x_linux.go` builds on Linux
+/// only, `x_windows_amd64.go` on 64-bit Windows. As in `go build`, the
+/// part before the first `_` doesn't count, so `linux.go` is unconstrained.
+fn go_file_suffix(file_name: &str) -> Option<String> {
+    let stem = file_name.split('.').next()?;
+    let (_, tail) = stem.split_once('_')?;
+    let mut parts: Vec<&str> = tail.split('_').collect();
+    if parts.last() == Some(&"test") {
+        parts.pop();
+    }
+    match parts.as_slice() {
+        [.., os, arch] if GOOS.contains(os) && GOARCH.contains(arch) => {
+            Some(format!("{os} && {arch}"))
+        }
+        [.., last] if GOOS.contains(last) || GOARCH.contains(last) => Some(last.to_string()),
+        _ => None,
+    }
+}
+
+/// The predicate of a `#[cfg(...)]` attribute, or `None` for any other.
+pub fn cfg_predicate(attribute: &str) -> Option<&str> {
+    attribute
+        .trim()
+        .strip_prefix("#[cfg(")?
+        .strip_suffix(")]")
+        .map(str::trim)
+}
+
+/// Both constraints at once, in `language`'s syntax.
+pub fn combine(language: &str, a: Option<String>, b: Option<String>) -> Option<String> {
+    match (a, b) {
+        (Some(a), Some(b)) if language == "rust" => Some(format!("all({a}, {b})")),
+        (Some(a), Some(b)) => Some(format!("({a}) && ({b})")),
+        (a, b) => a.or(b),
+    }
+}
+
+/// The [`PLATFORMS`] `constraint` can never hold on. Tags that aren't an
+/// operating system, like `amd64` or `test`, might hold anywhere, as does
+/// a constraint that doesn't parse.
+pub fn excluded_platforms(language: &str, constraint: Option<&str>) -> Vec<String> {
+    let Some(constraint) = constraint else {
+        return Vec::new();
+    };
+    PLATFORMS
+        .iter()
+        .filter(|&&platform| {
+            let holds = match language {
+                "go" => GoExpr::new(constraint, platform).parse(),
+                "rust" => CfgExpr::new(constraint, platform).parse(),
+                _ => None,
+            };
+            holds == Some(false)
+        })
+        .map(|p| p.to_string())
+        .collect()
+}
+
+// Constraints are evaluated in three-valued logic: `Some(true)` and
+// `Some(false)` on every machine running `platform`, `None` if it depends
+// on something else.
+
+fn and(a: Option<bool>, b: Option<bool>) -> Option<bool> {
+    match (a, b) {
+        (Some(false), _) | (_, Some(false)) => Some(false),
+        (Some(true), Some(true)) => Some(true),
+        _ => None,
+    }
+}
+
+fn or(a: Option<bool>, b: Option<bool>) -> Option<bool> {
+    match (a, b) {
+        (Some(true), _) | (_, Some(true)) => Some(true),
+        (Some(false), Some(false)) => Some(false),
+        _ => None,
+    }
+}
+
+/// Whether the Go tag `tag` is set on `platform`.
+fn go_tag(tag: &str, platform: &str) -> Option<bool> {
+    let goos = if platform == "macos" {
+        "darwin"
+    } else {
+        platform
+    };
+    match tag {
+        "unix" => Some(platform != "windows"),
+        // Android also satisfies `linux`, and iOS `darwin`
+        "linux" => Some(matches!(platform, "linux" | "android")),
+        "darwin" => Some(matches!(platform, "macos" | "ios")),
+        _ if GOOS.contains(&tag) => Some(tag == goos),
+        _ => None,
+    }
+}
+
+/// A recursive-descent evaluator for `//go:build` expressions.
+struct GoExpr<'a> {
+    rest: &'a str,
+    platform: &'a str,
+}
+
+impl<'a> GoExpr<'a> {
+    fn new(expr: &'a str, platform: &'a str) -> Self {
+        Self {
+            rest: expr,
+            platform,
+        }
+    }
+
+    fn parse(mut self) -> Option<bool> {
+        let value = self.or()?;
+        self.rest.trim().is_empty().then_some(value)?
+    }
+
+    fn eat(&mut self, token: &str) -> bool {
+        self.rest = self.rest.trim_start();
+        match self.rest.strip_prefix(token) {
+            Some(rest) => {
+                self.rest = rest;
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Each level returns `None` on a syntax error and `Some` of the
+    // three-valued result otherwise
+
+    fn or(&mut self) -> Option<Option<bool>> {
+        let mut value = self.and()?;
+        while self.eat("||") {
+            value = or(value, self.and()?);
+        }
+        Some(value)
+    }
+
+    fn and(&mut self) -> Option<Option<bool>> {
+        let mut value = self.not()?;
+        while self.eat("&&") {
+            value = and(value, self.not()?);
+        }
+        Some(value)
+    }
+
+    fn not(&mut self) -> Option<Option<bool>> {
+        if self.eat("!") {
+            return Some(self.not()?.map(|v| !v));
+        }
+        if self.eat("(") {
+            let value = self.or()?;
+            return self.eat(")").then_some(value);
+        }
+        self.rest = self.rest.trim_start();
+        let end = self
+            .rest
+            .find(|c: char| !(c.is_alphanumeric() || c == '_' || c == '.'))
+            .unwrap_or(self.rest.len());
+        if end == 0 {
+            return None;
+        }
+        let (tag, rest) = self.rest.split_at(end);
+        self.rest = rest;
+        Some(go_tag(tag, self.platform))
+    }
+}
+
+/// Whether the Rust cfg option `name` (with `value`, for `key = "value"`
+/// options) is set on `platform`.
+fn cfg_option(name: &str, value: Option<&str>, platform: &str) -> Option<bool> {
+    let family = if platform == "windows" {
+        "windows"
+    } else {
+        "unix"
+    };
+    match (name, value) {
+        ("unix" | "windows", None) => Some(name == family),
+        ("target_family", Some(value)) if matches!(value, "unix" | "windows") => {
+            Some(value == family)
+        }
+        ("target_os", Some(value)) => Some(value == platform),
+        ("target_vendor", Some("apple")) => Some(matches!(platform, "macos" | "ios")),
+        _ => None,
+    }
+}
+
+/// A recursive-descent evaluator for `cfg(...)` predicates.
+struct CfgExpr<'a> {
+    rest: &'a str,
+    platform: &'a str,
+}
+
+impl<'a> CfgExpr<'a> {
+    fn new(predicate: &'a str, platform: &'a str) -> Self {
+        Self {
+            rest: predicate,
+            platform,
+        }
+    }
+
+    fn parse(mut self) -> Option<bool> {
+        let value = self.predicate()?;
+        self.rest.trim().is_empty().then_some(value)?
+    }
+
+    fn eat(&mut self, token: &str) -> bool {
+        self.rest = self.rest.trim_start();
+        match self.rest.strip_prefix(token) {
+            Some(rest) => {
+                self.rest = rest;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn ident(&mut self) -> Option<&'a str> {
+        self.rest = self.rest.trim_start();
+        let end = self
+            .rest
+            .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+            .unwrap_or(self.rest.len());
+        let (ident, rest) = self.rest.split_at(end);
+        self.rest = rest;
+        (!ident.is_empty()).then_some(ident)
+    }
+
+    /// The comma-separated predicates of `all(...)` or `any(...)`, folded
+    /// with `combine` from `start`.
+    fn list(
+        &mut self,
+        start: Option<bool>,
+        combine: fn(Option<bool>, Option<bool>) -> Option<bool>,
+    ) -> Option<Option<bool>> {
+        let mut value = start;
+        if !self.eat("(") {
+            return None;
+        }
+        while !self.eat(")") {
+            value = combine(value, self.predicate()?);
+            if !self.eat(",") && !self.rest.trim_start().starts_with(')') {
+                return None;
+            }
+        }
+        Some(value)
+    }
+
+    fn predicate(&mut self) -> Option<Option<bool>> {
+        match self.ident()? {
+            "all" => self.list(Some(true), and),
+            "any" => self.list(Some(false), or),
+            "not" => {
+                let value = self.eat("(").then(|| self.predicate())??;
+                self.eat(")").then_some(value.map(|v| !v))
+            }
+            name if self.eat("=") => {
+                let rest = self.rest.trim_start().strip_prefix('"')?;
+                let (value, rest) = rest.split_once('"')?;
+                self.rest = rest;
+                Some(cfg_option(name, Some(value), self.platform))
+            }
+            name => Some(cfg_option(name, None, self.platform)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn go_constraints_come_from_build_lines_and_file_names() {
+        let source = "// Copyright\n\n//go:build linux && !arm\n\npackage net\n";
+        assert_eq!(
+            file_constraint("go", "poll.go", source).as_deref(),
+            Some("linux && !arm")
+        );
+        assert_eq!(
+            file_constraint("go", "fd_windows.go", "package net\n").as_deref(),
+            Some("windows")
+        );
+        assert_eq!(
+            file_constraint("go", "fd_darwin_arm64_test.go", source).as_deref(),
+            Some("(linux && !arm) && (darwin && arm64)")
+        );
+        assert_eq!(file_constraint("go", "linux.go", "package x\n"), None);
+        assert_eq!(
+            file_constraint("go", "user_handler.go", "package x\n"),
+            None
+        );
+        // A directive after the package clause isn't a constraint
+        assert_eq!(
+            file_constraint("go", "x.go", "package x\n//go:build linux\n"),
+            None
+        );
+    }
+
+    #[test]
+    fn rust_constraints_come_from_inner_cfg_attributes() {
+        let source = "//! Unix sockets.\n#![cfg(unix)]\n#![allow(dead_code)]\n\nuse std::os;\n";
+        assert_eq!(
+            file_constraint("rust", "sock.rs", source).as_deref(),
+            Some("unix")
+        );
+        assert_eq!(file_constraint("rust", "lib.rs", "fn main() {}\n"), None);
+        assert_eq!(
+            cfg_predicate("#[cfg(target_os = \"linux\")]"),
+            Some("target_os = \"linux\"")
+        );
+        assert_eq!(cfg_predicate("#[derive(Debug)]"), None);
+    }
+
+    #[test]
+    fn go_expressions_exclude_other_platforms() {
+        let excluded = |expr| excluded_platforms("go", Some(expr));
+        assert_eq!(
+            excluded("windows"),
+            [
+                "android", "freebsd", "ios", "linux", "macos", "netbsd", "openbsd"
+            ]
+        );
+        assert_eq!(excluded("unix"), ["windows"]);
+        assert_eq!(
+            excluded("darwin && !ios"),
+            [
+                "android", "freebsd", "ios", "linux", "netbsd", "openbsd", "windows"
+            ]
+        );
+        assert_eq!(excluded("!windows && (amd64 || arm64)"), ["windows"]);
+        // Tags that aren't operating systems rule nothing out
+        assert!(excluded("integration").is_empty());
+        assert!(excluded("linux ||").is_empty());
+        assert!(excluded_platforms("go", None).is_empty());
+    }
+
+    #[test]
+    fn rust_predicates_exclude_other_platforms() {
+        let excluded = |predicate| excluded_platforms("rust", Some(predicate));
+        assert_eq!(
+            excluded("windows"),
+            [
+                "android", "freebsd", "ios", "linux", "macos", "netbsd", "openbsd"
+            ]
+        );
+        assert_eq!(excluded("not(windows)"), ["windows"]);
+        assert_eq!(
+            excluded("any(target_os = \"linux\", target_os = \"android\")"),
+            ["freebsd", "ios", "macos", "netbsd", "openbsd", "windows"]
+        );
+        assert_eq!(
+            excluded("all(unix, not(target_vendor = \"apple\"))"),
+            ["ios", "macos", "windows"]
+        );
+        assert!(excluded("test").is_empty());
+        assert!(excluded("all(test, feature = \"x\")").is_empty());
+        assert!(excluded("all(unix").is_empty());
+    }
+}
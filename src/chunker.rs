@@ -1,60 +1,181 @@
+//! Splitting source files into chunks along the language's own boundaries.
+//!
+//! Tree-sitter grammars find functions, types, classes and the other node
+//! kinds each language is configured to chunk on; HCL, Protobuf, and Thrift
+//! get block scanners, Jupyter notebooks are split into cells, and files no
+//! grammar handles can be split into windows of lines.
+
+mod dynamic;
+mod hcl;
+mod idl;
+mod notebook;
+
+use std::borrow::Cow;
 use std::collections::HashSet;
+use std::ops::Range;
+use std::path::PathBuf;
 
-use tree_sitter::{Language, Node, Parser};
+use tree_sitter::{Language, Node, Parser, Tree};
 
-use crate::config::Config;
+use crate::buildtags::cfg_predicate;
+use crate::config::{Config, LangConfig, default_imports};
 use crate::error::{Error, Result};
 
+/// Language of files no configured language handles, indexed as windows
+/// of lines under `[indexing] fallback_chunking`.
+pub const FALLBACK_LANGUAGE: &str = "unknown";
+
 /// A contiguous chunk of source code with metadata.
 #[derive(Debug, Clone)]
 pub struct Chunk {
     pub content: String,
+    /// Bytes of the file the chunk covers, from its attached comments to
+    /// its end. Overlap lines and a split container's header are part of
+    /// `content` but not of this range. `None` when the text isn't in the
+    /// file as is, like a notebook cell's, which is escaped in JSON.
+    pub byte_range: Option<Range<usize>>,
     pub symbol_name: Option<String>,
     pub symbol_kind: Option<String>,
+    /// Doc comment or docstring, without comment markers.
+    pub doc: Option<String>,
+    /// The declaration without its body, e.g. `func Open(path string) error`.
+    pub signature: Option<String>,
+    /// Build constraint on the item itself, e.g. Rust's `#[cfg(unix)]` as
+    /// `unix`; file-wide constraints are added by the indexer.
+    pub build: Option<String>,
     pub start_line: usize, // 1-indexed
     pub end_line: usize,   // 1-indexed, inclusive
+    /// Share of the chunk's non-whitespace text that is comments or
+    /// docstrings, from 0.0 (all code) to 1.0 (all prose).
+    pub comment_ratio: f32,
+    /// Language of the chunk when it isn't the file's, e.g. `python` for
+    /// a notebook's code cells.
+    pub language: Option<String>,
+}
+
+/// A file's import statements, as [`TreeSitterChunker::imports`] finds
+/// them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Imports {
+    /// The statements' text, one after another in source order.
+    pub text: String,
+    pub start_line: usize, // 1-indexed, of the first statement
+    pub end_line: usize,   // 1-indexed, inclusive, of the last
+}
+
+/// How a language is parsed.
+enum Grammar {
+    TreeSitter(Language),
+    /// The built-in HCL block scanner (see [`hcl`]).
+    Hcl,
+    /// The built-in Protobuf and Thrift declaration scanner (see [`idl`]).
+    Idl,
+    /// The built-in Jupyter notebook cell splitter (see [`notebook`]).
+    Notebook,
 }
 
 /// A loaded language grammar with its chunking configuration.
 struct LoadedLanguage {
-    language: Language,
+    grammar: Grammar,
     chunk_on: HashSet<String>,
+    split_children: HashSet<String>,
+    skip_nested: bool,
+    overlap_lines: usize,
+    /// Top-level node kinds holding imports.
+    imports: HashSet<String>,
+}
+
+/// An enclosing container (e.g. a Rust `impl` block) that was split into
+/// per-child chunks.
+struct SplitScope {
+    /// Container text up to its body, e.g. `impl Display for Foo`.
+    header: String,
+    /// Name used to qualify child symbols, e.g. `Foo`.
+    qualifier: Option<String>,
 }
 
-/// Tree-sitter based chunker using natively compiled grammars.
+/// Tree-sitter based chunker using compiled-in grammars and grammars
+/// loaded from shared libraries.
 pub struct TreeSitterChunker {
     languages: Vec<(String, LoadedLanguage)>,
+    /// Lines per chunk for [`FALLBACK_LANGUAGE`] files.
+    fallback_lines: usize,
+    /// Keeps dynamically loaded grammars mapped. Declared after `languages`
+    /// so the languages are dropped first.
+    _libraries: Vec<libloading::Library>,
 }
 
 impl TreeSitterChunker {
     /// Create a chunker with all configured languages loaded.
     pub fn new(config: &Config) -> Result<Self> {
         let mut languages = Vec::new();
+        let mut libraries = Vec::new();
+        let search_paths = config.grammar_search_paths();
 
         for (name, lang_config) in &config.lang {
-            let language = native_language(&lang_config.grammar).ok_or_else(|| {
-                Error::Config(format!(
-                    "unknown grammar '{}' for language '{name}' -- \
-                     only built-in grammars are supported: \
-                     tree-sitter-go, tree-sitter-rust, tree-sitter-python",
-                    lang_config.grammar
-                ))
-            })?;
+            let grammar = match builtin_grammar(&lang_config.grammar) {
+                Some(grammar) => grammar,
+                None => {
+                    let loaded = load_dynamic(lang_config, &search_paths)
+                        .map_err(|e| Error::Config(format!("language '{name}': {e}")))?;
+                    libraries.push(loaded.library);
+                    Grammar::TreeSitter(loaded.language)
+                }
+            };
 
             let chunk_on: HashSet<String> = lang_config
                 .chunk_on
                 .as_ref()
                 .map(|v| v.iter().cloned().collect())
                 .unwrap_or_default();
-
-            languages.push((name.clone(), LoadedLanguage { language, chunk_on }));
+            let split_children: HashSet<String> =
+                lang_config.split_children.iter().cloned().collect();
+            let imports: HashSet<String> = lang_config
+                .imports
+                .clone()
+                .unwrap_or_else(|| default_imports(name))
+                .into_iter()
+                .collect();
+
+            languages.push((
+                name.clone(),
+                LoadedLanguage {
+                    grammar,
+                    chunk_on,
+                    split_children,
+                    skip_nested: lang_config.skip_nested.unwrap_or(false),
+                    overlap_lines: lang_config.overlap_lines,
+                    imports,
+                },
+            ));
         }
 
-        Ok(Self { languages })
+        Ok(Self {
+            languages,
+            fallback_lines: config.indexing.fallback_lines,
+            _libraries: libraries,
+        })
     }
 
-    /// Chunk source code for a given language.
+    /// Chunk source code for a given language. [`FALLBACK_LANGUAGE`] files
+    /// are split into windows of lines.
+    ///
+    /// Chunks are cut from the source without its byte-order mark and with
+    /// CRLF line endings as LF, so no chunk text carries a stray `\r`;
+    /// their byte ranges still point into `source` as given.
     pub fn chunk_file(&self, source: &str, lang_name: &str) -> Result<Vec<Chunk>> {
+        let normalized = Normalized::new(source);
+        let mut chunks = self.chunk_normalized(&normalized.text, lang_name)?;
+        for range in chunks.iter_mut().filter_map(|c| c.byte_range.as_mut()) {
+            *range = normalized.source_offset(range.start)..normalized.source_offset(range.end);
+        }
+        Ok(chunks)
+    }
+
+    fn chunk_normalized(&self, source: &str, lang_name: &str) -> Result<Vec<Chunk>> {
+        if lang_name == FALLBACK_LANGUAGE {
+            return Ok(chunk_lines(source, self.fallback_lines));
+        }
         let loaded = self
             .languages
             .iter()
@@ -64,76 +185,528 @@ impl TreeSitterChunker {
                 Error::TreeSitter(format!("no grammar loaded for language '{lang_name}'"))
             })?;
 
-        let mut parser = Parser::new();
-        parser
-            .set_language(&loaded.language)
-            .map_err(|e| Error::TreeSitter(format!("set_language failed: {e}")))?;
-
-        let tree = parser
-            .parse(source, None)
-            .ok_or_else(|| Error::TreeSitter("parsing returned no tree".to_string()))?;
+        let language = match &loaded.grammar {
+            Grammar::TreeSitter(language) => language,
+            Grammar::Hcl => {
+                return Ok(hcl::chunk_hcl(
+                    source,
+                    &loaded.chunk_on,
+                    loaded.overlap_lines,
+                ));
+            }
+            Grammar::Idl => return Ok(idl::chunk_idl(source, &loaded.chunk_on)),
+            Grammar::Notebook => return notebook::chunk_notebook(source, &loaded.chunk_on),
+        };
 
+        let tree = parse(language, source)?;
         let source_bytes = source.as_bytes();
         let mut chunks = Vec::new();
         collect_chunks(
             tree.root_node(),
             source_bytes,
-            &loaded.chunk_on,
+            loaded,
             lang_name,
+            None,
             &mut chunks,
         );
 
         Ok(chunks)
     }
+
+    /// The import statements at the top level of `source`, if its language
+    /// has `imports` node kinds and it has any. Like chunks, they are
+    /// taken from the source without a byte-order mark or CRLF endings.
+    pub fn imports(&self, source: &str, lang_name: &str) -> Result<Option<Imports>> {
+        let Some((_, loaded)) = self.languages.iter().find(|(name, _)| name == lang_name) else {
+            return Ok(None);
+        };
+        let Grammar::TreeSitter(language) = &loaded.grammar else {
+            return Ok(None);
+        };
+        if loaded.imports.is_empty() {
+            return Ok(None);
+        }
+
+        let normalized = Normalized::new(source);
+        let source = normalized.text.as_ref();
+        let tree = parse(language, source)?;
+        let root = tree.root_node();
+        let mut cursor = root.walk();
+        let statements: Vec<Node<'_>> = root
+            .children(&mut cursor)
+            .filter(|node| loaded.imports.contains(node.kind()))
+            .collect();
+        let (Some(first), Some(last)) = (statements.first(), statements.last()) else {
+            return Ok(None);
+        };
+        Ok(Some(Imports {
+            text: statements
+                .iter()
+                .map(|node| node.utf8_text(source.as_bytes()).unwrap_or("").trim_end())
+                .collect::<Vec<_>>()
+                .join("\n"),
+            start_line: first.start_position().row + 1,
+            end_line: last_line(*last),
+        }))
+    }
 }
 
-/// Map a grammar name to its natively compiled Language.
-fn native_language(grammar: &str) -> Option<Language> {
-    match grammar {
-        "tree-sitter-go" => Some(tree_sitter_go::LANGUAGE.into()),
-        "tree-sitter-rust" => Some(tree_sitter_rust::LANGUAGE.into()),
-        "tree-sitter-python" => Some(tree_sitter_python::LANGUAGE.into()),
-        _ => None,
+/// Parse `source` with `language`.
+fn parse(language: &Language, source: &str) -> Result<Tree> {
+    let mut parser = Parser::new();
+    parser
+        .set_language(language)
+        .map_err(|e| Error::TreeSitter(format!("set_language failed: {e}")))?;
+    parser
+        .parse(source, None)
+        .ok_or_else(|| Error::TreeSitter("parsing returned no tree".to_string()))
+}
+
+/// Source text as chunked: without a leading byte-order mark, and with
+/// CRLF line endings turned into LF.
+struct Normalized<'a> {
+    text: Cow<'a, str>,
+    /// Bytes of byte-order mark dropped from the front.
+    bom: usize,
+    /// Offsets in `text` of the newlines that lost their `\r`, ascending.
+    stripped_cr: Vec<usize>,
+}
+
+impl<'a> Normalized<'a> {
+    fn new(source: &'a str) -> Self {
+        let (text, bom) = match source.strip_prefix('\u{feff}') {
+            Some(rest) => (rest, source.len() - rest.len()),
+            None => (source, 0),
+        };
+        if !text.contains("\r\n") {
+            return Self {
+                text: Cow::Borrowed(text),
+                bom,
+                stripped_cr: Vec::new(),
+            };
+        }
+        let mut normalized = String::with_capacity(text.len());
+        let mut stripped_cr = Vec::new();
+        let mut rest = text;
+        while let Some(at) = rest.find("\r\n") {
+            normalized.push_str(&rest[..at]);
+            stripped_cr.push(normalized.len());
+            normalized.push('\n');
+            rest = &rest[at + 2..];
+        }
+        normalized.push_str(rest);
+        Self {
+            text: Cow::Owned(normalized),
+            bom,
+            stripped_cr,
+        }
     }
+
+    /// The offset in the original source of `offset` in the normalized
+    /// text. A range ending just before a newline ends before its `\r`.
+    fn source_offset(&self, offset: usize) -> usize {
+        offset + self.bom + self.stripped_cr.partition_point(|&cr| cr < offset)
+    }
+}
+
+/// Load a grammar that isn't compiled in from a shared library.
+fn load_dynamic(
+    lang_config: &LangConfig,
+    search_paths: &[PathBuf],
+) -> Result<dynamic::DynamicGrammar> {
+    let path = dynamic::resolve(&lang_config.grammar, search_paths)?;
+    let symbol = lang_config
+        .grammar_symbol
+        .clone()
+        .unwrap_or_else(|| dynamic::entry_symbol(&path));
+    let grammar = dynamic::load(&path, &symbol)?;
+    tracing::info!(
+        "loaded grammar {} from {}",
+        lang_config.grammar,
+        path.display()
+    );
+    Ok(grammar)
+}
+
+/// Map a grammar name to a natively compiled Language or built-in scanner.
+fn builtin_grammar(grammar: &str) -> Option<Grammar> {
+    let language: Language = match grammar {
+        "tree-sitter-go" => tree_sitter_go::LANGUAGE.into(),
+        "tree-sitter-rust" => tree_sitter_rust::LANGUAGE.into(),
+        "tree-sitter-python" => tree_sitter_python::LANGUAGE.into(),
+        "tree-sitter-bash" => tree_sitter_bash::LANGUAGE.into(),
+        "tree-sitter-yaml" => tree_sitter_yaml::LANGUAGE.into(),
+        #[cfg(feature = "ruby")]
+        "tree-sitter-ruby" => tree_sitter_ruby::LANGUAGE.into(),
+        #[cfg(feature = "php")]
+        "tree-sitter-php" => tree_sitter_php::LANGUAGE_PHP.into(),
+        #[cfg(feature = "swift")]
+        "tree-sitter-swift" => tree_sitter_swift::LANGUAGE.into(),
+        "builtin-hcl" => return Some(Grammar::Hcl),
+        "builtin-idl" => return Some(Grammar::Idl),
+        "builtin-ipynb" => return Some(Grammar::Notebook),
+        _ => return None,
+    };
+    Some(Grammar::TreeSitter(language))
 }
 
 /// Recursively walk the AST and extract chunks for matching node kinds.
 fn collect_chunks(
     node: Node<'_>,
     source: &[u8],
-    chunk_on: &HashSet<String>,
+    loaded: &LoadedLanguage,
     lang_name: &str,
+    scope: Option<&SplitScope>,
     chunks: &mut Vec<Chunk>,
 ) {
-    if chunk_on.contains(node.kind()) {
-        let content = node.utf8_text(source).unwrap_or("").to_string();
+    // Split containers emit their children instead of one enormous chunk
+    if loaded.split_children.contains(node.kind()) && has_chunk_descendant(node, loaded) {
+        let split = SplitScope {
+            header: container_header(node, source),
+            qualifier: container_qualifier(node, source, lang_name),
+        };
+        let first = chunks.len();
+        let mut cursor = node.walk();
+        for child in node.children(&mut cursor) {
+            collect_chunks(child, source, loaded, lang_name, Some(&split), chunks);
+        }
+        if let Some(rest) = container_remainder(node, source, lang_name, &split, &chunks[first..]) {
+            chunks.insert(first, rest);
+        }
+        return;
+    }
 
-        // Prepend doc comments from preceding siblings
-        let content = prepend_comments(node, source, &content);
+    // A Kubernetes manifest is one object; keep it whole rather than
+    // splitting it into apiVersion/kind/metadata/spec keys
+    if lang_name == "yaml"
+        && node.kind() == "document"
+        && let Some(name) = k8s_object_name(node, source)
+    {
+        chunks.push(Chunk {
+            content: node.utf8_text(source).unwrap_or("").trim_end().to_string(),
+            byte_range: Some(trimmed_range(source, node.start_byte(), node.end_byte())),
+            symbol_name: Some(name),
+            symbol_kind: Some("document".to_string()),
+            doc: None,
+            signature: None,
+            build: None,
+            start_line: node.start_position().row + 1,
+            end_line: last_line(node),
+            comment_ratio: comment_ratio(node, node.start_byte(), source),
+            language: None,
+        });
+        return;
+    }
 
-        let symbol_name = extract_symbol_name(node, source, lang_name);
+    // An OpenAPI document is chunked by path and schema, which are what
+    // its top-level keys hold
+    if lang_name == "yaml"
+        && node.kind() == "document"
+        && let Some(items) = openapi_items(node, source)
+    {
+        for item in items {
+            chunks.push(Chunk {
+                content: item
+                    .node
+                    .utf8_text(source)
+                    .unwrap_or("")
+                    .trim_end()
+                    .to_string(),
+                byte_range: Some(trimmed_range(
+                    source,
+                    item.node.start_byte(),
+                    item.node.end_byte(),
+                )),
+                symbol_name: Some(item.name),
+                symbol_kind: Some(item.kind.to_string()),
+                doc: item.doc,
+                signature: item.signature,
+                build: None,
+                start_line: item.node.start_position().row + 1,
+                end_line: last_line(item.node),
+                comment_ratio: comment_ratio(item.node, item.node.start_byte(), source),
+                language: None,
+            });
+        }
+        return;
+    }
+
+    // Named nodes only: Ruby's `class` keyword token is also kind "class"
+    if node.is_named() && loaded.chunk_on.contains(node.kind()) {
+        let content = node.utf8_text(source).unwrap_or("").trim_end().to_string();
+        let doc = extract_doc(node, source, lang_name);
+        let signature = extract_signature(node, source);
+        let build = extract_cfg(node, source, lang_name);
+
+        // Prepend doc comments from preceding siblings, then any overlap
+        let (mut content, first_byte) = prepend_comments(node, source, &content);
+        let comment_ratio = comment_ratio(node, first_byte, source);
+        if loaded.overlap_lines > 0 {
+            let overlap = preceding_lines(source, first_byte, loaded.overlap_lines);
+            content = format!("{overlap}{content}");
+        }
+
+        let mut symbol_name = extract_symbol_name(node, source, lang_name);
         let start_line = node.start_position().row + 1;
-        let end_line = node.end_position().row + 1;
+        let end_line = last_line(node);
+
+        // Every chunk inside the container, nested ones too, names it
+        if let Some(split) = scope {
+            content = format!("{}\n{content}", split.header);
+            if let (Some(qualifier), Some(name)) = (&split.qualifier, &symbol_name) {
+                symbol_name = Some(format!("{qualifier}::{name}"));
+            }
+        }
 
         chunks.push(Chunk {
             content,
+            byte_range: Some(trimmed_range(source, first_byte, node.end_byte())),
             symbol_name,
             symbol_kind: Some(node.kind().to_string()),
+            doc,
+            signature,
+            build,
             start_line,
             end_line,
+            comment_ratio,
+            language: None,
         });
+
+        if loaded.skip_nested {
+            return;
+        }
     }
 
     // Recurse into children (nested matches produce separate chunks)
     let mut cursor = node.walk();
     for child in node.children(&mut cursor) {
-        collect_chunks(child, source, chunk_on, lang_name, chunks);
+        collect_chunks(child, source, loaded, lang_name, scope, chunks);
+    }
+}
+
+/// Split `source` into chunks of `window` lines each, dropping blank ones,
+/// for files no grammar can parse.
+fn chunk_lines(source: &str, window: usize) -> Vec<Chunk> {
+    let mut line_starts = vec![0];
+    line_starts.extend(source.split_inclusive('\n').scan(0, |end, line| {
+        *end += line.len();
+        Some(*end)
+    }));
+    let lines = line_starts.len() - 1;
+
+    (0..lines)
+        .step_by(window.max(1))
+        .filter_map(|first| {
+            let last = (first + window).min(lines);
+            let byte_range =
+                trimmed_range(source.as_bytes(), line_starts[first], line_starts[last]);
+            let content = &source[byte_range.clone()];
+            if content.trim().is_empty() {
+                return None;
+            }
+            Some(Chunk {
+                content: content.to_string(),
+                byte_range: Some(byte_range),
+                symbol_name: None,
+                symbol_kind: None,
+                doc: None,
+                signature: None,
+                build: None,
+                start_line: first + 1,
+                end_line: first + content.lines().count(),
+                comment_ratio: 0.0,
+                language: None,
+            })
+        })
+        .collect()
+}
+
+/// `start..end` without the trailing whitespace of `source[start..end]`.
+fn trimmed_range(source: &[u8], start: usize, end: usize) -> Range<usize> {
+    start..start + source[start..end].trim_ascii_end().len()
+}
+
+/// 1-indexed last line of `node`. Some grammars (YAML) end a node at column
+/// 0 of the following line; that line isn't part of the chunk.
+fn last_line(node: Node<'_>) -> usize {
+    let end = node.end_position();
+    if end.column == 0 && end.row > node.start_position().row {
+        end.row
+    } else {
+        end.row + 1
+    }
+}
+
+/// Whether any descendant of `node` would be extracted as a chunk.
+fn has_chunk_descendant(node: Node<'_>, loaded: &LoadedLanguage) -> bool {
+    let mut cursor = node.walk();
+    node.children(&mut cursor)
+        .any(|child| loaded.chunk_on.contains(child.kind()) || has_chunk_descendant(child, loaded))
+}
+
+/// Text of a container node up to (not including) its body.
+fn container_header(node: Node<'_>, source: &[u8]) -> String {
+    let end = node
+        .child_by_field_name("body")
+        .map_or(node.end_byte(), |body| body.start_byte());
+    String::from_utf8_lossy(&source[node.start_byte()..end])
+        .trim_end()
+        .to_string()
+}
+
+/// What the chunks of split container `node`'s children leave out, as one
+/// chunk: its header and members not chunked on their own, such as
+/// fields, constants, associated types, and comments not attached to a
+/// child. `None` when that is only the header and closing delimiters.
+/// Pieced together from around the children, so it has no byte range.
+fn container_remainder(
+    node: Node<'_>,
+    source: &[u8],
+    lang_name: &str,
+    split: &SplitScope,
+    children: &[Chunk],
+) -> Option<Chunk> {
+    let mut covered: Vec<Range<usize>> = children
+        .iter()
+        .filter_map(|c| c.byte_range.clone())
+        .collect();
+    covered.sort_by_key(|range| range.start);
+    let is_covered = |n: Node<'_>| {
+        covered
+            .iter()
+            .any(|r| r.start <= n.start_byte() && n.end_byte() <= r.end)
+    };
+
+    let body = node.child_by_field_name("body")?;
+    let mut cursor = body.walk();
+    let members = body
+        .named_children(&mut cursor)
+        .any(|member| !is_covered(member));
+    if !members {
+        return None;
+    }
+
+    let (_, first_byte) = prepend_comments(node, source, "");
+    let mut pieces = Vec::new();
+    let mut at = first_byte;
+    for range in &covered {
+        if range.start > at {
+            pieces.push(at..range.start);
+        }
+        at = at.max(range.end);
     }
+    if at < node.end_byte() {
+        pieces.push(at..node.end_byte());
+    }
+    let text = |range: &Range<usize>| String::from_utf8_lossy(&source[range.clone()]);
+    let content = pieces
+        .iter()
+        .map(|piece| {
+            let text = text(piece);
+            let text = text.trim_end();
+            // From the first line with anything on it
+            let first = text.find(|c: char| !c.is_whitespace()).unwrap_or(0);
+            let start = text[..first].rfind('\n').map_or(0, |i| i + 1);
+            text[start..].to_string()
+        })
+        .filter(|piece| !piece.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n");
+    let total: usize = pieces
+        .iter()
+        .map(|piece| non_whitespace(&source[piece.clone()]))
+        .sum();
+    let comments = uncovered_comment_bytes(node, source, &is_covered);
+
+    Some(Chunk {
+        content,
+        byte_range: None,
+        symbol_name: split.qualifier.clone(),
+        symbol_kind: Some(node.kind().to_string()),
+        doc: extract_doc(node, source, lang_name),
+        signature: extract_signature(node, source),
+        build: extract_cfg(node, source, lang_name),
+        start_line: node.start_position().row + 1,
+        end_line: last_line(node),
+        comment_ratio: if total == 0 {
+            0.0
+        } else {
+            comments as f32 / total as f32
+        },
+        language: None,
+    })
+}
+
+/// Non-whitespace bytes inside comments under `node` that `is_covered`
+/// doesn't rule out.
+fn uncovered_comment_bytes(
+    node: Node<'_>,
+    source: &[u8],
+    is_covered: &dyn Fn(Node<'_>) -> bool,
+) -> usize {
+    if is_covered(node) {
+        return 0;
+    }
+    if node.kind().contains("comment") {
+        return non_whitespace(&source[node.start_byte()..node.end_byte()]);
+    }
+    let mut cursor = node.walk();
+    node.children(&mut cursor)
+        .map(|child| uncovered_comment_bytes(child, source, is_covered))
+        .sum()
+}
+
+/// Name used to qualify symbols inside a split container.
+///
+/// Rust impls are qualified by their implementing type (`Foo`, not
+/// `Display for Foo`); everything else uses the container's own name.
+fn container_qualifier(node: Node<'_>, source: &[u8], lang_name: &str) -> Option<String> {
+    if lang_name == "rust"
+        && node.kind() == "impl_item"
+        && let Some(type_node) = node.child_by_field_name("type")
+    {
+        return type_node.utf8_text(source).ok().map(|s| s.to_string());
+    }
+    extract_symbol_name(node, source, lang_name)
+}
+
+/// Comment share of `source[first_byte..]` up to the end of `node`, where
+/// everything before `node` is its attached comments.
+fn comment_ratio(node: Node<'_>, first_byte: usize, source: &[u8]) -> f32 {
+    let total = non_whitespace(&source[first_byte..node.end_byte()]);
+    if total == 0 {
+        return 0.0;
+    }
+    let comments =
+        non_whitespace(&source[first_byte..node.start_byte()]) + comment_bytes(node, source);
+    comments as f32 / total as f32
+}
+
+/// Non-whitespace bytes inside comments and docstrings under `node`.
+fn comment_bytes(node: Node<'_>, source: &[u8]) -> usize {
+    let is_docstring = node.kind() == "expression_statement"
+        && node.named_child_count() == 1
+        && node
+            .named_child(0)
+            .is_some_and(|child| child.kind() == "string");
+    if node.kind().contains("comment") || is_docstring {
+        return non_whitespace(&source[node.start_byte()..node.end_byte()]);
+    }
+    let mut cursor = node.walk();
+    node.children(&mut cursor)
+        .map(|child| comment_bytes(child, source))
+        .sum()
+}
+
+fn non_whitespace(text: &[u8]) -> usize {
+    text.iter().filter(|b| !b.is_ascii_whitespace()).count()
 }
 
 /// Collect comment text from preceding siblings and prepend to content.
-fn prepend_comments(node: Node<'_>, source: &[u8], content: &str) -> String {
+/// Also returns the byte offset where the result starts in `source`.
+fn prepend_comments(node: Node<'_>, source: &[u8], content: &str) -> (String, usize) {
     let mut comments = Vec::new();
+    let mut first_byte = node.start_byte();
     let mut sibling = node.prev_sibling();
     while let Some(sib) = sibling {
         if sib.kind() == "comment" || sib.kind() == "line_comment" || sib.kind() == "block_comment"
@@ -141,6 +714,7 @@ fn prepend_comments(node: Node<'_>, source: &[u8], content: &str) -> String {
             if let Ok(text) = sib.utf8_text(source) {
                 comments.push(text.to_string());
             }
+            first_byte = sib.start_byte();
             sibling = sib.prev_sibling();
         } else {
             break;
@@ -148,14 +722,228 @@ fn prepend_comments(node: Node<'_>, source: &[u8], content: &str) -> String {
     }
 
     if comments.is_empty() {
-        return content.to_string();
+        return (content.to_string(), first_byte);
     }
 
     comments.reverse();
     let mut result = comments.join("\n");
     result.push('\n');
     result.push_str(content);
-    result
+    (result, first_byte)
+}
+
+/// Up to `count` whole lines of `source` above the line containing `byte`,
+/// each newline-terminated. Leading blank lines are dropped.
+fn preceding_lines(source: &[u8], byte: usize, count: usize) -> String {
+    let line_start = |end: usize| {
+        source[..end]
+            .iter()
+            .rposition(|&b| b == b'\n')
+            .map_or(0, |i| i + 1)
+    };
+    let end = line_start(byte);
+    let mut start = end;
+    for _ in 0..count {
+        if start == 0 {
+            break;
+        }
+        start = line_start(start - 1);
+    }
+    String::from_utf8_lossy(&source[start..end])
+        .lines()
+        .skip_while(|line| line.trim().is_empty())
+        .map(|line| format!("{line}\n"))
+        .collect()
+}
+
+/// The declaration of `node` without its body: everything before its
+/// `body` field, or if it has none, its first line continued over any open
+/// brackets (Go type parameters), minus a trailing `{`. A decorated Python
+/// definition gives the definition's.
+fn extract_signature(node: Node<'_>, source: &[u8]) -> Option<String> {
+    if let Some(definition) = node.child_by_field_name("definition") {
+        return extract_signature(definition, source);
+    }
+    let start = node.start_byte();
+    let end = match node.child_by_field_name("body") {
+        Some(body) => body.start_byte(),
+        None => start + declaration_len(&source[start..node.end_byte()]),
+    };
+    let text = std::str::from_utf8(&source[start..end]).ok()?;
+    let signature = text.trim_end().trim_end_matches('{').trim_end();
+    (!signature.is_empty()).then(|| signature.to_string())
+}
+
+/// Length of the declaration that starts `text`: up to the first newline
+/// outside brackets. A `(` ending its line opens a group (Go's
+/// `type (`), which ends the declaration; brackets that never close give
+/// just the first line.
+fn declaration_len(text: &[u8]) -> usize {
+    let first_line = text.iter().position(|&b| b == b'\n').unwrap_or(text.len());
+    let mut depth = 0usize;
+    for (i, &b) in text.iter().enumerate() {
+        match b {
+            b'(' if depth == 0 && ends_line(&text[i + 1..]) => return i + 1,
+            b'(' | b'[' => depth += 1,
+            b')' | b']' => depth = depth.saturating_sub(1),
+            b'\n' if depth == 0 => return i,
+            _ => {}
+        }
+    }
+    if depth == 0 { text.len() } else { first_line }
+}
+
+/// Whether `text` is blank up to its first newline.
+fn ends_line(text: &[u8]) -> bool {
+    text.iter()
+        .take_while(|&&b| b != b'\n')
+        .all(u8::is_ascii_whitespace)
+}
+
+/// Documentation for `node` with comment markers removed: Go and Rust doc
+/// comments directly above it, or a Python docstring.
+fn extract_doc(node: Node<'_>, source: &[u8], lang_name: &str) -> Option<String> {
+    let doc = match lang_name {
+        "go" | "rust" => doc_comments(node, source, lang_name)?,
+        "python" => python_docstring(node, source)?,
+        _ => return None,
+    };
+    let doc = doc.trim();
+    (!doc.is_empty()).then(|| doc.to_string())
+}
+
+/// The `#[cfg(...)]` attributes on a Rust item, as one predicate.
+fn extract_cfg(node: Node<'_>, source: &[u8], lang_name: &str) -> Option<String> {
+    if lang_name != "rust" {
+        return None;
+    }
+    let mut predicates = Vec::new();
+    let mut sibling = node.prev_sibling();
+    while let Some(sib) = sibling {
+        match sib.kind() {
+            "attribute_item" => {
+                if let Some(predicate) = cfg_predicate(sib.utf8_text(source).unwrap_or("")) {
+                    predicates.push(predicate.to_string());
+                }
+            }
+            "line_comment" | "block_comment" => {}
+            _ => break,
+        }
+        sibling = sib.prev_sibling();
+    }
+    predicates.reverse();
+    match predicates.len() {
+        0 => None,
+        1 => predicates.pop(),
+        _ => Some(format!("all({})", predicates.join(", "))),
+    }
+}
+
+/// Doc comments immediately preceding `node`. Rust attributes between the
+/// comments and the item are skipped; a blank line ends the doc block.
+fn doc_comments(node: Node<'_>, source: &[u8], lang_name: &str) -> Option<String> {
+    let mut blocks = Vec::new();
+    let mut next_row = node.start_position().row;
+    let mut sibling = node.prev_sibling();
+
+    while let Some(sib) = sibling {
+        if sib.end_position().row + 1 < next_row {
+            break;
+        }
+        if sib.kind() == "attribute_item" {
+            next_row = sib.start_position().row;
+            sibling = sib.prev_sibling();
+            continue;
+        }
+        if !matches!(sib.kind(), "comment" | "line_comment" | "block_comment") {
+            break;
+        }
+        let text = sib.utf8_text(source).unwrap_or("").trim_end();
+        // `//go:generate` and friends are directives, not documentation
+        if !(lang_name == "go" && text.starts_with("//go:")) {
+            let Some(body) = comment_body(text, lang_name) else {
+                break;
+            };
+            blocks.push(body);
+        }
+        next_row = sib.start_position().row;
+        sibling = sib.prev_sibling();
+    }
+
+    if blocks.is_empty() {
+        return None;
+    }
+    blocks.reverse();
+    let lines: Vec<&str> = blocks
+        .iter()
+        .flat_map(|b| b.split('\n'))
+        .map(|l| l.strip_prefix(' ').unwrap_or(l))
+        .collect();
+    Some(lines.join("\n"))
+}
+
+/// Text of a doc comment without its markers, or `None` if `text` isn't one.
+/// Rust only counts `///` and `/** */`; in Go any comment above a
+/// declaration documents it.
+fn comment_body(text: &str, lang_name: &str) -> Option<String> {
+    let (line, block) = match lang_name {
+        "rust" => ("///", "/**"),
+        _ => ("//", "/*"),
+    };
+    if let Some(rest) = text.strip_prefix(line) {
+        // `////` is an ordinary Rust comment
+        return (!(lang_name == "rust" && rest.starts_with('/'))).then(|| rest.to_string());
+    }
+    let body = text.strip_prefix(block)?.strip_suffix("*/")?;
+    Some(
+        body.lines()
+            .map(|l| {
+                let t = l.trim_start();
+                t.strip_prefix('*').unwrap_or(t)
+            })
+            .collect::<Vec<_>>()
+            .join("\n"),
+    )
+}
+
+/// The docstring of a Python function or class, dedented.
+fn python_docstring(node: Node<'_>, source: &[u8]) -> Option<String> {
+    let definition = if node.kind() == "decorated_definition" {
+        node.child_by_field_name("definition")?
+    } else {
+        node
+    };
+    let string = definition
+        .child_by_field_name("body")?
+        .named_child(0)
+        .filter(|n| n.kind() == "expression_statement")?
+        .named_child(0)
+        .filter(|n| n.kind() == "string")?;
+    let literal = string
+        .utf8_text(source)
+        .ok()?
+        .trim_start_matches(|c: char| c.is_ascii_alphabetic());
+    let quote = ["\"\"\"", "'''", "\"", "'"]
+        .into_iter()
+        .find(|q| literal.starts_with(q))?;
+    let body = literal.strip_prefix(quote)?.strip_suffix(quote)?;
+
+    // Like inspect.cleandoc: the first line is flush, the rest share an indent
+    let mut lines = body.lines();
+    let first_line = lines.next().unwrap_or("").trim();
+    let rest: Vec<&str> = lines.collect();
+    let indent = rest
+        .iter()
+        .filter(|l| !l.trim().is_empty())
+        .map(|l| l.len() - l.trim_start().len())
+        .min()
+        .unwrap_or(0);
+    let mut out = vec![first_line];
+    out.extend(
+        rest.iter()
+            .map(|l| l.get(indent..).unwrap_or("").trim_end()),
+    );
+    Some(out.join("\n"))
 }
 
 /// Extract a human-readable symbol name from an AST node.
@@ -176,12 +964,235 @@ fn extract_symbol_name(node: Node<'_>, source: &[u8], lang_name: &str) -> Option
             .map(|s| s.to_string());
     }
 
+    // Special case: Go type, const, and var declarations name their specs,
+    // one or a parenthesized group of them
+    if lang_name == "go"
+        && matches!(
+            node.kind(),
+            "type_declaration" | "const_declaration" | "var_declaration"
+        )
+    {
+        let names = go_spec_names(node, source);
+        return (!names.is_empty()).then(|| names.join(", "));
+    }
+
+    // Special case: YAML mapping pairs are named by their key
+    if lang_name == "yaml" && node.kind() == "block_mapping_pair" {
+        return node
+            .child_by_field_name("key")
+            .and_then(|n| n.utf8_text(source).ok())
+            .map(|s| s.to_string());
+    }
+
     // General case: try the "name" field
     node.child_by_field_name("name")
         .and_then(|n| n.utf8_text(source).ok())
         .map(|s| s.to_string())
 }
 
+/// Names declared by the specs under a Go declaration, in order: one per
+/// type, and every name of `const a, b = 1, 2`.
+fn go_spec_names(node: Node<'_>, source: &[u8]) -> Vec<String> {
+    let mut names = Vec::new();
+    let mut cursor = node.walk();
+    for child in node.named_children(&mut cursor) {
+        match child.kind() {
+            "type_spec" | "type_alias" | "const_spec" | "var_spec" => {
+                let mut name_cursor = child.walk();
+                names.extend(
+                    child
+                        .children_by_field_name("name", &mut name_cursor)
+                        .filter(|name| name.is_named())
+                        .filter_map(|name| name.utf8_text(source).ok())
+                        .map(|name| name.to_string()),
+                );
+            }
+            // A parenthesized var group wraps its specs in a list
+            "var_spec_list" => names.extend(go_spec_names(child, source)),
+            _ => {}
+        }
+    }
+    names
+}
+
+/// `Kind/name` for a YAML document that looks like a Kubernetes object
+/// (top-level `apiVersion` and `kind` keys), otherwise `None`.
+fn k8s_object_name(document: Node<'_>, source: &[u8]) -> Option<String> {
+    let mapping = top_level_mapping(document)?;
+    let mut api_version = false;
+    let mut kind = None;
+    let mut name = None;
+
+    let mut cursor = mapping.walk();
+    for pair in mapping.named_children(&mut cursor) {
+        let key = pair
+            .child_by_field_name("key")
+            .and_then(|k| k.utf8_text(source).ok());
+        let value = pair.child_by_field_name("value");
+        match key {
+            Some("apiVersion") => api_version = true,
+            Some("kind") => kind = value.and_then(|v| v.utf8_text(source).ok()),
+            Some("metadata") => {
+                name = value
+                    .and_then(|v| mapping_value(v, "name", source))
+                    .map(str::to_string)
+            }
+            _ => {}
+        }
+    }
+
+    let kind = kind.filter(|_| api_version)?;
+    Some(match name {
+        Some(name) => format!("{kind}/{name}"),
+        None => kind.to_string(),
+    })
+}
+
+/// A path or schema of an OpenAPI document.
+struct OpenApiItem<'tree> {
+    /// `path` or `schema`.
+    kind: &'static str,
+    /// The path, e.g. `/users/{id}`, or the schema's name.
+    name: String,
+    /// The operations' summaries, or the schema's description.
+    doc: Option<String>,
+    /// The path's operations, e.g. `GET POST /users`.
+    signature: Option<String>,
+    /// The key-value pair holding the item.
+    node: Node<'tree>,
+}
+
+/// HTTP methods an OpenAPI path item can hold operations for.
+const HTTP_METHODS: [&str; 8] = [
+    "get", "put", "post", "delete", "options", "head", "patch", "trace",
+];
+
+/// The paths and schemas of a YAML document with a top-level `openapi` or
+/// `swagger` key, or `None` for any other document. Schemas are read from
+/// `components.schemas`, or `definitions` in Swagger 2.
+fn openapi_items<'tree>(document: Node<'tree>, source: &[u8]) -> Option<Vec<OpenApiItem<'tree>>> {
+    let mapping = top_level_mapping(document)?;
+    let pairs = mapping_pairs(mapping, source);
+    if !pairs
+        .iter()
+        .any(|(key, _)| matches!(key.as_str(), "openapi" | "swagger"))
+    {
+        return None;
+    }
+
+    let mut items = Vec::new();
+    for (key, pair) in &pairs {
+        let value = pair.child_by_field_name("value");
+        let schemas = match key.as_str() {
+            "paths" => {
+                let paths = value.and_then(top_level_mapping);
+                for (path, item) in paths.map(|m| mapping_pairs(m, source)).unwrap_or_default() {
+                    let operations: Vec<(String, Node<'tree>)> = item
+                        .child_by_field_name("value")
+                        .and_then(top_level_mapping)
+                        .map(|m| mapping_pairs(m, source))
+                        .unwrap_or_default()
+                        .into_iter()
+                        .filter(|(method, _)| HTTP_METHODS.contains(&method.as_str()))
+                        .collect();
+                    let summaries: Vec<String> = operations
+                        .iter()
+                        .filter_map(|(method, op)| {
+                            let op = op.child_by_field_name("value")?;
+                            let summary = mapping_value(op, "summary", source)
+                                .or_else(|| mapping_value(op, "description", source))?;
+                            Some(format!("{}: {}", method.to_uppercase(), unquote(summary)))
+                        })
+                        .collect();
+                    let methods: Vec<String> =
+                        operations.iter().map(|(m, _)| m.to_uppercase()).collect();
+                    items.push(OpenApiItem {
+                        kind: "path",
+                        signature: (!methods.is_empty())
+                            .then(|| format!("{} {path}", methods.join(" "))),
+                        doc: (!summaries.is_empty()).then(|| summaries.join("\n")),
+                        name: path,
+                        node: item,
+                    });
+                }
+                continue;
+            }
+            "components" => value
+                .and_then(top_level_mapping)
+                .map(|m| mapping_pairs(m, source))
+                .unwrap_or_default()
+                .into_iter()
+                .find(|(key, _)| key == "schemas")
+                .and_then(|(_, pair)| pair.child_by_field_name("value")),
+            "definitions" => value,
+            _ => None,
+        };
+        for (name, schema) in schemas
+            .and_then(top_level_mapping)
+            .map(|m| mapping_pairs(m, source))
+            .unwrap_or_default()
+        {
+            items.push(OpenApiItem {
+                kind: "schema",
+                doc: schema
+                    .child_by_field_name("value")
+                    .and_then(|v| mapping_value(v, "description", source))
+                    .map(|d| unquote(d).to_string()),
+                signature: None,
+                name,
+                node: schema,
+            });
+        }
+    }
+    Some(items)
+}
+
+/// The pairs of a YAML `block_mapping`, with their keys unquoted.
+fn mapping_pairs<'tree>(mapping: Node<'tree>, source: &[u8]) -> Vec<(String, Node<'tree>)> {
+    let mut cursor = mapping.walk();
+    mapping
+        .named_children(&mut cursor)
+        .filter_map(|pair| {
+            let key = pair.child_by_field_name("key")?.utf8_text(source).ok()?;
+            Some((unquote(key).to_string(), pair))
+        })
+        .collect()
+}
+
+/// A YAML scalar without the quotes around it, if any.
+fn unquote(scalar: &str) -> &str {
+    let scalar = scalar.trim();
+    ['"', '\'']
+        .iter()
+        .find_map(|&q| scalar.strip_prefix(q)?.strip_suffix(q))
+        .unwrap_or(scalar)
+}
+
+/// The `block_mapping` directly under a YAML document or block node.
+fn top_level_mapping(node: Node<'_>) -> Option<Node<'_>> {
+    let mut cursor = node.walk();
+    let children: Vec<Node<'_>> = node.named_children(&mut cursor).collect();
+    children.into_iter().find_map(|child| match child.kind() {
+        "block_mapping" => Some(child),
+        "block_node" => top_level_mapping(child),
+        _ => None,
+    })
+}
+
+/// Scalar value of `key` in the mapping held by `node`.
+fn mapping_value<'a>(node: Node<'_>, key: &str, source: &'a [u8]) -> Option<&'a str> {
+    let mapping = top_level_mapping(node)?;
+    let mut cursor = mapping.walk();
+    let pairs: Vec<Node<'_>> = mapping.named_children(&mut cursor).collect();
+    pairs.into_iter().find_map(|pair| {
+        let k = pair.child_by_field_name("key")?.utf8_text(source).ok()?;
+        if k != key {
+            return None;
+        }
+        pair.child_by_field_name("value")?.utf8_text(source).ok()
+    })
+}
+
 /// Extract `Type` or `Trait for Type` from a Rust impl item.
 fn extract_rust_impl_name(node: Node<'_>, source: &[u8]) -> Option<String> {
     let type_node = node.child_by_field_name("type")?;
@@ -231,6 +1242,31 @@ func hello() {
         );
     }
 
+    #[test]
+    fn imports_are_the_top_level_import_statements() {
+        let chunker = make_chunker(&["go", "rust", "python", "yaml"]);
+        let go = "package main\r\n\r\nimport \"fmt\"\r\n\r\nimport (\r\n\t\"os\"\r\n)\r\n\r\nfunc main() {}\r\n";
+        let imports = chunker.imports(go, "go").unwrap().unwrap();
+        assert_eq!(imports.text, "import \"fmt\"\nimport (\n\t\"os\"\n)");
+        assert_eq!((imports.start_line, imports.end_line), (3, 7));
+
+        let rust =
+            "//! Docs\nuse std::fmt;\n\nmod inner {\n    use std::io;\n}\n\nuse crate::Error;\n";
+        let imports = chunker.imports(rust, "rust").unwrap().unwrap();
+        // Nested ones belong to their module, not the file
+        assert_eq!(imports.text, "use std::fmt;\nuse crate::Error;");
+        assert_eq!((imports.start_line, imports.end_line), (2, 8));
+
+        let python = "from __future__ import annotations\nimport os\nfrom a import (\n    b,\n)\n";
+        let imports = chunker.imports(python, "python").unwrap().unwrap();
+        assert_eq!(imports.end_line, 5);
+        assert!(imports.text.starts_with("from __future__"));
+
+        assert_eq!(chunker.imports("fn main() {}\n", "rust").unwrap(), None);
+        assert_eq!(chunker.imports("a: 1\n", "yaml").unwrap(), None);
+        assert_eq!(chunker.imports("x", "haskell").unwrap(), None);
+    }
+
     #[test]
     fn go_method_declaration() {
         let chunker = make_chunker(&["go"]);
@@ -317,6 +1353,25 @@ func Hello() {
         );
     }
 
+    #[test]
+    fn go_doc_extracted_without_markers() {
+        let chunker = make_chunker(&["go"]);
+        let source = r#"package main
+
+// Unrelated comment.
+
+// Hello prints a greeting.
+// It never fails.
+//go:noinline
+func Hello() {}
+"#;
+        let chunks = chunker.chunk_file(source, "go").unwrap();
+        assert_eq!(
+            chunks[0].doc.as_deref(),
+            Some("Hello prints a greeting.\nIt never fails.")
+        );
+    }
+
     #[test]
     fn go_line_numbers_are_1_indexed() {
         let chunker = make_chunker(&["go"]);
@@ -512,6 +1567,266 @@ fn add(a: i32, b: i32) -> i32 {
         );
     }
 
+    #[test]
+    fn rust_cfg_attributes_become_build_constraints() {
+        let chunker = make_chunker(&["rust"]);
+        let source = r#"#[cfg(unix)]
+fn open() {}
+
+/// Windows needs a wide path.
+#[cfg(windows)]
+#[inline]
+#[cfg(target_arch = "x86_64")]
+fn open_wide() {}
+
+#[derive(Debug)]
+struct Handle;
+"#;
+        let chunks = chunker.chunk_file(source, "rust").unwrap();
+        let builds: Vec<Option<&str>> = chunks.iter().map(|c| c.build.as_deref()).collect();
+        assert_eq!(
+            builds,
+            [
+                Some("unix"),
+                Some("all(windows, target_arch = \"x86_64\")"),
+                None
+            ]
+        );
+    }
+
+    #[test]
+    fn comment_ratio_counts_comments_and_docstrings() {
+        let chunker = make_chunker(&["rust", "python"]);
+        let source = r#"/// Adds two numbers, wrapping on overflow.
+fn add(a: i32, b: i32) -> i32 {
+    a.wrapping_add(b)
+}
+
+fn sub(a: i32, b: i32) -> i32 {
+    a - b
+}
+"#;
+        let chunks = chunker.chunk_file(source, "rust").unwrap();
+        assert!(chunks[0].comment_ratio > 0.3, "{}", chunks[0].comment_ratio);
+        assert_eq!(chunks[1].comment_ratio, 0.0);
+
+        let source = r#"def retry(op):
+    """Call op until it succeeds, backing off exponentially between tries."""
+    return op()
+"#;
+        let chunks = chunker.chunk_file(source, "python").unwrap();
+        assert!(chunks[0].comment_ratio > 0.5, "{}", chunks[0].comment_ratio);
+    }
+
+    #[test]
+    fn rust_doc_skips_attributes_and_plain_comments() {
+        let chunker = make_chunker(&["rust"]);
+        let source = r#"// not documentation
+/// A point in space.
+///
+/// Coordinates are in meters.
+#[derive(Debug)]
+struct Point;
+
+// just a note
+fn undocumented() {}
+"#;
+        let chunks = chunker.chunk_file(source, "rust").unwrap();
+        assert_eq!(
+            chunks[0].doc.as_deref(),
+            Some("A point in space.\n\nCoordinates are in meters.")
+        );
+        assert_eq!(chunks[1].doc, None);
+    }
+
+    #[test]
+    fn overlap_prepends_preceding_lines() {
+        let mut config = Config::load().unwrap();
+        config.lang.retain(|name, _| name == "go");
+        if let Some(lang) = config.lang.get_mut("go") {
+            lang.overlap_lines = 3;
+        }
+        let chunker = TreeSitterChunker::new(&config).unwrap();
+        let source = r#"package main
+
+const maxRetries = 3
+
+// retry calls f until it succeeds.
+func retry(f func() error) error {
+	return f()
+}
+"#;
+        let chunks = chunker.chunk_file(source, "go").unwrap();
+        let retry = chunks
+            .iter()
+            .find(|c| c.symbol_name.as_deref() == Some("retry"))
+            .unwrap();
+        assert!(
+            retry
+                .content
+                .starts_with("const maxRetries = 3\n\n// retry calls f"),
+            "{}",
+            retry.content
+        );
+        assert_eq!(retry.start_line, 6);
+
+        // Off by default
+        let plain = make_chunker(&["go"]).chunk_file(source, "go").unwrap();
+        assert!(
+            plain
+                .iter()
+                .filter(|c| c.symbol_name.as_deref() == Some("retry"))
+                .all(|c| c.content.starts_with("// retry calls f"))
+        );
+    }
+
+    fn make_splitting_chunker(language: &str, kinds: &[&str]) -> TreeSitterChunker {
+        let mut config = Config::load().unwrap();
+        config.lang.retain(|name, _| name == language);
+        if let Some(lang) = config.lang.get_mut(language) {
+            lang.split_children = kinds.iter().map(|k| (*k).to_string()).collect();
+        }
+        TreeSitterChunker::new(&config).unwrap()
+    }
+
+    #[test]
+    fn rust_split_impl_into_methods() {
+        let chunker = make_splitting_chunker("rust", &["impl_item"]);
+        let source = r#"struct Point {
+    x: f64,
+}
+
+impl Point {
+    fn new(x: f64) -> Self {
+        Self { x }
+    }
+
+    fn x(&self) -> f64 {
+        self.x
+    }
+}
+"#;
+        let chunks = chunker.chunk_file(source, "rust").unwrap();
+        assert!(
+            !chunks
+                .iter()
+                .any(|c| c.symbol_kind.as_deref() == Some("impl_item")),
+            "split impl should not produce a whole-impl chunk: {chunks:?}"
+        );
+        let new = chunks
+            .iter()
+            .find(|c| c.symbol_name.as_deref() == Some("Point::new"))
+            .expect("should find Point::new");
+        assert!(
+            new.content.starts_with("impl Point\n"),
+            "impl header should be kept as context: {:?}",
+            new.content
+        );
+        assert_eq!(new.start_line, 6);
+        let x = chunks
+            .iter()
+            .find(|c| c.symbol_name.as_deref() == Some("Point::x"))
+            .expect("should find Point::x");
+        assert!(x.content.starts_with("impl Point\n"), "{:?}", x.content);
+    }
+
+    #[test]
+    fn rust_split_keeps_members_that_are_not_chunks() {
+        let chunker = make_splitting_chunker("rust", &["impl_item"]);
+        let source = r#"/// Parses lines.
+impl Parser for Lines {
+    forward!(len, is_empty);
+
+    fn parse(&self) -> String {
+        String::new()
+    }
+
+    fn reset(&mut self) {}
+
+    // Resets after each parse
+}
+"#;
+        let chunks = chunker.chunk_file(source, "rust").unwrap();
+        let rest: Vec<&Chunk> = chunks
+            .iter()
+            .filter(|c| c.symbol_kind.as_deref() == Some("impl_item"))
+            .collect();
+        assert_eq!(rest.len(), 1, "{chunks:?}");
+        let rest = rest[0];
+        assert_eq!(
+            rest.content,
+            "/// Parses lines.\nimpl Parser for Lines {\n    forward!(len, is_empty);\n    \
+             // Resets after each parse\n}"
+        );
+        assert_eq!(rest.symbol_name.as_deref(), Some("Lines"));
+        assert_eq!(rest.doc.as_deref(), Some("Parses lines."));
+        assert_eq!(rest.byte_range, None);
+        assert_eq!((rest.start_line, rest.end_line), (2, 12));
+        assert!(rest.comment_ratio > 0.0);
+        // Before the methods, as in the file
+        assert_eq!(chunks[0].symbol_kind.as_deref(), Some("impl_item"));
+        for method in ["Lines::parse", "Lines::reset"] {
+            let chunk = chunks
+                .iter()
+                .find(|c| c.symbol_name.as_deref() == Some(method))
+                .unwrap();
+            assert!(chunk.content.starts_with("impl Parser for Lines\n"));
+            assert!(!chunk.content.contains("forward!"));
+        }
+    }
+
+    #[test]
+    fn python_split_keeps_class_attributes() {
+        let chunker = make_splitting_chunker("python", &["class_definition"]);
+        let source = r#"class Config:
+    retries = 3
+
+    def load(self):
+        pass
+"#;
+        let chunks = chunker.chunk_file(source, "python").unwrap();
+        let rest = chunks
+            .iter()
+            .find(|c| c.symbol_kind.as_deref() == Some("class_definition"))
+            .expect("attributes should be kept in a chunk of their own");
+        assert_eq!(rest.content, "class Config:\n    retries = 3");
+        assert!(
+            chunks
+                .iter()
+                .any(|c| c.symbol_name.as_deref() == Some("Config::load"))
+        );
+    }
+
+    #[test]
+    fn rust_split_trait_impl_uses_type_name() {
+        let chunker = make_splitting_chunker("rust", &["impl_item"]);
+        let source = r#"impl Display for Foo {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "Foo")
+    }
+}
+"#;
+        let chunks = chunker.chunk_file(source, "rust").unwrap();
+        let fmt = chunks
+            .iter()
+            .find(|c| c.symbol_name.as_deref() == Some("Foo::fmt"))
+            .expect("should find Foo::fmt");
+        assert!(fmt.content.contains("impl Display for Foo"));
+    }
+
+    #[test]
+    fn rust_split_keeps_impl_without_methods_whole() {
+        let chunker = make_splitting_chunker("rust", &["impl_item"]);
+        let source = "impl Marker for Foo {}\n";
+        let chunks = chunker.chunk_file(source, "rust").unwrap();
+        assert!(
+            chunks
+                .iter()
+                .any(|c| c.symbol_kind.as_deref() == Some("impl_item")),
+            "empty impl should still be chunked: {chunks:?}"
+        );
+    }
+
     // ---------------------------------------------------------------
     // Python
     // ---------------------------------------------------------------
@@ -583,6 +1898,159 @@ def list_users():
         );
     }
 
+    #[test]
+    fn signatures_stop_at_the_body() {
+        let signatures = |lang: &str, source: &str| -> Vec<Option<String>> {
+            make_chunker(&[lang])
+                .chunk_file(source, lang)
+                .unwrap()
+                .into_iter()
+                .map(|c| c.signature)
+                .collect()
+        };
+
+        let go = r#"package main
+
+// Open opens path.
+func Open(path string,
+	mode int) (*File, error) {
+	return nil, nil
+}
+
+type Config struct {
+	Path string
+}
+"#;
+        assert_eq!(
+            signatures("go", go),
+            [
+                Some("func Open(path string,\n\tmode int) (*File, error)".to_string()),
+                Some("type Config struct".to_string()),
+            ]
+        );
+
+        let python = r#"@cache
+def load(path: str) -> str:
+    return open(path).read()
+"#;
+        // The decorated definition gives the function's own declaration
+        assert_eq!(
+            signatures("python", python)[0].as_deref(),
+            Some("def load(path: str) -> str:")
+        );
+    }
+
+    #[test]
+    fn go_generics_are_named_and_signed() {
+        let chunker = make_chunker(&["go"]);
+        let source = r#"package cache
+
+// Cache maps keys to values.
+type Cache[
+	K comparable,
+	V any,
+] struct {
+	items map[K]V
+}
+
+type (
+	Pair[A, B any] struct{ First A; Second B }
+	Set[T comparable] = map[T]struct{}
+)
+
+const minSize, maxSize = 1, 1 << 20
+
+var (
+	hits   = 0
+	misses = 0
+)
+
+// Get returns the value for key.
+func (c *Cache[K, V]) Get(
+	key K,
+) (V, bool) {
+	v, ok := c.items[key]
+	return v, ok
+}
+
+func Map[T, U any](in []T, f func(T) U) []U {
+	return nil
+}
+"#;
+        let chunks: Vec<(Option<String>, Option<String>)> = chunker
+            .chunk_file(source, "go")
+            .unwrap()
+            .into_iter()
+            .map(|c| (c.symbol_name, c.signature))
+            .collect();
+        let some = |s: &str| Some(s.to_string());
+        assert_eq!(
+            chunks,
+            [
+                (
+                    some("Cache"),
+                    some("type Cache[\n\tK comparable,\n\tV any,\n] struct")
+                ),
+                (some("Pair, Set"), some("type (")),
+                (
+                    some("minSize, maxSize"),
+                    some("const minSize, maxSize = 1, 1 << 20")
+                ),
+                (some("hits, misses"), some("var (")),
+                (
+                    some("Get"),
+                    some("func (c *Cache[K, V]) Get(\n\tkey K,\n) (V, bool)")
+                ),
+                (
+                    some("Map"),
+                    some("func Map[T, U any](in []T, f func(T) U) []U")
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn declarations_without_a_body_continue_over_open_brackets() {
+        let declaration = |text: &'static str| &text[..declaration_len(text.as_bytes())];
+        assert_eq!(declaration("var x = 1\nvar y = 2"), "var x = 1");
+        assert_eq!(
+            declaration("type Set[\n\tT any,\n] map[T]bool\nvar y = 2"),
+            "type Set[\n\tT any,\n] map[T]bool"
+        );
+        assert_eq!(declaration("type T struct {\n}"), "type T struct {");
+        // A group ends the declaration at its opening parenthesis
+        assert_eq!(declaration("const (\n\ta = 1\n)"), "const (");
+        // Unclosed brackets, as in a string, give the first line
+        assert_eq!(declaration("var re = \"[\"\nvar y = 2"), "var re = \"[\"");
+    }
+
+    #[test]
+    fn python_docstrings_are_dedented() {
+        let chunker = make_chunker(&["python"]);
+        let source = r#"@cache
+def load(path):
+    """Load a config file.
+
+    Args:
+        path: where to read from.
+    """
+    return open(path).read()
+
+def bare():
+    return 1
+"#;
+        let chunks = chunker.chunk_file(source, "python").unwrap();
+        assert_eq!(
+            chunks[0].doc.as_deref(),
+            Some("Load a config file.\n\nArgs:\n    path: where to read from.")
+        );
+        assert!(
+            chunks
+                .iter()
+                .all(|c| c.symbol_name.as_deref() != Some("bare") || c.doc.is_none())
+        );
+    }
+
     #[test]
     fn python_nested_class_methods() {
         let chunker = make_chunker(&["python"]);
@@ -600,6 +2068,242 @@ def list_users():
         );
     }
 
+    // ---------------------------------------------------------------
+    // Shell
+    // ---------------------------------------------------------------
+
+    #[test]
+    fn sh_functions_and_top_level_blocks() {
+        let chunker = make_chunker(&["sh"]);
+        let source = r#"#!/usr/bin/env bash
+set -euo pipefail
+
+# Deploy the given service
+deploy() {
+    if [ -z "$1" ]; then
+        echo "usage: deploy SERVICE" >&2
+        return 1
+    fi
+    kubectl rollout restart "deployment/$1"
+}
+
+for svc in api worker; do
+    deploy "$svc"
+done
+"#;
+        let chunks = chunker.chunk_file(source, "sh").unwrap();
+        let kinds: Vec<_> = chunks.iter().map(|c| c.symbol_kind.as_deref()).collect();
+        assert_eq!(
+            kinds,
+            vec![Some("function_definition"), Some("for_statement")],
+            "nested if inside deploy() must not be chunked separately"
+        );
+        assert_eq!(chunks[0].symbol_name.as_deref(), Some("deploy"));
+        assert!(chunks[0].content.starts_with("# Deploy the given service"));
+    }
+
+    // ---------------------------------------------------------------
+    // YAML
+    // ---------------------------------------------------------------
+
+    #[test]
+    fn yaml_top_level_keys() {
+        let chunker = make_chunker(&["yaml"]);
+        let source = r#"name: CI
+on:
+  push:
+    branches: [main]
+jobs:
+  test:
+    runs-on: ubuntu-latest
+"#;
+        let chunks = chunker.chunk_file(source, "yaml").unwrap();
+        let names: Vec<_> = chunks.iter().map(|c| c.symbol_name.as_deref()).collect();
+        assert_eq!(names, vec![Some("name"), Some("on"), Some("jobs")]);
+        assert_eq!((chunks[2].start_line, chunks[2].end_line), (5, 7));
+    }
+
+    #[test]
+    fn yaml_kubernetes_documents_stay_whole() {
+        let chunker = make_chunker(&["yaml"]);
+        let source = r#"apiVersion: apps/v1
+kind: Deployment
+metadata:
+  name: web
+spec:
+  replicas: 2
+---
+apiVersion: v1
+kind: Service
+metadata:
+  name: web
+"#;
+        let chunks = chunker.chunk_file(source, "yaml").unwrap();
+        let names: Vec<_> = chunks.iter().map(|c| c.symbol_name.as_deref()).collect();
+        assert_eq!(names, vec![Some("Deployment/web"), Some("Service/web")]);
+        assert!(chunks[0].content.contains("replicas: 2"));
+        assert_eq!(chunks[1].symbol_kind.as_deref(), Some("document"));
+    }
+
+    #[test]
+    fn yaml_openapi_paths_and_schemas() {
+        let chunker = make_chunker(&["yaml"]);
+        let source = r#"openapi: 3.0.3
+info:
+  title: Users
+  version: "1"
+paths:
+  "/users/{id}":
+    get:
+      summary: Fetch a user
+    delete:
+      operationId: deleteUser
+  /users:
+    post:
+      description: Create a user
+components:
+  schemas:
+    User:
+      description: A registered account.
+      type: object
+"#;
+        let chunks = chunker.chunk_file(source, "yaml").unwrap();
+        let names: Vec<_> = chunks
+            .iter()
+            .map(|c| (c.symbol_kind.as_deref(), c.symbol_name.as_deref()))
+            .collect();
+        assert_eq!(
+            names,
+            vec![
+                (Some("path"), Some("/users/{id}")),
+                (Some("path"), Some("/users")),
+                (Some("schema"), Some("User")),
+            ]
+        );
+        assert_eq!(
+            chunks[0].signature.as_deref(),
+            Some("GET DELETE /users/{id}")
+        );
+        assert_eq!(chunks[0].doc.as_deref(), Some("GET: Fetch a user"));
+        assert_eq!((chunks[0].start_line, chunks[0].end_line), (6, 10));
+        assert_eq!(chunks[1].doc.as_deref(), Some("POST: Create a user"));
+        assert_eq!(chunks[2].doc.as_deref(), Some("A registered account."));
+        assert!(chunks[2].content.contains("type: object"));
+    }
+
+    #[test]
+    fn protobuf_and_thrift_by_default() {
+        let chunker = make_chunker(&["protobuf", "thrift"]);
+        let proto = "service Greeter {\n  rpc SayHello(Req) returns (Resp);\n}\n";
+        let names: Vec<_> = chunker
+            .chunk_file(proto, "protobuf")
+            .unwrap()
+            .into_iter()
+            .map(|c| c.symbol_name)
+            .collect();
+        assert_eq!(
+            names,
+            [
+                Some("Greeter".to_string()),
+                Some("Greeter.SayHello".to_string())
+            ]
+        );
+
+        let thrift = "struct Work {\n  1: i32 num\n}\n";
+        let chunks = chunker.chunk_file(thrift, "thrift").unwrap();
+        assert_eq!(chunks[0].symbol_kind.as_deref(), Some("struct"));
+    }
+
+    // ---------------------------------------------------------------
+    // HCL
+    // ---------------------------------------------------------------
+
+    #[test]
+    fn hcl_resource_blocks() {
+        let chunker = make_chunker(&["hcl"]);
+        let source = r#"provider "aws" {
+  region = "us-east-1"
+}
+
+resource "aws_instance" "web" {
+  ami = "ami-123"
+}
+"#;
+        let chunks = chunker.chunk_file(source, "hcl").unwrap();
+        let names: Vec<_> = chunks.iter().map(|c| c.symbol_name.as_deref()).collect();
+        assert_eq!(names, vec![Some("aws"), Some("aws_instance.web")]);
+    }
+
+    // ---------------------------------------------------------------
+    // Second tier (cargo features)
+    // ---------------------------------------------------------------
+
+    #[cfg(feature = "ruby")]
+    #[test]
+    fn ruby_classes_and_methods() {
+        let chunker = make_chunker(&["ruby"]);
+        let source = r#"module Billing
+  class Invoice
+    def total
+      items.sum(&:price)
+    end
+
+    def self.for(customer)
+      new(customer)
+    end
+  end
+end
+"#;
+        let chunks = chunker.chunk_file(source, "ruby").unwrap();
+        let names: Vec<_> = chunks.iter().map(|c| c.symbol_name.as_deref()).collect();
+        assert_eq!(
+            names,
+            vec![Some("Billing"), Some("Invoice"), Some("total"), Some("for")]
+        );
+    }
+
+    #[cfg(feature = "php")]
+    #[test]
+    fn php_classes_and_functions() {
+        let chunker = make_chunker(&["php"]);
+        let source = r#"<?php
+function helper() { return 1; }
+
+class Cart {
+    public function add($item) { $this->items[] = $item; }
+}
+"#;
+        let chunks = chunker.chunk_file(source, "php").unwrap();
+        let names: Vec<_> = chunks.iter().map(|c| c.symbol_name.as_deref()).collect();
+        assert_eq!(names, vec![Some("helper"), Some("Cart"), Some("add")]);
+    }
+
+    #[cfg(feature = "swift")]
+    #[test]
+    fn swift_types_and_functions() {
+        let chunker = make_chunker(&["swift"]);
+        let source = r#"protocol Shape {
+    func area() -> Double
+}
+
+struct Square: Shape {
+    let side: Double
+    func area() -> Double { side * side }
+}
+"#;
+        let chunks = chunker.chunk_file(source, "swift").unwrap();
+        let kinds: Vec<_> = chunks.iter().map(|c| c.symbol_kind.as_deref()).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                Some("protocol_declaration"),
+                Some("class_declaration"),
+                Some("function_declaration")
+            ]
+        );
+        assert_eq!(chunks[1].symbol_name.as_deref(), Some("Square"));
+    }
+
     // ---------------------------------------------------------------
     // Cross-cutting
     // ---------------------------------------------------------------
@@ -610,4 +2314,82 @@ def list_users():
         let result = chunker.chunk_file("console.log('hi')", "javascript");
         assert!(result.is_err());
     }
+
+    #[test]
+    fn fallback_language_is_chunked_by_lines() {
+        let source = "a\nb\nc\n\n\n\nd\n";
+        let chunks = chunk_lines(source, 3);
+        let spans: Vec<_> = chunks
+            .iter()
+            .map(|c| (c.content.as_str(), c.start_line, c.end_line))
+            .collect();
+        // The blank window in the middle is dropped
+        assert_eq!(spans, [("a\nb\nc", 1, 3), ("d", 7, 7)]);
+        assert_eq!(&source[chunks[1].byte_range.clone().unwrap()], "d");
+        assert!(chunks[0].symbol_name.is_none());
+
+        let chunker = make_chunker(&[]);
+        let chunks = chunker.chunk_file("FROM rust\nRUN cargo build\n", FALLBACK_LANGUAGE);
+        assert_eq!(chunks.unwrap().len(), 1);
+    }
+
+    // ---------------------------------------------------------------
+    // Line endings and encoding
+    // ---------------------------------------------------------------
+
+    /// `source[chunk.byte_range]` as the chunk's text would be: CRLF as LF.
+    fn source_text(source: &str, chunk: &Chunk) -> String {
+        source[chunk.byte_range.clone().unwrap()].replace("\r\n", "\n")
+    }
+
+    #[test]
+    fn crlf_bom_and_multibyte_text_keep_lines_and_ranges() {
+        let chunker = make_chunker(&["go"]);
+        let source = "\u{feff}package main\r\n\r\n\
+                      // Greet says hello 👋 to the wörld.\r\n\
+                      func Greet() string {\r\n\
+                      \treturn \"héllo 🌍🚀 — ✓\"\r\n\
+                      }\r\n\r\n\
+                      func Bye() {}\r\n";
+        let chunks = chunker.chunk_file(source, "go").unwrap();
+        assert_eq!(chunks.len(), 2);
+
+        let greet = &chunks[0];
+        assert_eq!((greet.start_line, greet.end_line), (4, 6));
+        assert!(!greet.content.contains('\r'), "{:?}", greet.content);
+        assert!(greet.content.starts_with("// Greet says hello 👋"));
+        assert!(greet.content.ends_with("— ✓\"\n}"));
+        assert_eq!(
+            greet.doc.as_deref(),
+            Some("Greet says hello 👋 to the wörld.")
+        );
+        assert_eq!(greet.signature.as_deref(), Some("func Greet() string"));
+        assert_eq!(source_text(source, greet), greet.content);
+
+        let bye = &chunks[1];
+        assert_eq!((bye.start_line, bye.end_line), (8, 8));
+        assert_eq!(&source[bye.byte_range.clone().unwrap()], "func Bye() {}");
+    }
+
+    #[test]
+    fn bom_and_crlf_in_line_and_block_chunking() {
+        let source = "\u{feff}é1\r\né2\r\n🚀3\r\n4\r\n";
+        let chunks = make_chunker(&[])
+            .chunk_file(source, FALLBACK_LANGUAGE)
+            .unwrap();
+        let spans: Vec<_> = chunks
+            .iter()
+            .map(|c| (c.content.as_str(), c.start_line, c.end_line))
+            .collect();
+        assert_eq!(spans, [("é1\né2\n🚀3\n4", 1, 4)]);
+        assert_eq!(source_text(source, &chunks[0]), chunks[0].content);
+
+        let source = "# bucket 🪣\r\nresource \"aws_s3_bucket\" \"logs\" {\r\n  \
+                      bucket = \"lögs\"\r\n}\r\n";
+        let chunks = make_chunker(&["hcl"]).chunk_file(source, "hcl").unwrap();
+        assert_eq!(chunks.len(), 1);
+        assert_eq!((chunks[0].start_line, chunks[0].end_line), (2, 4));
+        assert!(!chunks[0].content.contains('\r'));
+        assert_eq!(source_text(source, &chunks[0]), chunks[0].content);
+    }
 }
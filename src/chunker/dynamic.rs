@@ -0,0 +1,180 @@
+//! Loading tree-sitter grammars compiled as shared libraries at runtime.
+//!
+//! A grammar that isn't compiled into claudevil can be built with
+//! `tree-sitter build` and referenced from the config, either by path or by
+//! name (searched for in the configured grammar directories).
+
+use std::env::consts::{DLL_EXTENSION, DLL_PREFIX};
+use std::path::{Path, PathBuf};
+
+use libloading::Library;
+use tree_sitter::{LANGUAGE_VERSION, Language, MIN_COMPATIBLE_LANGUAGE_VERSION};
+use tree_sitter_language::LanguageFn;
+
+use crate::error::{Error, Result};
+
+/// Type of the `tree_sitter_<name>` entry point every grammar exports.
+type LanguageEntry = unsafe extern "C" fn() -> *const ();
+
+/// A grammar loaded from a shared library. The library must stay loaded for
+/// as long as the language is in use.
+pub(super) struct DynamicGrammar {
+    pub language: Language,
+    pub library: Library,
+}
+
+/// Whether `grammar` names a file rather than a grammar to look up.
+fn is_path(grammar: &str) -> bool {
+    let path = Path::new(grammar);
+    path.components().count() > 1 || path.extension().is_some()
+}
+
+/// Resolve `grammar` to a shared library: used as-is when it is a path,
+/// otherwise searched for as `<grammar>.<ext>` or `lib<grammar>.<ext>` in
+/// `search_paths`.
+pub(super) fn resolve(grammar: &str, search_paths: &[PathBuf]) -> Result<PathBuf> {
+    if grammar.ends_with(".wasm") {
+        return Err(Error::Config(format!(
+            "grammar '{grammar}' is WebAssembly, which isn't supported -- \
+             build a native library with `tree-sitter build` and point grammar at the .{DLL_EXTENSION}"
+        )));
+    }
+
+    if is_path(grammar) {
+        let path = PathBuf::from(grammar);
+        if !path.exists() {
+            return Err(Error::Config(format!(
+                "grammar library {} does not exist",
+                path.display()
+            )));
+        }
+        return Ok(path);
+    }
+
+    let candidates = [
+        format!("{grammar}.{DLL_EXTENSION}"),
+        format!("{DLL_PREFIX}{grammar}.{DLL_EXTENSION}"),
+    ];
+    search_paths
+        .iter()
+        .flat_map(|dir| candidates.iter().map(move |name| dir.join(name)))
+        .find(|path| path.exists())
+        .ok_or_else(|| {
+            let searched: Vec<String> = search_paths
+                .iter()
+                .map(|p| p.display().to_string())
+                .collect();
+            Error::Config(format!(
+                "unknown grammar '{grammar}' -- it isn't built in and no {} was found in [{}]; \
+                 build it with `tree-sitter build` and put it in one of those directories, \
+                 or add the directory to [grammars] search_paths",
+                candidates[0],
+                searched.join(", ")
+            ))
+        })
+}
+
+/// Entry point symbol for a grammar library: `tree_sitter_<name>`, where
+/// `<name>` is the file stem without `lib`/`tree-sitter-` prefixes.
+pub(super) fn entry_symbol(path: &Path) -> String {
+    let stem = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let stem = stem.strip_prefix(DLL_PREFIX).unwrap_or(&stem);
+    let name = stem.strip_prefix("tree-sitter-").unwrap_or(stem);
+    format!("tree_sitter_{}", name.replace('-', "_"))
+}
+
+/// Load the grammar in the shared library at `path`.
+pub(super) fn load(path: &Path, symbol: &str) -> Result<DynamicGrammar> {
+    // SAFETY: Loading a library runs its initializers. Grammar libraries are
+    // generated by the tree-sitter CLI and have none beyond the C runtime's;
+    // the user opted into this library by naming it in their config.
+    let library = unsafe { Library::new(path) }.map_err(|e| {
+        Error::Config(format!(
+            "failed to load grammar library {}: {e}",
+            path.display()
+        ))
+    })?;
+
+    // SAFETY: `tree_sitter_<name>` has this signature in every generated
+    // grammar. The returned language points into the library's static data,
+    // so `library` is kept alongside it.
+    let language = unsafe {
+        let entry = library
+            .get::<LanguageEntry>(symbol.as_bytes())
+            .map_err(|e| {
+                Error::Config(format!(
+                    "{} has no '{symbol}' symbol ({e}) -- set grammar_symbol in the config \
+                 if the grammar uses a different name",
+                    path.display()
+                ))
+            })?;
+        Language::new(LanguageFn::from_raw(*entry))
+    };
+
+    let abi = language.abi_version();
+    if !(MIN_COMPATIBLE_LANGUAGE_VERSION..=LANGUAGE_VERSION).contains(&abi) {
+        return Err(Error::Config(format!(
+            "{} uses tree-sitter ABI {abi}, but claudevil supports \
+             {MIN_COMPATIBLE_LANGUAGE_VERSION} through {LANGUAGE_VERSION} -- \
+             rebuild it with a matching tree-sitter CLI",
+            path.display()
+        )));
+    }
+
+    Ok(DynamicGrammar { language, library })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn entry_symbol_from_file_name() {
+        let lib = format!("{DLL_PREFIX}tree-sitter-ruby.{DLL_EXTENSION}");
+        assert_eq!(entry_symbol(Path::new(&lib)), "tree_sitter_ruby");
+        assert_eq!(
+            entry_symbol(Path::new("/opt/grammars/c-sharp.so")),
+            "tree_sitter_c_sharp"
+        );
+    }
+
+    #[test]
+    fn resolve_searches_grammar_dirs() {
+        let first = TempDir::new().unwrap();
+        let second = TempDir::new().unwrap();
+        let lib = second
+            .path()
+            .join(format!("{DLL_PREFIX}tree-sitter-zig.{DLL_EXTENSION}"));
+        std::fs::write(&lib, b"").unwrap();
+
+        let dirs = [first.path().to_path_buf(), second.path().to_path_buf()];
+        assert_eq!(resolve("tree-sitter-zig", &dirs).unwrap(), lib);
+    }
+
+    #[test]
+    fn resolve_reports_searched_dirs() {
+        let dir = TempDir::new().unwrap();
+        let err = resolve("tree-sitter-zig", &[dir.path().to_path_buf()]).unwrap_err();
+        let msg = err.to_string();
+        assert!(msg.contains("unknown grammar 'tree-sitter-zig'"), "{msg}");
+        assert!(msg.contains(&dir.path().display().to_string()), "{msg}");
+    }
+
+    #[test]
+    fn resolve_rejects_wasm() {
+        let err = resolve("/opt/grammars/zig.wasm", &[]).unwrap_err();
+        assert!(err.to_string().contains("WebAssembly"), "{err}");
+    }
+
+    #[test]
+    fn load_rejects_non_library() {
+        let dir = TempDir::new().unwrap();
+        let bogus = dir.path().join(format!("bogus.{DLL_EXTENSION}"));
+        std::fs::write(&bogus, b"not a library").unwrap();
+        assert!(load(&bogus, "tree_sitter_bogus").is_err());
+    }
+}
@@ -0,0 +1,299 @@
+//! Block-level chunking for HCL / Terraform.
+//!
+//! HCL's top level is a flat list of blocks (`resource "type" "name" { ... }`)
+//! and attributes, so a brace-matching scanner that understands strings,
+//! comments, and heredocs is enough to find block boundaries without a full
+//! grammar.
+
+use std::collections::HashSet;
+
+use super::Chunk;
+
+/// Split `source` into one chunk per top-level block whose type is in
+/// `chunk_on`, keeping comments directly above each block plus up to
+/// `overlap_lines` lines above those.
+pub(super) fn chunk_hcl(
+    source: &str,
+    chunk_on: &HashSet<String>,
+    overlap_lines: usize,
+) -> Vec<Chunk> {
+    let lines: Vec<&str> = source.lines().collect();
+    let line_starts: Vec<usize> = source
+        .split_inclusive('\n')
+        .scan(0, |offset, line| {
+            let start = *offset;
+            *offset += line.len();
+            Some(start)
+        })
+        .collect();
+    let mut chunks = Vec::new();
+    let mut scanner = Scanner::default();
+    let mut comment_start: Option<usize> = None;
+    let mut idx = 0;
+
+    while idx < lines.len() {
+        let line = lines[idx];
+        let at_top = scanner.at_top_level();
+
+        if at_top {
+            let trimmed = line.trim();
+            if trimmed.starts_with('#') || trimmed.starts_with("//") {
+                comment_start.get_or_insert(idx);
+                idx += 1;
+                continue;
+            }
+            if let Some((kind, labels)) = parse_header(trimmed)
+                && chunk_on.contains(&kind)
+            {
+                let mut start = comment_start.unwrap_or(idx);
+                start = start.saturating_sub(overlap_lines);
+                while start < idx && lines[start].trim().is_empty() {
+                    start += 1;
+                }
+                let mut end = idx;
+                scanner.feed(line);
+                while !scanner.at_top_level() && end + 1 < lines.len() {
+                    end += 1;
+                    scanner.feed(lines[end]);
+                }
+                let comment_ratio = comment_ratio(&lines[comment_start.unwrap_or(idx)..=end]);
+                chunks.push(Chunk {
+                    content: lines[start..=end].join("\n"),
+                    byte_range: Some(line_starts[start]..line_starts[end] + lines[end].len()),
+                    symbol_name: (!labels.is_empty()).then(|| labels.join(".")),
+                    symbol_kind: Some(kind),
+                    doc: None,
+                    signature: Some(trimmed.trim_end_matches('{').trim_end().to_string()),
+                    build: None,
+                    start_line: idx + 1,
+                    end_line: end + 1,
+                    comment_ratio,
+                    language: None,
+                });
+                comment_start = None;
+                idx = end + 1;
+                continue;
+            }
+        }
+
+        comment_start = None;
+        scanner.feed(line);
+        idx += 1;
+    }
+
+    chunks
+}
+
+/// Share of the non-whitespace text in `lines` on whole-line comments.
+fn comment_ratio(lines: &[&str]) -> f32 {
+    let count = |line: &&str| line.chars().filter(|c| !c.is_whitespace()).count();
+    let total: usize = lines.iter().map(count).sum();
+    if total == 0 {
+        return 0.0;
+    }
+    let comments: usize = lines
+        .iter()
+        .filter(|line| {
+            let trimmed = line.trim_start();
+            trimmed.starts_with('#') || trimmed.starts_with("//")
+        })
+        .map(count)
+        .sum();
+    comments as f32 / total as f32
+}
+
+/// Parse a block header such as `resource "aws_s3_bucket" "logs" {` into its
+/// type and labels. Attributes (`name = ...`) and anything else return `None`.
+fn parse_header(line: &str) -> Option<(String, Vec<String>)> {
+    let mut rest = line;
+    let kind = take_ident(&mut rest)?;
+    let mut labels = Vec::new();
+
+    loop {
+        rest = rest.trim_start();
+        if rest.starts_with('{') {
+            return Some((kind, labels));
+        }
+        if let Some(quoted) = rest.strip_prefix('"') {
+            let end = quoted.find('"')?;
+            labels.push(quoted[..end].to_string());
+            rest = &quoted[end + 1..];
+        } else {
+            labels.push(take_ident(&mut rest)?);
+        }
+    }
+}
+
+fn take_ident(rest: &mut &str) -> Option<String> {
+    let s = rest.trim_start();
+    let first = s.chars().next()?;
+    if !(first.is_ascii_alphabetic() || first == '_') {
+        return None;
+    }
+    let end = s
+        .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '-'))
+        .unwrap_or(s.len());
+    *rest = &s[end..];
+    Some(s[..end].to_string())
+}
+
+/// Tracks brace depth across lines, ignoring braces inside strings,
+/// comments, and heredocs.
+#[derive(Default)]
+struct Scanner {
+    depth: usize,
+    in_block_comment: bool,
+    heredoc: Option<String>,
+}
+
+impl Scanner {
+    fn at_top_level(&self) -> bool {
+        self.depth == 0 && !self.in_block_comment && self.heredoc.is_none()
+    }
+
+    fn feed(&mut self, line: &str) {
+        if let Some(tag) = &self.heredoc {
+            if line.trim() == tag {
+                self.heredoc = None;
+            }
+            return;
+        }
+
+        let bytes = line.as_bytes();
+        let mut in_string = false;
+        let mut i = 0;
+        while i < bytes.len() {
+            let next = bytes.get(i + 1).copied();
+            if self.in_block_comment {
+                if bytes[i] == b'*' && next == Some(b'/') {
+                    self.in_block_comment = false;
+                    i += 1;
+                }
+            } else if in_string {
+                match bytes[i] {
+                    b'\\' => i += 1,
+                    b'"' => in_string = false,
+                    _ => {}
+                }
+            } else {
+                match (bytes[i], next) {
+                    (b'"', _) => in_string = true,
+                    (b'#', _) | (b'/', Some(b'/')) => return,
+                    (b'/', Some(b'*')) => {
+                        self.in_block_comment = true;
+                        i += 1;
+                    }
+                    (b'{', _) => self.depth += 1,
+                    (b'}', _) => self.depth = self.depth.saturating_sub(1),
+                    (b'<', Some(b'<')) => {
+                        let tag = line[i + 2..].trim_start_matches('-').trim();
+                        if !tag.is_empty()
+                            && tag.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+                        {
+                            self.heredoc = Some(tag.to_string());
+                            return;
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            i += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kinds(list: &[&str]) -> HashSet<String> {
+        list.iter().map(|s| (*s).to_string()).collect()
+    }
+
+    #[test]
+    fn resource_and_module_blocks() {
+        let source = r#"terraform {
+  required_version = ">= 1.5"
+}
+
+# Bucket for access logs
+resource "aws_s3_bucket" "logs" {
+  bucket = "acme-logs"
+  tags = {
+    team = "infra"
+  }
+}
+
+module "vpc" {
+  source = "terraform-aws-modules/vpc/aws"
+}
+"#;
+        let chunks = chunk_hcl(source, &kinds(&["resource", "module"]), 0);
+        assert_eq!(chunks.len(), 2);
+
+        assert_eq!(chunks[0].symbol_name.as_deref(), Some("aws_s3_bucket.logs"));
+        assert_eq!(chunks[0].symbol_kind.as_deref(), Some("resource"));
+        assert!(chunks[0].content.starts_with("# Bucket for access logs"));
+        assert!(chunks[0].content.ends_with('}'));
+        assert_eq!((chunks[0].start_line, chunks[0].end_line), (6, 11));
+
+        assert_eq!(chunks[1].symbol_name.as_deref(), Some("vpc"));
+        assert_eq!((chunks[1].start_line, chunks[1].end_line), (13, 15));
+        assert!(chunks[0].comment_ratio > 0.0 && chunks[0].comment_ratio < 0.5);
+        assert_eq!(chunks[1].comment_ratio, 0.0);
+    }
+
+    #[test]
+    fn braces_in_strings_comments_and_heredocs_are_ignored() {
+        let source = r#"resource "aws_iam_policy" "p" {
+  name = "weird-}-name"
+  # closing } in a comment
+  policy = <<-EOT
+    { "Version": "2012-10-17" }
+    }
+  EOT
+}
+
+output "id" {
+  value = aws_iam_policy.p.id
+}
+"#;
+        let chunks = chunk_hcl(source, &kinds(&["resource", "output"]), 0);
+        assert_eq!(chunks.len(), 2);
+        assert_eq!((chunks[0].start_line, chunks[0].end_line), (1, 8));
+        assert_eq!(chunks[1].symbol_name.as_deref(), Some("id"));
+    }
+
+    #[test]
+    fn unlabeled_blocks_have_no_symbol() {
+        let chunks = chunk_hcl("locals {\n  a = 1\n}\n", &kinds(&["locals"]), 0);
+        assert_eq!(chunks.len(), 1);
+        assert_eq!(chunks[0].symbol_name, None);
+        assert_eq!(chunks[0].symbol_kind.as_deref(), Some("locals"));
+    }
+
+    #[test]
+    fn top_level_attributes_are_skipped() {
+        let source = "region = \"us-east-1\"\ntags = {\n  a = \"b\"\n}\n";
+        assert!(chunk_hcl(source, &kinds(&["resource"]), 0).is_empty());
+    }
+
+    #[test]
+    fn single_line_block() {
+        let chunks = chunk_hcl("variable \"region\" {}\n", &kinds(&["variable"]), 0);
+        assert_eq!(chunks.len(), 1);
+        assert_eq!((chunks[0].start_line, chunks[0].end_line), (1, 1));
+    }
+
+    #[test]
+    fn overlap_includes_lines_above_the_comments() {
+        let source = "locals {\n  a = 1\n}\n\n# The VPC\nresource \"aws_vpc\" \"main\" {}\n";
+        let chunks = chunk_hcl(source, &kinds(&["resource"]), 3);
+        assert_eq!(chunks.len(), 1);
+        assert_eq!(
+            chunks[0].content,
+            "  a = 1\n}\n\n# The VPC\nresource \"aws_vpc\" \"main\" {}"
+        );
+        assert_eq!(chunks[0].start_line, 6);
+    }
+}
@@ -0,0 +1,505 @@
+//! Declaration-level chunking for Protocol Buffers and Thrift IDL.
+//!
+//! Both are C-like: declarations are a keyword, a name, and a braced body,
+//! with comments in `//`, `/* */`, or (Thrift) `#`. A small tokenizer that
+//! skips strings and comments is enough to find messages, structs, enums,
+//! and services, and the rpcs (Protobuf) or functions (Thrift) inside a
+//! service, without a grammar.
+
+use std::collections::HashSet;
+use std::ops::Range;
+
+use super::Chunk;
+
+/// Keywords that open a braced declaration worth chunking.
+const BLOCK_KEYWORDS: &[&str] = &["message", "enum", "service", "struct", "union", "exception"];
+
+/// Kind given to the methods of a Thrift service, which have no keyword.
+const FUNCTION_KIND: &str = "function";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Token<'a> {
+    Word(&'a str),
+    Punct(char),
+    Str,
+}
+
+/// Tokens of an IDL file with their byte ranges, and its comments.
+struct Lexed<'a> {
+    tokens: Vec<(Token<'a>, Range<usize>)>,
+    comments: Vec<Range<usize>>,
+}
+
+fn lex(source: &str) -> Lexed<'_> {
+    let bytes = source.as_bytes();
+    let mut tokens = Vec::new();
+    let mut comments = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        let start = i;
+        match (bytes[i], bytes.get(i + 1).copied()) {
+            (b'/', Some(b'/')) | (b'#', _) => {
+                while i < bytes.len() && bytes[i] != b'\n' {
+                    i += 1;
+                }
+                comments.push(start..i);
+            }
+            (b'/', Some(b'*')) => {
+                i += 2;
+                while i < bytes.len() && !(bytes[i] == b'*' && bytes.get(i + 1) == Some(&b'/')) {
+                    i += 1;
+                }
+                i = (i + 2).min(bytes.len());
+                comments.push(start..i);
+            }
+            (quote @ (b'"' | b'\''), _) => {
+                i += 1;
+                while i < bytes.len() && bytes[i] != quote {
+                    if bytes[i] == b'\\' {
+                        i += 1;
+                    }
+                    i += 1;
+                }
+                i = (i + 1).min(bytes.len());
+                tokens.push((Token::Str, start..i));
+            }
+            (c, _) if c.is_ascii_whitespace() => i += 1,
+            (c, _) if c.is_ascii_alphanumeric() || c == b'_' => {
+                while i < bytes.len() && (bytes[i].is_ascii_alphanumeric() || bytes[i] == b'_') {
+                    i += 1;
+                }
+                tokens.push((Token::Word(&source[start..i]), start..i));
+            }
+            _ => {
+                // Any other character, multi-byte ones whole
+                let len = source[i..].chars().next().map_or(1, char::len_utf8);
+                i += len;
+                let c = source[start..i].chars().next().unwrap_or(' ');
+                tokens.push((Token::Punct(c), start..i));
+            }
+        }
+    }
+    Lexed { tokens, comments }
+}
+
+/// Split `source` into one chunk per declaration whose kind is in
+/// `chunk_on`: `message`, `enum`, `service`, and `rpc` in Protobuf;
+/// `struct`, `union`, `exception`, `enum`, `service`, and `function` (a
+/// service method) in Thrift. Nested declarations are chunked too, named
+/// after their parents, e.g. `Greeter.SayHello`.
+pub(super) fn chunk_idl(source: &str, chunk_on: &HashSet<String>) -> Vec<Chunk> {
+    let lexed = lex(source);
+    let mut parser = Parser {
+        source,
+        lexed: &lexed,
+        chunk_on,
+        line_starts: std::iter::once(0)
+            .chain(source.match_indices('\n').map(|(i, _)| i + 1))
+            .collect(),
+        chunks: Vec::new(),
+    };
+    parser.body(0, lexed.tokens.len(), None, false);
+    parser.chunks
+}
+
+struct Parser<'a> {
+    source: &'a str,
+    lexed: &'a Lexed<'a>,
+    chunk_on: &'a HashSet<String>,
+    line_starts: Vec<usize>,
+    chunks: Vec<Chunk>,
+}
+
+impl<'a> Parser<'a> {
+    fn token(&self, i: usize) -> Option<Token<'a>> {
+        self.lexed.tokens.get(i).map(|(t, _)| *t)
+    }
+
+    /// Index of the token closing the bracket `open` at `i`, or the end.
+    fn matching(&self, i: usize, open: char, close: char) -> usize {
+        let mut depth = 0;
+        for j in i..self.lexed.tokens.len() {
+            match self.token(j) {
+                Some(Token::Punct(c)) if c == open => depth += 1,
+                Some(Token::Punct(c)) if c == close => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return j;
+                    }
+                }
+                _ => {}
+            }
+        }
+        self.lexed.tokens.len() - 1
+    }
+
+    /// Chunk the declarations among tokens `start..end`, the body of the
+    /// declaration named `scope`, if any, which is a service if `service`.
+    fn body(&mut self, start: usize, end: usize, scope: Option<&str>, service: bool) {
+        let mut i = start;
+        while i < end {
+            if let Some(next) = self.declaration(i, end, scope, service) {
+                i = next;
+                continue;
+            }
+            i = match self.token(i) {
+                // Option blocks, oneofs, and the like hold nothing to chunk
+                Some(Token::Punct('{')) => self.matching(i, '{', '}') + 1,
+                _ => i + 1,
+            };
+        }
+    }
+
+    /// Chunk the declaration starting at token `i`, if one does, returning
+    /// the index of the token after it.
+    fn declaration(
+        &mut self,
+        i: usize,
+        end: usize,
+        scope: Option<&str>,
+        service: bool,
+    ) -> Option<usize> {
+        let Token::Word(keyword) = self.token(i)? else {
+            return None;
+        };
+        if BLOCK_KEYWORDS.contains(&keyword) {
+            let Some(Token::Word(name)) = self.token(i + 1) else {
+                return None;
+            };
+            // `service Calculator extends shared.Base {` in Thrift
+            let mut open = i + 2;
+            while open < end && matches!(self.token(open)?, Token::Word(_) | Token::Punct('.')) {
+                open += 1;
+            }
+            if open >= end || self.token(open)? != Token::Punct('{') {
+                return None;
+            }
+            let close = self.matching(open, '{', '}').min(end);
+            let name = qualified(scope, name);
+            self.push(keyword, name.clone(), i, open, close);
+            self.body(open + 1, close, Some(&name), keyword == "service");
+            return Some(close + 1);
+        }
+        if !service {
+            return None;
+        }
+
+        if keyword == "rpc" {
+            // `rpc Name(Request) returns (Response);`, or with an options body
+            let Some(Token::Word(name)) = self.token(i + 1) else {
+                return None;
+            };
+            let mut j = i + 2;
+            while j < end && !matches!(self.token(j)?, Token::Punct(';' | '{')) {
+                j += 1;
+            }
+            let signature_end = j;
+            if self.token(j) == Some(Token::Punct('{')) {
+                j = self.matching(j, '{', '}').min(end);
+            }
+            self.push("rpc", qualified(scope, name), i, signature_end, j);
+            return Some(j + 1);
+        }
+
+        // A Thrift method: `[oneway] ReturnType name(args) [throws (...)]`
+        let mut paren = i;
+        while paren < end {
+            match self.token(paren)? {
+                Token::Punct('(') => break,
+                Token::Word(_) | Token::Punct('<' | '>' | ',' | '.') => paren += 1,
+                _ => return None,
+            }
+        }
+        let Some(Token::Word(name)) = paren.checked_sub(1).and_then(|p| self.token(p)) else {
+            return None;
+        };
+        if paren >= end || paren == i {
+            return None;
+        }
+        let mut last = self.matching(paren, '(', ')').min(end);
+        if self.token(last + 1) == Some(Token::Word("throws"))
+            && self.token(last + 2) == Some(Token::Punct('('))
+        {
+            last = self.matching(last + 2, '(', ')').min(end);
+        }
+        let signature_end = last + 1;
+        if matches!(self.token(last + 1), Some(Token::Punct(',' | ';'))) {
+            last += 1;
+        }
+        self.push(
+            FUNCTION_KIND,
+            qualified(scope, name),
+            i,
+            signature_end,
+            last,
+        );
+        Some(last + 1)
+    }
+
+    /// Record the declaration of `kind` spanning tokens `first..=last`,
+    /// whose signature ends before token `signature_end`, if its kind is
+    /// chunked.
+    fn push(&mut self, kind: &str, name: String, first: usize, signature_end: usize, last: usize) {
+        if !self.chunk_on.contains(kind) {
+            return;
+        }
+        let tokens = &self.lexed.tokens;
+        let decl_start = tokens[first].1.start;
+        let end = tokens[last.min(tokens.len() - 1)].1.end;
+        let signature_end = tokens
+            .get(signature_end)
+            .map_or(end, |(_, range)| range.start);
+        let leading = self.leading_comments(decl_start);
+        let start = leading.first().map_or(decl_start, |c| c.start);
+
+        let doc = leading
+            .iter()
+            .filter_map(|c| comment_text(&self.source[c.clone()]))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let signature = self.source[decl_start..signature_end]
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ");
+        self.chunks.push(Chunk {
+            content: self.source[start..end].to_string(),
+            byte_range: Some(start..end),
+            symbol_name: Some(name),
+            symbol_kind: Some(kind.to_string()),
+            doc: (!doc.is_empty()).then_some(doc),
+            signature: (!signature.is_empty()).then_some(signature),
+            build: None,
+            start_line: self.line(decl_start),
+            end_line: self.line(end.saturating_sub(1)),
+            comment_ratio: self.comment_ratio(start..end),
+            language: None,
+        });
+    }
+
+    /// Comments directly above the byte `start`, each on its own line, with
+    /// no blank line between them or after the last.
+    fn leading_comments(&self, start: usize) -> Vec<Range<usize>> {
+        let mut leading = Vec::new();
+        let mut next = start;
+        for comment in self.lexed.comments.iter().rev() {
+            if comment.end > next {
+                continue;
+            }
+            let between = &self.source[comment.end..next];
+            let line_start = self.line_starts[self.line(comment.start) - 1];
+            if !between.trim().is_empty()
+                || between.matches('\n').count() > 1
+                || !self.source[line_start..comment.start].trim().is_empty()
+            {
+                break;
+            }
+            leading.push(comment.clone());
+            next = comment.start;
+        }
+        leading.reverse();
+        leading
+    }
+
+    /// 1-indexed line of byte `offset`.
+    fn line(&self, offset: usize) -> usize {
+        self.line_starts.partition_point(|&start| start <= offset)
+    }
+
+    /// Share of the non-whitespace text in `range` inside comments.
+    fn comment_ratio(&self, range: Range<usize>) -> f32 {
+        let count = |text: &str| text.chars().filter(|c| !c.is_whitespace()).count();
+        let total = count(&self.source[range.clone()]);
+        if total == 0 {
+            return 0.0;
+        }
+        let comments: usize = self
+            .lexed
+            .comments
+            .iter()
+            .filter(|c| c.start >= range.start && c.end <= range.end)
+            .map(|c| count(&self.source[c.clone()]))
+            .sum();
+        comments as f32 / total as f32
+    }
+}
+
+fn qualified(scope: Option<&str>, name: &str) -> String {
+    match scope {
+        Some(scope) => format!("{scope}.{name}"),
+        None => name.to_string(),
+    }
+}
+
+/// The text of a comment without its markers, or `None` if there is none.
+fn comment_text(comment: &str) -> Option<String> {
+    let body = comment
+        .strip_prefix("/*")
+        .map(|c| c.strip_suffix("*/").unwrap_or(c))
+        .or_else(|| comment.strip_prefix("//"))
+        .or_else(|| comment.strip_prefix('#'))
+        .unwrap_or(comment);
+    let text = body
+        .lines()
+        .map(|line| {
+            let line = line.trim();
+            line.strip_prefix('*')
+                .or_else(|| line.strip_prefix('/'))
+                .unwrap_or(line)
+                .trim()
+        })
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n");
+    (!text.is_empty()).then_some(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kinds(list: &[&str]) -> HashSet<String> {
+        list.iter().map(|s| (*s).to_string()).collect()
+    }
+
+    #[test]
+    fn protobuf_messages_services_and_rpcs() {
+        let source = r#"syntax = "proto3";
+
+package greet.v1;
+
+option go_package = "example.com/greet/v1;greetv1";
+
+// A request to be greeted.
+// Names are case-sensitive.
+message HelloRequest {
+  string name = 1; // who to greet
+  message Locale {
+    string tag = 1;
+  }
+  oneof extra {
+    string note = 2;
+  }
+}
+
+enum Mood {
+  MOOD_UNSPECIFIED = 0;
+  MOOD_HAPPY = 1;
+}
+
+/* Greets people. */
+service Greeter {
+  // Says hello.
+  rpc SayHello(HelloRequest) returns (HelloReply);
+  rpc Chat(stream HelloRequest) returns (stream HelloReply) {
+    option deprecated = true;
+  }
+}
+"#;
+        let chunks = chunk_idl(source, &kinds(&["message", "enum", "service", "rpc"]));
+        let names: Vec<(&str, &str)> = chunks
+            .iter()
+            .map(|c| {
+                (
+                    c.symbol_kind.as_deref().unwrap(),
+                    c.symbol_name.as_deref().unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            names,
+            [
+                ("message", "HelloRequest"),
+                ("message", "HelloRequest.Locale"),
+                ("enum", "Mood"),
+                ("service", "Greeter"),
+                ("rpc", "Greeter.SayHello"),
+                ("rpc", "Greeter.Chat"),
+            ]
+        );
+
+        let request = &chunks[0];
+        assert!(request.content.starts_with("// A request to be greeted."));
+        assert_eq!(
+            request.doc.as_deref(),
+            Some("A request to be greeted.\nNames are case-sensitive.")
+        );
+        assert_eq!(request.start_line, 9);
+        assert_eq!(request.end_line, 17);
+        assert_eq!(request.signature.as_deref(), Some("message HelloRequest"));
+        assert_eq!(
+            &source[request.byte_range.clone().unwrap()],
+            request.content
+        );
+
+        let say_hello = &chunks[4];
+        assert_eq!(say_hello.doc.as_deref(), Some("Says hello."));
+        assert_eq!(
+            say_hello.signature.as_deref(),
+            Some("rpc SayHello(HelloRequest) returns (HelloReply)")
+        );
+        assert_eq!(chunks[3].doc.as_deref(), Some("Greets people."));
+    }
+
+    #[test]
+    fn thrift_structs_and_service_functions() {
+        let source = r#"namespace go tutorial
+
+# A unit of work.
+struct Work {
+  1: i32 num1 = 0,
+  2: i32 num2,
+  3: optional string comment,
+}
+
+exception InvalidOperation {
+  1: i32 whatOp
+}
+
+service Calculator extends shared.SharedService {
+  void ping(),
+  /** Adds two numbers. */
+  i32 add(1: i32 num1, 2: i32 num2)
+  i32 calculate(1: i32 logid, 2: Work w) throws (1: InvalidOperation ouch);
+  oneway void zip()
+  map<string, list<i32>> stats()
+}
+"#;
+        let chunks = chunk_idl(
+            source,
+            &kinds(&["struct", "exception", "service", "function"]),
+        );
+        let names: Vec<&str> = chunks
+            .iter()
+            .map(|c| c.symbol_name.as_deref().unwrap())
+            .collect();
+        assert_eq!(
+            names,
+            [
+                "Work",
+                "InvalidOperation",
+                "Calculator",
+                "Calculator.ping",
+                "Calculator.add",
+                "Calculator.calculate",
+                "Calculator.zip",
+                "Calculator.stats",
+            ]
+        );
+        assert_eq!(chunks[0].doc.as_deref(), Some("A unit of work."));
+        assert_eq!(chunks[4].doc.as_deref(), Some("Adds two numbers."));
+        assert_eq!(
+            chunks[5].signature.as_deref(),
+            Some("i32 calculate(1: i32 logid, 2: Work w) throws (1: InvalidOperation ouch)")
+        );
+        assert_eq!(chunks[5].symbol_kind.as_deref(), Some("function"));
+        assert_eq!(chunks[6].signature.as_deref(), Some("oneway void zip()"));
+    }
+
+    #[test]
+    fn fields_named_like_keywords_are_not_declarations() {
+        let source = "message Envelope {\n  string message = 1;\n  Kind service = 2;\n}\n";
+        let chunks = chunk_idl(source, &kinds(&["message", "service"]));
+        assert_eq!(chunks.len(), 1);
+        assert_eq!(chunks[0].symbol_name.as_deref(), Some("Envelope"));
+    }
+}
@@ -0,0 +1,238 @@
+//! Cell-level chunking for Jupyter notebooks.
+//!
+//! A notebook (nbformat 4) is JSON: a list of cells, each with a type and
+//! its source, and metadata naming the kernel's language. Cells have no
+//! lines of their own once escaped into the file, so cell N (counting from
+//! 1, over every cell) stands in as line N.
+
+use std::collections::HashSet;
+
+use serde::Deserialize;
+
+use super::Chunk;
+use crate::error::{Error, Result};
+
+/// Kernel language assumed when the metadata doesn't name one.
+const DEFAULT_LANGUAGE: &str = "python";
+
+/// Language markdown cells are stored under.
+const MARKDOWN_LANGUAGE: &str = "markdown";
+
+#[derive(Deserialize)]
+struct Notebook {
+    cells: Vec<Cell>,
+    #[serde(default)]
+    metadata: Metadata,
+}
+
+#[derive(Default, Deserialize)]
+struct Metadata {
+    kernelspec: Option<KernelSpec>,
+    language_info: Option<LanguageInfo>,
+}
+
+#[derive(Deserialize)]
+struct KernelSpec {
+    language: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct LanguageInfo {
+    name: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Cell {
+    cell_type: String,
+    #[serde(default)]
+    source: Source,
+}
+
+/// Cell source, stored either whole or as a list of lines.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Source {
+    Lines(Vec<String>),
+    Text(String),
+}
+
+impl Default for Source {
+    fn default() -> Self {
+        Self::Text(String::new())
+    }
+}
+
+impl Source {
+    fn text(self) -> String {
+        match self {
+            Self::Lines(lines) => lines.concat(),
+            Self::Text(text) => text,
+        }
+    }
+}
+
+/// Split the notebook `source` into one chunk per non-empty cell whose type
+/// (`code`, `markdown`, or `raw`) is in `chunk_on`. Code cells take the
+/// kernel's language from the metadata, markdown cells `markdown`.
+pub(super) fn chunk_notebook(source: &str, chunk_on: &HashSet<String>) -> Result<Vec<Chunk>> {
+    let notebook: Notebook = serde_json::from_str(source).map_err(|e| {
+        Error::Notebook(format!(
+            "{e} -- only nbformat 4 notebooks are read; convert older ones with \
+             `jupyter nbconvert --to notebook`"
+        ))
+    })?;
+    let kernel = notebook
+        .metadata
+        .language_info
+        .and_then(|info| info.name)
+        .or_else(|| notebook.metadata.kernelspec.and_then(|spec| spec.language))
+        .map(|name| name.to_lowercase())
+        .unwrap_or_else(|| DEFAULT_LANGUAGE.to_string());
+
+    let mut chunks = Vec::new();
+    for (idx, cell) in notebook.cells.into_iter().enumerate() {
+        if !chunk_on.contains(&cell.cell_type) {
+            continue;
+        }
+        let content = cell.source.text().trim_end().to_string();
+        if content.trim().is_empty() {
+            continue;
+        }
+        let markdown = cell.cell_type == "markdown";
+        chunks.push(Chunk {
+            symbol_name: if markdown {
+                heading(&content)
+            } else {
+                definition(&content)
+            },
+            symbol_kind: Some(format!("{}_cell", cell.cell_type)),
+            comment_ratio: if markdown {
+                1.0
+            } else {
+                comment_ratio(&content)
+            },
+            language: Some(if markdown {
+                MARKDOWN_LANGUAGE.to_string()
+            } else {
+                kernel.clone()
+            }),
+            content,
+            byte_range: None,
+            doc: None,
+            signature: None,
+            build: None,
+            start_line: idx + 1,
+            end_line: idx + 1,
+        });
+    }
+    Ok(chunks)
+}
+
+/// The first heading of a markdown cell, without its `#`s.
+fn heading(content: &str) -> Option<String> {
+    content
+        .lines()
+        .find_map(|line| line.strip_prefix('#'))
+        .map(|title| title.trim_start_matches('#').trim().to_string())
+        .filter(|title| !title.is_empty())
+}
+
+/// The name of the first function or class a code cell defines at its top
+/// level, so cells can be found by symbol.
+fn definition(content: &str) -> Option<String> {
+    content.lines().find_map(|line| {
+        let rest = line
+            .strip_prefix("async def ")
+            .or_else(|| line.strip_prefix("def "))
+            .or_else(|| line.strip_prefix("class "))?;
+        let name: String = rest
+            .chars()
+            .take_while(|c| c.is_alphanumeric() || *c == '_')
+            .collect();
+        (!name.is_empty()).then_some(name)
+    })
+}
+
+/// Share of the non-whitespace text of a code cell on `#` comment lines.
+fn comment_ratio(content: &str) -> f32 {
+    let count = |line: &str| line.chars().filter(|c| !c.is_whitespace()).count();
+    let total: usize = content.lines().map(count).sum();
+    if total == 0 {
+        return 0.0;
+    }
+    let comments: usize = content
+        .lines()
+        .filter(|line| line.trim_start().starts_with('#'))
+        .map(count)
+        .sum();
+    comments as f32 / total as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kinds(list: &[&str]) -> HashSet<String> {
+        list.iter().map(|s| (*s).to_string()).collect()
+    }
+
+    const NOTEBOOK: &str = r##"{
+ "cells": [
+  {"cell_type": "markdown", "metadata": {}, "source": ["# Load the data\n", "From the bucket."]},
+  {"cell_type": "code", "execution_count": 1, "metadata": {}, "outputs": [],
+   "source": ["import pandas as pd\n", "\n", "def load(path):\n", "    return pd.read_csv(path)"]},
+  {"cell_type": "code", "metadata": {}, "outputs": [], "source": []},
+  {"cell_type": "code", "metadata": {}, "outputs": [], "source": "# plot it\nload('x.csv').plot()\n"}
+ ],
+ "metadata": {"kernelspec": {"name": "ir", "language": "R"}, "language_info": {"name": "python"}},
+ "nbformat": 4,
+ "nbformat_minor": 5
+}"##;
+
+    #[test]
+    fn code_cells_become_chunks_numbered_by_cell() {
+        let chunks = chunk_notebook(NOTEBOOK, &kinds(&["code"])).unwrap();
+        assert_eq!(chunks.len(), 2);
+
+        assert_eq!(
+            chunks[0].content,
+            "import pandas as pd\n\ndef load(path):\n    return pd.read_csv(path)"
+        );
+        assert_eq!((chunks[0].start_line, chunks[0].end_line), (2, 2));
+        assert_eq!(chunks[0].symbol_name.as_deref(), Some("load"));
+        assert_eq!(chunks[0].symbol_kind.as_deref(), Some("code_cell"));
+        assert_eq!(chunks[0].language.as_deref(), Some("python"));
+        assert!(chunks[0].byte_range.is_none());
+
+        // The empty third cell still counts toward numbering
+        assert_eq!(chunks[1].start_line, 4);
+        assert!(chunks[1].comment_ratio > 0.0 && chunks[1].comment_ratio < 0.5);
+    }
+
+    #[test]
+    fn markdown_cells_only_when_asked_for() {
+        let chunks = chunk_notebook(NOTEBOOK, &kinds(&["code", "markdown"])).unwrap();
+        assert_eq!(chunks.len(), 3);
+        assert_eq!(chunks[0].content, "# Load the data\nFrom the bucket.");
+        assert_eq!(chunks[0].symbol_name.as_deref(), Some("Load the data"));
+        assert_eq!(chunks[0].language.as_deref(), Some("markdown"));
+        assert_eq!(chunks[0].start_line, 1);
+    }
+
+    #[test]
+    fn kernel_language_falls_back_to_kernelspec_then_python() {
+        let r = r#"{"cells": [{"cell_type": "code", "source": "x <- 1"}],
+                    "metadata": {"kernelspec": {"language": "R"}}}"#;
+        let chunks = chunk_notebook(r, &kinds(&["code"])).unwrap();
+        assert_eq!(chunks[0].language.as_deref(), Some("r"));
+
+        let bare = r#"{"cells": [{"cell_type": "code", "source": "x = 1"}]}"#;
+        let chunks = chunk_notebook(bare, &kinds(&["code"])).unwrap();
+        assert_eq!(chunks[0].language.as_deref(), Some("python"));
+
+        let err = chunk_notebook("{\"worksheets\": []}", &kinds(&["code"]))
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("nbformat 4"), "{err}");
+    }
+}
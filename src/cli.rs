@@ -0,0 +1,445 @@
+use std::path::PathBuf;
+
+use anyhow::{Result, bail};
+
+pub const USAGE: &str = "\
+usage:
+  claudevil [--read-only] [--no-git-root] [ROOT...]
+                                      serve ROOT over MCP stdio; by default, the top of the git
+                                      repository holding the current directory, or the current
+                                      directory itself with --no-git-root or outside a repository;
+                                      several ROOTs share one process and model, and tool calls
+                                      pick one with their `root` argument;
+                                      --read-only serves an existing index without modifying it
+  claudevil export ARCHIVE [ROOT]     bundle ROOT's index into ARCHIVE
+  claudevil import ARCHIVE [ROOT]     replace ROOT's index with the contents of ARCHIVE
+  claudevil eval SUITE [ROOT]         score ROOT's index against the queries in SUITE (YAML)
+  claudevil bench [FILES]             time chunking, embedding, the store, and a full index
+                                      of FILES (default 200) generated Go files
+  claudevil gc [--dry-run] [--older-than DAYS]
+                                      delete the indexes of roots that no longer exist and,
+                                      with --older-than, of roots not served for DAYS days;
+                                      --dry-run only lists them
+  claudevil relink OLD_ROOT [ROOT]    move the index built for OLD_ROOT, since moved or
+                                      renamed, over to ROOT
+  claudevil audit tail [N] [ROOT]     print the last N (default 20) tool calls logged for ROOT";
+
+/// Generated files indexed by `claudevil bench` unless given.
+const DEFAULT_BENCH_FILES: usize = 200;
+
+/// Tool calls printed by `claudevil audit tail` unless given.
+const DEFAULT_AUDIT_LINES: usize = 20;
+
+/// A parsed command line.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Command {
+    /// Print usage and exit.
+    Help,
+    /// Index `roots` and serve them over MCP stdio. With `read_only`, serve
+    /// the existing indexes as-is and never write to them. Without `roots`,
+    /// serve the enclosing git repository's top level if `git_root`, else
+    /// the current directory.
+    Serve {
+        roots: Vec<PathBuf>,
+        read_only: bool,
+        git_root: bool,
+    },
+    /// Write the index for `root` to `archive`.
+    Export {
+        archive: PathBuf,
+        root: Option<PathBuf>,
+    },
+    /// Replace the index for `root` with the contents of `archive`.
+    Import {
+        archive: PathBuf,
+        root: Option<PathBuf>,
+    },
+    /// Run the eval suite at `suite` against the index for `root`.
+    Eval {
+        suite: PathBuf,
+        root: Option<PathBuf>,
+    },
+    /// Benchmark indexing and search on `files` generated files.
+    Bench { files: usize },
+    /// Delete the data directories of roots that no longer exist, and of
+    /// ones not served for `older_than` days; with `dry_run`, only list
+    /// them.
+    Gc {
+        dry_run: bool,
+        older_than: Option<u64>,
+    },
+    /// Move the index built for `old_root` over to `root`.
+    Relink {
+        old_root: PathBuf,
+        root: Option<PathBuf>,
+    },
+    /// Print the last `lines` tool calls in the audit log for `root`.
+    AuditTail { lines: usize, root: Option<PathBuf> },
+}
+
+/// Parse arguments (excluding the program name).
+pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Command> {
+    let mut read_only = false;
+    let mut git_root = true;
+    let mut args = args
+        .into_iter()
+        .filter(|arg| match arg.as_str() {
+            "--read-only" => {
+                read_only = true;
+                false
+            }
+            "--no-git-root" => {
+                git_root = false;
+                false
+            }
+            _ => true,
+        })
+        .collect::<Vec<_>>()
+        .into_iter();
+    let first = args.next();
+
+    let command = match first.as_deref() {
+        Some("-h" | "--help") => Command::Help,
+        Some("bench") => {
+            if read_only {
+                bail!("--read-only only applies when serving\n\n{USAGE}");
+            }
+            let files = match args.next() {
+                Some(files) => match files.parse() {
+                    Ok(files) if files > 0 => files,
+                    _ => {
+                        bail!("`claudevil bench` takes a number of files, not '{files}'\n\n{USAGE}")
+                    }
+                },
+                None => DEFAULT_BENCH_FILES,
+            };
+            Command::Bench { files }
+        }
+        Some("gc") => {
+            if read_only {
+                bail!("--read-only only applies when serving\n\n{USAGE}");
+            }
+            let mut dry_run = false;
+            let mut older_than = None;
+            while let Some(arg) = args.next() {
+                match arg.as_str() {
+                    "--dry-run" => dry_run = true,
+                    "--older-than" => {
+                        let days = args.next().unwrap_or_default();
+                        match days.parse() {
+                            Ok(days) if days > 0 => older_than = Some(days),
+                            _ => bail!(
+                                "--older-than takes a number of days, not '{days}'\n\n{USAGE}"
+                            ),
+                        }
+                    }
+                    other => bail!("unexpected argument '{other}'\n\n{USAGE}"),
+                }
+            }
+            Command::Gc {
+                dry_run,
+                older_than,
+            }
+        }
+        Some("relink") => {
+            if read_only {
+                bail!("--read-only only applies when serving\n\n{USAGE}");
+            }
+            let Some(old_root) = args.next().map(PathBuf::from) else {
+                bail!("`claudevil relink` needs the OLD_ROOT the index was built for\n\n{USAGE}");
+            };
+            if !old_root.is_absolute() {
+                bail!(
+                    "OLD_ROOT must be the absolute path the index was built for, not '{}'\n\n{USAGE}",
+                    old_root.display()
+                );
+            }
+            Command::Relink {
+                old_root,
+                root: args.next().map(PathBuf::from),
+            }
+        }
+        Some("audit") => {
+            if read_only {
+                bail!("--read-only only applies when serving\n\n{USAGE}");
+            }
+            match args.next().as_deref() {
+                Some("tail") => {}
+                Some(other) => bail!("unknown audit command '{other}'\n\n{USAGE}"),
+                None => bail!("`claudevil audit` needs a command: tail\n\n{USAGE}"),
+            }
+            let mut next = args.next();
+            let lines = match next.as_deref().map(str::parse) {
+                Some(Ok(lines)) => {
+                    next = args.next();
+                    lines
+                }
+                _ => DEFAULT_AUDIT_LINES,
+            };
+            Command::AuditTail {
+                lines,
+                root: next.map(PathBuf::from),
+            }
+        }
+        Some(sub @ ("export" | "import" | "eval")) => {
+            let what = if sub == "eval" {
+                "a SUITE"
+            } else {
+                "an ARCHIVE"
+            };
+            let Some(path) = args.next() else {
+                bail!("`claudevil {sub}` needs {what} path\n\n{USAGE}");
+            };
+            let path = PathBuf::from(path);
+            let root = args.next().map(PathBuf::from);
+            if read_only {
+                bail!("--read-only only applies when serving\n\n{USAGE}");
+            }
+            match sub {
+                "export" => Command::Export {
+                    archive: path,
+                    root,
+                },
+                "import" => Command::Import {
+                    archive: path,
+                    root,
+                },
+                _ => Command::Eval { suite: path, root },
+            }
+        }
+        _ => Command::Serve {
+            roots: first
+                .into_iter()
+                .chain(args.by_ref())
+                .map(PathBuf::from)
+                .collect(),
+            read_only,
+            git_root,
+        },
+    };
+    if !git_root && !matches!(command, Command::Serve { .. }) {
+        bail!(
+            "--no-git-root only applies when serving -- pass ROOT to pick a directory\n\n{USAGE}"
+        );
+    }
+
+    if let Some(extra) = args.next() {
+        bail!("unexpected argument '{extra}'\n\n{USAGE}");
+    }
+    Ok(command)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_args(args: &[&str]) -> Result<Command> {
+        parse(args.iter().map(|s| (*s).to_string()))
+    }
+
+    #[test]
+    fn no_args_serves_current_directory() {
+        assert_eq!(
+            parse_args(&[]).unwrap(),
+            Command::Serve {
+                roots: Vec::new(),
+                read_only: false,
+                git_root: true,
+            }
+        );
+    }
+
+    #[test]
+    fn no_git_root_serves_the_current_directory_itself() {
+        assert_eq!(
+            parse_args(&["--no-git-root"]).unwrap(),
+            Command::Serve {
+                roots: Vec::new(),
+                read_only: false,
+                git_root: false,
+            }
+        );
+        let err = parse_args(&["gc", "--no-git-root"]).unwrap_err();
+        assert!(err.to_string().contains("pass ROOT"), "{err}");
+    }
+
+    #[test]
+    fn bare_path_serves_that_root() {
+        assert_eq!(
+            parse_args(&["/src/project"]).unwrap(),
+            Command::Serve {
+                roots: vec![PathBuf::from("/src/project")],
+                read_only: false,
+                git_root: true,
+            }
+        );
+    }
+
+    #[test]
+    fn several_roots_are_served_together() {
+        assert_eq!(
+            parse_args(&["/src/api", "--read-only", "/src/web"]).unwrap(),
+            Command::Serve {
+                roots: vec![PathBuf::from("/src/api"), PathBuf::from("/src/web")],
+                read_only: true,
+                git_root: true,
+            }
+        );
+    }
+
+    #[test]
+    fn read_only_flag_before_or_after_root() {
+        let expected = Command::Serve {
+            roots: vec![PathBuf::from("/mnt/shared")],
+            read_only: true,
+            git_root: true,
+        };
+        assert_eq!(
+            parse_args(&["--read-only", "/mnt/shared"]).unwrap(),
+            expected
+        );
+        assert_eq!(
+            parse_args(&["/mnt/shared", "--read-only"]).unwrap(),
+            expected
+        );
+    }
+
+    #[test]
+    fn read_only_rejected_for_import() {
+        let err = parse_args(&["import", "--read-only", "index.tar"]).unwrap_err();
+        assert!(
+            err.to_string().contains("only applies when serving"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn help_flag() {
+        assert_eq!(parse_args(&["--help"]).unwrap(), Command::Help);
+    }
+
+    #[test]
+    fn export_with_root() {
+        assert_eq!(
+            parse_args(&["export", "index.tar", "/src/project"]).unwrap(),
+            Command::Export {
+                archive: PathBuf::from("index.tar"),
+                root: Some(PathBuf::from("/src/project")),
+            }
+        );
+    }
+
+    #[test]
+    fn import_defaults_root() {
+        assert_eq!(
+            parse_args(&["import", "index.tar"]).unwrap(),
+            Command::Import {
+                archive: PathBuf::from("index.tar"),
+                root: None,
+            }
+        );
+    }
+
+    #[test]
+    fn eval_with_root() {
+        assert_eq!(
+            parse_args(&["eval", "evals.yaml", "/src/project"]).unwrap(),
+            Command::Eval {
+                suite: PathBuf::from("evals.yaml"),
+                root: Some(PathBuf::from("/src/project")),
+            }
+        );
+    }
+
+    #[test]
+    fn eval_requires_suite() {
+        let err = parse_args(&["eval"]).unwrap_err();
+        assert!(err.to_string().contains("needs a SUITE"), "{err}");
+    }
+
+    #[test]
+    fn bench_file_count() {
+        assert_eq!(
+            parse_args(&["bench"]).unwrap(),
+            Command::Bench {
+                files: DEFAULT_BENCH_FILES
+            }
+        );
+        assert_eq!(
+            parse_args(&["bench", "1000"]).unwrap(),
+            Command::Bench { files: 1000 }
+        );
+        let err = parse_args(&["bench", "many"]).unwrap_err();
+        assert!(err.to_string().contains("not 'many'"), "{err}");
+    }
+
+    #[test]
+    fn gc_and_relink() {
+        assert_eq!(
+            parse_args(&["gc", "--dry-run"]).unwrap(),
+            Command::Gc {
+                dry_run: true,
+                older_than: None,
+            }
+        );
+        assert_eq!(
+            parse_args(&["gc", "--older-than", "30", "--dry-run"]).unwrap(),
+            Command::Gc {
+                dry_run: true,
+                older_than: Some(30),
+            }
+        );
+        let err = parse_args(&["gc", "--older-than", "a month"]).unwrap_err();
+        assert!(err.to_string().contains("not 'a month'"), "{err}");
+        let err = parse_args(&["gc", "--older-than"]).unwrap_err();
+        assert!(err.to_string().contains("number of days"), "{err}");
+        assert_eq!(
+            parse_args(&["relink", "/old/project"]).unwrap(),
+            Command::Relink {
+                old_root: PathBuf::from("/old/project"),
+                root: None,
+            }
+        );
+        let err = parse_args(&["relink", "project"]).unwrap_err();
+        assert!(err.to_string().contains("absolute path"), "{err}");
+    }
+
+    #[test]
+    fn audit_tail_count_and_root() {
+        assert_eq!(
+            parse_args(&["audit", "tail"]).unwrap(),
+            Command::AuditTail {
+                lines: DEFAULT_AUDIT_LINES,
+                root: None,
+            }
+        );
+        assert_eq!(
+            parse_args(&["audit", "tail", "50", "/src/project"]).unwrap(),
+            Command::AuditTail {
+                lines: 50,
+                root: Some(PathBuf::from("/src/project")),
+            }
+        );
+        assert_eq!(
+            parse_args(&["audit", "tail", "/src/project"]).unwrap(),
+            Command::AuditTail {
+                lines: DEFAULT_AUDIT_LINES,
+                root: Some(PathBuf::from("/src/project")),
+            }
+        );
+        let err = parse_args(&["audit", "head"]).unwrap_err();
+        assert!(err.to_string().contains("unknown audit command"), "{err}");
+    }
+
+    #[test]
+    fn export_requires_archive() {
+        let err = parse_args(&["export"]).unwrap_err();
+        assert!(err.to_string().contains("needs an ARCHIVE"), "{err}");
+    }
+
+    #[test]
+    fn extra_arguments_are_rejected() {
+        assert!(parse_args(&["gc", "--dry-run", "/a"]).is_err());
+        assert!(parse_args(&["export", "index.tar", "/a", "/b"]).is_err());
+    }
+}
@@ -0,0 +1,205 @@
+//! `CODEOWNERS`: which teams own which paths.
+//!
+//! GitHub and GitLab look for the file at `.github/CODEOWNERS`, the root,
+//! then `docs/CODEOWNERS`. Each line is a gitignore-style pattern followed
+//! by its owners (`@user`, `@org/team`, or an email). The last line matching
+//! a path decides its owners, and one listing none leaves the path unowned.
+//! A pattern matching a directory covers everything below it, except that
+//! `dir/*` covers only the files directly in `dir`. GitLab's `[Section]`
+//! headers are skipped, so sections read as one list.
+
+use std::path::Path;
+
+use crate::ignore::glob;
+
+/// Where the file is looked for, relative to the root, in order.
+pub const CODEOWNERS_PATHS: [&str; 3] = [".github/CODEOWNERS", "CODEOWNERS", "docs/CODEOWNERS"];
+
+/// One pattern line.
+#[derive(Debug, Clone)]
+struct Rule {
+    glob: Vec<char>,
+    /// Matched against the whole relative path rather than the last
+    /// component.
+    anchored: bool,
+    /// `pattern/`: match directories only.
+    dir_only: bool,
+    /// `dir/*`: the files directly in `dir`, not those further down.
+    children_only: bool,
+    /// Lowercased, as GitHub compares them.
+    owners: Vec<String>,
+}
+
+/// Rules from a `CODEOWNERS` file, in file order.
+#[derive(Debug, Default, Clone)]
+pub struct CodeOwners {
+    rules: Vec<Rule>,
+}
+
+impl CodeOwners {
+    /// Read the first `CODEOWNERS` found under `root`; no rules if there is
+    /// none. An unreadable file is logged and ignored rather than failing
+    /// the index.
+    pub fn load(root: &Path) -> Self {
+        for name in CODEOWNERS_PATHS {
+            let path = root.join(name);
+            match std::fs::read_to_string(&path) {
+                Ok(text) => return Self::parse(&text),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => {
+                    tracing::warn!(
+                        "can't read {} ({e}); indexing without owners -- check its permissions",
+                        path.display()
+                    );
+                    return Self::default();
+                }
+            }
+        }
+        Self::default()
+    }
+
+    pub fn parse(text: &str) -> Self {
+        let rules = text.lines().filter_map(parse_line).collect();
+        Self { rules }
+    }
+
+    /// The owners of `rel_path`, relative to the root with `/` separators;
+    /// empty if no rule names any.
+    pub fn owners(&self, rel_path: &str) -> &[String] {
+        if self.rules.is_empty() {
+            return &[];
+        }
+        let path: Vec<char> = rel_path.chars().collect();
+        self.rules
+            .iter()
+            .rev()
+            .find(|rule| rule.covers(&path))
+            .map_or(&[], |rule| &rule.owners)
+    }
+}
+
+impl Rule {
+    /// Whether the rule matches `path` or, unless it only covers a
+    /// directory's own files, a directory `path` is in.
+    fn covers(&self, path: &[char]) -> bool {
+        if !self.dir_only && self.matches(path) {
+            return true;
+        }
+        !self.children_only
+            && path
+                .iter()
+                .enumerate()
+                .filter(|&(_, &c)| c == '/')
+                .any(|(i, _)| self.matches(&path[..i]))
+    }
+
+    fn matches(&self, path: &[char]) -> bool {
+        if self.anchored {
+            return glob(&self.glob, path);
+        }
+        let name_start = path
+            .iter()
+            .rposition(|&c| c == '/')
+            .map_or(0, |slash| slash + 1);
+        glob(&self.glob, &path[name_start..])
+    }
+}
+
+fn parse_line(line: &str) -> Option<Rule> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') || line.starts_with(['[', '^']) {
+        return None;
+    }
+    let mut words = line.split_whitespace();
+    let pattern = words.next()?;
+    let owners = words
+        .take_while(|word| !word.starts_with('#'))
+        .map(str::to_lowercase)
+        .collect();
+
+    let pattern = pattern.strip_prefix('\\').unwrap_or(pattern);
+    let (dir_only, pattern) = match pattern.strip_suffix('/') {
+        Some(rest) => (true, rest),
+        None => (false, pattern),
+    };
+    let anchored = pattern.contains('/');
+    let pattern = pattern.strip_prefix('/').unwrap_or(pattern);
+    if pattern.is_empty() {
+        return None;
+    }
+    Some(Rule {
+        children_only: pattern.ends_with("/*"),
+        glob: pattern.chars().collect(),
+        anchored,
+        dir_only,
+        owners,
+    })
+}
+
+/// `owner` as the owners of a chunk are stored, so a search filter can
+/// name a team with or without its `@`: lowercased, and with an `@` in
+/// front unless it is an email.
+pub fn normalize_owner(owner: &str) -> String {
+    let owner = owner.trim().to_lowercase();
+    if owner.contains('@') {
+        owner
+    } else {
+        format!("@{owner}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CODEOWNERS: &str = "\
+# Default owners
+*       @acme/core
+
+*.js    @acme/web @Jane
+/build/logs/ @acme/infra
+docs/*  docs@acme.com
+apps/   @acme/apps   # anywhere in the tree
+
+[Payments]
+/services/billing @acme/payments
+/services/billing/generated/
+";
+
+    fn owners(path: &str) -> Vec<String> {
+        CodeOwners::parse(CODEOWNERS).owners(path).to_vec()
+    }
+
+    #[test]
+    fn last_matching_rule_wins() {
+        assert_eq!(owners("README.md"), ["@acme/core"]);
+        assert_eq!(owners("web/app.js"), ["@acme/web", "@jane"]);
+        assert_eq!(owners("build/logs/today.txt"), ["@acme/infra"]);
+        assert_eq!(owners("services/billing/invoice.go"), ["@acme/payments"]);
+    }
+
+    #[test]
+    fn directories_cover_what_is_below_them() {
+        assert_eq!(owners("apps/api/main.go"), ["@acme/apps"]);
+        assert_eq!(owners("src/apps/main.go"), ["@acme/apps"]);
+        // `build/logs/` is anchored by its inner slash
+        assert_eq!(owners("src/build/logs/x.txt"), ["@acme/core"]);
+
+        // `docs/*` takes only the files directly in docs
+        assert_eq!(owners("docs/intro.md"), ["docs@acme.com"]);
+        assert_eq!(owners("docs/guides/setup.md"), ["@acme/core"]);
+    }
+
+    #[test]
+    fn a_rule_without_owners_leaves_paths_unowned() {
+        assert!(owners("services/billing/generated/api.go").is_empty());
+        assert!(CodeOwners::default().owners("a.go").is_empty());
+    }
+
+    #[test]
+    fn owners_are_normalized_for_filtering() {
+        assert_eq!(normalize_owner("acme/Web"), "@acme/web");
+        assert_eq!(normalize_owner("@acme/web"), "@acme/web");
+        assert_eq!(normalize_owner("Docs@acme.com"), "docs@acme.com");
+    }
+}
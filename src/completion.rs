@@ -0,0 +1,138 @@
+//! Completions for tool arguments, served over MCP's `completion/complete`.
+//!
+//! The protocol names the prompt or resource an argument belongs to, but
+//! not tools, so arguments are completed by name alone: `language` from
+//! the configured languages, `kind` from the symbol kinds in the index, and
+//! `path` (and `exclude_path`) one directory at a time from the indexed
+//! files. Clients that send a tool's argument under any reference get the
+//! same values.
+
+use std::collections::BTreeSet;
+
+use rmcp::model::CompletionInfo;
+
+/// What an argument of this name completes to, if anything.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Argument {
+    Language,
+    Kind,
+    Path,
+}
+
+impl Argument {
+    pub fn named(name: &str) -> Option<Self> {
+        match name {
+            "language" => Some(Self::Language),
+            "kind" => Some(Self::Kind),
+            "path" | "exclude_path" => Some(Self::Path),
+            _ => None,
+        }
+    }
+}
+
+/// `candidates` that start with `typed`, ignoring case, then those with a
+/// `_`-separated word that does, so `meth` finds `method_declaration` and
+/// `item` finds `function_item`.
+pub fn matching<'a>(candidates: impl IntoIterator<Item = &'a str>, typed: &str) -> CompletionInfo {
+    let typed = typed.to_lowercase();
+    let mut prefixed = BTreeSet::new();
+    let mut worded = BTreeSet::new();
+    for candidate in candidates {
+        let lower = candidate.to_lowercase();
+        if lower.starts_with(&typed) {
+            prefixed.insert(candidate);
+        } else if lower.split('_').any(|word| word.starts_with(&typed)) {
+            worded.insert(candidate);
+        }
+    }
+    capped(prefixed.into_iter().chain(worded).map(str::to_string))
+}
+
+/// The next step below `typed` toward each of `files` it begins: a
+/// directory, with its trailing `/`, or the file itself.
+pub fn path_steps<'a>(files: impl IntoIterator<Item = &'a str>, typed: &str) -> CompletionInfo {
+    let typed = typed.trim_start_matches("./");
+    let steps: BTreeSet<&str> = files
+        .into_iter()
+        .filter_map(|file| {
+            let rest = file.strip_prefix(typed)?;
+            Some(match rest.find('/') {
+                Some(slash) => &file[..typed.len() + slash + 1],
+                None => file,
+            })
+        })
+        .collect();
+    capped(steps.into_iter().map(str::to_string))
+}
+
+/// `values`, as many as a response may hold, noting how many there were.
+fn capped(values: impl Iterator<Item = String>) -> CompletionInfo {
+    let mut values: Vec<String> = values.collect();
+    let total = values.len();
+    values.truncate(CompletionInfo::MAX_VALUES);
+    CompletionInfo {
+        has_more: Some(total > values.len()),
+        total: Some(u32::try_from(total).unwrap_or(u32::MAX)),
+        values,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn arguments_are_known_by_name() {
+        assert_eq!(Argument::named("language"), Some(Argument::Language));
+        assert_eq!(Argument::named("exclude_path"), Some(Argument::Path));
+        assert_eq!(Argument::named("query"), None);
+    }
+
+    #[test]
+    fn prefixes_come_before_word_matches() {
+        let kinds = [
+            "method_declaration",
+            "function_item",
+            "Method",
+            "impl_item",
+            "struct_item",
+        ];
+        let completion = matching(kinds, "meth");
+        assert_eq!(completion.values, ["Method", "method_declaration"]);
+        assert_eq!(
+            matching(kinds, "item").values,
+            ["function_item", "impl_item", "struct_item"]
+        );
+        assert_eq!(matching(kinds, "").total, Some(5));
+        assert!(matching(kinds, "zzz").values.is_empty());
+    }
+
+    #[test]
+    fn paths_complete_a_directory_at_a_time() {
+        let files = [
+            "README.md",
+            "src/lib.rs",
+            "src/store.rs",
+            "src/store/dedup.rs",
+            "src/store/moves.rs",
+        ];
+        assert_eq!(path_steps(files, "").values, ["README.md", "src/"]);
+        assert_eq!(
+            path_steps(files, "./src/st").values,
+            ["src/store.rs", "src/store/"]
+        );
+        assert_eq!(
+            path_steps(files, "src/store/").values,
+            ["src/store/dedup.rs", "src/store/moves.rs"]
+        );
+    }
+
+    #[test]
+    fn long_lists_are_capped() {
+        let names: Vec<String> = (0..150).map(|i| format!("lang{i:03}")).collect();
+        let completion = matching(names.iter().map(String::as_str), "lang");
+        assert_eq!(completion.values.len(), CompletionInfo::MAX_VALUES);
+        assert_eq!(completion.total, Some(150));
+        assert_eq!(completion.has_more, Some(true));
+    }
+}
@@ -1,7 +1,12 @@
+//! Settings from `~/.config/claudevil/config.toml`, with defaults for
+//! everything it leaves out.
+
 use std::collections::HashMap;
 use std::path::PathBuf;
+use std::time::Duration;
 
-use serde::Deserialize;
+use rmcp::schemars;
+use serde::{Deserialize, Serialize};
 
 use crate::error::{Error, Result};
 
@@ -10,12 +15,747 @@ use crate::error::{Error, Result};
 pub struct LangConfig {
     /// File extensions that map to this language (e.g. `["rs"]`).
     pub extensions: Vec<String>,
-    /// Name of the tree-sitter grammar (e.g. `"tree-sitter-rust"`).
+    /// Name of the tree-sitter grammar (e.g. `"tree-sitter-rust"`),
+    /// `"builtin-hcl"` for the built-in HCL/Terraform block scanner,
+    /// `"builtin-idl"` for the Protobuf/Thrift declaration scanner, or
+    /// `"builtin-ipynb"` for Jupyter notebooks, chunked by cell. Names
+    /// that aren't compiled in are loaded from a shared library: either a
+    /// path to one, or a name looked up in `[grammars] search_paths`.
     pub grammar: String,
+    /// Entry point of a dynamically loaded grammar. Defaults to
+    /// `tree_sitter_<name>` derived from the library's file name.
+    pub grammar_symbol: Option<String>,
     /// AST node kinds to extract as chunks. If omitted, built-in defaults
     /// are used for known languages; unknown languages without this field
     /// cause an error at startup.
     pub chunk_on: Option<Vec<String>>,
+    /// Container node kinds (e.g. `"impl_item"`) that are split into one
+    /// chunk per child instead of being emitted whole. Child symbols are
+    /// qualified with the container's type name (`Type::method`) and keep
+    /// the container header as context. Members that aren't chunks of
+    /// their own, like fields and comments, go in one more chunk with the
+    /// header.
+    #[serde(default)]
+    pub split_children: Vec<String>,
+    /// Skip `chunk_on` matches nested inside another chunk (e.g. `if`
+    /// blocks inside shell functions, or nested YAML keys). If omitted,
+    /// built-in defaults are used: on for `sh` and `yaml`, off otherwise.
+    pub skip_nested: Option<bool>,
+    /// Lines of source above each chunk to include as context, so adjacent
+    /// chunks overlap (e.g. constants declared right above a function).
+    /// `0` (the default) disables overlap.
+    #[serde(default)]
+    pub overlap_lines: usize,
+    /// Directory names (e.g. `"vendor"`) the index walk skips wherever they
+    /// appear, because they hold this language's third-party code. If
+    /// omitted, built-in defaults are used for known languages; set it to
+    /// `[]` to index them anyway.
+    pub exclude_dirs: Option<Vec<String>>,
+    /// Top-level AST node kinds holding the file's imports (e.g.
+    /// `"use_declaration"`), shown beside search results that ask for
+    /// context. If omitted, built-in defaults are used for known
+    /// languages.
+    pub imports: Option<Vec<String>>,
+    /// Index this language's files in the `docs` namespace rather than
+    /// `code`, e.g. for Markdown, so searches can keep prose apart.
+    #[serde(default)]
+    pub docs: bool,
+}
+
+fn default_path_boost() -> f32 {
+    0.05
+}
+
+fn default_language_boost() -> f32 {
+    0.03
+}
+
+fn default_focus_boost() -> f32 {
+    0.1
+}
+
+fn default_structure_boost() -> f32 {
+    0.05
+}
+
+fn default_generated_penalty() -> f32 {
+    0.1
+}
+
+/// Search ranking adjustments applied on top of vector distance.
+#[derive(Debug, Clone, Deserialize)]
+pub struct RankingConfig {
+    /// Weight of the popularity boost for chunks that were previously
+    /// returned by a search and then opened with `read_file`. `0.0`
+    /// (the default) disables the boost.
+    #[serde(default)]
+    pub usage_boost: f32,
+    /// Distance subtracted from a result whose file path contains every
+    /// query term (proportionally less for some of them), so "auth
+    /// middleware" prefers `middleware/auth.go`. `0.0` disables it.
+    #[serde(default = "default_path_boost")]
+    pub path_boost: f32,
+    /// Distance subtracted from results in a language the query's wording
+    /// points at ("goroutine" → go) when no language filter is given.
+    /// `0.0` disables it.
+    #[serde(default = "default_language_boost")]
+    pub language_boost: f32,
+    /// Distance subtracted from results matching a search's `focus`
+    /// (`code` or `docs`) in proportion to how code- or comment-heavy
+    /// they are. `0.0` makes `focus` a no-op.
+    #[serde(default = "default_focus_boost")]
+    pub focus_boost: f32,
+    /// Distance subtracted from results of the kind of code the query's
+    /// wording asks for ("test for parser" → tests, "interface for
+    /// storage" → interfaces and traits) when no kind filter is given.
+    /// `0.0` disables it.
+    #[serde(default = "default_structure_boost")]
+    pub structure_boost: f32,
+    /// Distance added to results from files that look minified or
+    /// generated, when `[indexing] generated` is `demote`. `0.0` ranks
+    /// them like any other.
+    #[serde(default = "default_generated_penalty")]
+    pub generated_penalty: f32,
+}
+
+impl Default for RankingConfig {
+    fn default() -> Self {
+        Self {
+            usage_boost: 0.0,
+            path_boost: default_path_boost(),
+            language_boost: default_language_boost(),
+            focus_boost: default_focus_boost(),
+            structure_boost: default_structure_boost(),
+            generated_penalty: default_generated_penalty(),
+        }
+    }
+}
+
+/// Prometheus metrics endpoint settings.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct MetricsConfig {
+    /// Address to serve `/metrics` and `/healthz` on (e.g.
+    /// `"127.0.0.1:9464"`). The endpoint is disabled when unset, and
+    /// can't be set for a process serving several roots.
+    pub listen: Option<String>,
+}
+
+/// Where the embedding model comes from, and the instruction prefixes put
+/// in front of each text before it is embedded. Retrieval models such as
+/// e5, bge, and nomic are trained with one prefix for search queries and
+/// another for the documents searched.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EmbeddingConfig {
+    /// Prefix for search queries, e.g. `"query: "` for e5.
+    #[serde(default)]
+    pub query_prefix: String,
+    /// Prefix for indexed chunks, commit messages, and notes, e.g.
+    /// `"passage: "` for e5.
+    #[serde(default)]
+    pub passage_prefix: String,
+    /// Never download: load the model from the Hugging Face cache only.
+    /// Also on when `HF_HUB_OFFLINE` is set.
+    #[serde(default)]
+    pub offline: bool,
+    /// Hugging Face endpoint to download from, e.g. an internal mirror.
+    /// Defaults to `HF_ENDPOINT`, then <https://huggingface.co>.
+    #[serde(default)]
+    pub endpoint: Option<String>,
+    /// Directory holding `config.json`, `tokenizer.json`, and
+    /// `model.safetensors`, loaded instead of going through the hub cache.
+    #[serde(default)]
+    pub model_dir: Option<PathBuf>,
+    /// GGUF file of the model's weights, e.g. quantized to `q8_0`, loaded
+    /// in place of `model.safetensors`: less memory and faster on CPUs, at
+    /// a slight cost in accuracy. The config and tokenizer still come from
+    /// the hub or `model_dir`.
+    #[serde(default)]
+    pub gguf: Option<PathBuf>,
+    /// Copies of the model that embed at once, each taking a share of
+    /// every batch. One forward pass leaves most cores of a large CPU-only
+    /// machine idle; each copy costs about the size of the weights in
+    /// memory. Defaults to 1.
+    #[serde(default = "default_replicas")]
+    pub replicas: usize,
+}
+
+fn default_replicas() -> usize {
+    1
+}
+
+impl Default for EmbeddingConfig {
+    fn default() -> Self {
+        Self {
+            query_prefix: String::new(),
+            passage_prefix: String::new(),
+            offline: false,
+            endpoint: None,
+            model_dir: None,
+            gguf: None,
+            replicas: default_replicas(),
+        }
+    }
+}
+
+/// What part of the index a chunk belongs to. Searches cover every
+/// namespace but `dependencies` unless asked otherwise.
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    Serialize,
+    Deserialize,
+    schemars::JsonSchema,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Namespace {
+    /// The project's source files.
+    #[default]
+    Code,
+    /// The project's prose: files of languages configured with `docs = true`.
+    Docs,
+    /// Notes added with `add_note`.
+    Notes,
+    /// Indexed commit messages.
+    Commits,
+    /// Indexed dependency source.
+    Dependencies,
+}
+
+impl Namespace {
+    pub const ALL: [Self; 5] = [
+        Self::Code,
+        Self::Docs,
+        Self::Notes,
+        Self::Commits,
+        Self::Dependencies,
+    ];
+}
+
+impl std::fmt::Display for Namespace {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Code => "code",
+            Self::Docs => "docs",
+            Self::Notes => "notes",
+            Self::Commits => "commits",
+            Self::Dependencies => "dependencies",
+        })
+    }
+}
+
+/// Distance metric for the vector index.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Metric {
+    /// Cosine distance.
+    #[default]
+    Cos,
+    /// Inner product; equivalent to cosine for the normalized embeddings
+    /// claudevil produces, but cheaper.
+    Ip,
+    /// Squared Euclidean distance.
+    L2sq,
+}
+
+impl std::fmt::Display for Metric {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Cos => "cos",
+            Self::Ip => "ip",
+            Self::L2sq => "l2sq",
+        })
+    }
+}
+
+/// Where chunk vectors are stored.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    /// A usearch HNSW index on local disk.
+    #[default]
+    Usearch,
+    /// A Qdrant collection, which several machines can share.
+    Qdrant,
+}
+
+impl std::fmt::Display for Backend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Usearch => "usearch",
+            Self::Qdrant => "qdrant",
+        })
+    }
+}
+
+/// How the metadata keeps each chunk's text.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ContentStorage {
+    /// A copy of the text, in memory and in the metadata file.
+    #[default]
+    Inline,
+    /// Only the chunk's byte range in its file; the text is read back from
+    /// the file when a result is built.
+    Offsets,
+}
+
+/// What indexing does with files that look minified or generated: bundles,
+/// source maps, and files marked `DO NOT EDIT` or `@generated`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GeneratedFiles {
+    /// Index them, but rank their chunks lower by `[ranking]
+    /// generated_penalty`.
+    #[default]
+    Demote,
+    /// Leave them out of the index; `index_status` lists them as skipped.
+    Skip,
+    /// Index and rank them like any other file.
+    Index,
+}
+
+/// What opening an index built for another metric or dimension count does.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OnMismatch {
+    /// Drop the old vectors and embed every stored chunk again, in the
+    /// background; chunks are searchable again as they are embedded.
+    #[default]
+    Rebuild,
+    /// Refuse to open the index, e.g. for one too large to rebuild casually.
+    Fail,
+}
+
+fn default_qdrant_url() -> String {
+    "http://localhost:6333".to_string()
+}
+
+fn default_qdrant_collection() -> String {
+    "claudevil".to_string()
+}
+
+/// Connection settings for the Qdrant backend. The API key, if the server
+/// needs one, is read from `QDRANT_API_KEY` rather than the config file.
+#[derive(Debug, Clone, Deserialize)]
+pub struct QdrantConfig {
+    /// Base URL of the Qdrant REST API.
+    #[serde(default = "default_qdrant_url")]
+    pub url: String,
+    /// Collection holding this project's chunks; created on first use.
+    #[serde(default = "default_qdrant_collection")]
+    pub collection: String,
+}
+
+impl Default for QdrantConfig {
+    fn default() -> Self {
+        Self {
+            url: default_qdrant_url(),
+            collection: default_qdrant_collection(),
+        }
+    }
+}
+
+/// Vector index backend and HNSW hyperparameters. `0` leaves a setting at
+/// the backend's default. `metric`, `dimensions`, and `connectivity` are
+/// fixed when the index is built; a local index opened with a different
+/// metric or dimension count is rebuilt, as `on_mismatch` says, and one
+/// with a different connectivity has to be deleted.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct IndexConfig {
+    #[serde(default)]
+    pub backend: Backend,
+    #[serde(default)]
+    pub qdrant: QdrantConfig,
+    #[serde(default)]
+    pub metric: Metric,
+    /// Dimensions stored per vector. Embeddings are truncated to their
+    /// first `dimensions` components and re-normalized, shrinking the index
+    /// at some cost in recall. `0` stores all of them.
+    #[serde(default)]
+    pub dimensions: usize,
+    /// Graph edges per node. Higher improves recall on large indexes at the
+    /// cost of memory and insert speed.
+    #[serde(default)]
+    pub connectivity: usize,
+    /// Candidates considered while inserting. Higher builds a better graph,
+    /// more slowly.
+    #[serde(default)]
+    pub expansion_add: usize,
+    /// Candidates considered per search. Higher improves recall at the cost
+    /// of query latency.
+    #[serde(default)]
+    pub expansion_search: usize,
+    /// Score every chunk on each search instead of walking the HNSW graph,
+    /// while the index holds at most `exact_max_chunks` chunks. On a small
+    /// index this is fast enough and never misses a neighbor. The local
+    /// backend keeps a copy of every vector in memory for it.
+    #[serde(default)]
+    pub exact: bool,
+    /// Most chunks searched exactly, by `exact` or a search asking for it;
+    /// larger indexes are searched approximately. 0 means 50,000.
+    #[serde(default)]
+    pub exact_max_chunks: usize,
+    /// `"offsets"` keeps only where each chunk is in its file instead of a
+    /// copy of its text, so a large repository's source isn't held in
+    /// memory twice. Notes, commit messages, and dependency source are
+    /// always kept inline.
+    #[serde(default)]
+    pub content: ContentStorage,
+    /// With `content = "offsets"`, how many chunks' text read back from
+    /// disk is kept in memory for repeated results; 0 means 1024.
+    #[serde(default)]
+    pub snippet_cache: usize,
+    /// What to do when the index was built with another `metric` or
+    /// `dimensions`.
+    #[serde(default)]
+    pub on_mismatch: OnMismatch,
+    /// Most bytes the root's index and metadata may take on disk; once
+    /// they do, or a batch of chunks would take them past it, new chunks
+    /// are refused until space is freed or the limit raised. 0, the
+    /// default, sets no limit.
+    #[serde(default)]
+    pub max_bytes: u64,
+}
+
+fn default_max_file_size() -> u64 {
+    1024 * 1024
+}
+
+/// Limits on which files get indexed.
+#[derive(Debug, Clone, Deserialize)]
+pub struct IndexingConfig {
+    /// Files larger than this many bytes are skipped; they are almost
+    /// always generated or vendored. Defaults to 1 MiB.
+    #[serde(default = "default_max_file_size")]
+    pub max_file_size: u64,
+    /// What to do with files that look minified or generated.
+    #[serde(default)]
+    pub generated: GeneratedFiles,
+    /// In a git checkout, update the index at startup from the diff
+    /// between the last indexed commit and `HEAD` instead of re-reading
+    /// every file. Falls back to a full index when there is no usable
+    /// previous commit.
+    #[serde(default)]
+    pub sync_with_git: bool,
+    /// How many of the most frequent recent search queries to replay after
+    /// each full index, to warm up the model and index and catch a broken
+    /// model before a client does. `0` disables the warm-up.
+    #[serde(default = "default_warmup_queries")]
+    pub warmup_queries: usize,
+    /// Minutes between background reindexes of a running server, give or
+    /// take a tenth so servers started together don't reindex at once.
+    /// `0` turns scheduled reindexing off.
+    #[serde(default)]
+    pub reindex_interval_mins: u64,
+    /// Only reindex on schedule once at least this many indexed files were
+    /// modified or deleted since they were indexed. `0` reindexes every
+    /// interval.
+    #[serde(default)]
+    pub reindex_stale_files: usize,
+    /// In a git checkout, also index the messages of this many most recent
+    /// commits as `commit` chunks, so "why was this changed" questions can
+    /// find them. `0` leaves history out of the index, removing commits an
+    /// earlier run indexed.
+    #[serde(default)]
+    pub commit_messages: usize,
+    /// Index files whose extension no configured language handles as
+    /// fixed windows of lines, under the `unknown` language, instead of
+    /// leaving them out of the index.
+    #[serde(default)]
+    pub fallback_chunking: bool,
+    /// Lines per chunk for files indexed by `fallback_chunking`.
+    #[serde(default = "default_fallback_lines")]
+    pub fallback_lines: usize,
+    /// Milliseconds each embedding batch should take; batch sizes are
+    /// adjusted between `min_batch` and `max_batch` to match. `0` keeps
+    /// batches at 64 chunks.
+    #[serde(default = "default_batch_target_ms")]
+    pub batch_target_ms: u64,
+    /// Fewest chunks per embedding batch.
+    #[serde(default = "default_min_batch")]
+    pub min_batch: usize,
+    /// Most chunks per embedding batch.
+    #[serde(default = "default_max_batch")]
+    pub max_batch: usize,
+    /// A walk stops listing files after this many, so a root pointed at a
+    /// home directory by mistake fails fast instead of appearing hung. `0`
+    /// lifts the limit.
+    #[serde(default = "default_max_files")]
+    pub max_files: usize,
+    /// Directories more than this many levels below the root are not
+    /// walked. `0` lifts the limit.
+    #[serde(default = "default_max_depth")]
+    pub max_depth: usize,
+    /// A walk stops listing files once their sizes add up to this many
+    /// bytes. `0` lifts the limit.
+    #[serde(default = "default_max_total_bytes")]
+    pub max_total_bytes: u64,
+}
+
+fn default_warmup_queries() -> usize {
+    5
+}
+
+fn default_fallback_lines() -> usize {
+    40
+}
+
+fn default_batch_target_ms() -> u64 {
+    2000
+}
+
+fn default_min_batch() -> usize {
+    8
+}
+
+fn default_max_batch() -> usize {
+    256
+}
+
+fn default_max_files() -> usize {
+    100_000
+}
+
+fn default_max_depth() -> usize {
+    32
+}
+
+fn default_max_total_bytes() -> u64 {
+    2 * 1024 * 1024 * 1024
+}
+
+impl IndexingConfig {
+    /// Reject a fallback window no file could be split into, or batch
+    /// bounds no size fits.
+    fn validate(&self) -> Result<()> {
+        if self.fallback_chunking && self.fallback_lines == 0 {
+            return Err(Error::Config(
+                "[indexing] fallback_lines is 0, so no chunk would hold a line -- raise it"
+                    .to_string(),
+            ));
+        }
+        if self.min_batch == 0 || self.min_batch > self.max_batch {
+            return Err(Error::Config(format!(
+                "[indexing] min_batch {} and max_batch {} leave no batch size -- make \
+                 min_batch at least 1 and no more than max_batch",
+                self.min_batch, self.max_batch
+            )));
+        }
+        Ok(())
+    }
+}
+
+impl Default for IndexingConfig {
+    fn default() -> Self {
+        Self {
+            max_file_size: default_max_file_size(),
+            generated: GeneratedFiles::default(),
+            sync_with_git: false,
+            warmup_queries: default_warmup_queries(),
+            reindex_interval_mins: 0,
+            reindex_stale_files: 0,
+            commit_messages: 0,
+            fallback_chunking: false,
+            fallback_lines: default_fallback_lines(),
+            batch_target_ms: default_batch_target_ms(),
+            min_batch: default_min_batch(),
+            max_batch: default_max_batch(),
+            max_files: default_max_files(),
+            max_depth: default_max_depth(),
+            max_total_bytes: default_max_total_bytes(),
+        }
+    }
+}
+
+fn default_limit() -> usize {
+    10
+}
+
+fn default_max_limit() -> usize {
+    100
+}
+
+fn default_show_distance() -> bool {
+    true
+}
+
+fn default_max_concurrent_tools() -> usize {
+    8
+}
+
+fn default_tool_timeout_ms() -> u64 {
+    30_000
+}
+
+fn default_audit_log() -> bool {
+    true
+}
+
+fn default_audit_param_chars() -> usize {
+    256
+}
+
+fn default_audit_max_bytes() -> u64 {
+    10 * 1024 * 1024
+}
+
+fn default_query_cache_size() -> usize {
+    256
+}
+
+/// How result locations link to the files they are in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Links {
+    /// Plain paths relative to the root.
+    #[default]
+    None,
+    /// `file://` URIs.
+    File,
+    /// `vscode://file/...` URIs, which VS Code opens at the line.
+    Vscode,
+}
+
+/// Defaults and bounds for tool arguments, so whoever deploys claudevil
+/// can cap how much work one call may ask for.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ServerConfig {
+    /// Results returned by `search` and `find_similar` when the call
+    /// doesn't pass `limit`.
+    #[serde(default = "default_limit")]
+    pub default_limit: usize,
+    /// Largest `limit` any tool accepts; bigger requests are refused.
+    #[serde(default = "default_max_limit")]
+    pub max_limit: usize,
+    /// Lines of each chunk shown in results when the call doesn't pass
+    /// `max_snippet_lines`. `0` (the default) shows whole chunks.
+    #[serde(default)]
+    pub max_snippet_lines: usize,
+    /// Whether `search` and `find_similar` show each result's distance
+    /// when the call doesn't pass `show_distance`.
+    #[serde(default = "default_show_distance")]
+    pub show_distance: bool,
+    /// Whether result locations and `list_files` entries link to the file,
+    /// for clients that render Markdown links.
+    #[serde(default)]
+    pub links: Links,
+    /// Tool calls run at once; more wait their turn. `0` runs every call
+    /// as it arrives.
+    #[serde(default = "default_max_concurrent_tools")]
+    pub max_concurrent_tools: usize,
+    /// Milliseconds a tool call may take, waiting for its turn included,
+    /// before it is abandoned with a timeout error. `0` never times out.
+    #[serde(default = "default_tool_timeout_ms")]
+    pub tool_timeout_ms: u64,
+    /// Per-tool overrides of `tool_timeout_ms`, by tool name.
+    #[serde(default)]
+    pub tool_timeouts_ms: HashMap<String, u64>,
+    /// Append each tool call to `audit.jsonl` in the root's data directory.
+    /// Read-only servers never write it.
+    #[serde(default = "default_audit_log")]
+    pub audit_log: bool,
+    /// Characters of each string parameter kept in the audit log; longer
+    /// ones are cut short.
+    #[serde(default = "default_audit_param_chars")]
+    pub audit_param_chars: usize,
+    /// Bytes `audit.jsonl` may grow to before it is moved to
+    /// `audit.jsonl.1`, replacing the one before, and started afresh. `0`
+    /// lets it grow without limit.
+    #[serde(default = "default_audit_max_bytes")]
+    pub audit_max_bytes: u64,
+    /// Recent queries whose embeddings are kept, so a repeated query
+    /// skips the model. `0` embeds every query.
+    #[serde(default = "default_query_cache_size")]
+    pub query_cache_size: usize,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            default_limit: default_limit(),
+            max_limit: default_max_limit(),
+            max_snippet_lines: 0,
+            show_distance: default_show_distance(),
+            links: Links::None,
+            max_concurrent_tools: default_max_concurrent_tools(),
+            tool_timeout_ms: default_tool_timeout_ms(),
+            tool_timeouts_ms: HashMap::new(),
+            audit_log: default_audit_log(),
+            audit_param_chars: default_audit_param_chars(),
+            audit_max_bytes: default_audit_max_bytes(),
+            query_cache_size: default_query_cache_size(),
+        }
+    }
+}
+
+impl ServerConfig {
+    /// Reject limits no tool call could satisfy.
+    fn validate(&self) -> Result<()> {
+        if self.max_limit == 0 {
+            return Err(Error::Config(
+                "[server] max_limit is 0, so every search would be refused -- raise it".to_string(),
+            ));
+        }
+        if self.default_limit == 0 || self.default_limit > self.max_limit {
+            return Err(Error::Config(format!(
+                "[server] default_limit is {}, but must be between 1 and max_limit ({}) -- \
+                 change one of them",
+                self.default_limit, self.max_limit
+            )));
+        }
+        Ok(())
+    }
+
+    /// How long a call to `tool` may run, or `None` for no limit.
+    pub fn tool_timeout(&self, tool: &str) -> Option<Duration> {
+        let ms = self
+            .tool_timeouts_ms
+            .get(tool)
+            .copied()
+            .unwrap_or(self.tool_timeout_ms);
+        (ms > 0).then(|| Duration::from_millis(ms))
+    }
+}
+
+/// Indexing the sources of the project's direct dependencies.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct DependenciesConfig {
+    /// Index the Go modules `go.mod` requires and the crates `Cargo.toml`
+    /// depends on, from the module cache and cargo's registry, for searches
+    /// with `scope = "dependencies"`. Off by default.
+    #[serde(default)]
+    pub index: bool,
+    /// Only index dependencies with these module paths or crate names;
+    /// empty indexes all of them.
+    #[serde(default)]
+    pub include: Vec<String>,
+    /// Never index dependencies with these module paths or crate names.
+    #[serde(default)]
+    pub exclude: Vec<String>,
+}
+
+impl DependenciesConfig {
+    /// Whether the dependency `name` may be indexed.
+    pub fn allows(&self, name: &str) -> bool {
+        (self.include.is_empty() || self.include.iter().any(|n| n == name))
+            && !self.exclude.iter().any(|n| n == name)
+    }
+}
+
+/// Where to find grammars compiled as shared libraries.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct GrammarsConfig {
+    /// Directories searched for grammar libraries, before the default
+    /// `<data dir>/claudevil/grammars`.
+    #[serde(default)]
+    pub search_paths: Vec<PathBuf>,
 }
 
 /// Top-level configuration.
@@ -23,6 +763,22 @@ pub struct LangConfig {
 pub struct Config {
     #[serde(default)]
     pub lang: HashMap<String, LangConfig>,
+    #[serde(default)]
+    pub ranking: RankingConfig,
+    #[serde(default)]
+    pub metrics: MetricsConfig,
+    #[serde(default)]
+    pub grammars: GrammarsConfig,
+    #[serde(default)]
+    pub index: IndexConfig,
+    #[serde(default)]
+    pub indexing: IndexingConfig,
+    #[serde(default)]
+    pub server: ServerConfig,
+    #[serde(default)]
+    pub embedding: EmbeddingConfig,
+    #[serde(default)]
+    pub dependencies: DependenciesConfig,
 }
 
 impl Config {
@@ -43,10 +799,21 @@ impl Config {
             for (name, lang) in user.lang {
                 config.lang.insert(name, lang);
             }
+            config.ranking = user.ranking;
+            config.metrics = user.metrics;
+            config.grammars = user.grammars;
+            config.index = user.index;
+            config.indexing = user.indexing;
+            config.server = user.server;
+            config.embedding = user.embedding;
+            config.dependencies = user.dependencies;
 
             tracing::info!("loaded config from {}", path.display());
         }
 
+        config.server.validate()?;
+        config.indexing.validate()?;
+
         // Validate: every language must have chunk_on resolved
         for (name, lang) in &mut config.lang {
             if lang.chunk_on.is_none() {
@@ -59,6 +826,9 @@ impl Config {
                 }
                 lang.chunk_on = Some(defaults);
             }
+            if lang.skip_nested.is_none() {
+                lang.skip_nested = Some(matches!(name.as_str(), "sh" | "yaml"));
+            }
         }
 
         Ok(config)
@@ -72,7 +842,7 @@ impl Config {
             .map(|(name, lang)| (name.as_str(), lang))
     }
 
-    /// Hardcoded defaults for Go, Rust, and Python.
+    /// Hardcoded defaults for Go, Rust, Python, shell, YAML, and HCL.
     fn default_config() -> Self {
         let mut lang = HashMap::new();
 
@@ -82,6 +852,13 @@ impl Config {
                 extensions: vec!["go".to_string()],
                 grammar: "tree-sitter-go".to_string(),
                 chunk_on: None, // resolved by default_chunk_on
+                grammar_symbol: None,
+                split_children: Vec::new(),
+                skip_nested: None, // resolved in load()
+                overlap_lines: 0,
+                exclude_dirs: None,
+                imports: None,
+                docs: false,
             },
         );
 
@@ -91,6 +868,13 @@ impl Config {
                 extensions: vec!["rs".to_string()],
                 grammar: "tree-sitter-rust".to_string(),
                 chunk_on: None,
+                grammar_symbol: None,
+                split_children: Vec::new(),
+                skip_nested: None,
+                overlap_lines: 0,
+                exclude_dirs: None,
+                imports: None,
+                docs: false,
             },
         );
 
@@ -100,10 +884,136 @@ impl Config {
                 extensions: vec!["py".to_string()],
                 grammar: "tree-sitter-python".to_string(),
                 chunk_on: None,
+                grammar_symbol: None,
+                split_children: Vec::new(),
+                skip_nested: None,
+                overlap_lines: 0,
+                exclude_dirs: None,
+                imports: None,
+                docs: false,
+            },
+        );
+
+        lang.insert(
+            "sh".to_string(),
+            LangConfig {
+                extensions: vec!["sh".to_string(), "bash".to_string()],
+                grammar: "tree-sitter-bash".to_string(),
+                chunk_on: None,
+                grammar_symbol: None,
+                split_children: Vec::new(),
+                skip_nested: None,
+                overlap_lines: 0,
+                exclude_dirs: None,
+                imports: None,
+                docs: false,
+            },
+        );
+
+        lang.insert(
+            "yaml".to_string(),
+            LangConfig {
+                extensions: vec!["yaml".to_string(), "yml".to_string()],
+                grammar: "tree-sitter-yaml".to_string(),
+                chunk_on: None,
+                grammar_symbol: None,
+                split_children: Vec::new(),
+                skip_nested: None,
+                overlap_lines: 0,
+                exclude_dirs: None,
+                imports: None,
+                docs: false,
+            },
+        );
+
+        lang.insert(
+            "hcl".to_string(),
+            LangConfig {
+                extensions: vec!["hcl".to_string(), "tf".to_string()],
+                grammar: "builtin-hcl".to_string(),
+                chunk_on: None,
+                grammar_symbol: None,
+                split_children: Vec::new(),
+                skip_nested: None,
+                overlap_lines: 0,
+                exclude_dirs: None,
+                imports: None,
+                docs: false,
             },
         );
 
-        Self { lang }
+        lang.insert(
+            "protobuf".to_string(),
+            LangConfig {
+                extensions: vec!["proto".to_string()],
+                grammar: "builtin-idl".to_string(),
+                chunk_on: None,
+                grammar_symbol: None,
+                split_children: Vec::new(),
+                skip_nested: None,
+                overlap_lines: 0,
+                exclude_dirs: None,
+                imports: None,
+                docs: false,
+            },
+        );
+
+        lang.insert(
+            "thrift".to_string(),
+            LangConfig {
+                extensions: vec!["thrift".to_string()],
+                grammar: "builtin-idl".to_string(),
+                chunk_on: None,
+                grammar_symbol: None,
+                split_children: Vec::new(),
+                skip_nested: None,
+                overlap_lines: 0,
+                exclude_dirs: None,
+                imports: None,
+                docs: false,
+            },
+        );
+
+        lang.insert(
+            "notebook".to_string(),
+            LangConfig {
+                extensions: vec!["ipynb".to_string()],
+                grammar: "builtin-ipynb".to_string(),
+                chunk_on: None,
+                grammar_symbol: None,
+                split_children: Vec::new(),
+                skip_nested: None,
+                overlap_lines: 0,
+                exclude_dirs: None,
+                imports: None,
+                docs: false,
+            },
+        );
+
+        #[cfg(feature = "ruby")]
+        lang.insert(
+            "ruby".to_string(),
+            builtin_lang(&["rb"], "tree-sitter-ruby"),
+        );
+        #[cfg(feature = "php")]
+        lang.insert("php".to_string(), builtin_lang(&["php"], "tree-sitter-php"));
+        #[cfg(feature = "swift")]
+        lang.insert(
+            "swift".to_string(),
+            builtin_lang(&["swift"], "tree-sitter-swift"),
+        );
+
+        Self {
+            lang,
+            ranking: RankingConfig::default(),
+            metrics: MetricsConfig::default(),
+            grammars: GrammarsConfig::default(),
+            index: IndexConfig::default(),
+            indexing: IndexingConfig::default(),
+            server: ServerConfig::default(),
+            embedding: EmbeddingConfig::default(),
+            dependencies: DependenciesConfig::default(),
+        }
     }
 
     /// Path to the config file: `~/.config/claudevil/config.toml`
@@ -112,6 +1022,33 @@ impl Config {
             .map(|dirs| dirs.config_dir().join("config.toml"))
     }
 
+    /// Directories to search for grammar libraries: the configured ones,
+    /// then `<data dir>/claudevil/grammars`.
+    pub fn grammar_search_paths(&self) -> Vec<PathBuf> {
+        let mut paths = self.grammars.search_paths.clone();
+        if let Some(dirs) = directories::BaseDirs::new() {
+            paths.push(dirs.data_dir().join("claudevil").join("grammars"));
+        }
+        paths
+    }
+
+    /// Directory names skipped by the index walk: every language's
+    /// `exclude_dirs`, sorted and deduplicated.
+    pub fn excluded_dirs(&self) -> Vec<String> {
+        let mut dirs: Vec<String> = self
+            .lang
+            .iter()
+            .flat_map(|(name, lang)| {
+                lang.exclude_dirs
+                    .clone()
+                    .unwrap_or_else(|| default_exclude_dirs(name))
+            })
+            .collect();
+        dirs.sort_unstable();
+        dirs.dedup();
+        dirs
+    }
+
     /// Names of all configured languages, sorted for stable output.
     pub fn language_names(&self) -> Vec<&str> {
         let mut names: Vec<&str> = self.lang.keys().map(|s| s.as_str()).collect();
@@ -120,6 +1057,58 @@ impl Config {
     }
 }
 
+/// Config for a compiled-in grammar with every other setting at its default.
+#[cfg(any(feature = "ruby", feature = "php", feature = "swift"))]
+fn builtin_lang(extensions: &[&str], grammar: &str) -> LangConfig {
+    LangConfig {
+        extensions: extensions.iter().map(|e| e.to_string()).collect(),
+        grammar: grammar.to_string(),
+        chunk_on: None,
+        grammar_symbol: None,
+        split_children: Vec::new(),
+        skip_nested: None,
+        overlap_lines: 0,
+        exclude_dirs: None,
+        imports: None,
+        docs: false,
+    }
+}
+
+/// Built-in default `exclude_dirs`: where each language's package manager
+/// or build tool puts third-party and generated code.
+fn default_exclude_dirs(language: &str) -> Vec<String> {
+    let dirs: &[&str] = match language {
+        "go" => &["vendor"],
+        "rust" => &["target"],
+        "python" => &["venv", ".venv", "site-packages", "__pycache__"],
+        "ruby" => &["vendor"],
+        "php" => &["vendor"],
+        "swift" => &[".build", "Pods", "Carthage"],
+        "zig" => &["zig-cache", "zig-out"],
+        "javascript" | "typescript" => &["node_modules"],
+        _ => &[],
+    };
+    dirs.iter().map(|s| s.to_string()).collect()
+}
+
+/// Built-in default `imports` node kinds for known languages.
+pub fn default_imports(language: &str) -> Vec<String> {
+    let kinds: &[&str] = match language {
+        "go" => &["import_declaration"],
+        "rust" => &["use_declaration", "extern_crate_declaration"],
+        "python" => &[
+            "import_statement",
+            "import_from_statement",
+            "future_import_statement",
+        ],
+        "php" => &["namespace_use_declaration"],
+        "swift" => &["import_declaration"],
+        "javascript" | "typescript" => &["import_statement"],
+        _ => &[],
+    };
+    kinds.iter().map(|s| s.to_string()).collect()
+}
+
 /// Built-in default `chunk_on` node kinds for known languages.
 fn default_chunk_on(language: &str) -> Vec<String> {
     let kinds: &[&str] = match language {
@@ -147,6 +1136,60 @@ fn default_chunk_on(language: &str) -> Vec<String> {
             "class_definition",
             "decorated_definition",
         ],
+        "sh" => &[
+            "function_definition",
+            "if_statement",
+            "for_statement",
+            "c_style_for_statement",
+            "while_statement",
+            "case_statement",
+        ],
+        // Top-level keys; Kubernetes manifests are kept whole by the chunker
+        "yaml" => &["block_mapping_pair"],
+        "hcl" => &[
+            "resource",
+            "data",
+            "module",
+            "variable",
+            "output",
+            "provider",
+            "locals",
+            "terraform",
+        ],
+        // Declarations of the built-in IDL scanner; `function` is a Thrift
+        // service method
+        "protobuf" => &["message", "enum", "service", "rpc"],
+        "thrift" => &[
+            "struct",
+            "union",
+            "exception",
+            "enum",
+            "service",
+            "function",
+        ],
+        // Cell types; add "markdown" to index the prose between code cells
+        "notebook" => &["code"],
+        // Second tier: ruby, php, and swift are compiled in behind cargo
+        // features. Zig has no grammar crate, so it never is; its grammar
+        // is always loaded at runtime, and only these defaults are built in
+        "ruby" => &["method", "singleton_method", "class", "module"],
+        "php" => &[
+            "function_definition",
+            "class_declaration",
+            "interface_declaration",
+            "trait_declaration",
+            "enum_declaration",
+            "method_declaration",
+        ],
+        // `class_declaration` also covers structs, enums, actors, and extensions
+        "swift" => &[
+            "class_declaration",
+            "protocol_declaration",
+            "function_declaration",
+            "init_declaration",
+            "typealias_declaration",
+        ],
+        "zig" => &["function_declaration", "test_declaration"],
         _ => &[],
     };
     kinds.iter().map(|s| (*s).to_string()).collect()
@@ -156,13 +1199,50 @@ fn default_chunk_on(language: &str) -> Vec<String> {
 mod tests {
     use super::*;
 
+    const DEFAULT_LANGUAGES: [&str; 9] = [
+        "go", "rust", "python", "sh", "yaml", "hcl", "protobuf", "thrift", "notebook",
+    ];
+
+    /// The default config without languages added by cargo features.
+    fn base_config() -> Config {
+        let mut config = Config::default_config();
+        config
+            .lang
+            .retain(|name, _| DEFAULT_LANGUAGES.contains(&name.as_str()));
+        config
+    }
+
     #[test]
-    fn default_config_has_three_languages() {
+    fn default_config_has_nine_languages() {
         let config = Config::default_config();
-        assert_eq!(config.lang.len(), 3);
-        assert!(config.lang.contains_key("go"));
-        assert!(config.lang.contains_key("rust"));
-        assert!(config.lang.contains_key("python"));
+        let extra = [
+            cfg!(feature = "ruby"),
+            cfg!(feature = "php"),
+            cfg!(feature = "swift"),
+        ]
+        .into_iter()
+        .filter(|&enabled| enabled)
+        .count();
+        assert_eq!(config.lang.len(), DEFAULT_LANGUAGES.len() + extra);
+        for name in DEFAULT_LANGUAGES {
+            assert!(config.lang.contains_key(name), "missing {name}");
+        }
+    }
+
+    #[test]
+    fn extension_mapping_infra_formats() {
+        let config = Config::default_config();
+        for (ext, expected) in [
+            ("sh", "sh"),
+            ("yml", "yaml"),
+            ("tf", "hcl"),
+            ("ipynb", "notebook"),
+            ("proto", "protobuf"),
+            ("thrift", "thrift"),
+        ] {
+            let (name, _) = config.language_for_extension(ext).unwrap();
+            assert_eq!(name, expected, "extension {ext}");
+        }
     }
 
     #[test]
@@ -202,6 +1282,42 @@ mod tests {
         assert!(config.language_for_extension("js").is_none());
     }
 
+    #[test]
+    fn embedding_prefixes() {
+        let config = Config::default_config();
+        assert_eq!(config.embedding.query_prefix, "");
+        assert_eq!(config.embedding.passage_prefix, "");
+
+        let raw = r#"
+[embedding]
+query_prefix = "query: "
+passage_prefix = "passage: "
+"#;
+        let config: Config = toml::from_str(raw).unwrap();
+        assert_eq!(config.embedding.query_prefix, "query: ");
+        assert_eq!(config.embedding.passage_prefix, "passage: ");
+    }
+
+    #[test]
+    fn dependency_filters() {
+        let raw = r#"
+[dependencies]
+index = true
+exclude = ["golang.org/x/sys"]
+"#;
+        let config: Config = toml::from_str(raw).unwrap();
+        assert!(config.dependencies.index);
+        assert!(config.dependencies.allows("serde"));
+        assert!(!config.dependencies.allows("golang.org/x/sys"));
+
+        let only = DependenciesConfig {
+            include: vec!["serde".to_string()],
+            ..Default::default()
+        };
+        assert!(only.allows("serde"));
+        assert!(!only.allows("tokio"));
+    }
+
     #[test]
     fn toml_parsing_with_chunk_on() {
         let raw = r#"
@@ -229,6 +1345,250 @@ grammar = "tree-sitter-go"
 "#;
         let config: Config = toml::from_str(raw).unwrap();
         assert!(config.lang["go"].chunk_on.is_none());
+        assert!(config.lang["go"].split_children.is_empty());
+    }
+
+    #[test]
+    fn toml_parsing_with_split_children() {
+        let raw = r#"
+[lang.rust]
+extensions = ["rs"]
+grammar = "tree-sitter-rust"
+split_children = ["impl_item"]
+"#;
+        let config: Config = toml::from_str(raw).unwrap();
+        assert_eq!(config.lang["rust"].split_children, vec!["impl_item"]);
+    }
+
+    #[test]
+    fn toml_parsing_ranking() {
+        let raw = r#"
+[ranking]
+usage_boost = 0.05
+"#;
+        let config: Config = toml::from_str(raw).unwrap();
+        assert!((config.ranking.usage_boost - 0.05).abs() < f32::EPSILON);
+        assert!(config.lang.is_empty());
+    }
+
+    #[test]
+    fn toml_parsing_metrics() {
+        let raw = r#"
+[metrics]
+listen = "127.0.0.1:9464"
+"#;
+        let config: Config = toml::from_str(raw).unwrap();
+        assert_eq!(config.metrics.listen.as_deref(), Some("127.0.0.1:9464"));
+    }
+
+    #[test]
+    fn toml_parsing_grammars() {
+        let raw = r#"
+[grammars]
+search_paths = ["/opt/grammars"]
+
+[lang.zig]
+extensions = ["zig"]
+grammar = "tree-sitter-zig"
+grammar_symbol = "tree_sitter_zig"
+"#;
+        let config: Config = toml::from_str(raw).unwrap();
+        assert_eq!(
+            config.grammars.search_paths,
+            vec![PathBuf::from("/opt/grammars")]
+        );
+        assert_eq!(
+            config.lang["zig"].grammar_symbol.as_deref(),
+            Some("tree_sitter_zig")
+        );
+        assert_eq!(
+            config.grammar_search_paths()[0],
+            PathBuf::from("/opt/grammars")
+        );
+    }
+
+    #[test]
+    fn toml_parsing_index() {
+        let raw = r#"
+[index]
+metric = "ip"
+connectivity = 32
+expansion_search = 128
+dimensions = 256
+content = "offsets"
+"#;
+        let config: Config = toml::from_str(raw).unwrap();
+        assert_eq!(config.index.metric, Metric::Ip);
+        assert_eq!(config.index.connectivity, 32);
+        assert_eq!(config.index.expansion_add, 0);
+        assert_eq!(config.index.expansion_search, 128);
+        assert_eq!(config.index.dimensions, 256);
+        assert_eq!(config.index.content, ContentStorage::Offsets);
+        assert_eq!(IndexConfig::default().content, ContentStorage::Inline);
+    }
+
+    #[test]
+    fn toml_parsing_qdrant_backend() {
+        let raw = r#"
+[index]
+backend = "qdrant"
+
+[index.qdrant]
+url = "http://qdrant.internal:6333"
+"#;
+        let config: Config = toml::from_str(raw).unwrap();
+        assert_eq!(config.index.backend, Backend::Qdrant);
+        assert_eq!(config.index.qdrant.url, "http://qdrant.internal:6333");
+        assert_eq!(config.index.qdrant.collection, "claudevil");
+        assert_eq!(Config::default_config().index.backend, Backend::Usearch);
+    }
+
+    #[test]
+    fn toml_parsing_rejects_unknown_metric() {
+        let err = toml::from_str::<Config>("[index]\nmetric = \"hamming\"\n").unwrap_err();
+        assert!(err.to_string().contains("unknown variant"), "{err}");
+    }
+
+    #[test]
+    fn toml_parsing_indexing() {
+        let config: Config = toml::from_str("[indexing]\nmax_file_size = 4096\n").unwrap();
+        assert_eq!(config.indexing.max_file_size, 4096);
+        assert!(!config.indexing.sync_with_git);
+        assert_eq!(config.indexing.warmup_queries, 5);
+        assert_eq!(config.indexing.max_files, 100_000);
+        assert_eq!(Config::default_config().indexing.max_file_size, 1024 * 1024);
+
+        let config: Config = toml::from_str(
+            "[indexing]
+max_files = 0
+max_depth = 4
+max_total_bytes = 1000
+",
+        )
+        .unwrap();
+        assert_eq!(config.indexing.max_files, 0);
+        assert_eq!(config.indexing.max_depth, 4);
+        assert_eq!(config.indexing.max_total_bytes, 1000);
+    }
+
+    #[test]
+    fn toml_parsing_server() {
+        let config: Config =
+            toml::from_str("[server]\nmax_limit = 25\nshow_distance = false\n").unwrap();
+        assert_eq!(config.server.default_limit, 10);
+        assert_eq!(config.server.max_limit, 25);
+        assert_eq!(config.server.max_snippet_lines, 0);
+        assert!(!config.server.show_distance);
+        assert_eq!(config.server.links, Links::None);
+        let config: Config = toml::from_str("[server]\nlinks = \"vscode\"\n").unwrap();
+        assert_eq!(config.server.links, Links::Vscode);
+        assert!(config.server.validate().is_ok());
+        assert_eq!(config.server.max_concurrent_tools, 8);
+    }
+
+    #[test]
+    fn tool_timeouts_fall_back_to_the_default() {
+        let config: Config = toml::from_str(
+            "[server]\ntool_timeout_ms = 5000\n\
+             [server.tool_timeouts_ms]\nsearch = 10000\nreindex = 0\n",
+        )
+        .unwrap();
+        let timeout = |tool| config.server.tool_timeout(tool);
+        assert_eq!(timeout("search"), Some(Duration::from_secs(10)));
+        assert_eq!(timeout("reindex"), None);
+        assert_eq!(timeout("find_symbol"), Some(Duration::from_secs(5)));
+        assert_eq!(
+            ServerConfig::default().tool_timeout("search"),
+            Some(Duration::from_secs(30))
+        );
+    }
+
+    #[test]
+    fn server_limits_must_be_consistent() {
+        let config: Config =
+            toml::from_str("[server]\ndefault_limit = 50\nmax_limit = 20\n").unwrap();
+        let err = config.server.validate().unwrap_err().to_string();
+        assert!(err.contains("default_limit is 50"), "{err}");
+        assert!(
+            ServerConfig {
+                max_limit: 0,
+                ..Default::default()
+            }
+            .validate()
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn fallback_chunking() {
+        let config = Config::default_config();
+        assert!(!config.indexing.fallback_chunking);
+        assert_eq!(config.indexing.fallback_lines, 40);
+
+        let config: Config =
+            toml::from_str("[indexing]\nfallback_chunking = true\nfallback_lines = 0\n").unwrap();
+        let err = config.indexing.validate().unwrap_err().to_string();
+        assert!(err.contains("fallback_lines is 0"), "{err}");
+    }
+
+    #[test]
+    fn batch_bounds_must_hold_a_size() {
+        assert!(Config::default_config().indexing.validate().is_ok());
+        let config: Config =
+            toml::from_str("[indexing]\nmin_batch = 300\nmax_batch = 100\n").unwrap();
+        let err = config.indexing.validate().unwrap_err().to_string();
+        assert!(err.contains("min_batch 300 and max_batch 100"), "{err}");
+    }
+
+    #[test]
+    fn embedding_model_source() {
+        let config = Config::default_config();
+        assert!(!config.embedding.offline);
+        assert!(config.embedding.model_dir.is_none());
+
+        let config: Config = toml::from_str(
+            "[embedding]\noffline = true\nendpoint = \"https://hf.internal\"\n\
+             model_dir = \"/opt/models/minilm\"\n",
+        )
+        .unwrap();
+        assert!(config.embedding.offline);
+        assert_eq!(
+            config.embedding.endpoint.as_deref(),
+            Some("https://hf.internal")
+        );
+        assert_eq!(
+            config.embedding.model_dir,
+            Some(PathBuf::from("/opt/models/minilm"))
+        );
+    }
+
+    #[test]
+    fn metrics_disabled_by_default() {
+        let config = Config::default_config();
+        assert!(config.metrics.listen.is_none());
+    }
+
+    #[test]
+    fn ranking_defaults_to_no_boost() {
+        let config = Config::default_config();
+        assert_eq!(config.ranking.usage_boost, 0.0);
+    }
+
+    #[test]
+    fn path_boost_defaults_on() {
+        assert_eq!(Config::default_config().ranking.path_boost, 0.05);
+        let config: Config = toml::from_str("[ranking]\nusage_boost = 0.1\n").unwrap();
+        assert_eq!(config.ranking.path_boost, 0.05);
+        assert_eq!(config.ranking.focus_boost, 0.1);
+    }
+
+    #[test]
+    fn generated_files_are_demoted_by_default() {
+        let config = Config::default_config();
+        assert_eq!(config.indexing.generated, GeneratedFiles::Demote);
+        assert_eq!(config.ranking.generated_penalty, 0.1);
+        let config: Config = toml::from_str("[indexing]\ngenerated = \"skip\"\n").unwrap();
+        assert_eq!(config.indexing.generated, GeneratedFiles::Skip);
     }
 
     #[test]
@@ -255,16 +1615,58 @@ grammar = "tree-sitter-go"
         assert!(kinds.contains(&"decorated_definition".to_string()));
     }
 
+    #[test]
+    fn default_chunk_on_second_tier() {
+        assert!(default_chunk_on("ruby").contains(&"method".to_string()));
+        assert!(default_chunk_on("php").contains(&"method_declaration".to_string()));
+        assert!(default_chunk_on("swift").contains(&"protocol_declaration".to_string()));
+        assert!(default_chunk_on("zig").contains(&"function_declaration".to_string()));
+        // Only ever loaded at runtime, so never configured by default
+        assert!(!Config::default_config().lang.contains_key("zig"));
+    }
+
     #[test]
     fn default_chunk_on_unknown() {
         let kinds = default_chunk_on("haskell");
         assert!(kinds.is_empty());
     }
 
+    #[test]
+    fn excluded_dirs_default_and_override() {
+        let mut config = base_config();
+        assert_eq!(
+            config.excluded_dirs(),
+            [
+                ".venv",
+                "__pycache__",
+                "site-packages",
+                "target",
+                "vendor",
+                "venv"
+            ]
+        );
+
+        let raw = r#"
+[lang.go]
+extensions = ["go"]
+grammar = "tree-sitter-go"
+exclude_dirs = []
+"#;
+        let user: Config = toml::from_str(raw).unwrap();
+        config.lang.extend(user.lang);
+        assert!(!config.excluded_dirs().contains(&"vendor".to_string()));
+        assert!(config.excluded_dirs().contains(&"target".to_string()));
+    }
+
     #[test]
     fn language_names_sorted() {
-        let config = Config::default_config();
+        let config = base_config();
         let names = config.language_names();
-        assert_eq!(names, vec!["go", "python", "rust"]);
+        assert_eq!(
+            names,
+            vec![
+                "go", "hcl", "notebook", "protobuf", "python", "rust", "sh", "thrift", "yaml"
+            ]
+        );
     }
 }
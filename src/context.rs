@@ -0,0 +1,703 @@
+//! Assembling a consolidated context bundle for a task.
+//!
+//! `gather_context` runs a semantic search plus symbol lookups for names the
+//! task mentions, then stitches the hits into one bundle: overlapping chunks
+//! are collapsed, the most relevant ones are kept within a token budget, and
+//! the survivors are ordered by file and line so they read like source.
+//!
+//! `search` with `max_tokens` packs ranked results into a budget instead,
+//! keeping their order and cutting the last one that fits short, and with
+//! `group_by_file` gathers them under their files, joining results that
+//! touch or overlap. With `score_distribution`, it also summarizes the
+//! distances of the top candidates, so a caller can tell a strong match
+//! from the least bad of a weak field. With `with_context`, it adds each
+//! file's imports and the type each method belongs to, found by
+//! [`method_owner`], in what the results leave of the budget.
+
+use crate::error::Result;
+use crate::store::SearchResult;
+
+/// Rough characters per token for budgeting; close enough for code and far
+/// cheaper than running a tokenizer over every candidate.
+const CHARS_PER_TOKEN: usize = 4;
+
+/// Most symbol names pulled out of a task description.
+const MAX_SYMBOL_HINTS: usize = 8;
+
+/// Candidates whose distances a score distribution summarizes.
+pub const DISTRIBUTION_CANDIDATES: usize = 100;
+
+/// Ranks at which a score distribution reports the distance.
+const DISTRIBUTION_RANKS: [usize; 5] = [1, 5, 10, 50, 100];
+
+/// Estimated token cost of a chunk's content.
+pub fn estimate_tokens(text: &str) -> usize {
+    text.len().div_ceil(CHARS_PER_TOKEN)
+}
+
+/// The first `max_lines` lines of `content`, noting how many were left out.
+pub fn clip_lines(content: &str, max_lines: Option<usize>) -> String {
+    let total = content.lines().count();
+    match max_lines {
+        Some(max) if total > max => {
+            let mut clipped: Vec<&str> = content.lines().take(max).collect();
+            let more = format!("... {} more lines (use read_file)", total - max);
+            clipped.push(&more);
+            clipped.join("\n")
+        }
+        _ => content.to_string(),
+    }
+}
+
+/// Lines `start` through `end` of `content`, 1-based and inclusive,
+/// clamped to the lines it has.
+pub fn line_range(content: &str, start: i64, end: i64) -> String {
+    let start = start.max(1);
+    let skip = usize::try_from(start - 1).unwrap_or(0);
+    let take = usize::try_from(end.saturating_sub(start).saturating_add(1)).unwrap_or(0);
+    content
+        .lines()
+        .skip(skip)
+        .take(take)
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Results that fit a token budget, from [`pack`].
+#[derive(Debug)]
+pub struct Packed {
+    pub results: Vec<SearchResult>,
+    /// Tokens used by the packed results, as measured by the cost function.
+    pub tokens: usize,
+    /// Results left out entirely.
+    pub omitted: usize,
+}
+
+/// Take results (most relevant first) while their `cost` in tokens fits
+/// `max_tokens`. The first result that doesn't fit is cut to as many whole
+/// lines as do, and nothing after it is kept.
+pub fn pack(
+    results: Vec<SearchResult>,
+    max_tokens: usize,
+    mut cost: impl FnMut(&SearchResult) -> Result<usize>,
+) -> Result<Packed> {
+    let total = results.len();
+    let mut packed = Vec::new();
+    let mut tokens = 0;
+
+    for mut result in results {
+        let full_cost = cost(&result)?;
+        if tokens + full_cost <= max_tokens {
+            tokens += full_cost;
+            packed.push(result);
+            continue;
+        }
+
+        // Cost grows with the lines kept, so search for the most that fit
+        let content = std::mem::take(&mut result.content);
+        let (mut fits, mut fits_cost) = (0, 0);
+        let (mut lo, mut hi) = (1, content.lines().count().saturating_sub(1));
+        while lo <= hi {
+            let mid = lo + (hi - lo) / 2;
+            result.content = clip_lines(&content, Some(mid));
+            let mid_cost = cost(&result)?;
+            if tokens + mid_cost <= max_tokens {
+                (fits, fits_cost) = (mid, mid_cost);
+                lo = mid + 1;
+            } else {
+                hi = mid - 1;
+            }
+        }
+        if fits > 0 {
+            result.content = clip_lines(&content, Some(fits));
+            tokens += fits_cost;
+            packed.push(result);
+        }
+        break;
+    }
+
+    Ok(Packed {
+        omitted: total - packed.len(),
+        results: packed,
+        tokens,
+    })
+}
+
+/// The `sections` whose `cost` in tokens fits what is left of
+/// `max_tokens`, in order, and the tokens they use. Sections stand alone,
+/// so one that doesn't fit is left out and later ones still tried.
+pub fn pack_sections(
+    sections: Vec<String>,
+    max_tokens: usize,
+    mut cost: impl FnMut(&str) -> Result<usize>,
+) -> Result<(Vec<String>, usize)> {
+    let mut packed = Vec::new();
+    let mut tokens = 0;
+    for section in sections {
+        let section_cost = cost(&section)?;
+        if tokens + section_cost <= max_tokens {
+            tokens += section_cost;
+            packed.push(section);
+        }
+    }
+    Ok((packed, tokens))
+}
+
+/// Names in `task` that look like code symbols: anything in backticks, plus
+/// snake_case and camelCase/PascalCase words.
+pub fn symbol_hints(task: &str) -> Vec<String> {
+    let mut hints: Vec<String> = Vec::new();
+    let mut push = |name: &str| {
+        let name = name.trim_matches(|c: char| !(c.is_alphanumeric() || c == '_'));
+        if name.len() >= 3 && !hints.iter().any(|h| h == name) {
+            hints.push(name.to_string());
+        }
+    };
+
+    for (i, quoted) in task.split('`').enumerate() {
+        if i % 2 == 1 {
+            push(quoted.trim().trim_end_matches("()"));
+        }
+    }
+    for word in task.split(|c: char| !(c.is_alphanumeric() || c == '_')) {
+        let inner_upper = word.chars().skip(1).any(|c| c.is_uppercase())
+            && word.chars().any(|c| c.is_lowercase());
+        if word.contains('_') || inner_upper {
+            push(word);
+        }
+    }
+
+    hints.truncate(MAX_SYMBOL_HINTS);
+    hints
+}
+
+/// The chunks chosen for a bundle, in file/line order.
+#[derive(Debug)]
+pub struct Bundle {
+    pub chunks: Vec<SearchResult>,
+    /// Estimated tokens used by the chosen chunks.
+    pub tokens: usize,
+    /// Relevant chunks left out because they didn't fit the budget.
+    pub omitted: usize,
+}
+
+/// Pick chunks from `candidates` (most relevant first) into a bundle of at
+/// most `max_tokens`.
+///
+/// Duplicates and chunks nested inside an already chosen chunk are dropped;
+/// a chunk that encloses chosen ones replaces them.
+pub fn assemble(candidates: Vec<SearchResult>, max_tokens: usize) -> Bundle {
+    let mut chosen: Vec<SearchResult> = Vec::new();
+    let mut tokens = 0;
+    let mut omitted = 0;
+
+    for candidate in candidates {
+        if chosen
+            .iter()
+            .any(|c| c.id == candidate.id || encloses(c, &candidate))
+        {
+            continue;
+        }
+
+        let replaced: usize = chosen
+            .iter()
+            .filter(|c| encloses(&candidate, c))
+            .map(|c| estimate_tokens(&c.content))
+            .sum();
+        let cost = estimate_tokens(&candidate.content);
+        if tokens - replaced + cost > max_tokens {
+            omitted += 1;
+            continue;
+        }
+
+        chosen.retain(|c| !encloses(&candidate, c));
+        tokens = tokens - replaced + cost;
+        chosen.push(candidate);
+    }
+
+    chosen.sort_by(|a, b| {
+        a.file_path
+            .cmp(&b.file_path)
+            .then(a.start_line.cmp(&b.start_line))
+    });
+    Bundle {
+        chunks: chosen,
+        tokens,
+        omitted,
+    }
+}
+
+/// Whether `outer` covers every line of `inner` in the same file.
+fn encloses(outer: &SearchResult, inner: &SearchResult) -> bool {
+    outer.file_path == inner.file_path
+        && outer.start_line <= inner.start_line
+        && inner.end_line <= outer.end_line
+}
+
+/// How far the top candidates of a search were from the query, from
+/// [`score_distribution`].
+#[derive(Debug, PartialEq)]
+pub struct ScoreDistribution {
+    pub candidates: usize,
+    pub min: f32,
+    pub median: f32,
+    pub max: f32,
+    /// The distance at each of a few ranks the candidates reach, 1-based.
+    pub at_rank: Vec<(usize, f32)>,
+}
+
+/// Summarize `distances`, in rank order, or `None` if there are none.
+pub fn score_distribution(distances: &[f32]) -> Option<ScoreDistribution> {
+    let mut sorted = distances.to_vec();
+    sorted.sort_by(f32::total_cmp);
+    let (&min, &max) = (sorted.first()?, sorted.last()?);
+    let mid = sorted.len() / 2;
+    let median = if sorted.len().is_multiple_of(2) {
+        (sorted[mid - 1] + sorted[mid]) / 2.0
+    } else {
+        sorted[mid]
+    };
+    let at_rank = DISTRIBUTION_RANKS
+        .iter()
+        .filter_map(|&rank| distances.get(rank - 1).map(|&d| (rank, d)))
+        .collect();
+    Some(ScoreDistribution {
+        candidates: distances.len(),
+        min,
+        median,
+        max,
+        at_rank,
+    })
+}
+
+/// Search results from one file, from [`group_by_file`].
+#[derive(Debug)]
+pub struct FileGroup {
+    pub file_path: String,
+    pub spans: Vec<Span>,
+}
+
+/// Results covering one unbroken range of a file's lines.
+#[derive(Debug)]
+pub struct Span {
+    pub start_line: i64,
+    pub end_line: i64,
+    /// The range's lines, each once.
+    pub content: String,
+    /// The results joined into the span, in line order.
+    pub results: Vec<SearchResult>,
+}
+
+/// Gather `results` (most relevant first) by file, files in the order of
+/// their best result and each file's results in line order. Results that
+/// touch or overlap become one span. Notes and commits, whose lines aren't
+/// a file's, are never joined.
+pub fn group_by_file(results: Vec<SearchResult>) -> Vec<FileGroup> {
+    let mut files: Vec<(String, Vec<SearchResult>)> = Vec::new();
+    for result in results {
+        match files.iter_mut().find(|(path, _)| *path == result.file_path) {
+            Some((_, group)) => group.push(result),
+            None => files.push((result.file_path.clone(), vec![result])),
+        }
+    }
+
+    files
+        .into_iter()
+        .map(|(file_path, mut results)| {
+            let joinable = !file_path.starts_with('<');
+            if joinable {
+                results.sort_by_key(|r| (r.start_line, r.end_line));
+            }
+            let mut spans: Vec<Span> = Vec::new();
+            for result in results {
+                match spans.last_mut() {
+                    Some(span) if joinable && result.start_line <= span.end_line + 1 => {
+                        if result.end_line > span.end_line {
+                            let repeated =
+                                usize::try_from(span.end_line - result.start_line + 1).unwrap_or(0);
+                            for line in result.content.lines().skip(repeated) {
+                                span.content.push('\n');
+                                span.content.push_str(line);
+                            }
+                            span.end_line = result.end_line;
+                        }
+                        span.results.push(result);
+                    }
+                    _ => spans.push(Span {
+                        start_line: result.start_line,
+                        end_line: result.end_line,
+                        content: result.content.clone(),
+                        results: vec![result],
+                    }),
+                }
+            }
+            FileGroup { file_path, spans }
+        })
+        .collect()
+}
+
+/// Where the type a method belongs to is defined, as far as its search
+/// result tells.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Owner {
+    /// The type of this name: a Go receiver, the type of a Rust `impl`,
+    /// or the qualifier of a split container's child.
+    Named(String),
+    /// The chunk around the method, such as a Python class.
+    Enclosing,
+}
+
+/// The type `result` is a method of, if it is one. `enclosing` is the
+/// innermost chunk around it, if any.
+pub fn method_owner(result: &SearchResult, enclosing: Option<&SearchResult>) -> Option<Owner> {
+    let kind = result.symbol_kind.as_deref().unwrap_or_default();
+    if !is_callable(kind) {
+        return None;
+    }
+    if let Some((qualifier, _)) = result.symbol_name.as_deref()?.rsplit_once("::") {
+        return Some(Owner::Named(base_type(qualifier)));
+    }
+    if result.language == "go" && kind == "method_declaration" {
+        let signature = result.signature.as_deref().unwrap_or(&result.content);
+        return go_receiver(signature).map(Owner::Named);
+    }
+
+    let enclosing = enclosing?;
+    let around = enclosing.symbol_kind.as_deref().unwrap_or_default();
+    if around == "impl_item" {
+        // Named `Type` or `Trait for Type`
+        let name = enclosing.symbol_name.as_deref()?;
+        let type_name = name.rsplit_once(" for ").map_or(name, |(_, t)| t);
+        return Some(Owner::Named(base_type(type_name)));
+    }
+    is_type(around).then_some(Owner::Enclosing)
+}
+
+/// Whether chunks of `kind` are functions or methods.
+fn is_callable(kind: &str) -> bool {
+    // Python's decorated methods are chunked as the decorated definition
+    kind == "decorated_definition"
+        || kind
+            .split('_')
+            .any(|word| matches!(word, "function" | "method" | "constructor"))
+}
+
+/// Whether chunks of `kind` define a type that methods belong to.
+fn is_type(kind: &str) -> bool {
+    kind.split('_').any(|word| {
+        matches!(
+            word,
+            "class" | "struct" | "enum" | "trait" | "interface" | "protocol" | "module" | "object"
+        )
+    })
+}
+
+/// The receiver's type in a Go method declaration: `Stack` in
+/// `func (s *Stack[T]) Push(v T)`.
+fn go_receiver(signature: &str) -> Option<String> {
+    let rest = &signature[signature.find("func")? + "func".len()..];
+    let receiver = rest.trim_start().strip_prefix('(')?;
+    let receiver = &receiver[..receiver.find(')')?];
+    let type_name = receiver.split_whitespace().last()?;
+    Some(base_type(type_name)).filter(|name| !name.is_empty())
+}
+
+/// `type_name` without references, pointers, generic arguments, or a
+/// module path: `Point` for `&'a mut crate::geo::Point<f64>` or
+/// `*Point[T]`.
+fn base_type(type_name: &str) -> String {
+    let mut type_name = type_name.trim_start_matches(['&', '*']).trim_start();
+    if type_name.starts_with('\'') {
+        type_name = type_name.split_once(' ').map_or("", |(_, rest)| rest);
+    }
+    let type_name = type_name.trim_start_matches("mut ").trim();
+    let type_name = type_name.split(['<', '[']).next().unwrap_or(type_name);
+    type_name
+        .rsplit("::")
+        .next()
+        .unwrap_or(type_name)
+        .trim()
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(id: u64, file: &str, lines: (i64, i64), len: usize) -> SearchResult {
+        SearchResult {
+            id,
+            file_path: file.to_string(),
+            content: "x".repeat(len),
+            symbol_name: None,
+            symbol_kind: None,
+            doc: None,
+            signature: None,
+            build: None,
+            owners: Vec::new(),
+            module: None,
+            language: "rust".to_string(),
+            start_line: lines.0,
+            end_line: lines.1,
+            distance: 0.0,
+            duplicates: Vec::new(),
+            approximate: false,
+        }
+    }
+
+    fn lines(id: u64, file: &str, start: i64, end: i64) -> SearchResult {
+        let mut result = chunk(id, file, (start, end), 0);
+        result.content = (start..=end)
+            .map(|n| format!("line {n}"))
+            .collect::<Vec<_>>()
+            .join("\n");
+        result
+    }
+
+    fn symbol(kind: &str, name: &str, language: &str) -> SearchResult {
+        let mut result = chunk(1, "a", (10, 20), 0);
+        result.symbol_kind = Some(kind.to_string());
+        result.symbol_name = Some(name.to_string());
+        result.language = language.to_string();
+        result
+    }
+
+    #[test]
+    fn methods_know_their_type() {
+        let named = |name: &str| Some(Owner::Named(name.to_string()));
+
+        let mut go = symbol("method_declaration", "Push", "go");
+        go.signature = Some("func (s *Stack[T]) Push(v T)".to_string());
+        assert_eq!(method_owner(&go, None), named("Stack"));
+        go.signature = Some("func (Queue) Len() int".to_string());
+        assert_eq!(method_owner(&go, None), named("Queue"));
+
+        let split = symbol("function_item", "crate::geo::Point<f64>::new", "rust");
+        assert_eq!(base_type("&'a mut Point<T>"), "Point");
+        assert_eq!(method_owner(&split, None), named("Point"));
+
+        let method = symbol("function_item", "area", "rust");
+        let trait_impl = symbol("impl_item", "Display for &'a Shape", "rust");
+        assert_eq!(method_owner(&method, Some(&trait_impl)), named("Shape"));
+        let inherent = symbol("impl_item", "Point<T>", "rust");
+        assert_eq!(method_owner(&method, Some(&inherent)), named("Point"));
+        assert_eq!(method_owner(&method, None), None);
+        let module = symbol("mod_item", "geo", "rust");
+        assert_eq!(method_owner(&method, Some(&module)), None);
+
+        let python = symbol("function_definition", "save", "python");
+        let class = symbol("class_definition", "Invoice", "python");
+        assert_eq!(method_owner(&python, Some(&class)), Some(Owner::Enclosing));
+        let decorated = symbol("decorated_definition", "total", "python");
+        assert_eq!(
+            method_owner(&decorated, Some(&class)),
+            Some(Owner::Enclosing)
+        );
+        let outer = symbol("function_definition", "wrapper", "python");
+        assert_eq!(method_owner(&python, Some(&outer)), None);
+
+        // Types aren't methods of anything
+        assert_eq!(method_owner(&class, None), None);
+        let nested = symbol("struct_item", "Inner", "rust");
+        assert_eq!(method_owner(&nested, Some(&inherent)), None);
+    }
+
+    fn ids(bundle: &Bundle) -> Vec<u64> {
+        bundle.chunks.iter().map(|c| c.id).collect()
+    }
+
+    #[test]
+    fn groups_by_file_joining_touching_results() {
+        let groups = group_by_file(vec![
+            lines(1, "b.rs", 20, 24),
+            lines(2, "a.rs", 1, 3),
+            lines(3, "b.rs", 1, 5),
+            lines(4, "b.rs", 22, 30),
+            lines(5, "b.rs", 6, 8),
+            lines(6, "b.rs", 23, 25),
+        ]);
+        let files: Vec<&str> = groups.iter().map(|g| g.file_path.as_str()).collect();
+        assert_eq!(
+            files,
+            ["b.rs", "a.rs"],
+            "files in order of their best result"
+        );
+
+        let spans = &groups[0].spans;
+        let ranges: Vec<(i64, i64)> = spans.iter().map(|s| (s.start_line, s.end_line)).collect();
+        assert_eq!(ranges, [(1, 8), (20, 30)]);
+        let joined: Vec<u64> = spans[1].results.iter().map(|r| r.id).collect();
+        assert_eq!(joined, [1, 4, 6]);
+
+        // Overlapping lines appear once
+        let expected: Vec<String> = (20..=30).map(|n| format!("line {n}")).collect();
+        assert_eq!(spans[1].content, expected.join("\n"));
+    }
+
+    #[test]
+    fn notes_are_never_joined() {
+        let groups = group_by_file(vec![lines(1, "<notes>", 1, 1), lines(2, "<notes>", 1, 1)]);
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].spans.len(), 2);
+        assert_eq!(groups[0].spans[0].results[0].id, 1);
+    }
+
+    #[test]
+    fn distribution_of_candidate_distances() {
+        assert_eq!(score_distribution(&[]), None);
+
+        let distances: Vec<f32> = (0..12).map(|i| 0.25 + i as f32 * 0.05).collect();
+        let dist = score_distribution(&distances).unwrap();
+        assert_eq!(dist.candidates, 12);
+        assert_eq!((dist.min, dist.max), (0.25, distances[11]));
+        assert!((dist.median - 0.525).abs() < 1e-6, "{}", dist.median);
+        let ranks: Vec<usize> = dist.at_rank.iter().map(|&(rank, _)| rank).collect();
+        assert_eq!(ranks, [1, 5, 10]);
+        assert_eq!(dist.at_rank[1].1, distances[4]);
+
+        // Boosts may reorder results, so ranks need not be sorted
+        let dist = score_distribution(&[0.4, 0.2, 0.3]).unwrap();
+        assert_eq!((dist.min, dist.median, dist.max), (0.2, 0.3, 0.4));
+        assert_eq!(dist.at_rank, [(1, 0.4)]);
+    }
+
+    #[test]
+    fn hints_from_backticks_and_identifiers() {
+        let hints =
+            symbol_hints("Make `Server::start` retry, and fix parse_config in the ChunkRow code");
+        assert_eq!(hints, ["Server::start", "parse_config", "ChunkRow"]);
+    }
+
+    #[test]
+    fn line_range_is_inclusive_and_clamped() {
+        let content = "one\ntwo\nthree\nfour";
+        assert_eq!(line_range(content, 2, 3), "two\nthree");
+        assert_eq!(line_range(content, 0, 1), "one");
+        assert_eq!(line_range(content, 3, 10), "three\nfour");
+        assert_eq!(line_range(content, 5, 6), "");
+        assert_eq!(line_range(content, 3, 2), "");
+    }
+
+    #[test]
+    fn plain_words_are_not_hints() {
+        assert!(symbol_hints("Why does the indexer skip hidden directories?").is_empty());
+    }
+
+    #[test]
+    fn orders_by_file_and_line() {
+        let bundle = assemble(
+            vec![
+                chunk(1, "src/b.rs", (10, 20), 40),
+                chunk(2, "src/a.rs", (30, 40), 40),
+                chunk(3, "src/a.rs", (1, 5), 40),
+            ],
+            1000,
+        );
+        assert_eq!(ids(&bundle), [3, 2, 1]);
+        assert_eq!(bundle.tokens, 30);
+    }
+
+    #[test]
+    fn drops_duplicates_and_nested_chunks() {
+        let bundle = assemble(
+            vec![
+                chunk(1, "src/a.rs", (1, 50), 40),
+                chunk(1, "src/a.rs", (1, 50), 40),
+                chunk(2, "src/a.rs", (10, 20), 40),
+                chunk(3, "src/b.rs", (10, 20), 40),
+            ],
+            1000,
+        );
+        assert_eq!(ids(&bundle), [1, 3]);
+    }
+
+    #[test]
+    fn enclosing_chunk_replaces_nested_ones() {
+        let bundle = assemble(
+            vec![
+                chunk(2, "src/a.rs", (10, 20), 40),
+                chunk(1, "src/a.rs", (1, 50), 80),
+            ],
+            1000,
+        );
+        assert_eq!(ids(&bundle), [1]);
+        assert_eq!(bundle.tokens, 20);
+    }
+
+    fn numbered(id: u64, lines: usize) -> SearchResult {
+        let mut result = chunk(id, "src/a.rs", (1, lines as i64), 0);
+        result.content = (1..=lines)
+            .map(|n| format!("line {n}"))
+            .collect::<Vec<_>>()
+            .join("\n");
+        result
+    }
+
+    /// One token per line, so budgets are easy to reason about.
+    fn lines_cost(result: &SearchResult) -> Result<usize> {
+        Ok(result.content.lines().count())
+    }
+
+    #[test]
+    fn pack_keeps_order_and_cuts_the_last_result() {
+        let packed = pack(
+            vec![numbered(1, 3), numbered(2, 10), numbered(3, 2)],
+            8,
+            lines_cost,
+        )
+        .unwrap();
+        let ids: Vec<u64> = packed.results.iter().map(|r| r.id).collect();
+        assert_eq!(ids, [1, 2]);
+        assert_eq!(packed.tokens, 8);
+        assert_eq!(packed.omitted, 1);
+        assert_eq!(
+            packed.results[1].content,
+            "line 1\nline 2\nline 3\nline 4\n... 6 more lines (use read_file)"
+        );
+    }
+
+    #[test]
+    fn pack_drops_a_result_when_no_line_fits() {
+        let packed = pack(vec![numbered(1, 3), numbered(2, 5)], 4, lines_cost).unwrap();
+        assert_eq!(packed.results.len(), 1);
+        assert_eq!(packed.omitted, 1);
+
+        let packed = pack(vec![numbered(1, 5)], 1, lines_cost).unwrap();
+        assert!(packed.results.is_empty());
+        assert_eq!(packed.omitted, 1);
+    }
+
+    #[test]
+    fn sections_that_dont_fit_are_skipped() {
+        let sections = ["a\nb\nc", "d\ne\nf\ng\nh", "i"].map(String::from).to_vec();
+        let lines = |section: &str| Ok(section.lines().count());
+        let (packed, tokens) = pack_sections(sections.clone(), 5, lines).unwrap();
+        assert_eq!(packed, ["a\nb\nc", "i"]);
+        assert_eq!(tokens, 4);
+        assert!(pack_sections(sections, 0, lines).unwrap().0.is_empty());
+    }
+
+    #[test]
+    fn clip_lines_notes_what_was_cut() {
+        assert_eq!(
+            clip_lines("a\nb\nc", Some(1)),
+            "a\n... 2 more lines (use read_file)"
+        );
+        assert_eq!(clip_lines("a\nb", Some(2)), "a\nb");
+        assert_eq!(clip_lines("a\nb", None), "a\nb");
+    }
+
+    #[test]
+    fn respects_token_budget() {
+        let bundle = assemble(
+            vec![
+                chunk(1, "src/a.rs", (1, 5), 400),
+                chunk(2, "src/b.rs", (1, 5), 400),
+                chunk(3, "src/c.rs", (1, 5), 40),
+            ],
+            120,
+        );
+        assert_eq!(ids(&bundle), [1, 3]);
+        assert_eq!(bundle.omitted, 1);
+    }
+}
@@ -0,0 +1,270 @@
+//! How much of a root the index covers, by file extension.
+//!
+//! The `coverage_report` tool walks the root the way indexing does and
+//! tallies the files and bytes of each extension three ways: indexed,
+//! passed over despite a configured language (too large, binary, or not
+//! UTF-8), and left out for want of one. The last group, largest first, is
+//! the list of languages a config entry would add.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::Path;
+
+/// Extensions whose grammar is compiled in behind a cargo feature, with
+/// the feature.
+const FEATURE_GRAMMARS: &[(&str, &str)] = &[("rb", "ruby"), ("php", "php"), ("swift", "swift")];
+
+/// What indexing does with one file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    /// Indexed as this language.
+    Indexed(String),
+    /// Of a configured language, but passed over.
+    Skipped,
+    /// Of no configured language.
+    Unsupported,
+}
+
+/// Files and bytes of one extension.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Tally {
+    pub files: usize,
+    pub bytes: u64,
+    /// Language the files are indexed as, for indexed extensions.
+    pub language: Option<String>,
+}
+
+impl Tally {
+    fn add(&mut self, bytes: u64) {
+        self.files += 1;
+        self.bytes += bytes;
+    }
+}
+
+/// Files under a root by what indexing does with them, each keyed by
+/// extension (`.go`), or by file name for files without one (`Dockerfile`).
+#[derive(Debug, Default)]
+pub struct Coverage {
+    pub indexed: BTreeMap<String, Tally>,
+    pub skipped: BTreeMap<String, Tally>,
+    pub unsupported: BTreeMap<String, Tally>,
+    /// Extensions listed per group in the report; 0 lists them all.
+    pub shown: usize,
+}
+
+impl Coverage {
+    /// Count the file at `path`, of `bytes` bytes, which indexing gives
+    /// `outcome`.
+    pub fn add(&mut self, path: &Path, bytes: u64, outcome: Outcome) {
+        let key = match path.extension() {
+            Some(ext) => format!(".{}", ext.to_string_lossy()),
+            None => path
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_default(),
+        };
+        match outcome {
+            Outcome::Indexed(language) => {
+                let tally = self.indexed.entry(key).or_default();
+                tally.add(bytes);
+                tally.language.get_or_insert(language);
+            }
+            Outcome::Skipped => self.skipped.entry(key).or_default().add(bytes),
+            Outcome::Unsupported => self.unsupported.entry(key).or_default().add(bytes),
+        }
+    }
+
+    fn total(group: &BTreeMap<String, Tally>) -> (usize, u64) {
+        group.values().fold((0, 0), |(files, bytes), t| {
+            (files + t.files, bytes + t.bytes)
+        })
+    }
+
+    /// `group`'s extensions, most files first, then most bytes.
+    fn ranked(group: &BTreeMap<String, Tally>) -> Vec<(&String, &Tally)> {
+        let mut ranked: Vec<(&String, &Tally)> = group.iter().collect();
+        ranked.sort_by(|(a_key, a), (b_key, b)| {
+            b.files
+                .cmp(&a.files)
+                .then(b.bytes.cmp(&a.bytes))
+                .then(a_key.cmp(b_key))
+        });
+        ranked
+    }
+
+    fn write_group(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+        heading: &str,
+        group: &BTreeMap<String, Tally>,
+        note: impl Fn(&str, &Tally) -> String,
+    ) -> fmt::Result {
+        if group.is_empty() {
+            return Ok(());
+        }
+        writeln!(f, "\n{heading}:")?;
+        let ranked = Self::ranked(group);
+        let shown = if self.shown == 0 {
+            ranked.len()
+        } else {
+            self.shown.min(ranked.len())
+        };
+        for (key, tally) in &ranked[..shown] {
+            writeln!(
+                f,
+                "- {key}: {} files, {}{}",
+                tally.files,
+                size(tally.bytes),
+                note(key, tally)
+            )?;
+        }
+        if shown < ranked.len() {
+            let (files, bytes) = ranked[shown..]
+                .iter()
+                .fold((0, 0), |(files, bytes), (_, t)| {
+                    (files + t.files, bytes + t.bytes)
+                });
+            writeln!(
+                f,
+                "- ... {} more extensions: {files} files, {}",
+                ranked.len() - shown,
+                size(bytes)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Coverage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (indexed_files, indexed_bytes) = Self::total(&self.indexed);
+        let (skipped_files, skipped_bytes) = Self::total(&self.skipped);
+        let (unsupported_files, unsupported_bytes) = Self::total(&self.unsupported);
+        let files = indexed_files + skipped_files + unsupported_files;
+        let bytes = indexed_bytes + skipped_bytes + unsupported_bytes;
+        writeln!(
+            f,
+            "Indexed {indexed_files} of {files} files ({}), {} of {}.",
+            percent(indexed_files as u64, files as u64),
+            size(indexed_bytes),
+            size(bytes)
+        )?;
+
+        self.write_group(f, "Indexed", &self.indexed, |_, tally| {
+            tally
+                .language
+                .as_ref()
+                .map(|language| format!(" (as {language})"))
+                .unwrap_or_default()
+        })?;
+        self.write_group(
+            f,
+            "Not indexed: no configured language (largest gains first)",
+            &self.unsupported,
+            |key, _| {
+                let feature = key
+                    .strip_prefix('.')
+                    .and_then(|ext| FEATURE_GRAMMARS.iter().find(|(e, _)| *e == ext));
+                match feature {
+                    Some((_, feature)) => {
+                        format!(" -- built in behind the `{feature}` cargo feature")
+                    }
+                    None => String::new(),
+                }
+            },
+        )?;
+        self.write_group(
+            f,
+            "Not indexed: too large or binary (see index_status)",
+            &self.skipped,
+            |_, _| String::new(),
+        )?;
+        if !self.unsupported.is_empty() {
+            writeln!(
+                f,
+                "\nTo index an extension, add a [lang.<name>] entry with a tree-sitter grammar \
+                 to the config, or set [indexing] fallback_chunking = true to index every \
+                 text file in line-based chunks."
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// `part` as a share of `whole`, e.g. `"42%"`.
+fn percent(part: u64, whole: u64) -> String {
+    if whole == 0 {
+        return "0%".to_string();
+    }
+    format!("{}%", part * 100 / whole)
+}
+
+/// `bytes` in the largest unit that keeps the number at least 1.
+fn size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{bytes} B");
+    }
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit + 1 < UNITS.len() {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{value:.1} {}", UNITS[unit])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn report_groups_and_ranks_extensions() {
+        let mut coverage = Coverage {
+            shown: 2,
+            ..Default::default()
+        };
+        let go = || Outcome::Indexed("go".to_string());
+        coverage.add(Path::new("main.go"), 1000, go());
+        coverage.add(Path::new("pkg/util.go"), 1048, go());
+        coverage.add(Path::new("web/app.ts"), 4096, Outcome::Unsupported);
+        coverage.add(Path::new("web/index.ts"), 4096, Outcome::Unsupported);
+        coverage.add(Path::new("app.rb"), 10, Outcome::Unsupported);
+        coverage.add(Path::new("Dockerfile"), 10, Outcome::Unsupported);
+        coverage.add(Path::new("README"), 10, Outcome::Unsupported);
+        coverage.add(Path::new("gen/huge.go"), 2 << 20, Outcome::Skipped);
+
+        assert_eq!(coverage.indexed[".go"].files, 2);
+        assert_eq!(coverage.unsupported["Dockerfile"].files, 1);
+
+        let report = coverage.to_string();
+        assert!(
+            report.starts_with("Indexed 2 of 8 files (25%), 2.0 KiB of 2.0 MiB."),
+            "{report}"
+        );
+        assert!(
+            report.contains("- .go: 2 files, 2.0 KiB (as go)"),
+            "{report}"
+        );
+        assert!(report.contains("- .ts: 2 files, 8.0 KiB\n"), "{report}");
+        // Ties broken by name; the rest summarized
+        assert!(
+            report.contains("- .rb: 1 files, 10 B -- built in behind the `ruby` cargo feature")
+        );
+        assert!(
+            report.contains("- ... 2 more extensions: 2 files, 20 B"),
+            "{report}"
+        );
+        assert!(report.contains("- .go: 1 files, 2.0 MiB"), "{report}");
+        assert!(report.contains("fallback_chunking"));
+    }
+
+    #[test]
+    fn sizes_and_shares() {
+        assert_eq!(size(0), "0 B");
+        assert_eq!(size(1536), "1.5 KiB");
+        assert_eq!(size(3 << 30), "3.0 GiB");
+        assert_eq!(percent(1, 3), "33%");
+        assert_eq!(percent(0, 0), "0%");
+    }
+}
@@ -0,0 +1,238 @@
+//! The per-root data directories, and the ones a moved root leaves behind.
+//!
+//! A root's index lives in a directory named after a hash of the root's
+//! path, so moving or renaming the root starts a new, empty index and
+//! strands the old one. Each data directory records the root it indexes,
+//! which lets startup point out a stranded index that may be this root's,
+//! `claudevil gc` remove the ones whose root is gone, and `claudevil relink`
+//! hand one over to the root's new path. A server records the root again
+//! each time it starts, so a directory's newest file tells when its root
+//! was last served, and `claudevil gc --older-than` removes long-unused
+//! ones too.
+
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use crate::error::{Error, Result};
+use crate::lock;
+
+/// File in a data directory holding the path of the root it indexes.
+const ROOT_FILE: &str = "root";
+
+fn io_error(context: String) -> impl FnOnce(std::io::Error) -> Error {
+    move |source| Error::StoreIo { context, source }
+}
+
+/// Record that `db_path` holds the index of `root`.
+pub fn record_root(db_path: &Path, root: &Path) -> Result<()> {
+    let path = db_path.join(ROOT_FILE);
+    std::fs::write(&path, root.as_os_str().as_encoded_bytes())
+        .map_err(io_error(format!("writing {}", path.display())))
+}
+
+/// The root `db_path` indexes, if it was recorded.
+fn recorded_root(db_path: &Path) -> Option<PathBuf> {
+    let root = std::fs::read_to_string(db_path.join(ROOT_FILE)).ok()?;
+    Some(PathBuf::from(root.trim_end_matches('\n')))
+}
+
+/// One root's data directory.
+#[derive(Debug)]
+pub struct DataDir {
+    pub path: PathBuf,
+    /// The root it indexes; `None` for directories made before roots were
+    /// recorded.
+    pub root: Option<PathBuf>,
+    /// Total size of its files.
+    pub bytes: u64,
+    /// When its newest file was last written; `None` if it has no files.
+    pub last_used: Option<SystemTime>,
+}
+
+impl DataDir {
+    /// Whether the root it indexes no longer exists.
+    pub fn is_orphaned(&self) -> bool {
+        self.root.as_ref().is_some_and(|root| !root.exists())
+    }
+
+    /// How long ago it was last used, as of `now`.
+    pub fn unused_for(&self, now: SystemTime) -> Option<Duration> {
+        now.duration_since(self.last_used?).ok()
+    }
+}
+
+/// Total size of the files under `path`, and when the newest was last
+/// written.
+fn usage(path: &Path) -> (u64, Option<SystemTime>) {
+    walkdir::WalkDir::new(path)
+        .into_iter()
+        .filter_map(|e| e.ok()?.metadata().ok())
+        .filter(|m| m.is_file())
+        .fold((0, None), |(bytes, newest), m| {
+            (bytes + m.len(), newest.max(m.modified().ok()))
+        })
+}
+
+/// Total size of the files under `path`, such as a data directory.
+pub fn size(path: &Path) -> u64 {
+    usage(path).0
+}
+
+/// Every data directory under `base`, sorted by path.
+pub fn list(base: &Path) -> Result<Vec<DataDir>> {
+    let entries = match std::fs::read_dir(base) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(io_error(format!("listing {}", base.display()))(e)),
+    };
+    let mut dirs = Vec::new();
+    for entry in entries {
+        let path = entry
+            .map_err(io_error(format!("listing {}", base.display())))?
+            .path();
+        if !path.is_dir() {
+            continue;
+        }
+        let (bytes, last_used) = usage(&path);
+        dirs.push(DataDir {
+            root: recorded_root(&path),
+            path,
+            bytes,
+            last_used,
+        });
+    }
+    dirs.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(dirs)
+}
+
+/// Orphaned data directories under `base` that may hold `root`'s index
+/// from before it moved: those whose root had the same directory name.
+pub fn stranded(base: &Path, root: &Path) -> Result<Vec<DataDir>> {
+    Ok(list(base)?
+        .into_iter()
+        .filter(|dir| {
+            dir.is_orphaned()
+                && dir
+                    .root
+                    .as_deref()
+                    .is_some_and(|old| old.file_name() == root.file_name())
+        })
+        .collect())
+}
+
+/// Delete a data directory, unless a server still holds it.
+/// Returns whether it was deleted.
+pub fn remove(dir: &DataDir) -> Result<bool> {
+    let Some(_lock) = lock::acquire(&dir.path)? else {
+        return Ok(false);
+    };
+    std::fs::remove_dir_all(&dir.path)
+        .map_err(io_error(format!("removing {}", dir.path.display())))?;
+    Ok(true)
+}
+
+/// Move the index in `old_dir` to `new_dir`, the data directory of
+/// `new_root`, replacing whatever index `new_dir` held.
+pub fn relink(old_dir: &Path, new_dir: &Path, new_root: &Path) -> Result<()> {
+    let busy = |dir: &Path| Error::StoreIo {
+        context: format!("relinking {}", dir.display()),
+        source: std::io::Error::other(format!(
+            "{} is using it -- stop that server first",
+            lock::holder(dir)
+        )),
+    };
+    let Some(old_lock) = lock::acquire(old_dir)? else {
+        return Err(busy(old_dir));
+    };
+    if new_dir.exists() {
+        let Some(new_lock) = lock::acquire(new_dir)? else {
+            return Err(busy(new_dir));
+        };
+        drop(new_lock);
+        std::fs::remove_dir_all(new_dir)
+            .map_err(io_error(format!("removing {}", new_dir.display())))?;
+    }
+    drop(old_lock);
+    std::fs::rename(old_dir, new_dir).map_err(io_error(format!(
+        "moving {} to {}",
+        old_dir.display(),
+        new_dir.display()
+    )))?;
+    record_root(new_dir, new_root)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn orphans_are_found_removed_and_relinked() {
+        let base = TempDir::new().unwrap();
+        let roots = TempDir::new().unwrap();
+        let live = roots.path().join("live");
+        std::fs::create_dir(&live).unwrap();
+        let gone = roots.path().join("project");
+
+        for (name, root) in [
+            ("live-1", Some(&live)),
+            ("project-2", Some(&gone)),
+            ("old-3", None),
+        ] {
+            let dir = base.path().join(name);
+            std::fs::create_dir(&dir).unwrap();
+            std::fs::write(dir.join("index.usearch"), "12345").unwrap();
+            if let Some(root) = root {
+                record_root(&dir, root).unwrap();
+            }
+        }
+
+        let dirs = list(base.path()).unwrap();
+        let orphaned: Vec<&Path> = dirs
+            .iter()
+            .filter(|d| d.is_orphaned())
+            .map(|d| d.path.as_path())
+            .collect();
+        assert_eq!(orphaned, [base.path().join("project-2")]);
+        assert!(dirs.iter().all(|d| d.bytes >= 5));
+        let now = SystemTime::now();
+        assert!(
+            dirs.iter()
+                .all(|d| d.unused_for(now) < Some(Duration::from_secs(60)))
+        );
+        let later = now + Duration::from_secs(30 * 86_400);
+        assert!(
+            dirs.iter()
+                .all(|d| d.unused_for(later) > Some(Duration::from_secs(29 * 86_400)))
+        );
+
+        // The root moved to a directory of the same name
+        let moved = roots.path().join("elsewhere/project");
+        let stranded_dirs = stranded(base.path(), &moved).unwrap();
+        assert_eq!(stranded_dirs.len(), 1);
+        assert!(stranded(base.path(), &live).unwrap().is_empty());
+
+        let new_dir = base.path().join("project-4");
+        std::fs::create_dir(&new_dir).unwrap();
+        relink(&stranded_dirs[0].path, &new_dir, &moved).unwrap();
+        assert!(!stranded_dirs[0].path.exists());
+        assert_eq!(recorded_root(&new_dir), Some(moved));
+        assert!(new_dir.join("index.usearch").exists());
+
+        // A directory without a recorded root is never counted as orphaned
+        let unknown = list(base.path())
+            .unwrap()
+            .into_iter()
+            .find(|d| d.root.is_none())
+            .unwrap();
+        assert!(!unknown.is_orphaned());
+        assert!(remove(&unknown).unwrap());
+        assert!(!base.path().join("old-3").exists());
+    }
+
+    #[test]
+    fn missing_base_has_no_data_dirs() {
+        let base = TempDir::new().unwrap();
+        assert!(list(&base.path().join("nothing")).unwrap().is_empty());
+    }
+}
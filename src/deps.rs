@@ -0,0 +1,388 @@
+//! Finding the sources of a project's dependencies to index alongside it.
+//!
+//! "How does library X implement Y" is usually answered by reading X, and
+//! the exact version the project builds against is already on disk: Go
+//! keeps every required module in its module cache and cargo unpacks every
+//! crate into its registry. With `[dependencies] index = true`, the direct
+//! dependencies named in `go.mod` and `Cargo.toml` are found there and
+//! indexed under their own path prefix, searched only when asked for.
+
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+
+use crate::config::DependenciesConfig;
+use crate::store::DEPS_PATH;
+
+/// The unpacked source of one dependency version.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Dependency {
+    /// Module path or crate name.
+    pub name: String,
+    pub version: String,
+    /// Directory holding the unpacked source.
+    pub dir: PathBuf,
+}
+
+impl Dependency {
+    /// `name@version`, naming the dependency in the index.
+    pub fn id(&self) -> String {
+        format!("{}@{}", self.name, self.version)
+    }
+
+    /// Prefix of the index paths of this dependency's files.
+    pub fn path_prefix(&self) -> String {
+        format!("{DEPS_PATH}/{}/", self.id())
+    }
+}
+
+/// Where package managers keep unpacked sources.
+#[derive(Debug, Default)]
+pub struct Caches {
+    /// Go's module cache, `GOMODCACHE`.
+    pub go_mod_cache: Option<PathBuf>,
+    /// Cargo's unpacked registry sources, `$CARGO_HOME/registry/src`.
+    pub cargo_registry_src: Option<PathBuf>,
+}
+
+impl Caches {
+    /// The caches the `go` and `cargo` tools would use, from their
+    /// environment variables or their default locations under the home
+    /// directory.
+    pub fn from_env() -> Self {
+        let home = directories::BaseDirs::new().map(|dirs| dirs.home_dir().to_path_buf());
+        let var = |name| std::env::var_os(name).map(PathBuf::from);
+        let go_mod_cache = var("GOMODCACHE")
+            .or_else(|| {
+                // Only the first GOPATH entry holds the module cache
+                let gopath = var("GOPATH")?;
+                std::env::split_paths(&gopath)
+                    .next()
+                    .map(|p| p.join("pkg").join("mod"))
+            })
+            .or_else(|| home.as_ref().map(|h| h.join("go").join("pkg").join("mod")));
+        let cargo_registry_src = var("CARGO_HOME")
+            .or_else(|| home.as_ref().map(|h| h.join(".cargo")))
+            .map(|cargo| cargo.join("registry").join("src"));
+        Self {
+            go_mod_cache,
+            cargo_registry_src,
+        }
+    }
+}
+
+/// The direct dependencies of the project at `root` that `config` allows
+/// and whose sources are unpacked in `caches`. Dependencies that were never
+/// downloaded are logged and left out.
+pub fn discover(root: &Path, config: &DependenciesConfig, caches: &Caches) -> Vec<Dependency> {
+    let mut wanted = Vec::new();
+    if let Ok(go_mod) = std::fs::read_to_string(root.join("go.mod")) {
+        wanted.extend(go_requires(&go_mod).into_iter().map(|(name, version)| {
+            let dir = caches
+                .go_mod_cache
+                .as_ref()
+                .map(|cache| cache.join(format!("{}@{version}", escape_module_path(&name))));
+            (name, version, dir)
+        }));
+    }
+    if let (Ok(manifest), Ok(lock)) = (
+        std::fs::read_to_string(root.join("Cargo.toml")),
+        std::fs::read_to_string(root.join("Cargo.lock")),
+    ) {
+        let direct = cargo_direct(&manifest);
+        wanted.extend(
+            cargo_locked(&lock)
+                .into_iter()
+                .filter(|(name, _)| direct.contains(name))
+                .map(|(name, version)| {
+                    let dir = caches
+                        .cargo_registry_src
+                        .as_deref()
+                        .and_then(|src| find_crate(src, &name, &version));
+                    (name, version, dir)
+                }),
+        );
+    }
+
+    let mut found = Vec::new();
+    let mut missing = 0;
+    for (name, version, dir) in wanted {
+        if !config.allows(&name) {
+            continue;
+        }
+        match dir.filter(|d| d.is_dir()) {
+            Some(dir) => found.push(Dependency { name, version, dir }),
+            None => {
+                tracing::debug!("no source on disk for dependency {name}@{version}");
+                missing += 1;
+            }
+        }
+    }
+    if missing > 0 {
+        tracing::info!(
+            "{missing} dependencies have no source on disk -- run `go mod download` or \
+             `cargo fetch` to index them"
+        );
+    }
+    found
+}
+
+/// Module paths and versions of the direct requirements in a `go.mod`;
+/// ones marked `// indirect` are left out.
+fn go_requires(go_mod: &str) -> Vec<(String, String)> {
+    let mut requires = Vec::new();
+    let mut in_block = false;
+    for line in go_mod.lines() {
+        let line = line.trim();
+        let spec = if in_block {
+            if line.starts_with(')') {
+                in_block = false;
+                continue;
+            }
+            line
+        } else if let Some(rest) = line.strip_prefix("require") {
+            let rest = rest.trim_start();
+            if rest.starts_with('(') {
+                in_block = true;
+                continue;
+            }
+            rest
+        } else {
+            continue;
+        };
+        let (spec, comment) = spec.split_once("//").unwrap_or((spec, ""));
+        if comment.trim() == "indirect" {
+            continue;
+        }
+        let mut fields = spec.split_whitespace();
+        if let (Some(path), Some(version)) = (fields.next(), fields.next()) {
+            requires.push((path.to_string(), version.to_string()));
+        }
+    }
+    requires
+}
+
+/// A module path as the module cache spells it on disk: each capital
+/// letter becomes `!` and its lowercase, for case-insensitive file systems.
+fn escape_module_path(path: &str) -> String {
+    let mut escaped = String::with_capacity(path.len());
+    for c in path.chars() {
+        if c.is_ascii_uppercase() {
+            escaped.push('!');
+            escaped.push(c.to_ascii_lowercase());
+        } else {
+            escaped.push(c);
+        }
+    }
+    escaped
+}
+
+/// Names of the crates a `Cargo.toml` depends on directly, in any
+/// dependency table, under their package names when renamed.
+fn cargo_direct(manifest: &str) -> BTreeSet<String> {
+    let Ok(manifest) = toml::from_str::<toml::Table>(manifest) else {
+        return BTreeSet::new();
+    };
+    let mut parents = vec![&manifest];
+    parents.extend(manifest.get("workspace").and_then(|w| w.as_table()));
+    if let Some(targets) = manifest.get("target").and_then(|t| t.as_table()) {
+        parents.extend(targets.values().filter_map(|t| t.as_table()));
+    }
+
+    parents
+        .into_iter()
+        .flat_map(|parent| {
+            ["dependencies", "dev-dependencies", "build-dependencies"]
+                .into_iter()
+                .filter_map(|key| parent.get(key).and_then(|t| t.as_table()))
+        })
+        .flat_map(|table| table.iter())
+        .map(|(name, spec)| {
+            spec.get("package")
+                .and_then(|p| p.as_str())
+                .unwrap_or(name)
+                .to_string()
+        })
+        .collect()
+}
+
+/// Names and versions of the registry packages in a `Cargo.lock`; path and
+/// git dependencies have no registry source and are left out.
+fn cargo_locked(lock: &str) -> Vec<(String, String)> {
+    let Ok(lock) = toml::from_str::<toml::Table>(lock) else {
+        return Vec::new();
+    };
+    let Some(packages) = lock.get("package").and_then(|p| p.as_array()) else {
+        return Vec::new();
+    };
+    packages
+        .iter()
+        .filter(|p| {
+            p.get("source")
+                .and_then(|s| s.as_str())
+                .is_some_and(|s| s.starts_with("registry+") || s.starts_with("sparse+"))
+        })
+        .filter_map(|p| {
+            let name = p.get("name")?.as_str()?;
+            let version = p.get("version")?.as_str()?;
+            Some((name.to_string(), version.to_string()))
+        })
+        .collect()
+}
+
+/// The unpacked `name-version` directory under any registry in `src`.
+fn find_crate(src: &Path, name: &str, version: &str) -> Option<PathBuf> {
+    std::fs::read_dir(src)
+        .ok()?
+        .filter_map(|entry| entry.ok())
+        .map(|registry| registry.path().join(format!("{name}-{version}")))
+        .find(|dir| dir.is_dir())
+}
+
+/// The dependency among `deps` an index path like
+/// `<deps>/serde@1.0.200/src/de.rs` belongs to, and the path within it.
+pub fn resolve<'a>(deps: &'a [Dependency], path: &'a str) -> Option<(&'a Dependency, &'a str)> {
+    deps.iter().find_map(|dep| {
+        path.strip_prefix(&dep.path_prefix())
+            .map(|rel_path| (dep, rel_path))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn go_requires_skip_indirect() {
+        let go_mod = "module example.com/app\n\
+                      \n\
+                      go 1.22\n\
+                      \n\
+                      require github.com/BurntSushi/toml v1.3.2\n\
+                      \n\
+                      require (\n\
+                      \tgolang.org/x/sync v0.7.0\n\
+                      \tgithub.com/pkg/errors v0.9.1 // indirect\n\
+                      )\n\
+                      \n\
+                      replace golang.org/x/sync => ../sync\n";
+        assert_eq!(
+            go_requires(go_mod),
+            [
+                (
+                    "github.com/BurntSushi/toml".to_string(),
+                    "v1.3.2".to_string()
+                ),
+                ("golang.org/x/sync".to_string(), "v0.7.0".to_string()),
+            ]
+        );
+        assert_eq!(
+            escape_module_path("github.com/BurntSushi/toml"),
+            "github.com/!burnt!sushi/toml"
+        );
+    }
+
+    #[test]
+    fn cargo_direct_dependencies_only() {
+        let manifest = r#"
+[package]
+name = "app"
+
+[dependencies]
+serde = "1"
+json = { package = "serde_json", version = "1" }
+
+[dev-dependencies]
+tempfile = "3"
+
+[target.'cfg(unix)'.dependencies]
+libc = "0.2"
+"#;
+        let direct = cargo_direct(manifest);
+        assert_eq!(
+            direct.into_iter().collect::<Vec<_>>(),
+            ["libc", "serde", "serde_json", "tempfile"]
+        );
+
+        let lock = r#"
+version = 3
+
+[[package]]
+name = "app"
+version = "0.1.0"
+
+[[package]]
+name = "serde"
+version = "1.0.200"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+
+[[package]]
+name = "itoa"
+version = "1.0.11"
+source = "sparse+https://index.crates.io/"
+
+[[package]]
+name = "fork"
+version = "0.1.0"
+source = "git+https://example.com/fork#abc"
+"#;
+        assert_eq!(
+            cargo_locked(lock),
+            [
+                ("serde".to_string(), "1.0.200".to_string()),
+                ("itoa".to_string(), "1.0.11".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn discovers_downloaded_sources() {
+        let root = TempDir::new().unwrap();
+        let cache = TempDir::new().unwrap();
+        std::fs::write(
+            root.path().join("go.mod"),
+            "module app\n\nrequire (\n\tgithub.com/Foo/bar v1.0.0\n\texample.com/gone v0.1.0\n)\n",
+        )
+        .unwrap();
+        std::fs::write(
+            root.path().join("Cargo.toml"),
+            "[dependencies]\nserde = \"1\"\n",
+        )
+        .unwrap();
+        std::fs::write(
+            root.path().join("Cargo.lock"),
+            "[[package]]\nname = \"serde\"\nversion = \"1.0.200\"\n\
+             source = \"registry+https://github.com/rust-lang/crates.io-index\"\n",
+        )
+        .unwrap();
+        let go_dir = cache.path().join("mod/github.com/!foo/bar@v1.0.0");
+        let crate_dir = cache
+            .path()
+            .join("src/index.crates.io-1949cf8c/serde-1.0.200");
+        std::fs::create_dir_all(&go_dir).unwrap();
+        std::fs::create_dir_all(&crate_dir).unwrap();
+        let caches = Caches {
+            go_mod_cache: Some(cache.path().join("mod")),
+            cargo_registry_src: Some(cache.path().join("src")),
+        };
+
+        let deps = discover(root.path(), &DependenciesConfig::default(), &caches);
+        assert_eq!(
+            deps.iter().map(Dependency::id).collect::<Vec<_>>(),
+            ["github.com/Foo/bar@v1.0.0", "serde@1.0.200"]
+        );
+        assert_eq!(deps[0].dir, go_dir);
+        assert_eq!(deps[1].dir, crate_dir);
+        assert_eq!(
+            resolve(&deps, "<deps>/serde@1.0.200/src/de.rs").map(|(d, p)| (d.id(), p)),
+            Some(("serde@1.0.200".to_string(), "src/de.rs"))
+        );
+        assert!(resolve(&deps, "src/main.rs").is_none());
+
+        let config = DependenciesConfig {
+            exclude: vec!["serde".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(discover(root.path(), &config, &caches).len(), 1);
+    }
+}
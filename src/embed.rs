@@ -1,18 +1,110 @@
-use std::sync::Arc;
+//! Turning text into vectors with a sentence-embedding model run locally
+//! through candle.
+
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
 
 use candle_core::{Device, Tensor};
 use candle_nn::VarBuilder;
 use candle_transformers::models::bert::{BertModel, Config, DTYPE};
-use hf_hub::api::sync::Api;
-use tokenizers::{PaddingParams, PaddingStrategy, Tokenizer, TruncationParams};
+use hf_hub::Cache;
+use hf_hub::api::Progress;
+use hf_hub::api::sync::{ApiBuilder, ApiError, ApiRepo};
+use tokenizers::{
+    NormalizedString, Normalizer, NormalizerWrapper, PaddingParams, PaddingStrategy, Tokenizer,
+    TruncationParams,
+};
+use tokio::sync::{Notify, OwnedSemaphorePermit, Semaphore};
 
+use crate::config::EmbeddingConfig;
 use crate::error::{Error, Result};
+use crate::metrics::metrics;
+
+mod quantized;
+
+use quantized::QuantizedBert;
 
 /// Embedding dimension for all-MiniLM-L6-v2.
 pub const EMBEDDING_DIM: usize = 384;
 
+/// Maximum tokens per input; anything beyond this is truncated before
+/// embedding.
+pub const MAX_SEQ_LEN: usize = 512;
+
 const MODEL_ID: &str = "sentence-transformers/all-MiniLM-L6-v2";
-const MAX_SEQ_LEN: usize = 512;
+
+/// How long after a search batches keep being embedded in slices of
+/// [`INTERACTIVE_SLICE`], expecting more searches to follow.
+const INTERACTIVE_WINDOW: Duration = Duration::from_secs(30);
+
+/// Most texts a batch embeds per forward pass while searches are running,
+/// so one waits for at most a short pass to finish.
+const INTERACTIVE_SLICE: usize = 8;
+
+/// Files the model is loaded from: config, tokenizer, and weights.
+const MODEL_FILES: [&str; 3] = ["config.json", "tokenizer.json", "model.safetensors"];
+
+/// Where downloads come from unless `[embedding] endpoint` or
+/// `HF_ENDPOINT` say otherwise.
+const DEFAULT_ENDPOINT: &str = "https://huggingface.co";
+
+/// How many times a cached model file that fails to load is deleted and
+/// downloaded again before giving up.
+const MAX_REDOWNLOADS: usize = 2;
+
+/// The model, with full-precision weights or quantized ones from a GGUF
+/// file under `[embedding] gguf`.
+enum Model {
+    Full(BertModel),
+    Quantized(QuantizedBert),
+}
+
+impl Model {
+    fn forward(
+        &self,
+        token_ids: &Tensor,
+        token_type_ids: &Tensor,
+        attention_mask: &Tensor,
+    ) -> candle_core::Result<Tensor> {
+        match self {
+            Model::Full(model) => model.forward(token_ids, token_type_ids, Some(attention_mask)),
+            Model::Quantized(model) => model.forward(token_ids, token_type_ids, attention_mask),
+        }
+    }
+}
+
+/// A [`MODEL_FILES`] entry that failed to load, by index, and why.
+struct Corrupt {
+    file: usize,
+    source: Box<dyn std::error::Error + Send + Sync>,
+}
+
+/// Why loading the model failed: a file that didn't parse, which may be
+/// a download cut short, or anything else.
+enum LoadError {
+    Corrupt(Corrupt),
+    Other(Error),
+}
+
+impl From<Error> for LoadError {
+    fn from(e: Error) -> Self {
+        Self::Other(e)
+    }
+}
+
+impl From<std::io::Error> for LoadError {
+    fn from(e: std::io::Error) -> Self {
+        Self::Other(e.into())
+    }
+}
+
+/// A failure parsing [`MODEL_FILES`]`[file]`.
+fn corrupt(file: usize) -> impl FnOnce(Box<dyn std::error::Error + Send + Sync>) -> LoadError {
+    move |source| LoadError::Corrupt(Corrupt { file, source })
+}
 
 /// Pure-Rust sentence embedder using candle.
 ///
@@ -20,47 +112,117 @@ const MAX_SEQ_LEN: usize = 512;
 /// BERT inference entirely in Rust — no C++ or ONNX Runtime dependency.
 #[derive(Clone)]
 pub struct Embedder {
-    model: Arc<BertModel>,
+    replicas: Arc<Replicas>,
     tokenizer: Arc<Tokenizer>,
+    /// Same vocabulary without padding or truncation, for measuring inputs.
+    counter: Arc<Tokenizer>,
     device: Device,
+    query_prefix: Arc<str>,
+    passage_prefix: Arc<str>,
+    /// Fingerprint of the model and passage prefix; see [`Embedder::version`].
+    version: Arc<str>,
 }
 
 impl Embedder {
-    /// Initialize the embedding model.
+    /// Initialize the embedding model, with the query and passage prefixes
+    /// from `config` used by [`embed_query`](Self::embed_query) and
+    /// [`embed_passages`](Self::embed_passages).
     ///
     /// Downloads model weights from Hugging Face Hub on first use (~80 MB,
-    /// cached in `~/.cache/huggingface/hub`).
-    pub fn new() -> Result<Self> {
-        let device = Device::Cpu;
-
-        // Download model files from Hugging Face Hub
-        let api = Api::new().map_err(|e| Error::ModelDownload(e.into()))?;
-        let repo = api.model(MODEL_ID.to_string());
+    /// cached in `~/.cache/huggingface/hub`), unless `config` points at a
+    /// local copy or asks for offline mode. A cached file that doesn't
+    /// parse, such as one a crash cut short, is deleted and downloaded
+    /// again, up to [`MAX_REDOWNLOADS`] times.
+    pub fn new(config: &EmbeddingConfig) -> Result<Self> {
+        let mut redownloads = 0;
+        loop {
+            let paths = model_files(config)?;
+            match Self::load(&paths, config) {
+                Ok(embedder) => return Ok(embedder),
+                Err(LoadError::Other(e)) => return Err(e),
+                Err(LoadError::Corrupt(Corrupt { file, source }))
+                    if redownloads < MAX_REDOWNLOADS
+                        && downloads(config)
+                        && !(file == 2 && config.gguf.is_some()) =>
+                {
+                    redownloads += 1;
+                    tracing::warn!(
+                        "cached {} of {MODEL_ID} is corrupt ({source}); deleting it and \
+                         downloading it again (attempt {redownloads} of {MAX_REDOWNLOADS})",
+                        MODEL_FILES[file]
+                    );
+                    discard_cached(&paths[file])?;
+                    Hub::new(config)?.redownload(MODEL_FILES[file])?;
+                }
+                Err(LoadError::Corrupt(Corrupt { file, source })) => {
+                    return Err(Error::ModelCorrupt {
+                        path: paths[file].clone(),
+                        source,
+                    });
+                }
+            }
+        }
+    }
 
-        let config_path = repo
-            .get("config.json")
-            .map_err(|e| Error::ModelDownload(e.into()))?;
-        let tokenizer_path = repo
-            .get("tokenizer.json")
-            .map_err(|e| Error::ModelDownload(e.into()))?;
-        let weights_path = repo
-            .get("model.safetensors")
-            .map_err(|e| Error::ModelDownload(e.into()))?;
+    /// Load the model from `paths`, the [`MODEL_FILES`] in order, with the
+    /// GGUF file in place of the weights under `[embedding] gguf`.
+    fn load(
+        paths: &[PathBuf; 3],
+        config: &EmbeddingConfig,
+    ) -> std::result::Result<Self, LoadError> {
+        let device = Device::Cpu;
+        let [config_path, tokenizer_path, weights_path] = paths;
 
         // Load config
-        let config: Config = serde_json::from_str(&std::fs::read_to_string(&config_path)?)
-            .map_err(|e| Error::ModelLoad(e.into()))?;
-
-        // Load model weights (memory-mapped for efficiency)
-        // SAFETY: The model file is read-only and will not be modified while mapped.
-        let vb = unsafe {
-            VarBuilder::from_mmaped_safetensors(&[weights_path], DTYPE, &device)
-                .map_err(|e| Error::ModelLoad(e.into()))?
+        let config_json = std::fs::read_to_string(config_path).map_err(|e| {
+            if e.kind() == std::io::ErrorKind::InvalidData {
+                corrupt(0)(e.into())
+            } else {
+                e.into()
+            }
+        })?;
+        let bert_config: Config =
+            serde_json::from_str(&config_json).map_err(|e| corrupt(0)(e.into()))?;
+        let version = fingerprint(
+            &config_json,
+            &[tokenizer_path, weights_path],
+            &config.passage_prefix,
+        )?;
+
+        let replicas = config.replicas.max(1);
+        let models = if config.gguf.is_some() {
+            // Replicas share the quantized weights, which are read into memory
+            let vb = candle_transformers::quantized_var_builder::VarBuilder::from_gguf(
+                weights_path,
+                &device,
+            )
+            .map_err(|e| corrupt(2)(e.into()))?;
+            let model = QuantizedBert::load(vb, &bert_config).map_err(|e| corrupt(2)(e.into()))?;
+            (0..replicas)
+                .map(|_| Model::Quantized(model.clone()))
+                .collect()
+        } else {
+            // Load model weights (memory-mapped for efficiency)
+            // SAFETY: The model file is read-only and will not be modified while mapped.
+            let vb = unsafe {
+                VarBuilder::from_mmaped_safetensors(
+                    std::slice::from_ref(weights_path),
+                    DTYPE,
+                    &device,
+                )
+                .map_err(|e| corrupt(2)(e.into()))?
+            };
+            (0..replicas)
+                .map(|_| BertModel::load(vb.clone(), &bert_config).map(Model::Full))
+                .collect::<candle_core::Result<Vec<_>>>()
+                .map_err(|e| corrupt(2)(e.into()))?
         };
-        let model = BertModel::load(vb, &config).map_err(|e| Error::ModelLoad(e.into()))?;
 
         // Load and configure tokenizer with padding + truncation
-        let mut tokenizer = Tokenizer::from_file(&tokenizer_path).map_err(Error::ModelLoad)?;
+        let mut tokenizer = Tokenizer::from_file(tokenizer_path).map_err(corrupt(1))?;
+        let mut counter = tokenizer.clone();
+        counter.with_padding(None);
+        counter.with_truncation(None).map_err(Error::ModelLoad)?;
 
         tokenizer.with_padding(Some(PaddingParams {
             strategy: PaddingStrategy::BatchLongest,
@@ -74,40 +236,132 @@ impl Embedder {
             .map_err(Error::ModelLoad)?;
 
         Ok(Self {
-            model: Arc::new(model),
+            replicas: Arc::new(Replicas::new(models)),
             tokenizer: Arc::new(tokenizer),
+            counter: Arc::new(counter),
             device,
+            query_prefix: Arc::from(config.query_prefix.as_str()),
+            passage_prefix: Arc::from(config.passage_prefix.as_str()),
+            version: Arc::from(version),
         })
     }
 
+    /// Whether the tokenizer lowercases its input, so texts differing only
+    /// in case embed alike.
+    pub fn lowercases(&self) -> bool {
+        lowercases(self.tokenizer.get_normalizer())
+    }
+
+    /// Identifies the vector space passages are embedded into: changes
+    /// with the model's config, tokenizer, or weights, or the passage
+    /// prefix, and is the same for copies of the same model.
+    pub fn version(&self) -> &str {
+        &self.version
+    }
+
+    /// Embed a search query, with the query prefix.
+    pub async fn embed_query(&self, text: &str) -> Result<Vec<f32>> {
+        self.embed_one(&prefixed(&self.query_prefix, text)).await
+    }
+
+    /// Embed a text to be searched, with the passage prefix.
+    pub async fn embed_passage(&self, text: &str) -> Result<Vec<f32>> {
+        self.embed_one(&prefixed(&self.passage_prefix, text)).await
+    }
+
+    /// Embed texts to be searched, with the passage prefix.
+    pub async fn embed_passages(&self, texts: Vec<String>) -> Result<Vec<Vec<f32>>> {
+        if self.passage_prefix.is_empty() {
+            return self.embed_batch(texts).await;
+        }
+        let texts = texts
+            .iter()
+            .map(|text| prefixed(&self.passage_prefix, text))
+            .collect();
+        self.embed_batch(texts).await
+    }
+
     /// Embed a batch of texts, returning one 384-dim vector per input.
+    /// With several replicas, the batch is split between them and the
+    /// parts embedded at once, each as soon as a replica is free.
+    ///
+    /// Batches give way to [`embed_one`](Self::embed_one): no part starts
+    /// while one is waiting, and for a while after one ran, parts are cut
+    /// into slices of [`INTERACTIVE_SLICE`] texts, so a search during
+    /// indexing waits for a short forward pass rather than a whole batch.
+    #[tracing::instrument(level = "debug", skip_all, fields(texts = texts.len()))]
     pub async fn embed_batch(&self, texts: Vec<String>) -> Result<Vec<Vec<f32>>> {
         if texts.is_empty() {
             return Ok(Vec::new());
         }
-        let embedder = self.clone();
-        tokio::task::spawn_blocking(move || embedder.embed_batch_sync(&texts)).await?
+        let started = Instant::now();
+        let mut runs = VecDeque::from(split(texts, self.replicas.models.len()));
+        let mut parts = Vec::new();
+        while let Some(mut part) = runs.pop_front() {
+            let replica = self.replicas.lease_background().await?;
+            if self.replicas.interactive.is_recent() && part.len() > INTERACTIVE_SLICE {
+                runs.push_front(part.split_off(INTERACTIVE_SLICE));
+            }
+            let embedder = self.clone();
+            parts.push(tokio::task::spawn_blocking(move || {
+                embedder.embed_batch_sync(replica.model(), &part)
+            }));
+        }
+        let mut vectors = Vec::new();
+        for part in parts {
+            vectors.extend(part.await??);
+        }
+        metrics().observe_embedding(started.elapsed());
+        Ok(vectors)
     }
 
-    /// Embed a single text.
+    /// Embed a single text, ahead of any batch waiting for a replica.
     pub async fn embed_one(&self, text: &str) -> Result<Vec<f32>> {
-        let text = text.to_string();
-        let mut results = self.embed_batch(vec![text]).await?;
+        let _turn = self.replicas.interactive.begin();
+        let started = Instant::now();
+        let replica = self.replicas.lease().await?;
+        let embedder = self.clone();
+        let texts = vec![text.to_string()];
+        let mut results =
+            tokio::task::spawn_blocking(move || embedder.embed_batch_sync(replica.model(), &texts))
+                .await??;
+        metrics().observe_embedding(started.elapsed());
         results.pop().ok_or(Error::EmptyEmbedding)
     }
 
-    fn embed_batch_sync(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+    /// Count tokens per text (including special tokens) before truncation,
+    /// off the async runtime: a batch of chunks takes a while to tokenize.
+    pub async fn count_tokens(&self, texts: Vec<String>) -> Result<Vec<usize>> {
+        let embedder = self.clone();
+        tokio::task::spawn_blocking(move || embedder.count_tokens_sync(&texts)).await?
+    }
+
+    /// Count tokens per text (including special tokens) before truncation.
+    pub fn count_tokens_sync(&self, texts: &[String]) -> Result<Vec<usize>> {
+        let refs: Vec<&str> = texts.iter().map(|s| s.as_str()).collect();
+        let encodings = self
+            .counter
+            .encode_batch(refs, true)
+            .map_err(Error::Tokenize)?;
+        Ok(encodings.iter().map(|e| e.len()).collect())
+    }
+
+    fn embed_batch_sync(&self, model: &Model, texts: &[String]) -> Result<Vec<Vec<f32>>> {
         let refs: Vec<&str> = texts.iter().map(|s| s.as_str()).collect();
         let encodings = self
             .tokenizer
             .encode_batch(refs, true)
             .map_err(Error::Tokenize)?;
 
-        self.forward(&encodings).map_err(Error::Inference)
+        self.forward(model, &encodings).map_err(Error::Inference)
     }
 
     /// Run BERT forward pass, then mean-pool and L2-normalize.
-    fn forward(&self, encodings: &[tokenizers::Encoding]) -> candle_core::Result<Vec<Vec<f32>>> {
+    fn forward(
+        &self,
+        model: &Model,
+        encodings: &[tokenizers::Encoding],
+    ) -> candle_core::Result<Vec<Vec<f32>>> {
         let token_ids: Vec<Tensor> = encodings
             .iter()
             .map(|e| Tensor::new(e.get_ids(), &self.device))
@@ -123,9 +377,7 @@ impl Embedder {
         let token_type_ids = token_ids.zeros_like()?;
 
         // BERT forward pass -> [batch, seq_len, hidden_size]
-        let embeddings = self
-            .model
-            .forward(&token_ids, &token_type_ids, Some(&attention_mask))?;
+        let embeddings = model.forward(&token_ids, &token_type_ids, &attention_mask)?;
 
         // Mean pooling with attention mask
         let mask = attention_mask.unsqueeze(2)?.to_dtype(DTYPE)?;
@@ -141,10 +393,525 @@ impl Embedder {
     }
 }
 
+/// Copies of the model, each running one forward pass at a time.
+struct Replicas {
+    models: Vec<Model>,
+    /// Indexes into `models` of the replicas not running.
+    idle: Mutex<Vec<usize>>,
+    /// One permit per idle replica.
+    permits: Arc<Semaphore>,
+    interactive: Interactive,
+}
+
+impl Replicas {
+    fn new(models: Vec<Model>) -> Self {
+        Self {
+            idle: Mutex::new((0..models.len()).collect()),
+            permits: Arc::new(Semaphore::new(models.len())),
+            interactive: Interactive::default(),
+            models,
+        }
+    }
+
+    /// An idle replica once no interactive request is waiting for one.
+    async fn lease_background(self: &Arc<Self>) -> Result<Lease> {
+        loop {
+            self.interactive.quiet().await;
+            let lease = self.lease().await?;
+            // One may have queued up while this waited for a replica; hand
+            // it over
+            if !self.interactive.is_pending() {
+                return Ok(lease);
+            }
+            tracing::debug!("embedding batch paused for a search");
+        }
+    }
+
+    /// An idle replica, waiting for one to finish if none is.
+    async fn lease(self: &Arc<Self>) -> Result<Lease> {
+        let permit = Arc::clone(&self.permits)
+            .acquire_owned()
+            .await
+            .map_err(|_| Error::ModelMissing("the model replicas were shut down".to_string()))?;
+        let index = self
+            .idle
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .pop()
+            .ok_or_else(|| Error::ModelMissing("no idle model replica".to_string()))?;
+        Ok(Lease {
+            replicas: Arc::clone(self),
+            index,
+            _permit: permit,
+        })
+    }
+}
+
+/// A replica taken from [`Replicas`], put back when dropped.
+struct Lease {
+    replicas: Arc<Replicas>,
+    index: usize,
+    _permit: OwnedSemaphorePermit,
+}
+
+impl Lease {
+    fn model(&self) -> &Model {
+        &self.replicas.models[self.index]
+    }
+}
+
+impl Drop for Lease {
+    fn drop(&mut self) {
+        // Put back before the permit is released, so a permit always finds
+        // an idle replica
+        self.replicas
+            .idle
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(self.index);
+    }
+}
+
+/// Interactive requests, such as a search's query, which batch embedding
+/// gives way to.
+#[derive(Default)]
+struct Interactive {
+    pending: AtomicUsize,
+    /// Woken when the last pending request finishes.
+    done: Notify,
+    /// When the most recent request started.
+    last: Mutex<Option<Instant>>,
+}
+
+impl Interactive {
+    /// Count a request as pending until the returned guard is dropped.
+    fn begin(&self) -> Turn<'_> {
+        self.pending.fetch_add(1, Ordering::SeqCst);
+        *self.last.lock().unwrap_or_else(PoisonError::into_inner) = Some(Instant::now());
+        Turn(self)
+    }
+
+    fn is_pending(&self) -> bool {
+        self.pending.load(Ordering::SeqCst) > 0
+    }
+
+    /// Whether a request is pending or started in the last
+    /// [`INTERACTIVE_WINDOW`].
+    fn is_recent(&self) -> bool {
+        self.is_pending()
+            || self
+                .last
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .is_some_and(|last| last.elapsed() < INTERACTIVE_WINDOW)
+    }
+
+    /// Wait until no request is pending.
+    async fn quiet(&self) {
+        loop {
+            // Registered before checking, so a request finishing in
+            // between still wakes it
+            let done = self.done.notified();
+            if !self.is_pending() {
+                return;
+            }
+            done.await;
+        }
+    }
+}
+
+/// A pending interactive request; see [`Interactive::begin`].
+struct Turn<'a>(&'a Interactive);
+
+impl Drop for Turn<'_> {
+    fn drop(&mut self) {
+        if self.0.pending.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.0.done.notify_waiters();
+        }
+    }
+}
+
+/// `texts` in at most `parts` runs of near-equal length, in order.
+fn split(mut texts: Vec<String>, parts: usize) -> Vec<Vec<String>> {
+    let size = texts.len().div_ceil(parts.max(1)).max(1);
+    let mut runs = Vec::new();
+    while texts.len() > size {
+        let rest = texts.split_off(size);
+        runs.push(std::mem::replace(&mut texts, rest));
+    }
+    runs.push(texts);
+    runs
+}
+
+/// `text` with `prefix` in front, unless it already starts with it.
+fn prefixed(prefix: &str, text: &str) -> String {
+    if text.starts_with(prefix) {
+        text.to_string()
+    } else {
+        format!("{prefix}{text}")
+    }
+}
+
+/// Mix two normalized embeddings, giving `b` a `weight` share, and
+/// re-normalize the result.
+pub fn blend(a: &[f32], b: &[f32], weight: f32) -> Vec<f32> {
+    normalize(
+        a.iter()
+            .zip(b)
+            .map(|(x, y)| x * (1.0 - weight) + y * weight)
+            .collect(),
+    )
+}
+
+/// The normalized average of `vectors`, all of one length: a direction
+/// close to all of them. Empty input gives an empty vector.
+pub fn mean(vectors: &[Vec<f32>]) -> Vec<f32> {
+    let Some(first) = vectors.first() else {
+        return Vec::new();
+    };
+    let mut sum = vec![0.0; first.len()];
+    for v in vectors {
+        for (s, x) in sum.iter_mut().zip(v) {
+            *s += x;
+        }
+    }
+    normalize(sum)
+}
+
+/// Keep the first `dims` components of an embedding and re-normalize, so
+/// it can be compared with other vectors truncated the same way.
+pub fn truncate(v: &[f32], dims: usize) -> Vec<f32> {
+    normalize(v.iter().take(dims).copied().collect())
+}
+
+/// Scale `v` to unit length; a zero vector is returned unchanged.
+fn normalize(v: Vec<f32>) -> Vec<f32> {
+    let norm = v.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm == 0.0 {
+        return v;
+    }
+    v.into_iter().map(|x| x / norm).collect()
+}
+
+/// BLAKE3 hash of the model's `config_json`, the contents of its other
+/// `files`, and the `passage_prefix`, as hex. Stored in the index, so it
+/// must not change between builds. Each part is length-prefixed; the
+/// weights are streamed through, which BLAKE3 gets done in a fraction of
+/// the time loading them takes.
+fn fingerprint(config_json: &str, files: &[&Path], passage_prefix: &str) -> Result<String> {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(&(config_json.len() as u64).to_le_bytes());
+    hasher.update(config_json.as_bytes());
+    for file in files {
+        let file = std::fs::File::open(file)?;
+        hasher.update(&file.metadata()?.len().to_le_bytes());
+        hasher.update_reader(file)?;
+    }
+    hasher.update(&(passage_prefix.len() as u64).to_le_bytes());
+    hasher.update(passage_prefix.as_bytes());
+    Ok(hasher.finalize().to_hex().to_string())
+}
+
+/// Whether `normalizer` lowercases text, found by running it on some.
+fn lowercases(normalizer: Option<&NormalizerWrapper>) -> bool {
+    normalizer.is_some_and(|normalizer| {
+        let mut probe = NormalizedString::from("QUERY");
+        normalizer.normalize(&mut probe).is_ok() && probe.get() == "query"
+    })
+}
+
+/// Paths of the [`MODEL_FILES`], with the `[embedding] gguf` file in place
+/// of the weights when it is set, so they aren't downloaded.
+fn model_files(config: &EmbeddingConfig) -> Result<[PathBuf; 3]> {
+    let Some(gguf) = &config.gguf else {
+        return fetch(config, MODEL_FILES);
+    };
+    if !gguf.is_file() {
+        return Err(Error::ModelMissing(format!(
+            "{} does not exist -- [embedding] gguf must name a GGUF file of {MODEL_ID}'s \
+             weights, such as one `tensor-tools quantize` made from its model.safetensors",
+            gguf.display()
+        )));
+    }
+    let [config_path, tokenizer_path] = fetch(config, [MODEL_FILES[0], MODEL_FILES[1]])?;
+    Ok([config_path, tokenizer_path, gguf.clone()])
+}
+
+/// Paths of `files`: from `[embedding] model_dir` if set, else from the
+/// Hugging Face cache, downloading what is missing unless offline.
+fn fetch<const N: usize>(config: &EmbeddingConfig, files: [&str; N]) -> Result<[PathBuf; N]> {
+    if let Some(dir) = &config.model_dir {
+        return local_files(dir, files);
+    }
+    if !downloads(config) {
+        return cached_files(&Cache::from_env(), files);
+    }
+
+    let hub = Hub::new(config)?;
+    let mut paths = files.map(PathBuf::from);
+    for (path, file) in paths.iter_mut().zip(files) {
+        *path = hub.get(file)?;
+    }
+    Ok(paths)
+}
+
+/// Whether missing model files are downloaded into the Hugging Face cache:
+/// unless `[embedding] model_dir` is set or offline mode is on.
+fn downloads(config: &EmbeddingConfig) -> bool {
+    config.model_dir.is_none()
+        && !config.offline
+        && !offline_from_env(std::env::var("HF_HUB_OFFLINE").ok().as_deref())
+}
+
+/// The model's repository on the Hugging Face hub.
+struct Hub {
+    repo: ApiRepo,
+    /// Where downloads come from, for error messages.
+    endpoint: String,
+}
+
+impl Hub {
+    /// The repository on the hub `config` names.
+    fn new(config: &EmbeddingConfig) -> Result<Self> {
+        let mut builder = ApiBuilder::from_env();
+        if let Some(endpoint) = &config.endpoint {
+            builder = builder.with_endpoint(endpoint.clone());
+        }
+        let endpoint = config
+            .endpoint
+            .clone()
+            .or_else(|| std::env::var("HF_ENDPOINT").ok())
+            .unwrap_or_else(|| DEFAULT_ENDPOINT.to_string());
+        let api = builder
+            .build()
+            .map_err(|e| download_error(MODEL_ID, &endpoint, e))?;
+        Ok(Self {
+            repo: api.model(MODEL_ID.to_string()),
+            endpoint,
+        })
+    }
+
+    /// `file` from the cache, downloaded first if it isn't there.
+    fn get(&self, file: &str) -> Result<PathBuf> {
+        self.repo
+            .get(file)
+            .map_err(|e| download_error(file, &self.endpoint, e))
+    }
+
+    /// Download `file` again, logging how far it has got.
+    fn redownload(&self, file: &str) -> Result<()> {
+        self.repo
+            .download_with_progress(file, LogProgress::default())
+            .map_err(|e| download_error(file, &self.endpoint, e))?;
+        tracing::info!("downloaded {file} of {MODEL_ID} again");
+        Ok(())
+    }
+}
+
+fn download_error(file: &str, endpoint: &str, e: ApiError) -> Error {
+    Error::ModelDownload {
+        file: file.to_string(),
+        endpoint: endpoint.to_string(),
+        source: e.into(),
+    }
+}
+
+/// Delete a cached model file: the snapshot entry and, when that is a
+/// link, the blob it points to, so the next download starts afresh.
+fn discard_cached(path: &Path) -> Result<()> {
+    let blob = std::fs::canonicalize(path).ok().filter(|blob| blob != path);
+    for file in blob.iter().map(PathBuf::as_path).chain([path]) {
+        match std::fs::remove_file(file) {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => {
+                return Err(Error::ModelMissing(format!(
+                    "can't delete the corrupt {} ({e}) -- delete it by hand so it is \
+                     downloaded again",
+                    file.display()
+                )));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Download progress logged every quarter, for servers whose stderr is a
+/// log rather than a terminal.
+#[derive(Default)]
+struct LogProgress {
+    file: String,
+    size: usize,
+    done: usize,
+    /// Quarters logged so far.
+    logged: usize,
+}
+
+impl Progress for LogProgress {
+    fn init(&mut self, size: usize, filename: &str) {
+        self.file = filename.to_string();
+        self.size = size;
+        tracing::info!("downloading {filename} ({} MB)", size / 1_000_000);
+    }
+
+    fn update(&mut self, size: usize) {
+        self.done += size;
+        let quarters = (self.done * 4).checked_div(self.size).unwrap_or(0).min(4);
+        if quarters > self.logged {
+            self.logged = quarters;
+            tracing::info!("downloading {}: {}%", self.file, quarters * 25);
+        }
+    }
+
+    fn finish(&mut self) {}
+}
+
+/// `files` in `dir`, all of which must exist.
+fn local_files<const N: usize>(dir: &Path, files: [&str; N]) -> Result<[PathBuf; N]> {
+    let paths = files.map(|file| dir.join(file));
+    if let Some(missing) = paths.iter().find(|path| !path.is_file()) {
+        return Err(Error::ModelMissing(format!(
+            "{} does not exist -- [embedding] model_dir must hold {}, e.g. copied from \
+             the {MODEL_ID} repository",
+            missing.display(),
+            files.join(", ")
+        )));
+    }
+    Ok(paths)
+}
+
+/// `files` from `cache`, without touching the network.
+fn cached_files<const N: usize>(cache: &Cache, files: [&str; N]) -> Result<[PathBuf; N]> {
+    let repo = cache.model(MODEL_ID.to_string());
+    let mut paths = files.map(PathBuf::from);
+    for (path, file) in paths.iter_mut().zip(files) {
+        *path = repo.get(file).ok_or_else(|| {
+            Error::ModelMissing(format!(
+                "{file} of {MODEL_ID} is not in the Hugging Face cache at {} and offline mode \
+                 is on -- run once with network access, copy the cache from a machine that \
+                 has it, or set [embedding] model_dir to a local copy",
+                cache.path().display()
+            ))
+        })?;
+    }
+    Ok(paths)
+}
+
+/// Whether an `HF_HUB_OFFLINE` value turns offline mode on, as it does for
+/// the Python `huggingface_hub`.
+fn offline_from_env(value: Option<&str>) -> bool {
+    value.is_some_and(|v| {
+        matches!(
+            v.trim().to_ascii_lowercase().as_str(),
+            "1" | "true" | "yes" | "on"
+        )
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn offline_mode_reads_only_local_files() {
+        assert!(offline_from_env(Some("1")));
+        assert!(offline_from_env(Some("True")));
+        assert!(!offline_from_env(Some("0")));
+        assert!(!offline_from_env(None));
+
+        let tmp = tempfile::TempDir::new().unwrap();
+        let err = cached_files(&Cache::new(tmp.path().to_path_buf()), MODEL_FILES)
+            .unwrap_err()
+            .to_string();
+        assert!(
+            err.contains("config.json") && err.contains("offline"),
+            "{err}"
+        );
+
+        std::fs::write(tmp.path().join("config.json"), "{}").unwrap();
+        std::fs::write(tmp.path().join("tokenizer.json"), "{}").unwrap();
+        let err = local_files(tmp.path(), MODEL_FILES)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("model.safetensors does not exist"), "{err}");
+        std::fs::write(tmp.path().join("model.safetensors"), "").unwrap();
+        assert_eq!(
+            local_files(tmp.path(), MODEL_FILES).unwrap()[2],
+            tmp.path().join("model.safetensors")
+        );
+
+        // A GGUF file stands in for the weights, which then needn't exist
+        std::fs::remove_file(tmp.path().join("model.safetensors")).unwrap();
+        let mut config = EmbeddingConfig {
+            model_dir: Some(tmp.path().to_path_buf()),
+            gguf: Some(tmp.path().join("model-q8_0.gguf")),
+            ..Default::default()
+        };
+        let err = model_files(&config).unwrap_err().to_string();
+        assert!(err.contains("[embedding] gguf"), "{err}");
+        std::fs::write(tmp.path().join("model-q8_0.gguf"), "").unwrap();
+        assert_eq!(
+            model_files(&config).unwrap()[2],
+            tmp.path().join("model-q8_0.gguf")
+        );
+        config.gguf = None;
+        assert!(model_files(&config).is_err());
+    }
+
+    #[test]
+    fn unparsable_files_are_reported_as_corrupt() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        for file in MODEL_FILES {
+            std::fs::write(tmp.path().join(file), "truncat").unwrap();
+        }
+        let config = EmbeddingConfig {
+            model_dir: Some(tmp.path().to_path_buf()),
+            ..Default::default()
+        };
+        // A local copy is never downloaded again
+        let err = Embedder::new(&config).err().unwrap();
+        assert!(
+            matches!(&err, Error::ModelCorrupt { path, .. } if path.ends_with("config.json")),
+            "{err}"
+        );
+        assert!(err.to_string().contains("delete it"), "{err}");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn discarding_a_cached_file_removes_its_blob() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let blob = tmp.path().join("blobs/abc123");
+        let pointer = tmp.path().join("snapshots/main/model.safetensors");
+        std::fs::create_dir_all(blob.parent().unwrap()).unwrap();
+        std::fs::create_dir_all(pointer.parent().unwrap()).unwrap();
+        std::fs::write(&blob, "partial").unwrap();
+        std::os::unix::fs::symlink(&blob, &pointer).unwrap();
+
+        discard_cached(&pointer).unwrap();
+        assert!(!blob.exists());
+        assert!(pointer.symlink_metadata().is_err());
+        // Already gone is fine
+        discard_cached(&pointer).unwrap();
+    }
+
+    #[test]
+    fn download_progress_is_logged_by_quarter() {
+        let mut progress = LogProgress::default();
+        progress.init(100, "model.safetensors");
+        progress.update(30);
+        assert_eq!(progress.logged, 1);
+        progress.update(10);
+        assert_eq!(progress.logged, 1);
+        progress.update(60);
+        assert_eq!(progress.logged, 4);
+
+        let mut empty = LogProgress::default();
+        empty.init(0, "config.json");
+        empty.update(0);
+        assert_eq!(empty.logged, 0);
+    }
+
     fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
         let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
         let norm_a: f32 = a.iter().map(|x| x * x).sum::<f32>().sqrt();
@@ -152,16 +919,114 @@ mod tests {
         dot / (norm_a * norm_b)
     }
 
+    #[test]
+    fn fingerprint_follows_file_contents_and_is_stable() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let weights = tmp.path().join("model.safetensors");
+        std::fs::write(&weights, b"weights one").unwrap();
+        let first = fingerprint("{}", &[&weights], "passage: ").unwrap();
+        // Pinned: indexes store it, so it mustn't move between builds
+        assert_eq!(
+            first,
+            "541eadd64f0154fbc70b0712bae437154b891f357e29356f27442ae1940955fa"
+        );
+
+        // Same size, different contents
+        std::fs::write(&weights, b"weights two").unwrap();
+        assert_ne!(fingerprint("{}", &[&weights], "passage: ").unwrap(), first);
+        std::fs::write(&weights, b"weights one").unwrap();
+        assert_ne!(fingerprint("{}", &[&weights], "query: ").unwrap(), first);
+        assert_eq!(fingerprint("{}", &[&weights], "passage: ").unwrap(), first);
+    }
+
+    #[test]
+    fn lowercasing_is_read_off_the_normalizer() {
+        use tokenizers::normalizers::{BertNormalizer, Lowercase, Sequence, Strip};
+        assert!(lowercases(Some(&BertNormalizer::default().into())));
+        let cased = BertNormalizer::new(true, true, None, false);
+        assert!(!lowercases(Some(&cased.into())));
+        let sequence = Sequence::new(vec![Strip::new(true, true).into(), Lowercase.into()]);
+        assert!(lowercases(Some(&sequence.into())));
+        assert!(!lowercases(None));
+    }
+
+    #[test]
+    fn blend_weights_and_renormalizes() {
+        let v = blend(&[1.0, 0.0], &[0.0, 1.0], 0.25);
+        assert!((v[0] - 0.948_683).abs() < 1e-5, "{v:?}");
+        assert!((v[1] - 0.316_228).abs() < 1e-5, "{v:?}");
+        assert!((cosine_similarity(&v, &v) - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn batches_split_evenly_between_replicas() {
+        let texts = |n: usize| (0..n).map(|i| i.to_string()).collect::<Vec<_>>();
+        let lengths = |runs: Vec<Vec<String>>| runs.iter().map(Vec::len).collect::<Vec<_>>();
+        assert_eq!(lengths(split(texts(10), 1)), [10]);
+        assert_eq!(lengths(split(texts(10), 3)), [4, 4, 2]);
+        assert_eq!(lengths(split(texts(2), 4)), [1, 1]);
+        // In order
+        assert_eq!(split(texts(3), 2).concat(), texts(3));
+    }
+
+    #[tokio::test]
+    async fn batches_wait_for_interactive_requests() {
+        let interactive = Arc::new(Interactive::default());
+        assert!(!interactive.is_recent());
+        interactive.quiet().await;
+
+        let turn = interactive.begin();
+        let waiting = {
+            let interactive = Arc::clone(&interactive);
+            tokio::spawn(async move { interactive.quiet().await })
+        };
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(!waiting.is_finished());
+
+        drop(turn);
+        tokio::time::timeout(Duration::from_secs(5), waiting)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(!interactive.is_pending());
+        assert!(interactive.is_recent());
+    }
+
+    #[test]
+    fn prefix_is_added_once() {
+        assert_eq!(prefixed("query: ", "retry loop"), "query: retry loop");
+        assert_eq!(
+            prefixed("query: ", "query: retry loop"),
+            "query: retry loop"
+        );
+        assert_eq!(prefixed("", "retry loop"), "retry loop");
+    }
+
+    #[test]
+    fn mean_points_between_vectors() {
+        let v = mean(&[vec![1.0, 0.0], vec![0.0, 1.0]]);
+        assert!((v[0] - v[1]).abs() < 1e-6, "{v:?}");
+        assert!((cosine_similarity(&v, &v) - 1.0).abs() < 1e-6);
+        assert!(mean(&[]).is_empty());
+    }
+
+    #[test]
+    fn truncate_keeps_prefix_and_renormalizes() {
+        let v = truncate(&[0.6, 0.0, 0.8], 2);
+        assert_eq!(v, [1.0, 0.0]);
+        assert_eq!(truncate(&[0.0, 0.0, 1.0], 2), [0.0, 0.0]);
+    }
+
     #[tokio::test]
     async fn embedding_dimension_is_384() {
-        let embedder = Embedder::new().unwrap();
+        let embedder = Embedder::new(&EmbeddingConfig::default()).unwrap();
         let vec = embedder.embed_one("hello world").await.unwrap();
         assert_eq!(vec.len(), EMBEDDING_DIM);
     }
 
     #[tokio::test]
     async fn similar_texts_have_higher_similarity() {
-        let embedder = Embedder::new().unwrap();
+        let embedder = Embedder::new(&EmbeddingConfig::default()).unwrap();
 
         let v_hello = embedder.embed_one("hello world").await.unwrap();
         let v_hi = embedder.embed_one("hi world").await.unwrap();
@@ -181,7 +1046,7 @@ mod tests {
 
     #[tokio::test]
     async fn batch_matches_individual() {
-        let embedder = Embedder::new().unwrap();
+        let embedder = Embedder::new(&EmbeddingConfig::default()).unwrap();
 
         let texts = vec![
             "func main() {}".to_string(),
@@ -206,16 +1071,56 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn replicas_embed_like_one_model() {
+        let single = Embedder::new(&EmbeddingConfig::default()).unwrap();
+        let replicated = Embedder::new(&EmbeddingConfig {
+            replicas: 3,
+            ..Default::default()
+        })
+        .unwrap();
+        let texts: Vec<String> = (0..7).map(|i| format!("fn handler_{i}() {{}}")).collect();
+
+        let expected = single.embed_batch(texts.clone()).await.unwrap();
+        let (a, b) = tokio::join!(
+            replicated.embed_batch(texts.clone()),
+            replicated.embed_batch(texts)
+        );
+        for vectors in [a.unwrap(), b.unwrap()] {
+            assert_eq!(vectors.len(), expected.len());
+            for (got, want) in vectors.iter().zip(&expected) {
+                let sim = cosine_similarity(got, want);
+                assert!(sim > 0.99, "replica vs single similarity = {sim:.4}");
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn token_counts_are_not_truncated() {
+        let embedder = Embedder::new(&EmbeddingConfig::default()).unwrap();
+        let long = "token ".repeat(MAX_SEQ_LEN * 2);
+        let counts = embedder
+            .count_tokens(vec!["hello world".to_string(), long])
+            .await
+            .unwrap();
+        assert!(counts[0] < 10, "short text count = {}", counts[0]);
+        assert!(
+            counts[1] > MAX_SEQ_LEN,
+            "long text should exceed the window, got {}",
+            counts[1]
+        );
+    }
+
     #[tokio::test]
     async fn empty_batch_returns_empty() {
-        let embedder = Embedder::new().unwrap();
+        let embedder = Embedder::new(&EmbeddingConfig::default()).unwrap();
         let result = embedder.embed_batch(vec![]).await.unwrap();
         assert!(result.is_empty());
     }
 
     #[tokio::test]
     async fn embeddings_are_normalized() {
-        let embedder = Embedder::new().unwrap();
+        let embedder = Embedder::new(&EmbeddingConfig::default()).unwrap();
         let vec = embedder
             .embed_one("func HandleRequest(w http.ResponseWriter, r *http.Request)")
             .await
@@ -230,7 +1135,7 @@ mod tests {
 
     #[tokio::test]
     async fn code_semantic_similarity() {
-        let embedder = Embedder::new().unwrap();
+        let embedder = Embedder::new(&EmbeddingConfig::default()).unwrap();
 
         // Two Go HTTP handlers should be more similar to each other than to a math function
         let v_handler1 = embedder
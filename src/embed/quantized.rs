@@ -0,0 +1,348 @@
+//! BERT run from a GGUF file of quantized weights.
+//!
+//! candle has quantized versions of many decoder models but none of BERT,
+//! so this is its `BertModel` with the linear layers swapped for quantized
+//! matrix multiplies, which are most of a forward pass. Embedding tables
+//! and layer norms are small and dequantized on load. Tensors are looked
+//! up under the names `model.safetensors` gives them, which converting it
+//! with candle's `tensor-tools quantize` keeps.
+
+use candle_core::{D, DType, Module, Result, Tensor};
+use candle_nn::LayerNorm;
+use candle_transformers::models::bert::{Config, HiddenAct};
+use candle_transformers::quantized_nn::{Embedding, Linear, layer_norm, linear};
+use candle_transformers::quantized_var_builder::VarBuilder;
+
+#[derive(Clone)]
+struct Embeddings {
+    word: Embedding,
+    position: Embedding,
+    token_type: Embedding,
+    layer_norm: LayerNorm,
+}
+
+impl Embeddings {
+    fn load(vb: VarBuilder, config: &Config) -> Result<Self> {
+        let hidden = config.hidden_size;
+        Ok(Self {
+            word: Embedding::new(config.vocab_size, hidden, vb.pp("word_embeddings"))?,
+            position: Embedding::new(
+                config.max_position_embeddings,
+                hidden,
+                vb.pp("position_embeddings"),
+            )?,
+            token_type: Embedding::new(
+                config.type_vocab_size,
+                hidden,
+                vb.pp("token_type_embeddings"),
+            )?,
+            layer_norm: layer_norm(hidden, config.layer_norm_eps, vb.pp("LayerNorm"))?,
+        })
+    }
+
+    fn forward(&self, token_ids: &Tensor, token_type_ids: &Tensor) -> Result<Tensor> {
+        let (_, seq_len) = token_ids.dims2()?;
+        let positions = Tensor::arange(0u32, seq_len as u32, token_ids.device())?;
+        let embeddings = (self.word.forward(token_ids)?
+            + self.token_type.forward(token_type_ids)?)?
+        .broadcast_add(&self.position.forward(&positions)?)?;
+        self.layer_norm.forward(&embeddings)
+    }
+}
+
+/// One encoder layer: self-attention, then the feed-forward block, each
+/// added back to its input and normalized.
+#[derive(Clone)]
+struct Layer {
+    query: Linear,
+    key: Linear,
+    value: Linear,
+    attention_output: Linear,
+    attention_norm: LayerNorm,
+    intermediate: Linear,
+    output: Linear,
+    output_norm: LayerNorm,
+    activation: HiddenAct,
+    heads: usize,
+    head_size: usize,
+}
+
+impl Layer {
+    fn load(vb: VarBuilder, config: &Config) -> Result<Self> {
+        let hidden = config.hidden_size;
+        let eps = config.layer_norm_eps;
+        let attention = vb.pp("attention");
+        Ok(Self {
+            query: linear(hidden, hidden, attention.pp("self.query"))?,
+            key: linear(hidden, hidden, attention.pp("self.key"))?,
+            value: linear(hidden, hidden, attention.pp("self.value"))?,
+            attention_output: linear(hidden, hidden, attention.pp("output.dense"))?,
+            attention_norm: layer_norm(hidden, eps, attention.pp("output.LayerNorm"))?,
+            intermediate: linear(
+                hidden,
+                config.intermediate_size,
+                vb.pp("intermediate.dense"),
+            )?,
+            output: linear(config.intermediate_size, hidden, vb.pp("output.dense"))?,
+            output_norm: layer_norm(hidden, eps, vb.pp("output.LayerNorm"))?,
+            activation: config.hidden_act,
+            heads: config.num_attention_heads,
+            head_size: hidden / config.num_attention_heads,
+        })
+    }
+
+    /// `[batch, seq, hidden]` to `[batch, heads, seq, head_size]`.
+    fn split_heads(&self, xs: &Tensor) -> Result<Tensor> {
+        let (batch, seq_len, _) = xs.dims3()?;
+        xs.reshape((batch, seq_len, self.heads, self.head_size))?
+            .transpose(1, 2)?
+            .contiguous()
+    }
+
+    fn forward(&self, hidden: &Tensor, mask: &Tensor) -> Result<Tensor> {
+        let query = self.split_heads(&self.query.forward(hidden)?)?;
+        let key = self.split_heads(&self.key.forward(hidden)?)?;
+        let value = self.split_heads(&self.value.forward(hidden)?)?;
+
+        let scores = (query.matmul(&key.t()?)? / (self.head_size as f64).sqrt())?;
+        let probs = candle_nn::ops::softmax_last_dim(&scores.broadcast_add(mask)?)?;
+        let context = probs
+            .matmul(&value)?
+            .transpose(1, 2)?
+            .contiguous()?
+            .flatten_from(D::Minus2)?;
+        let attended = self
+            .attention_norm
+            .forward(&(self.attention_output.forward(&context)? + hidden)?)?;
+
+        let intermediate = self.intermediate.forward(&attended)?;
+        let intermediate = match self.activation {
+            HiddenAct::Gelu => intermediate.gelu_erf()?,
+            HiddenAct::GeluApproximate => intermediate.gelu()?,
+            HiddenAct::Relu => intermediate.relu()?,
+        };
+        self.output_norm
+            .forward(&(self.output.forward(&intermediate)? + attended)?)
+    }
+}
+
+/// BERT with quantized linear layers. Clones share the quantized weights.
+#[derive(Clone)]
+pub(super) struct QuantizedBert {
+    embeddings: Embeddings,
+    layers: Vec<Layer>,
+}
+
+impl QuantizedBert {
+    /// Load the model `config` describes from `vb`, with its tensors at
+    /// the top level or under the model type (`bert.`), as `BertModel`
+    /// accepts.
+    pub(super) fn load(vb: VarBuilder, config: &Config) -> Result<Self> {
+        Self::load_under(vb.clone(), config).or_else(|err| match &config.model_type {
+            Some(model_type) => Self::load_under(vb.pp(model_type), config).map_err(|_| err),
+            None => Err(err),
+        })
+    }
+
+    fn load_under(vb: VarBuilder, config: &Config) -> Result<Self> {
+        Ok(Self {
+            embeddings: Embeddings::load(vb.pp("embeddings"), config)?,
+            layers: (0..config.num_hidden_layers)
+                .map(|index| Layer::load(vb.pp(format!("encoder.layer.{index}")), config))
+                .collect::<Result<_>>()?,
+        })
+    }
+
+    /// The last hidden states, `[batch, seq, hidden]`, as
+    /// `BertModel::forward` returns them.
+    pub(super) fn forward(
+        &self,
+        token_ids: &Tensor,
+        token_type_ids: &Tensor,
+        attention_mask: &Tensor,
+    ) -> Result<Tensor> {
+        let mut hidden = self.embeddings.forward(token_ids, token_type_ids)?;
+        // 0 for tokens attended to, the most negative f32 for padding
+        let mask = attention_mask
+            .unsqueeze(1)?
+            .unsqueeze(1)?
+            .to_dtype(DType::F32)?;
+        let mask = ((mask.ones_like()? - &mask)? * f64::from(f32::MIN))?;
+        for layer in &self.layers {
+            hidden = layer.forward(&hidden, &mask)?;
+        }
+        Ok(hidden)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use candle_core::Device;
+    use candle_core::quantized::{GgmlDType, QTensor, gguf_file};
+    use candle_transformers::models::bert::BertModel;
+
+    use super::*;
+
+    fn tiny_config() -> Config {
+        serde_json::from_value(serde_json::json!({
+            "vocab_size": 40,
+            "hidden_size": 64,
+            "num_hidden_layers": 2,
+            "num_attention_heads": 4,
+            "intermediate_size": 128,
+            "hidden_act": "gelu",
+            "hidden_dropout_prob": 0.0,
+            "max_position_embeddings": 16,
+            "type_vocab_size": 2,
+            "initializer_range": 0.02,
+            "layer_norm_eps": 1e-12,
+            "pad_token_id": 0,
+            "classifier_dropout": null,
+            "model_type": "bert"
+        }))
+        .unwrap()
+    }
+
+    /// Random weights for `config`, named as in `model.safetensors`.
+    fn weights(config: &Config) -> HashMap<String, Tensor> {
+        let device = Device::Cpu;
+        let hidden = config.hidden_size;
+        let inner = config.intermediate_size;
+        let mut shapes: Vec<(String, Vec<usize>)> = vec![
+            (
+                "embeddings.word_embeddings.weight".into(),
+                vec![config.vocab_size, hidden],
+            ),
+            (
+                "embeddings.position_embeddings.weight".into(),
+                vec![config.max_position_embeddings, hidden],
+            ),
+            (
+                "embeddings.token_type_embeddings.weight".into(),
+                vec![config.type_vocab_size, hidden],
+            ),
+        ];
+        let norm = |prefix: String, shapes: &mut Vec<(String, Vec<usize>)>| {
+            shapes.push((format!("{prefix}.weight"), vec![hidden]));
+            shapes.push((format!("{prefix}.bias"), vec![hidden]));
+        };
+        norm("embeddings.LayerNorm".into(), &mut shapes);
+        for layer in 0..config.num_hidden_layers {
+            let at = |name: &str| format!("encoder.layer.{layer}.{name}");
+            for (name, out, input) in [
+                ("attention.self.query", hidden, hidden),
+                ("attention.self.key", hidden, hidden),
+                ("attention.self.value", hidden, hidden),
+                ("attention.output.dense", hidden, hidden),
+                ("intermediate.dense", inner, hidden),
+                ("output.dense", hidden, inner),
+            ] {
+                shapes.push((at(&format!("{name}.weight")), vec![out, input]));
+                shapes.push((at(&format!("{name}.bias")), vec![out]));
+            }
+            norm(at("attention.output.LayerNorm"), &mut shapes);
+            norm(at("output.LayerNorm"), &mut shapes);
+        }
+        shapes
+            .into_iter()
+            .map(|(name, shape)| {
+                let tensor = if name.contains("LayerNorm.weight") {
+                    Tensor::ones(shape, DType::F32, &device).unwrap()
+                } else {
+                    Tensor::randn(0f32, 0.1f32, shape, &device).unwrap()
+                };
+                (name, tensor)
+            })
+            .collect()
+    }
+
+    /// `weights` as a GGUF file, matrices stored as `dtype`.
+    fn gguf(weights: &HashMap<String, Tensor>, dtype: GgmlDType) -> Vec<u8> {
+        let tensors: Vec<(&str, QTensor)> = weights
+            .iter()
+            .map(|(name, tensor)| {
+                let dtype = if tensor.rank() == 2 {
+                    dtype
+                } else {
+                    GgmlDType::F32
+                };
+                (name.as_str(), QTensor::quantize(tensor, dtype).unwrap())
+            })
+            .collect();
+        let refs: Vec<(&str, &QTensor)> = tensors.iter().map(|(n, t)| (*n, t)).collect();
+        let mut buffer = std::io::Cursor::new(Vec::new());
+        gguf_file::write(&mut buffer, &[], &refs).unwrap();
+        buffer.into_inner()
+    }
+
+    /// Mean of the hidden states of each input, which is what embedding
+    /// pools them into.
+    fn pooled(hidden: &Tensor) -> Vec<Vec<f32>> {
+        hidden.mean(1).unwrap().to_vec2().unwrap()
+    }
+
+    fn max_difference(a: &[Vec<f32>], b: &[Vec<f32>]) -> f32 {
+        a.iter()
+            .flatten()
+            .zip(b.iter().flatten())
+            .map(|(x, y)| (x - y).abs())
+            .fold(0.0, f32::max)
+    }
+
+    #[test]
+    fn matches_bert_model_and_stays_close_when_quantized() {
+        let device = Device::Cpu;
+        let config = tiny_config();
+        let weights = weights(&config);
+        let full = BertModel::load(
+            candle_nn::VarBuilder::from_tensors(weights.clone(), DType::F32, &device),
+            &config,
+        )
+        .unwrap();
+
+        let token_ids = Tensor::new(&[[1u32, 5, 9, 2, 0], [1, 7, 3, 8, 2]], &device).unwrap();
+        let token_type_ids = token_ids.zeros_like().unwrap();
+        let mask = Tensor::new(&[[1u32, 1, 1, 1, 0], [1, 1, 1, 1, 1]], &device).unwrap();
+        let expected = pooled(
+            &full
+                .forward(&token_ids, &token_type_ids, Some(&mask))
+                .unwrap(),
+        );
+
+        let load = |dtype| {
+            let vb = VarBuilder::from_gguf_buffer(&gguf(&weights, dtype), &device).unwrap();
+            QuantizedBert::load(vb, &config).unwrap()
+        };
+        let run = |model: &QuantizedBert| {
+            pooled(&model.forward(&token_ids, &token_type_ids, &mask).unwrap())
+        };
+
+        // Unquantized, it computes what BertModel does
+        let exact = run(&load(GgmlDType::F32));
+        assert!(max_difference(&exact, &expected) < 1e-4);
+
+        // Quantized to 8 bits, it stays close
+        let q8 = run(&load(GgmlDType::Q8_0));
+        let difference = max_difference(&q8, &expected);
+        assert!(difference > 0.0 && difference < 0.05, "{difference}");
+    }
+
+    #[test]
+    fn tensors_may_sit_under_the_model_type() {
+        let device = Device::Cpu;
+        let config = tiny_config();
+        let weights: HashMap<String, Tensor> = weights(&config)
+            .into_iter()
+            .map(|(name, tensor)| (format!("bert.{name}"), tensor))
+            .collect();
+        let vb = VarBuilder::from_gguf_buffer(&gguf(&weights, GgmlDType::F32), &device).unwrap();
+        assert!(QuantizedBert::load(vb, &config).is_ok());
+
+        let vb =
+            VarBuilder::from_gguf_buffer(&gguf(&HashMap::new(), GgmlDType::F32), &device).unwrap();
+        let err = QuantizedBert::load(vb, &config).err().unwrap().to_string();
+        assert!(err.contains("embeddings.word_embeddings.weight"), "{err}");
+    }
+}
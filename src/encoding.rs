@@ -0,0 +1,99 @@
+//! Decoding source files that aren't UTF-8.
+//!
+//! Most code is UTF-8, but older projects carry files in Latin-1,
+//! Windows-1252, Shift-JIS and the like, which would otherwise be skipped.
+//! A file that isn't valid UTF-8 has its encoding taken from a byte order
+//! mark, or failing that guessed from its bytes, and is decoded to UTF-8,
+//! with any bytes the encoding can't map replaced. The chunker, and so
+//! the byte offsets chunks are stored as, see the decoded text; the
+//! encoding is recorded with each chunk so its file can be decoded the
+//! same way when its text is read back.
+
+use encoding_rs::Encoding;
+
+/// A file's text as UTF-8.
+#[derive(Debug)]
+pub struct Decoded {
+    pub text: String,
+    /// Name of the encoding the file was decoded from, e.g.
+    /// `windows-1252`; `None` if it was already UTF-8.
+    pub encoding: Option<&'static str>,
+}
+
+/// Whether `bytes` start with a UTF-16 byte order mark, whose text is
+/// full of NUL bytes without being binary.
+pub fn is_utf16(bytes: &[u8]) -> bool {
+    Encoding::for_bom(bytes).is_some_and(|(encoding, _)| encoding != encoding_rs::UTF_8)
+}
+
+/// `bytes` as text: unchanged if they are UTF-8, else decoded from the
+/// encoding of their byte order mark or the one they most look like.
+pub fn decode(bytes: Vec<u8>) -> Decoded {
+    let bytes = match String::from_utf8(bytes) {
+        Ok(text) => {
+            return Decoded {
+                text,
+                encoding: None,
+            };
+        }
+        Err(e) => e.into_bytes(),
+    };
+    let encoding = match Encoding::for_bom(&bytes) {
+        Some((encoding, _)) => encoding,
+        None => {
+            let mut detector = chardetng::EncodingDetector::new();
+            detector.feed(&bytes, true);
+            detector.guess(None, false)
+        }
+    };
+    let (text, _, _) = encoding.decode(&bytes);
+    Decoded {
+        text: text.into_owned(),
+        encoding: Some(encoding.name()),
+    }
+}
+
+/// `bytes` decoded from `encoding`, a name [`decode`] recorded, or as
+/// [`decode`] would if it is `None` or unknown.
+pub fn decode_as(bytes: Vec<u8>, encoding: Option<&str>) -> String {
+    match encoding.and_then(|name| Encoding::for_label(name.as_bytes())) {
+        Some(encoding) => encoding.decode(&bytes).0.into_owned(),
+        None => decode(bytes).text,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn legacy_encodings_are_detected_and_decoded() {
+        let utf8 = decode("fn café() {}".as_bytes().to_vec());
+        assert_eq!(utf8.text, "fn café() {}");
+        assert_eq!(utf8.encoding, None);
+
+        // "# Größe in Bytes" in Latin-1
+        let latin1 = b"# Gr\xf6\xdfe in Bytes\nsize = 42\n".to_vec();
+        let decoded = decode(latin1.clone());
+        assert_eq!(decoded.text, "# Größe in Bytes\nsize = 42\n");
+        assert_eq!(decoded.encoding, Some("windows-1252"));
+        assert_eq!(decode_as(latin1, decoded.encoding), decoded.text);
+
+        let sjis = b"// \x90\xdd\x92\xe8\x83t\x83@\x83C\x83\x8b\x82\xf0\x93\xc7\x82\xdd\x8d\x9e\x82\xf1\x82\xc5\x81A\x8a\xf9\x92\xe8\x92l\x82\xc6\x82\xdc\x82\xc6\x82\xdf\x82\xc4\x95\xd4\x82\xb7\x81B\n".to_vec();
+        let decoded = decode(sjis);
+        assert_eq!(
+            decoded.text,
+            "// 設定ファイルを読み込んで、既定値とまとめて返す。\n"
+        );
+        assert_eq!(decoded.encoding, Some("Shift_JIS"));
+
+        let utf16: Vec<u8> = [0xff, 0xfe]
+            .into_iter()
+            .chain("x = 1\n".encode_utf16().flat_map(u16::to_le_bytes))
+            .collect();
+        assert!(is_utf16(&utf16));
+        let decoded = decode(utf16);
+        assert_eq!(decoded.text, "x = 1\n");
+        assert_eq!(decoded.encoding, Some("UTF-16LE"));
+    }
+}
@@ -1,14 +1,39 @@
+//! The error type shared by the library.
+
 use std::path::PathBuf;
 
 /// Top-level error type for claudevil.
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
-    #[error("failed to download model from Hugging Face Hub")]
-    ModelDownload(#[source] Box<dyn std::error::Error + Send + Sync>),
+    #[error(
+        "failed to download {file} of the embedding model from {endpoint} -- check network \
+         access (a proxy is taken from HTTPS_PROXY), point [embedding] endpoint at a mirror, \
+         or set [embedding] model_dir to a local copy of the model"
+    )]
+    ModelDownload {
+        file: String,
+        endpoint: String,
+        #[source]
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+
+    #[error("embedding model unavailable: {0}")]
+    ModelMissing(String),
 
     #[error("failed to load embedding model")]
     ModelLoad(#[source] Box<dyn std::error::Error + Send + Sync>),
 
+    #[error(
+        "{} of the embedding model is corrupt -- delete it so it is downloaded again, or \
+         replace it with a good copy",
+        path.display()
+    )]
+    ModelCorrupt {
+        path: PathBuf,
+        #[source]
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+
     #[error("tokenization failed")]
     Tokenize(#[source] Box<dyn std::error::Error + Send + Sync>),
 
@@ -38,12 +63,49 @@ pub enum Error {
         source: std::io::Error,
     },
 
+    #[error(
+        "the index is open read-only, so {0} is unavailable -- restart without --read-only to modify it"
+    )]
+    ReadOnly(&'static str),
+
+    #[error(
+        "the index is full: it needs {} MiB on disk, and [index] max_bytes allows {} MiB -- \
+         raise the limit, exclude large directories, or free space with `claudevil gc`",
+        bytes / (1024 * 1024),
+        max_bytes / (1024 * 1024)
+    )]
+    IndexFull { bytes: u64, max_bytes: u64 },
+
+    #[error("index archive error: {0}")]
+    Archive(String),
+
+    #[error(
+        "could not listen for metrics on {addr} -- pick a free address for [metrics] listen in the config"
+    )]
+    MetricsBind {
+        addr: String,
+        #[source]
+        source: std::io::Error,
+    },
+
+    #[error("eval error: {0}")]
+    Eval(String),
+
     #[error("config error: {0}")]
     Config(String),
 
+    #[error("git error: {0}")]
+    Git(String),
+
+    #[error("model check failed: {0}")]
+    ModelCheck(String),
+
     #[error("tree-sitter error: {0}")]
     TreeSitter(String),
 
+    #[error("not a Jupyter notebook: {0}")]
+    Notebook(String),
+
     #[error("{0}")]
     Io(#[from] std::io::Error),
 
@@ -52,3 +114,125 @@ pub enum Error {
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
+
+/// What kind of failure an error is, for callers choosing how to react:
+/// the MCP server gives each its own error code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorClass {
+    /// The embedding model couldn't be downloaded, loaded, or run.
+    Model,
+    /// The index couldn't be read or written.
+    Index,
+    /// The index is open read-only, so it can't be changed.
+    ReadOnly,
+    /// A file that was asked for doesn't exist.
+    NotFound,
+    /// A path that may not be read, such as one outside the indexed root.
+    InvalidPath,
+    /// A call ran past its time limit and was abandoned.
+    Timeout,
+    /// Anything else.
+    Internal,
+}
+
+impl ErrorClass {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Model => "model",
+            Self::Index => "index",
+            Self::ReadOnly => "read_only",
+            Self::NotFound => "not_found",
+            Self::InvalidPath => "invalid_path",
+            Self::Timeout => "timeout",
+            Self::Internal => "internal",
+        }
+    }
+}
+
+impl Error {
+    pub fn class(&self) -> ErrorClass {
+        match self {
+            Self::ModelDownload { .. }
+            | Self::ModelMissing(_)
+            | Self::ModelLoad(_)
+            | Self::ModelCorrupt { .. }
+            | Self::ModelCheck(_)
+            | Self::Tokenize(_)
+            | Self::Inference(_)
+            | Self::EmptyEmbedding => ErrorClass::Model,
+            Self::StoreIo { .. }
+            | Self::StoreIndex(_)
+            | Self::StoreSerde(_)
+            | Self::TaskJoin(_) => ErrorClass::Index,
+            Self::ReadOnly(_) => ErrorClass::ReadOnly,
+            Self::IndexFull { .. } => ErrorClass::Index,
+            Self::FileRead { source, .. } | Self::Io(source)
+                if source.kind() == std::io::ErrorKind::NotFound =>
+            {
+                ErrorClass::NotFound
+            }
+            Self::FileRead { .. }
+            | Self::Io(_)
+            | Self::Archive(_)
+            | Self::MetricsBind { .. }
+            | Self::Eval(_)
+            | Self::Config(_)
+            | Self::Git(_)
+            | Self::TreeSitter(_)
+            | Self::Notebook(_) => ErrorClass::Internal,
+        }
+    }
+
+    /// Whether the same call may succeed if retried later without changing
+    /// anything: a download or an index backend that may come back, or a
+    /// task that was cut short.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Self::ModelDownload { .. } | Self::StoreIndex(_) | Self::TaskJoin(_) => true,
+            Self::StoreIo { source, .. } | Self::Io(source) => matches!(
+                source.kind(),
+                std::io::ErrorKind::Interrupted
+                    | std::io::ErrorKind::TimedOut
+                    | std::io::ErrorKind::WouldBlock
+            ),
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn errors_are_classified() {
+        let missing = || std::io::Error::from(std::io::ErrorKind::NotFound);
+        let file = |source| Error::FileRead {
+            path: PathBuf::from("src/gone.rs"),
+            source,
+        };
+        assert_eq!(file(missing()).class(), ErrorClass::NotFound);
+        assert_eq!(
+            file(std::io::Error::from(std::io::ErrorKind::PermissionDenied)).class(),
+            ErrorClass::Internal
+        );
+        assert_eq!(Error::Io(missing()).class(), ErrorClass::NotFound);
+        assert_eq!(
+            Error::ModelMissing("no weights".to_string()).class(),
+            ErrorClass::Model
+        );
+        assert_eq!(Error::ReadOnly("reindex").class(), ErrorClass::ReadOnly);
+
+        let index = Error::StoreIndex("qdrant unreachable".to_string());
+        assert_eq!(index.class(), ErrorClass::Index);
+        assert!(index.is_retryable());
+        assert!(!Error::ReadOnly("reindex").is_retryable());
+        assert!(
+            Error::StoreIo {
+                context: "saving".to_string(),
+                source: std::io::Error::from(std::io::ErrorKind::TimedOut),
+            }
+            .is_retryable()
+        );
+    }
+}
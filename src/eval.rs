@@ -0,0 +1,311 @@
+//! Retrieval quality evaluation.
+//!
+//! An eval suite is a YAML file of queries paired with the file and/or
+//! symbol a good search should return. Running it against an index reports
+//! recall@k and mean reciprocal rank, so chunking and embedding changes can
+//! be measured instead of guessed at.
+//!
+//! ```yaml
+//! k: 10               # results to consider per query (default 10)
+//! min_recall: 0.8     # optional: fail when recall@k drops below this
+//! cases:
+//!   - query: where is the config file parsed
+//!     file: src/config.rs
+//!     symbol: load
+//! ```
+
+use std::fmt::Write as _;
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::embed::Embedder;
+use crate::error::{Error, Result};
+use crate::store::{Filters, SearchResult, VectorStore};
+
+fn default_k() -> usize {
+    10
+}
+
+/// A set of queries with their expected results.
+#[derive(Debug, Deserialize)]
+pub struct EvalSuite {
+    /// Results considered per query.
+    #[serde(default = "default_k")]
+    pub k: usize,
+    /// Recall@k below which the run counts as failed.
+    pub min_recall: Option<f64>,
+    pub cases: Vec<EvalCase>,
+}
+
+/// One query and the result it should find.
+#[derive(Debug, Deserialize)]
+pub struct EvalCase {
+    pub query: String,
+    /// Restrict the search to one language.
+    pub language: Option<String>,
+    /// Expected file path, relative to the indexed root.
+    pub file: Option<String>,
+    /// Expected symbol. Matches qualified names by their last segment, so
+    /// `start` matches `Server::start`.
+    pub symbol: Option<String>,
+}
+
+impl EvalCase {
+    fn matches(&self, result: &SearchResult) -> bool {
+        let file_ok = self.file.as_ref().is_none_or(|f| *f == result.file_path);
+        let symbol_ok = self.symbol.as_ref().is_none_or(|want| {
+            result.symbol_name.as_deref().is_some_and(|name| {
+                name == want
+                    || name
+                        .rsplit(['.', ':'])
+                        .next()
+                        .is_some_and(|last| last == want)
+            })
+        });
+        file_ok && symbol_ok
+    }
+
+    /// 1-based rank of the first matching result.
+    fn rank(&self, results: &[SearchResult]) -> Option<usize> {
+        results.iter().position(|r| self.matches(r)).map(|i| i + 1)
+    }
+}
+
+/// Outcome of a single case.
+#[derive(Debug)]
+pub struct CaseResult {
+    pub query: String,
+    pub rank: Option<usize>,
+}
+
+/// Aggregate scores for a suite run.
+#[derive(Debug)]
+pub struct EvalReport {
+    pub k: usize,
+    pub cases: Vec<CaseResult>,
+}
+
+impl EvalReport {
+    /// Fraction of cases whose expected result appeared in the top k.
+    pub fn recall(&self) -> f64 {
+        if self.cases.is_empty() {
+            return 0.0;
+        }
+        let hits = self.cases.iter().filter(|c| c.rank.is_some()).count();
+        hits as f64 / self.cases.len() as f64
+    }
+
+    /// Mean reciprocal rank, counting misses as zero.
+    pub fn mrr(&self) -> f64 {
+        if self.cases.is_empty() {
+            return 0.0;
+        }
+        let sum: f64 = self
+            .cases
+            .iter()
+            .filter_map(|c| c.rank)
+            .map(|r| 1.0 / r as f64)
+            .sum();
+        sum / self.cases.len() as f64
+    }
+
+    /// Per-case ranks followed by the summary line.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        for case in &self.cases {
+            let rank = case
+                .rank
+                .map_or_else(|| "miss".to_string(), |r| format!("#{r}"));
+            let _ = writeln!(out, "{rank:>5}  {}", case.query);
+        }
+        let _ = write!(
+            out,
+            "\n{} cases, recall@{} {:.3}, MRR {:.3}",
+            self.cases.len(),
+            self.k,
+            self.recall(),
+            self.mrr()
+        );
+        out
+    }
+}
+
+impl EvalSuite {
+    /// Read a suite from a YAML file.
+    pub fn load(path: &Path) -> Result<Self> {
+        let raw = std::fs::read_to_string(path).map_err(|e| Error::FileRead {
+            path: path.to_path_buf(),
+            source: e,
+        })?;
+        Self::parse(&raw)
+            .map_err(|e| Error::Eval(format!("invalid eval suite {}: {e}", path.display())))
+    }
+
+    fn parse(raw: &str) -> std::result::Result<Self, String> {
+        let suite: Self = serde_yaml_ng::from_str(raw).map_err(|e| e.to_string())?;
+        if suite.k == 0 {
+            return Err("k must be at least 1".to_string());
+        }
+        if let Some(i) = suite
+            .cases
+            .iter()
+            .position(|c| c.file.is_none() && c.symbol.is_none())
+        {
+            return Err(format!(
+                "case {} ('{}') needs a file or symbol to expect",
+                i + 1,
+                suite.cases[i].query
+            ));
+        }
+        Ok(suite)
+    }
+
+    /// Run every case against `store` and score the results.
+    pub async fn run(&self, embedder: &Embedder, store: &VectorStore) -> Result<EvalReport> {
+        let mut cases = Vec::with_capacity(self.cases.len());
+        for case in &self.cases {
+            let query_vec = embedder.embed_query(&case.query).await?;
+            let results = store
+                .search(
+                    &query_vec,
+                    self.k,
+                    Filters {
+                        language: case.language.as_deref(),
+                        ..Default::default()
+                    },
+                    None,
+                )
+                .await?;
+            cases.push(CaseResult {
+                query: case.query.clone(),
+                rank: case.rank(&results),
+            });
+        }
+        Ok(EvalReport { k: self.k, cases })
+    }
+
+    /// Whether `report` meets this suite's `min_recall`, if any.
+    pub fn passes(&self, report: &EvalReport) -> bool {
+        self.min_recall.is_none_or(|min| report.recall() >= min)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result(file_path: &str, symbol: Option<&str>) -> SearchResult {
+        SearchResult {
+            id: 0,
+            file_path: file_path.to_string(),
+            content: String::new(),
+            symbol_name: symbol.map(str::to_string),
+            symbol_kind: None,
+            doc: None,
+            signature: None,
+            build: None,
+            owners: Vec::new(),
+            module: None,
+            language: "rust".to_string(),
+            start_line: 1,
+            end_line: 1,
+            distance: 0.0,
+            duplicates: Vec::new(),
+            approximate: false,
+        }
+    }
+
+    fn case(file: Option<&str>, symbol: Option<&str>) -> EvalCase {
+        EvalCase {
+            query: "q".to_string(),
+            language: None,
+            file: file.map(str::to_string),
+            symbol: symbol.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn parse_suite_with_defaults() {
+        let suite =
+            EvalSuite::parse("cases:\n  - query: parse config\n    file: src/config.rs\n").unwrap();
+        assert_eq!(suite.k, 10);
+        assert_eq!(suite.min_recall, None);
+        assert_eq!(suite.cases[0].file.as_deref(), Some("src/config.rs"));
+    }
+
+    #[test]
+    fn parse_rejects_case_without_expectation() {
+        let err = EvalSuite::parse("cases:\n  - query: anything\n").unwrap_err();
+        assert!(err.contains("needs a file or symbol"), "{err}");
+    }
+
+    #[test]
+    fn rank_matches_file_and_qualified_symbol() {
+        let results = [
+            result("src/a.rs", Some("other")),
+            result("src/server.rs", Some("Server::start")),
+        ];
+        assert_eq!(case(Some("src/server.rs"), None).rank(&results), Some(2));
+        assert_eq!(case(None, Some("start")).rank(&results), Some(2));
+        assert_eq!(
+            case(Some("src/a.rs"), Some("start")).rank(&results),
+            None,
+            "both file and symbol must match"
+        );
+    }
+
+    #[test]
+    fn recall_and_mrr() {
+        let report = EvalReport {
+            k: 5,
+            cases: vec![
+                CaseResult {
+                    query: "a".to_string(),
+                    rank: Some(1),
+                },
+                CaseResult {
+                    query: "b".to_string(),
+                    rank: Some(4),
+                },
+                CaseResult {
+                    query: "c".to_string(),
+                    rank: None,
+                },
+                CaseResult {
+                    query: "d".to_string(),
+                    rank: Some(2),
+                },
+            ],
+        };
+        assert!((report.recall() - 0.75).abs() < 1e-9);
+        assert!((report.mrr() - (1.0 + 0.25 + 0.5) / 4.0).abs() < 1e-9);
+        assert!(
+            report
+                .render()
+                .ends_with("4 cases, recall@5 0.750, MRR 0.438")
+        );
+    }
+
+    #[test]
+    fn min_recall_threshold() {
+        let suite =
+            EvalSuite::parse("min_recall: 0.5\ncases:\n  - query: q\n    symbol: s\n").unwrap();
+        let report = |rank| EvalReport {
+            k: 10,
+            cases: vec![CaseResult {
+                query: "q".to_string(),
+                rank,
+            }],
+        };
+        assert!(suite.passes(&report(Some(3))));
+        assert!(!suite.passes(&report(None)));
+    }
+
+    #[test]
+    fn bundled_suite_is_valid() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("evals/claudevil.yaml");
+        let suite = EvalSuite::load(&path).unwrap();
+        assert!(!suite.cases.is_empty());
+    }
+}
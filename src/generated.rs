@@ -0,0 +1,116 @@
+//! Spotting minified and generated files.
+//!
+//! A minified JavaScript bundle or a generated mock makes a poor search
+//! result, yet a good match: its chunks are long and dense and repeat the
+//! shapes of the code they were made from, so they crowd hand-written code
+//! out of similarity results. A file is taken for minified when its lines
+//! are very long or it has next to no whitespace, and for generated when
+//! it is a source map, ends in a `sourceMappingURL` comment, or carries a
+//! marker such as `Code generated ... DO NOT EDIT.` or `@generated` near
+//! its top. `[indexing] generated` decides whether such files are left
+//! out, ranked lower, or indexed like any other.
+
+/// Lines at the top of a file searched for a generated-code marker.
+const HEADER_LINES: usize = 20;
+
+/// Lines at the end of a file searched for a `sourceMappingURL` comment.
+const TRAILER_LINES: usize = 3;
+
+/// Markers of generated code, matched case-insensitively in the header.
+const MARKERS: &[&str] = &[
+    "do not edit",
+    "@generated",
+    "auto-generated",
+    "autogenerated",
+    "automatically generated",
+];
+
+/// Files smaller than this are never taken for minified; a short file of
+/// long lines is more likely a table of data than a bundle.
+const MINIFIED_MIN_BYTES: usize = 1024;
+
+/// Mean line length, in bytes, beyond which a file is taken for minified.
+const MINIFIED_LINE_LENGTH: usize = 250;
+
+/// Share of whitespace below which a file is taken for minified. Code laid
+/// out by hand is around a fifth whitespace.
+const MINIFIED_WHITESPACE: f64 = 0.05;
+
+/// Why the file `file_name` with `content` looks minified or generated, or
+/// `None` if it looks written by hand.
+pub fn detect(file_name: &str, content: &str) -> Option<&'static str> {
+    if file_name.ends_with(".map") && content.contains("\"mappings\"") {
+        return Some("a source map");
+    }
+    if content
+        .lines()
+        .rev()
+        .take(TRAILER_LINES)
+        .any(|line| line.contains("sourceMappingURL="))
+    {
+        return Some("built output with a source map");
+    }
+    let header = content
+        .lines()
+        .take(HEADER_LINES)
+        .collect::<Vec<_>>()
+        .join("\n")
+        .to_lowercase();
+    if MARKERS.iter().any(|marker| header.contains(marker)) {
+        return Some("marked as generated");
+    }
+    if is_minified(content) {
+        return Some("minified");
+    }
+    None
+}
+
+/// Whether `content` has the long lines or missing whitespace of minified
+/// code.
+fn is_minified(content: &str) -> bool {
+    if content.len() < MINIFIED_MIN_BYTES {
+        return false;
+    }
+    let lines = content.lines().count().max(1);
+    if content.len() / lines > MINIFIED_LINE_LENGTH {
+        return true;
+    }
+    let whitespace = content.bytes().filter(u8::is_ascii_whitespace).count();
+    (whitespace as f64) < content.len() as f64 * MINIFIED_WHITESPACE
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generated_and_minified_files_are_spotted() {
+        let handwritten = "package auth\n\n// Check reports whether the token is valid.\nfunc Check(token string) bool {\n\treturn token != \"\"\n}\n".repeat(20);
+        assert_eq!(detect("auth.go", &handwritten), None);
+
+        let mock = format!(
+            "// Code generated by MockGen. DO NOT EDIT.\n// Source: store.go\n\n{handwritten}"
+        );
+        assert_eq!(detect("mock_store.go", &mock), Some("marked as generated"));
+        let marked = format!("# @generated by tool\n{handwritten}");
+        assert_eq!(detect("schema.py", &marked), Some("marked as generated"));
+        // A marker far down the file is only talking about one
+        let mentioned = format!("{handwritten}// Do not edit this by hand.\n");
+        assert_eq!(detect("auth.go", &mentioned), None);
+
+        let bundle = "var a=function(b,c){return b+c};".repeat(100);
+        assert_eq!(detect("app.min.js", &bundle), Some("minified"));
+        let dense = "a=b+c;d=e*f;g=h-i;j=k/l;\n".repeat(100);
+        assert_eq!(detect("dense.js", &dense), Some("minified"));
+        // A short file of long lines isn't taken for a bundle
+        assert_eq!(detect("short.js", &bundle[..500]), None);
+
+        let built = "function add(a, b) {\n  return a + b;\n}\n//# sourceMappingURL=add.js.map\n";
+        assert_eq!(
+            detect("add.js", built),
+            Some("built output with a source map")
+        );
+        let map = r#"{"version":3,"sources":["add.ts"],"mappings":"AAAA"}"#;
+        assert_eq!(detect("add.js.map", map), Some("a source map"));
+    }
+}
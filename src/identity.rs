@@ -0,0 +1,154 @@
+//! Carries chunk identities across re-indexing of a file.
+//!
+//! When a file changes, each new chunk is paired with the old chunk it most
+//! likely evolved from so it can keep that chunk's store key. Anything keyed
+//! on it (usage counters, ids handed to clients) then survives edits and
+//! symbol renames instead of resetting on every reindex.
+
+use std::collections::HashSet;
+
+use crate::store::StoredChunk;
+
+/// Minimum line overlap (Jaccard over trimmed, non-empty lines) for an
+/// edited chunk to keep its old identity.
+const MIN_SIMILARITY: f32 = 0.5;
+
+/// A freshly chunked piece of a file awaiting an identity.
+pub struct NewChunk<'a> {
+    pub content: &'a str,
+    pub symbol_name: Option<&'a str>,
+}
+
+/// For each of `new`, the key of the old chunk it replaces, if any.
+///
+/// Matching runs in three passes, each only considering chunks not yet
+/// claimed: identical content, then identical symbol name, then the most
+/// similar remaining chunk above `MIN_SIMILARITY`.
+pub fn carry_over(old: &[StoredChunk], new: &[NewChunk<'_>]) -> Vec<Option<u64>> {
+    let mut keys: Vec<Option<u64>> = vec![None; new.len()];
+    let mut claimed = vec![false; old.len()];
+
+    // Unchanged chunks
+    for (slot, chunk) in keys.iter_mut().zip(new) {
+        if let Some(i) = (0..old.len()).find(|&i| !claimed[i] && old[i].content == chunk.content) {
+            claimed[i] = true;
+            *slot = Some(old[i].key);
+        }
+    }
+
+    // Edited bodies under the same name
+    for (slot, chunk) in keys.iter_mut().zip(new) {
+        if slot.is_some() || chunk.symbol_name.is_none() {
+            continue;
+        }
+        if let Some(i) = (0..old.len())
+            .find(|&i| !claimed[i] && old[i].symbol_name.as_deref() == chunk.symbol_name)
+        {
+            claimed[i] = true;
+            *slot = Some(old[i].key);
+        }
+    }
+
+    // Renames and heavier edits: pair by content similarity, best first
+    let old_lines: Vec<HashSet<&str>> = old.iter().map(|c| line_set(&c.content)).collect();
+    let mut candidates: Vec<(f32, usize, usize)> = Vec::new();
+    for (n, chunk) in new.iter().enumerate() {
+        if keys[n].is_some() {
+            continue;
+        }
+        let lines = line_set(chunk.content);
+        for o in (0..old.len()).filter(|&o| !claimed[o]) {
+            let score = jaccard(&lines, &old_lines[o]);
+            if score >= MIN_SIMILARITY {
+                candidates.push((score, n, o));
+            }
+        }
+    }
+    candidates.sort_by(|a, b| b.0.total_cmp(&a.0).then(a.1.cmp(&b.1)).then(a.2.cmp(&b.2)));
+    for (_, n, o) in candidates {
+        if keys[n].is_none() && !claimed[o] {
+            claimed[o] = true;
+            keys[n] = Some(old[o].key);
+        }
+    }
+
+    keys
+}
+
+fn line_set(content: &str) -> HashSet<&str> {
+    content
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty())
+        .collect()
+}
+
+fn jaccard(a: &HashSet<&str>, b: &HashSet<&str>) -> f32 {
+    let union = a.union(b).count();
+    if union == 0 {
+        return 0.0;
+    }
+    a.intersection(b).count() as f32 / union as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn old(key: u64, symbol: &str, content: &str) -> StoredChunk {
+        StoredChunk {
+            key,
+            content: content.to_string(),
+            symbol_name: Some(symbol.to_string()),
+        }
+    }
+
+    fn new<'a>(symbol: &'a str, content: &'a str) -> NewChunk<'a> {
+        NewChunk {
+            content,
+            symbol_name: Some(symbol),
+        }
+    }
+
+    #[test]
+    fn unchanged_chunks_keep_keys_even_when_reordered() {
+        let before = [old(1, "a", "fn a() {}"), old(2, "b", "fn b() {}")];
+        let after = [new("b", "fn b() {}"), new("a", "fn a() {}")];
+        assert_eq!(carry_over(&before, &after), vec![Some(2), Some(1)]);
+    }
+
+    #[test]
+    fn edited_body_keeps_key_by_name() {
+        let before = [old(7, "run", "fn run() {\n    old();\n}")];
+        let after = [new("run", "fn run() {\n    new();\n}")];
+        assert_eq!(carry_over(&before, &after), vec![Some(7)]);
+    }
+
+    #[test]
+    fn renamed_symbol_keeps_key_by_similarity() {
+        let body =
+            "    let x = load();\n    let y = parse(x);\n    validate(&y);\n    store(y);\n}";
+        let before_content = format!("fn process() {{\n{body}");
+        let after_content = format!("fn handle() {{\n{body}");
+        let before = [old(3, "process", &before_content)];
+        let after = [new("handle", &after_content)];
+        assert_eq!(carry_over(&before, &after), vec![Some(3)]);
+    }
+
+    #[test]
+    fn unrelated_new_chunk_gets_fresh_key() {
+        let before = [old(1, "a", "fn a() {\n    one();\n}")];
+        let after = [
+            new("a", "fn a() {\n    one();\n}"),
+            new("z", "fn z() {\n    two();\n}"),
+        ];
+        assert_eq!(carry_over(&before, &after), vec![Some(1), None]);
+    }
+
+    #[test]
+    fn each_old_key_is_used_once() {
+        let before = [old(1, "a", "fn a() {}")];
+        let after = [new("a", "fn a() {}"), new("a", "fn a() {}")];
+        assert_eq!(carry_over(&before, &after), vec![Some(1), None]);
+    }
+}
@@ -0,0 +1,253 @@
+//! `.claudevilignore`: paths kept out of the index, in gitignore syntax.
+//!
+//! Some files belong in the repository but not in search results, such as
+//! legal boilerplate or generated fixtures. Listing them in a
+//! `.claudevilignore` at the indexed root keeps them out without touching
+//! the `.gitignore` everyone else relies on.
+//!
+//! Patterns follow gitignore: `#` comments, `!` to re-include, a trailing
+//! `/` for directories only, a leading or inner `/` to anchor at the root,
+//! and `*`, `?`, `[...]` and `**` wildcards.
+
+use std::path::Path;
+
+/// Name of the ignore file read from the indexed root.
+pub const IGNORE_FILE: &str = ".claudevilignore";
+
+/// One pattern line.
+#[derive(Debug)]
+struct Rule {
+    glob: Vec<char>,
+    /// `!pattern`: re-include what an earlier rule excluded.
+    negated: bool,
+    /// `pattern/`: match directories only.
+    dir_only: bool,
+    /// Matched against the whole relative path rather than the last
+    /// component.
+    anchored: bool,
+}
+
+/// Patterns from a `.claudevilignore`, in file order.
+#[derive(Debug, Default)]
+pub struct IgnoreRules {
+    rules: Vec<Rule>,
+}
+
+impl IgnoreRules {
+    /// Read the `.claudevilignore` in `root`; no rules if there is none. An
+    /// unreadable file is logged and ignored rather than failing the index.
+    pub fn load(root: &Path) -> Self {
+        let path = root.join(IGNORE_FILE);
+        match std::fs::read_to_string(&path) {
+            Ok(text) => Self::parse(&text),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Self::default(),
+            Err(e) => {
+                tracing::warn!(
+                    "can't read {} ({e}); indexing without it -- check its permissions",
+                    path.display()
+                );
+                Self::default()
+            }
+        }
+    }
+
+    pub fn parse(text: &str) -> Self {
+        let rules = text.lines().filter_map(parse_line).collect();
+        Self { rules }
+    }
+
+    /// Whether `rel_path`, relative to the root with `/` separators, is kept
+    /// out of the index. As in git, nothing inside an excluded directory
+    /// can be re-included.
+    pub fn is_ignored(&self, rel_path: &str, is_dir: bool) -> bool {
+        if self.rules.is_empty() {
+            return false;
+        }
+        let path: Vec<char> = rel_path.chars().collect();
+        let in_ignored_dir = path
+            .iter()
+            .enumerate()
+            .filter(|&(_, &c)| c == '/')
+            .any(|(i, _)| self.decide(&path[..i], true));
+        in_ignored_dir || self.decide(&path, is_dir)
+    }
+
+    /// What the last rule matching `path` says, not looking at its parents.
+    fn decide(&self, path: &[char], is_dir: bool) -> bool {
+        let name_start = path
+            .iter()
+            .rposition(|&c| c == '/')
+            .map_or(0, |slash| slash + 1);
+        self.rules
+            .iter()
+            .rev()
+            .find(|rule| {
+                (is_dir || !rule.dir_only)
+                    && if rule.anchored {
+                        glob(&rule.glob, path)
+                    } else {
+                        glob(&rule.glob, &path[name_start..])
+                    }
+            })
+            .is_some_and(|rule| !rule.negated)
+    }
+}
+
+fn parse_line(line: &str) -> Option<Rule> {
+    // Trailing spaces are dropped unless escaped
+    let mut line = line.trim_end_matches(['\r', '\n']);
+    while line.ends_with(' ') && !line.ends_with("\\ ") {
+        line = &line[..line.len() - 1];
+    }
+    if line.is_empty() || line.starts_with('#') {
+        return None;
+    }
+    let (negated, line) = match line.strip_prefix('!') {
+        Some(rest) => (true, rest),
+        None => (
+            false,
+            line.strip_prefix('\\')
+                .filter(|rest| rest.starts_with(['#', '!']))
+                .unwrap_or(line),
+        ),
+    };
+    let (dir_only, line) = match line.strip_suffix('/') {
+        Some(rest) => (true, rest),
+        None => (false, line),
+    };
+    let anchored = line.contains('/');
+    let line = line.strip_prefix('/').unwrap_or(line);
+    if line.is_empty() {
+        return None;
+    }
+    Some(Rule {
+        glob: line.chars().collect(),
+        negated,
+        dir_only,
+        anchored,
+    })
+}
+
+/// Whether `path` matches the gitignore glob `pattern`: `*` and `?` stop at
+/// `/`, `**/` spans any number of directories, and a trailing `/**`
+/// everything below.
+pub(crate) fn glob(pattern: &[char], path: &[char]) -> bool {
+    match pattern {
+        [] => path.is_empty(),
+        ['*', '*', rest @ ..] if matches!(rest, [] | ['/', ..]) => match rest {
+            [] => true,
+            [_, after @ ..] => {
+                glob(after, path)
+                    || path
+                        .iter()
+                        .enumerate()
+                        .any(|(i, &c)| c == '/' && glob(after, &path[i + 1..]))
+            }
+        },
+        ['*', rest @ ..] => (0..=path.len())
+            .take_while(|&i| i == 0 || path[i - 1] != '/')
+            .any(|i| glob(rest, &path[i..])),
+        ['?', rest @ ..] => matches!(path, [c, ..] if *c != '/') && glob(rest, &path[1..]),
+        ['[', class @ ..] => match (class_match(class, path.first().copied()), path) {
+            (Some((true, len)), [_, tail @ ..]) => glob(&class[len..], tail),
+            (Some(_), _) => false,
+            // No closing bracket: a literal `[`
+            (None, ['[', tail @ ..]) => glob(class, tail),
+            (None, _) => false,
+        },
+        ['\\', c, rest @ ..] => path.first() == Some(c) && glob(rest, &path[1..]),
+        [c, rest @ ..] => path.first() == Some(c) && glob(rest, &path[1..]),
+    }
+}
+
+/// Match `c` against the bracket expression starting just after `[`.
+/// Returns whether it matched and how much of `class` the expression took,
+/// through the `]`; `None` if the bracket is never closed.
+fn class_match(class: &[char], c: Option<char>) -> Option<(bool, usize)> {
+    let (negated, start) = match class.first() {
+        Some('!' | '^') => (true, 1),
+        _ => (false, 0),
+    };
+    let mut i = start;
+    let mut found = false;
+    loop {
+        match *class.get(i)? {
+            // `]` first in the set is a member, not the end
+            ']' if i > start => break,
+            low => {
+                if class.get(i + 1) == Some(&'-') && class.get(i + 2).is_some_and(|&h| h != ']') {
+                    let high = class[i + 2];
+                    found |= c.is_some_and(|c| (low..=high).contains(&c));
+                    i += 3;
+                } else {
+                    found |= c == Some(low);
+                    i += 1;
+                }
+            }
+        }
+    }
+    let matched = c.is_some_and(|c| c != '/') && found != negated;
+    Some((matched, i + 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_anchors_and_directories() {
+        let rules = IgnoreRules::parse(
+            "# keep legal text out\n\
+             docs/legal/\n\
+             *.snap\n\
+             /build\n\
+             fixtures/**/*.json\n",
+        );
+        assert!(rules.is_ignored("docs/legal", true));
+        assert!(rules.is_ignored("docs/legal/terms.md", false));
+        assert!(!rules.is_ignored("docs/legal.md", false));
+        assert!(!rules.is_ignored("src/docs/legal/terms.md", false));
+
+        assert!(rules.is_ignored("ui/__snapshots__/button.snap", false));
+        assert!(rules.is_ignored("build/out.go", false));
+        assert!(!rules.is_ignored("cmd/build/main.go", false));
+
+        assert!(rules.is_ignored("fixtures/a.json", false));
+        assert!(rules.is_ignored("fixtures/x/y/a.json", false));
+        assert!(!rules.is_ignored("fixtures/a.go", false));
+    }
+
+    #[test]
+    fn negation_and_excluded_parents() {
+        let rules = IgnoreRules::parse("gen/*\n!gen/keep.go\nvendor/\n!vendor/ours.go\n");
+        assert!(rules.is_ignored("gen/types.go", false));
+        assert!(!rules.is_ignored("gen/keep.go", false));
+        // A file can't be re-included from an excluded directory
+        assert!(rules.is_ignored("vendor/ours.go", false));
+        assert!(!IgnoreRules::default().is_ignored("anything.go", false));
+    }
+
+    #[test]
+    fn wildcards() {
+        let glob = |pattern: &str, path: &str| {
+            let pattern: Vec<char> = pattern.chars().collect();
+            let path: Vec<char> = path.chars().collect();
+            super::glob(&pattern, &path)
+        };
+        assert!(glob("*.go", "main.go"));
+        assert!(!glob("*.go", "cmd/main.go"));
+        assert!(glob("file?.rs", "file1.rs"));
+        assert!(!glob("file?.rs", "file/.rs"));
+        assert!(glob("[a-c]x", "bx"));
+        assert!(!glob("[!a-c]x", "bx"));
+        assert!(glob("[]]", "]"));
+        assert!(glob("a[", "a["));
+        assert!(glob("**/test", "x/y/test"));
+        assert!(glob("**/test", "test"));
+        assert!(glob("out/**", "out/a/b"));
+        assert!(glob("a/**/b", "a/b"));
+        assert!(glob("a/**/b", "a/x/y/b"));
+        assert!(glob("\\*", "*"));
+        assert!(!glob("\\*", "x"));
+    }
+}
@@ -1,17 +1,71 @@
-use std::path::Path;
+//! Walking a directory tree and keeping its chunks in the store up to date.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use std::time::SystemTime;
+use std::time::{Instant, SystemTime};
 
 use walkdir::WalkDir;
 
-use crate::chunker::TreeSitterChunker;
-use crate::config::Config;
-use crate::embed::Embedder;
+use crate::batching::BatchSizer;
+use crate::buildtags;
+use crate::chunker::{FALLBACK_LANGUAGE, TreeSitterChunker};
+use crate::codeowners::{CODEOWNERS_PATHS, CodeOwners};
+use crate::config::{Config, GeneratedFiles, IndexingConfig, Namespace};
+use crate::coverage::{Coverage, Outcome};
+use crate::deps;
+use crate::embed::{Embedder, MAX_SEQ_LEN, blend};
+use crate::encoding::{self, Decoded};
 use crate::error::{Error, Result};
-use crate::store::{ChunkRow, VectorStore};
+use crate::generated;
+use crate::git;
+use crate::identity::{NewChunk, carry_over};
+use crate::ignore::{IGNORE_FILE, IgnoreRules};
+use crate::metrics::metrics;
+use crate::modpath;
+use crate::preprocess::preprocess_code;
+use crate::progress::IndexProgress;
+use crate::store::{COMMIT_KIND, COMMITS_LANGUAGE, COMMITS_PATH, ChunkRow, DEPS_PATH, VectorStore};
+
+/// Commit messages embedded per batch.
+const COMMIT_BATCH_SIZE: usize = 64;
+
+/// Share of a documented chunk's vector taken from its doc comment alone.
+/// The code embedding already contains the comment, so this weights the
+/// documentation above the code it describes.
+const DOC_WEIGHT: f32 = 0.4;
+
+/// Bytes inspected for NUL when deciding whether a file is binary (the same
+/// heuristic git uses).
+const BINARY_SNIFF_LEN: usize = 8000;
+
+/// Times a file that keeps changing while it is indexed is read; the last
+/// reading is stored whether or not the file changed again.
+const MAX_READS: usize = 3;
+
+/// Files listed between log lines while a walk lists files, so a slow walk
+/// of a huge tree shows it is moving.
+const LISTING_LOG_EVERY: usize = 10_000;
+
+/// Outcome of re-indexing a single file with [`Indexer::index_file`].
+#[derive(Debug, PartialEq, Eq)]
+pub enum FileUpdate {
+    /// The file was re-chunked and embedded into this many chunks.
+    Indexed(usize),
+    /// The file no longer exists; its chunks were removed.
+    Removed,
+    /// No configured language handles the file's extension.
+    Unsupported,
+    /// The file was passed over for this reason; its old chunks were removed.
+    Skipped(String),
+}
 
-/// Maximum number of chunks to embed in a single batch.
-const BATCH_SIZE: usize = 64;
+/// A file's text, or why it can't be indexed.
+enum Source {
+    Text(Decoded),
+    Skipped(String),
+}
 
 /// Walks a directory, chunks source files, embeds them, and stores in the vector DB.
 pub struct Indexer {
@@ -19,6 +73,8 @@ pub struct Indexer {
     store: VectorStore,
     chunker: Arc<TreeSitterChunker>,
     config: Config,
+    progress: IndexProgress,
+    batches: BatchSizer,
 }
 
 impl Indexer {
@@ -32,18 +88,76 @@ impl Indexer {
             embedder,
             store,
             chunker,
+            batches: BatchSizer::new(&config.indexing),
             config,
+            progress: IndexProgress::default(),
         }
     }
 
-    /// Index all supported files under `root`.
+    /// Report files done and failures to `progress` instead of a private
+    /// handle, so the server can show them.
+    pub fn with_progress(mut self, progress: IndexProgress) -> Self {
+        self.progress = progress;
+        self
+    }
+
+    pub fn progress(&self) -> &IndexProgress {
+        &self.progress
+    }
+
+    /// Index all supported files under `root`, giving chunks the owners
+    /// its `CODEOWNERS` assigns.
+    #[tracing::instrument(level = "debug", skip(self))]
     pub async fn index_directory(&self, root: &Path) -> Result<()> {
-        let mut pending_rows: Vec<PendingChunk> = Vec::new();
+        self.progress.start();
+        self.store.set_code_owners(CodeOwners::load(root)).await?;
+        let Walk { skipped, aliases } = self.index_tree(root, "").await?;
+        self.store.set_aliases(aliases).await?;
 
-        for entry in WalkDir::new(root)
+        let count = self.store.chunk_count().await?;
+        tracing::info!(
+            "indexing complete: {count} chunks stored, {} files skipped",
+            skipped.len()
+        );
+        self.store.set_skipped(skipped).await?;
+
+        let stats = self.store.token_stats().await?;
+        if stats.truncated > 0 {
+            tracing::warn!(
+                "{} of {} chunks exceed the {MAX_SEQ_LEN}-token embedding window and were \
+                 truncated -- consider split_children for large container nodes",
+                stats.truncated,
+                stats.measured
+            );
+        }
+        Ok(())
+    }
+
+    /// Chunk, embed, and store every supported file under `dir`, storing
+    /// each under `prefix` followed by its path relative to `dir`. A file
+    /// reached by several paths through symlinks is indexed once. Returns
+    /// the files passed over, with the reason, and the paths folded away.
+    async fn index_tree(&self, dir: &Path, prefix: &str) -> Result<Walk> {
+        let mut queue = Queue::default();
+        let mut skipped = BTreeMap::new();
+        let excluded = self.config.excluded_dirs();
+        let ignored = IgnoreRules::load(dir);
+
+        // Listed up front so progress can be reported against the total
+        let mut files = Vec::new();
+        let mut limits = WalkLimits::new(&self.config.indexing);
+        let max_depth = self.config.indexing.max_depth;
+        let mut too_deep = false;
+        for entry in WalkDir::new(dir)
             .follow_links(true)
             .into_iter()
-            .filter_entry(|e| !is_hidden(e))
+            .filter_entry(|e| {
+                if max_depth > 0 && e.depth() > max_depth && e.file_type().is_dir() {
+                    too_deep = true;
+                    return false;
+                }
+                !is_hidden(e) && !is_excluded(e, &excluded) && !is_ignored(e, dir, &ignored)
+            })
         {
             let entry = match entry {
                 Ok(e) => e,
@@ -57,61 +171,580 @@ impl Indexer {
                 continue;
             }
 
-            let path = entry.path();
-            let ext = match path.extension().and_then(|e| e.to_str()) {
-                Some(e) => e,
+            // Dependencies are indexed for their code alone
+            let lang_name = match self.language_for(entry.path()) {
+                Some(FALLBACK_LANGUAGE) if !prefix.is_empty() => continue,
+                Some(lang_name) => lang_name,
                 None => continue,
             };
+            let len = entry
+                .metadata()
+                .map_or(0, |m| m.len())
+                .min(self.config.indexing.max_file_size);
+            if let Some(limit) = limits.admit(len) {
+                let message = format!(
+                    "stopped listing files under {} after {} ({limit}); the rest are not \
+                     indexed -- check that this is the directory meant to be indexed, or \
+                     raise the limit",
+                    dir.display(),
+                    files.len(),
+                );
+                tracing::warn!("{message}");
+                self.progress.record_error(None, message);
+                break;
+            }
+            files.push((entry.into_path(), lang_name));
+            if files.len().is_multiple_of(LISTING_LOG_EVERY) {
+                tracing::info!(
+                    "listed {} files under {} so far",
+                    files.len(),
+                    dir.display()
+                );
+            }
+        }
+        if too_deep {
+            let message = format!(
+                "directories more than {max_depth} levels below {} were not walked \
+                 ([indexing] max_depth) -- raise it if they hold code to index",
+                dir.display()
+            );
+            tracing::warn!("{message}");
+            self.progress.record_error(None, message);
+        }
+        let (files, aliases) = fold_aliases(files, dir, prefix);
+        if !aliases.is_empty() {
+            tracing::info!(
+                "{} paths under {} lead through symlinks to files indexed under another path",
+                aliases.values().map(Vec::len).sum::<usize>(),
+                dir.display()
+            );
+        }
+        self.progress.add_files(files.len());
 
-            let (lang_name, _lang_config) = match self.config.language_for_extension(ext) {
-                Some(pair) => pair,
-                None => continue,
-            };
+        for (path, lang_name) in files {
+            let rel_path = format!("{prefix}{}", relative_path(&path, dir));
+            self.read_into(&mut queue, &mut skipped, path, rel_path, lang_name, 1)
+                .await?;
+            self.progress.file_done();
 
-            match self.collect_file_chunks(path, root, lang_name).await {
-                Ok(chunks) => pending_rows.extend(chunks),
-                Err(e) => {
-                    tracing::warn!("failed to chunk {}: {e}", path.display());
-                    continue;
+            // Flush in batches to keep memory bounded
+            self.flush(&mut queue, false).await?;
+        }
+        self.flush(&mut queue, true).await?;
+
+        // Files edited while they were being indexed are read again
+        while !queue.changed.is_empty() {
+            for file in std::mem::take(&mut queue.changed) {
+                self.read_into(
+                    &mut queue,
+                    &mut skipped,
+                    file.path,
+                    file.rel_path,
+                    &file.lang_name,
+                    file.reads + 1,
+                )
+                .await?;
+            }
+            self.flush(&mut queue, true).await?;
+        }
+        Ok(Walk { skipped, aliases })
+    }
+
+    /// Read, chunk, and queue the file at `path`, stored as `rel_path`, for
+    /// the `reads`th time. A file passed over is recorded in `skipped`, with
+    /// the reason, and one that fails to chunk is reported and left alone.
+    async fn read_into(
+        &self,
+        queue: &mut Queue,
+        skipped: &mut BTreeMap<String, String>,
+        path: PathBuf,
+        rel_path: String,
+        lang_name: &str,
+        reads: usize,
+    ) -> Result<()> {
+        let stamp = if reads < MAX_READS {
+            Stamp::of(&path)
+        } else {
+            None
+        };
+        let result = match read_source(&path, &self.config.indexing).await {
+            Ok(Source::Text(source)) => {
+                self.collect_file_chunks(&path, rel_path.clone(), lang_name, &source)
+                    .await
+            }
+            Ok(Source::Skipped(reason)) => {
+                self.store.delete_file(&rel_path).await?;
+                // Most files of no known language are images and the like
+                if lang_name != FALLBACK_LANGUAGE {
+                    tracing::info!("skipping {rel_path}: {reason}");
+                    skipped.insert(rel_path, reason);
                 }
+                return Ok(());
+            }
+            Err(e) => Err(e),
+        };
+        match result {
+            Ok(chunks) if self.store_moved(&rel_path, &chunks).await? => {}
+            Ok(chunks) => queue.push_file(
+                QueuedFile {
+                    path,
+                    rel_path,
+                    lang_name: lang_name.to_string(),
+                    stamp,
+                    reads,
+                    chunks: 0,
+                },
+                chunks,
+            ),
+            Err(e) => {
+                tracing::warn!("failed to chunk {}: {e}", path.display());
+                metrics().record_index_error();
+                self.progress.record_error(Some(&rel_path), &e);
             }
+        }
+        Ok(())
+    }
 
-            // Flush in batches to keep memory bounded
-            if pending_rows.len() >= BATCH_SIZE {
-                self.flush_batch(&mut pending_rows).await?;
+    /// Bring the index for `root` up to date the configured way: from git
+    /// when `[indexing] sync_with_git` is set, otherwise by a full walk.
+    /// Chunks an earlier embedding model embedded are then embedded again.
+    /// A rebuild for a changed metric or dimension count goes first.
+    pub async fn update(&self, root: &Path) -> Result<()> {
+        self.rebuild_vectors().await?;
+        if self.config.indexing.sync_with_git {
+            self.sync_with_git(root).await?;
+        } else {
+            self.index_directory(root).await?;
+        }
+        self.migrate_embeddings(root).await?;
+        self.index_commits(root).await?;
+        self.index_dependencies(root).await
+    }
+
+    /// Embed again, from their stored text, the chunks a rebuild for a new
+    /// metric or dimension count left without vectors. Each batch is
+    /// searchable as soon as it is stored.
+    async fn rebuild_vectors(&self) -> Result<()> {
+        let Some(status) = self.store.rebuild_status().await else {
+            return Ok(());
+        };
+        tracing::info!(
+            "rebuilding the index ({}): embedding {} chunks again",
+            status.reason,
+            status.remaining
+        );
+        loop {
+            let batch = self.store.rebuild_batch(self.batches.size()).await;
+            if batch.is_empty() {
+                return Ok(());
             }
+            let keys: Vec<u64> = batch.iter().map(|c| c.key).collect();
+            let docs: Vec<Option<String>> = batch.iter().map(|c| c.doc.clone()).collect();
+            let texts = batch
+                .into_iter()
+                .map(|chunk| match chunk.namespace {
+                    Namespace::Notes => chunk.content,
+                    // Commits are embedded by message, without the author
+                    // and date stored after it
+                    Namespace::Commits => match chunk.content.rsplit_once("\n\n") {
+                        Some((message, _)) => message.to_string(),
+                        None => chunk.content,
+                    },
+                    Namespace::Code | Namespace::Docs | Namespace::Dependencies => {
+                        preprocess_code(&chunk.content)
+                    }
+                })
+                .collect();
+            let embeddings = self.embed_documented(texts, &docs).await?;
+            self.store
+                .replace_vectors(keys.into_iter().zip(embeddings).collect())
+                .await?;
         }
+    }
 
-        // Flush remaining
-        if !pending_rows.is_empty() {
-            self.flush_batch(&mut pending_rows).await?;
+    /// Move the chunks the store reports as embedded by an earlier model to
+    /// the current one: their files are re-indexed, notes embedded again
+    /// in place, and commits and dependencies removed for
+    /// [`index_commits`](Self::index_commits) and
+    /// [`index_dependencies`](Self::index_dependencies) to add back.
+    async fn migrate_embeddings(&self, root: &Path) -> Result<()> {
+        let outdated = self.store.outdated_chunks().await;
+        if outdated.is_empty() {
+            return Ok(());
         }
+        tracing::info!(
+            "re-embedding {} chunks embedded by an earlier model",
+            outdated.len()
+        );
 
-        let count = self.store.chunk_count().await?;
-        tracing::info!("indexing complete: {count} chunks stored");
+        let mut files = BTreeSet::new();
+        let mut notes = Vec::new();
+        let mut gone = Vec::new();
+        for chunk in &outdated {
+            match chunk.namespace {
+                Namespace::Code | Namespace::Docs => {
+                    files.insert(chunk.file_path.as_str());
+                }
+                Namespace::Commits => gone.push(chunk.key),
+                Namespace::Notes => notes.extend(chunk.note.clone().map(|text| (chunk.key, text))),
+                Namespace::Dependencies => {}
+            }
+        }
+        // A dependency is only indexed again once all of it is gone
+        let keys: HashSet<u64> = outdated.iter().map(|c| c.key).collect();
+        for dep_keys in self.store.dependencies().await.into_values() {
+            if dep_keys.iter().any(|key| keys.contains(key)) {
+                gone.extend(dep_keys);
+            }
+        }
+        self.store.delete_chunks(&gone).await?;
+
+        for rel_path in files {
+            if let Err(e) = self.index_file(&root.join(rel_path), root).await {
+                tracing::warn!("failed to re-embed {rel_path}: {e}");
+                metrics().record_index_error();
+            }
+        }
+        for batch in notes.chunks(COMMIT_BATCH_SIZE) {
+            let texts: Vec<String> = batch.iter().map(|(_, text)| text.clone()).collect();
+            let embeddings = self.embedder.embed_passages(texts).await?;
+            let vectors = batch.iter().map(|(key, _)| *key).zip(embeddings).collect();
+            self.store.replace_vectors(vectors).await?;
+        }
+        Ok(())
+    }
+
+    /// Index the sources of the direct dependencies of the project at
+    /// `root` when `[dependencies] index` is set, each under
+    /// `<deps>/name@version/`. A dependency version never changes once
+    /// downloaded, so only new ones are indexed; ones the project no longer
+    /// uses, or that the config now leaves out, are removed.
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub async fn index_dependencies(&self, root: &Path) -> Result<()> {
+        self.index_dependencies_from(root, &deps::Caches::from_env())
+            .await
+    }
+
+    async fn index_dependencies_from(&self, root: &Path, caches: &deps::Caches) -> Result<()> {
+        let config = &self.config.dependencies;
+        let wanted = if config.index {
+            deps::discover(root, config, caches)
+        } else {
+            Vec::new()
+        };
+
+        let mut indexed = self.store.dependencies().await;
+        let mut added = 0;
+        for dep in &wanted {
+            if indexed.remove(&dep.id()).is_some() {
+                continue;
+            }
+            tracing::info!("indexing dependency {}", dep.id());
+            self.index_tree(&dep.dir, &dep.path_prefix()).await?;
+            added += 1;
+        }
+        let removed: Vec<u64> = indexed.values().flatten().copied().collect();
+        self.store.delete_chunks(&removed).await?;
+        if added > 0 || !indexed.is_empty() {
+            tracing::info!(
+                "dependencies: {added} indexed, {} removed, {} in the index",
+                indexed.len(),
+                wanted.len()
+            );
+        }
+        Ok(())
+    }
+
+    /// Index the messages of the `[indexing] commit_messages` most recent
+    /// commits as `commit` chunks. Only commits not indexed yet are
+    /// embedded; ones that fell out of the window, or out of history, are
+    /// removed, all of them once the setting is 0. Does nothing outside a
+    /// git checkout.
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub async fn index_commits(&self, root: &Path) -> Result<()> {
+        if git::head(root).is_none() {
+            return Ok(());
+        }
+        // Turned off: still clear out what earlier runs indexed
+        let commits = match self.config.indexing.commit_messages {
+            0 => Vec::new(),
+            limit => git::log(root, limit)?,
+        };
+
+        let indexed = self.store.file_chunks(COMMITS_PATH).await?;
+        let hashes: Vec<&str> = commits.iter().map(|c| c.hash.as_str()).collect();
+        let gone: Vec<u64> = indexed
+            .iter()
+            .filter(|c| {
+                !c.symbol_name
+                    .as_deref()
+                    .is_some_and(|h| hashes.contains(&h))
+            })
+            .map(|c| c.key)
+            .collect();
+        // Old commits leave as new ones arrive, in one step
+        let mut transaction = self.store.transaction();
+        transaction.delete_chunks(&gone);
+
+        let known: Vec<&str> = indexed
+            .iter()
+            .filter_map(|c| c.symbol_name.as_deref())
+            .collect();
+        let new: Vec<&git::Commit> = commits
+            .iter()
+            .filter(|c| !known.contains(&c.hash.as_str()))
+            .collect();
+        for batch in new.chunks(COMMIT_BATCH_SIZE) {
+            let texts: Vec<String> = batch.iter().map(|c| c.message.clone()).collect();
+            let (token_counts, embeddings) = tokio::try_join!(
+                self.embedder.count_tokens(texts.clone()),
+                self.embedder.embed_passages(texts)
+            )?;
+            let rows = batch
+                .iter()
+                .zip(token_counts)
+                .zip(embeddings)
+                .map(|((commit, token_count), vector)| {
+                    let content =
+                        format!("{}\n\n{}, {}", commit.message, commit.author, commit.date);
+                    ChunkRow {
+                        file_path: COMMITS_PATH.to_string(),
+                        end_line: content.lines().count() as i64,
+                        content,
+                        symbol_name: Some(commit.hash.clone()),
+                        symbol_kind: Some(COMMIT_KIND.to_string()),
+                        language: COMMITS_LANGUAGE.to_string(),
+                        start_line: 1,
+                        last_modified: commit.time,
+                        token_count: Some(token_count),
+                        comment_ratio: 1.0,
+                        namespace: Namespace::Commits,
+                        vector,
+                        ..Default::default()
+                    }
+                })
+                .collect();
+            transaction.insert(rows);
+        }
+        transaction.commit().await?;
+        tracing::info!(
+            "commit messages: {} indexed, {} removed",
+            new.len(),
+            gone.len()
+        );
+        Ok(())
+    }
+
+    /// Re-index one file under `root` right away, so it is searchable as
+    /// soon as this returns.
+    #[tracing::instrument(level = "debug", skip(self, root))]
+    pub async fn index_file(&self, path: &Path, root: &Path) -> Result<FileUpdate> {
+        let mut rel_path = relative_path(path, root);
+        // An edit through a symlink changes the file indexed under its target
+        let path = match self.store.alias_target(&rel_path).await {
+            Some(target) => {
+                let path = root.join(&target);
+                rel_path = target;
+                path
+            }
+            None => path.to_path_buf(),
+        };
+        let path = path.as_path();
+        if CODEOWNERS_PATHS.contains(&rel_path.as_str()) {
+            self.store.set_code_owners(CodeOwners::load(root)).await?;
+        }
+        if !path.exists() {
+            self.store.delete_file(&rel_path).await?;
+            return Ok(FileUpdate::Removed);
+        }
+
+        if IgnoreRules::load(root).is_ignored(&rel_path, false) {
+            self.store.delete_file(&rel_path).await?;
+            return Ok(FileUpdate::Skipped(format!("listed in {IGNORE_FILE}")));
+        }
+
+        let Some(lang_name) = self.language_for(path) else {
+            return Ok(FileUpdate::Unsupported);
+        };
+
+        let mut queue = Queue::default();
+        for reads in 1..=MAX_READS {
+            let stamp = if reads < MAX_READS {
+                Stamp::of(path)
+            } else {
+                None
+            };
+            let source = match read_source(path, &self.config.indexing).await? {
+                Source::Text(source) => source,
+                Source::Skipped(reason) => {
+                    self.store.delete_file(&rel_path).await?;
+                    return Ok(FileUpdate::Skipped(reason));
+                }
+            };
+            let chunks = self
+                .collect_file_chunks(path, rel_path.clone(), lang_name, &source)
+                .await?;
+            let count = chunks.len();
+            if self.store_moved(&rel_path, &chunks).await? {
+                return Ok(FileUpdate::Indexed(count));
+            }
+            queue.push_file(
+                QueuedFile {
+                    path: path.to_path_buf(),
+                    rel_path: rel_path.clone(),
+                    lang_name: lang_name.to_string(),
+                    stamp,
+                    reads,
+                    chunks: 0,
+                },
+                chunks,
+            );
+            self.flush(&mut queue, true).await?;
+            if queue.changed.is_empty() {
+                return Ok(FileUpdate::Indexed(count));
+            }
+            queue.changed.clear();
+        }
+        Err(Error::StoreIndex(format!(
+            "{rel_path} kept changing while it was indexed -- refresh it once edits settle"
+        )))
+    }
+
+    /// Bring the index up to date with `HEAD`, re-indexing only the files
+    /// changed since the last indexed commit. Falls back to a full
+    /// [`Indexer::index_directory`] when `root` isn't a git checkout or
+    /// there is no previous commit to diff against (e.g. after a history
+    /// rewrite).
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub async fn sync_with_git(&self, root: &Path) -> Result<()> {
+        let Some(head) = git::head(root) else {
+            tracing::info!(
+                "{} is not a git checkout; indexing every file",
+                root.display()
+            );
+            return self.index_directory(root).await;
+        };
+        self.store.set_code_owners(CodeOwners::load(root)).await?;
+
+        let changed = match self.store.last_commit().await {
+            Some(last) => git::changed_files(root, &last)
+                .inspect_err(|e| {
+                    tracing::warn!(
+                        "can't diff against last indexed commit {last} ({e}); indexing every file"
+                    );
+                })
+                .ok(),
+            None => None,
+        };
+        let Some(changed) = changed else {
+            self.index_directory(root).await?;
+            return self.store.set_last_commit(&head).await;
+        };
+
+        let excluded = self.config.excluded_dirs();
+        let ignored = IgnoreRules::load(root);
+        let mut skipped = self.store.skipped_files().await;
+        let changed_files: Vec<&String> = changed
+            .iter()
+            .filter(|p| {
+                !is_hidden_path(p)
+                    && !is_excluded_path(p, &excluded)
+                    && !ignored.is_ignored(p, false)
+            })
+            .collect();
+        // Removed files first, so files moved elsewhere are found again
+        // instead of embedded anew
+        let (removed, present): (Vec<&String>, Vec<&String>) = changed_files
+            .into_iter()
+            .partition(|p| !root.join(p).exists());
+        self.progress.start();
+        self.progress.add_files(removed.len() + present.len());
+        for rel_path in removed.into_iter().chain(present) {
+            skipped.remove(rel_path);
+            let result = self.index_file(&root.join(rel_path), root).await;
+            self.progress.file_done();
+            match result {
+                Ok(FileUpdate::Skipped(reason)) => {
+                    skipped.insert(rel_path.clone(), reason);
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    tracing::warn!("failed to index {rel_path}: {e}");
+                    metrics().record_index_error();
+                    self.progress.record_error(Some(rel_path), &e);
+                }
+            }
+        }
+        self.store.set_skipped(skipped).await?;
+        self.store.set_last_commit(&head).await?;
+        tracing::info!(
+            "synced index to {head}: {} files changed since the last indexed commit",
+            changed.len()
+        );
         Ok(())
     }
 
-    /// Read and chunk a single file, returning pending chunks (not yet embedded).
+    /// What indexing does with each file under `root`, walked as
+    /// [`Indexer::index_directory`] walks it, by extension. Files are
+    /// counted as skipped when the last index run passed over them.
+    pub async fn coverage(&self, root: &Path) -> Coverage {
+        let skipped = self.store.skipped_files().await;
+        let excluded = self.config.excluded_dirs();
+        let ignored = IgnoreRules::load(root);
+        let max_depth = self.config.indexing.max_depth;
+        let mut coverage = Coverage::default();
+        for entry in WalkDir::new(root)
+            .follow_links(true)
+            .into_iter()
+            .filter_entry(|e| {
+                let too_deep = max_depth > 0 && e.depth() > max_depth && e.file_type().is_dir();
+                !too_deep
+                    && !is_hidden(e)
+                    && !is_excluded(e, &excluded)
+                    && !is_ignored(e, root, &ignored)
+            })
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.file_type().is_file())
+        {
+            let bytes = entry.metadata().map_or(0, |m| m.len());
+            let outcome = match self.language_for(entry.path()) {
+                None => Outcome::Unsupported,
+                Some(_) if skipped.contains_key(&relative_path(entry.path(), root)) => {
+                    Outcome::Skipped
+                }
+                Some(lang_name) => Outcome::Indexed(lang_name.to_string()),
+            };
+            coverage.add(entry.path(), bytes, outcome);
+        }
+        coverage
+    }
+
+    /// The language `path` is indexed as: the one configured for its
+    /// extension or, under `[indexing] fallback_chunking`,
+    /// [`FALLBACK_LANGUAGE`]. `None` leaves it out of the index.
+    fn language_for(&self, path: &Path) -> Option<&str> {
+        path.extension()
+            .and_then(|e| e.to_str())
+            .and_then(|ext| self.config.language_for_extension(ext))
+            .map(|(name, _)| name)
+            .or_else(|| {
+                self.config
+                    .indexing
+                    .fallback_chunking
+                    .then_some(FALLBACK_LANGUAGE)
+            })
+    }
+
+    /// Chunk a single file's `content` to be stored as `rel_path`, returning
+    /// pending chunks (not yet embedded).
     async fn collect_file_chunks(
         &self,
         path: &Path,
-        root: &Path,
+        rel_path: String,
         lang_name: &str,
+        source: &Decoded,
     ) -> Result<Vec<PendingChunk>> {
-        let content = tokio::fs::read_to_string(path)
-            .await
-            .map_err(|e| Error::FileRead {
-                path: path.to_path_buf(),
-                source: e,
-            })?;
-
-        let rel_path = path
-            .strip_prefix(root)
-            .unwrap_or(path)
-            .to_string_lossy()
-            .to_string();
-
+        let content = source.text.as_str();
         let last_modified = path
             .metadata()
             .and_then(|m| m.modified())
@@ -120,71 +753,462 @@ impl Indexer {
             .unwrap_or_default()
             .as_secs() as i64;
 
-        // Delete existing chunks for this file before re-indexing
-        self.store.delete_file(&rel_path).await?;
-
-        let chunks = self.chunker.chunk_file(&content, lang_name)?;
+        let namespace = if rel_path.starts_with(DEPS_PATH) {
+            Namespace::Dependencies
+        } else if self.config.lang.get(lang_name).is_some_and(|l| l.docs) {
+            Namespace::Docs
+        } else {
+            Namespace::Code
+        };
+        let chunks = self.chunker.chunk_file(content, lang_name)?;
         tracing::debug!("{}: {} chunks ({})", rel_path, chunks.len(), lang_name);
+        let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+        let file_build = buildtags::file_constraint(lang_name, &file_name, content);
+        let generated = self.config.indexing.generated == GeneratedFiles::Demote
+            && generated::detect(&file_name, content).is_some();
+        let module = modpath::module_path(path, &rel_path, lang_name);
+
+        // Match new chunks to the ones they replace so identities survive
+        // edits; the old versions are replaced once the new are embedded
+        let old = self.store.file_chunks(&rel_path).await?;
+        let new: Vec<NewChunk<'_>> = chunks
+            .iter()
+            .map(|c| NewChunk {
+                content: &c.content,
+                symbol_name: c.symbol_name.as_deref(),
+            })
+            .collect();
+        let keys = carry_over(&old, &new);
 
         Ok(chunks
             .into_iter()
+            .zip(keys)
             .enumerate()
-            .map(|(idx, chunk)| PendingChunk {
+            .map(|(idx, (chunk, key))| PendingChunk {
+                key,
                 file_path: rel_path.clone(),
                 chunk_id: idx as i64,
                 content: chunk.content,
                 symbol_name: chunk.symbol_name,
                 symbol_kind: chunk.symbol_kind,
-                language: lang_name.to_string(),
+                doc: chunk.doc,
+                signature: chunk.signature,
+                build: buildtags::combine(lang_name, file_build.clone(), chunk.build),
+                module: module.clone(),
+                language: chunk.language.unwrap_or_else(|| lang_name.to_string()),
                 start_line: chunk.start_line as i64,
                 end_line: chunk.end_line as i64,
                 last_modified,
+                comment_ratio: chunk.comment_ratio,
+                span: chunk.byte_range,
+                encoding: source.encoding,
+                generated,
+                namespace,
             })
             .collect())
     }
 
-    /// Embed a batch of pending chunks and insert into the store.
-    async fn flush_batch(&self, pending: &mut Vec<PendingChunk>) -> Result<()> {
-        let batch: Vec<PendingChunk> = std::mem::take(pending);
+    /// Store `chunks`, those of a file new to the index at `rel_path`, with
+    /// the keys and vectors of a file just removed whose chunks they match,
+    /// as when the file was moved. Returns whether there was one; if not,
+    /// the chunks are left to embed.
+    async fn store_moved(&self, rel_path: &str, chunks: &[PendingChunk]) -> Result<bool> {
+        if chunks.is_empty() || chunks.iter().any(|c| c.key.is_some()) {
+            return Ok(false);
+        }
+        let mut rows: Vec<ChunkRow> = chunks
+            .iter()
+            .map(|c| c.clone().into_row(None, Vec::new()))
+            .collect();
+        let Some(from) = self.store.take_moved(&mut rows).await else {
+            return Ok(false);
+        };
+        tracing::info!(
+            "{rel_path}: moved from {from}, keeping its {} chunks' vectors",
+            rows.len()
+        );
+        self.store.replace_file(rel_path, rows).await?;
+        Ok(true)
+    }
+
+    /// Embed the queued chunks in batches of the tuned size, keeping back
+    /// a last partial batch unless `all`, and store each file whose chunks
+    /// are all embedded.
+    async fn flush(&self, queue: &mut Queue, all: bool) -> Result<()> {
+        loop {
+            let size = self.batches.size();
+            let pending = queue.pending.len();
+            if pending == 0 || (pending < size && !all) {
+                break;
+            }
+            let rest = queue.pending.split_off(size.min(pending));
+            let batch = std::mem::replace(&mut queue.pending, rest);
+            let rows = self.embed_batch(batch).await?;
+            queue.embedded.extend(rows);
+            self.store_embedded(queue).await?;
+        }
+        self.store_embedded(queue).await
+    }
+
+    /// Store the files at the front of `queue` whose chunks are all
+    /// embedded, replacing their old chunks in one transaction. A file that
+    /// changed since it was read is moved to `queue.changed` instead.
+    async fn store_embedded(&self, queue: &mut Queue) -> Result<()> {
+        let mut transaction = self.store.transaction();
+        while queue
+            .files
+            .front()
+            .is_some_and(|file| file.chunks <= queue.embedded.len())
+        {
+            let Some(file) = queue.files.pop_front() else {
+                break;
+            };
+            let rows: Vec<ChunkRow> = queue.embedded.drain(..file.chunks).collect();
+            if let Some(stamp) = file.stamp
+                && Stamp::of(&file.path) != Some(stamp)
+            {
+                tracing::debug!(
+                    "{} changed while it was indexed; reading it again",
+                    file.rel_path
+                );
+                queue.changed.push(file);
+                continue;
+            }
+            transaction.replace_file(&file.rel_path, rows);
+        }
+        transaction.commit().await?;
+        Ok(())
+    }
+
+    /// Embed `texts` as passages, each blended with the embedding of its
+    /// entry in `docs`, if any.
+    async fn embed_documented(
+        &self,
+        mut texts: Vec<String>,
+        docs: &[Option<String>],
+    ) -> Result<Vec<Vec<f32>>> {
+        // Docs are embedded on their own as well, in the same batch, so
+        // documentation-style queries can match them directly
+        let count = texts.len();
+        let documented: Vec<usize> = (0..count).filter(|&i| docs[i].is_some()).collect();
+        texts.extend(documented.iter().filter_map(|&i| docs[i].clone()));
+        let mut embeddings = self.embedder.embed_passages(texts).await?;
+        let doc_embeddings = embeddings.split_off(count);
+        for (&i, doc_vec) in documented.iter().zip(&doc_embeddings) {
+            embeddings[i] = blend(&embeddings[i], doc_vec, DOC_WEIGHT);
+        }
+        Ok(embeddings)
+    }
+
+    /// Embed a batch of pending chunks into rows ready to store.
+    #[tracing::instrument(level = "debug", skip_all, fields(chunks = batch.len()))]
+    async fn embed_batch(&self, batch: Vec<PendingChunk>) -> Result<Vec<ChunkRow>> {
         if batch.is_empty() {
-            return Ok(());
+            return Ok(Vec::new());
         }
 
-        let texts: Vec<String> = batch.iter().map(|c| c.content.clone()).collect();
-        let embeddings = self.embedder.embed_batch(texts).await?;
+        let started = Instant::now();
+        let chunks = batch.len();
+        // Copies of a chunk in the same batch are embedded once; the store
+        // keeps one vector for them anyway
+        let mut distinct: HashMap<(&str, Option<&str>), usize> = HashMap::new();
+        let mut texts = Vec::new();
+        let mut docs = Vec::new();
+        let slots: Vec<usize> = batch
+            .iter()
+            .map(|c| {
+                *distinct
+                    .entry((c.content.as_str(), c.doc.as_deref()))
+                    .or_insert_with(|| {
+                        texts.push(preprocess_code(&c.content));
+                        docs.push(c.doc.clone());
+                        texts.len() - 1
+                    })
+            })
+            .collect();
+        // Tokenizing a batch takes a while; it runs beside the embedding
+        let (token_counts, embeddings) = tokio::try_join!(
+            self.embedder.count_tokens(texts.clone()),
+            self.embed_documented(texts, &docs)
+        )?;
+        let (token_counts, embeddings): (Vec<usize>, Vec<Vec<f32>>) = slots
+            .iter()
+            .map(|&slot| (token_counts[slot], embeddings[slot].clone()))
+            .unzip();
 
         let rows: Vec<ChunkRow> = batch
             .into_iter()
+            .zip(token_counts)
             .zip(embeddings)
-            .map(|(chunk, vector)| ChunkRow {
-                file_path: chunk.file_path,
-                chunk_id: chunk.chunk_id,
-                content: chunk.content,
-                symbol_name: chunk.symbol_name,
-                symbol_kind: chunk.symbol_kind,
-                language: chunk.language,
-                start_line: chunk.start_line,
-                end_line: chunk.end_line,
-                last_modified: chunk.last_modified,
-                vector,
+            .map(|((chunk, token_count), vector)| {
+                if token_count > MAX_SEQ_LEN {
+                    tracing::debug!(
+                        "{}:{}: {token_count} tokens, truncated to {MAX_SEQ_LEN}",
+                        chunk.file_path,
+                        chunk.start_line
+                    );
+                }
+                chunk.into_row(Some(token_count), vector)
             })
             .collect();
 
-        self.store.insert(rows).await?;
-        Ok(())
+        let elapsed = started.elapsed();
+        metrics().observe_batch_flush(elapsed);
+        self.batches.observe(chunks, elapsed);
+        Ok(rows)
+    }
+}
+
+/// How many files, and how many bytes of them, one walk may list, from
+/// `[indexing] max_files` and `max_total_bytes`; `0` is no limit.
+struct WalkLimits {
+    max_files: usize,
+    max_total_bytes: u64,
+    files: usize,
+    bytes: u64,
+}
+
+impl WalkLimits {
+    fn new(config: &IndexingConfig) -> Self {
+        Self {
+            max_files: config.max_files,
+            max_total_bytes: config.max_total_bytes,
+            files: 0,
+            bytes: 0,
+        }
     }
+
+    /// Count one more file of `len` bytes. If that would pass a limit, it
+    /// isn't counted, and the limit is returned for the walk to stop at.
+    fn admit(&mut self, len: u64) -> Option<String> {
+        if self.max_files > 0 && self.files >= self.max_files {
+            return Some(format!("[indexing] max_files is {}", self.max_files));
+        }
+        if self.max_total_bytes > 0 && self.bytes + len > self.max_total_bytes {
+            return Some(format!(
+                "[indexing] max_total_bytes is {}",
+                self.max_total_bytes
+            ));
+        }
+        self.files += 1;
+        self.bytes += len;
+        None
+    }
+}
+
+/// A file's size and modification time when it was read; one that no
+/// longer matches was edited while it was being indexed.
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct Stamp {
+    len: u64,
+    modified: Option<SystemTime>,
 }
 
+impl Stamp {
+    fn of(path: &Path) -> Option<Self> {
+        let meta = std::fs::metadata(path).ok()?;
+        Some(Self {
+            len: meta.len(),
+            modified: meta.modified().ok(),
+        })
+    }
+}
+
+/// A file whose chunks are queued for embedding.
+struct QueuedFile {
+    path: PathBuf,
+    rel_path: String,
+    lang_name: String,
+    /// How the file looked before it was read; `None` stores it unchecked.
+    stamp: Option<Stamp>,
+    /// Times the file has been read in this run.
+    reads: usize,
+    chunks: usize,
+}
+
+/// Chunks waiting to be embedded, and embedded ones waiting for the rest
+/// of their file's, both in file order. A file's chunks are stored
+/// together, once all of them are embedded.
+#[derive(Default)]
+struct Queue {
+    pending: Vec<PendingChunk>,
+    embedded: Vec<ChunkRow>,
+    files: VecDeque<QueuedFile>,
+    /// Files edited after they were read, to be read again.
+    changed: Vec<QueuedFile>,
+}
+
+impl Queue {
+    fn push_file(&mut self, mut file: QueuedFile, chunks: Vec<PendingChunk>) {
+        file.chunks = chunks.len();
+        self.files.push_back(file);
+        self.pending.extend(chunks);
+    }
+}
+
+#[derive(Clone)]
 struct PendingChunk {
+    key: Option<u64>,
     file_path: String,
     chunk_id: i64,
     content: String,
     symbol_name: Option<String>,
     symbol_kind: Option<String>,
+    doc: Option<String>,
+    signature: Option<String>,
+    build: Option<String>,
+    module: Option<String>,
     language: String,
     start_line: i64,
     end_line: i64,
     last_modified: i64,
+    comment_ratio: f32,
+    span: Option<Range<usize>>,
+    encoding: Option<&'static str>,
+    generated: bool,
+    namespace: Namespace,
+}
+
+impl PendingChunk {
+    fn into_row(self, token_count: Option<usize>, vector: Vec<f32>) -> ChunkRow {
+        ChunkRow {
+            key: self.key,
+            file_path: self.file_path,
+            chunk_id: self.chunk_id,
+            content: self.content,
+            symbol_name: self.symbol_name,
+            symbol_kind: self.symbol_kind,
+            doc: self.doc,
+            signature: self.signature,
+            build: self.build,
+            module: self.module,
+            language: self.language,
+            start_line: self.start_line,
+            end_line: self.end_line,
+            last_modified: self.last_modified,
+            token_count,
+            comment_ratio: self.comment_ratio,
+            span: self.span,
+            encoding: self.encoding.map(str::to_string),
+            generated: self.generated,
+            namespace: self.namespace,
+            vector,
+        }
+    }
+}
+
+/// Read a file as UTF-8, decoding it from the encoding it is in, and
+/// passing over ones that are too large, binary, or generated when
+/// `[indexing] generated = "skip"` instead of failing on them.
+async fn read_source(path: &Path, indexing: &IndexingConfig) -> Result<Source> {
+    let max_file_size = indexing.max_file_size;
+    let read_err = |e| Error::FileRead {
+        path: path.to_path_buf(),
+        source: e,
+    };
+    let size = tokio::fs::metadata(path).await.map_err(read_err)?.len();
+    if size > max_file_size {
+        return Ok(Source::Skipped(format!(
+            "{size} bytes exceeds [indexing] max_file_size of {max_file_size}"
+        )));
+    }
+
+    let bytes = tokio::fs::read(path).await.map_err(read_err)?;
+    if !encoding::is_utf16(&bytes) && bytes.iter().take(BINARY_SNIFF_LEN).any(|&b| b == 0) {
+        return Ok(Source::Skipped("binary content".to_string()));
+    }
+    let source = encoding::decode(bytes);
+    if indexing.generated == GeneratedFiles::Skip {
+        let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+        if let Some(reason) = generated::detect(&file_name, &source.text) {
+            return Ok(Source::Skipped(format!(
+                "{reason}, and [indexing] generated is \"skip\""
+            )));
+        }
+    }
+    Ok(Source::Text(source))
+}
+
+/// What a walk of a tree found besides the files it indexed.
+struct Walk {
+    /// Files passed over, with the reason.
+    skipped: BTreeMap<String, String>,
+    aliases: Aliases,
+}
+
+/// Paths reached through symlinks, keyed by the path their file is
+/// indexed under.
+type Aliases = BTreeMap<String, Vec<String>>;
+
+/// `files` listed under `dir` with each file reached by several paths, as
+/// through a symlinked package in a pnpm workspace, kept once. The path
+/// kept is the one without symlinks if there is one, else the shortest;
+/// the others are returned keyed by it, all as stored under `prefix`.
+fn fold_aliases<'a>(
+    files: Vec<(PathBuf, &'a str)>,
+    dir: &Path,
+    prefix: &str,
+) -> (Vec<(PathBuf, &'a str)>, Aliases) {
+    let real_dir = dir.canonicalize().unwrap_or_else(|_| dir.to_path_buf());
+    let listed: Vec<(PathBuf, &str, String, PathBuf)> = files
+        .into_iter()
+        .map(|(path, lang_name)| {
+            let rel_path = relative_path(&path, dir);
+            let target = path.canonicalize().unwrap_or_else(|_| path.clone());
+            (path, lang_name, rel_path, target)
+        })
+        .collect();
+
+    let mut by_target: HashMap<&Path, Vec<usize>> = HashMap::new();
+    for (i, (.., target)) in listed.iter().enumerate() {
+        by_target.entry(target).or_default().push(i);
+    }
+    let mut folded: HashSet<usize> = HashSet::new();
+    let mut aliases = BTreeMap::new();
+    for indexes in by_target.values().filter(|indexes| indexes.len() > 1) {
+        let is_real = |i: usize| {
+            let (_, _, rel_path, target) = &listed[i];
+            target.strip_prefix(&real_dir).ok() == Some(Path::new(rel_path))
+        };
+        let Some(&kept) = indexes
+            .iter()
+            .min_by_key(|&&i| (!is_real(i), listed[i].2.len(), &listed[i].2))
+        else {
+            continue;
+        };
+        let mut others: Vec<String> = indexes
+            .iter()
+            .filter(|&&i| i != kept)
+            .map(|&i| format!("{prefix}{}", listed[i].2))
+            .collect();
+        others.sort();
+        folded.extend(indexes.iter().filter(|&&i| i != kept));
+        aliases.insert(format!("{prefix}{}", listed[kept].2), others);
+    }
+
+    let files = listed
+        .into_iter()
+        .enumerate()
+        .filter(|(i, _)| !folded.contains(i))
+        .map(|(_, (path, lang_name, ..))| (path, lang_name))
+        .collect();
+    (files, aliases)
+}
+
+/// `path` relative to `root`, as stored in the index.
+fn relative_path(path: &Path, root: &Path) -> String {
+    path.strip_prefix(root)
+        .unwrap_or(path)
+        .to_string_lossy()
+        .to_string()
+}
+
+/// Whether a relative path passes through a hidden file or directory, which
+/// a full index walk would never reach.
+fn is_hidden_path(rel_path: &str) -> bool {
+    Path::new(rel_path)
+        .components()
+        .any(|c| c.as_os_str().to_str().is_some_and(|s| s.starts_with('.')))
 }
 
 fn is_hidden(entry: &walkdir::DirEntry) -> bool {
@@ -195,9 +1219,34 @@ fn is_hidden(entry: &walkdir::DirEntry) -> bool {
             .is_some_and(|s| s.starts_with('.'))
 }
 
+/// Whether a relative path lies inside a directory named in `excluded`.
+fn is_excluded_path(rel_path: &str, excluded: &[String]) -> bool {
+    Path::new(rel_path)
+        .parent()
+        .into_iter()
+        .flat_map(Path::components)
+        .any(|c| excluded.iter().any(|dir| c.as_os_str() == dir.as_str()))
+}
+
+fn is_excluded(entry: &walkdir::DirEntry, excluded: &[String]) -> bool {
+    entry.depth() > 0
+        && entry.file_type().is_dir()
+        && excluded.iter().any(|dir| entry.file_name() == dir.as_str())
+}
+
+/// Whether the `.claudevilignore` in `root` keeps `entry` out.
+fn is_ignored(entry: &walkdir::DirEntry, root: &Path, ignored: &IgnoreRules) -> bool {
+    entry.depth() > 0
+        && ignored.is_ignored(
+            &relative_path(entry.path(), root),
+            entry.file_type().is_dir(),
+        )
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::store::{DEPS_PATH, Filters, Scope, SymbolQuery};
     use tempfile::TempDir;
 
     /// Create a temp directory with Go source files for testing.
@@ -256,8 +1305,8 @@ func (s *Server) Start() error {
 
         setup_go_project(project_dir.path());
 
-        let embedder = Embedder::new().unwrap();
-        let store = VectorStore::new(db_dir.path().to_str().unwrap())
+        let embedder = Embedder::new(&Default::default()).unwrap();
+        let store = VectorStore::new(db_dir.path().to_str().unwrap(), &Default::default())
             .await
             .unwrap();
         let (indexer, _chunker) = make_indexer(embedder, store.clone());
@@ -277,8 +1326,8 @@ func (s *Server) Start() error {
 
         setup_go_project(project_dir.path());
 
-        let embedder = Embedder::new().unwrap();
-        let store = VectorStore::new(db_dir.path().to_str().unwrap())
+        let embedder = Embedder::new(&Default::default()).unwrap();
+        let store = VectorStore::new(db_dir.path().to_str().unwrap(), &Default::default())
             .await
             .unwrap();
         let (indexer, _chunker) = make_indexer(embedder.clone(), store.clone());
@@ -287,7 +1336,10 @@ func (s *Server) Start() error {
 
         // Search for "http server" -- should find the Server type or Start method
         let query_vec = embedder.embed_one("http server listening").await.unwrap();
-        let results = store.search(&query_vec, 5, None).await.unwrap();
+        let results = store
+            .search(&query_vec, 5, Filters::default(), None)
+            .await
+            .unwrap();
 
         assert!(
             !results.is_empty(),
@@ -324,8 +1376,8 @@ func (s *Server) Start() error {
         )
         .unwrap();
 
-        let embedder = Embedder::new().unwrap();
-        let store = VectorStore::new(db_dir.path().to_str().unwrap())
+        let embedder = Embedder::new(&Default::default()).unwrap();
+        let store = VectorStore::new(db_dir.path().to_str().unwrap(), &Default::default())
             .await
             .unwrap();
         let (indexer, _chunker) = make_indexer(embedder.clone(), store.clone());
@@ -342,7 +1394,10 @@ func (s *Server) Start() error {
 
         // Verify via search that no hidden file content appears
         let query_vec = embedder.embed_one("secret function").await.unwrap();
-        let results = store.search(&query_vec, 100, None).await.unwrap();
+        let results = store
+            .search(&query_vec, 100, Filters::default(), None)
+            .await
+            .unwrap();
         for r in &results {
             assert!(
                 !r.file_path.contains(".hidden"),
@@ -352,6 +1407,62 @@ func (s *Server) Start() error {
         }
     }
 
+    #[tokio::test]
+    async fn claudevilignore_keeps_paths_out() {
+        let project_dir = TempDir::new().unwrap();
+        let db_dir = TempDir::new().unwrap();
+        let root = project_dir.path();
+        std::fs::write(root.join("main.go"), "package main\n\nfunc Main() {}\n").unwrap();
+        std::fs::create_dir_all(root.join("docs/legal")).unwrap();
+        std::fs::write(
+            root.join("docs/legal/terms.go"),
+            "package legal\n\nfunc Terms() {}\n",
+        )
+        .unwrap();
+        std::fs::write(root.join(IGNORE_FILE), "docs/legal/\n").unwrap();
+
+        let embedder = Embedder::new(&Default::default()).unwrap();
+        let store = VectorStore::new(db_dir.path().to_str().unwrap(), &Default::default())
+            .await
+            .unwrap();
+        let (indexer, _chunker) = make_indexer(embedder, store.clone());
+        indexer.index_directory(root).await.unwrap();
+
+        assert_eq!(store.list_files(None).await.unwrap(), ["main.go"]);
+        assert_eq!(
+            indexer
+                .index_file(&root.join("docs/legal/terms.go"), root)
+                .await
+                .unwrap(),
+            FileUpdate::Skipped(format!("listed in {IGNORE_FILE}"))
+        );
+    }
+
+    #[tokio::test]
+    async fn indexing_skips_vendored_directories() {
+        let project_dir = TempDir::new().unwrap();
+        let db_dir = TempDir::new().unwrap();
+
+        std::fs::write(
+            project_dir.path().join("main.go"),
+            "package main\n\nfunc main() {}\n",
+        )
+        .unwrap();
+        let vendored = project_dir.path().join("vendor/github.com/dep");
+        std::fs::create_dir_all(&vendored).unwrap();
+        std::fs::write(vendored.join("dep.go"), "package dep\n\nfunc Dep() {}\n").unwrap();
+
+        let embedder = Embedder::new(&Default::default()).unwrap();
+        let store = VectorStore::new(db_dir.path().to_str().unwrap(), &Default::default())
+            .await
+            .unwrap();
+        let (indexer, _chunker) = make_indexer(embedder, store.clone());
+
+        indexer.index_directory(project_dir.path()).await.unwrap();
+
+        assert_eq!(store.list_files(None).await.unwrap(), ["main.go"]);
+    }
+
     #[tokio::test]
     async fn indexing_skips_unsupported_files() {
         let project_dir = TempDir::new().unwrap();
@@ -365,8 +1476,8 @@ func (s *Server) Start() error {
         std::fs::write(project_dir.path().join("README.md"), "# My Project\n").unwrap();
         std::fs::write(project_dir.path().join("config.yaml"), "key: value\n").unwrap();
 
-        let embedder = Embedder::new().unwrap();
-        let store = VectorStore::new(db_dir.path().to_str().unwrap())
+        let embedder = Embedder::new(&Default::default()).unwrap();
+        let store = VectorStore::new(db_dir.path().to_str().unwrap(), &Default::default())
             .await
             .unwrap();
         let (indexer, _chunker) = make_indexer(embedder, store.clone());
@@ -384,6 +1495,33 @@ func (s *Server) Start() error {
         );
     }
 
+    #[tokio::test]
+    async fn fallback_chunking_indexes_unsupported_files() {
+        let project_dir = TempDir::new().unwrap();
+        let db_dir = TempDir::new().unwrap();
+        let root = project_dir.path();
+        std::fs::write(root.join("main.go"), "package main\n\nfunc main() {}\n").unwrap();
+        std::fs::write(root.join("Dockerfile"), "FROM rust\nRUN cargo build\n").unwrap();
+        std::fs::write(root.join("logo.png"), b"\x89PNG\0\0\0").unwrap();
+
+        let embedder = Embedder::new(&Default::default()).unwrap();
+        let store = VectorStore::new(db_dir.path().to_str().unwrap(), &Default::default())
+            .await
+            .unwrap();
+        let mut config = Config::load().unwrap();
+        config.indexing.fallback_chunking = true;
+        let chunker = Arc::new(TreeSitterChunker::new(&config).unwrap());
+        let indexer = Indexer::new(embedder, store.clone(), chunker, config);
+
+        indexer.index_directory(root).await.unwrap();
+        assert_eq!(
+            store.list_files(Some(FALLBACK_LANGUAGE)).await.unwrap(),
+            ["Dockerfile"]
+        );
+        // Binary files of no known language aren't reported as skipped
+        assert!(store.skipped_files().await.is_empty());
+    }
+
     #[tokio::test]
     async fn reindexing_replaces_old_chunks() {
         let project_dir = TempDir::new().unwrap();
@@ -396,8 +1534,8 @@ func (s *Server) Start() error {
         )
         .unwrap();
 
-        let embedder = Embedder::new().unwrap();
-        let store = VectorStore::new(db_dir.path().to_str().unwrap())
+        let embedder = Embedder::new(&Default::default()).unwrap();
+        let store = VectorStore::new(db_dir.path().to_str().unwrap(), &Default::default())
             .await
             .unwrap();
         let (indexer, _chunker) = make_indexer(embedder, store.clone());
@@ -423,13 +1561,389 @@ func (s *Server) Start() error {
         );
     }
 
+    #[tokio::test]
+    async fn index_file_refreshes_one_file() {
+        let project_dir = TempDir::new().unwrap();
+        let db_dir = TempDir::new().unwrap();
+        let root = project_dir.path();
+        let lib = root.join("lib.go");
+        std::fs::write(&lib, "package lib\n\nfunc Original() {}\n").unwrap();
+
+        let embedder = Embedder::new(&Default::default()).unwrap();
+        let store = VectorStore::new(db_dir.path().to_str().unwrap(), &Default::default())
+            .await
+            .unwrap();
+        let (indexer, _chunker) = make_indexer(embedder, store.clone());
+        indexer.index_directory(root).await.unwrap();
+
+        std::fs::write(
+            &lib,
+            "package lib\n\nfunc Updated() {}\n\nfunc Extra() {}\n",
+        )
+        .unwrap();
+        assert_eq!(
+            indexer.index_file(&lib, root).await.unwrap(),
+            FileUpdate::Indexed(2)
+        );
+        let found = store
+            .find_by_symbol(SymbolQuery::named("Updated"), 10)
+            .await
+            .unwrap();
+        assert_eq!(found.len(), 1);
+
+        std::fs::remove_file(&lib).unwrap();
+        assert_eq!(
+            indexer.index_file(&lib, root).await.unwrap(),
+            FileUpdate::Removed
+        );
+        assert_eq!(store.chunk_count().await.unwrap(), 0);
+
+        let notes = root.join("notes.txt");
+        std::fs::write(&notes, "not code").unwrap();
+        assert_eq!(
+            indexer.index_file(&notes, root).await.unwrap(),
+            FileUpdate::Unsupported
+        );
+    }
+
+    #[tokio::test]
+    async fn sync_with_git_indexes_only_changed_files() {
+        let project_dir = TempDir::new().unwrap();
+        let db_dir = TempDir::new().unwrap();
+        let root = project_dir.path();
+        let run_git = |args: &[&str]| {
+            let output = std::process::Command::new("git")
+                .arg("-C")
+                .arg(root)
+                .args(["-c", "user.name=t", "-c", "user.email=t@example.com"])
+                .args(args)
+                .output()
+                .unwrap();
+            assert!(output.status.success(), "git {args:?}");
+        };
+        run_git(&["init", "-q"]);
+        std::fs::write(root.join("a.go"), "package a\n\nfunc Alpha() {}\n").unwrap();
+        std::fs::write(root.join("b.go"), "package a\n\nfunc Beta() {}\n").unwrap();
+        run_git(&["add", "."]);
+        run_git(&["commit", "-qm", "one"]);
+
+        let embedder = Embedder::new(&Default::default()).unwrap();
+        let store = VectorStore::new(db_dir.path().to_str().unwrap(), &Default::default())
+            .await
+            .unwrap();
+        let (indexer, _chunker) = make_indexer(embedder, store.clone());
+        indexer.sync_with_git(root).await.unwrap();
+        assert_eq!(store.last_commit().await, git::head(root));
+        assert_eq!(store.chunk_count().await.unwrap(), 2);
+
+        // Uncommitted files aren't picked up; committed changes are
+        std::fs::write(root.join("scratch.go"), "package a\n\nfunc Scratch() {}\n").unwrap();
+        std::fs::remove_file(root.join("b.go")).unwrap();
+        std::fs::write(root.join("c.go"), "package a\n\nfunc Gamma() {}\n").unwrap();
+        run_git(&["add", "b.go", "c.go"]);
+        run_git(&["commit", "-qm", "two"]);
+        indexer.sync_with_git(root).await.unwrap();
+
+        assert_eq!(store.list_files(None).await.unwrap(), ["a.go", "c.go"]);
+        assert_eq!(store.last_commit().await, git::head(root));
+    }
+
+    #[tokio::test]
+    async fn index_commits_keeps_the_latest_messages() {
+        let project_dir = TempDir::new().unwrap();
+        let db_dir = TempDir::new().unwrap();
+        let root = project_dir.path();
+        let run_git = |args: &[&str]| {
+            let status = std::process::Command::new("git")
+                .arg("-C")
+                .arg(root)
+                .args(["-c", "user.name=t", "-c", "user.email=t@example.com"])
+                .args(args)
+                .status()
+                .unwrap();
+            assert!(status.success(), "git {args:?}");
+        };
+        run_git(&["init", "-q"]);
+        for message in [
+            "Add parser",
+            "Retry failed uploads with backoff",
+            "Bump version",
+        ] {
+            run_git(&["commit", "-q", "--allow-empty", "-m", message]);
+        }
+
+        let embedder = Embedder::new(&Default::default()).unwrap();
+        let store = VectorStore::new(db_dir.path().to_str().unwrap(), &Default::default())
+            .await
+            .unwrap();
+        let mut config = Config::load().unwrap();
+        config.indexing.commit_messages = 2;
+        let chunker = Arc::new(TreeSitterChunker::new(&config).unwrap());
+        let indexer = Indexer::new(
+            embedder.clone(),
+            store.clone(),
+            chunker.clone(),
+            config.clone(),
+        );
+
+        indexer.index_commits(root).await.unwrap();
+        assert_eq!(store.chunk_count().await.unwrap(), 2);
+        assert!(store.list_files(None).await.unwrap().is_empty());
+
+        let query = embedder.embed_one("why do uploads retry").await.unwrap();
+        let results = store
+            .search(
+                &query,
+                1,
+                Filters {
+                    kind: Some(COMMIT_KIND),
+                    ..Default::default()
+                },
+                None,
+            )
+            .await
+            .unwrap();
+        assert!(results[0].content.starts_with("Retry failed uploads"));
+
+        // The oldest indexed commit falls out of the window
+        run_git(&["commit", "-q", "--allow-empty", "-m", "Fix typo"]);
+        indexer.index_commits(root).await.unwrap();
+        let messages: Vec<String> = store
+            .file_chunks(COMMITS_PATH)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|c| c.content)
+            .collect();
+        assert_eq!(messages.len(), 2);
+        assert!(messages.iter().all(|m| !m.starts_with("Retry")));
+
+        // Turning it off removes what was indexed
+        config.indexing.commit_messages = 0;
+        let indexer = Indexer::new(embedder, store.clone(), chunker, config);
+        indexer.index_commits(root).await.unwrap();
+        assert_eq!(store.chunk_count().await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn index_dependencies_keeps_them_out_of_project_searches() {
+        let project_dir = TempDir::new().unwrap();
+        let cache_dir = TempDir::new().unwrap();
+        let db_dir = TempDir::new().unwrap();
+        let root = project_dir.path();
+        setup_go_project(root);
+        std::fs::write(
+            root.join("go.mod"),
+            "module example.com/app\n\nrequire github.com/Acme/retry v1.2.0\n",
+        )
+        .unwrap();
+        let dep_dir = cache_dir.path().join("github.com/!acme/retry@v1.2.0");
+        std::fs::create_dir_all(&dep_dir).unwrap();
+        std::fs::write(
+            dep_dir.join("retry.go"),
+            "package retry\n\n// Do calls fn until it succeeds, backing off between attempts.\nfunc Do(fn func() error) error { return fn() }\n",
+        )
+        .unwrap();
+        let caches = deps::Caches {
+            go_mod_cache: Some(cache_dir.path().to_path_buf()),
+            cargo_registry_src: None,
+        };
+
+        let embedder = Embedder::new(&Default::default()).unwrap();
+        let store = VectorStore::new(db_dir.path().to_str().unwrap(), &Default::default())
+            .await
+            .unwrap();
+        let mut config = Config::load().unwrap();
+        config.dependencies.index = true;
+        let chunker = Arc::new(TreeSitterChunker::new(&config).unwrap());
+        let indexer = Indexer::new(embedder.clone(), store.clone(), chunker, config.clone());
+        indexer.index_directory(root).await.unwrap();
+        indexer
+            .index_dependencies_from(root, &caches)
+            .await
+            .unwrap();
+
+        let path = format!("{DEPS_PATH}/github.com/Acme/retry@v1.2.0/retry.go");
+        let query = embedder.embed_one("retry with backoff").await.unwrap();
+        let deps_only = store
+            .search(
+                &query,
+                5,
+                Filters {
+                    scope: Scope::Dependencies,
+                    ..Default::default()
+                },
+                None,
+            )
+            .await
+            .unwrap();
+        assert!(!deps_only.is_empty());
+        assert!(deps_only.iter().all(|r| r.file_path == path));
+        let project = store
+            .search(&query, 100, Filters::default(), None)
+            .await
+            .unwrap();
+        assert!(project.iter().all(|r| !r.file_path.starts_with(DEPS_PATH)));
+        assert!(!store.list_files(None).await.unwrap().contains(&path));
+        assert_eq!(
+            store.dependencies().await.into_keys().collect::<Vec<_>>(),
+            ["github.com/Acme/retry@v1.2.0"]
+        );
+
+        // Turning the option off removes them again
+        config.dependencies.index = false;
+        let chunker = Arc::new(TreeSitterChunker::new(&config).unwrap());
+        let indexer = Indexer::new(embedder, store.clone(), chunker, config);
+        indexer
+            .index_dependencies_from(root, &caches)
+            .await
+            .unwrap();
+        assert!(store.dependencies().await.is_empty());
+    }
+
+    #[test]
+    fn hidden_paths() {
+        assert!(is_hidden_path(".github/workflows/ci.yaml"));
+        assert!(is_hidden_path("pkg/.cache/gen.go"));
+        assert!(!is_hidden_path("pkg/server.go"));
+    }
+
+    #[test]
+    fn excluded_paths() {
+        let excluded = vec!["vendor".to_string(), "site-packages".to_string()];
+        assert!(is_excluded_path("vendor/github.com/x/y.go", &excluded));
+        assert!(is_excluded_path(
+            "env/lib/site-packages/requests/api.py",
+            &excluded
+        ));
+        assert!(!is_excluded_path("pkg/vendor.go", &excluded));
+        assert!(!is_excluded_path("vendored/y.go", &excluded));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinked_files_are_kept_once() {
+        let tmp = TempDir::new().unwrap();
+        let root = tmp.path();
+        std::fs::create_dir_all(root.join("packages/ui")).unwrap();
+        std::fs::create_dir_all(root.join("apps/web/node_modules")).unwrap();
+        std::fs::write(root.join("packages/ui/button.ts"), "export {}\n").unwrap();
+        std::fs::write(root.join("packages/ui/menu.ts"), "export {}\n").unwrap();
+        std::os::unix::fs::symlink(
+            root.join("packages/ui"),
+            root.join("apps/web/node_modules/ui"),
+        )
+        .unwrap();
+
+        let mut files: Vec<(PathBuf, &str)> = WalkDir::new(root)
+            .follow_links(true)
+            .into_iter()
+            .filter_map(|e| e.ok())
+            .filter(|e| e.file_type().is_file())
+            .map(|e| (e.into_path(), "typescript"))
+            .collect();
+        // Walk order doesn't decide which path is kept
+        files.sort();
+        let (kept, aliases) = fold_aliases(files, root, "");
+
+        let mut kept: Vec<String> = kept.iter().map(|(p, _)| relative_path(p, root)).collect();
+        kept.sort();
+        assert_eq!(kept, ["packages/ui/button.ts", "packages/ui/menu.ts"]);
+        assert_eq!(
+            aliases["packages/ui/button.ts"],
+            ["apps/web/node_modules/ui/button.ts"]
+        );
+        assert_eq!(aliases.len(), 2);
+    }
+
+    #[test]
+    fn walks_stop_at_the_file_and_byte_limits() {
+        let mut limits = WalkLimits::new(&IndexingConfig {
+            max_files: 2,
+            max_total_bytes: 0,
+            ..Default::default()
+        });
+        assert_eq!(limits.admit(u64::MAX / 2), None);
+        assert_eq!(limits.admit(10), None);
+        let limit = limits.admit(0).unwrap();
+        assert!(limit.contains("max_files is 2"), "{limit}");
+
+        let mut limits = WalkLimits::new(&IndexingConfig {
+            max_files: 0,
+            max_total_bytes: 100,
+            ..Default::default()
+        });
+        assert_eq!(limits.admit(60), None);
+        assert!(limits.admit(50).unwrap().contains("max_total_bytes"));
+    }
+
+    #[tokio::test]
+    async fn read_source_skips_large_and_binary_files_and_decodes_others() {
+        let dir = TempDir::new().unwrap();
+        let indexing = IndexingConfig {
+            max_file_size: 1024,
+            ..Default::default()
+        };
+        let write = |name: &str, bytes: &[u8]| {
+            let path = dir.path().join(name);
+            std::fs::write(&path, bytes).unwrap();
+            path
+        };
+
+        let ok = write("ok.go", b"package ok\n");
+        assert!(
+            matches!(read_source(&ok, &indexing).await.unwrap(), Source::Text(s) if s.text == "package ok\n" && s.encoding.is_none())
+        );
+
+        let big = write("big.go", &[b'a'; 2048]);
+        let Source::Skipped(reason) = read_source(&big, &indexing).await.unwrap() else {
+            panic!("large file should be skipped");
+        };
+        assert!(reason.contains("max_file_size"), "{reason}");
+
+        let binary = write("blob.go", b"package x\0\x01\x02");
+        assert!(
+            matches!(read_source(&binary, &indexing).await.unwrap(), Source::Skipped(r) if r == "binary content")
+        );
+
+        let latin1 = write("latin1.go", b"// caf\xe9 cr\xe8me br\xfbl\xe9e\n");
+        let Source::Text(source) = read_source(&latin1, &indexing).await.unwrap() else {
+            panic!("Latin-1 file should be decoded");
+        };
+        assert_eq!(source.text, "// café crème brûlée\n");
+        assert_eq!(source.encoding, Some("windows-1252"));
+
+        let utf16 = write("utf16.py", b"\xff\xfex\0 \0=\0 \x001\0\n\0");
+        assert!(
+            matches!(read_source(&utf16, &indexing).await.unwrap(), Source::Text(s) if s.text == "x = 1\n")
+        );
+
+        // Generated files are only left out when asked to
+        let mock = write(
+            "mock.go",
+            b"// Code generated by MockGen. DO NOT EDIT.\npackage x\n",
+        );
+        assert!(matches!(
+            read_source(&mock, &indexing).await.unwrap(),
+            Source::Text(_)
+        ));
+        let skipping = IndexingConfig {
+            generated: GeneratedFiles::Skip,
+            ..indexing
+        };
+        let Source::Skipped(reason) = read_source(&mock, &skipping).await.unwrap() else {
+            panic!("generated file should be skipped");
+        };
+        assert!(reason.starts_with("marked as generated"), "{reason}");
+    }
+
     #[tokio::test]
     async fn index_empty_directory() {
         let project_dir = TempDir::new().unwrap();
         let db_dir = TempDir::new().unwrap();
 
-        let embedder = Embedder::new().unwrap();
-        let store = VectorStore::new(db_dir.path().to_str().unwrap())
+        let embedder = Embedder::new(&Default::default()).unwrap();
+        let store = VectorStore::new(db_dir.path().to_str().unwrap(), &Default::default())
             .await
             .unwrap();
         let (indexer, _chunker) = make_indexer(embedder, store.clone());
@@ -465,8 +1979,8 @@ func (s *Server) Start() error {
         )
         .unwrap();
 
-        let embedder = Embedder::new().unwrap();
-        let store = VectorStore::new(db_dir.path().to_str().unwrap())
+        let embedder = Embedder::new(&Default::default()).unwrap();
+        let store = VectorStore::new(db_dir.path().to_str().unwrap(), &Default::default())
             .await
             .unwrap();
         let (indexer, _chunker) = make_indexer(embedder, store.clone());
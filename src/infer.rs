@@ -0,0 +1,419 @@
+//! Guessing which language a query is about, and what kind of code it
+//! wants, from its wording.
+//!
+//! "goroutine leak" is almost certainly about Go and "borrow checker error"
+//! about Rust. When a search has no explicit language filter, the inferred
+//! languages get a small ranking boost instead of a hard filter, so a wrong
+//! guess costs little.
+//!
+//! Likewise "interface for storage", "test for parser", and "constructor of
+//! Server" ask for a kind of code rather than a topic. Without an explicit
+//! `kind` filter, chunks of the [`Structure`] asked for get a small boost.
+
+/// Words and phrases that point at a language, keyed by config language
+/// name. Deliberately limited to terms that rarely mean anything else.
+const CUES: &[(&str, &[&str])] = &[
+    (
+        "go",
+        &[
+            "golang",
+            "goroutine",
+            "goroutines",
+            "gofmt",
+            "go.mod",
+            "waitgroup",
+            "errgroup",
+            "chan",
+            "defer",
+            "go func",
+        ],
+    ),
+    (
+        "rust",
+        &[
+            "rust",
+            "borrow checker",
+            "lifetime",
+            "lifetimes",
+            "cargo",
+            "crate",
+            "impl",
+            "trait",
+            "traits",
+            "tokio",
+            "serde",
+            "unsafe",
+            "macro_rules",
+        ],
+    ),
+    (
+        "python",
+        &[
+            "python",
+            "pip",
+            "decorator",
+            "decorators",
+            "django",
+            "flask",
+            "pytest",
+            "asyncio",
+            "pandas",
+            "numpy",
+            "__init__",
+            "list comprehension",
+        ],
+    ),
+    (
+        "sh",
+        &["bash", "shell script", "shebang", "zsh", "posix sh"],
+    ),
+    (
+        "yaml",
+        &[
+            "yaml",
+            "kubernetes",
+            "k8s",
+            "helm",
+            "github actions",
+            "openapi",
+            "swagger",
+        ],
+    ),
+    ("hcl", &["terraform", "hcl", "tfvars", "tfstate"]),
+    ("protobuf", &["protobuf", "proto", "grpc", "protoc"]),
+    ("thrift", &["thrift"]),
+];
+
+/// Kinds of code a query can ask for, in any language.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Structure {
+    /// Tests: test functions, and anything in a test file.
+    Test,
+    /// Interfaces, traits, and protocols.
+    Interface,
+    /// Constructors: `new`, Go's `NewX`, Python's `__init__`.
+    Constructor,
+    /// Type definitions: structs, enums, classes.
+    Type,
+}
+
+/// Words and phrases that ask for a [`Structure`].
+const STRUCTURE_CUES: &[(Structure, &[&str])] = &[
+    (
+        Structure::Test,
+        &[
+            "test",
+            "tests",
+            "unit test",
+            "unit tests",
+            "test case",
+            "unittest",
+        ],
+    ),
+    (
+        Structure::Interface,
+        &[
+            "interface",
+            "interfaces",
+            "trait",
+            "traits",
+            "protocol",
+            "abstract class",
+            "abstract base class",
+        ],
+    ),
+    (
+        Structure::Constructor,
+        &[
+            "constructor",
+            "constructors",
+            "ctor",
+            "initializer",
+            "factory function",
+        ],
+    ),
+    (
+        Structure::Type,
+        &[
+            "struct",
+            "structs",
+            "class",
+            "classes",
+            "enum",
+            "enums",
+            "type definition",
+            "data type",
+        ],
+    ),
+];
+
+/// Words of a symbol kind that mark a type definition.
+const TYPE_KIND_WORDS: &[&str] = &["struct", "enum", "class", "type", "union"];
+
+/// What [`Structure::matches`] looks at in a chunk.
+pub struct Shape<'a> {
+    pub file_path: &'a str,
+    pub symbol_name: Option<&'a str>,
+    pub symbol_kind: Option<&'a str>,
+    /// The declaration without its body, if recorded.
+    pub signature: Option<&'a str>,
+    pub content: &'a str,
+}
+
+impl Shape<'_> {
+    /// The symbol name without the type or module it is qualified by, so
+    /// `Server::new` and `Server.__init__` end in `new` and `__init__`.
+    fn base_name(&self) -> Option<&str> {
+        let name = self.symbol_name?;
+        name.rsplit(['.', ':']).next()
+    }
+
+    fn kind_words(&self) -> impl Iterator<Item = &str> {
+        self.symbol_kind
+            .into_iter()
+            .flat_map(|kind| kind.split('_'))
+    }
+
+    /// The signature, or the first line for chunks indexed without one.
+    fn declaration(&self) -> &str {
+        self.signature
+            .unwrap_or_else(|| self.content.lines().next().unwrap_or_default())
+    }
+}
+
+impl Structure {
+    /// Whether the chunk described by `shape` is code of this kind.
+    pub fn matches(self, shape: &Shape<'_>) -> bool {
+        match self {
+            Self::Test => is_test_path(shape.file_path) || is_test_symbol(shape),
+            Self::Interface => {
+                shape
+                    .kind_words()
+                    .any(|word| matches!(word, "trait" | "interface"))
+                    || (shape.kind_words().any(|word| word == "type")
+                        && shape.declaration().contains(" interface"))
+                    || (shape.kind_words().any(|word| word == "class")
+                        && ["Protocol", "ABC"]
+                            .iter()
+                            .any(|base| shape.declaration().contains(base)))
+            }
+            Self::Constructor => shape.base_name().is_some_and(|name| {
+                matches!(name, "new" | "__init__" | "constructor")
+                    || name.starts_with("new_")
+                    || name
+                        .strip_prefix("New")
+                        .is_some_and(|rest| rest.is_empty() || rest.starts_with(char::is_uppercase))
+            }),
+            Self::Type => {
+                shape
+                    .kind_words()
+                    .any(|word| TYPE_KIND_WORDS.contains(&word))
+                    && !Self::Interface.matches(shape)
+            }
+        }
+    }
+}
+
+/// Files that hold tests by their language's convention: Go's `_test.go`,
+/// Python's `test_*.py` and `*_test.py`, JavaScript's `.test.` and
+/// `.spec.`, and anything under a `test` or `tests` directory.
+fn is_test_path(path: &str) -> bool {
+    let (dirs, file) = path.rsplit_once('/').unwrap_or(("", path));
+    dirs.split('/').any(|dir| matches!(dir, "test" | "tests"))
+        || file.ends_with("_test.go")
+        || file.ends_with("_test.py")
+        || (file.starts_with("test_") && file.ends_with(".py"))
+        || file.contains(".test.")
+        || file.contains(".spec.")
+}
+
+/// Test functions named or marked as their language's test runner wants:
+/// Go's `TestX` and `BenchmarkX`, Python's `test_x`, Rust's `#[test]` and
+/// its `mod tests`.
+fn is_test_symbol(shape: &Shape<'_>) -> bool {
+    let named = shape.base_name().is_some_and(|name| {
+        name == "tests"
+            || name.starts_with("test_")
+            || ["Test", "Benchmark"].iter().any(|prefix| {
+                name.strip_prefix(prefix)
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with(char::is_uppercase))
+            })
+    });
+    named
+        || ["#[test]", "#[tokio::test]", "#[cfg(test)]"]
+            .iter()
+            .any(|attribute| shape.content.contains(attribute))
+}
+
+/// Kinds of code the query's wording asks for, in `STRUCTURE_CUES` order.
+pub fn infer_structures(query: &str) -> Vec<Structure> {
+    let padded = padded_words(query);
+    STRUCTURE_CUES
+        .iter()
+        .filter(|(_, cues)| cues.iter().any(|cue| padded.contains(&format!(" {cue} "))))
+        .map(|(structure, _)| *structure)
+        .collect()
+}
+
+/// Languages the query's wording suggests, in `CUES` order.
+pub fn infer_languages(query: &str) -> Vec<&'static str> {
+    let padded = padded_words(query);
+    CUES.iter()
+        .filter(|(_, cues)| cues.iter().any(|cue| padded.contains(&format!(" {cue} "))))
+        .map(|(lang, _)| *lang)
+        .collect()
+}
+
+/// The query's words, lowercased and padded with spaces, so every cue can
+/// be matched as ` cue `.
+fn padded_words(query: &str) -> String {
+    let normalized: String = query
+        .to_lowercase()
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || matches!(c, '_' | '.') {
+                c
+            } else {
+                ' '
+            }
+        })
+        .collect();
+    format!(
+        " {} ",
+        normalized.split_whitespace().collect::<Vec<_>>().join(" ")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn single_language_cues() {
+        assert_eq!(infer_languages("goroutine leak in the worker pool"), ["go"]);
+        assert_eq!(infer_languages("fix the Borrow Checker error"), ["rust"]);
+        assert_eq!(infer_languages("pytest fixture for the db"), ["python"]);
+        assert_eq!(infer_languages("terraform module for the VPC"), ["hcl"]);
+    }
+
+    #[test]
+    fn cues_match_whole_words_only() {
+        assert!(infer_languages("where do we go from here").is_empty());
+        assert!(infer_languages("rusty bucket impls").is_empty());
+        assert!(infer_languages("parse the config file").is_empty());
+    }
+
+    #[test]
+    fn structure_cues() {
+        assert_eq!(infer_structures("test for parser"), [Structure::Test]);
+        assert_eq!(
+            infer_structures("interface for storage"),
+            [Structure::Interface]
+        );
+        assert_eq!(
+            infer_structures("constructor of Server"),
+            [Structure::Constructor]
+        );
+        assert_eq!(infer_structures("the Config struct"), [Structure::Type]);
+        assert!(infer_structures("latest parser tests run").contains(&Structure::Test));
+        assert!(infer_structures("how the parser attests signatures").is_empty());
+    }
+
+    fn shape<'a>(
+        file_path: &'a str,
+        symbol_name: &'a str,
+        symbol_kind: &'a str,
+        content: &'a str,
+    ) -> Shape<'a> {
+        Shape {
+            file_path,
+            symbol_name: Some(symbol_name),
+            symbol_kind: Some(symbol_kind),
+            signature: None,
+            content,
+        }
+    }
+
+    #[test]
+    fn structures_match_chunks_in_each_language() {
+        let go_interface = shape(
+            "store/store.go",
+            "Store",
+            "type_declaration",
+            "type Store interface {
+	Get(key string) []byte
+}",
+        );
+        let go_struct = shape(
+            "store/store.go",
+            "Server",
+            "type_declaration",
+            "type Server struct {
+	addr string
+}",
+        );
+        assert!(Structure::Interface.matches(&go_interface));
+        assert!(!Structure::Type.matches(&go_interface));
+        assert!(Structure::Type.matches(&go_struct));
+        assert!(!Structure::Interface.matches(&go_struct));
+        assert!(Structure::Interface.matches(&shape("src/lib.rs", "Store", "trait_item", "")));
+        assert!(Structure::Interface.matches(&shape(
+            "store.py",
+            "Store",
+            "class_definition",
+            "class Store(Protocol):"
+        )));
+
+        for (name, kind) in [
+            ("NewServer", "function_declaration"),
+            ("Server::new", "function_item"),
+            ("Server.__init__", "function_definition"),
+        ] {
+            assert!(
+                Structure::Constructor.matches(&shape("server", name, kind, "")),
+                "{name}"
+            );
+        }
+        assert!(!Structure::Constructor.matches(&shape(
+            "n.go",
+            "Newline",
+            "function_declaration",
+            ""
+        )));
+        assert!(!Structure::Constructor.matches(&shape(
+            "s.rs",
+            "Server::renew",
+            "function_item",
+            ""
+        )));
+
+        for (path, name, content) in [
+            ("parser/parser_test.go", "helper", ""),
+            ("parser.go", "TestParse", ""),
+            ("tests/test_parser.py", "check", ""),
+            ("parser.py", "test_parse", ""),
+            (
+                "src/parser.rs",
+                "parses_empty_input",
+                "#[test]
+fn parses_empty_input() {}",
+            ),
+            ("web/parser.test.ts", "parse", ""),
+        ] {
+            assert!(
+                Structure::Test.matches(&shape(path, name, "function", content)),
+                "{path} {name}"
+            );
+        }
+        assert!(!Structure::Test.matches(&shape("src/parser.rs", "Testament", "struct_item", "")));
+        assert!(!Structure::Test.matches(&shape("src/contest.rs", "parse", "function_item", "")));
+    }
+
+    #[test]
+    fn several_languages() {
+        assert_eq!(
+            infer_languages("kubernetes manifest rendered by a bash script"),
+            ["sh", "yaml"]
+        );
+    }
+}
@@ -0,0 +1,79 @@
+//! Semantic code search over a local directory.
+//!
+//! The `claudevil` binary serves this engine over MCP, but it can be built
+//! into other tools too. A [`TreeSitterChunker`] splits source files into
+//! functions, types and other chunks, an [`Embedder`] turns them into
+//! vectors, and a [`VectorStore`] keeps and searches them; an [`Indexer`]
+//! drives the three over a directory tree. [`Config`] holds the same
+//! settings the binary reads from `config.toml`.
+//!
+//! ```no_run
+//! use std::sync::Arc;
+//!
+//! use claudevil::{Config, Embedder, Filters, Indexer, TreeSitterChunker, VectorStore};
+//!
+//! # async fn run() -> claudevil::Result<()> {
+//! let config = Config::load()?;
+//! let embedder = Embedder::new(&config.embedding)?;
+//! let chunker = Arc::new(TreeSitterChunker::new(&config)?);
+//! let store = VectorStore::new("/tmp/myproject-index", &config.index).await?;
+//!
+//! let indexer = Indexer::new(embedder.clone(), store.clone(), chunker, config);
+//! indexer.index_directory("/src/myproject".as_ref()).await?;
+//!
+//! let query = embedder.embed_query("where is the config parsed").await?;
+//! for hit in store.search(&query, 5, Filters::default(), None).await? {
+//!     println!("{}:{} {:?}", hit.file_path, hit.start_line, hit.symbol_name);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+pub mod archive;
+mod atomicfile;
+pub mod audit;
+pub mod batching;
+pub mod bench;
+pub mod buildtags;
+pub mod chunker;
+pub mod codeowners;
+pub mod completion;
+pub mod config;
+mod context;
+pub mod coverage;
+pub mod datadirs;
+mod deps;
+pub mod embed;
+mod encoding;
+pub mod error;
+pub mod eval;
+mod generated;
+mod git;
+mod identity;
+pub mod ignore;
+pub mod indexer;
+mod infer;
+pub mod lock;
+pub mod metrics;
+pub mod modpath;
+#[cfg(feature = "otlp")]
+pub mod otlp;
+mod postings;
+mod preprocess;
+pub mod progress;
+pub mod querycache;
+pub mod querylog;
+pub mod schedule;
+mod selftest;
+pub mod server;
+mod stacktrace;
+pub mod store;
+mod timespec;
+pub mod usage;
+
+pub use chunker::{Chunk, TreeSitterChunker};
+pub use config::Config;
+pub use embed::Embedder;
+pub use error::{Error, Result};
+pub use indexer::Indexer;
+pub use store::{Filters, SearchResult, VectorStore};
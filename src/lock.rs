@@ -0,0 +1,84 @@
+//! An advisory lock on an index's data directory.
+//!
+//! Two servers for the same root (say, two editor windows) would otherwise
+//! both index it and overwrite each other's files on every persist. Only
+//! the process holding the lock writes; the OS releases it when that
+//! process exits, however it exits.
+
+use std::fs::{File, OpenOptions, TryLockError};
+use std::io::Write;
+use std::path::Path;
+
+use crate::error::{Error, Result};
+
+const LOCK_FILE: &str = "lock";
+
+/// Exclusive write access to a data directory, held until dropped.
+pub struct DataDirLock {
+    _file: File,
+}
+
+/// Take the write lock on `db_path`, recording this process's id in it.
+/// Returns `None` if another process already holds it.
+pub fn acquire(db_path: &Path) -> Result<Option<DataDirLock>> {
+    let path = db_path.join(LOCK_FILE);
+    let io_error = |e| Error::StoreIo {
+        context: format!("locking {}", path.display()),
+        source: e,
+    };
+    // Not truncated on open: until the lock is ours, the pid belongs to the holder
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(&path)
+        .map_err(io_error)?;
+    match file.try_lock() {
+        Ok(()) => {}
+        Err(TryLockError::WouldBlock) => return Ok(None),
+        Err(TryLockError::Error(e)) => return Err(io_error(e)),
+    }
+    file.set_len(0).map_err(io_error)?;
+    write!(file, "{}", std::process::id()).map_err(io_error)?;
+    Ok(Some(DataDirLock { _file: file }))
+}
+
+/// Who holds the lock on `db_path`, for messages: "another claudevil
+/// process (pid N)", without the pid if it can't be read.
+pub fn holder(db_path: &Path) -> String {
+    let pid = std::fs::read_to_string(db_path.join(LOCK_FILE))
+        .ok()
+        .and_then(|s| s.trim().parse::<u32>().ok());
+    match pid {
+        Some(pid) => format!("another claudevil process (pid {pid})"),
+        None => "another claudevil process".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn second_acquire_waits_for_release() {
+        let tmp = TempDir::new().unwrap();
+        let lock = acquire(tmp.path()).unwrap();
+        assert!(lock.is_some());
+        assert!(acquire(tmp.path()).unwrap().is_none());
+        assert_eq!(
+            holder(tmp.path()),
+            format!("another claudevil process (pid {})", std::process::id())
+        );
+
+        drop(lock);
+        assert!(acquire(tmp.path()).unwrap().is_some());
+    }
+
+    #[test]
+    fn holder_without_pid() {
+        let tmp = TempDir::new().unwrap();
+        assert_eq!(holder(tmp.path()), "another claudevil process");
+    }
+}
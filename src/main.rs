@@ -1,41 +1,286 @@
-mod chunker;
-mod config;
-mod embed;
-mod error;
-mod indexer;
-mod server;
-mod store;
-
-use std::path::PathBuf;
+mod cli;
+
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::Duration;
 
 use anyhow::{Context, Result};
 use directories::BaseDirs;
 use rmcp::ServiceExt;
-use tracing_subscriber::EnvFilter;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{EnvFilter, Layer};
+
+#[cfg(feature = "otlp")]
+use claudevil::otlp;
+use claudevil::{
+    archive, audit, bench, chunker, config, datadirs, embed, eval, indexer, lock, metrics,
+    progress, querylog, schedule, server, store, usage,
+};
+
+use crate::cli::Command;
 
 #[tokio::main]
 async fn main() -> Result<()> {
     // Logging goes to stderr; stdout is the MCP JSON-RPC transport.
-    tracing_subscriber::fmt()
-        .with_env_filter(EnvFilter::from_default_env().add_directive(tracing::Level::INFO.into()))
+    let logs = tracing_subscriber::fmt::layer()
         .with_writer(std::io::stderr)
         .with_target(false)
         .compact()
-        .init();
+        .with_filter(EnvFilter::from_default_env().add_directive(tracing::Level::INFO.into()));
+    let registry = tracing_subscriber::registry().with(logs);
+    #[cfg(feature = "otlp")]
+    let registry = registry.with(otlp::from_env());
+    registry.init();
+    #[cfg(not(feature = "otlp"))]
+    if std::env::var_os("OTEL_EXPORTER_OTLP_ENDPOINT").is_some() {
+        tracing::warn!(
+            "OTEL_EXPORTER_OTLP_ENDPOINT is set, but this build can't export traces -- \
+             rebuild with `--features otlp`"
+        );
+    }
 
-    let root = match std::env::args().nth(1) {
-        Some(path) => PathBuf::from(path),
-        None => std::env::current_dir().context("failed to get current directory")?,
-    };
-    let root = root
-        .canonicalize()
-        .with_context(|| format!("directory not found: {}", root.display()))?;
+    match cli::parse(std::env::args().skip(1))? {
+        Command::Help => {
+            println!("{}", cli::USAGE);
+            Ok(())
+        }
+        Command::Serve {
+            roots,
+            read_only,
+            git_root,
+        } => {
+            let roots = if roots.is_empty() {
+                vec![resolve_root(None, git_root)?]
+            } else {
+                roots
+                    .into_iter()
+                    .map(|root| resolve_root(Some(root), git_root))
+                    .collect::<Result<_>>()?
+            };
+            serve(roots, read_only).await
+        }
+        Command::Export { archive, root } => {
+            let root = resolve_root(root, true)?;
+            let db_path = data_dir_for(&root)?;
+            // A server saving mid-export would leave the files out of step.
+            // Without a data directory there's no index, which export reports
+            let _lock = if db_path.is_dir() {
+                let Some(lock) = lock::acquire(&db_path)? else {
+                    anyhow::bail!(
+                        "{} is serving {} -- stop it before exporting its index",
+                        lock::holder(&db_path),
+                        root.display()
+                    );
+                };
+                Some(lock)
+            } else {
+                None
+            };
+            archive::export(&db_path, &archive)
+                .with_context(|| format!("failed to export index for {}", root.display()))?;
+            tracing::info!(
+                "exported index for {} to {}",
+                root.display(),
+                archive.display()
+            );
+            Ok(())
+        }
+        Command::Import { archive, root } => {
+            let root = resolve_root(root, true)?;
+            let db_path = data_dir_for(&root)?;
+            std::fs::create_dir_all(&db_path)?;
+            let Some(_lock) = lock::acquire(&db_path)? else {
+                anyhow::bail!(
+                    "{} is serving {} -- stop it before importing an index",
+                    lock::holder(&db_path),
+                    root.display()
+                );
+            };
+            let config = config::Config::load().context("failed to load config")?;
+            archive::import(&archive, &db_path, &config.index)
+                .with_context(|| format!("failed to import {}", archive.display()))?;
+            datadirs::record_root(&db_path, &root)?;
+            tracing::info!("imported {} for {}", archive.display(), root.display());
+            Ok(())
+        }
+        Command::Eval { suite, root } => run_eval(&suite, resolve_root(root, true)?).await,
+        Command::Bench { files } => run_bench(files).await,
+        Command::Gc {
+            dry_run,
+            older_than,
+        } => run_gc(dry_run, older_than),
+        Command::Relink { old_root, root } => relink(&old_root, resolve_root(root, true)?),
+        Command::AuditTail { lines, root } => {
+            let db_path = data_dir_for(&resolve_root(root, true)?)?;
+            for entry in audit::tail(&db_path, lines)? {
+                println!("{entry}");
+            }
+            Ok(())
+        }
+    }
+}
 
-    tracing::info!("claudevil starting for: {}", root.display());
+/// Delete the data directories of roots that no longer exist, and of ones
+/// not served for `older_than` days.
+fn run_gc(dry_run: bool, older_than: Option<u64>) -> Result<()> {
+    let now = std::time::SystemTime::now();
+    let mut freed = 0;
+    for dir in datadirs::list(&data_base()?)? {
+        let unused_days = dir.unused_for(now).map(|age| age.as_secs() / 86_400);
+        let why = if dir.is_orphaned() {
+            "the root no longer exists".to_string()
+        } else if let Some(days) = unused_days.filter(|&days| older_than.is_some_and(|n| days >= n))
+        {
+            format!("not served for {days} days")
+        } else {
+            continue;
+        };
+        let root = dir.root.as_deref().unwrap_or(&dir.path).display();
+        let what = format!(
+            "{} (index of {root}, {:.1} MiB; {why})",
+            dir.path.display(),
+            dir.bytes as f64 / (1024.0 * 1024.0)
+        );
+        if dry_run {
+            println!("would remove {what}");
+        } else if datadirs::remove(&dir)? {
+            println!("removed {what}");
+        } else {
+            println!(
+                "skipped {}: {} is using it",
+                dir.path.display(),
+                lock::holder(&dir.path)
+            );
+            continue;
+        }
+        freed += dir.bytes;
+    }
+    let verb = if dry_run { "would free" } else { "freed" };
+    println!("{verb} {:.1} MiB", freed as f64 / (1024.0 * 1024.0));
+    Ok(())
+}
+
+/// Hand the index built for `old_root` over to `root`, which it moved to.
+fn relink(old_root: &Path, root: PathBuf) -> Result<()> {
+    let old_dir = data_dir_for(old_root)?;
+    if !old_dir.is_dir() {
+        anyhow::bail!(
+            "no index was built for {} -- pass the root's old path exactly as it was served \
+             (run `claudevil gc --dry-run` to list indexes whose root is gone)",
+            old_root.display()
+        );
+    }
+    let new_dir = data_dir_for(&root)?;
+    if old_dir == new_dir {
+        anyhow::bail!("{} is already {}", old_root.display(), root.display());
+    }
+    datadirs::relink(&old_dir, &new_dir, &root)
+        .with_context(|| format!("failed to relink the index to {}", root.display()))?;
+    tracing::info!(
+        "relinked the index for {} to {}",
+        old_root.display(),
+        root.display()
+    );
+    Ok(())
+}
+
+/// Log a hint when `root` is getting a new, empty index while the index
+/// of a root that no longer exists is left on disk.
+fn hint_stranded(root: &Path) -> Result<()> {
+    let base = data_base()?;
+    if let Some(dir) = datadirs::stranded(&base, root)?.first() {
+        let old_root = dir.root.as_deref().unwrap_or(&dir.path).display();
+        tracing::warn!(
+            "starting a new index for {}, but {old_root} has an index and no longer exists -- \
+             if the project moved, stop this server and run `claudevil relink {old_root} {}` \
+             to reuse it",
+            root.display(),
+            root.display()
+        );
+    } else if datadirs::list(&base)?
+        .iter()
+        .any(datadirs::DataDir::is_orphaned)
+    {
+        tracing::info!(
+            "some indexes belong to roots that no longer exist -- `claudevil gc` removes them"
+        );
+    }
+    Ok(())
+}
+
+/// Score the existing index for `root` against an eval suite and print the
+/// report, failing if the suite's `min_recall` isn't met.
+async fn run_eval(suite_path: &Path, root: PathBuf) -> Result<()> {
+    let suite = eval::EvalSuite::load(suite_path)?;
+    let config = config::Config::load().context("failed to load config")?;
+    let db_path = data_dir_for(&root)?;
+    let store = store::VectorStore::open_read_only(
+        db_path
+            .to_str()
+            .context("database path contains non-UTF-8 characters")?,
+        &config.index,
+    )
+    .await
+    .with_context(|| format!("failed to open the index for {}", root.display()))?
+    .with_source_root(&root)
+    .await;
+    let embedder =
+        embed::Embedder::new(&config.embedding).context("failed to initialize embedding model")?;
+    let store = store.with_model(embedder.version(), &config.embedding);
+
+    let report = suite.run(&embedder, &store).await?;
+    println!("{}", report.render());
+    if !suite.passes(&report) {
+        anyhow::bail!(
+            "recall@{} {:.3} is below min_recall {:.3}",
+            report.k,
+            report.recall(),
+            suite.min_recall.unwrap_or_default()
+        );
+    }
+    Ok(())
+}
+
+/// Run the synthetic benchmark on `files` generated files in a scratch
+/// directory and print the timings.
+async fn run_bench(files: usize) -> Result<()> {
+    let config = config::Config::load().context("failed to load config")?;
+    let embedder =
+        embed::Embedder::new(&config.embedding).context("failed to initialize embedding model")?;
+    let work_dir = std::env::temp_dir().join(format!("claudevil-bench-{}", std::process::id()));
+    std::fs::create_dir_all(&work_dir)
+        .with_context(|| format!("failed to create {}", work_dir.display()))?;
+
+    let report = bench::run(&config, &embedder, files, &work_dir).await;
+    if let Err(e) = std::fs::remove_dir_all(&work_dir) {
+        tracing::warn!(
+            "failed to remove {} ({e}) -- delete it by hand",
+            work_dir.display()
+        );
+    }
+    print!("{}", report.context("benchmark failed")?.render());
+    Ok(())
+}
+
+/// Index each of `roots` in the background and serve them over MCP stdio
+/// from one process, which loads the embedding model once. A read-only
+/// server skips indexing and serves whatever indexes are already on disk.
+async fn serve(roots: Vec<PathBuf>, read_only: bool) -> Result<()> {
+    if read_only {
+        tracing::info!("read-only mode: the index will not be modified");
+    }
 
     // Load config (uses hardcoded defaults if no config file exists)
     let config = config::Config::load().context("failed to load config")?;
+    // Its index gauge has no root label, so it can only describe one
+    if roots.len() > 1 && config.metrics.listen.is_some() {
+        anyhow::bail!(
+            "[metrics] listen reports on one root's index, but {} roots are given -- \
+             serve each root from its own process to scrape it, or remove [metrics] listen",
+            roots.len()
+        );
+    }
     let languages = config.language_names().join(", ");
     tracing::info!("configured languages: {languages}");
 
@@ -43,47 +288,203 @@ async fn main() -> Result<()> {
     let chunker =
         Arc::new(chunker::TreeSitterChunker::new(&config).context("failed to initialize chunker")?);
 
-    // Determine platform-appropriate data directory
-    let base_dirs = BaseDirs::new().context("could not determine data directory")?;
-    let db_path = base_dirs
-        .data_dir()
-        .join("claudevil")
-        .join(dir_name_for(&root));
-    tokio::fs::create_dir_all(&db_path).await?;
-
     // Initialize the embedding model (may download on first run)
     tracing::info!("loading embedding model...");
-    let embedder = embed::Embedder::new().context("failed to initialize embedding model")?;
+    let embedder =
+        embed::Embedder::new(&config.embedding).context("failed to initialize embedding model")?;
     tracing::info!("embedding model ready");
 
+    let mut servers = Vec::new();
+    // Held until the server exits
+    let mut locks = Vec::new();
+    for root in roots {
+        let (server, store, lock) =
+            open_root(root, read_only, &config, &embedder, &chunker).await?;
+        // Only ever one root here; see above
+        if let Some(addr) = &config.metrics.listen {
+            let bound = metrics::spawn_server(addr, store)
+                .await
+                .context("failed to start metrics endpoint")?;
+            tracing::info!("serving /metrics and /healthz on http://{bound}");
+        }
+        servers.push(server);
+        locks.extend(lock);
+    }
+
+    tracing::info!("MCP server starting on stdio");
+    if servers.len() == 1 {
+        let server = servers.remove(0);
+        serve_stdio(server).await
+    } else {
+        serve_stdio(server::Registry::new(servers)).await
+    }
+}
+
+/// Open the index for `root`, start indexing it in the background unless
+/// it is read-only, and return a server for it, its store, and the lock on
+/// its data directory, held while it is served.
+async fn open_root(
+    root: PathBuf,
+    read_only: bool,
+    config: &config::Config,
+    embedder: &embed::Embedder,
+    chunker: &Arc<chunker::TreeSitterChunker>,
+) -> Result<(
+    server::ClaudevilServer,
+    store::VectorStore,
+    Option<lock::DataDirLock>,
+)> {
+    tracing::info!("claudevil starting for: {}", root.display());
+
+    let db_path = data_dir_for(&root)?;
+    // A second server for the same root serves the first one's index
+    // read-only rather than writing over it
+    let mut lock_holder = None;
+    let (lock, read_only) = if read_only {
+        (None, true)
+    } else {
+        let is_new = !db_path.exists();
+        tokio::fs::create_dir_all(&db_path).await?;
+        match lock::acquire(&db_path)? {
+            Some(lock) => {
+                datadirs::record_root(&db_path, &root)?;
+                if is_new && let Err(e) = hint_stranded(&root) {
+                    tracing::debug!("failed to look for stranded indexes: {e:#}");
+                }
+                (Some(lock), false)
+            }
+            None => {
+                let holder = lock::holder(&db_path);
+                tracing::warn!(
+                    "{holder} is already indexing {}; serving its index read-only",
+                    root.display()
+                );
+                lock_holder = Some(holder);
+                (None, true)
+            }
+        }
+    };
+
     // Initialize vector store
-    let store = store::VectorStore::new(
-        db_path
-            .to_str()
-            .context("database path contains non-UTF-8 characters")?,
-    )
+    let db_str = db_path
+        .to_str()
+        .context("database path contains non-UTF-8 characters")?;
+    let store = if read_only {
+        store::VectorStore::open_read_only(db_str, &config.index).await
+    } else {
+        store::VectorStore::new(db_str, &config.index).await
+    }
+    .with_context(|| match &lock_holder {
+        Some(holder) => format!(
+            "failed to open the index {holder} is writing -- it saves the index once its first \
+             files are indexed; reconnect this server after that, and it will follow later saves"
+        ),
+        None => "failed to open vector store".to_string(),
+    })?
+    .with_source_root(&root)
     .await
-    .context("failed to open vector store")?;
+    .with_model(embedder.version(), &config.embedding);
+
+    // Load chunk usage counters used for popularity ranking
+    let usage = if read_only {
+        usage::UsageTracker::load_read_only(&db_path).await
+    } else {
+        usage::UsageTracker::load(&db_path).await
+    }
+    .context("failed to load usage counters")?;
+
+    // Load the query log replayed to warm up the model and index
+    let queries = if read_only {
+        querylog::QueryLog::load_read_only(&db_path).await
+    } else {
+        querylog::QueryLog::load(&db_path).await
+    }
+    .context("failed to load query log")?;
+    let warmup_queries = config.indexing.warmup_queries;
 
     // Index files in the background so the MCP server is available immediately
-    let indexer = indexer::Indexer::new(
+    let progress = progress::IndexProgress::default();
+    if read_only {
+        tokio::spawn(follow_saves(store.clone(), config.index.clone()));
+        let (embedder, store, queries) = (embedder.clone(), store.clone(), queries.clone());
+        tokio::spawn(async move {
+            if let Err(e) = querylog::warm_up(&embedder, &store, &queries, warmup_queries).await {
+                tracing::error!("warm-up failed: {e:#}");
+            }
+        });
+    } else {
+        let indexer = indexer::Indexer::new(
+            embedder.clone(),
+            store.clone(),
+            chunker.clone(),
+            config.clone(),
+        )
+        .with_progress(progress.clone());
+        let index_root = root.clone();
+        let schedule = schedule::ReindexSchedule::from_config(&config.indexing);
+        let (embedder, store, queries) = (embedder.clone(), store.clone(), queries.clone());
+        tokio::spawn(async move {
+            if let Err(e) = indexer.update(&index_root).await {
+                tracing::error!("indexing failed: {e:#}");
+                metrics::metrics().record_index_error();
+                indexer.progress().fail_run(format!("{e:#}"));
+            } else if let Err(e) =
+                querylog::warm_up(&embedder, &store, &queries, warmup_queries).await
+            {
+                tracing::error!("warm-up failed: {e:#}");
+            }
+            // Scheduled runs start after the first index so they never overlap it
+            if let Some(schedule) = schedule {
+                schedule::run(indexer, store, index_root, schedule).await;
+            }
+        });
+    }
+
+    let audit = audit::AuditLog::open(&db_path, read_only, &config.server);
+    let mut server = server::ClaudevilServer::new(
         embedder.clone(),
         store.clone(),
         chunker.clone(),
         config.clone(),
-    );
-    let index_root = root.clone();
-    tokio::spawn(async move {
-        if let Err(e) = indexer.index_directory(&index_root).await {
-            tracing::error!("indexing failed: {e:#}");
-        }
-    });
+        root,
+        usage,
+        queries,
+    )
+    .with_progress(progress)
+    .with_audit(audit);
+    if let Some(holder) = lock_holder {
+        server = server.with_lock_holder(holder);
+    }
+    Ok((server, store, lock))
+}
 
-    // Start MCP server over stdio
-    let mcp_server = server::ClaudevilServer::new(embedder, store, chunker, config, root);
-    tracing::info!("MCP server starting on stdio");
+/// How often a read-only server checks whether its index was saved again.
+const RELOAD_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Reload read-only `store` whenever its index is saved again, so a server
+/// sharing another process's index sees what that process indexes.
+async fn follow_saves(store: store::VectorStore, config: config::IndexConfig) {
+    let mut seen = store.saved_at();
+    let mut ticks = tokio::time::interval(RELOAD_INTERVAL);
+    loop {
+        ticks.tick().await;
+        let saved = store.saved_at();
+        if saved == seen {
+            continue;
+        }
+        match store.reload(&config).await {
+            Ok(()) => {
+                seen = saved;
+                tracing::info!("reloaded the index, saved again by the process writing it");
+            }
+            Err(e) => tracing::warn!("failed to reload the index, will retry: {e:#}"),
+        }
+    }
+}
 
-    let service = mcp_server
+/// Serve `handler` over MCP stdio until the client disconnects.
+async fn serve_stdio(handler: impl rmcp::ServerHandler) -> Result<()> {
+    let service = handler
         .serve(rmcp::transport::stdio())
         .await
         .inspect_err(|e| tracing::error!("serve error: {e:?}"))
@@ -93,11 +494,58 @@ async fn main() -> Result<()> {
     Ok(())
 }
 
+/// Canonicalize the requested root. Without one, default to the top level
+/// of the git repository holding the current directory if `git_root`, so
+/// starting in a subdirectory still indexes the whole project, and to the
+/// current directory otherwise.
+fn resolve_root(root: Option<PathBuf>, git_root: bool) -> Result<PathBuf> {
+    let root = match root {
+        Some(path) => path,
+        None => {
+            let cwd = std::env::current_dir()
+                .context("failed to get current directory")?
+                .canonicalize()
+                .context("failed to resolve the current directory")?;
+            match git_root.then(|| git_top_level(&cwd)).flatten() {
+                Some(top) if top != cwd => {
+                    tracing::info!(
+                        "using the git repository at {} as the root -- pass {} as ROOT, or \
+                         --no-git-root when serving, to use only that directory",
+                        top.display(),
+                        cwd.display()
+                    );
+                    top.to_path_buf()
+                }
+                _ => cwd,
+            }
+        }
+    };
+    root.canonicalize()
+        .with_context(|| format!("directory not found: {}", root.display()))
+}
+
+/// The nearest directory at or above `dir` with a `.git` entry: a
+/// directory in a plain clone, a file in worktrees and submodules.
+fn git_top_level(dir: &Path) -> Option<&Path> {
+    dir.ancestors().find(|d| d.join(".git").exists())
+}
+
+/// Platform-appropriate data directory holding the index for `root`.
+fn data_dir_for(root: &Path) -> Result<PathBuf> {
+    Ok(data_base()?.join(dir_name_for(root)))
+}
+
+/// Platform-appropriate directory holding every root's data directory.
+fn data_base() -> Result<PathBuf> {
+    let base_dirs = BaseDirs::new().context("could not determine data directory")?;
+    Ok(base_dirs.data_dir().join("claudevil"))
+}
+
 /// Generate a unique directory name from a root path.
 ///
 /// Uses the directory basename + a truncated hash for human readability
 /// while avoiding collisions between different roots with the same name.
-fn dir_name_for(root: &std::path::Path) -> String {
+fn dir_name_for(root: &Path) -> String {
     use std::hash::{Hash, Hasher};
     let mut hasher = std::collections::hash_map::DefaultHasher::new();
     root.hash(&mut hasher);
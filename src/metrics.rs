@@ -0,0 +1,335 @@
+//! Counters and timings exposed at `/metrics`, with `/healthz`.
+
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{LazyLock, Mutex};
+use std::time::Duration;
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+use crate::error::{Error, Result};
+use crate::store::VectorStore;
+
+/// Latency buckets in seconds, from a warm single-query embedding up to a
+/// large batch on a slow CPU.
+const LATENCY_BUCKETS: &[f64] = &[
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+static METRICS: LazyLock<Metrics> = LazyLock::new(Metrics::new);
+
+/// Process-wide metrics registry.
+pub fn metrics() -> &'static Metrics {
+    &METRICS
+}
+
+/// A cumulative Prometheus-style histogram with fixed buckets.
+struct Histogram {
+    counts: Vec<AtomicU64>,
+    sum_micros: AtomicU64,
+    count: AtomicU64,
+}
+
+impl Histogram {
+    fn new() -> Self {
+        Self {
+            counts: LATENCY_BUCKETS.iter().map(|_| AtomicU64::new(0)).collect(),
+            sum_micros: AtomicU64::new(0),
+            count: AtomicU64::new(0),
+        }
+    }
+
+    fn observe(&self, elapsed: Duration) {
+        let secs = elapsed.as_secs_f64();
+        for (bound, count) in LATENCY_BUCKETS.iter().zip(&self.counts) {
+            if secs <= *bound {
+                count.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        self.sum_micros
+            .fetch_add(elapsed.as_micros() as u64, Ordering::Relaxed);
+        self.count.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn render(&self, name: &str, help: &str, out: &mut String) {
+        let _ = writeln!(out, "# HELP {name} {help}");
+        let _ = writeln!(out, "# TYPE {name} histogram");
+        for (bound, count) in LATENCY_BUCKETS.iter().zip(&self.counts) {
+            let _ = writeln!(
+                out,
+                "{name}_bucket{{le=\"{bound}\"}} {}",
+                count.load(Ordering::Relaxed)
+            );
+        }
+        let total = self.count.load(Ordering::Relaxed);
+        let _ = writeln!(out, "{name}_bucket{{le=\"+Inf\"}} {total}");
+        let sum = self.sum_micros.load(Ordering::Relaxed) as f64 / 1e6;
+        let _ = writeln!(out, "{name}_sum {sum}");
+        let _ = writeln!(out, "{name}_count {total}");
+    }
+}
+
+#[derive(Default, Clone, Copy)]
+struct ToolCounts {
+    calls: u64,
+    errors: u64,
+}
+
+/// Counters and latency histograms exported on `/metrics`.
+pub struct Metrics {
+    searches: AtomicU64,
+    index_errors: AtomicU64,
+    tools: Mutex<BTreeMap<String, ToolCounts>>,
+    embedding: Histogram,
+    batch_flush: Histogram,
+}
+
+impl Metrics {
+    fn new() -> Self {
+        Self {
+            searches: AtomicU64::new(0),
+            index_errors: AtomicU64::new(0),
+            tools: Mutex::new(BTreeMap::new()),
+            embedding: Histogram::new(),
+            batch_flush: Histogram::new(),
+        }
+    }
+
+    pub fn record_search(&self) {
+        self.searches.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_index_error(&self) {
+        self.index_errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_tool_call(&self, tool: &str, failed: bool) {
+        // Poisoning only happens if a holder panicked mid-increment; the
+        // counters are still usable.
+        let mut tools = self.tools.lock().unwrap_or_else(|e| e.into_inner());
+        let counts = tools.entry(tool.to_string()).or_default();
+        counts.calls += 1;
+        if failed {
+            counts.errors += 1;
+        }
+    }
+
+    pub fn observe_embedding(&self, elapsed: Duration) {
+        self.embedding.observe(elapsed);
+    }
+
+    pub fn observe_batch_flush(&self, elapsed: Duration) {
+        self.batch_flush.observe(elapsed);
+    }
+
+    /// Render all metrics in the Prometheus text exposition format.
+    pub fn render(&self, index_chunks: u64) -> String {
+        let mut out = String::new();
+
+        let _ = writeln!(
+            out,
+            "# HELP claudevil_searches_total Vector searches served."
+        );
+        let _ = writeln!(out, "# TYPE claudevil_searches_total counter");
+        let _ = writeln!(
+            out,
+            "claudevil_searches_total {}",
+            self.searches.load(Ordering::Relaxed)
+        );
+
+        let _ = writeln!(
+            out,
+            "# HELP claudevil_index_errors_total Files or batches that failed to index."
+        );
+        let _ = writeln!(out, "# TYPE claudevil_index_errors_total counter");
+        let _ = writeln!(
+            out,
+            "claudevil_index_errors_total {}",
+            self.index_errors.load(Ordering::Relaxed)
+        );
+
+        let _ = writeln!(
+            out,
+            "# HELP claudevil_index_chunks Chunks currently stored."
+        );
+        let _ = writeln!(out, "# TYPE claudevil_index_chunks gauge");
+        let _ = writeln!(out, "claudevil_index_chunks {index_chunks}");
+
+        let tools = self.tools.lock().unwrap_or_else(|e| e.into_inner()).clone();
+        let _ = writeln!(out, "# HELP claudevil_tool_calls_total MCP tool calls.");
+        let _ = writeln!(out, "# TYPE claudevil_tool_calls_total counter");
+        for (tool, counts) in &tools {
+            let _ = writeln!(
+                out,
+                "claudevil_tool_calls_total{{tool=\"{tool}\"}} {}",
+                counts.calls
+            );
+        }
+        let _ = writeln!(
+            out,
+            "# HELP claudevil_tool_errors_total MCP tool calls that returned an error."
+        );
+        let _ = writeln!(out, "# TYPE claudevil_tool_errors_total counter");
+        for (tool, counts) in &tools {
+            let _ = writeln!(
+                out,
+                "claudevil_tool_errors_total{{tool=\"{tool}\"}} {}",
+                counts.errors
+            );
+        }
+
+        self.embedding.render(
+            "claudevil_embedding_seconds",
+            "Embedding batch latency.",
+            &mut out,
+        );
+        self.batch_flush.render(
+            "claudevil_batch_flush_seconds",
+            "Indexer batch flush (embed + insert) duration.",
+            &mut out,
+        );
+        out
+    }
+}
+
+/// Serve `/metrics` and `/healthz` on `addr` in the background, returning
+/// the bound address.
+pub async fn spawn_server(addr: &str, store: VectorStore) -> Result<SocketAddr> {
+    let listener = TcpListener::bind(addr)
+        .await
+        .map_err(|e| Error::MetricsBind {
+            addr: addr.to_string(),
+            source: e,
+        })?;
+    let local = listener.local_addr()?;
+
+    tokio::spawn(async move {
+        loop {
+            let stream = match listener.accept().await {
+                Ok((stream, _)) => stream,
+                Err(e) => {
+                    tracing::warn!("metrics accept failed: {e}");
+                    continue;
+                }
+            };
+            let store = store.clone();
+            tokio::spawn(async move {
+                if let Err(e) = handle_connection(stream, &store).await {
+                    tracing::debug!("metrics connection error: {e}");
+                }
+            });
+        }
+    });
+
+    Ok(local)
+}
+
+/// Answer a single HTTP/1.x request. Only the request line is inspected.
+async fn handle_connection(mut stream: TcpStream, store: &VectorStore) -> std::io::Result<()> {
+    let mut buf = [0u8; 1024];
+    let n = stream.read(&mut buf).await?;
+    let request = String::from_utf8_lossy(&buf[..n]);
+    let path = request.split_whitespace().nth(1).unwrap_or("/");
+
+    let (status, content_type, body) = match path {
+        "/metrics" => {
+            let chunks = store.chunk_count().await.unwrap_or_default();
+            (
+                "200 OK",
+                "text/plain; version=0.0.4",
+                metrics().render(chunks),
+            )
+        }
+        "/healthz" => ("200 OK", "text/plain", "ok\n".to_string()),
+        _ => ("404 Not Found", "text/plain", "not found\n".to_string()),
+    };
+
+    let response = format!(
+        "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn render_includes_counters() {
+        let m = Metrics::new();
+        m.record_search();
+        m.record_search();
+        m.record_tool_call("search", false);
+        m.record_tool_call("read_file", true);
+
+        let out = m.render(42);
+        assert!(out.contains("claudevil_searches_total 2"), "{out}");
+        assert!(out.contains("claudevil_index_chunks 42"), "{out}");
+        assert!(
+            out.contains("claudevil_tool_calls_total{tool=\"search\"} 1"),
+            "{out}"
+        );
+        assert!(
+            out.contains("claudevil_tool_errors_total{tool=\"read_file\"} 1"),
+            "{out}"
+        );
+    }
+
+    #[test]
+    fn histogram_buckets_are_cumulative() {
+        let m = Metrics::new();
+        m.observe_embedding(Duration::from_millis(20));
+        m.observe_embedding(Duration::from_secs(3));
+
+        let out = m.render(0);
+        assert!(
+            out.contains("claudevil_embedding_seconds_bucket{le=\"0.025\"} 1"),
+            "{out}"
+        );
+        assert!(
+            out.contains("claudevil_embedding_seconds_bucket{le=\"5\"} 2"),
+            "{out}"
+        );
+        assert!(
+            out.contains("claudevil_embedding_seconds_bucket{le=\"+Inf\"} 2"),
+            "{out}"
+        );
+        assert!(out.contains("claudevil_embedding_seconds_count 2"), "{out}");
+    }
+
+    async fn get(addr: SocketAddr, path: &str) -> String {
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream
+            .write_all(format!("GET {path} HTTP/1.1\r\nHost: localhost\r\n\r\n").as_bytes())
+            .await
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        response
+    }
+
+    #[tokio::test]
+    async fn http_endpoints() {
+        let tmp = TempDir::new().unwrap();
+        let store = VectorStore::new(tmp.path().to_str().unwrap(), &Default::default())
+            .await
+            .unwrap();
+        let addr = spawn_server("127.0.0.1:0", store).await.unwrap();
+
+        let health = get(addr, "/healthz").await;
+        assert!(health.starts_with("HTTP/1.1 200 OK"), "{health}");
+        assert!(health.ends_with("ok\n"), "{health}");
+
+        let metrics = get(addr, "/metrics").await;
+        assert!(metrics.contains("claudevil_index_chunks 0"), "{metrics}");
+
+        let missing = get(addr, "/nope").await;
+        assert!(missing.starts_with("HTTP/1.1 404"), "{missing}");
+    }
+}
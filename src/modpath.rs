@@ -0,0 +1,217 @@
+//! The package or module a source file belongs to, named as its language
+//! names it, so results from the many `handler.go`s of a monorepo can be
+//! told apart.
+//!
+//! - Go: the import path, the `module` of the nearest `go.mod` followed by
+//!   the file's directory below it. Without a `go.mod`, the directory
+//!   relative to the root.
+//! - Rust: the module path, the crate name from the nearest `Cargo.toml`
+//!   followed by the modules below `src/`, as in `claudevil::store::dedup`.
+//!   Binaries under `src/bin/`, and tests, examples, and benches, are
+//!   crates of their own, named for their file or directory.
+//! - Python: the dotted module, the file's name preceded by each enclosing
+//!   directory that is a package (has an `__init__.py`).
+//!
+//! Manifests are looked for no further up than the root the file is
+//! indexed under.
+
+use std::path::Path;
+
+/// The module of the file at `path`, stored as `rel_path` relative to the
+/// root it is indexed under, in `language`; `None` for other languages or
+/// files outside any module.
+pub fn module_path(path: &Path, rel_path: &str, language: &str) -> Option<String> {
+    match language {
+        "go" => go_package(path, rel_path),
+        "rust" => rust_module(path, rel_path),
+        "python" => python_module(path),
+        _ => None,
+    }
+}
+
+/// The directories holding `path`, nearest first, up to the root
+/// `rel_path` is relative to.
+fn ancestors<'a>(path: &'a Path, rel_path: &str) -> impl Iterator<Item = &'a Path> {
+    let depth = Path::new(rel_path).components().count();
+    path.ancestors().skip(1).take(depth)
+}
+
+/// `path`'s components as strings; `None` if one isn't UTF-8.
+fn components(path: &Path) -> Option<Vec<&str>> {
+    path.components().map(|c| c.as_os_str().to_str()).collect()
+}
+
+fn go_package(path: &Path, rel_path: &str) -> Option<String> {
+    for dir in ancestors(path, rel_path) {
+        let Ok(go_mod) = std::fs::read_to_string(dir.join("go.mod")) else {
+            continue;
+        };
+        let module = go_mod
+            .lines()
+            .find_map(|line| line.trim().strip_prefix("module "))?
+            .split_whitespace()
+            .next()?
+            .trim_matches('"');
+        let below = components(path.parent()?.strip_prefix(dir).ok()?)?;
+        return Some(
+            std::iter::once(module)
+                .chain(below)
+                .collect::<Vec<_>>()
+                .join("/"),
+        );
+    }
+    let dir = components(Path::new(rel_path).parent()?)?;
+    (!dir.is_empty()).then(|| dir.join("/"))
+}
+
+fn rust_module(path: &Path, rel_path: &str) -> Option<String> {
+    for dir in ancestors(path, rel_path) {
+        let Ok(manifest) = std::fs::read_to_string(dir.join("Cargo.toml")) else {
+            continue;
+        };
+        // A workspace's own manifest names no package
+        let manifest: toml::Value = toml::from_str(&manifest).ok()?;
+        let package = manifest.get("package")?.get("name")?.as_str()?;
+        return crate_module(package, &components(path.strip_prefix(dir).ok()?)?);
+    }
+    None
+}
+
+/// The module path of the file at `parts` below the directory of the
+/// manifest of `package`.
+fn crate_module(package: &str, parts: &[&str]) -> Option<String> {
+    let (file, dirs) = parts.split_last()?;
+    let stem = file.strip_suffix(".rs")?;
+    let (krate, dirs) = match dirs {
+        ["src", "bin", rest @ ..] | ["tests" | "examples" | "benches", rest @ ..] => {
+            match rest.split_first() {
+                Some((krate, rest)) => (*krate, rest),
+                None => return Some(stem.replace('-', "_")),
+            }
+        }
+        ["src", rest @ ..] => (package, rest),
+        _ => return None,
+    };
+    let mut modules = vec![krate.replace('-', "_")];
+    modules.extend(dirs.iter().map(|dir| dir.to_string()));
+    let names_parent = stem == "mod" || (dirs.is_empty() && matches!(stem, "lib" | "main"));
+    if !names_parent {
+        modules.push(stem.to_string());
+    }
+    Some(modules.join("::"))
+}
+
+fn python_module(path: &Path) -> Option<String> {
+    let stem = path.file_stem()?.to_str()?;
+    let mut modules = Vec::new();
+    if stem != "__init__" {
+        modules.push(stem);
+    }
+    let mut dir = path.parent();
+    while let Some(package) = dir.filter(|d| d.join("__init__.py").is_file()) {
+        modules.push(package.file_name()?.to_str()?);
+        dir = package.parent();
+    }
+    modules.reverse();
+    (!modules.is_empty()).then(|| modules.join("."))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn write(root: &Path, rel_path: &str, text: &str) {
+        let path = root.join(rel_path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, text).unwrap();
+    }
+
+    fn module(root: &Path, rel_path: &str, language: &str) -> Option<String> {
+        module_path(&root.join(rel_path), rel_path, language)
+    }
+
+    #[test]
+    fn go_files_take_the_import_path() {
+        let tmp = TempDir::new().unwrap();
+        let root = tmp.path();
+        write(
+            root,
+            "services/api/go.mod",
+            "module github.com/acme/api // api\n\ngo 1.22\n",
+        );
+        assert_eq!(
+            module(root, "services/api/handlers/handler.go", "go").as_deref(),
+            Some("github.com/acme/api/handlers")
+        );
+        assert_eq!(
+            module(root, "services/api/main.go", "go").as_deref(),
+            Some("github.com/acme/api")
+        );
+        // No go.mod: the directory
+        assert_eq!(
+            module(root, "tools/gen/gen.go", "go").as_deref(),
+            Some("tools/gen")
+        );
+        assert_eq!(module(root, "main.go", "go"), None);
+    }
+
+    #[test]
+    fn rust_files_take_the_module_path() {
+        let tmp = TempDir::new().unwrap();
+        let root = tmp.path();
+        write(
+            root,
+            "Cargo.toml",
+            "[workspace]\nmembers = [\"crates/*\"]\n",
+        );
+        write(
+            root,
+            "crates/my-store/Cargo.toml",
+            "[package]\nname = \"my-store\"\n",
+        );
+        let rust = |rel_path| module(root, rel_path, "rust");
+        assert_eq!(
+            rust("crates/my-store/src/lib.rs").as_deref(),
+            Some("my_store")
+        );
+        assert_eq!(
+            rust("crates/my-store/src/store/mod.rs").as_deref(),
+            Some("my_store::store")
+        );
+        assert_eq!(
+            rust("crates/my-store/src/store/dedup.rs").as_deref(),
+            Some("my_store::store::dedup")
+        );
+        assert_eq!(
+            rust("crates/my-store/src/bin/dump.rs").as_deref(),
+            Some("dump")
+        );
+        assert_eq!(
+            rust("crates/my-store/tests/it/helpers.rs").as_deref(),
+            Some("it::helpers")
+        );
+        assert_eq!(rust("crates/my-store/build.rs"), None);
+        // Only the workspace manifest, which names no crate
+        assert_eq!(rust("scripts/tool.rs"), None);
+    }
+
+    #[test]
+    fn python_files_take_the_dotted_module() {
+        let tmp = TempDir::new().unwrap();
+        let root = tmp.path();
+        write(root, "src/acme/__init__.py", "");
+        write(root, "src/acme/billing/__init__.py", "");
+        let python = |rel_path| module(root, rel_path, "python");
+        assert_eq!(
+            python("src/acme/billing/invoice.py").as_deref(),
+            Some("acme.billing.invoice")
+        );
+        assert_eq!(
+            python("src/acme/billing/__init__.py").as_deref(),
+            Some("acme.billing")
+        );
+        assert_eq!(python("scripts/run.py").as_deref(), Some("run"));
+        assert_eq!(module(root, "src/acme/x.js", "javascript"), None);
+    }
+}
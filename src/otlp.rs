@@ -0,0 +1,338 @@
+//! Exporting tracing spans to an OpenTelemetry collector over OTLP/HTTP.
+//!
+//! Tool calls, indexing runs, embedding batches, and store lock waits are
+//! recorded as `tracing` spans. With the `otlp` cargo feature and
+//! `OTEL_EXPORTER_OTLP_ENDPOINT` set, this layer turns each finished span
+//! into an OTLP span and a background thread posts them in batches, JSON
+//! encoded, with the `ureq` client the Qdrant backend already uses.
+
+use std::collections::hash_map::RandomState;
+use std::fmt::Debug;
+use std::hash::BuildHasher;
+use std::sync::mpsc::{Receiver, RecvTimeoutError, SyncSender};
+use std::time::{Duration, Instant, SystemTime};
+
+use serde_json::{Value, json};
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Level, Subscriber};
+use tracing_subscriber::Layer;
+use tracing_subscriber::filter::{Filtered, Targets};
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::registry::LookupSpan;
+
+/// Base URL of the collector; `/v1/traces` is appended.
+const ENDPOINT_ENV: &str = "OTEL_EXPORTER_OTLP_ENDPOINT";
+/// Full traces URL, used as-is; takes precedence over [`ENDPOINT_ENV`].
+const TRACES_ENDPOINT_ENV: &str = "OTEL_EXPORTER_OTLP_TRACES_ENDPOINT";
+/// Extra request headers as `key=value,key=value`, e.g. for an API key.
+const HEADERS_ENV: &str = "OTEL_EXPORTER_OTLP_HEADERS";
+const SERVICE_NAME_ENV: &str = "OTEL_SERVICE_NAME";
+
+/// Finished spans waiting to be exported; more are dropped rather than
+/// slowing down the code being traced.
+const QUEUE_CAPACITY: usize = 4096;
+/// Most spans sent in one request.
+const MAX_BATCH: usize = 512;
+/// How long a batch waits to fill before it is sent anyway.
+const FLUSH_INTERVAL: Duration = Duration::from_secs(5);
+const EXPORT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The exporting layer, limited to claudevil's own spans.
+pub type FilteredOtlpLayer<S> = Filtered<OtlpLayer, Targets, S>;
+
+/// Where and as what to export spans.
+#[derive(Debug, PartialEq)]
+struct ExportConfig {
+    url: String,
+    headers: Vec<(String, String)>,
+    service_name: String,
+}
+
+impl ExportConfig {
+    /// Read the standard OpenTelemetry variables through `var`; `None`
+    /// unless an endpoint is set.
+    fn from_vars(var: impl Fn(&str) -> Option<String>) -> Option<Self> {
+        let url = match var(TRACES_ENDPOINT_ENV) {
+            Some(url) => url,
+            None => format!("{}/v1/traces", var(ENDPOINT_ENV)?.trim_end_matches('/')),
+        };
+        Some(Self {
+            url,
+            headers: var(HEADERS_ENV)
+                .map(|headers| parse_headers(&headers))
+                .unwrap_or_default(),
+            service_name: var(SERVICE_NAME_ENV).unwrap_or_else(|| "claudevil".to_string()),
+        })
+    }
+}
+
+/// `key=value` pairs separated by commas; malformed pairs are skipped.
+fn parse_headers(headers: &str) -> Vec<(String, String)> {
+    headers
+        .split(',')
+        .filter_map(|pair| {
+            let (key, value) = pair.split_once('=')?;
+            Some((key.trim().to_string(), value.trim().to_string()))
+        })
+        .filter(|(key, _)| !key.is_empty())
+        .collect()
+}
+
+/// The exporting layer if `OTEL_EXPORTER_OTLP_ENDPOINT` (or the traces
+/// variant) is set, starting its export thread.
+pub fn from_env<S>() -> Option<FilteredOtlpLayer<S>>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    let config = ExportConfig::from_vars(|name| std::env::var(name).ok())?;
+    let (sender, receiver) = std::sync::mpsc::sync_channel(QUEUE_CAPACITY);
+    std::thread::spawn(move || export_loop(&receiver, &config));
+    Some(OtlpLayer { sender }.with_filter(Targets::new().with_target("claudevil", Level::DEBUG)))
+}
+
+/// Turns closed spans into OTLP JSON spans for the export thread.
+pub struct OtlpLayer {
+    sender: SyncSender<Value>,
+}
+
+/// What the layer keeps with a span while it is open.
+struct SpanData {
+    trace_id: u128,
+    span_id: u64,
+    parent_span_id: Option<u64>,
+    start_nanos: u128,
+    attributes: Vec<(String, String)>,
+}
+
+/// Collects span fields as string attributes.
+struct Recorder<'a>(&'a mut Vec<(String, String)>);
+
+impl Visit for Recorder<'_> {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.push((field.name().to_string(), value.to_string()));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        self.0
+            .push((field.name().to_string(), format!("{value:?}")));
+    }
+}
+
+fn unix_nanos() -> u128 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos()
+}
+
+/// A random id; std seeds every `RandomState` afresh.
+fn random_u64() -> u64 {
+    RandomState::new().hash_one(unix_nanos())
+}
+
+impl<S> Layer<S> for OtlpLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        let parent = span.parent().and_then(|parent| {
+            parent
+                .extensions()
+                .get::<SpanData>()
+                .map(|data| (data.trace_id, data.span_id))
+        });
+        let mut attributes = Vec::new();
+        attrs.record(&mut Recorder(&mut attributes));
+        span.extensions_mut().insert(SpanData {
+            trace_id: parent.map_or_else(
+                || (u128::from(random_u64()) << 64) | u128::from(random_u64()),
+                |(trace_id, _)| trace_id,
+            ),
+            span_id: random_u64(),
+            parent_span_id: parent.map(|(_, span_id)| span_id),
+            start_nanos: unix_nanos(),
+            attributes,
+        });
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id)
+            && let Some(data) = span.extensions_mut().get_mut::<SpanData>()
+        {
+            values.record(&mut Recorder(&mut data.attributes));
+        }
+    }
+
+    fn on_close(&self, id: Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(&id) else {
+            return;
+        };
+        let Some(data) = span.extensions_mut().remove::<SpanData>() else {
+            return;
+        };
+        // A full queue drops the span rather than blocking the traced code
+        let _ = self
+            .sender
+            .try_send(encode_span(span.name(), &data, unix_nanos()));
+    }
+}
+
+/// An OTLP JSON span. Ids are hex and times are decimal strings, as the
+/// protobuf JSON mapping requires.
+fn encode_span(name: &str, data: &SpanData, end_nanos: u128) -> Value {
+    let mut span = json!({
+        "traceId": format!("{:032x}", data.trace_id),
+        "spanId": format!("{:016x}", data.span_id),
+        "name": name,
+        "kind": 1,
+        "startTimeUnixNano": data.start_nanos.to_string(),
+        "endTimeUnixNano": end_nanos.to_string(),
+        "attributes": data
+            .attributes
+            .iter()
+            .map(|(key, value)| json!({ "key": key, "value": { "stringValue": value } }))
+            .collect::<Vec<_>>(),
+    });
+    if let Some(parent) = data.parent_span_id {
+        span["parentSpanId"] = json!(format!("{parent:016x}"));
+    }
+    span
+}
+
+/// An export request carrying `spans` from `service_name`.
+fn encode_request(service_name: &str, spans: Vec<Value>) -> Value {
+    json!({
+        "resourceSpans": [{
+            "resource": {
+                "attributes": [
+                    { "key": "service.name", "value": { "stringValue": service_name } },
+                ],
+            },
+            "scopeSpans": [{
+                "scope": { "name": "claudevil", "version": env!("CARGO_PKG_VERSION") },
+                "spans": spans,
+            }],
+        }],
+    })
+}
+
+/// Send spans from `receiver` in batches until every sender is gone.
+/// Failures are reported once until an export succeeds again.
+fn export_loop(receiver: &Receiver<Value>, config: &ExportConfig) {
+    let agent = ureq::AgentBuilder::new().timeout(EXPORT_TIMEOUT).build();
+    tracing::info!("exporting traces to {}", config.url);
+    let mut failing = false;
+    while let Ok(first) = receiver.recv() {
+        let mut batch = vec![first];
+        let deadline = Instant::now() + FLUSH_INTERVAL;
+        let mut disconnected = false;
+        while batch.len() < MAX_BATCH {
+            match receiver.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                Ok(span) => batch.push(span),
+                Err(RecvTimeoutError::Timeout) => break,
+                Err(RecvTimeoutError::Disconnected) => {
+                    disconnected = true;
+                    break;
+                }
+            }
+        }
+
+        let mut request = agent.post(&config.url);
+        for (key, value) in &config.headers {
+            request = request.set(key, value);
+        }
+        match request.send_json(encode_request(&config.service_name, batch)) {
+            Ok(_) => failing = false,
+            Err(e) if !failing => {
+                failing = true;
+                tracing::warn!(
+                    "exporting traces to {} failed ({e}) -- check {ENDPOINT_ENV} and that \
+                     the collector accepts OTLP over HTTP",
+                    config.url
+                );
+            }
+            Err(_) => {}
+        }
+        if disconnected {
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn vars(pairs: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+        let map: HashMap<String, String> = pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        move |name| map.get(name).cloned()
+    }
+
+    #[test]
+    fn config_from_standard_variables() {
+        assert_eq!(ExportConfig::from_vars(vars(&[])), None);
+
+        let config = ExportConfig::from_vars(vars(&[
+            (ENDPOINT_ENV, "http://collector:4318/"),
+            (HEADERS_ENV, "api-key=secret, x-team = search,broken"),
+        ]))
+        .unwrap();
+        assert_eq!(config.url, "http://collector:4318/v1/traces");
+        assert_eq!(
+            config.headers,
+            [
+                ("api-key".to_string(), "secret".to_string()),
+                ("x-team".to_string(), "search".to_string()),
+            ]
+        );
+        assert_eq!(config.service_name, "claudevil");
+
+        let config = ExportConfig::from_vars(vars(&[
+            (ENDPOINT_ENV, "http://collector:4318"),
+            (TRACES_ENDPOINT_ENV, "https://traces.example.com/otlp"),
+            (SERVICE_NAME_ENV, "claudevil-ci"),
+        ]))
+        .unwrap();
+        assert_eq!(config.url, "https://traces.example.com/otlp");
+        assert_eq!(config.service_name, "claudevil-ci");
+    }
+
+    #[test]
+    fn spans_encode_as_otlp_json() {
+        let data = SpanData {
+            trace_id: 0xabc,
+            span_id: 0x12,
+            parent_span_id: Some(0x34),
+            start_nanos: 1_000,
+            attributes: vec![("name".to_string(), "search".to_string())],
+        };
+        let span = encode_span("tool", &data, 2_500);
+        assert_eq!(
+            span,
+            json!({
+                "traceId": "00000000000000000000000000000abc",
+                "spanId": "0000000000000012",
+                "parentSpanId": "0000000000000034",
+                "name": "tool",
+                "kind": 1,
+                "startTimeUnixNano": "1000",
+                "endTimeUnixNano": "2500",
+                "attributes": [{ "key": "name", "value": { "stringValue": "search" } }],
+            })
+        );
+
+        let request = encode_request("claudevil", vec![span]);
+        assert_eq!(
+            request["resourceSpans"][0]["scopeSpans"][0]["spans"][0]["name"],
+            "tool"
+        );
+    }
+}
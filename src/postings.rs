@@ -0,0 +1,141 @@
+//! Inverted index from identifiers to the chunks that contain them.
+//!
+//! Vector search is fuzzy by design, which is the wrong tool when a query
+//! names an exact identifier (`` `parse_config` ``). The postings map each
+//! identifier in chunk content to chunk keys so such queries can be narrowed
+//! to the chunks that actually mention it without scanning every chunk.
+
+use std::collections::{HashMap, HashSet};
+
+/// Identifiers shorter than this are too common to be worth indexing.
+const MIN_IDENT_LEN: usize = 3;
+
+/// Identifier → chunk keys, matched case-insensitively.
+#[derive(Debug, Default, Clone)]
+pub struct Postings {
+    map: HashMap<String, HashSet<u64>>,
+}
+
+impl Postings {
+    /// Index the identifiers in `content` under `key`.
+    pub fn add(&mut self, key: u64, content: &str) {
+        for ident in identifiers(content) {
+            self.map.entry(ident).or_default().insert(key);
+        }
+    }
+
+    /// Forget `key`, previously added with the same `content`.
+    pub fn remove(&mut self, key: u64, content: &str) {
+        for ident in identifiers(content) {
+            if let Some(keys) = self.map.get_mut(&ident) {
+                keys.remove(&key);
+                if keys.is_empty() {
+                    self.map.remove(&ident);
+                }
+            }
+        }
+    }
+
+    /// Keys of chunks containing every identifier in `names`. Qualified
+    /// names like `Server::start` require each of their parts.
+    pub fn lookup(&self, names: &[String]) -> HashSet<u64> {
+        let mut parts: Vec<String> = names.iter().flat_map(|n| identifiers(n)).collect();
+        // Intersect starting from the rarest identifier
+        parts.sort_by_key(|p| self.map.get(p).map_or(0, HashSet::len));
+
+        let Some((first, rest)) = parts.split_first() else {
+            return HashSet::new();
+        };
+        let mut keys = self.map.get(first).cloned().unwrap_or_default();
+        for part in rest {
+            if keys.is_empty() {
+                break;
+            }
+            let Some(more) = self.map.get(part) else {
+                return HashSet::new();
+            };
+            keys.retain(|k| more.contains(k));
+        }
+        keys
+    }
+}
+
+/// Distinct lowercased identifiers in `text`: runs of letters, digits, and
+/// underscores that don't start with a digit.
+fn identifiers(text: &str) -> HashSet<String> {
+    text.split(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .filter(|w| w.len() >= MIN_IDENT_LEN && !w.starts_with(|c: char| c.is_ascii_digit()))
+        .map(str::to_lowercase)
+        .collect()
+}
+
+/// Identifiers the query quotes in backticks, e.g. `` `VectorStore` ``.
+pub fn quoted_identifiers(query: &str) -> Vec<String> {
+    query
+        .split('`')
+        .skip(1)
+        .step_by(2)
+        .map(|q| q.trim().trim_end_matches("()"))
+        .filter(|q| !identifiers(q).is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| (*s).to_string()).collect()
+    }
+
+    #[test]
+    fn lookup_is_case_insensitive_and_whole_word() {
+        let mut postings = Postings::default();
+        postings.add(1, "fn parse_config(path: &Path) -> Config");
+        postings.add(2, "fn parse(input: &str)");
+
+        assert_eq!(
+            postings.lookup(&names(&["parse_config"])),
+            HashSet::from([1])
+        );
+        assert_eq!(postings.lookup(&names(&["CONFIG"])), HashSet::from([1]));
+        assert_eq!(postings.lookup(&names(&["parse"])), HashSet::from([2]));
+    }
+
+    #[test]
+    fn qualified_names_need_every_part() {
+        let mut postings = Postings::default();
+        postings.add(1, "impl Server { fn start(&self) {} }");
+        postings.add(2, "fn start() {}");
+
+        assert_eq!(
+            postings.lookup(&names(&["Server::start"])),
+            HashSet::from([1])
+        );
+        assert!(postings.lookup(&names(&["Server::stop"])).is_empty());
+    }
+
+    #[test]
+    fn remove_forgets_a_chunk() {
+        let mut postings = Postings::default();
+        postings.add(1, "let token_count = 3;");
+        postings.add(2, "token_count += 1;");
+        postings.remove(1, "let token_count = 3;");
+
+        assert_eq!(
+            postings.lookup(&names(&["token_count"])),
+            HashSet::from([2])
+        );
+        assert!(!postings.map.contains_key("let"));
+    }
+
+    #[test]
+    fn quoted_identifiers_from_backticks() {
+        assert_eq!(
+            quoted_identifiers("where is `VectorStore::search()` called, and `a`? not `->`"),
+            names(&["VectorStore::search"])
+        );
+        assert!(quoted_identifiers("no quotes here").is_empty());
+    }
+}
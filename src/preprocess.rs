@@ -0,0 +1,294 @@
+//! Code-aware cleanup applied to chunk text before it is tokenized for
+//! embedding. Stored content is left untouched; only the embedding input
+//! changes, so boilerplate and opaque data don't use up the token budget
+//! before the model reaches the actual logic.
+
+/// String literals longer than this many characters are collapsed.
+const MAX_LITERAL_CHARS: usize = 48;
+
+/// Characters of a collapsed string literal that are kept as a hint.
+const LITERAL_PREFIX_CHARS: usize = 16;
+
+/// Runs of at least this many hex digits are treated as opaque blobs.
+const MIN_HEX_BLOB: usize = 32;
+
+/// Words that mark a leading comment block as a license header.
+const LICENSE_MARKERS: &[&str] = &["license", "copyright", "spdx-license-identifier"];
+
+/// Share of the longer of two texts the shorter must make up for
+/// [`is_same_code`] to call them the same.
+const SAME_CODE_SHARE: f32 = 0.8;
+
+/// Prepare `text` for embedding: strip a leading license header, collapse
+/// long string literals and hex blobs, and normalize whitespace.
+pub fn preprocess_code(text: &str) -> String {
+    let body = strip_license_header(text);
+    let collapsed = collapse_literals(body);
+    let collapsed = collapse_hex_blobs(&collapsed);
+    normalize_whitespace(&collapsed)
+}
+
+/// Whether `a` and `b` are the same code but for whitespace, comment
+/// lines, and what [`preprocess_code`] cleans up: one holds the other,
+/// which makes up most of it, as a pasted function does the chunk holding
+/// it and the struct it is a method of.
+pub fn is_same_code(a: &str, b: &str) -> bool {
+    let squeeze = |text: &str| -> String {
+        preprocess_code(text)
+            .lines()
+            .filter(|line| !is_comment_line(line))
+            .flat_map(str::chars)
+            .filter(|c| !c.is_whitespace())
+            .collect()
+    };
+    let (a, b) = (squeeze(a), squeeze(b));
+    let (short, long) = if a.len() <= b.len() { (a, b) } else { (b, a) };
+    !short.is_empty()
+        && long.contains(&short)
+        && short.len() as f32 >= SAME_CODE_SHARE * long.len() as f32
+}
+
+/// Whether `line` holds only a comment, in C-style or `#` syntax.
+fn is_comment_line(line: &str) -> bool {
+    let line = line.trim();
+    matches!(line, "*" | "#")
+        || ["//", "/*", "* ", "*/", "# ", "##"]
+            .iter()
+            .any(|marker| line.starts_with(marker))
+}
+
+/// Drop a leading comment block that mentions a license or copyright.
+fn strip_license_header(text: &str) -> &str {
+    let trimmed = text.trim_start();
+
+    let header_len = if let Some(rest) = trimmed.strip_prefix("/*") {
+        match rest.find("*/") {
+            Some(end) => 2 + end + 2,
+            None => return text,
+        }
+    } else {
+        let mut len = 0;
+        for line in trimmed.split_inclusive('\n') {
+            let l = line.trim_start();
+            let is_comment = l.starts_with("//")
+                || l.starts_with("--")
+                || (l.starts_with('#') && !l.starts_with("#!") && !l.starts_with("#["));
+            if !is_comment {
+                break;
+            }
+            len += line.len();
+        }
+        len
+    };
+
+    let header = trimmed[..header_len].to_lowercase();
+    if header_len == 0 || !LICENSE_MARKERS.iter().any(|m| header.contains(m)) {
+        return text;
+    }
+    &trimmed[header_len..]
+}
+
+/// Shorten `"..."` and `` `...` `` literals longer than `MAX_LITERAL_CHARS`,
+/// keeping their first few characters. Double-quoted literals never span
+/// lines; unterminated ones are left alone.
+fn collapse_literals(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find(['"', '`']) {
+        out.push_str(&rest[..start]);
+        let quote = rest[start..].chars().next().unwrap_or('"');
+        let after = &rest[start + 1..];
+
+        let Some(len) = literal_len(after, quote) else {
+            out.push(quote);
+            rest = after;
+            continue;
+        };
+
+        let inner = &after[..len];
+        out.push(quote);
+        if inner.chars().count() > MAX_LITERAL_CHARS {
+            out.extend(inner.chars().take(LITERAL_PREFIX_CHARS));
+            out.push('…');
+        } else {
+            out.push_str(inner);
+        }
+        out.push(quote);
+        rest = &after[len + 1..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Byte length of a literal body up to (not including) its closing `quote`.
+fn literal_len(body: &str, quote: char) -> Option<usize> {
+    let mut escaped = false;
+    for (i, c) in body.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if quote == '"' => escaped = true,
+            '\n' if quote == '"' => return None,
+            c if c == quote => return Some(i),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Replace long runs of hex digits (hashes, keys, embedded binaries) with a
+/// short prefix.
+fn collapse_hex_blobs(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut run_start = None;
+
+    for (i, c) in text.char_indices() {
+        if c.is_ascii_hexdigit() {
+            run_start.get_or_insert(i);
+            continue;
+        }
+        if let Some(start) = run_start.take() {
+            push_hex_run(&mut out, &text[start..i]);
+        }
+        out.push(c);
+    }
+    if let Some(start) = run_start {
+        push_hex_run(&mut out, &text[start..]);
+    }
+    out
+}
+
+fn push_hex_run(out: &mut String, run: &str) {
+    // Only digit-bearing runs count: long identifiers like "deadbeefcafe..."
+    // spelled from a-f letters alone are rare enough to ignore.
+    if run.len() >= MIN_HEX_BLOB && run.bytes().any(|b| b.is_ascii_digit()) {
+        out.push_str(&run[..8]);
+        out.push('…');
+    } else {
+        out.push_str(run);
+    }
+}
+
+/// Trim trailing whitespace, collapse runs of blank lines, and squeeze
+/// repeated spaces and tabs after the indentation.
+fn normalize_whitespace(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut blank_run = 0;
+
+    for line in text.trim().lines() {
+        let line = line.trim_end();
+        if line.is_empty() {
+            blank_run += 1;
+            if blank_run > 1 {
+                continue;
+            }
+        } else {
+            blank_run = 0;
+        }
+
+        let body = line.trim_start();
+        out.push_str(&line[..line.len() - body.len()]);
+        let mut prev_space = false;
+        for c in body.chars() {
+            let is_space = c == ' ' || c == '\t';
+            if !(is_space && prev_space) {
+                out.push(if is_space { ' ' } else { c });
+            }
+            prev_space = is_space;
+        }
+        out.push('\n');
+    }
+    out.pop();
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn same_code_ignores_whitespace_and_a_doc_comment() {
+        let chunk = "// Open opens the file at path for reading. The file is closed\n// when the returned File is garbage collected.\nfunc Open(path string) (*File, error) {\n\treturn open(path, O_RDONLY)\n}";
+        let pasted =
+            "func Open(path string) (*File, error) {\n    return open(path, O_RDONLY)\n}\n";
+        assert!(is_same_code(pasted, chunk));
+        assert!(is_same_code(chunk, pasted));
+        // A line of a function is related code, not a copy of it
+        assert!(!is_same_code("return open(path, O_RDONLY)", chunk));
+        assert!(!is_same_code(
+            "func Open(path string) (*File, error) {\n\treturn open(path, O_RDWR)\n}",
+            chunk
+        ));
+        assert!(!is_same_code("", chunk));
+    }
+
+    #[test]
+    fn strips_line_comment_license_header() {
+        let src =
+            "// Copyright 2024 Acme Inc.\n// Licensed under the MIT License.\n\nfn main() {}\n";
+        assert_eq!(preprocess_code(src), "fn main() {}");
+    }
+
+    #[test]
+    fn strips_block_license_header() {
+        let src = "/*\n * SPDX-License-Identifier: Apache-2.0\n */\npackage main\n";
+        assert_eq!(preprocess_code(src), "package main");
+    }
+
+    #[test]
+    fn keeps_ordinary_leading_comments() {
+        let src = "// Parse the config file.\nfn parse() {}";
+        assert_eq!(preprocess_code(src), src);
+    }
+
+    #[test]
+    fn keeps_rust_attributes_and_shebangs() {
+        let src = "#[derive(Debug)]\n// license: see below\nstruct A;";
+        assert_eq!(strip_license_header(src), src);
+        let src = "#!/usr/bin/env python\n# Copyright 2024\nimport os";
+        assert_eq!(strip_license_header(src), src);
+    }
+
+    #[test]
+    fn collapses_long_string_literals() {
+        let long = "x".repeat(200);
+        let src = format!("let s = \"{long}\";\nlet t = \"short\";");
+        let out = preprocess_code(&src);
+        assert_eq!(
+            out,
+            format!("let s = \"{}…\";\nlet t = \"short\";", "x".repeat(16))
+        );
+    }
+
+    #[test]
+    fn escaped_quotes_do_not_end_literals() {
+        let long = format!("say \\\"hi\\\" {}", "y".repeat(60));
+        let out = collapse_literals(&format!("f(\"{long}\", \"b\")"));
+        assert!(out.ends_with("…\", \"b\")"), "{out}");
+    }
+
+    #[test]
+    fn unterminated_literal_is_left_alone() {
+        let src = "let s = \"unterminated\nnext line";
+        assert_eq!(collapse_literals(src), src);
+    }
+
+    #[test]
+    fn collapses_hex_blobs() {
+        let hash = "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08";
+        let out = preprocess_code(&format!("const SHA: &str = {hash};"));
+        assert_eq!(out, "const SHA: &str = 9f86d081…;");
+    }
+
+    #[test]
+    fn short_hex_and_words_are_kept() {
+        let src = "let mask = 0xdeadbeef; let faced = decade;";
+        assert_eq!(preprocess_code(src), src);
+    }
+
+    #[test]
+    fn normalizes_whitespace_but_keeps_indentation() {
+        let src = "fn a() {\n    let  x =\t\t1;   \n\n\n\n    x\n}\n";
+        assert_eq!(preprocess_code(src), "fn a() {\n    let x = 1;\n\n    x\n}");
+    }
+}
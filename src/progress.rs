@@ -0,0 +1,148 @@
+//! Progress and failures of background indexing, shared with the server.
+//!
+//! Indexing runs in a background task, so a file that fails to chunk or a
+//! run that dies halfway would otherwise only show up in the server's log,
+//! which MCP clients never see. The indexer records them here and the
+//! `index_status` and `index_errors` tools report them.
+
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+/// Failures kept per run; later ones are only counted.
+const MAX_ERRORS: usize = 1000;
+
+/// A file, or a whole run, that failed to index.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexError {
+    /// Path as stored in the index; `None` when the run as a whole failed.
+    pub path: Option<String>,
+    pub error: String,
+}
+
+/// What [`IndexProgress::snapshot`] reports.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProgressSnapshot {
+    /// Files the current or last run set out to index.
+    pub total: usize,
+    /// Of those, files done with, successfully or not.
+    pub done: usize,
+    pub errors: Vec<IndexError>,
+    /// Failures beyond the ones kept in `errors`.
+    pub dropped_errors: usize,
+}
+
+impl ProgressSnapshot {
+    /// Whether a run is still working through its files.
+    pub fn is_running(&self) -> bool {
+        self.done < self.total
+    }
+
+    /// Share of the run's files done, from 0 to 100.
+    pub fn percent(&self) -> f64 {
+        if self.total == 0 {
+            100.0
+        } else {
+            self.done as f64 * 100.0 / self.total as f64
+        }
+    }
+}
+
+/// Handle to the indexing progress; clones share the same state.
+#[derive(Clone, Default)]
+pub struct IndexProgress {
+    state: Arc<Mutex<ProgressSnapshot>>,
+}
+
+impl IndexProgress {
+    fn lock(&self) -> MutexGuard<'_, ProgressSnapshot> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Start a new run, forgetting the last one's counts and failures.
+    pub fn start(&self) {
+        *self.lock() = ProgressSnapshot::default();
+    }
+
+    /// Add `files` more files to the current run.
+    pub fn add_files(&self, files: usize) {
+        self.lock().total += files;
+    }
+
+    /// Count one file of the current run as done.
+    pub fn file_done(&self) {
+        let mut state = self.lock();
+        state.done = (state.done + 1).min(state.total);
+    }
+
+    /// Record a failure to index `path`, or the run as a whole if `None`.
+    pub fn record_error(&self, path: Option<&str>, error: impl ToString) {
+        let mut state = self.lock();
+        if state.errors.len() < MAX_ERRORS {
+            state.errors.push(IndexError {
+                path: path.map(str::to_string),
+                error: error.to_string(),
+            });
+        } else {
+            state.dropped_errors += 1;
+        }
+    }
+
+    /// Record that the run as a whole failed, so it stops counting as
+    /// running.
+    pub fn fail_run(&self, error: impl ToString) {
+        self.record_error(None, error);
+        let mut state = self.lock();
+        state.done = state.total;
+    }
+
+    pub fn snapshot(&self) -> ProgressSnapshot {
+        self.lock().clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn progress_counts_files_and_keeps_errors() {
+        let progress = IndexProgress::default();
+        assert!(!progress.snapshot().is_running());
+
+        progress.start();
+        progress.add_files(4);
+        progress.file_done();
+        progress.record_error(Some("src/bad.go"), "syntax tree too deep");
+        progress.file_done();
+        let snapshot = progress.snapshot();
+        assert!(snapshot.is_running());
+        assert_eq!(snapshot.percent(), 50.0);
+        assert_eq!(
+            snapshot.errors,
+            [IndexError {
+                path: Some("src/bad.go".to_string()),
+                error: "syntax tree too deep".to_string(),
+            }]
+        );
+
+        progress.fail_run("embedding failed");
+        let snapshot = progress.snapshot();
+        assert!(!snapshot.is_running());
+        assert_eq!(snapshot.errors.len(), 2);
+        assert_eq!(snapshot.errors[1].path, None);
+
+        // A new run starts clean
+        progress.start();
+        assert_eq!(progress.snapshot(), ProgressSnapshot::default());
+    }
+
+    #[test]
+    fn errors_beyond_the_cap_are_counted() {
+        let progress = IndexProgress::default();
+        for i in 0..MAX_ERRORS + 3 {
+            progress.record_error(Some(&format!("f{i}.go")), "failed");
+        }
+        let snapshot = progress.snapshot();
+        assert_eq!(snapshot.errors.len(), MAX_ERRORS);
+        assert_eq!(snapshot.dropped_errors, 3);
+    }
+}
@@ -0,0 +1,195 @@
+//! Embeddings of recent search queries, kept so a repeated query skips the
+//! model.
+//!
+//! Agents often issue the same query again, or one that differs only in
+//! case or spacing, within a session. Embedding a query is a full forward
+//! pass of the model and most of a search's latency, so the server keeps
+//! the vectors of the last few hundred queries, keyed by the normalized
+//! text. The model's tokenizer splits on whitespace, so collapsing it
+//! can't change the vector; the key is lowercased only when the
+//! tokenizer's normalizer lowercases too, since a cased model embeds
+//! `Config` and `config` differently.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, PoisonError};
+
+/// How often the cache spared an embedding.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    /// Queries held now.
+    pub entries: usize,
+    pub capacity: usize,
+}
+
+impl CacheStats {
+    /// Share of lookups that were hits, as a percentage.
+    pub fn hit_rate(&self) -> f64 {
+        let lookups = self.hits + self.misses;
+        if lookups == 0 {
+            return 0.0;
+        }
+        self.hits as f64 * 100.0 / lookups as f64
+    }
+}
+
+#[derive(Default)]
+struct Inner {
+    /// Vector and last use of each normalized query.
+    entries: HashMap<String, (Arc<[f32]>, u64)>,
+    /// Counts lookups, ordering the entries by last use.
+    clock: u64,
+    hits: u64,
+    misses: u64,
+}
+
+/// Query embeddings by normalized text, dropping the least recently used
+/// beyond a capacity. Clones share the cache.
+#[derive(Clone)]
+pub struct QueryCache {
+    inner: Arc<Mutex<Inner>>,
+    capacity: usize,
+    /// Whether queries differing only in case share an entry.
+    lowercase: bool,
+}
+
+impl QueryCache {
+    /// A cache of up to `capacity` queries; 0 caches nothing. `lowercase`
+    /// says whether the model's tokenizer lowercases its input, see
+    /// [`Embedder::lowercases`](crate::embed::Embedder::lowercases).
+    pub fn new(capacity: usize, lowercase: bool) -> Self {
+        Self {
+            inner: Arc::default(),
+            capacity,
+            lowercase,
+        }
+    }
+
+    /// The vector cached for `query`, counting the lookup as a hit or a
+    /// miss.
+    pub fn get(&self, query: &str) -> Option<Arc<[f32]>> {
+        if self.capacity == 0 {
+            return None;
+        }
+        let mut inner = self.inner.lock().unwrap_or_else(PoisonError::into_inner);
+        inner.clock += 1;
+        let now = inner.clock;
+        match inner.entries.get_mut(&normalize(query, self.lowercase)) {
+            Some((vector, last_used)) => {
+                *last_used = now;
+                let vector = Arc::clone(vector);
+                inner.hits += 1;
+                Some(vector)
+            }
+            None => {
+                inner.misses += 1;
+                None
+            }
+        }
+    }
+
+    /// Cache `vector` as the embedding of `query`, dropping the least
+    /// recently used query if the cache is full.
+    pub fn insert(&self, query: &str, vector: Arc<[f32]>) {
+        if self.capacity == 0 {
+            return;
+        }
+        let key = normalize(query, self.lowercase);
+        let mut inner = self.inner.lock().unwrap_or_else(PoisonError::into_inner);
+        if !inner.entries.contains_key(&key) && inner.entries.len() >= self.capacity {
+            let oldest = inner
+                .entries
+                .iter()
+                .min_by_key(|(_, (_, last_used))| *last_used)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                inner.entries.remove(&oldest);
+            }
+        }
+        let now = inner.clock;
+        inner.entries.insert(key, (vector, now));
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        let inner = self.inner.lock().unwrap_or_else(PoisonError::into_inner);
+        CacheStats {
+            hits: inner.hits,
+            misses: inner.misses,
+            entries: inner.entries.len(),
+            capacity: self.capacity,
+        }
+    }
+}
+
+/// `query` as the cache keys it: with runs of whitespace collapsed to one
+/// space and none at either end, and lowercased if `lowercase` is set.
+pub fn normalize(query: &str, lowercase: bool) -> String {
+    let query = query.split_whitespace().collect::<Vec<_>>().join(" ");
+    if lowercase {
+        query.to_lowercase()
+    } else {
+        query
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vector(x: f32) -> Arc<[f32]> {
+        Arc::from(vec![x; 4])
+    }
+
+    #[test]
+    fn near_identical_queries_share_an_entry() {
+        assert_eq!(
+            normalize("  Parse   the\tConfig\n", true),
+            normalize("parse the config", true)
+        );
+        assert_eq!(normalize(" Parse  the Config", false), "Parse the Config");
+
+        let cache = QueryCache::new(8, true);
+        assert!(cache.get("parse the config").is_none());
+        cache.insert("parse the config", vector(1.0));
+        assert_eq!(
+            cache.get("Parse  the CONFIG ").as_deref(),
+            Some(&[1.0; 4][..])
+        );
+        assert!(cache.get("parse a config").is_none());
+
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses, stats.entries), (1, 2, 1));
+        assert!((stats.hit_rate() - 100.0 / 3.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn a_cased_model_keeps_case_apart() {
+        let cache = QueryCache::new(8, false);
+        cache.insert("parse the Config", vector(1.0));
+        assert!(cache.get("parse the config").is_none());
+        assert!(cache.get(" parse  the Config").is_some());
+    }
+
+    #[test]
+    fn least_recently_used_queries_are_dropped() {
+        let cache = QueryCache::new(2, true);
+        cache.insert("a", vector(1.0));
+        cache.insert("b", vector(2.0));
+        // Using "a" makes "b" the one to go
+        assert!(cache.get("a").is_some());
+        cache.insert("c", vector(3.0));
+        assert!(cache.get("b").is_none());
+        assert!(cache.get("a").is_some());
+        assert!(cache.get("c").is_some());
+        assert_eq!(cache.stats().entries, 2);
+    }
+
+    #[test]
+    fn zero_capacity_caches_nothing() {
+        let cache = QueryCache::new(0, true);
+        cache.insert("a", vector(1.0));
+        assert!(cache.get("a").is_none());
+        assert_eq!(cache.stats(), CacheStats::default());
+    }
+}
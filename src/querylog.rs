@@ -0,0 +1,448 @@
+//! A log of recent search queries, replayed to warm up the server.
+//!
+//! The first search after startup pays for paging in the model weights and
+//! the index, and is also where a broken model download or a mismatched
+//! index config first shows up. Re-running the queries a root sees most
+//! often, before a client asks, moves both costs to startup and surfaces
+//! any problem in the logs instead of in a failed tool call.
+//!
+//! The log also keeps how many results each query's latest search had and
+//! where its top hits were, which the `query_history` tool shows so a new
+//! session can see what earlier ones looked for and found.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Instant, SystemTime};
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+
+use crate::atomicfile;
+use crate::embed::{EMBEDDING_DIM, Embedder};
+use crate::error::{Error, Result};
+use crate::store::{Filters, VectorStore};
+
+/// Searches run against the index, kept next to it.
+pub const QUERY_LOG_FILE: &str = "queries.json";
+
+/// Distinct queries kept; the least recently used are dropped beyond this.
+const MAX_QUERIES: usize = 500;
+
+/// Queries unused for longer than this are no longer replayed.
+const RECENT_SECS: i64 = 30 * 24 * 60 * 60;
+
+/// How far an embedding's length may stray from 1 before it is reported.
+const NORM_TOLERANCE: f32 = 1e-3;
+
+/// Top hit locations kept per query.
+pub const MAX_FOUND: usize = 3;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct QueryStats {
+    count: u64,
+    /// Unix seconds of the most recent use.
+    last_used: i64,
+    /// Results of the most recent search; `None` in logs saved before
+    /// they were recorded.
+    #[serde(default)]
+    hits: Option<usize>,
+    /// `path:line` of its top results, best first.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    found: Vec<String>,
+}
+
+/// One query in [`QueryLog::history`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueryEntry {
+    pub text: String,
+    pub count: u64,
+    /// Unix seconds of the most recent use.
+    pub last_used: i64,
+    /// Results of the most recent search, if recorded.
+    pub hits: Option<usize>,
+    /// `path:line` of up to [`MAX_FOUND`] of its top results, best first.
+    pub found: Vec<String>,
+}
+
+/// Search queries seen for one root, persisted next to its index.
+#[derive(Clone)]
+pub struct QueryLog {
+    queries: Arc<Mutex<HashMap<String, QueryStats>>>,
+    path: PathBuf,
+    read_only: bool,
+}
+
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs() as i64
+}
+
+impl QueryLog {
+    /// Load the log from `db_path`, starting empty if none was saved.
+    pub async fn load(db_path: &Path) -> Result<Self> {
+        Self::open(db_path, false).await
+    }
+
+    /// Load the log from `db_path` but keep new queries in memory only, for
+    /// indexes opened with `--read-only`.
+    pub async fn load_read_only(db_path: &Path) -> Result<Self> {
+        Self::open(db_path, true).await
+    }
+
+    async fn open(db_path: &Path, read_only: bool) -> Result<Self> {
+        let path = db_path.join(QUERY_LOG_FILE);
+        let queries = if path.exists() {
+            let data = tokio::fs::read_to_string(&path)
+                .await
+                .map_err(|e| Error::StoreIo {
+                    context: format!("reading {}", path.display()),
+                    source: e,
+                })?;
+            serde_json::from_str(&data).unwrap_or_else(|e| {
+                tracing::warn!(
+                    "ignoring unreadable query log in {} ({e}); starting over",
+                    path.display()
+                );
+                HashMap::new()
+            })
+        } else {
+            HashMap::new()
+        };
+
+        Ok(Self {
+            queries: Arc::new(Mutex::new(queries)),
+            path,
+            read_only,
+        })
+    }
+
+    /// Record a search for `text` that had `hits` results, the best of them
+    /// at the `path:line` locations in `found`.
+    pub async fn record(&self, text: &str, hits: usize, found: &[String]) -> Result<()> {
+        self.record_at(text, hits, found, now()).await
+    }
+
+    async fn record_at(&self, text: &str, hits: usize, found: &[String], at: i64) -> Result<()> {
+        let text = text.trim();
+        if text.is_empty() {
+            return Ok(());
+        }
+
+        let mut queries = self.queries.lock().await;
+        let stats = queries.entry(text.to_string()).or_insert(QueryStats {
+            count: 0,
+            last_used: at,
+            hits: Some(hits),
+            found: Vec::new(),
+        });
+        stats.count += 1;
+        if at >= stats.last_used {
+            stats.last_used = at;
+            stats.hits = Some(hits);
+            stats.found = found.iter().take(MAX_FOUND).cloned().collect();
+        }
+
+        if queries.len() > MAX_QUERIES {
+            let oldest = queries
+                .iter()
+                .min_by_key(|(_, stats)| stats.last_used)
+                .map(|(text, _)| text.clone());
+            if let Some(oldest) = oldest {
+                queries.remove(&oldest);
+            }
+        }
+        self.persist_locked(&queries).await
+    }
+
+    /// Up to `limit` queries used in the last 30 days, most frequent first
+    /// and the most recent among equally frequent ones.
+    pub async fn frequent(&self, limit: usize) -> Vec<String> {
+        self.frequent_at(limit, now()).await
+    }
+
+    async fn frequent_at(&self, limit: usize, at: i64) -> Vec<String> {
+        let queries = self.queries.lock().await;
+        let mut recent: Vec<(&String, &QueryStats)> = queries
+            .iter()
+            .filter(|(_, stats)| at - stats.last_used <= RECENT_SECS)
+            .collect();
+        recent.sort_by(|(a_text, a), (b_text, b)| {
+            b.count
+                .cmp(&a.count)
+                .then(b.last_used.cmp(&a.last_used))
+                .then(a_text.cmp(b_text))
+        });
+        recent
+            .into_iter()
+            .take(limit)
+            .map(|(text, _)| text.clone())
+            .collect()
+    }
+
+    /// Up to `limit` queries, most recently used first, whose text contains
+    /// `contains` (ignoring case) if given.
+    pub async fn history(&self, limit: usize, contains: Option<&str>) -> Vec<QueryEntry> {
+        let contains = contains.map(str::to_lowercase);
+        let queries = self.queries.lock().await;
+        let mut entries: Vec<QueryEntry> = queries
+            .iter()
+            .filter(|(text, _)| {
+                contains
+                    .as_deref()
+                    .is_none_or(|part| text.to_lowercase().contains(part))
+            })
+            .map(|(text, stats)| QueryEntry {
+                text: text.clone(),
+                count: stats.count,
+                last_used: stats.last_used,
+                hits: stats.hits,
+                found: stats.found.clone(),
+            })
+            .collect();
+        entries.sort_by(|a, b| b.last_used.cmp(&a.last_used).then(a.text.cmp(&b.text)));
+        entries.truncate(limit);
+        entries
+    }
+
+    /// Persist the log to disk. Caller must hold the lock.
+    async fn persist_locked(&self, queries: &HashMap<String, QueryStats>) -> Result<()> {
+        if self.read_only {
+            return Ok(());
+        }
+        let json = serde_json::to_string(queries).map_err(Error::StoreSerde)?;
+        atomicfile::write(&self.path, json)
+            .await
+            .map_err(|e| Error::StoreIo {
+                context: format!("writing {}", self.path.display()),
+                source: e,
+            })
+    }
+}
+
+/// What is wrong with `vector`, if it isn't what the model should produce:
+/// the right size, finite, and unit length.
+fn embedding_problem(vector: &[f32]) -> Option<String> {
+    if vector.len() != EMBEDDING_DIM {
+        return Some(format!(
+            "got a {}-dimensional embedding, expected {EMBEDDING_DIM}",
+            vector.len()
+        ));
+    }
+    if vector.iter().any(|x| !x.is_finite()) {
+        return Some("embedding contains NaN or infinite values".to_string());
+    }
+    let norm = vector.iter().map(|x| x * x).sum::<f32>().sqrt();
+    ((norm - 1.0).abs() > NORM_TOLERANCE)
+        .then(|| format!("embedding has length {norm:.4} instead of 1"))
+}
+
+/// Embed and search for the `limit` most frequent recent queries in `log`,
+/// logging each query's top hit and timing. Fails on the first malformed
+/// embedding, since every real search would be wrong the same way.
+pub async fn warm_up(
+    embedder: &Embedder,
+    store: &VectorStore,
+    log: &QueryLog,
+    limit: usize,
+) -> Result<()> {
+    let queries = log.frequent(limit).await;
+    if queries.is_empty() {
+        return Ok(());
+    }
+
+    let started = Instant::now();
+    let mut missed = 0;
+    for query in &queries {
+        let query_started = Instant::now();
+        let vector = embedder.embed_query(query).await?;
+        if let Some(problem) = embedding_problem(&vector) {
+            return Err(Error::ModelCheck(format!(
+                "warm-up query {query:?}: {problem} -- delete the model from \
+                 ~/.cache/huggingface/hub so it is downloaded again"
+            )));
+        }
+        let results = store.search(&vector, 1, Filters::default(), None).await?;
+        let elapsed = query_started.elapsed();
+        match results.first() {
+            Some(hit) => tracing::info!(
+                "warm-up query {query:?}: top hit {}:{} ({elapsed:.0?})",
+                hit.file_path,
+                hit.start_line
+            ),
+            None => {
+                missed += 1;
+                tracing::warn!("warm-up query {query:?}: no results ({elapsed:.0?})");
+            }
+        }
+    }
+    tracing::info!(
+        "warm-up: {} queries in {:.0?}, {missed} without results",
+        queries.len(),
+        started.elapsed()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    const DAY: i64 = 24 * 60 * 60;
+
+    #[tokio::test]
+    async fn frequent_queries_come_first() {
+        let tmp = TempDir::new().unwrap();
+        let log = QueryLog::load(tmp.path()).await.unwrap();
+        for query in ["auth middleware", "retry loop", "auth middleware", "  "] {
+            log.record_at(query, 0, &[], 100 * DAY).await.unwrap();
+        }
+        log.record_at("parse config", 0, &[], 101 * DAY)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            log.frequent_at(5, 101 * DAY).await,
+            ["auth middleware", "parse config", "retry loop"]
+        );
+        assert_eq!(log.frequent_at(1, 101 * DAY).await, ["auth middleware"]);
+    }
+
+    #[tokio::test]
+    async fn stale_queries_are_not_replayed() {
+        let tmp = TempDir::new().unwrap();
+        let log = QueryLog::load(tmp.path()).await.unwrap();
+        log.record_at("old question", 0, &[], 0).await.unwrap();
+        log.record_at("old question", 0, &[], 0).await.unwrap();
+        log.record_at("new question", 0, &[], 40 * DAY)
+            .await
+            .unwrap();
+
+        assert_eq!(log.frequent_at(5, 40 * DAY).await, ["new question"]);
+    }
+
+    #[tokio::test]
+    async fn least_recent_query_is_evicted() {
+        let tmp = TempDir::new().unwrap();
+        let log = QueryLog::load(tmp.path()).await.unwrap();
+        for i in 0..=MAX_QUERIES {
+            log.record_at(&format!("query {i}"), 0, &[], i as i64)
+                .await
+                .unwrap();
+        }
+
+        let kept = log.frequent_at(MAX_QUERIES + 1, MAX_QUERIES as i64).await;
+        assert_eq!(kept.len(), MAX_QUERIES);
+        assert!(!kept.contains(&"query 0".to_string()));
+    }
+
+    #[tokio::test]
+    async fn log_survives_reload_unless_read_only() {
+        let tmp = TempDir::new().unwrap();
+        QueryLog::load(tmp.path())
+            .await
+            .unwrap()
+            .record("find the handler", 0, &[])
+            .await
+            .unwrap();
+        let read_only = QueryLog::load_read_only(tmp.path()).await.unwrap();
+        read_only.record("only in memory", 0, &[]).await.unwrap();
+        assert_eq!(read_only.frequent(5).await.len(), 2);
+
+        let log = QueryLog::load(tmp.path()).await.unwrap();
+        assert_eq!(log.frequent(5).await, ["find the handler"]);
+    }
+
+    #[tokio::test]
+    async fn history_lists_recent_queries_with_their_hits() {
+        let tmp = TempDir::new().unwrap();
+        let log = QueryLog::load(tmp.path()).await.unwrap();
+        let found: Vec<String> = ["src/auth.rs:10", "src/a.rs:1", "src/b.rs:2", "src/c.rs:3"]
+            .map(String::from)
+            .to_vec();
+        log.record_at("auth middleware", 4, &found, 100 * DAY)
+            .await
+            .unwrap();
+        log.record_at("retry loop", 0, &[], 101 * DAY)
+            .await
+            .unwrap();
+        // An older search than the one recorded doesn't replace its hits
+        log.record_at("auth middleware", 0, &[], 99 * DAY)
+            .await
+            .unwrap();
+
+        let history = log.history(5, None).await;
+        assert_eq!(
+            history,
+            [
+                QueryEntry {
+                    text: "retry loop".to_string(),
+                    count: 1,
+                    last_used: 101 * DAY,
+                    hits: Some(0),
+                    found: vec![],
+                },
+                QueryEntry {
+                    text: "auth middleware".to_string(),
+                    count: 2,
+                    last_used: 100 * DAY,
+                    hits: Some(4),
+                    found: found[..MAX_FOUND].to_vec(),
+                },
+            ]
+        );
+        assert_eq!(log.history(1, None).await[0].text, "retry loop");
+        let auth = log.history(5, Some("AUTH")).await;
+        assert_eq!(auth.len(), 1);
+        assert_eq!(auth[0].text, "auth middleware");
+
+        // Hits survive a reload
+        let reloaded = QueryLog::load(tmp.path()).await.unwrap();
+        assert_eq!(reloaded.history(5, None).await, history);
+    }
+
+    #[tokio::test]
+    async fn logs_saved_without_hits_still_load() {
+        let tmp = TempDir::new().unwrap();
+        std::fs::write(
+            tmp.path().join(QUERY_LOG_FILE),
+            r#"{"parse config":{"count":2,"last_used":5}}"#,
+        )
+        .unwrap();
+        let log = QueryLog::load(tmp.path()).await.unwrap();
+        let history = log.history(5, None).await;
+        assert_eq!(history.len(), 1);
+        assert_eq!((history[0].count, history[0].hits), (2, None));
+    }
+
+    #[tokio::test]
+    async fn truncated_log_starts_over() {
+        let tmp = TempDir::new().unwrap();
+        std::fs::write(tmp.path().join(QUERY_LOG_FILE), r#"{"parse config":{"co"#).unwrap();
+        let log = QueryLog::load(tmp.path()).await.unwrap();
+        assert!(log.frequent(5).await.is_empty());
+        log.record("parse config", 1, &[]).await.unwrap();
+        let log = QueryLog::load(tmp.path()).await.unwrap();
+        assert_eq!(log.frequent(5).await, ["parse config"]);
+    }
+
+    #[test]
+    fn embedding_checks() {
+        let mut vector = vec![0.0; EMBEDDING_DIM];
+        vector[0] = 1.0;
+        assert_eq!(embedding_problem(&vector), None);
+
+        vector[0] = 0.5;
+        assert!(
+            embedding_problem(&vector)
+                .unwrap()
+                .contains("length 0.5000")
+        );
+        vector[0] = f32::NAN;
+        assert!(embedding_problem(&vector).unwrap().contains("NaN"));
+        assert!(embedding_problem(&[1.0]).unwrap().contains("1-dimensional"));
+    }
+}
@@ -0,0 +1,222 @@
+//! Periodic background reindexing for long-running servers.
+//!
+//! A server left running for days only learns about edits made through
+//! `refresh_file`; branch switches, pulls, and edits from other tools
+//! drift out of the index until someone asks for a `reindex`. With
+//! `[indexing] reindex_interval_mins` set, a scheduler task reindexes on its
+//! own, optionally only once enough indexed files have changed on disk.
+
+use std::collections::BTreeSet;
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use crate::config::IndexingConfig;
+use crate::indexer::Indexer;
+use crate::metrics::metrics;
+use crate::store::VectorStore;
+
+/// Largest share of the interval a wake-up is moved earlier or later by.
+const JITTER: f64 = 0.1;
+
+/// When the scheduler wakes up and whether it then reindexes.
+#[derive(Debug, PartialEq)]
+pub struct ReindexSchedule {
+    interval: Duration,
+    stale_files: usize,
+}
+
+impl ReindexSchedule {
+    /// The schedule `config` asks for, or `None` if periodic reindexing is
+    /// off.
+    pub fn from_config(config: &IndexingConfig) -> Option<Self> {
+        (config.reindex_interval_mins > 0).then(|| Self {
+            interval: Duration::from_secs(config.reindex_interval_mins * 60),
+            stale_files: config.reindex_stale_files,
+        })
+    }
+
+    /// The interval moved by `spread` (from -1 to 1) times [`JITTER`], so
+    /// servers started together don't all reindex at once.
+    fn delay(&self, spread: f64) -> Duration {
+        self.interval
+            .mul_f64(1.0 + JITTER * spread.clamp(-1.0, 1.0))
+    }
+}
+
+/// A fresh value from -1 to 1, without pulling in a random number crate:
+/// std seeds each `RandomState` randomly.
+fn random_spread() -> f64 {
+    let bits = RandomState::new().hash_one(SystemTime::now());
+    (bits as f64 / u64::MAX as f64) * 2.0 - 1.0
+}
+
+/// The indexed files under `root` modified or deleted since they were
+/// indexed: those whose modification time on disk is no longer the one
+/// indexed. New files aren't listed; they show up at the next reindex.
+pub async fn stale_files(store: &VectorStore, root: &Path) -> BTreeSet<String> {
+    stale_among(root, store.file_mtimes().await).await
+}
+
+/// Those of `files`, paths under `root` with the modification time each
+/// was indexed at, that were modified or deleted since. They are looked up
+/// in one blocking task rather than one per file.
+pub async fn stale_among(
+    root: &Path,
+    files: impl IntoIterator<Item = (String, i64)>,
+) -> BTreeSet<String> {
+    let root = root.to_path_buf();
+    let files: Vec<(String, i64)> = files.into_iter().collect();
+    tokio::task::spawn_blocking(move || {
+        files
+            .into_iter()
+            .filter(|(path, indexed)| {
+                let modified = std::fs::metadata(root.join(path))
+                    .and_then(|m| m.modified())
+                    .ok()
+                    .and_then(|t| t.duration_since(SystemTime::UNIX_EPOCH).ok())
+                    .map(|d| d.as_secs() as i64);
+                modified != Some(*indexed)
+            })
+            .map(|(path, _)| path)
+            .collect()
+    })
+    .await
+    .unwrap_or_else(|e| {
+        tracing::warn!("failed to look for stale files: {e}");
+        BTreeSet::new()
+    })
+}
+
+/// Reindex `root` on `schedule` until the process exits. Failures are
+/// logged and retried at the next wake-up.
+pub async fn run(indexer: Indexer, store: VectorStore, root: PathBuf, schedule: ReindexSchedule) {
+    tracing::info!(
+        "reindexing every {} minutes{}",
+        schedule.interval.as_secs() / 60,
+        if schedule.stale_files > 0 {
+            format!(" once {} indexed files have changed", schedule.stale_files)
+        } else {
+            String::new()
+        }
+    );
+    loop {
+        tokio::time::sleep(schedule.delay(random_spread())).await;
+
+        if schedule.stale_files > 0 {
+            let stale = stale_files(&store, &root).await.len();
+            if stale < schedule.stale_files {
+                tracing::debug!("scheduled reindex skipped: {stale} indexed files changed");
+                continue;
+            }
+            tracing::info!("scheduled reindex: {stale} indexed files changed");
+        } else {
+            tracing::info!("scheduled reindex of {}", root.display());
+        }
+
+        if let Err(e) = indexer.update(&root).await {
+            tracing::error!("scheduled reindex failed: {e:#}");
+            metrics().record_index_error();
+            indexer.progress().fail_run(format!("{e:#}"));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::embed::EMBEDDING_DIM;
+    use crate::store::ChunkRow;
+    use tempfile::TempDir;
+
+    fn mtime(path: &Path) -> i64 {
+        std::fs::metadata(path)
+            .unwrap()
+            .modified()
+            .unwrap()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as i64
+    }
+
+    fn row(file_path: &str, last_modified: i64) -> ChunkRow {
+        let mut vector = vec![0.0; EMBEDDING_DIM];
+        vector[0] = 1.0;
+        ChunkRow {
+            file_path: file_path.to_string(),
+            content: "package a".to_string(),
+            language: "go".to_string(),
+            start_line: 1,
+            end_line: 1,
+            last_modified,
+            vector,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn schedule_follows_config() {
+        let mut config = IndexingConfig::default();
+        assert_eq!(ReindexSchedule::from_config(&config), None);
+
+        config.reindex_interval_mins = 30;
+        config.reindex_stale_files = 5;
+        let schedule = ReindexSchedule::from_config(&config).unwrap();
+        assert_eq!(schedule.stale_files, 5);
+        assert_eq!(schedule.delay(0.0), Duration::from_secs(30 * 60));
+        assert_eq!(schedule.delay(-1.0), Duration::from_secs(27 * 60));
+        assert_eq!(schedule.delay(5.0), Duration::from_secs(33 * 60));
+    }
+
+    #[test]
+    fn spread_stays_in_range() {
+        for _ in 0..100 {
+            let spread = random_spread();
+            assert!((-1.0..=1.0).contains(&spread), "{spread}");
+        }
+    }
+
+    #[tokio::test]
+    async fn counts_modified_and_deleted_files() {
+        let root = TempDir::new().unwrap();
+        let db = TempDir::new().unwrap();
+        let store = VectorStore::new(db.path().to_str().unwrap(), &Default::default())
+            .await
+            .unwrap();
+        for name in ["same.go", "edited.go"] {
+            std::fs::write(root.path().join(name), "package a\n").unwrap();
+        }
+        let same = mtime(&root.path().join("same.go"));
+        let edited = mtime(&root.path().join("edited.go")) - 60;
+        store
+            .insert(vec![
+                row("same.go", same),
+                row("edited.go", edited),
+                row("deleted.go", same),
+            ])
+            .await
+            .unwrap();
+        store
+            .add_note("not a file", row("", 0).vector)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            stale_files(&store, root.path()).await,
+            BTreeSet::from(["deleted.go".to_string(), "edited.go".to_string()])
+        );
+        // Only the files asked about are looked up
+        assert_eq!(
+            stale_among(
+                root.path(),
+                [
+                    ("same.go".to_string(), same),
+                    ("edited.go".to_string(), edited)
+                ]
+            )
+            .await,
+            BTreeSet::from(["edited.go".to_string()])
+        );
+    }
+}
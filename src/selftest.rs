@@ -0,0 +1,287 @@
+//! A one-call check that the server works end to end.
+//!
+//! The `self_test` tool embeds a canary string, searches the index, then
+//! stores the canary as a chunk, searches for it, and deletes it again,
+//! timing each step, so a client can tell right after startup whether the
+//! model, the index, and search all work. The canary goes into an
+//! in-memory store with the index's settings rather than the index itself,
+//! so the test leaves nothing behind and saves nothing, and works the same
+//! on a read-only index.
+
+use std::fmt;
+use std::future::Future;
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::config::IndexConfig;
+use crate::embed::Embedder;
+use crate::store::{ChunkRow, Filters, VectorStore};
+
+/// Text embedded and searched for.
+pub const CANARY: &str = "claudevil self-test canary: verify that embedding, storing, and \
+                          searching chunks round-trips";
+
+/// File path and language the canary is stored under.
+pub const SELF_TEST_PATH: &str = "<self-test>";
+pub const SELF_TEST_LANGUAGE: &str = "self-test";
+
+/// Steps slower than this are flagged, though they still pass.
+const SLOW_STEP: Duration = Duration::from_secs(2);
+
+/// How one step of the test went.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    Passed,
+    /// Not run, for this reason.
+    Skipped(String),
+    Failed(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct Step {
+    pub name: &'static str,
+    pub elapsed: Duration,
+    pub outcome: Outcome,
+}
+
+/// The steps run, in order. The test stops at the first that fails,
+/// except that a stored canary is always deleted.
+#[derive(Debug, Clone, Default)]
+pub struct SelfTest {
+    pub steps: Vec<Step>,
+}
+
+impl SelfTest {
+    pub fn passed(&self) -> bool {
+        self.steps
+            .iter()
+            .all(|step| !matches!(step.outcome, Outcome::Failed(_)))
+    }
+
+    /// Run `step`, recording how long it took and whether it failed;
+    /// its value if it didn't.
+    async fn step<T>(
+        &mut self,
+        name: &'static str,
+        step: impl Future<Output = Result<T, String>>,
+    ) -> Option<T> {
+        let started = Instant::now();
+        let result = step.await;
+        let outcome = match &result {
+            Ok(_) => Outcome::Passed,
+            Err(e) => Outcome::Failed(e.clone()),
+        };
+        self.steps.push(Step {
+            name,
+            elapsed: started.elapsed(),
+            outcome,
+        });
+        result.ok()
+    }
+
+    fn skip(&mut self, name: &'static str, reason: &str) {
+        self.steps.push(Step {
+            name,
+            elapsed: Duration::ZERO,
+            outcome: Outcome::Skipped(reason.to_string()),
+        });
+    }
+}
+
+impl fmt::Display for SelfTest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let total: Duration = self.steps.iter().map(|step| step.elapsed).sum();
+        let verdict = if self.passed() { "passed" } else { "FAILED" };
+        writeln!(f, "Self-test {verdict} in {} ms:", total.as_millis())?;
+        for step in &self.steps {
+            let ms = step.elapsed.as_millis();
+            match &step.outcome {
+                Outcome::Passed if step.elapsed > SLOW_STEP => writeln!(
+                    f,
+                    "- {}: ok, but slow ({ms} ms, over {} ms)",
+                    step.name,
+                    SLOW_STEP.as_millis()
+                )?,
+                Outcome::Passed => writeln!(f, "- {}: ok ({ms} ms)", step.name)?,
+                Outcome::Skipped(reason) => writeln!(f, "- {}: skipped, {reason}", step.name)?,
+                Outcome::Failed(error) => {
+                    writeln!(f, "- {}: failed after {ms} ms: {error}", step.name)?
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Embed the canary and search `store` for it, then store, find, and
+/// delete it in an in-memory store set up by `config`.
+pub async fn run(embedder: &Embedder, store: &VectorStore, config: &IndexConfig) -> SelfTest {
+    let mut test = SelfTest::default();
+    let passage = test
+        .step("embed canary", async {
+            embedder
+                .embed_passage(CANARY)
+                .await
+                .map_err(|e| e.to_string())
+        })
+        .await;
+    let query = test
+        .step("embed query", async {
+            embedder
+                .embed_query(CANARY)
+                .await
+                .map_err(|e| e.to_string())
+        })
+        .await;
+    let (Some(passage), Some(query)) = (passage, query) else {
+        test.skip("search index", "nothing to search for");
+        test.skip("store canary", "nothing to store");
+        return test;
+    };
+    // The index failing a search doesn't stop the canary from telling
+    // whether search itself works
+    test.step("search index", async {
+        store
+            .search(&query, 1, Filters::default(), None)
+            .await
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    })
+    .await;
+    let scratch = test
+        .step("open scratch store", async {
+            VectorStore::in_memory(config).map_err(|e| e.to_string())
+        })
+        .await;
+    if let Some(scratch) = scratch {
+        round_trip(&mut test, &scratch, passage, query).await;
+    }
+    test
+}
+
+/// Store `passage` as the canary in the empty `store`, check that a
+/// search for `query` finds it, and delete it, recording each step in
+/// `test`.
+async fn round_trip(test: &mut SelfTest, store: &VectorStore, passage: Vec<f32>, query: Vec<f32>) {
+    let Some(key) = test
+        .step("store canary", async {
+            let keys = store
+                .insert(vec![canary_row(passage)])
+                .await
+                .map_err(|e| e.to_string())?;
+            keys.first()
+                .copied()
+                .ok_or_else(|| "the store returned no key for the canary".to_string())
+        })
+        .await
+    else {
+        return;
+    };
+    let found = test
+        .step("search canary", async {
+            match top_canary(store, &query).await? {
+                Some(top) if top == key => Ok(()),
+                Some(top) => Err(format!("expected canary #{key} first, got #{top}")),
+                None => Err(format!("canary #{key} was stored but not found")),
+            }
+        })
+        .await;
+    let deleted = test
+        .step("delete canary", async {
+            store
+                .delete_file(SELF_TEST_PATH)
+                .await
+                .map_err(|e| e.to_string())
+        })
+        .await;
+    if found.is_some() && deleted.is_some() {
+        test.step("check deleted", async {
+            match top_canary(store, &query).await? {
+                Some(top) => Err(format!("canary #{top} is still found after deleting it")),
+                None => Ok(()),
+            }
+        })
+        .await;
+    }
+}
+
+/// The key of the stored canary closest to `query`, if any.
+async fn top_canary(store: &VectorStore, query: &[f32]) -> Result<Option<u64>, String> {
+    let hits = store
+        .search(query, 1, Filters::default(), None)
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(hits.first().map(|hit| hit.id))
+}
+
+fn canary_row(vector: Vec<f32>) -> ChunkRow {
+    let now = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs() as i64;
+    ChunkRow {
+        file_path: SELF_TEST_PATH.to_string(),
+        content: CANARY.to_string(),
+        language: SELF_TEST_LANGUAGE.to_string(),
+        start_line: 1,
+        end_line: 1,
+        last_modified: now,
+        vector,
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::embed::EMBEDDING_DIM;
+
+    fn unit_vector(idx: usize) -> Vec<f32> {
+        let mut v = vec![0.0f32; EMBEDDING_DIM];
+        v[idx] = 1.0;
+        v
+    }
+
+    #[tokio::test]
+    async fn canary_round_trips_in_memory() {
+        let store = VectorStore::in_memory(&Default::default()).unwrap();
+        let mut test = SelfTest::default();
+        round_trip(&mut test, &store, unit_vector(0), unit_vector(0)).await;
+        assert!(test.passed(), "{test}");
+        let names: Vec<&str> = test.steps.iter().map(|step| step.name).collect();
+        assert_eq!(
+            names,
+            [
+                "store canary",
+                "search canary",
+                "delete canary",
+                "check deleted"
+            ]
+        );
+        assert_eq!(store.chunk_count().await.unwrap(), 0);
+        assert!(store.saved_at().is_none());
+        assert!(test.to_string().starts_with("Self-test passed"));
+    }
+
+    #[test]
+    fn failures_and_skips_are_reported() {
+        let test = SelfTest {
+            steps: vec![
+                Step {
+                    name: "embed canary",
+                    elapsed: Duration::from_millis(3),
+                    outcome: Outcome::Failed("model missing".to_string()),
+                },
+                Step {
+                    name: "store canary",
+                    elapsed: Duration::ZERO,
+                    outcome: Outcome::Skipped("the index is read-only".to_string()),
+                },
+            ],
+        };
+        assert!(!test.passed());
+        let report = test.to_string();
+        assert!(report.starts_with("Self-test FAILED"), "{report}");
+        assert!(report.contains("- embed canary: failed after 3 ms: model missing"));
+        assert!(report.contains("- store canary: skipped, the index is read-only"));
+    }
+}
@@ -1,17 +1,90 @@
-use std::path::PathBuf;
+//! The MCP server: the tools agents call, over the engine.
+
+use std::collections::{HashMap, HashSet};
+use std::path::{Component, Path, PathBuf};
 use std::sync::Arc;
+use std::time::{Instant, SystemTime};
 
 use rmcp::handler::server::router::tool::ToolRouter;
+use rmcp::handler::server::tool::ToolCallContext;
 use rmcp::handler::server::wrapper::Parameters;
 use rmcp::model::*;
-use rmcp::{ErrorData as McpError, ServerHandler, schemars, tool, tool_handler, tool_router};
+use rmcp::service::RequestContext;
+use rmcp::{ErrorData as McpError, RoleServer, ServerHandler, schemars, tool, tool_router};
 use serde::Deserialize;
+use tokio::sync::Semaphore;
+use tracing::Instrument;
+
+use crate::audit::AuditLog;
+use crate::buildtags::PLATFORMS;
+use crate::chunker::{FALLBACK_LANGUAGE, Imports, TreeSitterChunker};
+use crate::codeowners::normalize_owner;
+use crate::completion::{self, Argument};
+use crate::config::{Config, EmbeddingConfig, GeneratedFiles, Links, Namespace};
+use crate::context::{
+    DISTRIBUTION_CANDIDATES, Owner, ScoreDistribution, assemble, clip_lines, group_by_file,
+    line_range, method_owner, pack, pack_sections, score_distribution, symbol_hints,
+};
+use crate::deps;
+use crate::embed::{EMBEDDING_DIM, Embedder, MAX_SEQ_LEN};
+use crate::encoding;
+use crate::error::{Error, ErrorClass};
+use crate::indexer::{FileUpdate, Indexer};
+use crate::metrics::metrics;
+use crate::preprocess::{is_same_code, preprocess_code};
+use crate::progress::IndexProgress;
+use crate::querycache::QueryCache;
+use crate::querylog::{MAX_FOUND, QueryEntry, QueryLog, warm_up};
+use crate::schedule::{stale_among, stale_files};
+use crate::selftest;
+use crate::stacktrace::{match_indexed, parse_frames};
+use crate::store::{
+    COMMITS_LANGUAGE, DEPS_PATH, FileHit, Filters, Focus, Location, Modified, NOTES_LANGUAGE,
+    QueryText, Scope, SearchResult, SymbolEntry, SymbolQuery, VectorStore,
+};
+use crate::timespec;
+use crate::usage::UsageTracker;
+
+/// How many extra candidates to fetch per requested result when a ranking
+/// boost may reorder them.
+const BOOST_OVERSAMPLE: usize = 2;
+
+/// How many candidates `find_similar` fetches per requested result, so
+/// some are left after dropping the snippet's own file and copies.
+const SIMILAR_OVERSAMPLE: usize = 2;
+
+/// Semantic search hits considered by `gather_context`.
+const CONTEXT_SEARCH_RESULTS: usize = 20;
+
+/// Symbol lookups per name mentioned in a `gather_context` task.
+const CONTEXT_SYMBOL_RESULTS: usize = 3;
+
+/// Frames `resolve_stacktrace` resolves unless asked for more.
+const DEFAULT_STACK_FRAMES: usize = 10;
+
+/// Symbol names listed per file by `search_files` before summarizing the rest.
+const FILE_SYMBOLS_SHOWN: usize = 8;
+
+/// Symbols listed per `list_symbols` page unless the client asks otherwise.
+const DEFAULT_SYMBOLS_PAGE: usize = 100;
+
+/// Queries `query_history` lists unless the client asks otherwise.
+const DEFAULT_HISTORY_LIMIT: usize = 20;
+
+/// Extensions `coverage_report` lists per group unless the client asks otherwise.
+const DEFAULT_COVERAGE_LIMIT: usize = 10;
+
+/// Lines of a type definition shown as context for its methods.
+const TYPE_CONTEXT_LINES: usize = 30;
+
+/// Heading of the context `with_context` adds after the results.
+const CONTEXT_HEADING: &str = "# Context\n\n";
+
+/// Skipped files listed by `index_status` before summarizing the rest.
+const SKIPPED_FILES_SHOWN: usize = 10;
 
-use crate::chunker::TreeSitterChunker;
-use crate::config::Config;
-use crate::embed::Embedder;
-use crate::indexer::Indexer;
-use crate::store::VectorStore;
+/// Tools that modify the index; hidden and refused when it is read-only.
+const MUTATING_TOOLS: &[&str] = &["reindex", "refresh_file", "add_note", "delete_note"];
 
 #[derive(Debug, Deserialize, schemars::JsonSchema)]
 pub struct SearchParams {
@@ -19,21 +92,91 @@ pub struct SearchParams {
     pub query: String,
     /// Filter by programming language (e.g. "go", "rust", "python"). If omitted, searches all languages.
     pub language: Option<String>,
-    /// Maximum number of results to return (default: 10).
+    /// Filter by chunk kind: "commit" for indexed commit messages, "note" for notes, or a symbol kind like "function_item". If omitted, searches all kinds.
+    pub kind: Option<String>,
+    /// Maximum number of results to return (default: 10, or as configured by the server).
+    pub limit: Option<usize>,
+    /// Show at most this many lines of each result (default: whole chunks, or as configured by the server).
+    pub max_snippet_lines: Option<usize>,
+    /// Show each result's distance from the query, lower is closer (default: true, or as configured by the server).
+    pub show_distance: Option<bool>,
+    /// "code" to favor implementations, "docs" to favor heavily commented or documented chunks (default: no preference).
+    pub focus: Option<Focus>,
+    /// Return as many top results as fit in about this many tokens, cutting the last one short at a line boundary (default: no budget).
+    pub max_tokens: Option<usize>,
+    /// "project" to search the project's own code (default), "dependencies" to search indexed dependency source, e.g. to see how a library implements something, or "all".
+    pub scope: Option<Scope>,
+    /// Only one part of the index: "code", "docs", "notes", "commits", or "dependencies" (default: all the scope covers).
+    pub namespace: Option<Namespace>,
+    /// Only chunks whose file (or note, or commit) changed at or after this time: a date like "2024-05-01", or an age like "7d", "12h", or "2w" for that long ago.
+    pub modified_after: Option<String>,
+    /// Only chunks whose file (or note, or commit) changed before this time, in the same forms as modified_after.
+    pub modified_before: Option<String>,
+    /// Return one line per result, its location and declaration, instead of the code. Costs a fraction of the context; read the ones that look right afterwards (default: false).
+    pub compact: Option<bool>,
+    /// Gather results under one heading per file, in line order, with results that touch or overlap shown once as a single range. Reads better when several results come from the same file (default: false).
+    pub group_by_file: Option<bool>,
+    /// Only code that builds on this operating system: "linux", "macos", "windows", "android", "ios", "freebsd", "netbsd", or "openbsd". Use it when a function has one implementation per OS (Go `//go:build` lines and `_windows.go` files, Rust `#[cfg(...)]`); code without such constraints always matches.
+    pub platform: Option<String>,
+    /// Only code owned by this team or user under the repository's CODEOWNERS, e.g. "@acme/payments" (the "@" may be left out). Results show their owners either way.
+    pub owner: Option<String>,
+    /// Only code in exactly this package or module, as results show it: a Go import path ("github.com/acme/api/handlers"), a Rust module path ("mycrate::store"), or a Python dotted module ("acme.billing.invoice").
+    pub module: Option<String>,
+    /// Compare the query against every chunk instead of searching the approximate index, so no close match is missed (default: the server's [index] exact setting). Only indexes of up to [index] exact_max_chunks chunks (50,000 by default) are searched exactly; larger ones ignore this.
+    pub exact: Option<bool>,
+    /// Also report how far the top 100 candidates are from the query: min, median, and max distance, and the distance at ranks 1, 5, 10, 50, and 100. A best result far below the median is a strong match; one close to it means nothing matched well (default: false).
+    pub score_distribution: Option<bool>,
+    /// Also return each result file's imports and, for results that are methods, the definition of their type (a Go receiver, a Rust impl's type, a Python class), each once. Saves reading the files to find out what a type holds or where a name comes from. With max_tokens, it gets what the results leave of the budget, and pieces that don't fit are left out (default: false).
+    pub with_context: Option<bool>,
+}
+
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+pub struct SearchFilesParams {
+    /// Natural language description of what the file deals with, e.g. "billing" or "database migrations".
+    pub query: String,
+    /// Filter by programming language (e.g. "go", "rust", "python"). If omitted, searches all languages.
+    pub language: Option<String>,
+    /// Maximum number of files to return (default: 10, or as configured by the server).
     pub limit: Option<usize>,
 }
 
 #[derive(Debug, Deserialize, schemars::JsonSchema)]
 pub struct IndexStatusParams {}
 
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+pub struct IndexErrorsParams {}
+
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+pub struct SelfTestParams {}
+
 #[derive(Debug, Deserialize, schemars::JsonSchema)]
 pub struct FindSymbolParams {
-    /// Symbol name to search for (case-insensitive substring match).
+    /// Symbol name to search for: a case-insensitive substring, or a pattern with `*` wildcards matching the whole name (e.g. "New*"). May also hold `kind:` and `path:` terms to narrow it, e.g. "kind:method path:pkg/auth New*".
     pub name: String,
-    /// Filter by symbol kind (e.g. "function_declaration", "struct_item", "class_definition").
+    /// Filter by symbol kind, exactly or by one word of it (e.g. "function_declaration", "method", "struct").
     pub kind: Option<String>,
+    /// Only symbols in this part of the index: "code", "docs", or "dependencies" (default: all).
+    pub namespace: Option<Namespace>,
     /// Maximum number of results to return (default: 20).
     pub limit: Option<usize>,
+    /// Show at most this many lines of each result (default: whole chunks, or as configured by the server).
+    pub max_snippet_lines: Option<usize>,
+}
+
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+pub struct ListSymbolsParams {
+    /// Only list symbols in this file or under this directory, relative to the indexed root (e.g. "src/store" or "src/store.rs").
+    pub path: Option<String>,
+    /// Filter by programming language (e.g. "go", "rust", "python"). If omitted, lists all languages.
+    pub language: Option<String>,
+    /// Filter by symbol kind (e.g. "function_declaration", "struct_item", "class_definition").
+    pub kind: Option<String>,
+    /// Only symbols in this part of the index, e.g. "dependencies" (default: the project's code and docs).
+    pub namespace: Option<Namespace>,
+    /// Number of symbols to skip, to fetch the next page (default: 0).
+    pub offset: Option<usize>,
+    /// Maximum number of symbols to return (default: 100, or as configured by the server).
+    pub limit: Option<usize>,
 }
 
 #[derive(Debug, Deserialize, schemars::JsonSchema)]
@@ -46,19 +189,85 @@ pub struct ListFilesParams {
 pub struct ReadFileParams {
     /// File path relative to the indexed root directory.
     pub path: String,
+    /// Return only this symbol's source instead of the whole file, e.g. "parse_config" or "Point::new" as listed by find_symbol.
+    pub symbol: Option<String>,
 }
 
 #[derive(Debug, Deserialize, schemars::JsonSchema)]
 pub struct ReindexParams {}
 
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+pub struct RefreshFileParams {
+    /// File path relative to the indexed root directory.
+    pub path: String,
+}
+
 #[derive(Debug, Deserialize, schemars::JsonSchema)]
 pub struct FindSimilarParams {
     /// Code snippet to find similar chunks for.
     pub code: String,
     /// Filter by programming language (e.g. "go", "rust", "python"). If omitted, searches all languages.
     pub language: Option<String>,
-    /// Maximum number of results to return (default: 10).
+    /// Maximum number of results to return (default: 10, or as configured by the server).
+    pub limit: Option<usize>,
+    /// Show at most this many lines of each result (default: whole chunks, or as configured by the server).
+    pub max_snippet_lines: Option<usize>,
+    /// Show each result's distance from the query, lower is closer (default: true, or as configured by the server).
+    pub show_distance: Option<bool>,
+    /// Leave out results from this file, or from every file under this directory, such as the file the snippet was copied from. Relative to the indexed root (an absolute path inside the root also works). Results that are the snippet itself are left out wherever they are.
+    pub exclude_path: Option<String>,
+}
+
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+pub struct GatherContextParams {
+    /// Description of the task you need code context for. Symbol names in backticks or written as snake_case/CamelCase are also looked up by name.
+    pub task: String,
+    /// Filter by programming language (e.g. "go", "rust", "python"). If omitted, searches all languages.
+    pub language: Option<String>,
+    /// Approximate token budget for the returned bundle (default: 4000).
+    pub max_tokens: Option<usize>,
+}
+
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+pub struct SymbolAtParams {
+    /// File path relative to the indexed root directory (an absolute path inside the root also works).
+    pub path: String,
+    /// 1-based line number, e.g. from a stack trace or compiler error.
+    pub line: i64,
+}
+
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+pub struct ResolveStacktraceParams {
+    /// A Go, Rust, or Python stack trace or panic message, pasted as-is.
+    pub trace: String,
+    /// Maximum number of frames to resolve, in the order the trace lists them (default: 10).
+    pub max_frames: Option<usize>,
+}
+
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+pub struct QueryHistoryParams {
+    /// Maximum number of queries to return, most recent first (default: 20).
     pub limit: Option<usize>,
+    /// Only queries containing this text, ignoring case.
+    pub contains: Option<String>,
+}
+
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+pub struct CoverageReportParams {
+    /// Maximum number of extensions to list in each group, largest first (default: 10).
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+pub struct AddNoteParams {
+    /// The note: a design decision, TODO, or finding worth remembering. Keep it short and self-contained.
+    pub text: String,
+}
+
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+pub struct DeleteNoteParams {
+    /// Id of the note, shown after '#' in search results.
+    pub id: u64,
 }
 
 #[derive(Clone)]
@@ -68,40 +277,590 @@ pub struct ClaudevilServer {
     chunker: Arc<TreeSitterChunker>,
     config: Config,
     root: PathBuf,
+    usage: UsageTracker,
+    queries: QueryLog,
+    /// Embeddings of recent queries, under `[server] query_cache_size`.
+    query_cache: QueryCache,
+    /// Earlier models whose chunks the index still holds, by version,
+    /// loaded to embed queries into their spaces; `None` for one that
+    /// couldn't be loaded.
+    earlier: Arc<tokio::sync::Mutex<HashMap<String, Option<Embedder>>>>,
+    /// The process holding the index's write lock, when that is why this
+    /// server is read-only.
+    lock_holder: Option<String>,
+    /// Progress and failures of background indexing.
+    progress: IndexProgress,
+    /// Turns to run a tool call, under `[server] max_concurrent_tools`.
+    permits: Option<Arc<Semaphore>>,
+    /// Where tool calls are recorded, under `[server] audit_log`.
+    audit: Option<AuditLog>,
     tool_router: ToolRouter<Self>,
 }
 
-/// Format search results into a markdown string.
-fn format_results(results: &[crate::store::SearchResult], show_distance: bool) -> String {
+/// Writes result locations as plain paths or, under `[server] links`, as
+/// Markdown links to the file.
+struct Linker<'a> {
+    links: Links,
+    root: &'a Path,
+}
+
+impl Linker<'_> {
+    /// `text` linking to line `line` of `path`, relative to the root. Notes,
+    /// commits, and dependency source aren't files under the root and stay
+    /// plain.
+    fn link(&self, text: &str, path: &str, line: i64) -> String {
+        if self.links == Links::None || path.starts_with('<') {
+            return text.to_string();
+        }
+        let absolute = encode_path(&self.root.join(path));
+        match self.links {
+            Links::None => text.to_string(),
+            Links::File => format!("[{text}](file://{absolute})"),
+            Links::Vscode => format!("[{text}](vscode://file{absolute}:{line})"),
+        }
+    }
+
+    /// `path:start-end` of a result, linked.
+    fn location(&self, result: &SearchResult) -> String {
+        let text = format!(
+            "{}:{}-{}",
+            result.file_path, result.start_line, result.end_line
+        );
+        self.link(&text, &result.file_path, result.start_line)
+    }
+
+    /// `Also at: path:start-end, ...` for the other copies of a chunk,
+    /// linked; empty if there are none.
+    fn copies(&self, copies: &[Location]) -> String {
+        if copies.is_empty() {
+            return String::new();
+        }
+        let locations: Vec<String> = copies
+            .iter()
+            .map(|c| {
+                let text = format!("{}:{}-{}", c.file_path, c.start_line, c.end_line);
+                self.link(&text, &c.file_path, c.start_line)
+            })
+            .collect();
+        format!("Also at: {}\n", locations.join(", "))
+    }
+}
+
+/// `path` with everything but unreserved characters and `/`
+/// percent-encoded, for use in a URI.
+fn encode_path(path: &Path) -> String {
+    let mut encoded = String::new();
+    for &byte in path.to_string_lossy().as_bytes() {
+        if byte.is_ascii_alphanumeric() || b"-._~/".contains(&byte) {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{byte:02X}"));
+        }
+    }
+    encoded
+}
+
+/// Format search results into a markdown string. Each heading ends with the
+/// chunk's stable id (`#42`), which stays valid across edits to the file,
+/// and is followed by where else the same text is, and the chunk's
+/// documentation, if any, quoted. Chunks longer than `max_lines` are cut
+/// short.
+fn format_results(
+    links: &Linker<'_>,
+    results: &[SearchResult],
+    show_distance: bool,
+    max_lines: Option<usize>,
+) -> String {
     let mut output = String::new();
     for result in results {
-        let symbol_info = match (&result.symbol_kind, &result.symbol_name) {
-            (Some(kind), Some(name)) => format!(" ({kind} {name})"),
-            _ => String::new(),
+        let content = clip_lines(&result.content, max_lines);
+        let mut symbol_info = match &result.build {
+            Some(build) => format!(" [build: {build}]"),
+            None => String::new(),
         };
+        symbol_info.push_str(&owners_note(&result.owners));
+        symbol_info.push_str(approximate_note(result.approximate));
+        let title = match result_title(result, true) {
+            Some(title) => format!("{title}\n"),
+            None => String::new(),
+        };
+        let doc = match &result.doc {
+            Some(doc) => doc.lines().map(|l| format!("> {l}\n")).collect::<String>() + "\n",
+            None => String::new(),
+        };
+        let fence = fence_language(&result.language);
+        let copies = links.copies(&result.duplicates);
 
+        let location = links.location(result);
         if show_distance {
             output.push_str(&format!(
-                "## {path}:{start}-{end}{symbol_info} [{dist:.3}]\n```\n{content}\n```\n\n",
-                path = result.file_path,
-                start = result.start_line,
-                end = result.end_line,
+                "## {location}{symbol_info} [{dist:.3}] #{id}\n{title}{copies}{doc}```{fence}\n{content}\n```\n\n",
+                id = result.id,
                 dist = result.distance,
-                content = result.content,
             ));
         } else {
             output.push_str(&format!(
-                "## {path}:{start}-{end}{symbol_info}\n```\n{content}\n```\n\n",
-                path = result.file_path,
-                start = result.start_line,
-                end = result.end_line,
-                content = result.content,
+                "## {location}{symbol_info} #{id}\n{title}{copies}{doc}```{fence}\n{content}\n```\n\n",
+                id = result.id,
+            ));
+        }
+    }
+    output
+}
+
+/// Format search results gathered by file: a heading per file, in the
+/// order of its best result, then each range of touching results once,
+/// headed by its lines, symbols, and ids. Under `compact`, each file's
+/// results are listed one line each instead.
+fn format_grouped(
+    links: &Linker<'_>,
+    results: &[SearchResult],
+    show_distance: bool,
+    max_lines: Option<usize>,
+    compact: bool,
+) -> String {
+    let mut output = String::new();
+    for group in group_by_file(results.to_vec()) {
+        let path = &group.file_path;
+        let (owners, module) = group.spans.first().map_or_else(Default::default, |span| {
+            let first = &span.results[0];
+            (
+                owners_note(&first.owners),
+                module_note(first.module.as_deref()),
+            )
+        });
+        output.push_str(&format!(
+            "## {}{module}{owners}\n\n",
+            links.link(path, path, 1)
+        ));
+        if compact {
+            let results: Vec<SearchResult> = group
+                .spans
+                .into_iter()
+                .flat_map(|span| span.results)
+                .collect();
+            output.push_str(&format_compact(links, &results, show_distance));
+            output.push('\n');
+            continue;
+        }
+
+        for span in &group.spans {
+            let unique = |field: fn(&SearchResult) -> Option<&str>| {
+                let mut values: Vec<&str> = Vec::new();
+                for value in span.results.iter().filter_map(field) {
+                    if !values.contains(&value) {
+                        values.push(value);
+                    }
+                }
+                values
+            };
+            let builds = unique(|r| r.build.as_deref());
+            let mut symbol_info = if builds.is_empty() {
+                String::new()
+            } else {
+                format!(" [build: {}]", builds.join("; "))
+            };
+            symbol_info.push_str(approximate_note(span.results.iter().any(|r| r.approximate)));
+            // The module is the file's, named in its heading
+            let mut titles: Vec<String> = Vec::new();
+            for title in span.results.iter().filter_map(|r| result_title(r, false)) {
+                if !titles.contains(&title) {
+                    titles.push(title);
+                }
+            }
+            let title = if titles.is_empty() {
+                String::new()
+            } else {
+                format!("{}\n", titles.join(", "))
+            };
+            let docs = unique(|r| r.doc.as_deref());
+            let doc = if docs.is_empty() {
+                String::new()
+            } else {
+                docs.iter()
+                    .flat_map(|doc| doc.lines())
+                    .map(|l| format!("> {l}\n"))
+                    .collect::<String>()
+                    + "\n"
+            };
+            let distance = if show_distance {
+                let best = span
+                    .results
+                    .iter()
+                    .map(|r| r.distance)
+                    .fold(f32::INFINITY, f32::min);
+                format!(" [{best:.3}]")
+            } else {
+                String::new()
+            };
+            let ids: String = span.results.iter().map(|r| format!(" #{}", r.id)).collect();
+            let duplicates: Vec<Location> = span
+                .results
+                .iter()
+                .flat_map(|r| r.duplicates.iter().cloned())
+                .collect();
+            let copies = links.copies(&duplicates);
+            let lines = format!("lines {}-{}", span.start_line, span.end_line);
+            let fence = fence_language(&span.results[0].language);
+            output.push_str(&format!(
+                "### {location}{symbol_info}{distance}{ids}\n{title}{copies}{doc}```{fence}\n{content}\n```\n\n",
+                location = links.link(&lines, path, span.start_line),
+                content = clip_lines(&span.content, max_lines),
             ));
         }
     }
     output
 }
 
+/// A footer summarizing how far the top candidates were from the query.
+fn format_distribution(dist: &ScoreDistribution) -> String {
+    let ranks: Vec<String> = dist
+        .at_rank
+        .iter()
+        .map(|(rank, distance)| format!("{rank}: {distance:.3}"))
+        .collect();
+    format!(
+        "\nDistances of the top {} candidates (lower is closer): min {:.3}, median {:.3}, \
+         max {:.3}; by rank {}. The closest is {:.3} below the median.\n",
+        dist.candidates,
+        dist.min,
+        dist.median,
+        dist.max,
+        ranks.join(", "),
+        dist.median - dist.min,
+    )
+}
+
+/// A result's one-line title, `kind name — module`, with those of the three
+/// it has, the module only `with_module`; `None` if it has none.
+fn result_title(result: &SearchResult, with_module: bool) -> Option<String> {
+    let what = [result.symbol_kind.as_deref(), result.symbol_name.as_deref()]
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>()
+        .join(" ");
+    let module = result.module.as_deref().filter(|_| with_module);
+    match (what.is_empty(), module) {
+        (true, None) => None,
+        (true, Some(module)) => Some(module.to_string()),
+        (false, None) => Some(what),
+        (false, Some(module)) => Some(format!("{what} — {module}")),
+    }
+}
+
+/// ` [module: name]` for a result whose package or module is known.
+fn module_note(module: Option<&str>) -> String {
+    module.map_or(String::new(), |module| format!(" [module: {module}]"))
+}
+
+/// ` [owners: @a, @b]` for a result with owners under CODEOWNERS.
+fn owners_note(owners: &[String]) -> String {
+    if owners.is_empty() {
+        String::new()
+    } else {
+        format!(" [owners: {}]", owners.join(", "))
+    }
+}
+
+/// ` [ranked roughly: ...]` for a result embedded by an earlier model
+/// that couldn't embed the query, whose rank is a guess.
+fn approximate_note(approximate: bool) -> &'static str {
+    if approximate {
+        " [ranked roughly: embedded by an earlier model]"
+    } else {
+        ""
+    }
+}
+
+/// Load the earlier model `version` offline from the settings it indexed
+/// with, or `None`, with a warning, when they're unknown, its files are no
+/// longer cached, or they now load a different model.
+async fn load_earlier(version: &str, config: Option<EmbeddingConfig>) -> Option<Embedder> {
+    let Some(config) = config else {
+        tracing::warn!(
+            "the settings of earlier model {version} weren't recorded; its chunks are \
+             ranked roughly until the next index run re-embeds them"
+        );
+        return None;
+    };
+    let config = EmbeddingConfig {
+        offline: true,
+        replicas: 1,
+        ..config
+    };
+    let loaded = tokio::task::spawn_blocking(move || Embedder::new(&config)).await;
+    match loaded {
+        Ok(Ok(embedder)) if embedder.version() == version => Some(embedder),
+        Ok(Ok(_)) => {
+            tracing::warn!(
+                "the settings of earlier model {version} now load a different model; its \
+                 chunks are ranked roughly until the next index run re-embeds them"
+            );
+            None
+        }
+        Ok(Err(e)) => {
+            tracing::warn!(
+                "couldn't load earlier model {version} ({e}); its chunks are ranked roughly \
+                 until the next index run re-embeds them"
+            );
+            None
+        }
+        Err(e) => {
+            tracing::warn!("loading earlier model {version} panicked: {e}");
+            None
+        }
+    }
+}
+
+/// The `with_context` sections under their heading; nothing if there are
+/// none.
+fn context_text(sections: Vec<String>) -> String {
+    if sections.is_empty() {
+        return String::new();
+    }
+    CONTEXT_HEADING.to_string() + &sections.concat()
+}
+
+/// The info string for a code block of `language`, so markdown clients
+/// highlight it: the language's name, which for the built-in languages is
+/// the one highlighters know, and `text` for notes, commit messages, and
+/// files chunked by lines.
+fn fence_language(language: &str) -> &str {
+    if [NOTES_LANGUAGE, COMMITS_LANGUAGE, FALLBACK_LANGUAGE].contains(&language) {
+        "text"
+    } else {
+        language
+    }
+}
+
+/// Format search results one line each: location, declaration, how many
+/// copies of it there are elsewhere, and id. Results without a
+/// declaration, like notes, show their first line.
+fn format_compact(links: &Linker<'_>, results: &[SearchResult], show_distance: bool) -> String {
+    let mut output = String::new();
+    for result in results {
+        let signature = result
+            .signature
+            .as_deref()
+            .or_else(|| result.content.lines().find(|l| !l.trim().is_empty()))
+            .unwrap_or("")
+            .trim();
+        let signature = signature.split_whitespace().collect::<Vec<_>>().join(" ");
+        let build = match &result.build {
+            Some(build) => format!(" [build: {build}]"),
+            None => String::new(),
+        };
+        let owners = owners_note(&result.owners);
+        let module = module_note(result.module.as_deref());
+        let approximate = approximate_note(result.approximate);
+        let copies = match result.duplicates.len() {
+            0 => String::new(),
+            1 => " [+1 copy]".to_string(),
+            n => format!(" [+{n} copies]"),
+        };
+        let distance = if show_distance {
+            format!(" [{:.3}]", result.distance)
+        } else {
+            String::new()
+        };
+        output.push_str(&format!(
+            "- {location} `{signature}`{module}{build}{owners}{approximate}{copies}{distance} #{id}\n",
+            location = links.location(result),
+            id = result.id,
+        ));
+    }
+    output
+}
+
+/// The text a tool call returned.
+fn result_text(result: &CallToolResult) -> String {
+    result
+        .content
+        .iter()
+        .filter_map(|c| c.as_text().map(|t| t.text.as_str()))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Entries a tool listed in `text`, for the audit log: its `## ` headings,
+/// one per result or, grouped by file, per file, and its list items.
+fn listed_results(text: &str) -> usize {
+    text.lines()
+        .filter(|line| line.starts_with("## ") || line.starts_with("- "))
+        .count()
+}
+
+/// JSON-RPC codes for tool failures, from the range left to servers, so
+/// clients can tell them apart without parsing messages. Missing files use
+/// the standard `RESOURCE_NOT_FOUND`, bad paths `INVALID_PARAMS`.
+const MODEL_UNAVAILABLE: ErrorCode = ErrorCode(-32010);
+const INDEX_UNAVAILABLE: ErrorCode = ErrorCode(-32011);
+const INDEX_READ_ONLY: ErrorCode = ErrorCode(-32012);
+const TOOL_TIMEOUT: ErrorCode = ErrorCode(-32013);
+
+/// The `data` of a tool error: its class, and whether the same call may
+/// succeed later, for clients deciding whether to back off and retry.
+fn error_data(class: ErrorClass, retryable: bool) -> serde_json::Value {
+    serde_json::json!({ "class": class.as_str(), "retryable": retryable })
+}
+
+/// The MCP error for a tool call that failed with `e` while doing
+/// `context`, coded by the error's class.
+fn tool_error(context: impl std::fmt::Display, e: Error) -> McpError {
+    let class = e.class();
+    let code = match class {
+        ErrorClass::Model => MODEL_UNAVAILABLE,
+        ErrorClass::Index => INDEX_UNAVAILABLE,
+        ErrorClass::ReadOnly => INDEX_READ_ONLY,
+        ErrorClass::NotFound => ErrorCode::RESOURCE_NOT_FOUND,
+        ErrorClass::InvalidPath => ErrorCode::INVALID_PARAMS,
+        ErrorClass::Timeout => TOOL_TIMEOUT,
+        ErrorClass::Internal => ErrorCode::INTERNAL_ERROR,
+    };
+    McpError::new(
+        code,
+        format!("{context}: {e}"),
+        Some(error_data(class, e.is_retryable())),
+    )
+}
+
+/// The error for a path the tools may not read.
+fn invalid_path(message: String) -> McpError {
+    McpError::invalid_params(message, Some(error_data(ErrorClass::InvalidPath, false)))
+}
+
+/// Check the `platform` search parameter is one the index knows.
+fn check_platform(platform: Option<&str>) -> Result<Option<&str>, McpError> {
+    match platform {
+        Some(platform) if !PLATFORMS.contains(&platform) => Err(McpError::invalid_params(
+            format!(
+                "unknown platform '{platform}' -- use one of {}",
+                PLATFORMS.join(", ")
+            ),
+            None,
+        )),
+        _ => Ok(platform),
+    }
+}
+
+/// The narrowing terms and name pattern of a `find_symbol` query such as
+/// `kind:method path:pkg/auth New*`.
+#[derive(Debug, Default)]
+struct SymbolTerms<'a> {
+    name: &'a str,
+    kind: Option<&'a str>,
+    path: Option<&'a str>,
+}
+
+/// Split a `find_symbol` name into its `kind:` and `path:` terms and the
+/// name pattern left over.
+fn parse_symbol_terms(query: &str) -> Result<SymbolTerms<'_>, McpError> {
+    let invalid = |message: String| McpError::invalid_params(message, None);
+    let mut terms = SymbolTerms::default();
+    for word in query.split_whitespace() {
+        let (slot, value, term) = if let Some(kind) = word.strip_prefix("kind:") {
+            (&mut terms.kind, kind, "kind")
+        } else if let Some(path) = word.strip_prefix("path:") {
+            let path = path.trim_start_matches("./");
+            (&mut terms.path, path, "path")
+        } else if terms.name.is_empty() {
+            terms.name = word;
+            continue;
+        } else {
+            return Err(invalid(format!(
+                "find_symbol takes one name pattern, but got '{}' and '{word}' -- \
+                 use `*` to span the gap, e.g. \"{}*{word}\"",
+                terms.name, terms.name
+            )));
+        };
+        if value.is_empty() {
+            return Err(invalid(format!(
+                "'{word}' names no {term} -- write e.g. \"kind:method\" or \"path:src/auth\""
+            )));
+        }
+        if slot.replace(value).is_some() {
+            return Err(invalid(format!(
+                "find_symbol takes one {term}: term -- drop all but one"
+            )));
+        }
+    }
+    Ok(terms)
+}
+
+/// The Unix time named by the `param` search parameter, if given.
+fn parse_time(param: &str, spec: Option<&str>) -> Result<Option<i64>, McpError> {
+    let Some(spec) = spec else {
+        return Ok(None);
+    };
+    let now = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs() as i64;
+    timespec::parse(spec, now).map(Some).ok_or_else(|| {
+        McpError::invalid_params(
+            format!(
+                "{param} '{spec}' is not a date or an age -- use e.g. \"2024-05-01\" or \"7d\""
+            ),
+            None,
+        )
+    })
+}
+
+/// Format file-level search hits: one heading per file with its distance
+/// and chunk count, followed by the symbols it defines.
+fn format_file_hits(links: &Linker<'_>, hits: &[FileHit], show_distance: bool) -> String {
+    let mut output = String::new();
+    for hit in hits {
+        let distance = if show_distance {
+            format!(" [{:.3}]", hit.distance)
+        } else {
+            String::new()
+        };
+        output.push_str(&format!(
+            "## {} ({}, {} chunks){distance}\n",
+            links.link(&hit.file_path, &hit.file_path, 1),
+            hit.language,
+            hit.chunks
+        ));
+        if !hit.symbols.is_empty() {
+            let mut symbols = hit.symbols[..hit.symbols.len().min(FILE_SYMBOLS_SHOWN)].join(", ");
+            if hit.symbols.len() > FILE_SYMBOLS_SHOWN {
+                symbols.push_str(&format!(
+                    ", ... {} more",
+                    hit.symbols.len() - FILE_SYMBOLS_SHOWN
+                ));
+            }
+            output.push_str(&format!("Defines: {symbols}\n"));
+        }
+        output.push('\n');
+    }
+    output
+}
+
+/// Format `query_history` entries: one line per query with when it was
+/// last run, how often, and what its latest search found.
+fn format_query_history(history: &[QueryEntry]) -> String {
+    let mut output = String::new();
+    for entry in history {
+        let times = if entry.count == 1 { "time" } else { "times" };
+        output.push_str(&format!(
+            "- {:?}: last run {}, {} {times}, ",
+            entry.text,
+            timespec::format_utc(entry.last_used),
+            entry.count
+        ));
+        match (entry.hits, entry.found.as_slice()) {
+            (None, _) => output.push_str("results not recorded\n"),
+            (Some(0), _) => output.push_str("no results\n"),
+            (Some(hits), []) => output.push_str(&format!("{hits} results\n")),
+            (Some(hits), found) => {
+                output.push_str(&format!("{hits} results, top: {}\n", found.join(", ")))
+            }
+        }
+    }
+    output
+}
+
 #[tool_router]
 impl ClaudevilServer {
     pub fn new(
@@ -110,100 +869,1013 @@ impl ClaudevilServer {
         chunker: Arc<TreeSitterChunker>,
         config: Config,
         root: PathBuf,
+        usage: UsageTracker,
+        queries: QueryLog,
     ) -> Self {
+        let tool_router = Self::tool_router();
+        for tool in config.server.tool_timeouts_ms.keys() {
+            if tool_router.get(tool).is_none() {
+                tracing::warn!(
+                    "[server.tool_timeouts_ms] names '{tool}', which isn't a tool -- \
+                     check the spelling against the tools the server lists"
+                );
+            }
+        }
+        let permits = (config.server.max_concurrent_tools > 0)
+            .then(|| Arc::new(Semaphore::new(config.server.max_concurrent_tools)));
+        let query_cache = QueryCache::new(config.server.query_cache_size, embedder.lowercases());
         Self {
             embedder,
             store,
             chunker,
             config,
             root,
-            tool_router: Self::tool_router(),
+            usage,
+            queries,
+            query_cache,
+            earlier: Arc::default(),
+            lock_holder: None,
+            progress: IndexProgress::default(),
+            permits,
+            audit: None,
+            tool_router,
+        }
+    }
+
+    /// Mark the server as read-only because `holder` owns the index.
+    pub fn with_lock_holder(mut self, holder: String) -> Self {
+        self.lock_holder = Some(holder);
+        self
+    }
+
+    /// Report the progress and failures of the indexing `progress` tracks.
+    pub fn with_progress(mut self, progress: IndexProgress) -> Self {
+        self.progress = progress;
+        self
+    }
+
+    /// Record every tool call in `audit`.
+    pub fn with_audit(mut self, audit: Option<AuditLog>) -> Self {
+        self.audit = audit;
+        self
+    }
+
+    /// The `limit` for a call: the requested one, or `default`, refused if
+    /// it exceeds `[server] max_limit`.
+    fn check_limit(&self, requested: Option<usize>, default: usize) -> Result<usize, McpError> {
+        let max = self.config.server.max_limit;
+        match requested {
+            Some(limit) if limit > max => Err(McpError::invalid_params(
+                format!(
+                    "limit {limit} exceeds this server's maximum of {max} -- ask for {max} or fewer"
+                ),
+                None,
+            )),
+            Some(0) => Err(McpError::invalid_params(
+                "limit must be at least 1".to_string(),
+                None,
+            )),
+            Some(limit) => Ok(limit),
+            None => Ok(default.min(max)),
+        }
+    }
+
+    /// How result locations link to their files.
+    fn linker(&self) -> Linker<'_> {
+        Linker {
+            links: self.config.server.links,
+            root: &self.root,
+        }
+    }
+
+    /// Lines shown per result: the requested count, or the configured
+    /// default. `None` shows whole chunks.
+    fn snippet_lines(&self, requested: Option<usize>) -> Option<usize> {
+        Some(requested.unwrap_or(self.config.server.max_snippet_lines)).filter(|&n| n > 0)
+    }
+
+    /// Why the index can't be modified, as advice for the client.
+    fn read_only_hint(&self) -> String {
+        match &self.lock_holder {
+            Some(holder) => format!(
+                "{holder} holds the write lock on this index -- make changes through that \
+                 server, or stop it and restart this one"
+            ),
+            None => "restart claudevil without --read-only to modify it".to_string(),
+        }
+    }
+
+    /// Embed `text`, run vector search, apply ranking boosts, and record the
+    /// query and the returned chunks for warm-up and usage tracking.
+    async fn ranked_search(
+        &self,
+        text: &str,
+        filters: Filters<'_>,
+        limit: usize,
+        focus: Focus,
+    ) -> Result<Vec<SearchResult>, McpError> {
+        let (results, _) = self.sampled_search(text, filters, limit, focus, 0).await?;
+        Ok(results)
+    }
+
+    /// The embedding of the search query `text`, from the query cache when
+    /// a near-identical query was embedded recently.
+    async fn embed_query(&self, text: &str) -> Result<Arc<[f32]>, McpError> {
+        if let Some(vector) = self.query_cache.get(text) {
+            return Ok(vector);
+        }
+        let vector: Arc<[f32]> = self
+            .embedder
+            .embed_query(text)
+            .await
+            .map_err(|e| tool_error("embedding failed", e))?
+            .into();
+        self.query_cache.insert(text, Arc::clone(&vector));
+        Ok(vector)
+    }
+
+    /// The query embedded by each earlier model whose chunks the index
+    /// still holds, loading each the first time. Models that can't be
+    /// loaded are left out, and their chunks ranked roughly.
+    async fn earlier_queries(&self, text: &str) -> Vec<(String, Vec<f32>)> {
+        let models = self.store.earlier_models().await;
+        let mut embedders = Vec::new();
+        {
+            let mut loaded = self.earlier.lock().await;
+            loaded.retain(|version, _| models.iter().any(|(v, _)| v == version));
+            for (version, config) in models {
+                if !loaded.contains_key(&version) {
+                    let embedder = load_earlier(&version, config).await;
+                    loaded.insert(version.clone(), embedder);
+                }
+                if let Some(Some(embedder)) = loaded.get(&version) {
+                    embedders.push((version, embedder.clone()));
+                }
+            }
+        }
+        let mut queries = Vec::new();
+        for (version, embedder) in embedders {
+            match embedder.embed_query(text).await {
+                Ok(vector) => queries.push((version, vector)),
+                Err(e) => tracing::warn!(
+                    "embedding the query with earlier model {version} failed ({e}); \
+                     its chunks are ranked roughly"
+                ),
+            }
+        }
+        queries
+    }
+
+    /// Context for `results` under `with_context`, one section each: the
+    /// imports of each file they come from, and the definition of each
+    /// type one of them is a method of, each once and in the order the
+    /// results need them.
+    async fn hit_context(&self, results: &[SearchResult]) -> Vec<String> {
+        let links = self.linker();
+        let mut sections = Vec::new();
+        let mut files: HashSet<&str> = HashSet::new();
+        // Types shown as results aren't shown again
+        let mut types: HashSet<u64> = results.iter().map(|r| r.id).collect();
+        for result in results {
+            let path = result.file_path.as_str();
+            if files.insert(path)
+                && let Some(imports) = self.file_imports(result).await
+            {
+                let line = i64::try_from(imports.start_line).unwrap_or(1);
+                sections.push(format!(
+                    "## Imports of {} (lines {}-{})\n```{}\n{}\n```\n\n",
+                    links.link(path, path, line),
+                    imports.start_line,
+                    imports.end_line,
+                    fence_language(&result.language),
+                    imports.text
+                ));
+            }
+
+            let Some(definition) = self.method_type(result).await else {
+                continue;
+            };
+            if !types.insert(definition.id) {
+                continue;
+            }
+            let name = definition.symbol_name.as_deref().unwrap_or("type");
+            let method = result.symbol_name.as_deref().unwrap_or("the method");
+            sections.push(format!(
+                "## Type {name} of {method}, {}\n```{}\n{}\n```\n\n",
+                links.location(&definition),
+                fence_language(&definition.language),
+                clip_lines(&definition.content, Some(TYPE_CONTEXT_LINES))
+            ));
+        }
+        sections
+    }
+
+    /// The imports of the project file `result` comes from, if its
+    /// language has them.
+    async fn file_imports(&self, result: &SearchResult) -> Option<Imports> {
+        let path = &result.file_path;
+        if path.starts_with('<')
+            || path.starts_with(DEPS_PATH)
+            || !self.config.lang.contains_key(&result.language)
+        {
+            return None;
+        }
+        let bytes = tokio::fs::read(self.root.join(path)).await.ok()?;
+        let source = encoding::decode(bytes).text;
+        self.chunker
+            .imports(&source, &result.language)
+            .unwrap_or_else(|e| {
+                tracing::debug!("no imports for {path}: {e}");
+                None
+            })
+    }
+
+    /// The definition of the type `result` is a method of, if it is one
+    /// and the type is indexed.
+    async fn method_type(&self, result: &SearchResult) -> Option<SearchResult> {
+        let enclosing = self
+            .store
+            .enclosing_chunk(&result.file_path, result.start_line, result.end_line)
+            .await
+            .ok()
+            .flatten();
+        match method_owner(result, enclosing.as_ref())? {
+            Owner::Named(name) => self
+                .store
+                .type_definition(&name, &result.file_path, result.module.as_deref())
+                .await
+                .ok()
+                .flatten(),
+            Owner::Enclosing => enclosing,
+        }
+    }
+
+    /// [`Self::ranked_search`], drawing at least `sample` candidates from
+    /// the store and returning their distances, in rank order, alongside.
+    async fn sampled_search(
+        &self,
+        text: &str,
+        filters: Filters<'_>,
+        limit: usize,
+        focus: Focus,
+        sample: usize,
+    ) -> Result<(Vec<SearchResult>, Vec<f32>), McpError> {
+        metrics().record_search();
+        let boost = self.config.ranking.usage_boost;
+        let candidates = if boost > 0.0 {
+            limit * BOOST_OVERSAMPLE
+        } else {
+            limit
+        }
+        .max(sample);
+
+        let query_vec = self.embed_query(text).await?;
+        let earlier = self.earlier_queries(text).await;
+
+        let mut results = self
+            .store
+            .search_spaces(
+                &query_vec,
+                &earlier,
+                candidates,
+                filters,
+                Some(QueryText {
+                    text,
+                    path_boost: self.config.ranking.path_boost,
+                    language_boost: self.config.ranking.language_boost,
+                    focus,
+                    focus_boost: self.config.ranking.focus_boost,
+                    structure_boost: self.config.ranking.structure_boost,
+                    // Chunks flagged before a switch to `index` rank as others
+                    generated_penalty: match self.config.indexing.generated {
+                        GeneratedFiles::Demote => self.config.ranking.generated_penalty,
+                        GeneratedFiles::Skip | GeneratedFiles::Index => 0.0,
+                    },
+                }),
+            )
+            .await
+            .map_err(|e| tool_error("search failed", e))?;
+        let distances = results.iter().take(sample).map(|r| r.distance).collect();
+
+        self.usage.apply_boost(&mut results, boost).await;
+        results.truncate(limit);
+
+        if let Err(e) = self.usage.record_returned(&results).await {
+            tracing::warn!("failed to record usage: {e}");
+        }
+        let found: Vec<String> = results
+            .iter()
+            .take(MAX_FOUND)
+            .map(|r| format!("{}:{}", r.file_path, r.start_line))
+            .collect();
+        if let Err(e) = self.queries.record(text, results.len(), &found).await {
+            tracing::warn!("failed to record query: {e}");
+        }
+        Ok((results, distances))
+    }
+
+    /// Join a client-supplied relative path onto the root, refusing anything
+    /// that would escape it. The file need not exist (it may have just been
+    /// deleted).
+    fn resolve_in_root(&self, rel_path: &str) -> Result<PathBuf, McpError> {
+        let outside = || {
+            invalid_path(format!(
+                "path '{rel_path}' is outside the project root -- only files within {} are accessible",
+                self.root.display()
+            ))
+        };
+
+        let requested = Path::new(rel_path);
+        if requested
+            .components()
+            .any(|c| !matches!(c, Component::Normal(_) | Component::CurDir))
+        {
+            return Err(outside());
+        }
+        let path = self.root.join(requested);
+
+        // A symlink inside the root can still point out of it
+        if let Ok(canonical) = path.canonicalize()
+            && !canonical.starts_with(&self.root)
+        {
+            return Err(outside());
+        }
+        Ok(path)
+    }
+
+    #[tool(
+        description = "Semantic code search over the indexed codebase. Finds functions, types, methods, and other code by natural language query. Returns matching code chunks with file paths and line numbers. If commit messages are indexed, kind \"commit\" searches them for why code changed. If dependencies are indexed, scope \"dependencies\" searches their source."
+    )]
+    async fn search(
+        &self,
+        Parameters(params): Parameters<SearchParams>,
+    ) -> Result<CallToolResult, McpError> {
+        let limit = self.check_limit(params.limit, self.config.server.default_limit)?;
+        if params.max_tokens == Some(0) {
+            return Err(McpError::invalid_params(
+                "max_tokens must be at least 1".to_string(),
+                None,
+            ));
+        }
+
+        let modified = Modified {
+            after: parse_time("modified_after", params.modified_after.as_deref())?,
+            before: parse_time("modified_before", params.modified_before.as_deref())?,
+        };
+        let platform = check_platform(params.platform.as_deref())?;
+        let owner = params.owner.as_deref().map(normalize_owner);
+        let sample = if params.score_distribution.unwrap_or(false) {
+            DISTRIBUTION_CANDIDATES
+        } else {
+            0
+        };
+        let (results, distances) = self
+            .sampled_search(
+                &params.query,
+                Filters {
+                    language: params.language.as_deref(),
+                    kind: params.kind.as_deref(),
+                    // Naming a namespace asks for it, wherever it is
+                    scope: params.scope.unwrap_or(if params.namespace.is_some() {
+                        Scope::All
+                    } else {
+                        Scope::Project
+                    }),
+                    namespace: params.namespace,
+                    modified,
+                    platform,
+                    owner: owner.as_deref(),
+                    module: params.module.as_deref().map(str::trim),
+                    exact: params.exact,
+                },
+                limit,
+                params.focus.unwrap_or_default(),
+                sample,
+            )
+            .await?;
+        let distribution = score_distribution(&distances)
+            .map(|dist| format_distribution(&dist))
+            .unwrap_or_default();
+
+        if results.is_empty() {
+            return Ok(CallToolResult::success(vec![Content::text(
+                "No results found. The index may still be building, or no matching code was found.",
+            )]));
+        }
+
+        let show_distance = params
+            .show_distance
+            .unwrap_or(self.config.server.show_distance);
+        let max_lines = self.snippet_lines(params.max_snippet_lines);
+        let compact = params.compact.unwrap_or(false);
+        let format = |results: &[SearchResult]| {
+            if params.group_by_file.unwrap_or(false) {
+                format_grouped(&self.linker(), results, show_distance, max_lines, compact)
+            } else if compact {
+                format_compact(&self.linker(), results, show_distance)
+            } else {
+                format_results(&self.linker(), results, show_distance, max_lines)
+            }
+        };
+        let with_context = params.with_context.unwrap_or(false);
+        let Some(max_tokens) = params.max_tokens else {
+            let context = if with_context {
+                context_text(self.hit_context(&results).await)
+            } else {
+                String::new()
+            };
+            return Ok(CallToolResult::success(vec![Content::text(
+                format(&results) + &context + &distribution,
+            )]));
+        };
+
+        // Measured with the embedding model's tokenizer: not the client's,
+        // but close for code and already loaded
+        let count = |text: &str| -> crate::error::Result<usize> {
+            Ok(self
+                .embedder
+                .count_tokens_sync(&[text.to_string()])?
+                .iter()
+                .sum())
+        };
+        let packed = pack(results, max_tokens, |result| {
+            count(&format(std::slice::from_ref(result)))
+        })
+        .map_err(|e| tool_error("packing results failed", e))?;
+        if packed.results.is_empty() {
+            return Err(McpError::invalid_params(
+                format!(
+                    "max_tokens {max_tokens} is too small for even the first line of the top \
+                     result -- raise it"
+                ),
+                None,
+            ));
+        }
+
+        // Context gets what the results leave of the budget
+        let (mut context, mut context_tokens, mut context_omitted) = (Vec::new(), 0, 0);
+        if with_context {
+            let sections = self.hit_context(&packed.results).await;
+            let total = sections.len();
+            let heading =
+                count(CONTEXT_HEADING).map_err(|e| tool_error("packing context failed", e))?;
+            let left = max_tokens.saturating_sub(packed.tokens + heading);
+            (context, context_tokens) = pack_sections(sections, left, count)
+                .map_err(|e| tool_error("packing context failed", e))?;
+            context_omitted = total - context.len();
+            if !context.is_empty() {
+                context_tokens += heading;
+            }
+        }
+
+        let mut output = format(&packed.results);
+        output.push_str(&format!(
+            "About {} of max_tokens {max_tokens} used",
+            packed.tokens + context_tokens
+        ));
+        if packed.omitted > 0 {
+            output.push_str(&format!("; {} more results left out", packed.omitted));
+        }
+        if context_omitted > 0 {
+            output.push_str(&format!("; {context_omitted} context sections left out"));
+        }
+        output.push_str(".\n");
+        if !context.is_empty() {
+            output.push('\n');
+            output.push_str(&context_text(context));
+        }
+        output.push_str(&distribution);
+        Ok(CallToolResult::success(vec![Content::text(output)]))
+    }
+
+    #[tool(
+        description = "Find the files most related to a topic, ranked by the average of each file's chunk embeddings. Use this for file-level questions like \"which file deals with billing?\"; use 'search' to find specific functions or types. Returns file paths with the symbols each defines."
+    )]
+    async fn search_files(
+        &self,
+        Parameters(params): Parameters<SearchFilesParams>,
+    ) -> Result<CallToolResult, McpError> {
+        let limit = self.check_limit(params.limit, self.config.server.default_limit)?;
+        metrics().record_search();
+
+        let query_vec = self.embed_query(&params.query).await?;
+        let hits = self
+            .store
+            .search_files(&query_vec, limit, params.language.as_deref())
+            .await
+            .map_err(|e| tool_error("search failed", e))?;
+        let found: Vec<String> = hits
+            .iter()
+            .take(MAX_FOUND)
+            .map(|hit| hit.file_path.clone())
+            .collect();
+        if let Err(e) = self.queries.record(&params.query, hits.len(), &found).await {
+            tracing::warn!("failed to record query: {e}");
+        }
+
+        if hits.is_empty() {
+            return Ok(CallToolResult::success(vec![Content::text(
+                "No files found. The index may still be building.",
+            )]));
+        }
+
+        Ok(CallToolResult::success(vec![Content::text(
+            format_file_hits(&self.linker(), &hits, self.config.server.show_distance),
+        )]))
+    }
+
+    #[tool(
+        description = "Get the current indexing status: number of chunks stored, the root directory being indexed, how far a running index has got, and how many files failed to index."
+    )]
+    async fn index_status(
+        &self,
+        Parameters(_params): Parameters<IndexStatusParams>,
+    ) -> Result<CallToolResult, McpError> {
+        let count = self
+            .store
+            .chunk_count()
+            .await
+            .map_err(|e| tool_error("count failed", e))?;
+
+        let tokens = self
+            .store
+            .token_stats()
+            .await
+            .map_err(|e| tool_error("token stats failed", e))?;
+
+        let settings = self.store.settings().await;
+        let languages = self.config.language_names().join(", ");
+        let namespaces = self
+            .store
+            .namespace_counts()
+            .await
+            .iter()
+            .map(|(namespace, count)| format!("{namespace} {count}"))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let by_namespace = if namespaces.is_empty() {
+            String::new()
+        } else {
+            format!(" ({namespaces})")
+        };
+        let mut status = format!(
+            "Root: {}\nChunks indexed: {count}{by_namespace}\nSupported languages: {languages}\n\
+             Index: {} backend, {} metric, connectivity {}, expansion_add {}, expansion_search {}",
+            self.root.display(),
+            settings.backend,
+            settings.metric,
+            settings.connectivity,
+            settings.expansion_add,
+            settings.expansion_search
+        );
+        let mib = |bytes: u64| bytes as f64 / (1024.0 * 1024.0);
+        match self.store.disk_usage() {
+            (bytes, Some(max_bytes)) if bytes >= max_bytes => status.push_str(&format!(
+                "\nDisk: FULL, {:.1} MiB of the {:.1} MiB [index] max_bytes allows; new chunks \
+                 are refused -- raise the limit, exclude large directories, or free space with \
+                 `claudevil gc`",
+                mib(bytes),
+                mib(max_bytes)
+            )),
+            (bytes, Some(max_bytes)) => status.push_str(&format!(
+                "\nDisk: {:.1} MiB of {:.1} MiB ([index] max_bytes)",
+                mib(bytes),
+                mib(max_bytes)
+            )),
+            (bytes, None) => status.push_str(&format!("\nDisk: {:.1} MiB", mib(bytes))),
+        }
+        let cache = self.query_cache.stats();
+        if cache.hits + cache.misses > 0 {
+            status.push_str(&format!(
+                "\nQuery cache: {} hits, {} misses ({:.0}% hit rate), {} of {} queries held",
+                cache.hits,
+                cache.misses,
+                cache.hit_rate(),
+                cache.entries,
+                cache.capacity
+            ));
+        }
+        let duplicates = self.store.duplicate_count().await;
+        if duplicates > 0 {
+            status.push_str(&format!(
+                "\nDuplicates: {duplicates} chunks share the vector of an identical one"
+            ));
+        }
+        let aliases = self.store.alias_count().await;
+        if aliases > 0 {
+            status.push_str(&format!(
+                "\nSymlinked paths: {aliases} lead to files indexed under another path"
+            ));
+        }
+        if self.store.is_read_only() {
+            status.push_str(&format!("\nRead-only: {}", self.read_only_hint()));
+        }
+        let repair = self.store.repair_summary();
+        if !repair.is_clean() {
+            let action = if self.store.is_read_only() {
+                "found at startup (read-only, left in place)"
+            } else {
+                "repaired at startup"
+            };
+            status.push_str(&format!("\nInconsistencies {action}: {repair}"));
+        }
+        if settings.dimensions < EMBEDDING_DIM {
+            status.push_str(&format!(
+                "\nVectors: {} of {EMBEDDING_DIM} dimensions stored (smaller index, lower recall)",
+                settings.dimensions
+            ));
+        }
+        let dependencies = self.store.dependencies().await;
+        if !dependencies.is_empty() {
+            status.push_str(&format!(
+                "\nDependencies indexed: {} (search them with scope \"dependencies\")",
+                dependencies.len()
+            ));
+        }
+        let progress = self.progress.snapshot();
+        if progress.is_running() {
+            status.push_str(&format!(
+                "\nIndexing: {} of {} files ({:.0}%)",
+                progress.done,
+                progress.total,
+                progress.percent()
+            ));
+        }
+        let failures = progress.errors.len() + progress.dropped_errors;
+        if failures > 0 {
+            status.push_str(&format!(
+                "\nIndex errors: {failures} (list them with index_errors)"
+            ));
+        }
+        let stale = stale_files(&self.store, &self.root).await;
+        if !stale.is_empty() {
+            status.push_str(&format!(
+                "\nStale files: {} changed or deleted since indexed (list_files marks them; \
+                 refresh_file them, or reindex if there are many)",
+                stale.len()
+            ));
+        }
+        let outdated = self.store.outdated_chunks().await.len();
+        if outdated > 0 {
+            status.push_str(&format!(
+                "\nEmbedding migration: {outdated} chunks still embedded by an earlier model \
+                 (searched in their own vector space, with the earlier model embedding the \
+                 query, until the next index run re-embeds them)"
+            ));
+        }
+        if let Some(rebuild) = self.store.rebuild_status().await {
+            status.push_str(&format!(
+                "\nIndex rebuild: {} chunks left to embed again after a settings change ({}); \
+                 they are missing from results until then",
+                rebuild.remaining, rebuild.reason
+            ));
+        }
+        let skipped = self.store.skipped_files().await;
+        if !skipped.is_empty() {
+            status.push_str(&format!("\nSkipped files: {}", skipped.len()));
+            for (path, reason) in skipped.iter().take(SKIPPED_FILES_SHOWN) {
+                status.push_str(&format!("\n  {path}: {reason}"));
+            }
+            if skipped.len() > SKIPPED_FILES_SHOWN {
+                status.push_str(&format!(
+                    "\n  ... and {} more",
+                    skipped.len() - SKIPPED_FILES_SHOWN
+                ));
+            }
+        }
+        if tokens.measured > 0 {
+            let pct = tokens.truncated as f64 * 100.0 / tokens.measured as f64;
+            status.push_str(&format!(
+                "\nTruncated chunks: {} of {} ({pct:.1}%) exceed the {MAX_SEQ_LEN}-token embedding window",
+                tokens.truncated, tokens.measured
+            ));
+        }
+
+        Ok(CallToolResult::success(vec![Content::text(status)]))
+    }
+
+    #[tool(
+        description = "List the files that failed to index in the current or last indexing run, with the error for each, and any failure that stopped a run. Use it when index_status reports index errors or a file you expect is missing from search results."
+    )]
+    async fn index_errors(
+        &self,
+        Parameters(_params): Parameters<IndexErrorsParams>,
+    ) -> Result<CallToolResult, McpError> {
+        let progress = self.progress.snapshot();
+        if progress.errors.is_empty() {
+            return Ok(CallToolResult::success(vec![Content::text(
+                if progress.is_running() {
+                    "No indexing errors so far; indexing is still running."
+                } else {
+                    "No indexing errors in the last run."
+                },
+            )]));
+        }
+
+        let mut output = format!("{} indexing errors:\n", progress.errors.len());
+        for error in &progress.errors {
+            match &error.path {
+                Some(path) => output.push_str(&format!("- {path}: {}\n", error.error)),
+                None => output.push_str(&format!("- (whole run): {}\n", error.error)),
+            }
+        }
+        if progress.dropped_errors > 0 {
+            output.push_str(&format!(
+                "... and {} more; see the server log.\n",
+                progress.dropped_errors
+            ));
+        }
+        Ok(CallToolResult::success(vec![Content::text(output)]))
+    }
+
+    #[tool(
+        description = "Check that the server works end to end: embed a canary string, search the index, then store the canary in a scratch store, search for it, and delete it again, reporting each step and how long it took. Use it once after startup, or when searches return nothing or fail, to tell a broken model or index from a query that matches nothing. The index itself is only searched, never written."
+    )]
+    async fn self_test(
+        &self,
+        Parameters(_params): Parameters<SelfTestParams>,
+    ) -> Result<CallToolResult, McpError> {
+        let test = selftest::run(&self.embedder, &self.store, &self.config.index).await;
+        let report = Content::text(test.to_string());
+        if test.passed() {
+            Ok(CallToolResult::success(vec![report]))
+        } else {
+            Ok(CallToolResult::error(vec![report]))
+        }
+    }
+
+    #[tool(
+        description = "Report how much of the root is indexed, by file extension: files and bytes indexed, passed over (too large, binary), and left out because no configured language handles them, largest first. Use it to see which languages a config entry would add to the index."
+    )]
+    async fn coverage_report(
+        &self,
+        Parameters(params): Parameters<CoverageReportParams>,
+    ) -> Result<CallToolResult, McpError> {
+        let limit = self.check_limit(params.limit, DEFAULT_COVERAGE_LIMIT)?;
+        let indexer = Indexer::new(
+            self.embedder.clone(),
+            self.store.clone(),
+            self.chunker.clone(),
+            self.config.clone(),
+        );
+        let mut coverage = indexer.coverage(&self.root).await;
+        coverage.shown = limit;
+        Ok(CallToolResult::success(vec![Content::text(
+            coverage.to_string(),
+        )]))
+    }
+
+    #[tool(
+        description = "List the searches earlier sessions ran against this index, most recent first: when each query was last run, how often, how many results it had, and where its top hits were. Use it at the start of a task to see what was already looked for and found in this repo before searching again."
+    )]
+    async fn query_history(
+        &self,
+        Parameters(params): Parameters<QueryHistoryParams>,
+    ) -> Result<CallToolResult, McpError> {
+        let limit = self.check_limit(params.limit, DEFAULT_HISTORY_LIMIT)?;
+        let history = self
+            .queries
+            .history(limit, params.contains.as_deref())
+            .await;
+        if history.is_empty() {
+            return Ok(CallToolResult::success(vec![Content::text(
+                match params.contains {
+                    Some(part) => format!("No earlier queries contain {part:?}."),
+                    None => "No queries recorded yet.".to_string(),
+                },
+            )]));
         }
+        Ok(CallToolResult::success(vec![Content::text(
+            format_query_history(&history),
+        )]))
+    }
+
+    #[tool(
+        description = "Find symbols (functions, types, methods, etc.) by name. Performs a case-insensitive substring match on symbol names in the index, or a whole-name match for patterns with `*` wildcards. Narrow it in the same string with `kind:` and `path:` terms, e.g. \"kind:method path:pkg/auth New*\". Use this when you know the name of what you're looking for."
+    )]
+    async fn find_symbol(
+        &self,
+        Parameters(params): Parameters<FindSymbolParams>,
+    ) -> Result<CallToolResult, McpError> {
+        let limit = self.check_limit(params.limit, 20)?;
+        let terms = parse_symbol_terms(&params.name)?;
+        let kind = match (terms.kind, params.kind.as_deref()) {
+            (Some(term), Some(param)) if term != param => {
+                return Err(McpError::invalid_params(
+                    format!("'kind:{term}' contradicts kind '{param}' -- give the kind once"),
+                    None,
+                ));
+            }
+            (term, param) => term.or(param),
+        };
+        if terms.name.is_empty() && kind.is_none() && terms.path.is_none() {
+            return Err(McpError::invalid_params(
+                "find_symbol needs a name pattern, a kind: term, or a path: term".to_string(),
+                None,
+            ));
+        }
+
+        let results = self
+            .store
+            .find_by_symbol(
+                SymbolQuery {
+                    name: terms.name,
+                    kind,
+                    path: terms.path,
+                    namespace: params.namespace,
+                },
+                limit,
+            )
+            .await
+            .map_err(|e| tool_error("symbol search failed", e))?;
+
+        if results.is_empty() {
+            return Ok(CallToolResult::success(vec![Content::text(format!(
+                "No symbols matching '{}' found in the index.",
+                params.name
+            ))]));
+        }
+
+        Ok(CallToolResult::success(vec![Content::text(
+            format_results(
+                &self.linker(),
+                &results,
+                false,
+                self.snippet_lines(params.max_snippet_lines),
+            ),
+        )]))
     }
 
     #[tool(
-        description = "Semantic code search over the indexed codebase. Finds functions, types, methods, and other code by natural language query. Returns matching code chunks with file paths and line numbers."
+        description = "Find the indexed symbol (function, method, type, ...) enclosing a file and line, e.g. from a stack trace or compiler error like pkg/server/handler.go:217. Returns the innermost enclosing chunk with its name, kind, and full line range."
     )]
-    async fn search(
+    async fn symbol_at(
         &self,
-        Parameters(params): Parameters<SearchParams>,
+        Parameters(params): Parameters<SymbolAtParams>,
     ) -> Result<CallToolResult, McpError> {
-        let limit = params.limit.unwrap_or(10);
-
-        let query_vec = self
-            .embedder
-            .embed_one(&params.query)
-            .await
-            .map_err(|e| McpError::internal_error(format!("embedding failed: {e}"), None))?;
+        let path = Path::new(&params.path);
+        let rel_path = path
+            .strip_prefix(&self.root)
+            .unwrap_or(path)
+            .to_string_lossy();
+        let rel_path = rel_path.trim_start_matches("./");
 
-        let results = self
+        let hit = self
             .store
-            .search(&query_vec, limit, params.language.as_deref())
+            .chunk_at(rel_path, params.line)
             .await
-            .map_err(|e| McpError::internal_error(format!("search failed: {e}"), None))?;
+            .map_err(|e| tool_error("lookup failed", e))?;
 
-        if results.is_empty() {
-            return Ok(CallToolResult::success(vec![Content::text(
-                "No results found. The index may still be building, or no matching code was found.",
-            )]));
+        match hit {
+            Some(chunk) => Ok(CallToolResult::success(vec![Content::text(
+                format_results(&self.linker(), &[chunk], false, None),
+            )])),
+            None => Ok(CallToolResult::success(vec![Content::text(format!(
+                "No indexed chunk encloses {rel_path}:{}. The line may fall between \
+                 declarations, or the file isn't indexed (check list_files).",
+                params.line
+            ))])),
         }
-
-        Ok(CallToolResult::success(vec![Content::text(
-            format_results(&results[..results.len().min(limit)], true),
-        )]))
     }
 
     #[tool(
-        description = "Get the current indexing status: number of chunks stored and the root directory being indexed."
+        description = "Resolve a pasted Go, Rust, or Python stack trace to code. Parses each frame's file and line, maps it to the indexed file (ignoring machine-specific path prefixes), and returns the enclosing function or type for every frame that is part of this project."
     )]
-    async fn index_status(
+    async fn resolve_stacktrace(
         &self,
-        Parameters(_params): Parameters<IndexStatusParams>,
+        Parameters(params): Parameters<ResolveStacktraceParams>,
     ) -> Result<CallToolResult, McpError> {
-        let count = self
+        let max_frames = params.max_frames.unwrap_or(DEFAULT_STACK_FRAMES);
+        let frames = parse_frames(&params.trace);
+        if frames.is_empty() {
+            return Err(McpError::invalid_params(
+                "no file:line locations found in the trace -- paste the full trace, \
+                 including the lines that name source files",
+                None,
+            ));
+        }
+
+        let indexed = self
             .store
-            .chunk_count()
+            .list_files(None)
             .await
-            .map_err(|e| McpError::internal_error(format!("count failed: {e}"), None))?;
+            .map_err(|e| tool_error("list files failed", e))?;
 
-        let languages = self.config.language_names().join(", ");
-        let status = format!(
-            "Root: {}\nChunks indexed: {count}\nSupported languages: {languages}",
-            self.root.display()
-        );
+        let mut output = String::new();
+        let mut shown = HashSet::new();
+        for (i, frame) in frames.iter().take(max_frames).enumerate() {
+            let Some(rel_path) = match_indexed(&frame.path, &indexed) else {
+                output.push_str(&format!(
+                    "# Frame {i}: {}:{} (not in the index)\n\n",
+                    frame.path, frame.line
+                ));
+                continue;
+            };
+            let hit = self
+                .store
+                .chunk_at(rel_path, frame.line)
+                .await
+                .map_err(|e| tool_error("lookup failed", e))?;
+            output.push_str(&format!("# Frame {i}: {rel_path}:{}\n", frame.line));
+            match hit {
+                Some(chunk) if !shown.insert(chunk.id) => {
+                    output.push_str(&format!("Same chunk as above (#{}).\n\n", chunk.id));
+                }
+                Some(chunk) => {
+                    output.push_str(&format_results(&self.linker(), &[chunk], false, None))
+                }
+                None => output.push_str("No indexed chunk encloses this line.\n\n"),
+            }
+        }
+        if frames.len() > max_frames {
+            output.push_str(&format!(
+                "{} more frames not shown; raise max_frames to see them.\n",
+                frames.len() - max_frames
+            ));
+        }
 
-        Ok(CallToolResult::success(vec![Content::text(status)]))
+        Ok(CallToolResult::success(vec![Content::text(output)]))
     }
 
     #[tool(
-        description = "Find symbols (functions, types, methods, etc.) by name. Performs a case-insensitive substring match on symbol names in the index. Use this when you know the name of what you're looking for."
+        description = "List the indexed symbols (functions, types, methods, ...) grouped by kind, with their locations, a page at a time. Filter by file or directory, language, or kind to get an overview of an unfamiliar module without reading every file."
     )]
-    async fn find_symbol(
+    async fn list_symbols(
         &self,
-        Parameters(params): Parameters<FindSymbolParams>,
+        Parameters(params): Parameters<ListSymbolsParams>,
     ) -> Result<CallToolResult, McpError> {
-        let limit = params.limit.unwrap_or(20);
+        let limit = self.check_limit(params.limit, DEFAULT_SYMBOLS_PAGE)?;
+        let offset = params.offset.unwrap_or(0);
+        let path = params
+            .path
+            .as_deref()
+            .map(|p| p.trim_start_matches("./"))
+            .filter(|p| !p.is_empty() && *p != ".");
 
-        let results = self
+        let symbols = self
             .store
-            .find_by_symbol(&params.name, params.kind.as_deref(), limit)
-            .await
-            .map_err(|e| McpError::internal_error(format!("symbol search failed: {e}"), None))?;
+            .list_symbols(
+                path,
+                params.language.as_deref(),
+                params.kind.as_deref(),
+                params.namespace,
+            )
+            .await;
+        if symbols.is_empty() {
+            return Ok(CallToolResult::success(vec![Content::text(
+                "No matching symbols in the index. Check the path with list_files, or drop \
+                 a filter; the index may also still be building.",
+            )]));
+        }
+        if offset >= symbols.len() {
+            return Err(McpError::invalid_params(
+                format!(
+                    "offset {offset} is past the last of {} symbols -- use a smaller offset",
+                    symbols.len()
+                ),
+                None,
+            ));
+        }
 
-        if results.is_empty() {
-            return Ok(CallToolResult::success(vec![Content::text(format!(
-                "No symbols matching '{}' found in the index.",
-                params.name
-            ))]));
+        let mut per_kind: HashMap<&str, usize> = HashMap::new();
+        for symbol in &symbols {
+            *per_kind.entry(symbol.kind.as_str()).or_default() += 1;
+        }
+        let page: &[SymbolEntry] = &symbols[offset..symbols.len().min(offset + limit)];
+        let mut output = format!(
+            "Symbols {}-{} of {}, grouped by kind:\n",
+            offset + 1,
+            offset + page.len(),
+            symbols.len()
+        );
+        let mut kind = None;
+        for symbol in page {
+            if kind != Some(symbol.kind.as_str()) {
+                kind = Some(symbol.kind.as_str());
+                output.push_str(&format!(
+                    "\n## {} ({})\n",
+                    symbol.kind,
+                    per_kind[symbol.kind.as_str()]
+                ));
+            }
+            output.push_str(&format!(
+                "- {} {}:{}-{}\n",
+                symbol.name, symbol.file_path, symbol.start_line, symbol.end_line
+            ));
+        }
+        let next = offset + page.len();
+        if next < symbols.len() {
+            output.push_str(&format!(
+                "\n{} more; call again with offset={next} for the next page.\n",
+                symbols.len() - next
+            ));
         }
 
-        Ok(CallToolResult::success(vec![Content::text(
-            format_results(&results, false),
-        )]))
+        Ok(CallToolResult::success(vec![Content::text(output)]))
     }
 
     #[tool(
-        description = "List all files currently in the index. Optionally filter by programming language."
+        description = "List all files currently in the index, marking those changed or deleted on disk since they were indexed as (stale). Optionally filter by programming language."
     )]
     async fn list_files(
         &self,
@@ -213,7 +1885,7 @@ impl ClaudevilServer {
             .store
             .list_files(params.language.as_deref())
             .await
-            .map_err(|e| McpError::internal_error(format!("list files failed: {e}"), None))?;
+            .map_err(|e| tool_error("list files failed", e))?;
 
         if files.is_empty() {
             return Ok(CallToolResult::success(vec![Content::text(
@@ -221,39 +1893,133 @@ impl ClaudevilServer {
             )]));
         }
 
-        let output = format!("{} files indexed:\n{}", files.len(), files.join("\n"));
+        // Only the files listed are looked up
+        let mut mtimes = self.store.file_mtimes().await;
+        let listed_mtimes = files.iter().filter_map(|f| mtimes.remove_entry(f.as_str()));
+        let stale = stale_among(&self.root, listed_mtimes).await;
+        let links = self.linker();
+        let listed: Vec<String> = files
+            .iter()
+            .map(|f| {
+                let marker = if stale.contains(f) { " (stale)" } else { "" };
+                format!("{}{marker}", links.link(f, f, 1))
+            })
+            .collect();
+        let stale_listed = files.iter().filter(|f| stale.contains(*f)).count();
+        let header = if stale_listed > 0 {
+            format!(
+                "{} files indexed, {stale_listed} stale (changed or deleted since indexed -- \
+                 refresh_file them, or reindex if there are many):",
+                files.len()
+            )
+        } else {
+            format!("{} files indexed:", files.len())
+        };
+        let output = format!("{header}\n{}", listed.join("\n"));
         Ok(CallToolResult::success(vec![Content::text(output)]))
     }
 
     #[tool(
-        description = "Read the contents of a file from the indexed directory. The path must be relative to the project root."
+        description = "Read the contents of a file from the indexed directory. The path must be relative to the project root. Pass symbol to get just that function, type, or method, with its line range, instead of the whole file."
     )]
     async fn read_file(
         &self,
         Parameters(params): Parameters<ReadFileParams>,
     ) -> Result<CallToolResult, McpError> {
-        let requested = self.root.join(&params.path);
+        // Dependency source is read from the cache it was indexed from
+        let (base, prefix, requested) = if params.path.starts_with(DEPS_PATH) {
+            let dependencies = deps::discover(
+                &self.root,
+                &self.config.dependencies,
+                &deps::Caches::from_env(),
+            );
+            let (dep, rest) = deps::resolve(&dependencies, &params.path).ok_or_else(|| {
+                McpError::resource_not_found(
+                    format!(
+                        "'{}' isn't in a dependency of this project whose source is on disk -- \
+                         use a path from a search result",
+                        params.path
+                    ),
+                    Some(error_data(ErrorClass::NotFound, false)),
+                )
+            })?;
+            let dir = dep.dir.canonicalize().map_err(|source| {
+                let path = dep.dir.clone();
+                tool_error(
+                    "failed to open the dependency",
+                    Error::FileRead { path, source },
+                )
+            })?;
+            let requested = dir.join(rest);
+            (dir, dep.path_prefix(), requested)
+        } else {
+            (
+                self.root.clone(),
+                String::new(),
+                self.root.join(&params.path),
+            )
+        };
         let canonical = requested.canonicalize().map_err(|_| {
-            McpError::invalid_params(format!("file not found: {}", params.path), None)
+            McpError::resource_not_found(
+                format!(
+                    "file not found: {} -- use a path from list_files",
+                    params.path
+                ),
+                Some(error_data(ErrorClass::NotFound, false)),
+            )
         })?;
 
         // Prevent directory traversal outside the root
-        if !canonical.starts_with(&self.root) {
+        if !canonical.starts_with(&base) {
+            return Err(invalid_path(format!(
+                "path '{}' is outside the project root -- only files within {} are accessible",
+                params.path,
+                base.display()
+            )));
+        }
+
+        let bytes = tokio::fs::read(&canonical).await.map_err(|source| {
+            let path = canonical.clone();
+            tool_error("failed to read the file", Error::FileRead { path, source })
+        })?;
+        let content = encoding::decode(bytes).text;
+
+        let rel_path = format!(
+            "{prefix}{}",
+            canonical
+                .strip_prefix(&base)
+                .unwrap_or(&canonical)
+                .to_string_lossy()
+        );
+        if let Err(e) = self.usage.record_read(&rel_path).await {
+            tracing::warn!("failed to record usage: {e}");
+        }
+
+        let Some(symbol) = params.symbol.as_deref() else {
+            return Ok(CallToolResult::success(vec![Content::text(content)]));
+        };
+        let mut chunks = self
+            .store
+            .symbol_chunks(&rel_path, symbol)
+            .await
+            .map_err(|e| tool_error("lookup failed", e))?;
+        if chunks.is_empty() {
             return Err(McpError::invalid_params(
                 format!(
-                    "path '{}' is outside the project root -- only files within {} are accessible",
-                    params.path,
-                    self.root.display()
+                    "no symbol '{symbol}' indexed in {rel_path} -- use find_symbol to look up \
+                     its exact name and file"
                 ),
                 None,
             ));
         }
-
-        let content = tokio::fs::read_to_string(&canonical).await.map_err(|e| {
-            McpError::internal_error(format!("failed to read {}: {e}", params.path), None)
-        })?;
-
-        Ok(CallToolResult::success(vec![Content::text(content)]))
+        // Sliced from the file on disk: stored chunks may carry overlap
+        // lines and lag behind edits
+        for chunk in &mut chunks {
+            chunk.content = line_range(&content, chunk.start_line, chunk.end_line);
+        }
+        Ok(CallToolResult::success(vec![Content::text(
+            format_results(&self.linker(), &chunks, false, None),
+        )]))
     }
 
     #[tool(
@@ -268,20 +2034,76 @@ impl ClaudevilServer {
             self.store.clone(),
             self.chunker.clone(),
             self.config.clone(),
-        );
+        )
+        .with_progress(self.progress.clone());
         let root = self.root.clone();
+        let (embedder, store, queries) = (
+            self.embedder.clone(),
+            self.store.clone(),
+            self.queries.clone(),
+        );
+        let warmup_queries = self.config.indexing.warmup_queries;
         tokio::spawn(async move {
-            if let Err(e) = indexer.index_directory(&root).await {
+            let result = async {
+                indexer.index_directory(&root).await?;
+                indexer.index_commits(&root).await?;
+                indexer.index_dependencies(&root).await
+            }
+            .await;
+            if let Err(e) = result {
                 tracing::error!("reindex failed: {e:#}");
+                metrics().record_index_error();
+                indexer.progress().fail_run(format!("{e:#}"));
+            } else if let Err(e) = warm_up(&embedder, &store, &queries, warmup_queries).await {
+                tracing::error!("warm-up failed: {e:#}");
             }
         });
 
         Ok(CallToolResult::success(vec![Content::text(format!(
-            "Re-indexing started for {}. Use index_status to check progress.",
+            "Re-indexing started for {}. Use index_status to check progress and index_errors \
+             to see files that fail.",
             self.root.display()
         ))]))
     }
 
+    #[tool(
+        description = "Re-index a single file right away and return once it is searchable. Call this after editing or deleting a file so search reflects the change without a full reindex."
+    )]
+    async fn refresh_file(
+        &self,
+        Parameters(params): Parameters<RefreshFileParams>,
+    ) -> Result<CallToolResult, McpError> {
+        let path = self.resolve_in_root(&params.path)?;
+        let indexer = Indexer::new(
+            self.embedder.clone(),
+            self.store.clone(),
+            self.chunker.clone(),
+            self.config.clone(),
+        );
+        let update = indexer
+            .index_file(&path, &self.root)
+            .await
+            .map_err(|e| tool_error(format_args!("failed to refresh {}", params.path), e))?;
+
+        let message = match update {
+            FileUpdate::Indexed(chunks) => {
+                format!("Refreshed {}: {chunks} chunks indexed.", params.path)
+            }
+            FileUpdate::Removed => format!(
+                "{} no longer exists; its chunks were removed from the index.",
+                params.path
+            ),
+            FileUpdate::Unsupported => format!(
+                "{} was not indexed: no configured language handles its extension.",
+                params.path
+            ),
+            FileUpdate::Skipped(reason) => {
+                format!("{} was not indexed: {reason}.", params.path)
+            }
+        };
+        Ok(CallToolResult::success(vec![Content::text(message)]))
+    }
+
     #[tool(
         description = "Find code chunks semantically similar to a given code snippet. Use this to find related implementations, similar patterns, or duplicated logic."
     )]
@@ -289,47 +2111,599 @@ impl ClaudevilServer {
         &self,
         Parameters(params): Parameters<FindSimilarParams>,
     ) -> Result<CallToolResult, McpError> {
-        let limit = params.limit.unwrap_or(10);
+        let limit = self.check_limit(params.limit, self.config.server.default_limit)?;
 
-        let query_vec = self
-            .embedder
-            .embed_one(&params.code)
-            .await
-            .map_err(|e| McpError::internal_error(format!("embedding failed: {e}"), None))?;
+        let exclude = params.exclude_path.as_deref().map(|path| {
+            let path = Path::new(path);
+            let rel_path = path.strip_prefix(&self.root).unwrap_or(path);
+            rel_path
+                .to_string_lossy()
+                .trim_start_matches("./")
+                .trim_end_matches('/')
+                .to_string()
+        });
 
-        let results = self
-            .store
-            .search(&query_vec, limit, params.language.as_deref())
-            .await
-            .map_err(|e| McpError::internal_error(format!("search failed: {e}"), None))?;
+        // Clean the snippet the same way indexed chunks are cleaned so both
+        // sides of the comparison see the same kind of text.
+        let code = preprocess_code(&params.code);
+        let candidates = self
+            .ranked_search(
+                &code,
+                Filters {
+                    language: params.language.as_deref(),
+                    ..Default::default()
+                },
+                limit * SIMILAR_OVERSAMPLE,
+                Focus::Any,
+            )
+            .await?;
+
+        let mut results = Vec::new();
+        let (mut excluded, mut identical) = (0, 0);
+        for hit in candidates {
+            let in_excluded = exclude.as_deref().is_some_and(|path| {
+                hit.file_path == path
+                    || hit
+                        .file_path
+                        .strip_prefix(path)
+                        .is_some_and(|rest| rest.starts_with('/'))
+            });
+            if in_excluded {
+                excluded += 1;
+            } else if is_same_code(&params.code, &hit.content) {
+                identical += 1;
+            } else {
+                results.push(hit);
+            }
+        }
+        results.truncate(limit);
+
+        let mut left_out = Vec::new();
+        if identical > 0 {
+            left_out.push(format!("{identical} identical to the snippet"));
+        }
+        if let (Some(path), true) = (&exclude, excluded > 0) {
+            left_out.push(format!("{excluded} from {path}"));
+        }
+        let note = if left_out.is_empty() {
+            String::new()
+        } else {
+            format!("\n(Left out {}.)", left_out.join(" and "))
+        };
 
         if results.is_empty() {
+            return Ok(CallToolResult::success(vec![Content::text(format!(
+                "No similar code found. The index may still be building.{note}"
+            ))]));
+        }
+
+        Ok(CallToolResult::success(vec![Content::text(format!(
+            "{}{note}",
+            format_results(
+                &self.linker(),
+                &results,
+                params
+                    .show_distance
+                    .unwrap_or(self.config.server.show_distance),
+                self.snippet_lines(params.max_snippet_lines),
+            )
+        ))]))
+    }
+
+    #[tool(
+        description = "Gather the code relevant to a task in one call. Runs a semantic search plus lookups of any symbols the task names, drops duplicate and nested chunks, and returns the most relevant ones ordered by file and line within a token budget."
+    )]
+    async fn gather_context(
+        &self,
+        Parameters(params): Parameters<GatherContextParams>,
+    ) -> Result<CallToolResult, McpError> {
+        let max_tokens = params.max_tokens.unwrap_or(4000);
+
+        // Exact symbol hits first: a name the task spells out is a stronger
+        // signal than embedding similarity.
+        let mut candidates = Vec::new();
+        for name in symbol_hints(&params.task) {
+            let hits = self
+                .store
+                .find_by_symbol(SymbolQuery::named(&name), CONTEXT_SYMBOL_RESULTS)
+                .await
+                .map_err(|e| tool_error("symbol search failed", e))?;
+            candidates.extend(hits);
+        }
+        candidates.extend(
+            self.ranked_search(
+                &params.task,
+                Filters {
+                    language: params.language.as_deref(),
+                    ..Default::default()
+                },
+                CONTEXT_SEARCH_RESULTS,
+                Focus::Any,
+            )
+            .await?,
+        );
+
+        let bundle = assemble(candidates, max_tokens);
+        if bundle.chunks.is_empty() {
             return Ok(CallToolResult::success(vec![Content::text(
-                "No similar code found. The index may still be building.",
+                "No relevant code found. The index may still be building.",
             )]));
         }
 
-        Ok(CallToolResult::success(vec![Content::text(
-            format_results(&results[..results.len().min(limit)], true),
-        )]))
+        let files = bundle
+            .chunks
+            .iter()
+            .map(|c| c.file_path.as_str())
+            .collect::<HashSet<_>>()
+            .len();
+        let mut output = format!(
+            "{} chunks from {files} files, ~{} tokens",
+            bundle.chunks.len(),
+            bundle.tokens
+        );
+        if bundle.omitted > 0 {
+            output.push_str(&format!(
+                " ({} more omitted to fit max_tokens={max_tokens})",
+                bundle.omitted
+            ));
+        }
+        output.push_str("\n\n");
+        output.push_str(&format_results(&self.linker(), &bundle.chunks, false, None));
+        Ok(CallToolResult::success(vec![Content::text(output)]))
+    }
+
+    #[tool(
+        description = "Save a short free-text note (a design decision, a TODO, something learned about the code) to the index. Notes persist for this project and show up in later searches alongside code; search with language \"notes\" to see only notes."
+    )]
+    async fn add_note(
+        &self,
+        Parameters(params): Parameters<AddNoteParams>,
+    ) -> Result<CallToolResult, McpError> {
+        let text = params.text.trim();
+        if text.is_empty() {
+            return Err(McpError::invalid_params(
+                "note text is empty -- pass the note to save as text",
+                None,
+            ));
+        }
+
+        let vector = self
+            .embedder
+            .embed_passage(text)
+            .await
+            .map_err(|e| tool_error("embedding failed", e))?;
+        let id = self
+            .store
+            .add_note(text, vector)
+            .await
+            .map_err(|e| tool_error("failed to save note", e))?;
+
+        Ok(CallToolResult::success(vec![Content::text(format!(
+            "Saved note #{id}. Delete it with delete_note once it no longer applies."
+        ))]))
+    }
+
+    #[tool(description = "Delete a note previously saved with add_note.")]
+    async fn delete_note(
+        &self,
+        Parameters(params): Parameters<DeleteNoteParams>,
+    ) -> Result<CallToolResult, McpError> {
+        let deleted = self
+            .store
+            .delete_note(params.id)
+            .await
+            .map_err(|e| tool_error("failed to delete note", e))?;
+        if !deleted {
+            return Err(McpError::invalid_params(
+                format!(
+                    "no note #{} -- search with language \"{NOTES_LANGUAGE}\" to find note ids",
+                    params.id
+                ),
+                None,
+            ));
+        }
+        Ok(CallToolResult::success(vec![Content::text(format!(
+            "Deleted note #{}.",
+            params.id
+        ))]))
     }
 }
 
-#[tool_handler]
 impl ServerHandler for ClaudevilServer {
+    async fn call_tool(
+        &self,
+        request: CallToolRequestParams,
+        context: RequestContext<RoleServer>,
+    ) -> Result<CallToolResult, McpError> {
+        let tool = request.name.clone();
+        if self.store.is_read_only() && MUTATING_TOOLS.contains(&tool.as_ref()) {
+            metrics().record_tool_call(&tool, true);
+            return Err(McpError::new(
+                INDEX_READ_ONLY,
+                format!(
+                    "{tool} is disabled because the index is read-only: {}",
+                    self.read_only_hint()
+                ),
+                Some(error_data(ErrorClass::ReadOnly, false)),
+            ));
+        }
+        let span = tracing::debug_span!("tool", name = %tool, failed = tracing::field::Empty);
+        let started = Instant::now();
+        let params = self
+            .audit
+            .as_ref()
+            .map(|_| serde_json::Value::Object(request.arguments.clone().unwrap_or_default()));
+        let tcc = ToolCallContext::new(self, request, context);
+        let call = async {
+            // The semaphore is never closed, so a permit always comes
+            let _permit = match &self.permits {
+                Some(permits) => permits.acquire().await.ok(),
+                None => None,
+            };
+            self.tool_router.call(tcc).await
+        }
+        .instrument(span.clone());
+        let result = match self.config.server.tool_timeout(&tool) {
+            Some(limit) => tokio::time::timeout(limit, call).await.unwrap_or_else(|_| {
+                tracing::warn!("{tool} timed out after {limit:?}");
+                Err(McpError::new(
+                    TOOL_TIMEOUT,
+                    format!(
+                        "{tool} took longer than {limit:?} and was abandoned -- retry it, narrow \
+                         the request, or raise [server.tool_timeouts_ms] {tool}"
+                    ),
+                    Some(error_data(ErrorClass::Timeout, true)),
+                ))
+            }),
+            None => call.await,
+        };
+
+        let failed = result
+            .as_ref()
+            .map_or(true, |r| r.is_error.unwrap_or(false));
+        span.record("failed", failed);
+        metrics().record_tool_call(&tool, failed);
+        if let (Some(audit), Some(params)) = (&self.audit, params) {
+            let (error, results) = match &result {
+                Ok(r) if failed => (Some(result_text(r)), 0),
+                Ok(r) => (None, listed_results(&result_text(r))),
+                Err(e) => (Some(e.message.to_string()), 0),
+            };
+            audit
+                .record(&tool, params, error, results, started.elapsed())
+                .await;
+        }
+        result
+    }
+
+    async fn list_tools(
+        &self,
+        _request: Option<PaginatedRequestParams>,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<ListToolsResult, McpError> {
+        let mut tools = self.tool_router.list_all();
+        if self.store.is_read_only() {
+            tools.retain(|t| !MUTATING_TOOLS.contains(&t.name.as_ref()));
+        }
+        Ok(ListToolsResult {
+            tools,
+            meta: None,
+            next_cursor: None,
+        })
+    }
+
+    async fn complete(
+        &self,
+        request: CompleteRequestParams,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<CompleteResult, McpError> {
+        let typed = request.argument.value.as_str();
+        let completion = match Argument::named(&request.argument.name) {
+            Some(Argument::Language) => completion::matching(self.config.language_names(), typed),
+            Some(Argument::Kind) => {
+                let kinds = self.store.symbol_kinds().await;
+                completion::matching(kinds.iter().map(String::as_str), typed)
+            }
+            Some(Argument::Path) => {
+                let files = self
+                    .store
+                    .list_files(None)
+                    .await
+                    .map_err(|e| tool_error("failed to list indexed files", e))?;
+                completion::path_steps(files.iter().map(String::as_str), typed)
+            }
+            None => CompletionInfo::default(),
+        };
+        Ok(CompleteResult { completion })
+    }
+
+    fn get_tool(&self, name: &str) -> Option<Tool> {
+        self.tool_router.get(name).cloned()
+    }
+
     fn get_info(&self) -> ServerInfo {
         ServerInfo {
             instructions: Some(
                 "claudevil provides semantic code search over local files. \
                  Use the 'search' tool with natural language queries to find \
-                 relevant code in the indexed codebase. Use 'find_symbol' for \
-                 exact name lookups, 'list_files' to see indexed files, \
+                 relevant code in the indexed codebase, or 'search_files' to \
+                 find which files deal with a topic. Use 'find_symbol' for \
+                 exact name lookups, 'symbol_at' to map a file:line to its \
+                 enclosing symbol, 'resolve_stacktrace' to map a whole \
+                 stack trace to code, 'list_symbols' to survey a module's \
+                 symbols by kind, 'list_files' to see indexed files, \
+                 'index_status' and 'index_errors' to check on indexing, \
+                 'coverage_report' to see which file types are left out, \
                  'read_file' to view file contents, 'reindex' to refresh the \
-                 index, and 'find_similar' to find related code."
+                 index, 'refresh_file' to re-index a file you just edited, \
+                 'find_similar' to find related code, 'gather_context' \
+                 to collect everything relevant to a task in one call, \
+                 'query_history' to see what earlier sessions searched for \
+                 and found, and \
+                 'add_note'/'delete_note' to keep notes that later searches \
+                 surface."
                     .into(),
             ),
-            capabilities: ServerCapabilities::builder().enable_tools().build(),
+            capabilities: ServerCapabilities::builder()
+                .enable_completions()
+                .enable_tools()
+                .build(),
             ..Default::default()
         }
     }
 }
+
+/// Argument, and key of a request's `_meta`, naming the root of a
+/// [`Registry`] a tool call is for.
+const ROOT_ARGUMENT: &str = "root";
+
+/// Several roots served from one process, which loads the embedding model
+/// once for all of them. Each root has a [`ClaudevilServer`] of its own,
+/// with its own store, indexer, and usage counters. A tool call goes to the
+/// root its `root` argument names, or else the `root` key of the request's
+/// `_meta`, and to the first root when neither names one.
+#[derive(Clone)]
+pub struct Registry {
+    /// Each root's name and server, in the order they were given.
+    roots: Vec<(String, ClaudevilServer)>,
+}
+
+impl Registry {
+    /// Serve each of `servers`, named for its root directory, or by the
+    /// root's whole path where two directories share a name.
+    pub fn new(servers: Vec<ClaudevilServer>) -> Self {
+        let base = |server: &ClaudevilServer| {
+            server.root.file_name().map_or_else(
+                || server.root.display().to_string(),
+                |name| name.to_string_lossy().into_owned(),
+            )
+        };
+        let mut counts: HashMap<String, usize> = HashMap::new();
+        for server in &servers {
+            *counts.entry(base(server)).or_default() += 1;
+        }
+        let roots = servers
+            .into_iter()
+            .map(|server| {
+                let name = base(&server);
+                let name = if counts[&name] > 1 {
+                    server.root.display().to_string()
+                } else {
+                    name
+                };
+                (name, server)
+            })
+            .collect();
+        Self { roots }
+    }
+
+    fn names(&self) -> Vec<&str> {
+        self.roots.iter().map(|(name, _)| name.as_str()).collect()
+    }
+
+    /// The server for the root `name` gives, by name or path; the first
+    /// root's if it gives none.
+    fn server(&self, name: Option<&serde_json::Value>) -> Result<&ClaudevilServer, McpError> {
+        let roots: Vec<(&str, &Path)> = self
+            .roots
+            .iter()
+            .map(|(name, server)| (name.as_str(), server.root.as_path()))
+            .collect();
+        let index = pick_root(&roots, name)?;
+        Ok(&self.roots[index].1)
+    }
+
+    /// `tool` with a `root` argument choosing among the served roots.
+    fn with_root_argument(&self, mut tool: Tool) -> Tool {
+        let first = self.names().first().copied().unwrap_or_default();
+        let mut schema = (*tool.input_schema).clone();
+        let properties = schema
+            .entry("properties")
+            .or_insert_with(|| serde_json::json!({}));
+        if let Some(properties) = properties.as_object_mut() {
+            properties.insert(
+                ROOT_ARGUMENT.to_string(),
+                serde_json::json!({
+                    "type": "string",
+                    "description": format!(
+                        "Which served root to use, by name or path (default: {first})."
+                    ),
+                    "enum": self.names(),
+                }),
+            );
+        }
+        tool.input_schema = Arc::new(schema);
+        tool
+    }
+}
+
+/// The root a tool call names, taken out of its arguments so the root's
+/// server doesn't see it, or else from its `_meta`.
+fn root_argument(request: &mut CallToolRequestParams, meta: &Meta) -> Option<serde_json::Value> {
+    request
+        .arguments
+        .as_mut()
+        .and_then(|arguments| arguments.remove(ROOT_ARGUMENT))
+        .or_else(|| meta.get(ROOT_ARGUMENT).cloned())
+}
+
+/// Which of `roots`, each a name and a path, the root `name` gives picks,
+/// by name or path; the first if it gives none.
+fn pick_root(roots: &[(&str, &Path)], name: Option<&serde_json::Value>) -> Result<usize, McpError> {
+    let unknown = |name: &str| {
+        let names: Vec<&str> = roots.iter().map(|(name, _)| *name).collect();
+        McpError::invalid_params(
+            format!(
+                "no root named '{name}' is served -- pass one of: {}",
+                names.join(", ")
+            ),
+            None,
+        )
+    };
+    let Some(name) = name else {
+        return if roots.is_empty() {
+            Err(unknown(""))
+        } else {
+            Ok(0)
+        };
+    };
+    let Some(name) = name.as_str() else {
+        return Err(McpError::invalid_params(
+            format!("{ROOT_ARGUMENT} must be a string, the name or path of a served root"),
+            None,
+        ));
+    };
+    let name = name.trim_end_matches('/');
+    roots
+        .iter()
+        .position(|(n, root)| *n == name || *root == Path::new(name))
+        .ok_or_else(|| unknown(name))
+}
+
+impl ServerHandler for Registry {
+    async fn call_tool(
+        &self,
+        mut request: CallToolRequestParams,
+        context: RequestContext<RoleServer>,
+    ) -> Result<CallToolResult, McpError> {
+        let name = root_argument(&mut request, &context.meta);
+        self.server(name.as_ref())?
+            .call_tool(request, context)
+            .await
+    }
+
+    async fn list_tools(
+        &self,
+        _request: Option<PaginatedRequestParams>,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<ListToolsResult, McpError> {
+        let Some((_, first)) = self.roots.first() else {
+            return Ok(ListToolsResult::default());
+        };
+        let mut tools = first.tool_router.list_all();
+        // Writes are refused per root, by the root's own server
+        if self
+            .roots
+            .iter()
+            .all(|(_, server)| server.store.is_read_only())
+        {
+            tools.retain(|t| !MUTATING_TOOLS.contains(&t.name.as_ref()));
+        }
+        Ok(ListToolsResult {
+            tools: tools
+                .into_iter()
+                .map(|tool| self.with_root_argument(tool))
+                .collect(),
+            meta: None,
+            next_cursor: None,
+        })
+    }
+
+    async fn complete(
+        &self,
+        request: CompleteRequestParams,
+        context: RequestContext<RoleServer>,
+    ) -> Result<CompleteResult, McpError> {
+        let name = request
+            .context
+            .as_ref()
+            .and_then(|c| c.get_argument(ROOT_ARGUMENT))
+            .map(|name| serde_json::Value::String(name.clone()))
+            .or_else(|| context.meta.get(ROOT_ARGUMENT).cloned());
+        self.server(name.as_ref())?.complete(request, context).await
+    }
+
+    fn get_tool(&self, name: &str) -> Option<Tool> {
+        let (_, first) = self.roots.first()?;
+        first
+            .get_tool(name)
+            .map(|tool| self.with_root_argument(tool))
+    }
+
+    fn get_info(&self) -> ServerInfo {
+        let Some((first_name, first)) = self.roots.first() else {
+            return ServerInfo::default();
+        };
+        let mut info = first.get_info();
+        let roots = self
+            .roots
+            .iter()
+            .map(|(name, server)| format!("'{name}' ({})", server.root.display()))
+            .collect::<Vec<_>>()
+            .join(", ");
+        info.instructions = Some(format!(
+            "{} This server serves several roots: {roots}. Every tool takes a 'root' \
+             argument naming the one to use; without it, tools use '{first_name}'.",
+            info.instructions.unwrap_or_default(),
+        ));
+        info
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn roots() -> [(&'static str, &'static Path); 2] {
+        [
+            ("api", Path::new("/src/api")),
+            ("web", Path::new("/src/web")),
+        ]
+    }
+
+    fn call(arguments: serde_json::Value) -> CallToolRequestParams {
+        serde_json::from_value(serde_json::json!({
+            "name": "search",
+            "arguments": arguments,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn tool_calls_go_to_the_root_they_name() {
+        let mut request = call(serde_json::json!({"root": "web", "query": "parse"}));
+        let name = root_argument(&mut request, &Meta::new());
+        assert_eq!(pick_root(&roots(), name.as_ref()).unwrap(), 1);
+        // The root's server sees only its own arguments
+        let arguments = request.arguments.unwrap();
+        assert!(!arguments.contains_key(ROOT_ARGUMENT));
+        assert_eq!(arguments["query"], "parse");
+
+        // By path, and from _meta
+        let mut request = call(serde_json::json!({"query": "parse"}));
+        let mut meta = Meta::new();
+        meta.0.insert(ROOT_ARGUMENT.to_string(), "/src/web/".into());
+        let name = root_argument(&mut request, &meta);
+        assert_eq!(pick_root(&roots(), name.as_ref()).unwrap(), 1);
+    }
+
+    #[test]
+    fn tool_calls_naming_no_root_go_to_the_first() {
+        let mut request = call(serde_json::json!({"query": "parse"}));
+        let name = root_argument(&mut request, &Meta::new());
+        assert_eq!(pick_root(&roots(), name.as_ref()).unwrap(), 0);
+
+        let err = pick_root(&roots(), Some(&"docs".into())).unwrap_err();
+        assert!(err.message.contains("pass one of: api, web"), "{err:?}");
+    }
+}
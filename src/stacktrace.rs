@@ -0,0 +1,171 @@
+//! Pulling source locations out of pasted stack traces.
+//!
+//! Go (`/src/app/handler.go:217 +0x1a5`), Rust (`at ./src/server.rs:42:5`,
+//! `panicked at src/main.rs:10:5:`) and Python (`File "app/models.py", line
+//! 88, in save`) traces all name a file and a line per frame; the rest of
+//! each format is noise for the purpose of finding the code.
+
+/// A file and line named by one frame of a stack trace.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Frame {
+    /// Path as written in the trace; often absolute and from another machine.
+    pub path: String,
+    pub line: i64,
+}
+
+/// Frames in `trace`, in the order it lists them (innermost first for Go
+/// and Rust, last for Python). Consecutive repeats (recursion, or a frame
+/// printed twice) are collapsed.
+pub fn parse_frames(trace: &str) -> Vec<Frame> {
+    let mut frames: Vec<Frame> = Vec::new();
+    for line in trace.lines() {
+        let Some(frame) = python_frame(line).or_else(|| file_line_frame(line)) else {
+            continue;
+        };
+        if frames.last() != Some(&frame) {
+            frames.push(frame);
+        }
+    }
+    frames
+}
+
+/// `File "path", line N, in func`.
+fn python_frame(line: &str) -> Option<Frame> {
+    let rest = line.trim_start().strip_prefix("File \"")?;
+    let (path, rest) = rest.split_once('"')?;
+    let number = rest.trim_start_matches(',').trim().strip_prefix("line ")?;
+    let digits: String = number.chars().take_while(char::is_ascii_digit).collect();
+    Some(Frame {
+        path: path.to_string(),
+        line: digits.parse().ok()?,
+    })
+}
+
+/// The first `path.ext:N` token on a line, as Go and Rust print them.
+fn file_line_frame(line: &str) -> Option<Frame> {
+    line.split_whitespace().find_map(|token| {
+        let token = token.trim_matches(|c: char| matches!(c, '(' | ')' | '[' | ']' | ',' | '`'));
+        let mut parts = token.split(':');
+        let mut path = parts.next()?.to_string();
+        // Windows drive letters (`C:\src\main.rs:3`) contain a colon too
+        if path.len() == 1 {
+            path = format!("{path}:{}", parts.next()?);
+        }
+        let line = parts.next()?.parse().ok()?;
+        let file_name = path.rsplit(['/', '\\']).next()?;
+        let has_extension = file_name
+            .rsplit_once('.')
+            .is_some_and(|(stem, ext)| !stem.is_empty() && ext.chars().all(char::is_alphanumeric));
+        has_extension.then_some(Frame { path, line })
+    })
+}
+
+/// The indexed file a trace path refers to: the path itself, relative to
+/// the root, or the longest indexed path it ends with (traces from CI or a
+/// container carry a different absolute prefix).
+pub fn match_indexed<'a>(trace_path: &str, indexed: &'a [String]) -> Option<&'a str> {
+    let normalized = trace_path.replace('\\', "/");
+    let normalized = normalized.trim_start_matches("./");
+    indexed
+        .iter()
+        .filter(|file| {
+            normalized == file.as_str()
+                || normalized
+                    .strip_suffix(file.as_str())
+                    .is_some_and(|prefix| prefix.ends_with('/'))
+        })
+        .max_by_key(|file| file.len())
+        .map(String::as_str)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(path: &str, line: i64) -> Frame {
+        Frame {
+            path: path.to_string(),
+            line,
+        }
+    }
+
+    #[test]
+    fn go_trace() {
+        let trace = "panic: runtime error: invalid memory address\n\n\
+            goroutine 1 [running]:\n\
+            main.(*Server).handle(0x0)\n\
+            \t/home/ci/src/app/pkg/server/handler.go:217 +0x1a5\n\
+            main.main()\n\
+            \t/home/ci/src/app/main.go:12 +0x25\n\
+            exit status 2";
+        assert_eq!(
+            parse_frames(trace),
+            [
+                frame("/home/ci/src/app/pkg/server/handler.go", 217),
+                frame("/home/ci/src/app/main.go", 12),
+            ]
+        );
+    }
+
+    #[test]
+    fn rust_trace() {
+        let trace = "thread 'main' panicked at src/main.rs:10:5:\n\
+            called `Option::unwrap()` on a `None` value\n\
+            stack backtrace:\n\
+            \x20  0: app::server::start\n\
+            \x20            at ./src/server.rs:42:9\n\
+            \x20  1: app::main\n\
+            \x20            at ./src/main.rs:10:5";
+        assert_eq!(
+            parse_frames(trace),
+            [
+                frame("src/main.rs", 10),
+                frame("./src/server.rs", 42),
+                frame("./src/main.rs", 10),
+            ]
+        );
+    }
+
+    #[test]
+    fn python_trace() {
+        let trace = "Traceback (most recent call last):\n\
+            \x20 File \"/srv/app/views.py\", line 31, in post\n\
+            \x20   obj.save()\n\
+            \x20 File \"/srv/app/models.py\", line 88, in save\n\
+            \x20   raise ValueError(\"bad\")\n\
+            ValueError: bad";
+        assert_eq!(
+            parse_frames(trace),
+            [
+                frame("/srv/app/views.py", 31),
+                frame("/srv/app/models.py", 88)
+            ]
+        );
+    }
+
+    #[test]
+    fn ignores_version_numbers_and_urls() {
+        assert!(
+            parse_frames("go version go1.22.1 linux/amd64\nsee https://example.com:443").is_empty()
+        );
+    }
+
+    #[test]
+    fn matches_indexed_paths_by_suffix() {
+        let indexed = vec![
+            "main.go".to_string(),
+            "pkg/server/handler.go".to_string(),
+            "server/handler.go".to_string(),
+        ];
+        assert_eq!(
+            match_indexed("/home/ci/src/app/pkg/server/handler.go", &indexed),
+            Some("pkg/server/handler.go")
+        );
+        assert_eq!(match_indexed("./main.go", &indexed), Some("main.go"));
+        assert_eq!(
+            match_indexed("/usr/local/go/src/net/http/server.go", &indexed),
+            None
+        );
+        assert_eq!(match_indexed("/x/notmain.go", &indexed), None);
+    }
+}
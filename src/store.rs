@@ -1,865 +1,6019 @@
-use std::collections::HashMap;
-use std::path::PathBuf;
+//! The vector store: chunk vectors in a usearch or Qdrant index, and the
+//! metadata to filter and show them, persisted to a data directory.
+
+mod dedup;
+mod hnsw;
+mod moves;
+mod qdrant;
+mod snippets;
+mod transaction;
+
+use std::collections::{BTreeMap, BTreeSet, BinaryHeap, HashMap, HashSet};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::SystemTime;
 
+use rmcp::schemars;
 use serde::{Deserialize, Serialize};
-use tokio::sync::RwLock;
-use usearch::{Index, IndexOptions, MetricKind, ScalarKind};
+use tokio::sync::{Mutex, RwLock, RwLockWriteGuard, Semaphore};
+use tracing::Instrument;
 
-use crate::embed::EMBEDDING_DIM;
+use crate::buildtags::excluded_platforms;
+use crate::codeowners::CodeOwners;
+use crate::config::{
+    Backend, ContentStorage, EmbeddingConfig, IndexConfig, Metric, Namespace, OnMismatch,
+};
+use crate::embed::{EMBEDDING_DIM, MAX_SEQ_LEN, mean, truncate};
 use crate::error::{Error, Result};
+use crate::infer::{Shape, Structure, infer_languages, infer_structures};
+use crate::postings::{Postings, quoted_identifiers};
+use dedup::{Copies, content_hash};
+use moves::{RemovedFile, RemovedFiles};
+use snippets::Snippets;
+pub use transaction::Transaction;
 
 const INDEX_FILE: &str = "index.usearch";
 const META_FILE: &str = "metadata.json";
 
-// usearch::Index contains raw C++ pointers that aren't Send/Sync in Rust,
-// but the underlying C++ library is thread-safe for concurrent reads and
-// exclusive writes -- which we enforce via RwLock.
-struct SendSyncIndex(Index);
-unsafe impl Send for SendSyncIndex {}
-unsafe impl Sync for SendSyncIndex {}
+/// Language recorded for notes added with [`VectorStore::add_note`], so a
+/// language filter can select them.
+pub const NOTES_LANGUAGE: &str = "notes";
+
+/// File path notes are stored under. Not a valid relative path, so no
+/// indexed file can collide with it.
+const NOTES_PATH: &str = "<notes>";
+
+/// Language and path recorded for commit messages indexed by
+/// [`crate::indexer::Indexer::index_commits`]. Like notes' `<notes>`, the path
+/// can't collide with an indexed file.
+pub const COMMITS_LANGUAGE: &str = "commits";
+pub const COMMITS_PATH: &str = "<commits>";
+
+/// Symbol kind of an indexed commit message; its symbol name is the
+/// abbreviated hash.
+pub const COMMIT_KIND: &str = "commit";
+
+/// Directory dependency sources are indexed under, as
+/// `<deps>/name@version/path`. Like notes' `<notes>`, it can't collide with
+/// an indexed file.
+pub const DEPS_PATH: &str = "<deps>";
+
+/// Files that make up a persisted store, relative to its directory.
+pub const STORE_FILES: [&str; 2] = [INDEX_FILE, META_FILE];
+
+/// Bytes the [`STORE_FILES`] under `db_path` take on disk. The audit log
+/// and usage counters beside them aren't the index, and don't count
+/// against `[index] max_bytes`.
+fn stored_size(db_path: &Path) -> u64 {
+    STORE_FILES
+        .iter()
+        .filter_map(|name| std::fs::metadata(db_path.join(name)).ok())
+        .map(|metadata| metadata.len())
+        .sum()
+}
+
+/// Storage for chunk vectors. The default keeps an HNSW index on local
+/// disk next to the metadata; the Qdrant backend uses a shared server.
+trait VectorStoreBackend: Send + Sync {
+    /// Add points under fresh keys. Backends that keep their own copy of
+    /// chunk metadata store the accompanying [`ChunkMeta`] too.
+    fn insert(&mut self, points: &[(u64, &[f32], &ChunkMeta)]) -> Result<()>;
+
+    /// Up to `limit` keys nearest `vector` that `filter` allows, with their
+    /// distances, closest first.
+    fn search(
+        &self,
+        vector: &[f32],
+        limit: usize,
+        filter: &SearchFilter<'_>,
+    ) -> Result<Vec<(u64, f32)>>;
+
+    /// Up to `limit` keys nearest `vector` that `filter` allows, like
+    /// [`search`](Self::search), but found by scoring every stored vector
+    /// rather than approximately.
+    fn search_exact(
+        &self,
+        vector: &[f32],
+        limit: usize,
+        filter: &SearchFilter<'_>,
+    ) -> Result<Vec<(u64, f32)>>;
+
+    /// Keep the vectors of `keys`, and of points inserted later, in memory
+    /// for [`search_exact`](Self::search_exact) to scan, until there are
+    /// more than `max`. Backends that search exactly on their own ignore it.
+    fn hold_vectors(&mut self, keys: &[u64], max: usize) -> Result<()>;
+
+    /// Remove the points with these keys.
+    fn delete(&mut self, keys: &[u64]) -> Result<()>;
+
+    /// The stored vectors for `keys`; keys with no vector are left out.
+    fn vectors(&self, keys: &[u64]) -> Result<Vec<(u64, Vec<f32>)>>;
+
+    /// Every stored chunk, for backends that keep metadata with the
+    /// vectors; `None` if the local sidecar is the only copy.
+    fn list(&self) -> Result<Option<HashMap<u64, ChunkMeta>>>;
+
+    /// Keys of every stored vector, for checking them against the sidecar;
+    /// `None` if the backend's own metadata is authoritative. The store
+    /// hands out keys sequentially below `next_key`.
+    fn keys(&self, next_key: u64) -> Result<Option<HashSet<u64>>>;
+
+    /// Write pending changes to `dir`, if the backend stores anything there.
+    fn save(&self, dir: &Path) -> Result<()>;
+
+    fn settings(&self) -> IndexSettings;
+}
+
+/// Restrictions on which chunks a search may return.
+struct SearchFilter<'a> {
+    /// Only these keys, when set.
+    keys: Option<&'a HashSet<u64>>,
+    /// Only chunks in this language, when set.
+    language: Option<&'a str>,
+    /// Only chunks of this symbol kind, when set.
+    kind: Option<&'a str>,
+    /// Only chunks in these namespaces.
+    namespaces: &'a [Namespace],
+    /// Only chunks modified in this window.
+    modified: Modified,
+    /// Only chunks whose build constraints don't rule out this platform.
+    platform: Option<&'a str>,
+    /// Only chunks with this owner.
+    owner: Option<&'a str>,
+    /// Only chunks in this package or module.
+    module: Option<&'a str>,
+    /// Only chunks whose vectors come from this model's space, when set: a
+    /// query embedded by one model can't be compared with another's
+    /// vectors.
+    space: Option<Space<'a>>,
+    /// Metadata for checking `language`, `kind`, `namespaces`, `modified`,
+    /// `platform`, `owner`, `module`, and `space` against a key.
+    chunks: &'a HashMap<u64, ChunkMeta>,
+    /// The duplicates of each key, any of which the filter may allow
+    /// instead.
+    copies: &'a Copies,
+}
+
+impl SearchFilter<'_> {
+    fn is_empty(&self) -> bool {
+        self.keys.is_none()
+            && self.language.is_none()
+            && self.kind.is_none()
+            && self.namespaces.len() == Namespace::ALL.len()
+            && self.modified.is_any()
+            && self.platform.is_none()
+            && self.owner.is_none()
+            && self.module.is_none()
+            && self.space.is_none()
+    }
+
+    /// Whether the chunk stored under `key`, or one of its duplicates,
+    /// passes. Duplicates share the vector of the chunk under `key`, so
+    /// only its model counts.
+    fn allows(&self, key: u64) -> bool {
+        self.space.is_none_or(|space| {
+            self.chunks
+                .get(&key)
+                .is_some_and(|c| space.contains(c.model.as_deref()))
+        }) && (self.allows_chunk(key) || self.copies.duplicates(key).any(|d| self.allows_chunk(d)))
+    }
+
+    /// Whether the chunk stored under `key` itself passes.
+    fn allows_chunk(&self, key: u64) -> bool {
+        self.keys.is_none_or(|keys| keys.contains(&key))
+            && self
+                .language
+                .is_none_or(|lang| self.chunks.get(&key).is_some_and(|c| c.language == lang))
+            && self.kind.is_none_or(|kind| {
+                self.chunks
+                    .get(&key)
+                    .is_some_and(|c| c.symbol_kind.as_deref() == Some(kind))
+            })
+            && (self.namespaces.len() == Namespace::ALL.len()
+                || self
+                    .chunks
+                    .get(&key)
+                    .is_some_and(|c| self.namespaces.contains(&c.namespace)))
+            && (self.modified.is_any()
+                || self
+                    .chunks
+                    .get(&key)
+                    .is_some_and(|c| self.modified.includes(c.last_modified)))
+            && self.platform.is_none_or(|platform| {
+                self.chunks
+                    .get(&key)
+                    .is_some_and(|c| !c.excluded_platforms.iter().any(|p| p == platform))
+            })
+            && self.owner.is_none_or(|owner| {
+                self.chunks
+                    .get(&key)
+                    .is_some_and(|c| c.owners.iter().any(|o| o == owner))
+            })
+            && self.module.is_none_or(|module| {
+                self.chunks
+                    .get(&key)
+                    .is_some_and(|c| c.module.as_deref() == Some(module))
+            })
+    }
+}
+
+/// The vectors of one embedding model. While chunks embedded by an earlier
+/// model remain, each model's chunks are searched apart.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Space<'a> {
+    /// Chunks embedded by the current model, or stored before chunks
+    /// recorded their model.
+    Current(&'a str),
+    /// Chunks embedded by this earlier model.
+    Earlier(&'a str),
+}
+
+impl Space<'_> {
+    /// Whether a chunk embedded by `model` is in this space.
+    fn contains(self, model: Option<&str>) -> bool {
+        match self {
+            Space::Current(current) => model.is_none_or(|m| m == current),
+            Space::Earlier(earlier) => model == Some(earlier),
+        }
+    }
+}
 
-#[derive(Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
 struct Metadata {
     next_key: u64,
+    /// Metric the index was built with; older indexes are all cosine.
+    #[serde(default)]
+    metric: Metric,
+    /// Dimensions stored per vector; older indexes are all full-size.
+    #[serde(default = "full_dimensions")]
+    dimensions: usize,
     chunks: HashMap<u64, ChunkMeta>,
+    /// Files passed over by the last full index run, with the reason.
+    #[serde(default)]
+    skipped: BTreeMap<String, String>,
+    /// Paths the last full index run reached through a symlink to a file
+    /// indexed under another path, keyed by that path.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    aliases: BTreeMap<String, Vec<String>>,
+    /// Git commit the index was last synced to, for incremental updates.
+    #[serde(default)]
+    last_commit: Option<String>,
+    /// A rebuild for a changed metric or dimension count still under way.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    rebuild: Option<Rebuild>,
+    /// Rebuilt from `chunks` on load rather than persisted.
+    #[serde(skip)]
+    postings: Postings,
+    /// Rebuilt from `chunks` on load rather than persisted.
+    #[serde(skip)]
+    copies: Copies,
+    /// Set by [`VectorStore::set_code_owners`] rather than persisted; the
+    /// owners it gives are stored with each chunk.
+    #[serde(skip)]
+    code_owners: CodeOwners,
+    /// The settings each model that embedded chunks was loaded with, by
+    /// [`Embedder::version`](crate::embed::Embedder::version), so a search
+    /// can embed its query with an earlier model while its chunks remain.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    models: BTreeMap<String, EmbeddingConfig>,
+    /// Chunks with a vector of their own from a model other than the
+    /// store's, by that model, counted by [`VectorStore::with_model`] and
+    /// kept up to date as chunks come and go, so searches needn't count
+    /// them.
+    #[serde(skip)]
+    outdated: BTreeMap<String, usize>,
+}
+
+impl Metadata {
+    /// Count one more vector from the earlier `model`.
+    fn add_outdated(&mut self, model: &str) {
+        *self.outdated.entry(model.to_string()).or_default() += 1;
+    }
+
+    /// Count one vector fewer from the earlier `model`.
+    fn remove_outdated(&mut self, model: &str) {
+        if let Some(count) = self.outdated.get_mut(model) {
+            *count = count.saturating_sub(1);
+            if *count == 0 {
+                self.outdated.remove(model);
+            }
+        }
+    }
+
+    /// Vectors from any earlier model.
+    fn outdated_total(&self) -> usize {
+        self.outdated.values().sum()
+    }
+
+    /// Metadata of a store that holds nothing yet.
+    fn empty(metric: Metric, dimensions: usize) -> Self {
+        Self {
+            next_key: 0,
+            metric,
+            dimensions,
+            chunks: HashMap::new(),
+            skipped: BTreeMap::new(),
+            aliases: BTreeMap::new(),
+            last_commit: None,
+            rebuild: None,
+            postings: Postings::default(),
+            copies: Copies::default(),
+            code_owners: CodeOwners::default(),
+            models: BTreeMap::new(),
+            outdated: BTreeMap::new(),
+        }
+    }
+}
+
+fn full_dimensions() -> usize {
+    EMBEDDING_DIM
+}
+
+/// The owners `code_owners` gives a chunk of `file_path`. Notes, commits,
+/// and dependency source aren't the project's files, so have none.
+fn owners_of(code_owners: &CodeOwners, namespace: Namespace, file_path: &str) -> Vec<String> {
+    match namespace {
+        Namespace::Code | Namespace::Docs => code_owners.owners(file_path).to_vec(),
+        Namespace::Notes | Namespace::Commits | Namespace::Dependencies => Vec::new(),
+    }
+}
+
+/// A rebuild of the vector index from the stored chunks, started when the
+/// configured metric or dimensions no longer match the index's.
+#[derive(Clone, Serialize, Deserialize)]
+struct Rebuild {
+    /// What changed, e.g. "cos metric, 384 dimensions -> ip metric, 384
+    /// dimensions".
+    reason: String,
+    /// Chunks still waiting for a vector.
+    keys: BTreeSet<u64>,
+}
+
+/// Progress of a rebuild started by a metric or dimension change, from
+/// [`VectorStore::rebuild_status`].
+#[derive(Debug, PartialEq, Eq)]
+pub struct RebuildStatus {
+    pub reason: String,
+    /// Chunks not yet embedded again, and so not yet searchable.
+    pub remaining: usize,
+}
+
+/// A chunk's stored text, to embed again during a rebuild.
+#[derive(Debug)]
+pub struct ChunkText {
+    pub key: u64,
+    pub namespace: Namespace,
+    pub content: String,
+    pub doc: Option<String>,
+}
+
+/// How an index on disk stores its vectors and their text; a copy of the
+/// index can only be searched as is by a store configured the same way.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StoredLayout {
+    pub metric: Metric,
+    pub dimensions: usize,
+    pub content: ContentStorage,
+}
+
+impl StoredLayout {
+    /// The layout of the index saved under `db_path`. Its content is
+    /// `offsets` if any chunk is stored as one.
+    pub fn read(db_path: &Path) -> Result<Self> {
+        #[derive(Deserialize)]
+        struct Stored {
+            #[serde(default)]
+            metric: Metric,
+            #[serde(default = "full_dimensions")]
+            dimensions: usize,
+            chunks: HashMap<u64, ChunkSpan>,
+        }
+        #[derive(Deserialize)]
+        struct ChunkSpan {
+            #[serde(default)]
+            span: Option<Range<usize>>,
+        }
+
+        let meta_path = db_path.join(META_FILE);
+        let data = std::fs::read_to_string(&meta_path).map_err(|e| Error::StoreIo {
+            context: format!("reading {}", meta_path.display()),
+            source: e,
+        })?;
+        let stored: Stored = serde_json::from_str(&data).map_err(Error::StoreSerde)?;
+        let content = if stored.chunks.values().any(|c| c.span.is_some()) {
+            ContentStorage::Offsets
+        } else {
+            ContentStorage::Inline
+        };
+        Ok(Self {
+            metric: stored.metric,
+            dimensions: stored.dimensions,
+            content,
+        })
+    }
+
+    /// The layout a store opened with `config` writes.
+    pub fn of(config: &IndexConfig) -> Result<Self> {
+        Ok(Self {
+            metric: config.metric,
+            dimensions: stored_dimensions(config)?,
+            content: config.content,
+        })
+    }
+}
+
+/// Dimensions to store per vector under `config`: all of them unless
+/// `dimensions` asks for fewer.
+fn stored_dimensions(config: &IndexConfig) -> Result<usize> {
+    match config.dimensions {
+        0 => Ok(EMBEDDING_DIM),
+        dims if dims > EMBEDDING_DIM => Err(Error::Config(format!(
+            "[index] dimensions is {dims}, but embeddings only have {EMBEDDING_DIM} -- \
+             set it to at most {EMBEDDING_DIM}, or 0 to store them all"
+        ))),
+        dims => Ok(dims),
+    }
+}
+
+/// Wait for one of the store's locks inside a `lock_wait` span, so traces
+/// separate time spent behind an indexing batch from the work itself.
+async fn lock_wait<G>(lock: &'static str, acquire: impl Future<Output = G>) -> G {
+    acquire
+        .instrument(tracing::debug_span!("lock_wait", lock))
+        .await
+}
+
+/// Whether `chunk` came from one of the project's files rather than being
+/// a note, a commit message, or dependency source.
+fn is_source(chunk: &ChunkMeta) -> bool {
+    matches!(chunk.namespace, Namespace::Code | Namespace::Docs)
+}
+
+/// The namespace of a chunk from metadata written before namespaces, which
+/// loads as `code`: told apart by the paths and languages notes, commits,
+/// and dependencies are stored under. Docs were indexed as code then.
+fn legacy_namespace(chunk: &ChunkMeta) -> Namespace {
+    if chunk.file_path.starts_with(DEPS_PATH) {
+        Namespace::Dependencies
+    } else if chunk.language == NOTES_LANGUAGE {
+        Namespace::Notes
+    } else if chunk.language == COMMITS_LANGUAGE {
+        Namespace::Commits
+    } else {
+        Namespace::Code
+    }
 }
 
 #[derive(Clone, Serialize, Deserialize)]
 struct ChunkMeta {
     file_path: String,
     chunk_id: i64,
+    /// Empty when `span` is set; the text is then read from the file.
     content: String,
+    /// Byte range of the chunk in its file, kept instead of `content` under
+    /// `[index] content = "offsets"`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    span: Option<Range<usize>>,
+    /// Encoding the chunk's file was decoded from, e.g. `Shift_JIS`;
+    /// absent for UTF-8 files, whose `span` indexes the bytes on disk.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    encoding: Option<String>,
+    /// Whether the chunk's file looks minified or generated; such chunks
+    /// rank lower by `[ranking] generated_penalty`.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    generated: bool,
     symbol_name: Option<String>,
     symbol_kind: Option<String>,
+    /// Doc comment or docstring, without comment markers.
+    #[serde(default)]
+    doc: Option<String>,
+    /// The declaration without its body; absent for chunks indexed before
+    /// signatures were recorded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    signature: Option<String>,
+    /// Build constraint the chunk is compiled under, in its language's
+    /// syntax: `linux && amd64` in Go, `cfg` predicates in Rust.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    build: Option<String>,
+    /// Platforms `build` rules out, worked out on insert so either backend
+    /// can filter on them.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    excluded_platforms: Vec<String>,
+    /// Owners of the chunk's file under `CODEOWNERS`, lowercased.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    owners: Vec<String>,
+    /// Package or module the chunk's file belongs to, e.g. a Go import path.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    module: Option<String>,
     language: String,
     start_line: i64,
     end_line: i64,
     last_modified: i64,
+    /// Tokens before truncation; absent for chunks indexed before counts
+    /// were recorded.
+    #[serde(default)]
+    token_count: Option<usize>,
+    /// Share of the chunk that is comments or docstrings; chunks indexed
+    /// before it was recorded count as all code.
+    #[serde(default)]
+    comment_ratio: f32,
+    /// Part of the index the chunk belongs to; also stored so the Qdrant
+    /// backend can filter on it.
+    #[serde(default)]
+    namespace: Namespace,
+    /// Fingerprint of the embedding model the vector came from; chunks
+    /// embedded before it was recorded count as the current model's.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    model: Option<String>,
+    /// Hash of the text the vector is embedded from, for finding copies;
+    /// absent for chunks never checked for them.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    content_hash: Option<u64>,
+    /// Key of the chunk with the same text whose vector this one shares;
+    /// such a chunk has no vector of its own.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    duplicate_of: Option<u64>,
+}
+
+/// Where the text of a chunk stored as offsets is, copied out of its
+/// [`ChunkMeta`] so it can be read once the store's locks are released.
+struct TextSpan {
+    key: u64,
+    file_path: String,
+    range: Range<usize>,
+    encoding: Option<String>,
+    last_modified: i64,
 }
 
 /// A row ready to be inserted into the vector store.
+#[derive(Default)]
 pub struct ChunkRow {
+    /// Key of the chunk this row replaces, so its identity carries over.
+    /// `None` allocates a fresh key.
+    pub key: Option<u64>,
     pub file_path: String,
     pub chunk_id: i64,
     pub content: String,
     pub symbol_name: Option<String>,
     pub symbol_kind: Option<String>,
+    pub doc: Option<String>,
+    pub signature: Option<String>,
+    /// Build constraint, e.g. `windows` or `all(unix, not(target_os = "macos"))`.
+    pub build: Option<String>,
+    /// Package or module, as [`module_path`](crate::modpath::module_path)
+    /// names it.
+    pub module: Option<String>,
     pub language: String,
     pub start_line: i64,
     pub end_line: i64,
     pub last_modified: i64,
+    pub token_count: Option<usize>,
+    /// Share of the content that is comments or docstrings (0.0 to 1.0).
+    pub comment_ratio: f32,
+    /// Byte range of `content` in the file, if it is a contiguous slice of
+    /// it; lets the store keep the range instead of the text.
+    pub span: Option<Range<usize>>,
+    /// Encoding the file was decoded from, if it isn't UTF-8.
+    pub encoding: Option<String>,
+    /// Whether the file looks minified or generated, which ranks the
+    /// chunk lower.
+    pub generated: bool,
+    pub namespace: Namespace,
     pub vector: Vec<f32>,
 }
 
 /// A single search result.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct SearchResult {
+    /// Stable chunk identity; survives re-indexing of an edited file.
+    pub id: u64,
     pub file_path: String,
     pub content: String,
     pub symbol_name: Option<String>,
     pub symbol_kind: Option<String>,
+    pub doc: Option<String>,
+    /// The declaration without its body, if the chunk has one.
+    pub signature: Option<String>,
+    /// Build constraint the chunk is compiled under, if any.
+    pub build: Option<String>,
+    /// Owners of the chunk's file under `CODEOWNERS`.
+    pub owners: Vec<String>,
+    /// Package or module the chunk's file belongs to, if known.
+    pub module: Option<String>,
+    /// Language of the file the chunk came from, e.g. `go`, or
+    /// [`NOTES_LANGUAGE`] for a note.
+    pub language: String,
     pub start_line: i64,
     pub end_line: i64,
     pub distance: f32,
+    /// Other places the same text is indexed, which share this result's
+    /// vector.
+    pub duplicates: Vec<Location>,
+    /// Whether the chunk was embedded by an earlier model that couldn't
+    /// embed the query, so it was ranked by a query from another model and
+    /// its distance means little.
+    pub approximate: bool,
 }
 
-#[derive(Clone)]
-pub struct VectorStore {
-    index: Arc<RwLock<SendSyncIndex>>,
-    meta: Arc<RwLock<Metadata>>,
-    db_path: PathBuf,
+impl SearchResult {
+    /// The order results are listed in: closest first, ties broken by
+    /// path, then lines, then id, so the same search of the same index
+    /// lists the same results in the same order every time.
+    pub fn rank_cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.distance
+            .total_cmp(&other.distance)
+            .then_with(|| self.file_path.cmp(&other.file_path))
+            .then(self.start_line.cmp(&other.start_line))
+            .then(self.end_line.cmp(&other.end_line))
+            .then(self.id.cmp(&other.id))
+    }
 }
 
-impl VectorStore {
-    pub async fn new(path: &str) -> Result<Self> {
-        let db_path = PathBuf::from(path);
-        let index_path = db_path.join(INDEX_FILE);
-        let meta_path = db_path.join(META_FILE);
+/// Lines of an indexed file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Location {
+    pub file_path: String,
+    pub start_line: i64,
+    pub end_line: i64,
+}
 
-        let opts = IndexOptions {
-            dimensions: EMBEDDING_DIM,
-            metric: MetricKind::Cos,
-            quantization: ScalarKind::F32,
-            ..Default::default()
-        };
+/// A file ranked by [`VectorStore::search_files`].
+#[derive(Debug)]
+pub struct FileHit {
+    pub file_path: String,
+    pub language: String,
+    pub chunks: usize,
+    /// Names of the symbols the file defines, in source order.
+    pub symbols: Vec<String>,
+    /// Cosine distance from the query to the mean of the file's chunk vectors.
+    pub distance: f32,
+}
 
-        let index = Index::new(&opts).map_err(|e| Error::StoreIndex(e.to_string()))?;
+/// A symbol listed by [`VectorStore::list_symbols`].
+#[derive(Debug, PartialEq)]
+pub struct SymbolEntry {
+    pub name: String,
+    pub kind: String,
+    pub file_path: String,
+    pub start_line: i64,
+    pub end_line: i64,
+}
 
-        // Load existing index from disk if present
-        if index_path.exists() {
-            index
-                .load(index_path.to_str().unwrap_or_default())
-                .map_err(|e| Error::StoreIndex(e.to_string()))?;
-        }
+/// A previously indexed chunk, used to match identities on reindex.
+pub struct StoredChunk {
+    pub key: u64,
+    /// Empty for chunks stored as offsets, so their identity carries over
+    /// by symbol name alone.
+    pub content: String,
+    pub symbol_name: Option<String>,
+}
 
-        // Load existing metadata or start fresh
-        let meta = if meta_path.exists() {
-            let data = tokio::fs::read_to_string(&meta_path)
-                .await
-                .map_err(|e| Error::StoreIo {
-                    context: format!("reading {}", meta_path.display()),
-                    source: e,
-                })?;
-            serde_json::from_str(&data).map_err(Error::StoreSerde)?
-        } else {
-            Metadata {
-                next_key: 0,
-                chunks: HashMap::new(),
-            }
-        };
+/// A chunk whose vector came from an embedding model other than the
+/// current one.
+pub struct OutdatedChunk {
+    pub key: u64,
+    pub file_path: String,
+    pub namespace: Namespace,
+    /// The text to embed again, for notes, which have no file to re-read.
+    pub note: Option<String>,
+}
 
-        Ok(Self {
-            index: Arc::new(RwLock::new(SendSyncIndex(index))),
-            meta: Arc::new(RwLock::new(meta)),
-            db_path,
-        })
-    }
+/// How many extra candidates to fetch per requested result when the path
+/// or language boost may reorder them.
+const BOOST_OVERSAMPLE: usize = 2;
 
-    /// Insert a batch of chunk rows.
-    pub async fn insert(&self, rows: Vec<ChunkRow>) -> Result<()> {
-        if rows.is_empty() {
-            return Ok(());
-        }
+/// Chunks a filter may allow for a search that came back short to score
+/// them all exactly; beyond this it searches unfiltered instead.
+const EXACT_RESCORE_MAX: usize = 20_000;
 
-        let mut meta = self.meta.write().await;
-        let index = self.index.write().await;
+/// Most chunks searched exactly when `[index] exact_max_chunks` is 0.
+const EXACT_SEARCH_MAX: usize = 50_000;
 
-        // Reserve space in the index for the new rows
-        let new_capacity = index.0.size() + rows.len();
-        index
-            .0
-            .reserve(new_capacity)
-            .map_err(|e| Error::StoreIndex(e.to_string()))?;
+/// Factor an unfiltered retry asks for more results by each round.
+const RETRY_GROWTH: usize = 4;
 
-        for row in rows {
-            let key = meta.next_key;
-            meta.next_key += 1;
+/// Words too common in queries to say anything about a file's location.
+const PATH_STOPWORDS: &[&str] = &[
+    "the", "and", "for", "with", "how", "what", "where", "when", "does", "that", "this", "from",
+    "into", "code", "function", "method",
+];
+
+/// The text a query vector was embedded from, for lexical refinements on
+/// top of vector similarity.
+pub struct QueryText<'a> {
+    pub text: &'a str,
+    /// Distance subtracted from results whose path matches every query term.
+    pub path_boost: f32,
+    /// Distance subtracted from results in a language the query's wording
+    /// suggests. Only applies without an explicit language filter.
+    pub language_boost: f32,
+    /// Whether to favor code-heavy or comment-heavy chunks.
+    pub focus: Focus,
+    /// Distance subtracted from a chunk entirely of the favored kind
+    /// (proportionally less for mixed ones).
+    pub focus_boost: f32,
+    /// Distance subtracted from results of the kind of code the query's
+    /// wording asks for. Only applies without an explicit kind filter.
+    pub structure_boost: f32,
+    /// Distance added to results from files that look minified or
+    /// generated.
+    pub generated_penalty: f32,
+}
 
-            index
-                .0
-                .add(key, &row.vector)
-                .map_err(|e| Error::StoreIndex(e.to_string()))?;
+/// Whether a search favors implementations or documentation: "where is X
+/// implemented" and "where is X documented" want opposite chunks.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, schemars::JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Focus {
+    /// No preference.
+    #[default]
+    Any,
+    /// Favor chunks that are mostly code.
+    Code,
+    /// Favor chunks that are mostly comments and docstrings.
+    Docs,
+}
 
-            meta.chunks.insert(
-                key,
-                ChunkMeta {
-                    file_path: row.file_path,
-                    chunk_id: row.chunk_id,
-                    content: row.content,
-                    symbol_name: row.symbol_name,
-                    symbol_kind: row.symbol_kind,
-                    language: row.language,
-                    start_line: row.start_line,
-                    end_line: row.end_line,
-                    last_modified: row.last_modified,
-                },
-            );
+impl Focus {
+    /// How strongly a chunk with `comment_ratio` matches, from 0.0 to 1.0.
+    fn affinity(self, comment_ratio: f32) -> f32 {
+        match self {
+            Self::Any => 0.0,
+            Self::Code => 1.0 - comment_ratio,
+            Self::Docs => comment_ratio,
         }
+    }
+}
 
-        self.persist_locked(&index, &meta).await?;
-        Ok(())
+/// Which sources a search covers: dependency source is only searched when
+/// asked for, so it doesn't crowd out the project's own code.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, schemars::JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Scope {
+    /// The project's files, notes, and commit messages.
+    #[default]
+    Project,
+    /// Indexed dependency source only.
+    Dependencies,
+    /// Both.
+    All,
+}
+
+impl Scope {
+    /// Whether chunks in `namespace` are searched.
+    fn includes(self, namespace: Namespace) -> bool {
+        match self {
+            Self::Project => namespace != Namespace::Dependencies,
+            Self::Dependencies => namespace == Namespace::Dependencies,
+            Self::All => true,
+        }
     }
+}
 
-    /// Semantic search by vector similarity.
-    pub async fn search(
-        &self,
-        query_vec: &[f32],
-        limit: usize,
-        language_filter: Option<&str>,
-    ) -> Result<Vec<SearchResult>> {
-        let index = self.index.read().await;
-        let meta = self.meta.read().await;
+/// What a search is restricted to; the default searches all of the
+/// project.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Filters<'a> {
+    /// Only chunks in this language, when set.
+    pub language: Option<&'a str>,
+    /// Only chunks of this symbol kind, when set.
+    pub kind: Option<&'a str>,
+    pub scope: Scope,
+    /// Only chunks in this namespace, when set; it must be one `scope`
+    /// covers.
+    pub namespace: Option<Namespace>,
+    pub modified: Modified,
+    /// Only chunks that can build on this platform, when set; chunks
+    /// without build constraints always can.
+    pub platform: Option<&'a str>,
+    /// Only chunks owned by this owner under `CODEOWNERS`, when set, given
+    /// as [`normalize_owner`](crate::codeowners::normalize_owner) leaves it.
+    pub owner: Option<&'a str>,
+    /// Only chunks in exactly this package or module, when set.
+    pub module: Option<&'a str>,
+    /// Score every chunk rather than searching approximately, overriding
+    /// `[index] exact`, when set. Indexes over `[index] exact_max_chunks`
+    /// are always searched approximately.
+    pub exact: Option<bool>,
+}
 
-        if meta.chunks.is_empty() {
-            return Ok(Vec::new());
+impl Filters<'_> {
+    /// The namespaces both `scope` and `namespace` allow.
+    fn namespaces(&self) -> Vec<Namespace> {
+        Namespace::ALL
+            .into_iter()
+            .filter(|&n| self.scope.includes(n) && self.namespace.is_none_or(|only| only == n))
+            .collect()
+    }
+}
+
+/// Which chunks [`VectorStore::find_by_symbol`] returns.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SymbolQuery<'a> {
+    /// Case-insensitive name pattern: a substring of the name, or, if it
+    /// has `*` wildcards, the whole name. Empty matches any named symbol.
+    pub name: &'a str,
+    /// Only symbols of this kind, when set: the exact kind, or one word of
+    /// it, so "method" covers both `method_declaration` and `method_item`.
+    pub kind: Option<&'a str>,
+    /// Only symbols in this file or under this directory, when set.
+    pub path: Option<&'a str>,
+    pub namespace: Option<Namespace>,
+}
+
+impl<'a> SymbolQuery<'a> {
+    /// Symbols whose name matches `name`, of any kind, anywhere.
+    pub fn named(name: &'a str) -> Self {
+        Self {
+            name,
+            ..Self::default()
         }
+    }
+}
 
-        let results = match language_filter {
-            Some(lang) => {
-                let lang = lang.to_string();
-                index
-                    .0
-                    .filtered_search(query_vec, limit, |key| {
-                        meta.chunks.get(&key).is_some_and(|c| c.language == lang)
-                    })
-                    .map_err(|e| Error::StoreIndex(e.to_string()))?
-            }
-            None => index
-                .0
-                .search(query_vec, limit)
-                .map_err(|e| Error::StoreIndex(e.to_string()))?,
-        };
+/// Sort key of a symbol match, from [`symbol_rank`].
+type SymbolRank<'a> = (u8, usize, usize, &'a str, i64);
 
-        Ok(results
-            .keys
-            .iter()
-            .zip(results.distances.iter())
-            .filter_map(|(&key, &dist)| {
-                let chunk = meta.chunks.get(&key)?;
-                Some(SearchResult {
-                    file_path: chunk.file_path.clone(),
-                    content: chunk.content.clone(),
-                    symbol_name: chunk.symbol_name.clone(),
-                    symbol_kind: chunk.symbol_kind.clone(),
-                    start_line: chunk.start_line,
-                    end_line: chunk.end_line,
-                    distance: dist,
-                })
-            })
-            .collect())
+/// How well a chunk's symbol matches the name pattern of a
+/// [`SymbolQuery`], lower first: exact names (case and all, then ignoring
+/// case), then prefixes, then other matches; among those shorter names,
+/// then files nearer the root, then file and line, so the same query
+/// always ranks the same way. A wildcard pattern's prefix is the text
+/// before its first `*`.
+fn symbol_rank<'a>(pattern: &str, chunk: &'a ChunkMeta) -> SymbolRank<'a> {
+    let name = chunk.symbol_name.as_deref().unwrap_or_default();
+    let (lower_name, lower_pattern) = (name.to_lowercase(), pattern.to_lowercase());
+    let prefix = lower_pattern.split('*').next().unwrap_or_default();
+    let exactness = if pattern.is_empty() || name == pattern {
+        0
+    } else if lower_name == lower_pattern {
+        1
+    } else if !prefix.is_empty() && lower_name.starts_with(prefix) {
+        2
+    } else {
+        3
+    };
+    let length = if pattern.is_empty() { 0 } else { name.len() };
+    let depth = chunk.file_path.matches('/').count();
+    (exactness, length, depth, &chunk.file_path, chunk.start_line)
+}
+
+/// Whether the lowercase `name` matches the lowercase `pattern`, where `*`
+/// matches any run of characters.
+fn wildcard_match(pattern: &[char], name: &[char]) -> bool {
+    match pattern {
+        [] => name.is_empty(),
+        ['*', rest @ ..] => (0..=name.len()).any(|i| wildcard_match(rest, &name[i..])),
+        [c, rest @ ..] => name.first() == Some(c) && wildcard_match(rest, &name[1..]),
     }
+}
 
-    /// Delete all chunks for a given file path.
-    pub async fn delete_file(&self, file_path: &str) -> Result<()> {
-        let mut meta = self.meta.write().await;
-        let index = self.index.write().await;
+/// A window of modification times, in Unix seconds, that searched chunks
+/// must fall in: files by when they were last indexed changed, notes by
+/// when they were added, commits by when they were made.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Modified {
+    /// At or after this time, when set.
+    pub after: Option<i64>,
+    /// Before this time, when set.
+    pub before: Option<i64>,
+}
 
-        let keys_to_remove: Vec<u64> = meta
-            .chunks
-            .iter()
-            .filter(|(_, c)| c.file_path == file_path)
-            .map(|(&k, _)| k)
-            .collect();
+impl Modified {
+    fn is_any(self) -> bool {
+        self.after.is_none() && self.before.is_none()
+    }
 
-        if keys_to_remove.is_empty() {
-            return Ok(());
-        }
+    fn includes(self, last_modified: i64) -> bool {
+        self.after.is_none_or(|after| last_modified >= after)
+            && self.before.is_none_or(|before| last_modified < before)
+    }
+}
 
-        for &key in &keys_to_remove {
-            index
-                .0
-                .remove(key)
-                .map_err(|e| Error::StoreIndex(e.to_string()))?;
-            meta.chunks.remove(&key);
+/// Lowercased query words worth looking for in a path.
+fn query_terms(query: &str) -> Vec<String> {
+    let mut terms: Vec<String> = Vec::new();
+    for word in query.split(|c: char| !c.is_alphanumeric()) {
+        let word = word.to_lowercase();
+        if word.len() >= 3 && !PATH_STOPWORDS.contains(&word.as_str()) && !terms.contains(&word) {
+            terms.push(word);
         }
-
-        self.persist_locked(&index, &meta).await?;
-        Ok(())
     }
+    terms
+}
 
-    /// Find chunks whose symbol_name contains the given pattern (case-insensitive substring match).
-    pub async fn find_by_symbol(
-        &self,
-        pattern: &str,
-        kind_filter: Option<&str>,
-        limit: usize,
-    ) -> Result<Vec<SearchResult>> {
-        let meta = self.meta.read().await;
-        let lower_pattern = pattern.to_lowercase();
+/// Distance between `a` and `b` as usearch computes it for `metric`.
+fn distance(metric: Metric, a: &[f32], b: &[f32]) -> f32 {
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    match metric {
+        Metric::Cos => {
+            let norms = a.iter().map(|x| x * x).sum::<f32>().sqrt()
+                * b.iter().map(|x| x * x).sum::<f32>().sqrt();
+            if norms > 0.0 { 1.0 - dot / norms } else { 1.0 }
+        }
+        Metric::Ip => 1.0 - dot,
+        Metric::L2sq => a.iter().zip(b).map(|(x, y)| (x - y) * (x - y)).sum(),
+    }
+}
 
-        let results: Vec<SearchResult> = meta
-            .chunks
-            .values()
-            .filter(|c| {
-                c.symbol_name
-                    .as_ref()
-                    .is_some_and(|name| name.to_lowercase().contains(&lower_pattern))
-            })
-            .filter(|c| kind_filter.is_none_or(|kind| c.symbol_kind.as_deref() == Some(kind)))
-            .take(limit)
-            .map(|c| SearchResult {
+/// Where the other copies of the chunk stored under `key`, shown as
+/// `shown`, are, by path and line; only those `filter` passes. Each copy's
+/// file is also reported under its aliases, as is the shown chunk's.
+fn duplicates_of(meta: &Metadata, key: u64, shown: u64, filter: &SearchFilter) -> Vec<Location> {
+    let mut locations: Vec<Location> = std::iter::once(key)
+        .chain(meta.copies.duplicates(key))
+        .filter(|&k| k == shown || filter.allows_chunk(k))
+        .filter_map(|k| Some((k, meta.chunks.get(&k)?)))
+        .flat_map(|(k, c)| {
+            let copy = (k != shown).then(|| Location {
                 file_path: c.file_path.clone(),
-                content: c.content.clone(),
-                symbol_name: c.symbol_name.clone(),
-                symbol_kind: c.symbol_kind.clone(),
                 start_line: c.start_line,
                 end_line: c.end_line,
-                distance: 0.0,
-            })
-            .collect();
+            });
+            copy.into_iter().chain(aliases_of(meta, c))
+        })
+        .collect();
+    locations.sort_by(|a, b| (&a.file_path, a.start_line).cmp(&(&b.file_path, b.start_line)));
+    locations
+}
 
-        Ok(results)
-    }
+/// The chunk's lines under each path its file is also reached by through
+/// a symlink.
+fn aliases_of<'a>(meta: &'a Metadata, chunk: &'a ChunkMeta) -> impl Iterator<Item = Location> + 'a {
+    meta.aliases
+        .get(&chunk.file_path)
+        .into_iter()
+        .flatten()
+        .map(|alias| Location {
+            file_path: alias.clone(),
+            start_line: chunk.start_line,
+            end_line: chunk.end_line,
+        })
+}
 
-    /// Return distinct file paths in the index, optionally filtered by language.
-    pub async fn list_files(&self, language_filter: Option<&str>) -> Result<Vec<String>> {
-        let meta = self.meta.read().await;
+/// Finish a filtered search that found fewer than `limit` chunks. HNSW
+/// search stops after a bounded set of candidates, so a filter that rejects
+/// most of them can miss chunks that match. If more chunks match than were
+/// `found`, they are scored exactly when there are few enough, and
+/// otherwise the index is searched without the filter, for more results
+/// each round, and the filter applied to what comes back.
+fn refill(
+    backend: &dyn VectorStoreBackend,
+    metric: Metric,
+    query: &[f32],
+    limit: usize,
+    filter: &SearchFilter<'_>,
+    found: Vec<(u64, f32)>,
+) -> Result<Vec<(u64, f32)>> {
+    // Duplicates have no vectors of their own to score
+    let searchable = |key: u64| {
+        filter.allows(key)
+            && filter
+                .chunks
+                .get(&key)
+                .is_some_and(|c| c.duplicate_of.is_none())
+    };
+    let allowed: Vec<u64> = match filter.keys {
+        Some(keys) => keys.iter().copied().filter(|&k| searchable(k)).collect(),
+        None => filter
+            .chunks
+            .keys()
+            .copied()
+            .filter(|&k| searchable(k))
+            .collect(),
+    };
+    if allowed.len() <= found.len() {
+        return Ok(found);
+    }
+    tracing::debug!(
+        "filtered search found {} of {} matching chunks; searching further",
+        found.len(),
+        allowed.len()
+    );
 
-        let mut paths = std::collections::BTreeSet::new();
-        for chunk in meta.chunks.values() {
-            if language_filter.is_none_or(|lang| chunk.language == lang) {
-                paths.insert(chunk.file_path.clone());
+    let mut hits: Vec<(u64, f32)> = if allowed.len() <= EXACT_RESCORE_MAX {
+        backend
+            .vectors(&allowed)?
+            .into_iter()
+            .map(|(key, vector)| (key, distance(metric, query, &vector)))
+            .collect()
+    } else {
+        let unfiltered = SearchFilter {
+            keys: None,
+            language: None,
+            kind: None,
+            namespaces: &Namespace::ALL,
+            modified: Modified::default(),
+            platform: None,
+            owner: None,
+            module: None,
+            space: None,
+            chunks: filter.chunks,
+            copies: filter.copies,
+        };
+        let total = filter.chunks.len();
+        let mut fetch = limit;
+        loop {
+            fetch = (fetch * RETRY_GROWTH).min(total);
+            let hits: Vec<(u64, f32)> = backend
+                .search(query, fetch, &unfiltered)?
+                .into_iter()
+                .filter(|&(key, _)| filter.allows(key))
+                .collect();
+            if hits.len() >= limit || fetch == total {
+                break hits;
             }
         }
-
-        Ok(paths.into_iter().collect())
+    };
+    if hits.len() < found.len() {
+        return Ok(found);
     }
+    hits.sort_by(|a, b| a.1.total_cmp(&b.1).then(a.0.cmp(&b.0)));
+    hits.truncate(limit);
+    Ok(hits)
+}
 
-    /// Count total indexed chunks.
-    pub async fn chunk_count(&self) -> Result<u64> {
-        let meta = self.meta.read().await;
-        Ok(meta.chunks.len() as u64)
+/// One model's part of a search of an index whose chunks come from more
+/// than one.
+struct SpaceQuery<'a> {
+    /// The chunks searched; `None` for all of them.
+    space: Option<Space<'a>>,
+    /// The query as the space's model embeds it, or the current model's
+    /// when the space's couldn't embed it.
+    vector: Vec<f32>,
+    /// Share of the index's chunks in the space, and so of the results.
+    share: f32,
+    /// Whether `vector` came from another model than the space's.
+    approximate: bool,
+}
+
+/// What the query's text favors; see [`QueryText`].
+#[derive(Clone, Copy)]
+struct Boosts<'a> {
+    terms: &'a [String],
+    languages: &'a [&'static str],
+    structures: &'a [Structure],
+}
+
+/// Merge results from the vector spaces of different embedding models,
+/// whose distances can't be compared, by rank within each: each space
+/// holds its share of the chunks and contributes that share of the
+/// results, ties going to the earlier space. Each list is in rank order.
+fn merge_spaces(spaces: Vec<(Vec<SearchResult>, f32)>) -> Vec<SearchResult> {
+    let rank = |i: usize, share: f32| (i as f32 + 1.0) / share.max(f32::EPSILON);
+    let mut spaces: Vec<_> = spaces
+        .into_iter()
+        .map(|(hits, share)| (hits.into_iter().enumerate().peekable(), share))
+        .collect();
+    let mut merged = Vec::new();
+    loop {
+        let next = spaces
+            .iter_mut()
+            .filter_map(|(hits, share)| Some((rank(hits.peek()?.0, *share), hits)))
+            .min_by(|a, b| a.0.total_cmp(&b.0));
+        match next.and_then(|(_, hits)| hits.next()) {
+            Some((_, hit)) => merged.push(hit),
+            None => return merged,
+        }
+    }
+}
+
+/// Fraction of `terms` that appear among the components of `file_path`
+/// (directories, which double as Go/Python package names, and the file
+/// stem). A term matches a component it is a prefix of, or vice versa, so
+/// "auth" matches `authn/` and "handlers" matches `handler.go`.
+fn path_match(terms: &[String], file_path: &str) -> f32 {
+    if terms.is_empty() {
+        return 0.0;
+    }
+    let lower = file_path.to_lowercase();
+    let parts: Vec<&str> = lower
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|p| p.len() >= 3)
+        .collect();
+    let matched = terms
+        .iter()
+        .filter(|t| {
+            parts
+                .iter()
+                .any(|p| p.starts_with(t.as_str()) || t.starts_with(p))
+        })
+        .count();
+    matched as f32 / terms.len() as f32
+}
+
+/// Backend and HNSW settings an open index is running with.
+#[derive(Debug)]
+pub struct IndexSettings {
+    pub backend: Backend,
+    pub metric: Metric,
+    /// Dimensions stored per vector, at most [`EMBEDDING_DIM`].
+    pub dimensions: usize,
+    pub connectivity: usize,
+    pub expansion_add: usize,
+    pub expansion_search: usize,
+}
+
+/// Disagreements between vectors and metadata found when a store opened,
+/// e.g. after a crash between saving the index and saving the metadata.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct RepairSummary {
+    /// Chunks with no vector, dropped so the next index run re-embeds them.
+    pub chunks_without_vectors: usize,
+    /// Vectors with no chunk metadata. Deleted, or ignored by searches when
+    /// the store is read-only.
+    pub vectors_without_chunks: usize,
+}
+
+impl RepairSummary {
+    pub fn is_clean(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+impl std::fmt::Display for RepairSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} chunks without vectors, {} vectors without chunks",
+            self.chunks_without_vectors, self.vectors_without_chunks
+        )
+    }
+}
+
+/// Drop metadata for chunks the backend has no vector for and, unless
+/// `read_only`, delete vectors no chunk refers to.
+fn reconcile(
+    backend: &mut dyn VectorStoreBackend,
+    meta: &mut Metadata,
+    read_only: bool,
+) -> Result<RepairSummary> {
+    let Some(stored) = backend.keys(meta.next_key)? else {
+        return Ok(RepairSummary::default());
+    };
+
+    // Chunks awaiting a rebuild have no vectors yet, and duplicates use
+    // the vector of the chunk they copy
+    let rebuilding = meta.rebuild.as_ref().map(|r| &r.keys);
+    let has_vector =
+        |key: &u64| stored.contains(key) || rebuilding.is_some_and(|keys| keys.contains(key));
+    let dangling: Vec<u64> = meta
+        .chunks
+        .iter()
+        .filter(|(key, chunk)| !has_vector(chunk.duplicate_of.as_ref().unwrap_or(key)))
+        .map(|(&key, _)| key)
+        .collect();
+    for key in &dangling {
+        if let Some(chunk) = meta.chunks.remove(key) {
+            meta.postings.remove(*key, &chunk.content);
+        }
+    }
+
+    let orphans: Vec<u64> = stored
+        .iter()
+        .filter(|key| !meta.chunks.contains_key(key))
+        .copied()
+        .collect();
+    if !read_only && !orphans.is_empty() {
+        backend.delete(&orphans)?;
+    }
+
+    Ok(RepairSummary {
+        chunks_without_vectors: dangling.len(),
+        vectors_without_chunks: orphans.len(),
+    })
+}
+
+/// How many stored chunks overflow the embedding window.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct TokenStats {
+    /// Chunks with a recorded token count.
+    pub measured: u64,
+    /// Chunks longer than `MAX_SEQ_LEN` tokens, whose tail was never embedded.
+    pub truncated: u64,
+}
+
+/// Chunk vectors and their metadata, searched together.
+///
+/// The store's locks are always taken in the order `meta`, `backend`,
+/// `file_vectors`, `removed`, skipping the ones not needed, so no task
+/// waits on a lock while holding one that comes after it. A search waits
+/// for its turn in `searches` before taking any. Writers save
+/// the store under read locks, so searches and metadata lookups go on
+/// while a batch is written to disk; they wait while it is applied,
+/// since writers hold `meta` for writing then.
+#[derive(Clone)]
+pub struct VectorStore {
+    /// Chunks by key, and everything else kept in the metadata file.
+    /// Lookups by path or symbol take only this lock.
+    meta: Arc<RwLock<Metadata>>,
+    /// The vectors, searched under a read lock.
+    backend: Arc<RwLock<Box<dyn VectorStoreBackend>>>,
+    /// Turns to search a usearch index; `None` for Qdrant. Taken before
+    /// the locks, so a search waiting for a turn holds none of them.
+    searches: Option<Arc<Semaphore>>,
+    /// Directory the store is saved in; `None` for one kept in memory.
+    db_path: Option<PathBuf>,
+    read_only: bool,
+    repair: RepairSummary,
+    /// Embeddings are truncated to this many dimensions before they reach
+    /// the backend.
+    dimensions: usize,
+    /// Mean chunk vector per file, computed on first use by
+    /// [`VectorStore::search_files`] and dropped when the file changes.
+    file_vectors: Arc<RwLock<HashMap<String, Vec<f32>>>>,
+    /// How new chunks keep their text.
+    content: ContentStorage,
+    snippet_cache: usize,
+    /// Reads the text of chunks stored as offsets; set by
+    /// [`VectorStore::with_source_root`].
+    snippets: Option<Arc<Snippets>>,
+    /// Fingerprint of the embedding model new vectors come from; set by
+    /// [`VectorStore::with_model`].
+    model: Option<Arc<str>>,
+    /// Whether chunks with the same text share one vector. Only the local
+    /// backend dedups, since Qdrant's points are the only record other
+    /// readers of the collection have.
+    dedup: bool,
+    /// Whether searches score every chunk unless they say otherwise.
+    exact: bool,
+    /// Most chunks searched exactly.
+    exact_max: usize,
+    /// Files [`VectorStore::delete_file`] removed, until they turn up
+    /// under another path or grow too old to keep.
+    removed: Arc<Mutex<RemovedFiles>>,
+    /// Most bytes the [`STORE_FILES`] may take before new chunks are
+    /// refused; `None` for no limit.
+    max_bytes: Option<u64>,
+    /// Bytes the [`STORE_FILES`] took when the store was last saved.
+    disk_bytes: Arc<AtomicU64>,
+}
+
+impl VectorStore {
+    pub async fn new(path: &str, config: &IndexConfig) -> Result<Self> {
+        Self::open(path, config, false).await
+    }
+
+    /// Open an existing store without write access. A usearch index is
+    /// memory-mapped rather than loaded, and every mutation fails with
+    /// [`Error::ReadOnly`], so a shared index (e.g. on a network drive or a
+    /// Qdrant server) is never modified.
+    pub async fn open_read_only(path: &str, config: &IndexConfig) -> Result<Self> {
+        Self::open(path, config, true).await
+    }
+
+    async fn open(path: &str, config: &IndexConfig, read_only: bool) -> Result<Self> {
+        let db_path = PathBuf::from(path);
+        let meta_path = db_path.join(META_FILE);
+        let dimensions = stored_dimensions(config)?;
+
+        // Load existing metadata or start fresh
+        let mut meta = if meta_path.exists() {
+            let data = tokio::fs::read_to_string(&meta_path)
+                .await
+                .map_err(|e| Error::StoreIo {
+                    context: format!("reading {}", meta_path.display()),
+                    source: e,
+                })?;
+            serde_json::from_str(&data).map_err(Error::StoreSerde)?
+        } else {
+            Metadata::empty(config.metric, dimensions)
+        };
+
+        // A local index built for other settings is rebuilt from the stored
+        // chunks: the old vectors are dropped and each chunk is embedded
+        // again by the indexer, searchable once it is
+        let mismatched = meta.dimensions != dimensions || meta.metric != config.metric;
+        if mismatched
+            && !read_only
+            && config.backend == Backend::Usearch
+            && config.on_mismatch == OnMismatch::Rebuild
+        {
+            let reason = format!(
+                "{} metric, {} dimensions -> {} metric, {dimensions} dimensions",
+                meta.metric, meta.dimensions, config.metric
+            );
+            tracing::warn!(
+                "the index at {} was built for other settings ({reason}); rebuilding it",
+                db_path.display()
+            );
+            match std::fs::remove_file(db_path.join(INDEX_FILE)) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                    return Err(Error::StoreIo {
+                        context: format!("removing the old index in {}", db_path.display()),
+                        source: e,
+                    });
+                }
+                _ => {}
+            }
+            meta.metric = config.metric;
+            meta.dimensions = dimensions;
+            let keys = meta
+                .chunks
+                .iter()
+                .filter(|(_, chunk)| chunk.duplicate_of.is_none())
+                .map(|(&key, _)| key)
+                .collect();
+            meta.rebuild = Some(Rebuild { reason, keys });
+        }
+
+        let rebuild_hint = if read_only {
+            "open it without --read-only to rebuild it"
+        } else if config.backend == Backend::Qdrant {
+            "delete the Qdrant collection to rebuild it"
+        } else {
+            "set [index] on_mismatch = \"rebuild\" to rebuild it"
+        };
+
+        // Checked before opening the backend, which can't load a mismatched index
+        if meta.dimensions != dimensions {
+            return Err(Error::StoreIndex(format!(
+                "the index at {} stores {}-dimensional vectors, but [index] dimensions asks \
+                 for {dimensions} -- set it back to {}, or {rebuild_hint}",
+                db_path.display(),
+                meta.dimensions,
+                if meta.dimensions == EMBEDDING_DIM {
+                    0
+                } else {
+                    meta.dimensions
+                }
+            )));
+        }
+
+        let mut backend: Box<dyn VectorStoreBackend> = match config.backend {
+            Backend::Usearch => Box::new(hnsw::HnswBackend::open(
+                &db_path, config, dimensions, read_only,
+            )?),
+            Backend::Qdrant => Box::new(qdrant::QdrantBackend::connect(
+                config, dimensions, read_only,
+            )?),
+        };
+
+        // A shared backend is the source of truth for what's indexed
+        if let Some(chunks) = backend.list()? {
+            let next_key = chunks.keys().max().map_or(0, |&k| k + 1);
+            meta.next_key = meta.next_key.max(next_key);
+            meta.chunks = chunks;
+        }
+        let Metadata {
+            chunks, postings, ..
+        } = &mut meta;
+        for (&key, chunk) in chunks.iter_mut() {
+            postings.add(key, &chunk.content);
+            if chunk.namespace == Namespace::Code {
+                chunk.namespace = legacy_namespace(chunk);
+            }
+        }
+
+        if meta.metric != config.metric {
+            return Err(Error::StoreIndex(format!(
+                "the index at {} was built with the {} metric, but [index] metric is {} -- \
+                 set it back to {}, or {rebuild_hint}",
+                db_path.display(),
+                meta.metric,
+                config.metric,
+                meta.metric
+            )));
+        }
+
+        let repair = reconcile(backend.as_mut(), &mut meta, read_only)?;
+        meta.copies = Copies::build(&meta.chunks);
+        let exact_max = match config.exact_max_chunks {
+            0 => EXACT_SEARCH_MAX,
+            max => max,
+        };
+        if config.exact && meta.chunks.len() <= exact_max {
+            let keys: Vec<u64> = meta
+                .chunks
+                .iter()
+                .filter(|(_, chunk)| chunk.duplicate_of.is_none())
+                .map(|(&key, _)| key)
+                .collect();
+            backend.hold_vectors(&keys, exact_max)?;
+        }
+        if !repair.is_clean() {
+            tracing::warn!(
+                "index at {} was inconsistent ({repair}){}",
+                db_path.display(),
+                if read_only {
+                    "; ignoring the mismatches since it is read-only"
+                } else {
+                    "; repaired it"
+                }
+            );
+        }
+
+        let disk_bytes = stored_size(&db_path);
+        let store = Self {
+            backend: Arc::new(RwLock::new(backend)),
+            searches: (config.backend == Backend::Usearch).then(|| Arc::new(hnsw::search_slots())),
+            meta: Arc::new(RwLock::new(meta)),
+            db_path: Some(db_path),
+            read_only,
+            repair,
+            dimensions,
+            file_vectors: Arc::default(),
+            content: config.content,
+            snippet_cache: config.snippet_cache,
+            snippets: None,
+            model: None,
+            dedup: config.backend == Backend::Usearch,
+            exact: config.exact,
+            exact_max,
+            removed: Arc::default(),
+            max_bytes: (config.max_bytes > 0).then_some(config.max_bytes),
+            disk_bytes: Arc::new(AtomicU64::new(disk_bytes)),
+        };
+        if !read_only && (!repair.is_clean() || mismatched) {
+            let meta = store.meta.read().await;
+            let backend = store.backend.read().await;
+            store.persist_locked(backend.as_ref(), &meta).await?;
+        }
+        Ok(store)
+    }
+
+    /// Read chunk text from the files under `root`, which the index was
+    /// built from. Needed to return chunks stored as offsets under `[index]
+    /// content = "offsets"`, and to store new ones that way; their quoted
+    /// identifier lookups are also rebuilt here, from the files.
+    pub async fn with_source_root(mut self, root: &Path) -> Self {
+        let snippets = Snippets::new(root.to_path_buf(), self.snippet_cache);
+        {
+            let mut meta = self.meta.write().await;
+            let Metadata {
+                chunks, postings, ..
+            } = &mut *meta;
+            type Spans<'a> = (Option<&'a str>, Vec<(u64, &'a Range<usize>)>);
+            let mut by_file: HashMap<&str, Spans<'_>> = HashMap::new();
+            for (&key, chunk) in chunks.iter() {
+                if let Some(span) = &chunk.span {
+                    let (encoding, spans) = by_file.entry(&chunk.file_path).or_default();
+                    *encoding = chunk.encoding.as_deref();
+                    spans.push((key, span));
+                }
+            }
+            for (file_path, (encoding, spans)) in by_file {
+                let Some(text) = snippets.read_file(file_path, encoding) else {
+                    continue;
+                };
+                for (key, span) in spans {
+                    if let Some(content) = text.get(span.clone()) {
+                        postings.add(key, content);
+                    }
+                }
+            }
+        }
+        self.snippets = Some(Arc::new(snippets));
+        self
+    }
+
+    /// Record `version`, an [`Embedder::version`](crate::embed::Embedder::version),
+    /// as the model new vectors come from, loaded with `config`. Chunks
+    /// embedded by another model are then reported by
+    /// [`VectorStore::outdated_chunks`], and searched in their own vector
+    /// space until they are embedded again.
+    pub fn with_model(mut self, version: &str, config: &EmbeddingConfig) -> Self {
+        self.model = Some(Arc::from(version));
+        // Nothing else holds the store yet
+        if let Ok(mut meta) = self.meta.try_write() {
+            meta.outdated = self.count_outdated(&meta);
+            let Metadata {
+                models, outdated, ..
+            } = &mut *meta;
+            models.retain(|model, _| outdated.contains_key(model));
+            models.insert(version.to_string(), config.clone());
+        }
+        self
+    }
+
+    /// Chunks in `meta` with a vector of their own from another model, by
+    /// that model.
+    fn count_outdated(&self, meta: &Metadata) -> BTreeMap<String, usize> {
+        let mut outdated = BTreeMap::new();
+        for chunk in meta.chunks.values() {
+            if let Some(model) = self
+                .outdated_model(chunk)
+                .filter(|_| chunk.duplicate_of.is_none())
+            {
+                *outdated.entry(model.to_string()).or_default() += 1;
+            }
+        }
+        outdated
+    }
+
+    /// The earlier models chunks still hold vectors from, each with the
+    /// settings it was loaded with, if the index recorded them.
+    pub async fn earlier_models(&self) -> Vec<(String, Option<EmbeddingConfig>)> {
+        let meta = self.meta.read().await;
+        meta.outdated
+            .keys()
+            .map(|model| (model.clone(), meta.models.get(model).cloned()))
+            .collect()
+    }
+
+    /// When the index in the data directory was last saved, by this
+    /// process or another; `None` if it never was.
+    pub fn saved_at(&self) -> Option<SystemTime> {
+        std::fs::metadata(self.db_path.as_ref()?.join(META_FILE))
+            .and_then(|m| m.modified())
+            .ok()
+    }
+
+    /// Replace what a read-only store holds with the index as last saved,
+    /// to pick up what the process writing it has indexed since.
+    pub async fn reload(&self, config: &IndexConfig) -> Result<()> {
+        let Some(db_path) = &self.db_path else {
+            return Ok(());
+        };
+        let mut fresh = Self::open(&db_path.to_string_lossy(), config, true).await?;
+        if let Some(snippets) = &self.snippets {
+            fresh = fresh.with_source_root(snippets.root()).await;
+        }
+        let mut meta = self.meta.write().await;
+        let mut backend = self.backend.write().await;
+        std::mem::swap(&mut *meta, &mut *fresh.meta.write().await);
+        std::mem::swap(&mut *backend, &mut *fresh.backend.write().await);
+        meta.outdated = self.count_outdated(&meta);
+        self.file_vectors.write().await.clear();
+        // Chunks keep their keys when their file is indexed again
+        if let Some(snippets) = &self.snippets {
+            snippets.clear();
+        }
+        Ok(())
+    }
+
+    /// Whether `chunk` was embedded by a model other than the current one.
+    fn is_outdated(&self, chunk: &ChunkMeta) -> bool {
+        self.outdated_model(chunk).is_some()
+    }
+
+    /// The model other than the current one that embedded `chunk`, if any.
+    fn outdated_model<'c>(&self, chunk: &'c ChunkMeta) -> Option<&'c str> {
+        match (&self.model, &chunk.model) {
+            (Some(current), Some(model)) if **current != **model => Some(model),
+            _ => None,
+        }
+    }
+
+    /// The text of `chunk`, stored under `key`: its content, or for a chunk
+    /// stored as offsets, its span of the file.
+    fn text(&self, key: u64, chunk: &ChunkMeta) -> String {
+        match (&chunk.span, &self.snippets) {
+            (Some(span), Some(snippets)) => snippets.get(
+                key,
+                &chunk.file_path,
+                span,
+                chunk.encoding.as_deref(),
+                chunk.last_modified,
+            ),
+            _ => chunk.content.clone(),
+        }
+    }
+
+    /// Where to read the text of `chunk`, stored under `key`, if it is
+    /// stored as offsets.
+    fn text_span(&self, key: u64, chunk: &ChunkMeta) -> Option<TextSpan> {
+        self.snippets.as_ref()?;
+        Some(TextSpan {
+            key,
+            file_path: chunk.file_path.clone(),
+            range: chunk.span.clone()?,
+            encoding: chunk.encoding.clone(),
+            last_modified: chunk.last_modified,
+        })
+    }
+
+    /// Read the text of the chunks at `spans`, each for the hit at its
+    /// index, in a blocking task; called without the store's locks held.
+    async fn read_texts(
+        &self,
+        hits: &mut [SearchResult],
+        spans: Vec<(usize, TextSpan)>,
+    ) -> Result<()> {
+        let Some(snippets) = self.snippets.clone().filter(|_| !spans.is_empty()) else {
+            return Ok(());
+        };
+        let texts = tokio::task::spawn_blocking(move || {
+            spans
+                .into_iter()
+                .map(|(hit, span)| {
+                    let text = snippets.get(
+                        span.key,
+                        &span.file_path,
+                        &span.range,
+                        span.encoding.as_deref(),
+                        span.last_modified,
+                    );
+                    (hit, text)
+                })
+                .collect::<Vec<_>>()
+        })
+        .await?;
+        for (hit, text) in texts {
+            hits[hit].content = text;
+        }
+        Ok(())
+    }
+
+    /// What the consistency check found when the store was opened.
+    pub fn repair_summary(&self) -> RepairSummary {
+        self.repair
+    }
+
+    /// The backend, metric, dimensions, and HNSW parameters in effect.
+    pub async fn settings(&self) -> IndexSettings {
+        self.backend.read().await.settings()
+    }
+
+    /// Truncate a full embedding to the stored dimensions, if fewer.
+    fn fit(&self, vector: Vec<f32>) -> Vec<f32> {
+        if vector.len() > self.dimensions {
+            truncate(&vector, self.dimensions)
+        } else {
+            vector
+        }
+    }
+
+    /// Bytes the [`STORE_FILES`] take on disk, as of the last save, and the
+    /// most `[index] max_bytes` allows, if it sets a limit.
+    pub fn disk_usage(&self) -> (u64, Option<u64>) {
+        (self.disk_bytes.load(Ordering::Relaxed), self.max_bytes)
+    }
+
+    /// [`Error::IndexFull`] if the index files have reached `[index]
+    /// max_bytes`, so no more chunks may be stored.
+    fn check_space(&self) -> Result<()> {
+        let bytes = self.disk_bytes.load(Ordering::Relaxed);
+        match self.max_bytes {
+            Some(max_bytes) if bytes >= max_bytes => Err(Error::IndexFull { bytes, max_bytes }),
+            _ => Ok(()),
+        }
+    }
+
+    /// [`Error::IndexFull`] if the index files would outgrow `[index]
+    /// max_bytes` once a batch that turned `before` into `after` is saved,
+    /// going by the chunks it added and removed: their metadata, and their
+    /// vectors where the index file holds them. Chunks kept under the same
+    /// key count as unchanged.
+    fn check_space_after(
+        &self,
+        before: &Metadata,
+        after: &Metadata,
+        backend: &dyn VectorStoreBackend,
+    ) -> Result<()> {
+        let Some(max_bytes) = self.max_bytes else {
+            return Ok(());
+        };
+        let settings = backend.settings();
+        // A vector and its neighbor links on the graph's base layer
+        let vector = match settings.backend {
+            Backend::Usearch => {
+                settings.dimensions * size_of::<f32>()
+                    + settings.connectivity * 2 * size_of::<u64>()
+            }
+            Backend::Qdrant => 0,
+        } as u64;
+        let size = |chunk: &ChunkMeta| {
+            let json = serde_json::to_vec(chunk).map_or(0, |json| json.len() as u64);
+            json + if chunk.duplicate_of.is_none() {
+                vector
+            } else {
+                0
+            }
+        };
+        let changed = |from: &Metadata, to: &Metadata| -> u64 {
+            to.chunks
+                .iter()
+                .filter(|(key, _)| !from.chunks.contains_key(key))
+                .map(|(_, chunk)| size(chunk))
+                .sum()
+        };
+        let bytes = (self.disk_bytes.load(Ordering::Relaxed) + changed(before, after))
+            .saturating_sub(changed(after, before));
+        if bytes > max_bytes {
+            return Err(Error::IndexFull { bytes, max_bytes });
+        }
+        Ok(())
+    }
+
+    /// An empty store with `config`'s metric and dimensions that lives in
+    /// memory: nothing written to it is saved, and it always uses the local
+    /// backend, so trying out writes touches no index.
+    pub fn in_memory(config: &IndexConfig) -> Result<Self> {
+        let dimensions = stored_dimensions(config)?;
+        let backend = hnsw::HnswBackend::empty(config, dimensions)?;
+        Ok(Self {
+            backend: Arc::new(RwLock::new(Box::new(backend))),
+            searches: Some(Arc::new(hnsw::search_slots())),
+            meta: Arc::new(RwLock::new(Metadata::empty(config.metric, dimensions))),
+            db_path: None,
+            read_only: false,
+            repair: RepairSummary::default(),
+            dimensions,
+            file_vectors: Arc::default(),
+            content: ContentStorage::default(),
+            snippet_cache: 0,
+            snippets: None,
+            model: None,
+            dedup: true,
+            exact: false,
+            exact_max: EXACT_SEARCH_MAX,
+            removed: Arc::default(),
+            max_bytes: None,
+            disk_bytes: Arc::default(),
+        })
+    }
+
+    /// Whether this store was opened with [`VectorStore::open_read_only`].
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    /// Insert a batch of chunk rows, returning the key each was stored under.
+    #[tracing::instrument(level = "debug", skip_all, fields(rows = rows.len()))]
+    pub async fn insert(&self, rows: Vec<ChunkRow>) -> Result<Vec<u64>> {
+        let mut transaction = self.transaction();
+        transaction.insert(rows);
+        transaction.commit().await
+    }
+
+    /// Replace the chunks of `file_path` with `rows` in one step, so a
+    /// search sees either the old chunks or the new ones, never neither or
+    /// both. Returns the key each row was stored under.
+    #[tracing::instrument(level = "debug", skip(self, rows), fields(rows = rows.len()))]
+    pub async fn replace_file(&self, file_path: &str, rows: Vec<ChunkRow>) -> Result<Vec<u64>> {
+        let mut transaction = self.transaction();
+        transaction.replace_file(file_path, rows);
+        transaction.commit().await
+    }
+
+    /// Start buffering writes to apply together, under one taking of the
+    /// store's locks and with one save, when the transaction is committed.
+    pub fn transaction(&self) -> Transaction<'_> {
+        Transaction::new(self)
+    }
+
+    /// Store `rows` with the store's locks held by the caller.
+    fn insert_locked(
+        &self,
+        meta: &mut Metadata,
+        backend: &mut dyn VectorStoreBackend,
+        file_vectors: &mut HashMap<String, Vec<f32>>,
+        rows: Vec<ChunkRow>,
+    ) -> Result<Vec<u64>> {
+        if rows.is_empty() {
+            return Ok(Vec::new());
+        }
+        let mut points = Vec::with_capacity(rows.len());
+        // Chunks of this batch that will hold a vector, by content hash
+        let mut batch_primaries: HashMap<u64, u64> = HashMap::new();
+        for row in rows {
+            let key = match row.key {
+                Some(key) if !meta.chunks.contains_key(&key) => key,
+                _ => {
+                    let key = meta.next_key;
+                    meta.next_key += 1;
+                    key
+                }
+            };
+            if let Some(snippets) = &self.snippets {
+                snippets.forget(key);
+            }
+            if let Some(rebuild) = &mut meta.rebuild {
+                rebuild.keys.remove(&key);
+            }
+            meta.postings.add(key, &row.content);
+            let content_hash = (self.dedup && row.namespace != Namespace::Notes).then(|| {
+                content_hash(
+                    row.namespace,
+                    &row.language,
+                    &row.content,
+                    row.doc.as_deref(),
+                )
+            });
+            let duplicate_of = content_hash.and_then(|hash| {
+                batch_primaries.get(&hash).copied().or_else(|| {
+                    meta.copies.primary(hash).filter(|primary| {
+                        meta.chunks
+                            .get(primary)
+                            .is_some_and(|c| !self.is_outdated(c))
+                            && !meta
+                                .rebuild
+                                .as_ref()
+                                .is_some_and(|r| r.keys.contains(primary))
+                    })
+                })
+            });
+            if let (Some(hash), None) = (content_hash, duplicate_of) {
+                batch_primaries.insert(hash, key);
+            }
+            // Dependency source lives outside the root, so keeps its text
+            let span = row.span.filter(|_| {
+                self.content == ContentStorage::Offsets
+                    && self.snippets.is_some()
+                    && row.namespace != Namespace::Dependencies
+            });
+            let chunk = ChunkMeta {
+                chunk_id: row.chunk_id,
+                content: if span.is_some() {
+                    String::new()
+                } else {
+                    row.content
+                },
+                span,
+                encoding: row.encoding,
+                generated: row.generated,
+                symbol_name: row.symbol_name,
+                symbol_kind: row.symbol_kind,
+                doc: row.doc,
+                signature: row.signature,
+                excluded_platforms: excluded_platforms(&row.language, row.build.as_deref()),
+                owners: owners_of(&meta.code_owners, row.namespace, &row.file_path),
+                module: row.module,
+                build: row.build,
+                language: row.language,
+                start_line: row.start_line,
+                end_line: row.end_line,
+                last_modified: row.last_modified,
+                token_count: row.token_count,
+                comment_ratio: row.comment_ratio,
+                namespace: row.namespace,
+                file_path: row.file_path,
+                model: self.model.as_deref().map(str::to_string),
+                content_hash,
+                duplicate_of,
+            };
+            points.push((key, self.fit(row.vector), chunk));
+        }
+
+        let batch: Vec<(u64, &[f32], &ChunkMeta)> = points
+            .iter()
+            .filter(|(_, _, chunk)| chunk.duplicate_of.is_none())
+            .map(|(key, vector, chunk)| (*key, vector.as_slice(), chunk))
+            .collect();
+        backend.insert(&batch)?;
+
+        let mut keys = Vec::with_capacity(points.len());
+        for (key, _, chunk) in points {
+            file_vectors.remove(&chunk.file_path);
+            meta.copies.add(key, &chunk);
+            meta.chunks.insert(key, chunk);
+            keys.push(key);
+        }
+
+        Ok(keys)
+    }
+
+    /// Store a free-text note so searches surface it alongside code. Notes
+    /// survive reindexing and are only removed by [`VectorStore::delete_note`].
+    pub async fn add_note(&self, text: &str, vector: Vec<f32>) -> Result<u64> {
+        let created = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs() as i64;
+        let keys = self
+            .insert(vec![ChunkRow {
+                file_path: NOTES_PATH.to_string(),
+                content: text.to_string(),
+                symbol_kind: Some("note".to_string()),
+                language: NOTES_LANGUAGE.to_string(),
+                start_line: 1,
+                end_line: text.lines().count().max(1) as i64,
+                last_modified: created,
+                comment_ratio: 1.0,
+                namespace: Namespace::Notes,
+                vector,
+                ..Default::default()
+            }])
+            .await?;
+        keys.first()
+            .copied()
+            .ok_or_else(|| Error::StoreIndex("note was not stored".to_string()))
+    }
+
+    /// Delete the note stored under `key`. Returns `false` if there is no
+    /// such note; other chunks are never deleted.
+    pub async fn delete_note(&self, key: u64) -> Result<bool> {
+        if self.read_only {
+            return Err(Error::ReadOnly("deleting notes"));
+        }
+        let mut meta = self.meta.write().await;
+        let mut backend = self.backend.write().await;
+
+        if meta
+            .chunks
+            .get(&key)
+            .is_none_or(|c| c.namespace != Namespace::Notes)
+        {
+            return Ok(false);
+        }
+        backend.delete(&[key])?;
+        if let Some(chunk) = meta.chunks.remove(&key) {
+            meta.postings.remove(key, &chunk.content);
+            if let Some(model) = self.outdated_model(&chunk) {
+                meta.remove_outdated(model);
+            }
+        }
+
+        self.persist_written(backend, meta).await?;
+        Ok(true)
+    }
+
+    /// Semantic search by vector similarity among the chunks `filters`
+    /// allows.
+    ///
+    /// With the query's text, identifiers it quotes in backticks restrict
+    /// the search to chunks containing them (when any do), and results
+    /// whose file path matches the query's terms or whose language the
+    /// query hints at are favored.
+    pub async fn search(
+        &self,
+        query_vec: &[f32],
+        limit: usize,
+        filters: Filters<'_>,
+        query: Option<QueryText<'_>>,
+    ) -> Result<Vec<SearchResult>> {
+        self.search_spaces(query_vec, &[], limit, filters, query)
+            .await
+    }
+
+    /// [`VectorStore::search`], with the query also embedded by earlier
+    /// models: `earlier` pairs an [`VectorStore::earlier_models`] version
+    /// with the query as that model embeds it. While chunks embedded by
+    /// earlier models remain, each model's chunks are searched with its own
+    /// query vector and the results merged by rank, each model taking its
+    /// share of the chunks' share of the results. An earlier model missing
+    /// from `earlier` has its chunks searched with `query_vec` instead,
+    /// their results marked [`SearchResult::approximate`].
+    #[tracing::instrument(level = "debug", skip_all, fields(limit))]
+    pub async fn search_spaces(
+        &self,
+        query_vec: &[f32],
+        earlier: &[(String, Vec<f32>)],
+        limit: usize,
+        filters: Filters<'_>,
+        query: Option<QueryText<'_>>,
+    ) -> Result<Vec<SearchResult>> {
+        let namespaces = filters.namespaces();
+        let Filters {
+            language: language_filter,
+            kind: kind_filter,
+            modified,
+            platform,
+            owner,
+            module,
+            exact,
+            ..
+        } = filters;
+        // Given back when dropped, however the search ends; the semaphore
+        // is never closed
+        let _turn = match &self.searches {
+            Some(searches) => lock_wait("searches", searches.acquire()).await.ok(),
+            None => None,
+        };
+        let meta = lock_wait("meta", self.meta.read()).await;
+        let backend = lock_wait("backend", self.backend.read()).await;
+
+        if meta.chunks.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let terms = query
+            .as_ref()
+            .filter(|q| q.path_boost > 0.0)
+            .map(|q| query_terms(q.text))
+            .unwrap_or_default();
+        let languages = query
+            .as_ref()
+            .filter(|q| q.language_boost > 0.0 && language_filter.is_none())
+            .map(|q| infer_languages(q.text))
+            .unwrap_or_default();
+        let structures = query
+            .as_ref()
+            .filter(|q| q.structure_boost > 0.0 && kind_filter.is_none())
+            .map(|q| infer_structures(q.text))
+            .unwrap_or_default();
+        let focused = query
+            .as_ref()
+            .is_some_and(|q| q.focus != Focus::Any && q.focus_boost > 0.0);
+        let demoted = query.as_ref().is_some_and(|q| q.generated_penalty > 0.0)
+            && meta.chunks.values().any(|c| c.generated);
+        let boosted = !terms.is_empty()
+            || !languages.is_empty()
+            || !structures.is_empty()
+            || focused
+            || demoted;
+        let candidates = if boosted {
+            limit * BOOST_OVERSAMPLE
+        } else {
+            limit
+        };
+
+        let identifiers = query
+            .as_ref()
+            .map(|q| quoted_identifiers(q.text))
+            .unwrap_or_default();
+        // Postings of chunks stored as offsets outlive them, since their
+        // text is gone by the time they are removed
+        let quoted = Some(meta.postings.lookup(&identifiers))
+            .map(|mut keys| {
+                keys.retain(|key| meta.chunks.contains_key(key));
+                keys
+            })
+            .filter(|keys| !keys.is_empty());
+
+        let filter = SearchFilter {
+            keys: quoted.as_ref(),
+            language: language_filter,
+            kind: kind_filter,
+            namespaces: &namespaces,
+            modified,
+            platform,
+            owner,
+            module,
+            space: None,
+            chunks: &meta.chunks,
+            copies: &meta.copies,
+        };
+        let query_vec = self.fit(query_vec.to_vec());
+        let exact = exact.unwrap_or(self.exact) && meta.chunks.len() <= self.exact_max;
+
+        // Each model's vectors only compare with a query it embedded
+        let spaces: Vec<SpaceQuery> = match self.model.as_deref() {
+            Some(current) if !meta.outdated.is_empty() => {
+                let total = meta.chunks.len().max(1) as f32;
+                let mut spaces = vec![SpaceQuery {
+                    space: Some(Space::Current(current)),
+                    vector: query_vec.clone(),
+                    share: (1.0 - meta.outdated_total() as f32 / total).max(0.0),
+                    approximate: false,
+                }];
+                for (model, &count) in &meta.outdated {
+                    let own = earlier.iter().find(|(version, _)| version == model);
+                    spaces.push(SpaceQuery {
+                        space: Some(Space::Earlier(model)),
+                        vector: own.map_or_else(
+                            || query_vec.clone(),
+                            |(_, vector)| self.fit(vector.clone()),
+                        ),
+                        share: count as f32 / total,
+                        approximate: own.is_none(),
+                    });
+                }
+                spaces
+            }
+            _ => vec![SpaceQuery {
+                space: None,
+                vector: query_vec,
+                share: 1.0,
+                approximate: false,
+            }],
+        };
+
+        let mut ranked = Vec::with_capacity(spaces.len());
+        for space in &spaces {
+            let filter = SearchFilter {
+                space: space.space,
+                ..filter
+            };
+            let hits = Self::search_space(
+                &meta,
+                backend.as_ref(),
+                &filter,
+                space,
+                candidates,
+                exact,
+                query.as_ref().filter(|_| boosted),
+                &Boosts {
+                    terms: &terms,
+                    languages: &languages,
+                    structures: &structures,
+                },
+            )?;
+            ranked.push((hits, space.share));
+        }
+        let mut hits = merge_spaces(ranked);
+        hits.truncate(limit);
+
+        // Text is read for the hits returned, not every candidate, and text
+        // stored as offsets only once the locks are released
+        let mut spans = Vec::new();
+        for (i, hit) in hits.iter_mut().enumerate() {
+            let Some(chunk) = meta.chunks.get(&hit.id) else {
+                continue;
+            };
+            match self.text_span(hit.id, chunk) {
+                Some(span) => spans.push((i, span)),
+                None => hit.content = chunk.content.clone(),
+            }
+        }
+        drop(backend);
+        drop(meta);
+        self.read_texts(&mut hits, spans).await?;
+        Ok(hits)
+    }
+
+    /// The `candidates` chunks in one model's `space` closest to its query,
+    /// boosted as the query's text asks, in rank order.
+    #[allow(clippy::too_many_arguments)]
+    fn search_space(
+        meta: &Metadata,
+        backend: &dyn VectorStoreBackend,
+        filter: &SearchFilter<'_>,
+        space: &SpaceQuery<'_>,
+        candidates: usize,
+        exact: bool,
+        query: Option<&QueryText<'_>>,
+        boosts: &Boosts<'_>,
+    ) -> Result<Vec<SearchResult>> {
+        let Boosts {
+            terms,
+            languages,
+            structures,
+        } = *boosts;
+        let query_vec = &space.vector;
+        let mut results = if exact {
+            backend.search_exact(query_vec, candidates, filter)?
+        } else {
+            backend.search(query_vec, candidates, filter)?
+        };
+        if results.len() < candidates && !filter.is_empty() && !exact {
+            results = refill(backend, meta.metric, query_vec, candidates, filter, results)?;
+        }
+
+        let mut hits: Vec<SearchResult> = results
+            .into_iter()
+            .filter_map(|(key, dist)| {
+                // A filter may pass only some copies of a text; show the
+                // first it passes
+                let shown = std::iter::once(key)
+                    .chain(meta.copies.duplicates(key))
+                    .find(|&k| filter.allows_chunk(k))
+                    .unwrap_or(key);
+                let chunk = meta.chunks.get(&shown)?;
+                let mut distance = dist;
+                if let Some(q) = query {
+                    distance -= q.path_boost * path_match(terms, &chunk.file_path);
+                    if languages.contains(&chunk.language.as_str()) {
+                        distance -= q.language_boost;
+                    }
+                    distance -= q.focus_boost * q.focus.affinity(chunk.comment_ratio);
+                    let shape = Shape {
+                        file_path: &chunk.file_path,
+                        symbol_name: chunk.symbol_name.as_deref(),
+                        symbol_kind: chunk.symbol_kind.as_deref(),
+                        signature: chunk.signature.as_deref(),
+                        // Text stored as offsets is read only for the final
+                        // hits, so only the signature and name tell for it
+                        content: &chunk.content,
+                    };
+                    if structures.iter().any(|s| s.matches(&shape)) {
+                        distance -= q.structure_boost;
+                    }
+                    if chunk.generated {
+                        distance += q.generated_penalty;
+                    }
+                }
+                Some(SearchResult {
+                    id: shown,
+                    file_path: chunk.file_path.clone(),
+                    content: String::new(),
+                    symbol_name: chunk.symbol_name.clone(),
+                    symbol_kind: chunk.symbol_kind.clone(),
+                    doc: chunk.doc.clone(),
+                    signature: chunk.signature.clone(),
+                    build: chunk.build.clone(),
+                    owners: chunk.owners.clone(),
+                    module: chunk.module.clone(),
+                    language: chunk.language.clone(),
+                    start_line: chunk.start_line,
+                    end_line: chunk.end_line,
+                    distance,
+                    duplicates: duplicates_of(meta, key, shown, filter),
+                    approximate: space.approximate,
+                })
+            })
+            .collect();
+
+        hits.sort_by(SearchResult::rank_cmp);
+        Ok(hits)
+    }
+
+    /// Rank whole files by how close the mean of their chunk vectors is to
+    /// `query_vec`, for questions about where something lives rather than
+    /// which function does it. Notes and commits aren't files and are never
+    /// returned.
+    #[tracing::instrument(level = "debug", skip_all, fields(limit))]
+    pub async fn search_files(
+        &self,
+        query_vec: &[f32],
+        limit: usize,
+        language_filter: Option<&str>,
+    ) -> Result<Vec<FileHit>> {
+        let meta = lock_wait("meta", self.meta.read()).await;
+        let backend = lock_wait("backend", self.backend.read()).await;
+
+        let mut files: BTreeMap<&str, Vec<(u64, &ChunkMeta)>> = BTreeMap::new();
+        for (&key, chunk) in &meta.chunks {
+            if is_source(chunk) && language_filter.is_none_or(|lang| chunk.language == lang) {
+                files
+                    .entry(chunk.file_path.as_str())
+                    .or_default()
+                    .push((key, chunk));
+            }
+        }
+
+        let mut file_vectors = lock_wait("file_vectors", self.file_vectors.write()).await;
+        for (&path, chunks) in &files {
+            if file_vectors.contains_key(path) {
+                continue;
+            }
+            let keys: Vec<u64> = chunks
+                .iter()
+                .map(|&(key, c)| c.duplicate_of.unwrap_or(key))
+                .collect();
+            let vectors: Vec<Vec<f32>> = backend
+                .vectors(&keys)?
+                .into_iter()
+                .map(|(_, vector)| vector)
+                .collect();
+            if !vectors.is_empty() {
+                file_vectors.insert(path.to_string(), mean(&vectors));
+            }
+        }
+
+        let query_vec = self.fit(query_vec.to_vec());
+        let mut hits: Vec<FileHit> = files
+            .into_iter()
+            .filter_map(|(path, mut chunks)| {
+                let vector = file_vectors.get(path)?;
+                let similarity: f32 = vector.iter().zip(&query_vec).map(|(a, b)| a * b).sum();
+                chunks.sort_by_key(|(_, c)| c.start_line);
+                Some(FileHit {
+                    file_path: path.to_string(),
+                    language: chunks[0].1.language.clone(),
+                    chunks: chunks.len(),
+                    symbols: chunks
+                        .iter()
+                        .filter_map(|(_, c)| c.symbol_name.clone())
+                        .collect(),
+                    distance: 1.0 - similarity,
+                })
+            })
+            .collect();
+        hits.sort_by(|a, b| {
+            a.distance
+                .total_cmp(&b.distance)
+                .then_with(|| a.file_path.cmp(&b.file_path))
+        });
+        hits.truncate(limit);
+        Ok(hits)
+    }
+
+    /// Delete all chunks for a given file path.
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub async fn delete_file(&self, file_path: &str) -> Result<()> {
+        let mut transaction = self.transaction();
+        transaction.delete_file(file_path);
+        transaction.commit().await.map(|_| ())
+    }
+
+    /// The chunks of `file_path` with their vectors, to keep in case the
+    /// file moved; `None` if any has no current vector to keep.
+    fn removed_file_locked(
+        &self,
+        meta: &Metadata,
+        backend: &dyn VectorStoreBackend,
+        file_path: &str,
+    ) -> Result<Option<RemovedFile>> {
+        let chunks: Vec<(u64, &ChunkMeta)> = meta
+            .chunks
+            .iter()
+            .filter(|(_, c)| c.file_path == file_path)
+            .map(|(&k, c)| (k, c))
+            .collect();
+        let rebuilding = |key: &u64| meta.rebuild.as_ref().is_some_and(|r| r.keys.contains(key));
+        if chunks
+            .iter()
+            .any(|(key, c)| self.is_outdated(c) || rebuilding(key))
+        {
+            return Ok(None);
+        }
+        // Copies share the vector of the chunk they duplicate
+        let holders: Vec<u64> = chunks
+            .iter()
+            .map(|(key, c)| c.duplicate_of.unwrap_or(*key))
+            .collect();
+        let vectors: HashMap<u64, Vec<f32>> = backend.vectors(&holders)?.into_iter().collect();
+        let kept: Option<Vec<(u64, ChunkMeta, Vec<f32>)>> = chunks
+            .into_iter()
+            .zip(holders)
+            .map(|((key, c), holder)| Some((key, c.clone(), vectors.get(&holder)?.clone())))
+            .collect();
+        Ok(kept.map(|chunks| RemovedFile::new(file_path, chunks)))
+    }
+
+    /// If `rows`, the chunks of a file new to the index, are those of a file
+    /// [`VectorStore::delete_file`] removed in the last few minutes, give
+    /// each the key and vector it had there, so the file can be stored
+    /// without embedding it again. Returns the path it was removed from.
+    pub async fn take_moved(&self, rows: &mut [ChunkRow]) -> Option<String> {
+        let removed = self.removed.lock().await.take(rows)?;
+        for (row, (key, old, vector)) in rows.iter_mut().zip(removed.chunks) {
+            row.key = Some(key);
+            row.vector = vector;
+            row.token_count = old.token_count;
+        }
+        Some(removed.file_path)
+    }
+
+    /// Remove the chunks of `file_path` with the store's locks held by the
+    /// caller. Returns whether it had any.
+    fn remove_file_locked(
+        &self,
+        meta: &mut Metadata,
+        backend: &mut dyn VectorStoreBackend,
+        file_path: &str,
+    ) -> Result<bool> {
+        let keys_to_remove: Vec<u64> = meta
+            .chunks
+            .iter()
+            .filter(|(_, c)| c.file_path == file_path)
+            .map(|(&k, _)| k)
+            .collect();
+
+        if keys_to_remove.is_empty() {
+            return Ok(false);
+        }
+        self.remove_keys_locked(meta, backend, &keys_to_remove)?;
+        Ok(true)
+    }
+
+    /// Remove the chunks stored under `keys`, all of which exist, with the
+    /// store's locks held by the caller. A removed chunk whose vector other
+    /// chunks share hands it to one of them that is left. Returns the
+    /// removed chunks.
+    fn remove_keys_locked(
+        &self,
+        meta: &mut Metadata,
+        backend: &mut dyn VectorStoreBackend,
+        keys: &[u64],
+    ) -> Result<Vec<ChunkMeta>> {
+        let removing: HashSet<u64> = keys.iter().copied().collect();
+        let mut handovers = Vec::new();
+        for &key in keys {
+            let survivors: Vec<u64> = meta
+                .copies
+                .duplicates(key)
+                .filter(|d| !removing.contains(d))
+                .collect();
+            if let Some((&heir, rest)) = survivors.split_first() {
+                handovers.push((key, heir, rest.to_vec()));
+            }
+        }
+        let old_keys: Vec<u64> = handovers.iter().map(|&(old, _, _)| old).collect();
+        let vectors: HashMap<u64, Vec<f32>> = backend.vectors(&old_keys)?.into_iter().collect();
+
+        let with_vectors: Vec<u64> = keys
+            .iter()
+            .copied()
+            .filter(|key| {
+                meta.chunks
+                    .get(key)
+                    .is_some_and(|c| c.duplicate_of.is_none())
+            })
+            .collect();
+        backend.delete(&with_vectors)?;
+
+        let mut promoted = Vec::new();
+        for (old, heir, rest) in handovers {
+            // Without the vector, the copies are left dangling for the
+            // consistency check to drop when the store is next opened
+            let Some(vector) = vectors.get(&old) else {
+                continue;
+            };
+            if let Some(hash) = meta.chunks.get(&old).and_then(|c| c.content_hash) {
+                meta.copies.promote(old, heir, hash, &rest);
+            }
+            for key in rest {
+                if let Some(chunk) = meta.chunks.get_mut(&key) {
+                    chunk.duplicate_of = Some(heir);
+                }
+            }
+            if let Some(chunk) = meta.chunks.get_mut(&heir) {
+                chunk.duplicate_of = None;
+                promoted.push((heir, vector.as_slice(), chunk.clone()));
+            }
+            // The heir now has a vector of its own, from the removed
+            // chunk's model
+            if let Some(model) = meta
+                .chunks
+                .get(&heir)
+                .and_then(|c| self.outdated_model(c))
+                .map(str::to_string)
+            {
+                meta.add_outdated(&model);
+            }
+        }
+        let points: Vec<(u64, &[f32], &ChunkMeta)> = promoted
+            .iter()
+            .map(|(key, vector, chunk)| (*key, *vector, chunk))
+            .collect();
+        backend.insert(&points)?;
+
+        let mut removed = Vec::with_capacity(keys.len());
+        for &key in keys {
+            if let Some(snippets) = &self.snippets {
+                snippets.forget(key);
+            }
+            if let Some(chunk) = meta.chunks.remove(&key) {
+                meta.postings.remove(key, &chunk.content);
+                meta.copies.remove(key, &chunk);
+                if let Some(model) = self
+                    .outdated_model(&chunk)
+                    .filter(|_| chunk.duplicate_of.is_none())
+                {
+                    meta.remove_outdated(model);
+                }
+                removed.push(chunk);
+            }
+        }
+        Ok(removed)
+    }
+
+    /// The indexed dependencies, as `name@version`, with their chunk keys.
+    pub async fn dependencies(&self) -> BTreeMap<String, Vec<u64>> {
+        let meta = self.meta.read().await;
+        let mut deps: BTreeMap<String, Vec<u64>> = BTreeMap::new();
+        for (&key, chunk) in meta
+            .chunks
+            .iter()
+            .filter(|(_, c)| c.namespace == Namespace::Dependencies)
+        {
+            // Module paths contain slashes but never `@`, and versions
+            // neither, so the id ends at the first slash after the `@`
+            let id = chunk
+                .file_path
+                .strip_prefix(DEPS_PATH)
+                .map(|p| p.trim_start_matches('/'))
+                .and_then(|p| {
+                    let at = p.find('@')?;
+                    let end = p[at..].find('/')?;
+                    Some(&p[..at + end])
+                })
+                .unwrap_or_default();
+            deps.entry(id.to_string()).or_default().push(key);
+        }
+        deps
+    }
+
+    /// Delete the chunks stored under `keys`; keys with no chunk are
+    /// ignored.
+    pub async fn delete_chunks(&self, keys: &[u64]) -> Result<()> {
+        let mut transaction = self.transaction();
+        transaction.delete_chunks(keys);
+        transaction.commit().await.map(|_| ())
+    }
+
+    /// Find chunks whose symbol matches `query`, the best `limit` of them
+    /// by [`symbol_rank`].
+    pub async fn find_by_symbol(
+        &self,
+        query: SymbolQuery<'_>,
+        limit: usize,
+    ) -> Result<Vec<SearchResult>> {
+        let meta = self.meta.read().await;
+        let lower_pattern = query.name.to_lowercase();
+        let wildcard: Option<Vec<char>> = lower_pattern
+            .contains('*')
+            .then(|| lower_pattern.chars().collect());
+        let dir = query.path.map(|p| format!("{}/", p.trim_end_matches('/')));
+
+        // Keep the best `limit` matches in a max-heap, so the worst kept is
+        // the one dropped when a better match turns up
+        let mut best: BinaryHeap<(SymbolRank<'_>, u64)> = BinaryHeap::new();
+        let matches = meta
+            .chunks
+            .iter()
+            .filter(|(_, c)| {
+                c.symbol_name.as_ref().is_some_and(|name| {
+                    let name = name.to_lowercase();
+                    match &wildcard {
+                        Some(pattern) => wildcard_match(pattern, &name.chars().collect::<Vec<_>>()),
+                        None => name.contains(&lower_pattern),
+                    }
+                })
+            })
+            .filter(|(_, c)| {
+                query.kind.is_none_or(|kind| {
+                    c.symbol_kind
+                        .as_deref()
+                        .is_some_and(|k| k == kind || k.split('_').any(|word| word == kind))
+                })
+            })
+            .filter(|(_, c)| {
+                query.path.is_none_or(|p| c.file_path == p)
+                    || dir.as_ref().is_some_and(|d| c.file_path.starts_with(d))
+            })
+            .filter(|(_, c)| query.namespace.is_none_or(|n| c.namespace == n));
+        for (&key, c) in matches {
+            best.push((symbol_rank(query.name, c), key));
+            if best.len() > limit {
+                best.pop();
+            }
+        }
+
+        let results: Vec<SearchResult> = best
+            .into_sorted_vec()
+            .into_iter()
+            .filter_map(|(_, key)| Some((key, meta.chunks.get(&key)?)))
+            .map(|(key, c)| self.stored_result(&meta, key, c))
+            .collect();
+
+        Ok(results)
+    }
+
+    /// The innermost chunk of `file_path` whose line range contains `line`.
+    pub async fn chunk_at(&self, file_path: &str, line: i64) -> Result<Option<SearchResult>> {
+        let meta = self.meta.read().await;
+        Ok(meta
+            .chunks
+            .iter()
+            .filter(|(_, c)| {
+                c.file_path == file_path && (c.start_line..=c.end_line).contains(&line)
+            })
+            .min_by_key(|(_, c)| c.end_line - c.start_line)
+            .map(|(&key, c)| self.stored_result(&meta, key, c)))
+    }
+
+    /// The innermost chunk of `file_path` around lines `start_line`
+    /// through `end_line` that covers more than them, such as the class
+    /// around a method.
+    pub async fn enclosing_chunk(
+        &self,
+        file_path: &str,
+        start_line: i64,
+        end_line: i64,
+    ) -> Result<Option<SearchResult>> {
+        let meta = self.meta.read().await;
+        Ok(meta
+            .chunks
+            .iter()
+            .filter(|(_, c)| {
+                c.file_path == file_path
+                    && c.start_line <= start_line
+                    && c.end_line >= end_line
+                    && (c.start_line, c.end_line) != (start_line, end_line)
+            })
+            .min_by_key(|(_, c)| c.end_line - c.start_line)
+            .map(|(&key, c)| self.stored_result(&meta, key, c)))
+    }
+
+    /// The chunk defining the type `name`, for a method in `file_path` of
+    /// `module`: one in that file if there is one, else in another file of
+    /// the module, else the only one in the index. Functions, methods, and
+    /// impl blocks of the same name are passed over.
+    pub async fn type_definition(
+        &self,
+        name: &str,
+        file_path: &str,
+        module: Option<&str>,
+    ) -> Result<Option<SearchResult>> {
+        let meta = self.meta.read().await;
+        let defines = |c: &ChunkMeta| {
+            // A Go type declaration may group several, named `A, B`
+            c.symbol_name
+                .as_deref()
+                .is_some_and(|names| names.split(", ").any(|n| n == name))
+                && c.symbol_kind.as_deref().is_none_or(|kind| {
+                    !(kind == "impl_item" || kind.contains("function") || kind.contains("method"))
+                })
+        };
+        let candidates: Vec<(&u64, &ChunkMeta)> =
+            meta.chunks.iter().filter(|(_, c)| defines(c)).collect();
+        let nearest = candidates
+            .iter()
+            .filter(|(_, c)| {
+                c.file_path == file_path || module.is_some_and(|m| c.module.as_deref() == Some(m))
+            })
+            .min_by_key(|(_, c)| (c.file_path != file_path, &c.file_path, c.start_line));
+        let found = match (nearest, candidates.as_slice()) {
+            (Some(found), _) => Some(found),
+            (None, [only]) => Some(only),
+            (None, _) => None,
+        };
+        Ok(found.map(|&(&key, c)| self.stored_result(&meta, key, c)))
+    }
+
+    /// `c`, stored under `key`, as a result of a lookup rather than a
+    /// search.
+    fn stored_result(&self, meta: &Metadata, key: u64, c: &ChunkMeta) -> SearchResult {
+        SearchResult {
+            id: key,
+            file_path: c.file_path.clone(),
+            language: c.language.clone(),
+            content: self.text(key, c),
+            symbol_name: c.symbol_name.clone(),
+            symbol_kind: c.symbol_kind.clone(),
+            doc: c.doc.clone(),
+            signature: c.signature.clone(),
+            build: c.build.clone(),
+            owners: c.owners.clone(),
+            module: c.module.clone(),
+            start_line: c.start_line,
+            end_line: c.end_line,
+            distance: 0.0,
+            duplicates: aliases_of(meta, c).collect(),
+            approximate: false,
+        }
+    }
+
+    /// The chunks of `file_path` defining `name`, in source order. A
+    /// qualified name matches exactly; a bare one also matches methods
+    /// qualified with their type, so `new` finds `Point::new`.
+    pub async fn symbol_chunks(&self, file_path: &str, name: &str) -> Result<Vec<SearchResult>> {
+        let meta = self.meta.read().await;
+        let suffix = format!("::{name}");
+        let mut results: Vec<SearchResult> = meta
+            .chunks
+            .iter()
+            .filter(|(_, c)| {
+                c.file_path == file_path
+                    && c.symbol_name
+                        .as_deref()
+                        .is_some_and(|s| s == name || s.ends_with(&suffix))
+            })
+            .map(|(&key, c)| self.stored_result(&meta, key, c))
+            .collect();
+        results.sort_by_key(|r| r.start_line);
+        Ok(results)
+    }
+
+    /// Return the chunks currently stored for `file_path`.
+    pub async fn file_chunks(&self, file_path: &str) -> Result<Vec<StoredChunk>> {
+        let meta = self.meta.read().await;
+        Ok(meta
+            .chunks
+            .iter()
+            .filter(|(_, c)| c.file_path == file_path)
+            .map(|(&key, c)| StoredChunk {
+                key,
+                content: c.content.clone(),
+                symbol_name: c.symbol_name.clone(),
+            })
+            .collect())
+    }
+
+    /// Return distinct file paths in the index, optionally filtered by
+    /// language. Notes and commits aren't files and are never listed.
+    pub async fn list_files(&self, language_filter: Option<&str>) -> Result<Vec<String>> {
+        let meta = self.meta.read().await;
+
+        let mut paths = std::collections::BTreeSet::new();
+        for chunk in meta.chunks.values() {
+            if is_source(chunk) && language_filter.is_none_or(|lang| chunk.language == lang) {
+                paths.insert(chunk.file_path.clone());
+            }
+        }
+
+        Ok(paths.into_iter().collect())
+    }
+
+    /// The named symbols of the project's own files, sorted by kind, then
+    /// name, then location. `path` keeps those in that file or under that
+    /// directory; symbols without a kind are listed as `other`.
+    pub async fn list_symbols(
+        &self,
+        path: Option<&str>,
+        language_filter: Option<&str>,
+        kind_filter: Option<&str>,
+        namespace: Option<Namespace>,
+    ) -> Vec<SymbolEntry> {
+        let meta = self.meta.read().await;
+        let dir = path.map(|p| format!("{}/", p.trim_end_matches('/')));
+
+        let mut symbols: Vec<SymbolEntry> = meta
+            .chunks
+            .values()
+            .filter(|c| namespace.map_or(is_source(c), |n| c.namespace == n))
+            .filter(|c| {
+                path.is_none_or(|p| c.file_path == p)
+                    || dir.as_ref().is_some_and(|d| c.file_path.starts_with(d))
+            })
+            .filter(|c| language_filter.is_none_or(|lang| c.language == lang))
+            .filter_map(|c| {
+                let kind = c.symbol_kind.as_deref().unwrap_or("other");
+                if kind_filter.is_some_and(|k| k != kind) {
+                    return None;
+                }
+                Some(SymbolEntry {
+                    name: c.symbol_name.clone()?,
+                    kind: kind.to_string(),
+                    file_path: c.file_path.clone(),
+                    start_line: c.start_line,
+                    end_line: c.end_line,
+                })
+            })
+            .collect();
+        symbols.sort_by(|a, b| {
+            (&a.kind, &a.name, &a.file_path, a.start_line).cmp(&(
+                &b.kind,
+                &b.name,
+                &b.file_path,
+                b.start_line,
+            ))
+        });
+        symbols
+    }
+
+    /// The distinct symbol kinds of stored chunks, notes' and commits'
+    /// included, as `kind` filters take them.
+    pub async fn symbol_kinds(&self) -> BTreeSet<String> {
+        let meta = self.meta.read().await;
+        meta.chunks
+            .values()
+            .filter_map(|c| c.symbol_kind.clone())
+            .collect()
+    }
+
+    /// Each indexed file with the modification time it had when indexed.
+    pub async fn file_mtimes(&self) -> HashMap<String, i64> {
+        let meta = self.meta.read().await;
+        let mut files = HashMap::new();
+        for chunk in meta.chunks.values() {
+            if is_source(chunk) {
+                files.insert(chunk.file_path.clone(), chunk.last_modified);
+            }
+        }
+        files
+    }
+
+    /// Chunks embedded by a model other than the one set by
+    /// [`VectorStore::with_model`], in key order.
+    pub async fn outdated_chunks(&self) -> Vec<OutdatedChunk> {
+        let meta = self.meta.read().await;
+        let mut outdated: Vec<OutdatedChunk> = meta
+            .chunks
+            .iter()
+            .filter(|(_, c)| c.duplicate_of.is_none() && self.is_outdated(c))
+            .map(|(&key, c)| OutdatedChunk {
+                key,
+                file_path: c.file_path.clone(),
+                namespace: c.namespace,
+                note: (c.namespace == Namespace::Notes).then(|| self.text(key, c)),
+            })
+            .collect();
+        outdated.sort_by_key(|c| c.key);
+        outdated
+    }
+
+    /// Progress of a rebuild for a changed metric or dimension count, if
+    /// one is under way.
+    pub async fn rebuild_status(&self) -> Option<RebuildStatus> {
+        let meta = self.meta.read().await;
+        let rebuild = meta.rebuild.as_ref()?;
+        Some(RebuildStatus {
+            reason: rebuild.reason.clone(),
+            remaining: rebuild
+                .keys
+                .iter()
+                .filter(|key| meta.chunks.contains_key(key))
+                .count(),
+        })
+    }
+
+    /// The text of up to `limit` chunks still waiting for a vector in a
+    /// rebuild, to embed and hand to [`VectorStore::replace_vectors`].
+    /// Chunks deleted since the rebuild started are dropped from it.
+    pub async fn rebuild_batch(&self, limit: usize) -> Vec<ChunkText> {
+        let mut meta = self.meta.write().await;
+        let Metadata {
+            chunks, rebuild, ..
+        } = &mut *meta;
+        let Some(rebuild) = rebuild else {
+            return Vec::new();
+        };
+        rebuild.keys.retain(|key| chunks.contains_key(key));
+        let batch = rebuild
+            .keys
+            .iter()
+            .take(limit)
+            .filter_map(|&key| {
+                let chunk = chunks.get(&key)?;
+                Some(ChunkText {
+                    key,
+                    namespace: chunk.namespace,
+                    content: self.text(key, chunk),
+                    doc: chunk.doc.clone(),
+                })
+            })
+            .collect();
+        if rebuild.keys.is_empty() {
+            meta.rebuild = None;
+        }
+        batch
+    }
+
+    /// Replace the vectors of the chunks stored under the given keys with
+    /// ones from the current model, keeping everything else about them;
+    /// keys with no chunk are ignored.
+    pub async fn replace_vectors(&self, vectors: Vec<(u64, Vec<f32>)>) -> Result<()> {
+        if self.read_only {
+            return Err(Error::ReadOnly("replacing vectors"));
+        }
+        let mut meta = self.meta.write().await;
+        let mut backend = self.backend.write().await;
+
+        let model = self.model.as_deref().map(str::to_string);
+        let mut points = Vec::with_capacity(vectors.len());
+        let mut moved = Vec::new();
+        for (key, vector) in vectors {
+            let Some(chunk) = meta
+                .chunks
+                .get_mut(&key)
+                .filter(|c| c.duplicate_of.is_none())
+            else {
+                continue;
+            };
+            if let Some(earlier) = self.outdated_model(chunk) {
+                moved.push(earlier.to_string());
+            }
+            chunk.model = model.clone();
+            points.push((key, self.fit(vector), chunk.clone()));
+            let duplicates: Vec<u64> = meta.copies.duplicates(key).collect();
+            for duplicate in duplicates {
+                if let Some(chunk) = meta.chunks.get_mut(&duplicate) {
+                    chunk.model = model.clone();
+                }
+            }
+        }
+        for earlier in &moved {
+            meta.remove_outdated(earlier);
+        }
+        if points.is_empty() {
+            return Ok(());
+        }
+        if let Some(rebuild) = &mut meta.rebuild {
+            for (key, _, _) in &points {
+                rebuild.keys.remove(key);
+            }
+            if rebuild.keys.is_empty() {
+                tracing::info!("rebuilt the index ({})", rebuild.reason);
+                meta.rebuild = None;
+            }
+        }
+        let keys: Vec<u64> = points.iter().map(|(key, _, _)| *key).collect();
+        backend.delete(&keys)?;
+        let batch: Vec<(u64, &[f32], &ChunkMeta)> = points
+            .iter()
+            .map(|(key, vector, chunk)| (*key, vector.as_slice(), chunk))
+            .collect();
+        backend.insert(&batch)?;
+
+        self.persist_written(backend, meta).await
+    }
+
+    /// Chunks stored in each namespace that has any.
+    pub async fn namespace_counts(&self) -> BTreeMap<Namespace, usize> {
+        let meta = self.meta.read().await;
+        let mut counts = BTreeMap::new();
+        for chunk in meta.chunks.values() {
+            *counts.entry(chunk.namespace).or_default() += 1;
+        }
+        counts
+    }
+
+    /// Chunks that share the vector of another with the same text rather
+    /// than storing their own.
+    pub async fn duplicate_count(&self) -> usize {
+        self.meta.read().await.copies.duplicate_count()
+    }
+
+    /// Count total indexed chunks.
+    pub async fn chunk_count(&self) -> Result<u64> {
+        let meta = self.meta.read().await;
+        Ok(meta.chunks.len() as u64)
+    }
+
+    /// Summarize recorded token counts against the embedding window.
+    pub async fn token_stats(&self) -> Result<TokenStats> {
+        let meta = self.meta.read().await;
+        let mut stats = TokenStats::default();
+        for count in meta.chunks.values().filter_map(|c| c.token_count) {
+            stats.measured += 1;
+            if count > MAX_SEQ_LEN {
+                stats.truncated += 1;
+            }
+        }
+        Ok(stats)
+    }
+
+    /// Replace the record of files skipped during indexing.
+    pub async fn set_skipped(&self, skipped: BTreeMap<String, String>) -> Result<()> {
+        if self.read_only {
+            return Err(Error::ReadOnly("recording skipped files"));
+        }
+        let mut meta = self.meta.write().await;
+        let backend = self.backend.write().await;
+        meta.skipped = skipped;
+        self.persist_written(backend, meta).await
+    }
+
+    /// Take chunk owners from `owners` from now on, and give every stored
+    /// chunk of the project's own files the owners it assigns.
+    pub async fn set_code_owners(&self, owners: CodeOwners) -> Result<()> {
+        let mut meta = self.meta.write().await;
+        let mut changed = Vec::new();
+        for (&key, chunk) in &mut meta.chunks {
+            let new = owners_of(&owners, chunk.namespace, &chunk.file_path);
+            if chunk.owners != new {
+                chunk.owners = new;
+                changed.push(key);
+            }
+        }
+        meta.code_owners = owners;
+        if changed.is_empty() || self.read_only {
+            return Ok(());
+        }
+        tracing::debug!("owners changed for {} chunks", changed.len());
+
+        // Qdrant filters on its own copy of the metadata, so it is stored
+        // again; usearch keeps only the vectors
+        let mut backend = self.backend.write().await;
+        if backend.settings().backend == Backend::Qdrant {
+            let vectors = backend.vectors(&changed)?;
+            let points: Vec<(u64, &[f32], &ChunkMeta)> = vectors
+                .iter()
+                .filter_map(|(key, vector)| Some((*key, vector.as_slice(), meta.chunks.get(key)?)))
+                .collect();
+            backend.insert(&points)?;
+        }
+        self.persist_written(backend, meta).await
+    }
+
+    /// Files skipped by the last full index run, mapped to the reason.
+    pub async fn skipped_files(&self) -> BTreeMap<String, String> {
+        self.meta.read().await.skipped.clone()
+    }
+
+    /// Replace the record of paths reached through symlinks, each indexed
+    /// file mapped to its other paths. Chunks stored under an alias by
+    /// earlier runs are removed.
+    pub async fn set_aliases(&self, aliases: BTreeMap<String, Vec<String>>) -> Result<()> {
+        if self.read_only {
+            return Err(Error::ReadOnly("recording symlinked paths"));
+        }
+        let mut meta = self.meta.write().await;
+        let mut backend = self.backend.write().await;
+        for alias in aliases.values().flatten() {
+            if self.remove_file_locked(&mut meta, backend.as_mut(), alias)? {
+                self.file_vectors.write().await.remove(alias);
+            }
+        }
+        meta.aliases = aliases;
+        self.persist_written(backend, meta).await
+    }
+
+    /// The path the file at `alias` is indexed under, if the last full
+    /// index run reached it through a symlink.
+    pub async fn alias_target(&self, alias: &str) -> Option<String> {
+        let meta = self.meta.read().await;
+        meta.aliases
+            .iter()
+            .find(|(_, aliases)| aliases.iter().any(|a| a == alias))
+            .map(|(target, _)| target.clone())
+    }
+
+    /// How many paths reach an indexed file through a symlink.
+    pub async fn alias_count(&self) -> usize {
+        self.meta.read().await.aliases.values().map(Vec::len).sum()
+    }
+
+    /// Record the git commit the index now reflects.
+    pub async fn set_last_commit(&self, commit: &str) -> Result<()> {
+        if self.read_only {
+            return Err(Error::ReadOnly("recording the indexed commit"));
+        }
+        let mut meta = self.meta.write().await;
+        let backend = self.backend.write().await;
+        meta.last_commit = Some(commit.to_string());
+        self.persist_written(backend, meta).await
+    }
+
+    /// The git commit the index was last synced to, if any.
+    pub async fn last_commit(&self) -> Option<String> {
+        self.meta.read().await.last_commit.clone()
+    }
+
+    /// Persist a write to disk, with its locks downgraded so readers can
+    /// go ahead during the save. Other writers still wait for it.
+    async fn persist_written(
+        &self,
+        backend: RwLockWriteGuard<'_, Box<dyn VectorStoreBackend>>,
+        meta: RwLockWriteGuard<'_, Metadata>,
+    ) -> Result<()> {
+        let meta = meta.downgrade();
+        let backend = backend.downgrade();
+        self.persist_locked(backend.as_ref(), &meta).await
+    }
+
+    /// Persist index and metadata to disk. Caller must hold both locks.
+    async fn persist_locked(
+        &self,
+        backend: &dyn VectorStoreBackend,
+        meta: &Metadata,
+    ) -> Result<()> {
+        let Some(db_path) = &self.db_path else {
+            return Ok(());
+        };
+        let meta_path = db_path.join(META_FILE);
+
+        backend.save(db_path)?;
+
+        let json = serde_json::to_string(meta).map_err(Error::StoreSerde)?;
+        crate::atomicfile::write(&meta_path, json)
+            .await
+            .map_err(|e| Error::StoreIo {
+                context: format!("writing {}", meta_path.display()),
+                source: e,
+            })?;
+        self.disk_bytes
+            .store(stored_size(db_path), Ordering::Relaxed);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tempfile::TempDir;
+
+    fn make_vector(seed: f32) -> Vec<f32> {
+        // Create a normalized vector with a distinguishable direction.
+        let mut v = vec![0.0f32; EMBEDDING_DIM];
+        let idx = (seed.abs() as usize) % EMBEDDING_DIM;
+        v[idx] = 1.0;
+        for (i, val) in v.iter_mut().enumerate() {
+            *val += (i as f32 * seed * 0.001).sin() * 0.01;
+        }
+        let norm: f32 = v.iter().map(|x| x * x).sum::<f32>().sqrt();
+        v.iter_mut().for_each(|x| *x /= norm);
+        v
+    }
+
+    fn sample_row(
+        file_path: &str,
+        chunk_id: i64,
+        content: &str,
+        language: &str,
+        vector: Vec<f32>,
+    ) -> ChunkRow {
+        ChunkRow {
+            file_path: file_path.to_string(),
+            chunk_id,
+            content: content.to_string(),
+            symbol_name: Some(format!("symbol_{chunk_id}")),
+            symbol_kind: Some("func".to_string()),
+            language: language.to_string(),
+            start_line: chunk_id * 10 + 1,
+            end_line: chunk_id * 10 + 9,
+            last_modified: 1700000000,
+            namespace: if file_path.starts_with(DEPS_PATH) {
+                Namespace::Dependencies
+            } else {
+                Namespace::Code
+            },
+            vector,
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn chunks_from_an_earlier_model_are_searched_apart_until_replaced() {
+        let tmp = TempDir::new().unwrap();
+        let path = tmp.path().to_str().unwrap();
+        let store = VectorStore::new(path, &Default::default())
+            .await
+            .unwrap()
+            .with_model("old", &Default::default());
+        store
+            .insert(vec![
+                sample_row("a.rs", 0, "fn old_a() {}", "rust", make_vector(1.0)),
+                sample_row("b.rs", 0, "fn old_b() {}", "rust", make_vector(2.0)),
+            ])
+            .await
+            .unwrap();
+        drop(store);
+
+        let store = VectorStore::new(path, &Default::default())
+            .await
+            .unwrap()
+            .with_model("new", &Default::default());
+        store
+            .insert(vec![
+                sample_row("c.rs", 0, "fn new_c() {}", "rust", make_vector(3.0)),
+                sample_row("d.rs", 0, "fn new_d() {}", "rust", make_vector(4.0)),
+            ])
+            .await
+            .unwrap();
+        let outdated = store.outdated_chunks().await;
+        let files: Vec<&str> = outdated.iter().map(|c| c.file_path.as_str()).collect();
+        assert_eq!(files, ["a.rs", "b.rs"]);
+        assert_eq!(store.meta.read().await.outdated_total(), 2);
+        store.delete_file("b.rs").await.unwrap();
+        assert_eq!(store.meta.read().await.outdated_total(), 1);
+        let outdated = store.outdated_chunks().await;
+        // The earlier model's settings were saved with its chunks
+        let earlier = store.earlier_models().await;
+        assert_eq!(earlier.len(), 1);
+        assert_eq!(earlier[0].0, "old");
+        assert!(earlier[0].1.is_some());
+
+        // The query as the earlier model embeds it finds a.rs in its space,
+        // which gets its share of the results
+        let hits = store
+            .search_spaces(
+                &make_vector(4.0),
+                &[("old".to_string(), make_vector(1.0))],
+                3,
+                Filters::default(),
+                None,
+            )
+            .await
+            .unwrap();
+        let mut files: Vec<&str> = hits.iter().map(|h| h.file_path.as_str()).collect();
+        assert_eq!(files[0], "d.rs");
+        files.sort_unstable();
+        assert_eq!(files, ["a.rs", "c.rs", "d.rs"]);
+        assert!(hits.iter().all(|h| !h.approximate));
+
+        // Without it, a.rs is still found, marked as ranked roughly
+        let hits = store
+            .search(&make_vector(4.0), 3, Filters::default(), None)
+            .await
+            .unwrap();
+        let a = hits.iter().find(|h| h.file_path == "a.rs").unwrap();
+        assert!(a.approximate);
+        assert!(hits.iter().filter(|h| h.approximate).count() == 1);
+
+        let vectors = outdated
+            .iter()
+            .map(|c| (c.key, make_vector(c.key as f32 + 10.0)))
+            .collect();
+        store.replace_vectors(vectors).await.unwrap();
+        assert!(store.outdated_chunks().await.is_empty());
+        assert_eq!(store.meta.read().await.outdated_total(), 0);
+        assert!(store.earlier_models().await.is_empty());
+        assert_eq!(store.chunk_count().await.unwrap(), 3);
+        let hits = store
+            .search(&make_vector(10.0), 1, Filters::default(), None)
+            .await
+            .unwrap();
+        assert_eq!(hits[0].file_path, "a.rs");
+    }
+
+    #[test]
+    fn merge_spaces_interleaves_by_share() {
+        let hit = |file_path: &str| SearchResult {
+            id: 0,
+            file_path: file_path.to_string(),
+            content: String::new(),
+            symbol_name: None,
+            symbol_kind: None,
+            doc: None,
+            signature: None,
+            build: None,
+            owners: Vec::new(),
+            module: None,
+            language: "rust".to_string(),
+            start_line: 1,
+            end_line: 1,
+            distance: 0.0,
+            duplicates: Vec::new(),
+            approximate: false,
+        };
+        let files = |merged: Vec<SearchResult>| -> Vec<String> {
+            merged.into_iter().map(|h| h.file_path).collect()
+        };
+        let current = vec![hit("c1"), hit("c2"), hit("c3")];
+        let earlier = vec![hit("o1"), hit("o2")];
+        let merged = merge_spaces(vec![(current, 0.75), (earlier, 0.25)]);
+        assert_eq!(files(merged), ["c1", "c2", "c3", "o1", "o2"]);
+
+        let current = vec![hit("c1"), hit("c2")];
+        let earlier = vec![hit("o1"), hit("o2")];
+        let merged = merge_spaces(vec![(current, 0.5), (earlier, 0.5)]);
+        assert_eq!(files(merged), ["c1", "o1", "c2", "o2"]);
+
+        let merged = merge_spaces(vec![
+            (vec![hit("c1"), hit("c2")], 0.5),
+            (vec![hit("o1")], 0.25),
+            (vec![hit("p1")], 0.25),
+        ]);
+        assert_eq!(files(merged), ["c1", "c2", "o1", "p1"]);
+    }
+
+    #[tokio::test]
+    async fn empty_store_has_zero_count() {
+        let tmp = TempDir::new().unwrap();
+        let store = VectorStore::new(tmp.path().to_str().unwrap(), &Default::default())
+            .await
+            .unwrap();
+        assert_eq!(store.chunk_count().await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn empty_store_search_returns_empty() {
+        let tmp = TempDir::new().unwrap();
+        let store = VectorStore::new(tmp.path().to_str().unwrap(), &Default::default())
+            .await
+            .unwrap();
+        let query = make_vector(1.0);
+        let results = store
+            .search(&query, 10, Filters::default(), None)
+            .await
+            .unwrap();
+        assert!(results.is_empty());
+    }
+
+    #[tokio::test]
+    async fn insert_empty_batch_is_noop() {
+        let tmp = TempDir::new().unwrap();
+        let store = VectorStore::new(tmp.path().to_str().unwrap(), &Default::default())
+            .await
+            .unwrap();
+        store.insert(vec![]).await.unwrap();
+        assert_eq!(store.chunk_count().await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn insert_and_count() {
+        let tmp = TempDir::new().unwrap();
+        let store = VectorStore::new(tmp.path().to_str().unwrap(), &Default::default())
+            .await
+            .unwrap();
+
+        let rows = vec![
+            sample_row("main.go", 0, "func main() {}", "go", make_vector(1.0)),
+            sample_row("main.go", 1, "func helper() {}", "go", make_vector(2.0)),
+            sample_row("util.go", 0, "func util() {}", "go", make_vector(3.0)),
+        ];
+        store.insert(rows).await.unwrap();
+
+        assert_eq!(store.chunk_count().await.unwrap(), 3);
+    }
+
+    #[tokio::test]
+    async fn search_returns_closest_match() {
+        let tmp = TempDir::new().unwrap();
+        let store = VectorStore::new(tmp.path().to_str().unwrap(), &Default::default())
+            .await
+            .unwrap();
+
+        let v1 = make_vector(1.0);
+        let v2 = make_vector(50.0);
+        let v3 = make_vector(100.0);
+
+        let rows = vec![
+            sample_row("a.go", 0, "func alpha() {}", "go", v1.clone()),
+            sample_row("b.go", 0, "func beta() {}", "go", v2.clone()),
+            sample_row("c.go", 0, "func gamma() {}", "go", v3),
+        ];
+        store.insert(rows).await.unwrap();
+
+        let results = store
+            .search(&v1, 3, Filters::default(), None)
+            .await
+            .unwrap();
+        assert!(!results.is_empty());
+        assert_eq!(results[0].content, "func alpha() {}");
+        assert!(results[0].distance < results.last().unwrap().distance);
+    }
+
+    #[tokio::test]
+    async fn search_respects_limit() {
+        let tmp = TempDir::new().unwrap();
+        let store = VectorStore::new(tmp.path().to_str().unwrap(), &Default::default())
+            .await
+            .unwrap();
+
+        let rows: Vec<ChunkRow> = (0..10)
+            .map(|i| {
+                sample_row(
+                    "file.go",
+                    i,
+                    &format!("func f{i}() {{}}"),
+                    "go",
+                    make_vector(i as f32),
+                )
+            })
+            .collect();
+        store.insert(rows).await.unwrap();
+
+        let results = store
+            .search(&make_vector(0.0), 3, Filters::default(), None)
+            .await
+            .unwrap();
+        assert_eq!(results.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn delete_file_removes_only_that_file() {
+        let tmp = TempDir::new().unwrap();
+        let store = VectorStore::new(tmp.path().to_str().unwrap(), &Default::default())
+            .await
+            .unwrap();
+
+        let rows = vec![
+            sample_row("keep.go", 0, "func keep() {}", "go", make_vector(1.0)),
+            sample_row("keep.go", 1, "func keep2() {}", "go", make_vector(2.0)),
+            sample_row("remove.go", 0, "func remove() {}", "go", make_vector(3.0)),
+        ];
+        store.insert(rows).await.unwrap();
+        assert_eq!(store.chunk_count().await.unwrap(), 3);
+
+        store.delete_file("remove.go").await.unwrap();
+
+        assert_eq!(store.chunk_count().await.unwrap(), 2);
+
+        let results = store
+            .search(&make_vector(3.0), 10, Filters::default(), None)
+            .await
+            .unwrap();
+        for r in &results {
+            assert_eq!(
+                r.file_path, "keep.go",
+                "deleted file should not appear in results"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn identical_chunks_share_one_vector() {
+        let tmp = TempDir::new().unwrap();
+        let path = tmp.path().to_str().unwrap();
+        let store = VectorStore::new(path, &Default::default()).await.unwrap();
+        let body = "func copied() {}";
+        store
+            .insert(vec![
+                sample_row("a.go", 0, body, "go", make_vector(1.0)),
+                sample_row("b.go", 0, body, "go", make_vector(1.0)),
+                sample_row("other.go", 0, "func other() {}", "go", make_vector(5.0)),
+            ])
+            .await
+            .unwrap();
+        store
+            .insert(vec![sample_row("c.go", 0, body, "go", make_vector(1.0))])
+            .await
+            .unwrap();
+        assert_eq!(store.chunk_count().await.unwrap(), 4);
+        assert_eq!(store.duplicate_count().await, 2);
+
+        let results = store
+            .search(&make_vector(1.0), 10, Filters::default(), None)
+            .await
+            .unwrap();
+        assert_eq!(results.len(), 2, "copies should come back as one hit");
+        assert_eq!(results[0].file_path, "a.go");
+        let copies: Vec<&str> = results[0]
+            .duplicates
+            .iter()
+            .map(|d| d.file_path.as_str())
+            .collect();
+        assert_eq!(copies, ["b.go", "c.go"]);
+
+        // The vector passes to a copy that is left
+        store.delete_file("a.go").await.unwrap();
+        drop(store);
+        let store = VectorStore::new(path, &Default::default()).await.unwrap();
+        assert_eq!(store.duplicate_count().await, 1);
+        let results = store
+            .search(&make_vector(1.0), 10, Filters::default(), None)
+            .await
+            .unwrap();
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].file_path, "b.go");
+        assert_eq!(results[0].duplicates.len(), 1);
+        assert_eq!(results[0].duplicates[0].file_path, "c.go");
+    }
+
+    #[tokio::test]
+    async fn replace_file_swaps_a_files_chunks() {
+        let tmp = TempDir::new().unwrap();
+        let store = VectorStore::new(tmp.path().to_str().unwrap(), &Default::default())
+            .await
+            .unwrap();
+        store
+            .insert(vec![
+                sample_row("a.go", 0, "func old() {}", "go", make_vector(1.0)),
+                sample_row("a.go", 1, "func old2() {}", "go", make_vector(2.0)),
+                sample_row("b.go", 0, "func other() {}", "go", make_vector(3.0)),
+            ])
+            .await
+            .unwrap();
+
+        let keys = store
+            .replace_file(
+                "a.go",
+                vec![sample_row(
+                    "a.go",
+                    0,
+                    "func new() {}",
+                    "go",
+                    make_vector(4.0),
+                )],
+            )
+            .await
+            .unwrap();
+        assert_eq!(keys.len(), 1);
+        assert_eq!(store.chunk_count().await.unwrap(), 2);
+        let contents: Vec<String> = store
+            .file_chunks("a.go")
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|c| c.content)
+            .collect();
+        assert_eq!(contents, ["func new() {}"]);
+
+        // Replacing with nothing removes the file, and replacing twice
+        // doesn't pile up duplicates
+        store.replace_file("a.go", Vec::new()).await.unwrap();
+        assert!(store.file_chunks("a.go").await.unwrap().is_empty());
+        for _ in 0..2 {
+            store
+                .replace_file(
+                    "b.go",
+                    vec![sample_row(
+                        "b.go",
+                        0,
+                        "func other() {}",
+                        "go",
+                        make_vector(3.0),
+                    )],
+                )
+                .await
+                .unwrap();
+        }
+        assert_eq!(store.chunk_count().await.unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn transactions_apply_their_writes_together() {
+        let tmp = TempDir::new().unwrap();
+        let path = tmp.path().to_str().unwrap();
+        let store = VectorStore::new(path, &Default::default()).await.unwrap();
+        store
+            .insert(vec![
+                sample_row("a.go", 0, "func old() {}", "go", make_vector(1.0)),
+                sample_row("b.go", 0, "func gone() {}", "go", make_vector(2.0)),
+            ])
+            .await
+            .unwrap();
+
+        // Dropped uncommitted: nothing happens
+        let mut transaction = store.transaction();
+        transaction.delete_file("a.go");
+        drop(transaction);
+        assert_eq!(store.chunk_count().await.unwrap(), 2);
+
+        let mut transaction = store.transaction();
+        transaction.replace_file(
+            "a.go",
+            vec![sample_row(
+                "a.go",
+                0,
+                "func new() {}",
+                "go",
+                make_vector(3.0),
+            )],
+        );
+        transaction.delete_file("b.go");
+        transaction.insert(vec![sample_row(
+            "c.go",
+            0,
+            "func added() {}",
+            "go",
+            make_vector(4.0),
+        )]);
+        let keys = transaction.commit().await.unwrap();
+        assert_eq!(keys.len(), 2);
+        // One chunk each for a.go and c.go
+        assert_eq!(store.chunk_count().await.unwrap(), 2);
+        assert!(store.file_chunks("b.go").await.unwrap().is_empty());
+
+        // Saved once, with every write
+        let reopened = VectorStore::new(path, &Default::default()).await.unwrap();
+        assert_eq!(reopened.list_files(None).await.unwrap(), ["a.go", "c.go"]);
+        let a = reopened.file_chunks("a.go").await.unwrap();
+        assert_eq!(a[0].content, "func new() {}");
+        assert!(store.transaction().commit().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn failed_transactions_leave_the_store_as_it_was() {
+        let tmp = TempDir::new().unwrap();
+        let path = tmp.path().to_str().unwrap();
+        let store = VectorStore::new(path, &Default::default()).await.unwrap();
+        store
+            .insert(vec![
+                sample_row("a.go", 0, "func old() {}", "go", make_vector(1.0)),
+                sample_row("b.go", 0, "func kept() {}", "go", make_vector(2.0)),
+            ])
+            .await
+            .unwrap();
+
+        // The replacement's second vector is too short for the index, so
+        // its insert fails after a.go's chunks and b.go were removed
+        let mut transaction = store.transaction();
+        transaction.delete_file("b.go");
+        transaction.replace_file(
+            "a.go",
+            vec![
+                sample_row("a.go", 0, "func new() {}", "go", make_vector(3.0)),
+                sample_row("a.go", 1, "func short() {}", "go", vec![1.0; 3]),
+            ],
+        );
+        assert!(transaction.commit().await.is_err());
+
+        for store in [
+            store.clone(),
+            VectorStore::new(path, &Default::default()).await.unwrap(),
+        ] {
+            assert_eq!(store.list_files(None).await.unwrap(), ["a.go", "b.go"]);
+            let a = store.file_chunks("a.go").await.unwrap();
+            assert_eq!(a.len(), 1);
+            assert_eq!(a[0].content, "func old() {}");
+            // Their vectors are back too
+            for (seed, file) in [(1.0, "a.go"), (2.0, "b.go")] {
+                let hits = store
+                    .search(&make_vector(seed), 1, Filters::default(), None)
+                    .await
+                    .unwrap();
+                assert_eq!(hits[0].file_path, file);
+            }
+        }
+        let found = store
+            .find_by_symbol(SymbolQuery::named("symbol_1"), 5)
+            .await
+            .unwrap();
+        assert!(found.is_empty());
+    }
+
+    #[tokio::test]
+    async fn full_index_refuses_new_chunks_but_not_deletions() {
+        let tmp = TempDir::new().unwrap();
+        // The audit log isn't the index, so doesn't count against the limit
+        std::fs::write(tmp.path().join("audit.jsonl"), "{}\n".repeat(100)).unwrap();
+        let mut store = VectorStore::new(tmp.path().to_str().unwrap(), &Default::default())
+            .await
+            .unwrap();
+        assert_eq!(store.disk_usage(), (0, None));
+        store
+            .insert(vec![sample_row(
+                "a.go",
+                0,
+                "func a() {}",
+                "go",
+                make_vector(1.0),
+            )])
+            .await
+            .unwrap();
+        store.max_bytes = Some(1);
+        let (bytes, _) = store.disk_usage();
+        assert!(bytes > 1);
+
+        let err = store
+            .insert(vec![sample_row(
+                "b.go",
+                0,
+                "func b() {}",
+                "go",
+                make_vector(2.0),
+            )])
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::IndexFull { .. }), "{err}");
+        assert!(err.to_string().contains("claudevil gc"), "{err}");
+        store.delete_file("a.go").await.unwrap();
+        assert_eq!(store.chunk_count().await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn batches_that_would_outgrow_the_limit_are_refused() {
+        let tmp = TempDir::new().unwrap();
+        let config = IndexConfig {
+            max_bytes: 10_000,
+            ..Default::default()
+        };
+        let store = VectorStore::new(tmp.path().to_str().unwrap(), &config)
+            .await
+            .unwrap();
+        let row = |i: usize| {
+            let path = format!("{i}.go");
+            let content = format!("func f{i}() {{}}");
+            sample_row(&path, 0, &content, "go", make_vector(i as f32))
+        };
+        store.insert(vec![row(1)]).await.unwrap();
+        let (before, _) = store.disk_usage();
+        assert!(before < 10_000);
+
+        // Under the limit before the batch, but far over it after
+        let err = store.insert((2..20).map(row).collect()).await.unwrap_err();
+        match err {
+            Error::IndexFull { bytes, max_bytes } => {
+                assert!(bytes > max_bytes, "{bytes} <= {max_bytes}")
+            }
+            err => panic!("{err}"),
+        }
+        assert_eq!(store.chunk_count().await.unwrap(), 1);
+        assert_eq!(store.disk_usage().0, before);
+        let hits = store
+            .search(&make_vector(2.0), 10, Filters::default(), None)
+            .await
+            .unwrap();
+        assert_eq!(hits.len(), 1);
+
+        // A batch that fits still goes in
+        store.insert(vec![row(2)]).await.unwrap();
+        assert_eq!(store.chunk_count().await.unwrap(), 2);
+    }
+
+    #[tokio::test]
+    async fn tied_results_are_listed_by_path_then_line() {
+        let tmp = TempDir::new().unwrap();
+        let store = VectorStore::new(tmp.path().to_str().unwrap(), &Default::default())
+            .await
+            .unwrap();
+        let tied = |path: &str, chunk_id: i64, content: &str| {
+            let mut row = sample_row(path, chunk_id, content, "go", make_vector(1.0));
+            row.start_line = 1 + 10 * chunk_id;
+            row
+        };
+        store
+            .insert(vec![
+                tied("b.go", 0, "func b() {}"),
+                tied("a.go", 1, "func a2() {}"),
+                tied("c.go", 0, "func c() {}"),
+                tied("a.go", 0, "func a1() {}"),
+            ])
+            .await
+            .unwrap();
+
+        for _ in 0..3 {
+            let hits = store
+                .search(&make_vector(1.0), 10, Filters::default(), None)
+                .await
+                .unwrap();
+            let order: Vec<(&str, i64)> = hits
+                .iter()
+                .map(|h| (h.file_path.as_str(), h.start_line))
+                .collect();
+            assert_eq!(order, [("a.go", 1), ("a.go", 11), ("b.go", 1), ("c.go", 1)]);
+        }
+    }
+
+    #[tokio::test]
+    async fn search_with_language_filter() {
+        let tmp = TempDir::new().unwrap();
+        let store = VectorStore::new(tmp.path().to_str().unwrap(), &Default::default())
+            .await
+            .unwrap();
+
+        let rows = vec![
+            sample_row("main.go", 0, "func goFunc() {}", "go", make_vector(1.0)),
+            sample_row("main.rs", 0, "fn rust_func() {}", "rust", make_vector(2.0)),
+            sample_row(
+                "app.py",
+                0,
+                "def python_func():",
+                "python",
+                make_vector(3.0),
+            ),
+        ];
+        store.insert(rows).await.unwrap();
+
+        let results = store
+            .search(
+                &make_vector(1.0),
+                10,
+                Filters {
+                    language: Some("go"),
+                    ..Default::default()
+                },
+                None,
+            )
+            .await
+            .unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].content, "func goFunc() {}");
+
+        let results = store
+            .search(
+                &make_vector(2.0),
+                10,
+                Filters {
+                    language: Some("rust"),
+                    ..Default::default()
+                },
+                None,
+            )
+            .await
+            .unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].content, "fn rust_func() {}");
+    }
+
+    #[tokio::test]
+    async fn short_filtered_search_is_refilled() {
+        let tmp = TempDir::new().unwrap();
+        let store = VectorStore::new(tmp.path().to_str().unwrap(), &Default::default())
+            .await
+            .unwrap();
+        let mut rows: Vec<ChunkRow> = (0..20)
+            .map(|i| sample_row("main.go", i, "func f() {}", "go", make_vector(i as f32)))
+            .collect();
+        rows.push(sample_row("a.rs", 0, "fn a() {}", "rust", make_vector(3.0)));
+        rows.push(sample_row("b.rs", 0, "fn b() {}", "rust", make_vector(9.0)));
+        store.insert(rows).await.unwrap();
+
+        let meta = store.meta.read().await;
+        let backend = store.backend.read().await;
+        let namespaces = Namespace::ALL;
+        let filter = SearchFilter {
+            keys: None,
+            language: Some("rust"),
+            kind: None,
+            namespaces: &namespaces,
+            modified: Modified::default(),
+            platform: None,
+            owner: None,
+            module: None,
+            space: None,
+            chunks: &meta.chunks,
+            copies: &meta.copies,
+        };
+        // As if the index gave up before reaching either Rust chunk
+        let query = make_vector(9.0);
+        let hits = refill(
+            backend.as_ref(),
+            meta.metric,
+            &query,
+            5,
+            &filter,
+            Vec::new(),
+        )
+        .unwrap();
+        let files: Vec<&str> = hits
+            .iter()
+            .map(|(key, _)| meta.chunks[key].file_path.as_str())
+            .collect();
+        assert_eq!(files, ["b.rs", "a.rs"]);
+        assert!(hits[0].1 <= hits[1].1);
+
+        // Nothing more to find
+        let found = vec![(hits[0].0, hits[0].1), (hits[1].0, hits[1].1)];
+        assert_eq!(
+            refill(
+                backend.as_ref(),
+                meta.metric,
+                &query,
+                5,
+                &filter,
+                found.clone()
+            )
+            .unwrap(),
+            found
+        );
+    }
+
+    #[test]
+    fn distances_match_the_metric() {
+        let (a, b) = ([1.0, 0.0], [0.6, 0.8]);
+        assert!((distance(Metric::Cos, &a, &b) - 0.4).abs() < 1e-6);
+        assert!((distance(Metric::Cos, &a, &[3.0, 4.0]) - 0.4).abs() < 1e-6);
+        assert!((distance(Metric::Ip, &a, &b) - 0.4).abs() < 1e-6);
+        assert!((distance(Metric::L2sq, &a, &b) - 0.8).abs() < 1e-6);
+    }
+
+    #[tokio::test]
+    async fn search_result_contains_metadata() {
+        let tmp = TempDir::new().unwrap();
+        let store = VectorStore::new(tmp.path().to_str().unwrap(), &Default::default())
+            .await
+            .unwrap();
+
+        let rows = vec![ChunkRow {
+            file_path: "handler.go".to_string(),
+            content: "func HandleRequest() {}".to_string(),
+            symbol_name: Some("HandleRequest".to_string()),
+            symbol_kind: Some("func".to_string()),
+            language: "go".to_string(),
+            start_line: 10,
+            end_line: 25,
+            last_modified: 1700000000,
+            vector: make_vector(1.0),
+            ..Default::default()
+        }];
+        store.insert(rows).await.unwrap();
+
+        let results = store
+            .search(&make_vector(1.0), 1, Filters::default(), None)
+            .await
+            .unwrap();
+        assert_eq!(results.len(), 1);
+        let r = &results[0];
+        assert_eq!(r.file_path, "handler.go");
+        assert_eq!(r.content, "func HandleRequest() {}");
+        assert_eq!(r.symbol_name.as_deref(), Some("HandleRequest"));
+        assert_eq!(r.symbol_kind.as_deref(), Some("func"));
+        assert_eq!(r.start_line, 10);
+        assert_eq!(r.end_line, 25);
+    }
+
+    #[tokio::test]
+    async fn insert_multiple_batches() {
+        let tmp = TempDir::new().unwrap();
+        let store = VectorStore::new(tmp.path().to_str().unwrap(), &Default::default())
+            .await
+            .unwrap();
+
+        let rows1 = vec![sample_row("a.go", 0, "func a() {}", "go", make_vector(1.0))];
+        store.insert(rows1).await.unwrap();
+
+        let rows2 = vec![
+            sample_row("b.go", 0, "func b() {}", "go", make_vector(2.0)),
+            sample_row("c.go", 0, "func c() {}", "go", make_vector(3.0)),
+        ];
+        store.insert(rows2).await.unwrap();
+
+        assert_eq!(store.chunk_count().await.unwrap(), 3);
+    }
+
+    #[tokio::test]
+    async fn delete_nonexistent_file_is_noop() {
+        let tmp = TempDir::new().unwrap();
+        let store = VectorStore::new(tmp.path().to_str().unwrap(), &Default::default())
+            .await
+            .unwrap();
+
+        let rows = vec![sample_row(
+            "exists.go",
+            0,
+            "func exists() {}",
+            "go",
+            make_vector(1.0),
+        )];
+        store.insert(rows).await.unwrap();
+
+        store.delete_file("does_not_exist.go").await.unwrap();
+        assert_eq!(store.chunk_count().await.unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn null_optional_fields() {
+        let tmp = TempDir::new().unwrap();
+        let store = VectorStore::new(tmp.path().to_str().unwrap(), &Default::default())
+            .await
+            .unwrap();
+
+        let rows = vec![ChunkRow {
+            file_path: "test.go".to_string(),
+            content: "package main".to_string(),
+            language: "go".to_string(),
+            start_line: 1,
+            end_line: 1,
+            last_modified: 1700000000,
+            vector: make_vector(1.0),
+            ..Default::default()
+        }];
+        store.insert(rows).await.unwrap();
+
+        let results = store
+            .search(&make_vector(1.0), 1, Filters::default(), None)
+            .await
+            .unwrap();
+        assert_eq!(results.len(), 1);
+        assert!(results[0].symbol_name.is_none());
+        assert!(results[0].symbol_kind.is_none());
+    }
+
+    // ---------------------------------------------------------------
+    // find_by_symbol tests
+    // ---------------------------------------------------------------
+
+    #[tokio::test]
+    async fn find_by_symbol_matches_substring() {
+        let tmp = TempDir::new().unwrap();
+        let store = VectorStore::new(tmp.path().to_str().unwrap(), &Default::default())
+            .await
+            .unwrap();
+
+        let rows = vec![
+            ChunkRow {
+                file_path: "server.go".to_string(),
+                content: "func NewServer() {}".to_string(),
+                symbol_name: Some("NewServer".to_string()),
+                symbol_kind: Some("func".to_string()),
+                language: "go".to_string(),
+                start_line: 1,
+                end_line: 1,
+                last_modified: 1700000000,
+                vector: make_vector(1.0),
+                ..Default::default()
+            },
+            ChunkRow {
+                file_path: "server.go".to_string(),
+                chunk_id: 1,
+                content: "func (s *Server) Start() {}".to_string(),
+                symbol_name: Some("Server.Start".to_string()),
+                symbol_kind: Some("method".to_string()),
+                language: "go".to_string(),
+                start_line: 3,
+                end_line: 3,
+                last_modified: 1700000000,
+                vector: make_vector(2.0),
+                ..Default::default()
+            },
+            ChunkRow {
+                file_path: "client.go".to_string(),
+                content: "func NewClient() {}".to_string(),
+                symbol_name: Some("NewClient".to_string()),
+                symbol_kind: Some("func".to_string()),
+                language: "go".to_string(),
+                start_line: 1,
+                end_line: 1,
+                last_modified: 1700000000,
+                vector: make_vector(3.0),
+                ..Default::default()
+            },
+        ];
+        store.insert(rows).await.unwrap();
+
+        let results = store
+            .find_by_symbol(SymbolQuery::named("Server"), 10)
+            .await
+            .unwrap();
+        assert_eq!(results.len(), 2);
+        let names: Vec<_> = results
+            .iter()
+            .filter_map(|r| r.symbol_name.as_deref())
+            .collect();
+        assert!(names.contains(&"NewServer"));
+        assert!(names.contains(&"Server.Start"));
+    }
+
+    #[tokio::test]
+    async fn find_by_symbol_is_case_insensitive() {
+        let tmp = TempDir::new().unwrap();
+        let store = VectorStore::new(tmp.path().to_str().unwrap(), &Default::default())
+            .await
+            .unwrap();
+
+        let rows = vec![ChunkRow {
+            file_path: "handler.go".to_string(),
+            content: "func HandleRequest() {}".to_string(),
+            symbol_name: Some("HandleRequest".to_string()),
+            symbol_kind: Some("func".to_string()),
+            language: "go".to_string(),
+            start_line: 1,
+            end_line: 1,
+            last_modified: 1700000000,
+            vector: make_vector(1.0),
+            ..Default::default()
+        }];
+        store.insert(rows).await.unwrap();
+
+        let results = store
+            .find_by_symbol(SymbolQuery::named("handlerequest"), 10)
+            .await
+            .unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].symbol_name.as_deref(), Some("HandleRequest"));
+    }
+
+    #[tokio::test]
+    async fn find_by_symbol_with_kind_filter() {
+        let tmp = TempDir::new().unwrap();
+        let store = VectorStore::new(tmp.path().to_str().unwrap(), &Default::default())
+            .await
+            .unwrap();
+
+        let rows = vec![
+            ChunkRow {
+                file_path: "types.go".to_string(),
+                content: "type Server struct {}".to_string(),
+                symbol_name: Some("Server".to_string()),
+                symbol_kind: Some("type".to_string()),
+                language: "go".to_string(),
+                start_line: 1,
+                end_line: 1,
+                last_modified: 1700000000,
+                vector: make_vector(1.0),
+                ..Default::default()
+            },
+            ChunkRow {
+                file_path: "funcs.go".to_string(),
+                content: "func NewServer() {}".to_string(),
+                symbol_name: Some("NewServer".to_string()),
+                symbol_kind: Some("func".to_string()),
+                language: "go".to_string(),
+                start_line: 1,
+                end_line: 1,
+                last_modified: 1700000000,
+                vector: make_vector(2.0),
+                ..Default::default()
+            },
+        ];
+        store.insert(rows).await.unwrap();
+
+        let results = store
+            .find_by_symbol(
+                SymbolQuery {
+                    kind: Some("type"),
+                    ..SymbolQuery::named("Server")
+                },
+                10,
+            )
+            .await
+            .unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].symbol_kind.as_deref(), Some("type"));
+    }
+
+    #[tokio::test]
+    async fn find_by_symbol_empty_on_no_match() {
+        let tmp = TempDir::new().unwrap();
+        let store = VectorStore::new(tmp.path().to_str().unwrap(), &Default::default())
+            .await
+            .unwrap();
+
+        let rows = vec![sample_row(
+            "a.go",
+            0,
+            "func alpha() {}",
+            "go",
+            make_vector(1.0),
+        )];
+        store.insert(rows).await.unwrap();
+
+        let results = store
+            .find_by_symbol(SymbolQuery::named("nonexistent"), 10)
+            .await
+            .unwrap();
+        assert!(results.is_empty());
+    }
+
+    #[tokio::test]
+    async fn find_by_symbol_skips_null_names() {
+        let tmp = TempDir::new().unwrap();
+        let store = VectorStore::new(tmp.path().to_str().unwrap(), &Default::default())
+            .await
+            .unwrap();
+
+        let rows = vec![ChunkRow {
+            file_path: "test.go".to_string(),
+            content: "package main".to_string(),
+            language: "go".to_string(),
+            start_line: 1,
+            end_line: 1,
+            last_modified: 1700000000,
+            vector: make_vector(1.0),
+            ..Default::default()
+        }];
+        store.insert(rows).await.unwrap();
+
+        let results = store
+            .find_by_symbol(SymbolQuery::named("main"), 10)
+            .await
+            .unwrap();
+        assert!(results.is_empty());
+    }
+
+    #[tokio::test]
+    async fn find_by_symbol_wildcards_kind_words_and_paths() {
+        let tmp = TempDir::new().unwrap();
+        let store = VectorStore::new(tmp.path().to_str().unwrap(), &Default::default())
+            .await
+            .unwrap();
+
+        let symbol = |path: &str, name: &str, kind: &str, x: f32| ChunkRow {
+            symbol_name: Some(name.to_string()),
+            symbol_kind: Some(kind.to_string()),
+            ..sample_row(path, x as i64, "func x() {}", "go", make_vector(x))
+        };
+        store
+            .insert(vec![
+                symbol("pkg/auth/token.go", "NewToken", "function_declaration", 1.0),
+                symbol(
+                    "pkg/auth/token.go",
+                    "Token.Renew",
+                    "method_declaration",
+                    2.0,
+                ),
+                symbol(
+                    "pkg/auth/session.go",
+                    "NewSession",
+                    "method_declaration",
+                    3.0,
+                ),
+                symbol(
+                    "pkg/authz/policy.go",
+                    "NewPolicy",
+                    "method_declaration",
+                    4.0,
+                ),
+            ])
+            .await
+            .unwrap();
+
+        let names = |query: SymbolQuery<'static>| {
+            let store = &store;
+            async move {
+                let mut names: Vec<String> = store
+                    .find_by_symbol(query, 10)
+                    .await
+                    .unwrap()
+                    .into_iter()
+                    .filter_map(|r| r.symbol_name)
+                    .collect();
+                names.sort();
+                names
+            }
+        };
+        // A wildcard pattern matches the whole name, not a substring
+        assert_eq!(names(SymbolQuery::named("new*")).await.len(), 3);
+        assert_eq!(names(SymbolQuery::named("*new")).await, ["Token.Renew"]);
+        assert_eq!(
+            names(SymbolQuery {
+                kind: Some("method"),
+                path: Some("pkg/auth"),
+                ..SymbolQuery::named("New*")
+            })
+            .await,
+            ["NewSession"]
+        );
+        assert_eq!(
+            names(SymbolQuery {
+                path: Some("pkg/auth/token.go"),
+                ..SymbolQuery::default()
+            })
+            .await,
+            ["NewToken", "Token.Renew"]
+        );
+    }
+
+    #[tokio::test]
+    async fn find_by_symbol_ranks_the_best_matches_first() {
+        let tmp = TempDir::new().unwrap();
+        let store = VectorStore::new(tmp.path().to_str().unwrap(), &Default::default())
+            .await
+            .unwrap();
+
+        let symbol = |path: &str, name: &str, x: f32| ChunkRow {
+            symbol_name: Some(name.to_string()),
+            ..sample_row(path, x as i64, "func x() {}", "go", make_vector(x))
+        };
+        store
+            .insert(vec![
+                symbol("a/b/c/config.go", "ParseConfigFile", 1.0),
+                symbol("a/b/config.go", "parseConfig", 2.0),
+                symbol("a/config.go", "ParseConfigs", 3.0),
+                symbol("config.go", "ParseConfigs", 4.0),
+                symbol("deep/er/config.go", "ParseConfig", 5.0),
+                symbol("z.go", "MustParseConfig", 6.0),
+            ])
+            .await
+            .unwrap();
+
+        let ranked = |limit: usize| {
+            let store = &store;
+            async move {
+                store
+                    .find_by_symbol(SymbolQuery::named("ParseConfig"), limit)
+                    .await
+                    .unwrap()
+                    .into_iter()
+                    .map(|r| format!("{}:{}", r.file_path, r.symbol_name.unwrap_or_default()))
+                    .collect::<Vec<_>>()
+            }
+        };
+        // Exact, then exact but for case, then prefixes shortest and
+        // shallowest first, then other matches
+        let all = ranked(10).await;
+        assert_eq!(
+            all,
+            [
+                "deep/er/config.go:ParseConfig",
+                "a/b/config.go:parseConfig",
+                "config.go:ParseConfigs",
+                "a/config.go:ParseConfigs",
+                "a/b/c/config.go:ParseConfigFile",
+                "z.go:MustParseConfig",
+            ]
+        );
+
+        // A limit keeps the best, and the same query ranks the same way
+        // every time
+        for _ in 0..5 {
+            assert_eq!(ranked(3).await, all[..3]);
+            assert_eq!(ranked(10).await, all);
+        }
+    }
+
+    // ---------------------------------------------------------------
+    // list_files tests
+    // ---------------------------------------------------------------
+
+    #[tokio::test]
+    async fn list_files_returns_unique_paths() {
+        let tmp = TempDir::new().unwrap();
+        let store = VectorStore::new(tmp.path().to_str().unwrap(), &Default::default())
+            .await
+            .unwrap();
+
+        let rows = vec![
+            sample_row("main.go", 0, "package main", "go", make_vector(1.0)),
+            sample_row("main.go", 1, "func main() {}", "go", make_vector(2.0)),
+            sample_row("util.go", 0, "func util() {}", "go", make_vector(3.0)),
+            sample_row("server.go", 0, "func serve() {}", "go", make_vector(4.0)),
+        ];
+        store.insert(rows).await.unwrap();
+
+        let files = store.list_files(None).await.unwrap();
+        assert_eq!(files.len(), 3);
+        assert!(files.contains(&"main.go".to_string()));
+        assert!(files.contains(&"util.go".to_string()));
+        assert!(files.contains(&"server.go".to_string()));
+    }
+
+    #[tokio::test]
+    async fn list_files_sorted() {
+        let tmp = TempDir::new().unwrap();
+        let store = VectorStore::new(tmp.path().to_str().unwrap(), &Default::default())
+            .await
+            .unwrap();
+
+        let rows = vec![
+            sample_row("z.go", 0, "func z() {}", "go", make_vector(1.0)),
+            sample_row("a.go", 0, "func a() {}", "go", make_vector(2.0)),
+            sample_row("m.go", 0, "func m() {}", "go", make_vector(3.0)),
+        ];
+        store.insert(rows).await.unwrap();
+
+        let files = store.list_files(None).await.unwrap();
+        assert_eq!(files, vec!["a.go", "m.go", "z.go"]);
+    }
+
+    #[tokio::test]
+    async fn list_files_with_language_filter() {
+        let tmp = TempDir::new().unwrap();
+        let store = VectorStore::new(tmp.path().to_str().unwrap(), &Default::default())
+            .await
+            .unwrap();
+
+        let rows = vec![
+            sample_row("main.go", 0, "func main() {}", "go", make_vector(1.0)),
+            sample_row("lib.rs", 0, "fn lib() {}", "rust", make_vector(2.0)),
+        ];
+        store.insert(rows).await.unwrap();
+
+        let go_files = store.list_files(Some("go")).await.unwrap();
+        assert_eq!(go_files, vec!["main.go"]);
+
+        let rust_files = store.list_files(Some("rust")).await.unwrap();
+        assert_eq!(rust_files, vec!["lib.rs"]);
+    }
+
+    #[tokio::test]
+    async fn list_files_empty_store() {
+        let tmp = TempDir::new().unwrap();
+        let store = VectorStore::new(tmp.path().to_str().unwrap(), &Default::default())
+            .await
+            .unwrap();
+
+        let files = store.list_files(None).await.unwrap();
+        assert!(files.is_empty());
+    }
+
+    #[tokio::test]
+    async fn dependencies_are_searched_only_in_their_scope() {
+        let tmp = TempDir::new().unwrap();
+        let store = VectorStore::new(tmp.path().to_str().unwrap(), &Default::default())
+            .await
+            .unwrap();
+        let dep_path = format!("{DEPS_PATH}/golang.org/x/sync@v0.7.0/errgroup/errgroup.go");
+        store
+            .insert(vec![
+                sample_row(
+                    &dep_path,
+                    0,
+                    "func (g *Group) Go() {}",
+                    "go",
+                    make_vector(1.0),
+                ),
+                sample_row("main.go", 0, "func main() {}", "go", make_vector(1.0)),
+            ])
+            .await
+            .unwrap();
+
+        let paths = |scope| {
+            let store = store.clone();
+            async move {
+                store
+                    .search(
+                        &make_vector(1.0),
+                        5,
+                        Filters {
+                            scope,
+                            ..Default::default()
+                        },
+                        None,
+                    )
+                    .await
+                    .unwrap()
+                    .into_iter()
+                    .map(|r| r.file_path)
+                    .collect::<Vec<_>>()
+            }
+        };
+        assert_eq!(paths(Scope::Project).await, ["main.go"]);
+        assert_eq!(paths(Scope::Dependencies).await, [dep_path]);
+        assert_eq!(paths(Scope::All).await.len(), 2);
+        assert_eq!(store.list_files(None).await.unwrap(), ["main.go"]);
+
+        let deps = store.dependencies().await;
+        assert_eq!(
+            deps.keys().collect::<Vec<_>>(),
+            ["golang.org/x/sync@v0.7.0"]
+        );
+        store
+            .delete_chunks(&deps["golang.org/x/sync@v0.7.0"])
+            .await
+            .unwrap();
+        assert!(store.dependencies().await.is_empty());
+        assert_eq!(store.chunk_count().await.unwrap(), 1);
     }
 
-    /// Persist index and metadata to disk. Caller must hold both locks.
-    async fn persist_locked(&self, index: &SendSyncIndex, meta: &Metadata) -> Result<()> {
-        let index_path = self.db_path.join(INDEX_FILE);
-        let meta_path = self.db_path.join(META_FILE);
+    #[tokio::test]
+    async fn namespaces_are_filtered_counted_and_migrated() {
+        let tmp = TempDir::new().unwrap();
+        let store = VectorStore::new(tmp.path().to_str().unwrap(), &Default::default())
+            .await
+            .unwrap();
+        let mut readme = sample_row("README.md", 0, "# Usage", "markdown", make_vector(1.0));
+        readme.namespace = Namespace::Docs;
+        store
+            .insert(vec![
+                sample_row("main.go", 0, "func main() {}", "go", make_vector(1.0)),
+                readme,
+            ])
+            .await
+            .unwrap();
+        store
+            .add_note("docs live in README", make_vector(1.0))
+            .await
+            .unwrap();
 
-        index
-            .0
-            .save(index_path.to_str().unwrap_or_default())
-            .map_err(|e| Error::StoreIndex(e.to_string()))?;
+        let search = |namespace| {
+            let store = store.clone();
+            async move {
+                let filters = Filters {
+                    namespace: Some(namespace),
+                    ..Default::default()
+                };
+                store
+                    .search(&make_vector(1.0), 5, filters, None)
+                    .await
+                    .unwrap()
+                    .into_iter()
+                    .map(|r| r.file_path)
+                    .collect::<Vec<_>>()
+            }
+        };
+        assert_eq!(search(Namespace::Docs).await, ["README.md"]);
+        assert_eq!(search(Namespace::Notes).await, [NOTES_PATH]);
+        // Outside the default project scope
+        assert!(search(Namespace::Dependencies).await.is_empty());
+        assert_eq!(
+            store
+                .find_by_symbol(
+                    SymbolQuery {
+                        namespace: Some(Namespace::Code),
+                        ..SymbolQuery::named("symbol")
+                    },
+                    5,
+                )
+                .await
+                .unwrap()[0]
+                .file_path,
+            "main.go"
+        );
+        assert_eq!(
+            store.namespace_counts().await,
+            BTreeMap::from([
+                (Namespace::Code, 1),
+                (Namespace::Docs, 1),
+                (Namespace::Notes, 1)
+            ])
+        );
 
-        let json = serde_json::to_string(meta).map_err(Error::StoreSerde)?;
-        tokio::fs::write(&meta_path, json)
+        // Metadata from before namespaces loads everything as code
+        drop(store);
+        let meta_path = tmp.path().join(META_FILE);
+        let meta = std::fs::read_to_string(&meta_path).unwrap();
+        std::fs::write(
+            &meta_path,
+            meta.replace(r#""namespace":"notes""#, r#""namespace":"code""#),
+        )
+        .unwrap();
+        let store = VectorStore::new(tmp.path().to_str().unwrap(), &Default::default())
             .await
-            .map_err(|e| Error::StoreIo {
-                context: format!("writing {}", meta_path.display()),
-                source: e,
-            })?;
+            .unwrap();
+        assert_eq!(store.namespace_counts().await[&Namespace::Notes], 1);
+    }
 
-        Ok(())
+    #[tokio::test]
+    async fn search_filters_on_modification_time() {
+        let tmp = TempDir::new().unwrap();
+        let store = VectorStore::new(tmp.path().to_str().unwrap(), &Default::default())
+            .await
+            .unwrap();
+        let mut rows = Vec::new();
+        for (i, (path, modified)) in [("old.go", 1_000), ("new.go", 2_000)]
+            .into_iter()
+            .enumerate()
+        {
+            let content = format!("func f{i}() {{}}");
+            let mut row = sample_row(path, 0, &content, "go", make_vector(i as f32 + 1.0));
+            row.last_modified = modified;
+            rows.push(row);
+        }
+        store.insert(rows).await.unwrap();
+
+        let paths = |after, before| {
+            let store = store.clone();
+            async move {
+                let filters = Filters {
+                    modified: Modified { after, before },
+                    ..Default::default()
+                };
+                let mut paths: Vec<String> = store
+                    .search(&make_vector(1.0), 5, filters, None)
+                    .await
+                    .unwrap()
+                    .into_iter()
+                    .map(|r| r.file_path)
+                    .collect();
+                paths.sort();
+                paths
+            }
+        };
+        assert_eq!(paths(None, None).await, ["new.go", "old.go"]);
+        assert_eq!(paths(Some(2_000), None).await, ["new.go"]);
+        assert_eq!(paths(None, Some(2_000)).await, ["old.go"]);
+        assert!(paths(Some(1_001), Some(2_000)).await.is_empty());
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use tempfile::TempDir;
+    #[tokio::test]
+    async fn search_filters_on_platform() {
+        let tmp = TempDir::new().unwrap();
+        let store = VectorStore::new(tmp.path().to_str().unwrap(), &Default::default())
+            .await
+            .unwrap();
+        let mut rows = Vec::new();
+        for (i, (path, build)) in [
+            ("fd_linux.go", Some("linux")),
+            ("fd_windows.go", Some("windows")),
+            ("fd_unix.go", Some("unix && !linux")),
+            ("fd.go", None),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            let content = format!("func open{i}() {{}}");
+            let mut row = sample_row(path, 0, &content, "go", make_vector(i as f32 + 1.0));
+            row.build = build.map(str::to_string);
+            rows.push(row);
+        }
+        store.insert(rows).await.unwrap();
 
-    fn make_vector(seed: f32) -> Vec<f32> {
-        // Create a normalized vector with a distinguishable direction.
-        let mut v = vec![0.0f32; EMBEDDING_DIM];
-        let idx = (seed.abs() as usize) % EMBEDDING_DIM;
-        v[idx] = 1.0;
-        for (i, val) in v.iter_mut().enumerate() {
-            *val += (i as f32 * seed * 0.001).sin() * 0.01;
+        let paths = |platform| {
+            let store = store.clone();
+            async move {
+                let filters = Filters {
+                    platform,
+                    ..Default::default()
+                };
+                let mut paths: Vec<String> = store
+                    .search(&make_vector(1.0), 5, filters, None)
+                    .await
+                    .unwrap()
+                    .into_iter()
+                    .map(|r| r.file_path)
+                    .collect();
+                paths.sort();
+                paths
+            }
+        };
+        assert_eq!(paths(None).await.len(), 4);
+        assert_eq!(paths(Some("linux")).await, ["fd.go", "fd_linux.go"]);
+        assert_eq!(paths(Some("windows")).await, ["fd.go", "fd_windows.go"]);
+        assert_eq!(paths(Some("macos")).await, ["fd.go", "fd_unix.go"]);
+    }
+
+    #[tokio::test]
+    async fn exact_search_scores_every_chunk() {
+        let tmp = TempDir::new().unwrap();
+        let path = tmp.path().to_str().unwrap();
+        let config = IndexConfig {
+            exact: true,
+            exact_max_chunks: 4,
+            ..Default::default()
+        };
+        let row = |i: usize| {
+            let content = format!("func f{i}() {{}}");
+            sample_row(
+                &format!("f{i}.go"),
+                0,
+                &content,
+                "go",
+                make_vector(i as f32),
+            )
+        };
+        let query = make_vector(2.0);
+        // Every stored chunk, nearest first, found by brute force
+        let expected = |stored: &[usize]| {
+            let mut ranked: Vec<(f32, String)> = stored
+                .iter()
+                .map(|&i| {
+                    let vector = make_vector(i as f32);
+                    (distance(Metric::Cos, &query, &vector), format!("f{i}.go"))
+                })
+                .collect();
+            ranked.sort_by(|a, b| a.0.total_cmp(&b.0));
+            ranked.into_iter().map(|(_, path)| path).collect::<Vec<_>>()
+        };
+        let search = |store: &VectorStore, exact| {
+            let store = store.clone();
+            let query = query.clone();
+            async move {
+                let filters = Filters {
+                    exact,
+                    ..Default::default()
+                };
+                let results = store.search(&query, 10, filters, None).await.unwrap();
+                results.into_iter().map(|r| r.file_path).collect::<Vec<_>>()
+            }
+        };
+
+        let store = VectorStore::new(path, &config).await.unwrap();
+        store.insert((1..5).map(row).collect()).await.unwrap();
+        assert_eq!(search(&store, None).await, expected(&[1, 2, 3, 4]));
+        store.delete_file("f2.go").await.unwrap();
+        assert_eq!(search(&store, None).await, expected(&[1, 3, 4]));
+        drop(store);
+
+        // Held again from the saved index
+        let store = VectorStore::new(path, &config).await.unwrap();
+        assert_eq!(search(&store, None).await, expected(&[1, 3, 4]));
+        // Past the limit, searched approximately
+        store.insert((5..7).map(row).collect()).await.unwrap();
+        assert_eq!(search(&store, None).await.len(), 5);
+        drop(store);
+
+        // Asked for by one search, without holding the vectors
+        let store = VectorStore::new(path, &Default::default()).await.unwrap();
+        assert_eq!(search(&store, Some(true)).await, expected(&[1, 3, 4, 5, 6]));
+    }
+
+    #[tokio::test]
+    async fn search_filters_on_module() {
+        let tmp = TempDir::new().unwrap();
+        let store = VectorStore::new(tmp.path().to_str().unwrap(), &Default::default())
+            .await
+            .unwrap();
+        let mut rows = Vec::new();
+        for (i, (path, module)) in [
+            ("api/handler.go", Some("github.com/acme/api")),
+            ("billing/handler.go", Some("github.com/acme/billing")),
+            ("main.go", None),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            let content = format!("func handle{i}() {{}}");
+            let mut row = sample_row(path, 0, &content, "go", make_vector(i as f32 + 1.0));
+            row.module = module.map(str::to_string);
+            rows.push(row);
         }
-        let norm: f32 = v.iter().map(|x| x * x).sum::<f32>().sqrt();
-        v.iter_mut().for_each(|x| *x /= norm);
-        v
+        store.insert(rows).await.unwrap();
+
+        let search = |module| {
+            let store = store.clone();
+            async move {
+                let filters = Filters {
+                    module,
+                    ..Default::default()
+                };
+                store
+                    .search(&make_vector(1.0), 5, filters, None)
+                    .await
+                    .unwrap()
+            }
+        };
+        assert_eq!(search(None).await.len(), 3);
+        let results = search(Some("github.com/acme/billing")).await;
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].file_path, "billing/handler.go");
+        assert_eq!(
+            results[0].module.as_deref(),
+            Some("github.com/acme/billing")
+        );
+        // Exact, not a prefix
+        assert!(search(Some("github.com/acme")).await.is_empty());
     }
 
-    fn sample_row(
-        file_path: &str,
-        chunk_id: i64,
-        content: &str,
-        language: &str,
-        vector: Vec<f32>,
-    ) -> ChunkRow {
-        ChunkRow {
-            file_path: file_path.to_string(),
-            chunk_id,
-            content: content.to_string(),
-            symbol_name: Some(format!("symbol_{chunk_id}")),
-            symbol_kind: Some("func".to_string()),
-            language: language.to_string(),
-            start_line: chunk_id * 10 + 1,
-            end_line: chunk_id * 10 + 9,
-            last_modified: 1700000000,
-            vector,
+    #[tokio::test]
+    async fn chunks_carry_and_filter_on_code_owners() {
+        let tmp = TempDir::new().unwrap();
+        let path = tmp.path().to_str().unwrap();
+        let store = VectorStore::new(path, &Default::default()).await.unwrap();
+        store
+            .insert(vec![sample_row(
+                "billing/invoice.go",
+                0,
+                "func invoice() {}",
+                "go",
+                make_vector(1.0),
+            )])
+            .await
+            .unwrap();
+        store
+            .add_note("billing is owned by payments", make_vector(3.0))
+            .await
+            .unwrap();
+
+        // Chunks stored before the owners are known get them too
+        store
+            .set_code_owners(CodeOwners::parse(
+                "* @acme/core\n/billing/ @acme/payments\n",
+            ))
+            .await
+            .unwrap();
+        store
+            .insert(vec![sample_row(
+                "web/app.go",
+                0,
+                "func app() {}",
+                "go",
+                make_vector(2.0),
+            )])
+            .await
+            .unwrap();
+
+        let owned_by = |owner| {
+            let store = store.clone();
+            async move {
+                let filters = Filters {
+                    owner,
+                    ..Default::default()
+                };
+                store
+                    .search(&make_vector(1.0), 5, filters, None)
+                    .await
+                    .unwrap()
+                    .into_iter()
+                    .map(|r| (r.file_path, r.owners))
+                    .collect::<Vec<_>>()
+            }
+        };
+        assert_eq!(
+            owned_by(Some("@acme/payments")).await,
+            [(
+                "billing/invoice.go".to_string(),
+                vec!["@acme/payments".to_string()]
+            )]
+        );
+        assert_eq!(
+            owned_by(Some("@acme/core")).await,
+            [("web/app.go".to_string(), vec!["@acme/core".to_string()])]
+        );
+        // Notes belong to no team
+        assert_eq!(owned_by(None).await.len(), 3);
+        drop(store);
+
+        // Persisted, and updated when CODEOWNERS changes
+        let store = VectorStore::new(path, &Default::default()).await.unwrap();
+        let results = store
+            .find_by_symbol(SymbolQuery::named("symbol_0"), 5)
+            .await
+            .unwrap();
+        assert!(results.iter().all(|r| !r.owners.is_empty()));
+        store.set_code_owners(CodeOwners::default()).await.unwrap();
+        let results = store
+            .find_by_symbol(SymbolQuery::named("symbol_0"), 5)
+            .await
+            .unwrap();
+        assert!(results.iter().all(|r| r.owners.is_empty()));
+    }
+
+    #[tokio::test]
+    async fn offsets_mode_reads_content_from_the_source_root() {
+        let tmp = TempDir::new().unwrap();
+        let root = TempDir::new().unwrap();
+        let source = "package main\n\nfunc HandleRequest() {}\n";
+        std::fs::write(root.path().join("main.go"), source).unwrap();
+        let config = IndexConfig {
+            content: ContentStorage::Offsets,
+            ..Default::default()
+        };
+        let path = tmp.path().to_str().unwrap();
+        let store = VectorStore::new(path, &config)
+            .await
+            .unwrap()
+            .with_source_root(root.path())
+            .await;
+        let mut row = sample_row(
+            "main.go",
+            0,
+            "func HandleRequest() {}",
+            "go",
+            make_vector(1.0),
+        );
+        row.span = Some(14..37);
+        // Rows without a span keep their text either way
+        let inline = sample_row("doc.go", 0, "// see HandleRequest", "go", make_vector(2.0));
+        store.insert(vec![row, inline]).await.unwrap();
+
+        {
+            let meta = store.meta.read().await;
+            let contents: HashSet<&str> =
+                meta.chunks.values().map(|c| c.content.as_str()).collect();
+            assert_eq!(contents, HashSet::from(["", "// see HandleRequest"]));
         }
+        drop(store);
+
+        // Reopened, the text and quoted identifier lookups come from the file
+        let store = VectorStore::open_read_only(path, &config)
+            .await
+            .unwrap()
+            .with_source_root(root.path())
+            .await;
+        let query = QueryText {
+            text: "where is `HandleRequest` defined",
+            path_boost: 0.0,
+            language_boost: 0.0,
+            focus: Focus::Any,
+            focus_boost: 0.0,
+            structure_boost: 0.0,
+            generated_penalty: 0.0,
+        };
+        let results = store
+            .search(&make_vector(2.0), 5, Filters::default(), Some(query))
+            .await
+            .unwrap();
+        assert_eq!(results.len(), 2);
+        assert!(
+            results
+                .iter()
+                .any(|r| r.content == "func HandleRequest() {}")
+        );
+        let chunk = store.chunk_at("main.go", 5).await.unwrap().unwrap();
+        assert_eq!(chunk.content, "func HandleRequest() {}");
     }
 
     #[tokio::test]
-    async fn empty_store_has_zero_count() {
+    async fn commits_are_searchable_by_kind_but_not_files() {
         let tmp = TempDir::new().unwrap();
-        let store = VectorStore::new(tmp.path().to_str().unwrap())
+        let store = VectorStore::new(tmp.path().to_str().unwrap(), &Default::default())
             .await
             .unwrap();
-        assert_eq!(store.chunk_count().await.unwrap(), 0);
+        let mut commit = sample_row(
+            COMMITS_PATH,
+            0,
+            "Retry uploads",
+            COMMITS_LANGUAGE,
+            make_vector(1.0),
+        );
+        commit.symbol_name = Some("abc1234".to_string());
+        commit.symbol_kind = Some(COMMIT_KIND.to_string());
+        commit.namespace = Namespace::Commits;
+        let keys = store
+            .insert(vec![
+                commit,
+                sample_row("upload.go", 0, "func upload() {}", "go", make_vector(1.0)),
+            ])
+            .await
+            .unwrap();
+
+        let results = store
+            .search(
+                &make_vector(1.0),
+                5,
+                Filters {
+                    kind: Some(COMMIT_KIND),
+                    ..Default::default()
+                },
+                None,
+            )
+            .await
+            .unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].symbol_name.as_deref(), Some("abc1234"));
+        assert_eq!(store.list_files(None).await.unwrap(), ["upload.go"]);
+
+        store.delete_chunks(&[keys[0], 999]).await.unwrap();
+        assert_eq!(store.chunk_count().await.unwrap(), 1);
     }
 
     #[tokio::test]
-    async fn empty_store_search_returns_empty() {
+    async fn search_files_ranks_by_mean_chunk_vector() {
         let tmp = TempDir::new().unwrap();
-        let store = VectorStore::new(tmp.path().to_str().unwrap())
+        let store = VectorStore::new(tmp.path().to_str().unwrap(), &Default::default())
             .await
             .unwrap();
-        let query = make_vector(1.0);
-        let results = store.search(&query, 10, None).await.unwrap();
-        assert!(results.is_empty());
+
+        store
+            .insert(vec![
+                sample_row("billing.go", 1, "func charge() {}", "go", make_vector(1.0)),
+                sample_row("billing.go", 0, "func invoice() {}", "go", make_vector(2.0)),
+                sample_row(
+                    "auth.py",
+                    0,
+                    "def login(): pass",
+                    "python",
+                    make_vector(50.0),
+                ),
+            ])
+            .await
+            .unwrap();
+        store
+            .add_note("billing runs nightly", make_vector(1.0))
+            .await
+            .unwrap();
+
+        let hits = store
+            .search_files(&make_vector(1.0), 10, None)
+            .await
+            .unwrap();
+        let paths: Vec<&str> = hits.iter().map(|h| h.file_path.as_str()).collect();
+        assert_eq!(paths, ["billing.go", "auth.py"]);
+        assert_eq!(hits[0].chunks, 2);
+        assert_eq!(hits[0].symbols, ["symbol_0", "symbol_1"]);
+        assert!(hits[0].distance < hits[1].distance);
+
+        let python = store
+            .search_files(&make_vector(1.0), 10, Some("python"))
+            .await
+            .unwrap();
+        assert_eq!(python.len(), 1);
+
+        // Replacing a file's chunks replaces its file vector
+        store.delete_file("billing.go").await.unwrap();
+        store
+            .insert(vec![sample_row(
+                "billing.go",
+                0,
+                "func refund() {}",
+                "go",
+                make_vector(80.0),
+            )])
+            .await
+            .unwrap();
+        let hits = store
+            .search_files(&make_vector(80.0), 1, None)
+            .await
+            .unwrap();
+        assert_eq!(hits[0].file_path, "billing.go");
+        assert!(hits[0].distance < 0.01, "{hits:?}");
     }
 
+    // ---------------------------------------------------------------
+    // Identity tests
+    // ---------------------------------------------------------------
+
     #[tokio::test]
-    async fn insert_empty_batch_is_noop() {
+    async fn insert_reuses_requested_key() {
         let tmp = TempDir::new().unwrap();
-        let store = VectorStore::new(tmp.path().to_str().unwrap())
+        let store = VectorStore::new(tmp.path().to_str().unwrap(), &Default::default())
             .await
             .unwrap();
-        store.insert(vec![]).await.unwrap();
-        assert_eq!(store.chunk_count().await.unwrap(), 0);
+        store
+            .insert(vec![sample_row(
+                "a.go",
+                0,
+                "func a() {}",
+                "go",
+                make_vector(1.0),
+            )])
+            .await
+            .unwrap();
+        let old = store.file_chunks("a.go").await.unwrap();
+        assert_eq!(old.len(), 1);
+        let key = old[0].key;
+
+        store.delete_file("a.go").await.unwrap();
+        store
+            .insert(vec![ChunkRow {
+                key: Some(key),
+                ..sample_row("a.go", 0, "func a() { edited() }", "go", make_vector(1.0))
+            }])
+            .await
+            .unwrap();
+
+        let results = store
+            .search(&make_vector(1.0), 1, Filters::default(), None)
+            .await
+            .unwrap();
+        assert_eq!(results[0].id, key);
+        assert_eq!(results[0].content, "func a() { edited() }");
     }
 
     #[tokio::test]
-    async fn insert_and_count() {
+    async fn insert_allocates_fresh_key_on_conflict() {
         let tmp = TempDir::new().unwrap();
-        let store = VectorStore::new(tmp.path().to_str().unwrap())
+        let store = VectorStore::new(tmp.path().to_str().unwrap(), &Default::default())
             .await
             .unwrap();
+        store
+            .insert(vec![sample_row(
+                "a.go",
+                0,
+                "func a() {}",
+                "go",
+                make_vector(1.0),
+            )])
+            .await
+            .unwrap();
+        let taken = store.file_chunks("a.go").await.unwrap()[0].key;
 
-        let rows = vec![
-            sample_row("main.go", 0, "func main() {}", "go", make_vector(1.0)),
-            sample_row("main.go", 1, "func helper() {}", "go", make_vector(2.0)),
-            sample_row("util.go", 0, "func util() {}", "go", make_vector(3.0)),
-        ];
-        store.insert(rows).await.unwrap();
+        store
+            .insert(vec![ChunkRow {
+                key: Some(taken),
+                ..sample_row("b.go", 0, "func b() {}", "go", make_vector(2.0))
+            }])
+            .await
+            .unwrap();
 
-        assert_eq!(store.chunk_count().await.unwrap(), 3);
+        assert_eq!(store.chunk_count().await.unwrap(), 2);
+        assert_ne!(store.file_chunks("b.go").await.unwrap()[0].key, taken);
+    }
+
+    // ---------------------------------------------------------------
+    // Read-only tests
+    // ---------------------------------------------------------------
+
+    #[test]
+    fn query_terms_skip_short_and_common_words() {
+        assert_eq!(
+            query_terms("Where is the auth middleware? auth in Go"),
+            ["auth", "middleware"]
+        );
+    }
+
+    #[test]
+    fn path_match_counts_matching_components() {
+        let terms = query_terms("auth middleware");
+        assert_eq!(path_match(&terms, "internal/middleware/auth.go"), 1.0);
+        assert_eq!(path_match(&terms, "pkg/authn/session.go"), 0.5);
+        assert_eq!(path_match(&terms, "cmd/server/main.go"), 0.0);
     }
 
     #[tokio::test]
-    async fn search_returns_closest_match() {
+    async fn path_boost_prefers_matching_paths() {
+        let tmp = TempDir::new().unwrap();
+        let store = VectorStore::new(tmp.path().to_str().unwrap(), &Default::default())
+            .await
+            .unwrap();
+        store
+            .insert(vec![
+                sample_row("auth/session.go", 0, "func a() {}", "go", make_vector(1.0)),
+                sample_row(
+                    "middleware/auth.go",
+                    0,
+                    "func b() {}",
+                    "go",
+                    make_vector(1.1),
+                ),
+            ])
+            .await
+            .unwrap();
+
+        let query = make_vector(1.0);
+        let plain = store
+            .search(&query, 1, Filters::default(), None)
+            .await
+            .unwrap();
+        assert_eq!(plain[0].file_path, "auth/session.go");
+
+        let boost = QueryText {
+            text: "auth middleware",
+            path_boost: 0.05,
+            language_boost: 0.0,
+            focus: Focus::Any,
+            focus_boost: 0.0,
+            structure_boost: 0.0,
+            generated_penalty: 0.0,
+        };
+        let boosted = store
+            .search(&query, 1, Filters::default(), Some(boost))
+            .await
+            .unwrap();
+        assert_eq!(boosted.len(), 1);
+        assert_eq!(boosted[0].file_path, "middleware/auth.go");
+    }
+
+    #[tokio::test]
+    async fn generated_chunks_rank_below_handwritten_ones() {
+        let tmp = TempDir::new().unwrap();
+        let store = VectorStore::new(tmp.path().to_str().unwrap(), &Default::default())
+            .await
+            .unwrap();
+        let mut mock = sample_row("mock_store.go", 0, "func Get() {}", "go", make_vector(1.0));
+        mock.generated = true;
+        store
+            .insert(vec![
+                mock,
+                sample_row("store.go", 0, "func Get() int {}", "go", make_vector(1.1)),
+            ])
+            .await
+            .unwrap();
+
+        let query = make_vector(1.0);
+        let penalty = |generated_penalty| QueryText {
+            text: "get",
+            path_boost: 0.0,
+            language_boost: 0.0,
+            focus: Focus::Any,
+            focus_boost: 0.0,
+            structure_boost: 0.0,
+            generated_penalty,
+        };
+        let plain = store
+            .search(&query, 1, Filters::default(), Some(penalty(0.0)))
+            .await
+            .unwrap();
+        assert_eq!(plain[0].file_path, "mock_store.go");
+        let demoted = store
+            .search(&query, 1, Filters::default(), Some(penalty(0.1)))
+            .await
+            .unwrap();
+        assert_eq!(demoted.len(), 1);
+        assert_eq!(demoted[0].file_path, "store.go");
+    }
+
+    #[tokio::test]
+    async fn language_hints_boost_without_filtering() {
         let tmp = TempDir::new().unwrap();
-        let store = VectorStore::new(tmp.path().to_str().unwrap())
+        let store = VectorStore::new(tmp.path().to_str().unwrap(), &Default::default())
+            .await
+            .unwrap();
+        store
+            .insert(vec![
+                sample_row("pool.py", 0, "def spawn(): ...", "python", make_vector(1.0)),
+                sample_row("pool.go", 0, "func spawn() {}", "go", make_vector(1.1)),
+            ])
             .await
             .unwrap();
 
-        let v1 = make_vector(1.0);
-        let v2 = make_vector(50.0);
-        let v3 = make_vector(100.0);
-
-        let rows = vec![
-            sample_row("a.go", 0, "func alpha() {}", "go", v1.clone()),
-            sample_row("b.go", 0, "func beta() {}", "go", v2.clone()),
-            sample_row("c.go", 0, "func gamma() {}", "go", v3),
-        ];
-        store.insert(rows).await.unwrap();
-
-        let results = store.search(&v1, 3, None).await.unwrap();
-        assert!(!results.is_empty());
-        assert_eq!(results[0].content, "func alpha() {}");
-        assert!(results[0].distance < results.last().unwrap().distance);
+        let query = QueryText {
+            text: "spawn a goroutine per job",
+            path_boost: 0.0,
+            language_boost: 0.03,
+            focus: Focus::Any,
+            focus_boost: 0.0,
+            structure_boost: 0.0,
+            generated_penalty: 0.0,
+        };
+        let results = store
+            .search(&make_vector(1.0), 2, Filters::default(), Some(query))
+            .await
+            .unwrap();
+        assert_eq!(results[0].file_path, "pool.go");
+        assert_eq!(results.len(), 2, "other languages are still returned");
     }
 
     #[tokio::test]
-    async fn search_respects_limit() {
+    async fn structural_hints_boost_tests_without_filtering() {
         let tmp = TempDir::new().unwrap();
-        let store = VectorStore::new(tmp.path().to_str().unwrap())
+        let store = VectorStore::new(tmp.path().to_str().unwrap(), &Default::default())
             .await
             .unwrap();
-
-        let rows: Vec<ChunkRow> = (0..10)
-            .map(|i| {
+        store
+            .insert(vec![
+                sample_row("parser.go", 0, "func Parse() {}", "go", make_vector(1.0)),
                 sample_row(
-                    "file.go",
-                    i,
-                    &format!("func f{i}() {{}}"),
+                    "parser_test.go",
+                    0,
+                    "func TestParse(t *testing.T) {}",
                     "go",
-                    make_vector(i as f32),
-                )
-            })
-            .collect();
-        store.insert(rows).await.unwrap();
+                    make_vector(1.1),
+                ),
+            ])
+            .await
+            .unwrap();
 
-        let results = store.search(&make_vector(0.0), 3, None).await.unwrap();
-        assert_eq!(results.len(), 3);
+        let search = |text, kind| {
+            let store = store.clone();
+            async move {
+                let query = QueryText {
+                    text,
+                    path_boost: 0.0,
+                    language_boost: 0.0,
+                    focus: Focus::Any,
+                    focus_boost: 0.0,
+                    structure_boost: 0.05,
+                    generated_penalty: 0.0,
+                };
+                let filters = Filters {
+                    kind,
+                    ..Default::default()
+                };
+                store
+                    .search(&make_vector(1.0), 2, filters, Some(query))
+                    .await
+                    .unwrap()
+            }
+        };
+        let results = search("test for parser", None).await;
+        assert_eq!(results[0].file_path, "parser_test.go");
+        assert_eq!(results.len(), 2, "other chunks are still returned");
+        assert_eq!(search("parser", None).await[0].file_path, "parser.go");
+        // An explicit kind filter turns the hints off
+        let filtered = search("test for parser", Some("func")).await;
+        assert_eq!(filtered[0].file_path, "parser.go");
     }
 
     #[tokio::test]
-    async fn delete_file_removes_only_that_file() {
+    async fn focus_prefers_code_or_docs() {
         let tmp = TempDir::new().unwrap();
-        let store = VectorStore::new(tmp.path().to_str().unwrap())
+        let store = VectorStore::new(tmp.path().to_str().unwrap(), &Default::default())
+            .await
+            .unwrap();
+        let mut documented = sample_row(
+            "guide.py",
+            0,
+            "# How retries work\ndef retry(): ...",
+            "python",
+            make_vector(1.1),
+        );
+        documented.comment_ratio = 0.8;
+        store
+            .insert(vec![
+                sample_row(
+                    "retry.py",
+                    0,
+                    "def retry(): ...",
+                    "python",
+                    make_vector(1.0),
+                ),
+                documented,
+            ])
             .await
             .unwrap();
 
-        let rows = vec![
-            sample_row("keep.go", 0, "func keep() {}", "go", make_vector(1.0)),
-            sample_row("keep.go", 1, "func keep2() {}", "go", make_vector(2.0)),
-            sample_row("remove.go", 0, "func remove() {}", "go", make_vector(3.0)),
-        ];
-        store.insert(rows).await.unwrap();
-        assert_eq!(store.chunk_count().await.unwrap(), 3);
-
-        store.delete_file("remove.go").await.unwrap();
-
-        assert_eq!(store.chunk_count().await.unwrap(), 2);
-
-        let results = store.search(&make_vector(3.0), 10, None).await.unwrap();
-        for r in &results {
-            assert_eq!(
-                r.file_path, "keep.go",
-                "deleted file should not appear in results"
-            );
-        }
+        let search = |focus| {
+            let store = store.clone();
+            async move {
+                let query = QueryText {
+                    text: "retry",
+                    path_boost: 0.0,
+                    language_boost: 0.0,
+                    focus,
+                    focus_boost: 0.1,
+                    structure_boost: 0.0,
+                    generated_penalty: 0.0,
+                };
+                store
+                    .search(&make_vector(1.0), 2, Filters::default(), Some(query))
+                    .await
+                    .unwrap()
+            }
+        };
+        assert_eq!(search(Focus::Code).await[0].file_path, "retry.py");
+        assert_eq!(search(Focus::Docs).await[0].file_path, "guide.py");
+        assert_eq!(Focus::Any.affinity(0.8), 0.0);
     }
 
     #[tokio::test]
-    async fn search_with_language_filter() {
+    async fn backticked_identifiers_restrict_search() {
         let tmp = TempDir::new().unwrap();
-        let store = VectorStore::new(tmp.path().to_str().unwrap())
+        let path = tmp.path().to_str().unwrap();
+        let store = VectorStore::new(path, &Default::default()).await.unwrap();
+        store
+            .insert(vec![
+                sample_row("a.go", 0, "func alpha() {}", "go", make_vector(1.0)),
+                sample_row("b.go", 0, "func beta() { alpha() }", "go", make_vector(9.0)),
+                sample_row("c.go", 0, "func gamma() {}", "go", make_vector(20.0)),
+            ])
             .await
             .unwrap();
 
-        let rows = vec![
-            sample_row("main.go", 0, "func goFunc() {}", "go", make_vector(1.0)),
-            sample_row("main.rs", 0, "fn rust_func() {}", "rust", make_vector(2.0)),
-            sample_row(
-                "app.py",
-                0,
-                "def python_func():",
-                "python",
-                make_vector(3.0),
-            ),
-        ];
-        store.insert(rows).await.unwrap();
+        let query = |text| QueryText {
+            text,
+            path_boost: 0.0,
+            language_boost: 0.0,
+            focus: Focus::Any,
+            focus_boost: 0.0,
+            structure_boost: 0.0,
+            generated_penalty: 0.0,
+        };
+        let results = store
+            .search(
+                &make_vector(20.0),
+                10,
+                Filters::default(),
+                Some(query("who calls `alpha`")),
+            )
+            .await
+            .unwrap();
+        let files: Vec<&str> = results.iter().map(|r| r.file_path.as_str()).collect();
+        assert_eq!(files.len(), 2);
+        assert!(
+            files.contains(&"a.go") && files.contains(&"b.go"),
+            "{files:?}"
+        );
 
+        // Unknown identifiers fall back to plain vector search
         let results = store
-            .search(&make_vector(1.0), 10, Some("go"))
+            .search(
+                &make_vector(20.0),
+                10,
+                Filters::default(),
+                Some(query("`nowhere`")),
+            )
             .await
             .unwrap();
-        assert_eq!(results.len(), 1);
-        assert_eq!(results[0].content, "func goFunc() {}");
+        assert_eq!(results.len(), 3);
 
+        // Postings are rebuilt on reopen and follow deletes
+        drop(store);
+        let store = VectorStore::new(path, &Default::default()).await.unwrap();
+        store.delete_file("b.go").await.unwrap();
         let results = store
-            .search(&make_vector(2.0), 10, Some("rust"))
+            .search(
+                &make_vector(20.0),
+                10,
+                Filters::default(),
+                Some(query("`alpha`")),
+            )
             .await
             .unwrap();
         assert_eq!(results.len(), 1);
-        assert_eq!(results[0].content, "fn rust_func() {}");
+        assert_eq!(results[0].file_path, "a.go");
     }
 
     #[tokio::test]
-    async fn search_result_contains_metadata() {
+    async fn chunk_at_picks_innermost_enclosing_chunk() {
         let tmp = TempDir::new().unwrap();
-        let store = VectorStore::new(tmp.path().to_str().unwrap())
+        let store = VectorStore::new(tmp.path().to_str().unwrap(), &Default::default())
             .await
             .unwrap();
+        let mut outer = sample_row("s.rs", 0, "impl Server {}", "rust", make_vector(1.0));
+        outer.start_line = 10;
+        outer.end_line = 50;
+        let mut inner = sample_row("s.rs", 1, "fn start() {}", "rust", make_vector(2.0));
+        inner.start_line = 20;
+        inner.end_line = 30;
+        store.insert(vec![outer, inner]).await.unwrap();
 
-        let rows = vec![ChunkRow {
-            file_path: "handler.go".to_string(),
-            chunk_id: 0,
-            content: "func HandleRequest() {}".to_string(),
-            symbol_name: Some("HandleRequest".to_string()),
-            symbol_kind: Some("func".to_string()),
-            language: "go".to_string(),
-            start_line: 10,
-            end_line: 25,
-            last_modified: 1700000000,
-            vector: make_vector(1.0),
-        }];
-        store.insert(rows).await.unwrap();
+        let hit = store.chunk_at("s.rs", 25).await.unwrap().unwrap();
+        assert_eq!(hit.symbol_name.as_deref(), Some("symbol_1"));
+        let hit = store.chunk_at("s.rs", 50).await.unwrap().unwrap();
+        assert_eq!(hit.symbol_name.as_deref(), Some("symbol_0"));
+        assert!(store.chunk_at("s.rs", 5).await.unwrap().is_none());
+        assert!(store.chunk_at("other.rs", 25).await.unwrap().is_none());
 
-        let results = store.search(&make_vector(1.0), 1, None).await.unwrap();
-        assert_eq!(results.len(), 1);
-        let r = &results[0];
-        assert_eq!(r.file_path, "handler.go");
-        assert_eq!(r.content, "func HandleRequest() {}");
-        assert_eq!(r.symbol_name.as_deref(), Some("HandleRequest"));
-        assert_eq!(r.symbol_kind.as_deref(), Some("func"));
-        assert_eq!(r.start_line, 10);
-        assert_eq!(r.end_line, 25);
+        let around = store
+            .enclosing_chunk("s.rs", 20, 30)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(around.symbol_name.as_deref(), Some("symbol_0"));
+        assert!(
+            store
+                .enclosing_chunk("s.rs", 10, 50)
+                .await
+                .unwrap()
+                .is_none()
+        );
     }
 
     #[tokio::test]
-    async fn insert_multiple_batches() {
+    async fn type_definitions_are_found_nearest_the_method() {
         let tmp = TempDir::new().unwrap();
-        let store = VectorStore::new(tmp.path().to_str().unwrap())
+        let store = VectorStore::new(tmp.path().to_str().unwrap(), &Default::default())
+            .await
+            .unwrap();
+        let row = |file: &str, id: i64, name: &str, kind: &str, module: &str| {
+            let mut row = sample_row(file, id, name, "go", make_vector(id as f32));
+            row.symbol_name = Some(name.to_string());
+            row.symbol_kind = Some(kind.to_string());
+            row.module = Some(module.to_string());
+            row
+        };
+        store
+            .insert(vec![
+                row(
+                    "api/server.go",
+                    0,
+                    "Server, Option",
+                    "type_declaration",
+                    "acme/api",
+                ),
+                row(
+                    "api/handler.go",
+                    1,
+                    "Server",
+                    "function_declaration",
+                    "acme/api",
+                ),
+                row("web/server.go", 2, "Server", "type_declaration", "acme/web"),
+                row("web/server.go", 3, "Client", "type_declaration", "acme/web"),
+            ])
             .await
             .unwrap();
 
-        let rows1 = vec![sample_row("a.go", 0, "func a() {}", "go", make_vector(1.0))];
-        store.insert(rows1).await.unwrap();
+        // Grouped declarations count; a function of the same name doesn't
+        let found = |name, file, module| {
+            let store = &store;
+            async move {
+                store
+                    .type_definition(name, file, module)
+                    .await
+                    .unwrap()
+                    .map(|r| r.file_path)
+            }
+        };
+        assert_eq!(
+            found("Server", "api/handler.go", Some("acme/api"))
+                .await
+                .as_deref(),
+            Some("api/server.go")
+        );
+        assert_eq!(
+            found("Server", "web/server.go", None).await.as_deref(),
+            Some("web/server.go")
+        );
+        // Outside the module, only an unambiguous name is trusted
+        assert_eq!(found("Server", "x.go", Some("acme/x")).await, None);
+        assert_eq!(
+            found("Client", "x.go", Some("acme/x")).await.as_deref(),
+            Some("web/server.go")
+        );
+        assert_eq!(found("Missing", "x.go", None).await, None);
+    }
 
-        let rows2 = vec![
-            sample_row("b.go", 0, "func b() {}", "go", make_vector(2.0)),
-            sample_row("c.go", 0, "func c() {}", "go", make_vector(3.0)),
-        ];
-        store.insert(rows2).await.unwrap();
+    #[tokio::test]
+    async fn symbol_chunks_match_bare_and_qualified_names() {
+        let tmp = TempDir::new().unwrap();
+        let store = VectorStore::new(tmp.path().to_str().unwrap(), &Default::default())
+            .await
+            .unwrap();
+        let mut rows = Vec::new();
+        for (i, (name, start)) in [("Point::new", 20), ("new", 5), ("renew", 40)]
+            .into_iter()
+            .enumerate()
+        {
+            let mut row = sample_row(
+                "p.rs",
+                i as i64,
+                "fn new() {}",
+                "rust",
+                make_vector(i as f32),
+            );
+            row.symbol_name = Some(name.to_string());
+            row.start_line = start;
+            row.end_line = start + 3;
+            rows.push(row);
+        }
+        let mut elsewhere = sample_row("q.rs", 0, "fn new() {}", "rust", make_vector(9.0));
+        elsewhere.symbol_name = Some("new".to_string());
+        rows.push(elsewhere);
+        store.insert(rows).await.unwrap();
 
-        assert_eq!(store.chunk_count().await.unwrap(), 3);
+        let names = |hits: Vec<SearchResult>| {
+            hits.into_iter()
+                .filter_map(|h| h.symbol_name)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            names(store.symbol_chunks("p.rs", "new").await.unwrap()),
+            ["new", "Point::new"]
+        );
+        assert_eq!(
+            names(store.symbol_chunks("p.rs", "Point::new").await.unwrap()),
+            ["Point::new"]
+        );
+        assert!(store.symbol_chunks("p.rs", "old").await.unwrap().is_empty());
     }
 
     #[tokio::test]
-    async fn delete_nonexistent_file_is_noop() {
+    async fn list_symbols_sorts_by_kind_and_filters() {
         let tmp = TempDir::new().unwrap();
-        let store = VectorStore::new(tmp.path().to_str().unwrap())
+        let store = VectorStore::new(tmp.path().to_str().unwrap(), &Default::default())
             .await
             .unwrap();
-
-        let rows = vec![sample_row(
-            "exists.go",
-            0,
-            "func exists() {}",
-            "go",
-            make_vector(1.0),
-        )];
+        let mut rows = Vec::new();
+        for (i, (path, name, kind)) in [
+            ("api/server.go", "Start", "method_declaration"),
+            ("api/server.go", "Server", "type_declaration"),
+            ("api/client.go", "NewClient", "function_declaration"),
+            ("apiv2/client.go", "Dial", "function_declaration"),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            let mut row = sample_row(path, i as i64, "func f() {}", "go", make_vector(i as f32));
+            row.symbol_name = Some(name.to_string());
+            row.symbol_kind = Some(kind.to_string());
+            rows.push(row);
+        }
+        let mut anonymous = sample_row("api/doc.go", 0, "package api", "go", make_vector(7.0));
+        anonymous.symbol_name = None;
+        rows.push(anonymous);
         store.insert(rows).await.unwrap();
+        store
+            .add_note("Server is single-threaded", make_vector(8.0))
+            .await
+            .unwrap();
 
-        store.delete_file("does_not_exist.go").await.unwrap();
-        assert_eq!(store.chunk_count().await.unwrap(), 1);
+        let names =
+            |symbols: Vec<SymbolEntry>| symbols.into_iter().map(|s| s.name).collect::<Vec<_>>();
+        assert_eq!(
+            names(store.list_symbols(None, None, None, None).await),
+            ["Dial", "NewClient", "Start", "Server"]
+        );
+        assert_eq!(
+            names(store.list_symbols(Some("api/"), None, None, None).await),
+            ["NewClient", "Start", "Server"]
+        );
+        assert_eq!(
+            names(
+                store
+                    .list_symbols(Some("api/server.go"), None, None, None)
+                    .await
+            ),
+            ["Start", "Server"]
+        );
+        assert_eq!(
+            names(
+                store
+                    .list_symbols(None, None, Some("function_declaration"), None)
+                    .await
+            ),
+            ["Dial", "NewClient"]
+        );
+        assert!(
+            store
+                .list_symbols(None, Some("rust"), None, None)
+                .await
+                .is_empty()
+        );
     }
 
     #[tokio::test]
-    async fn null_optional_fields() {
+    async fn skipped_files_persist() {
         let tmp = TempDir::new().unwrap();
-        let store = VectorStore::new(tmp.path().to_str().unwrap())
+        let path = tmp.path().to_str().unwrap();
+        let store = VectorStore::new(path, &Default::default()).await.unwrap();
+        let skipped = BTreeMap::from([("gen/big.go".to_string(), "binary content".to_string())]);
+        store.set_skipped(skipped.clone()).await.unwrap();
+        drop(store);
+
+        let ro = VectorStore::open_read_only(path, &Default::default())
             .await
             .unwrap();
+        assert_eq!(ro.skipped_files().await, skipped);
+        assert!(ro.set_skipped(BTreeMap::new()).await.is_err());
+    }
 
-        let rows = vec![ChunkRow {
-            file_path: "test.go".to_string(),
-            chunk_id: 0,
-            content: "package main".to_string(),
-            symbol_name: None,
-            symbol_kind: None,
-            language: "go".to_string(),
-            start_line: 1,
-            end_line: 1,
-            last_modified: 1700000000,
-            vector: make_vector(1.0),
-        }];
-        store.insert(rows).await.unwrap();
+    #[tokio::test]
+    async fn moved_files_keep_their_keys_and_vectors() {
+        let tmp = TempDir::new().unwrap();
+        let store = VectorStore::new(tmp.path().to_str().unwrap(), &Default::default())
+            .await
+            .unwrap();
+        let rows = |path: &str, vector: fn(f32) -> Vec<f32>| {
+            vec![
+                sample_row(path, 0, "func Parse() {}", "go", vector(1.0)),
+                // A copy of the first chunk, sharing its vector
+                sample_row(path, 1, "func Parse() {}", "go", vector(1.0)),
+                sample_row(path, 2, "func Lex() {}", "go", vector(5.0)),
+            ]
+        };
+        let keys = store
+            .replace_file("old/parse.go", rows("old/parse.go", make_vector))
+            .await
+            .unwrap();
+        store.delete_file("old/parse.go").await.unwrap();
 
-        let results = store.search(&make_vector(1.0), 1, None).await.unwrap();
-        assert_eq!(results.len(), 1);
-        assert!(results[0].symbol_name.is_none());
-        assert!(results[0].symbol_kind.is_none());
-    }
+        // Edited text doesn't match
+        let mut edited = rows("new/parse.go", |_| Vec::new());
+        edited[2].content = "func Lex(s string) {}".to_string();
+        assert_eq!(store.take_moved(&mut edited).await, None);
 
-    // ---------------------------------------------------------------
-    // find_by_symbol tests
-    // ---------------------------------------------------------------
+        let mut moved = rows("new/parse.go", |_| Vec::new());
+        assert_eq!(
+            store.take_moved(&mut moved).await.as_deref(),
+            Some("old/parse.go")
+        );
+        assert_eq!(
+            moved.iter().map(|row| row.key).collect::<Vec<_>>(),
+            keys.iter().copied().map(Some).collect::<Vec<_>>()
+        );
+        assert!(moved.iter().all(|row| row.vector.len() == EMBEDDING_DIM));
+        store.replace_file("new/parse.go", moved).await.unwrap();
+
+        let results = store
+            .search(&make_vector(5.0), 1, Filters::default(), None)
+            .await
+            .unwrap();
+        assert_eq!(results[0].file_path, "new/parse.go");
+        assert_eq!(results[0].id, keys[2]);
+        // Taken once
+        let mut again = rows("other/parse.go", |_| Vec::new());
+        assert_eq!(store.take_moved(&mut again).await, None);
+    }
 
     #[tokio::test]
-    async fn find_by_symbol_matches_substring() {
+    async fn aliases_are_reported_with_results() {
         let tmp = TempDir::new().unwrap();
-        let store = VectorStore::new(tmp.path().to_str().unwrap())
+        let path = tmp.path().to_str().unwrap();
+        let store = VectorStore::new(path, &Default::default()).await.unwrap();
+        store
+            .insert(vec![
+                sample_row(
+                    "packages/ui/button.ts",
+                    0,
+                    "export function button() {}",
+                    "typescript",
+                    make_vector(1.0),
+                ),
+                // Stored by a run before the path was known to be an alias
+                sample_row(
+                    "apps/web/node_modules/ui/button.ts",
+                    0,
+                    "export function button() {}",
+                    "typescript",
+                    make_vector(1.0),
+                ),
+            ])
+            .await
+            .unwrap();
+        let alias = "apps/web/node_modules/ui/button.ts".to_string();
+        store
+            .set_aliases(BTreeMap::from([(
+                "packages/ui/button.ts".to_string(),
+                vec![alias.clone()],
+            )]))
             .await
             .unwrap();
 
-        let rows = vec![
-            ChunkRow {
-                file_path: "server.go".to_string(),
-                chunk_id: 0,
-                content: "func NewServer() {}".to_string(),
-                symbol_name: Some("NewServer".to_string()),
-                symbol_kind: Some("func".to_string()),
-                language: "go".to_string(),
-                start_line: 1,
-                end_line: 1,
-                last_modified: 1700000000,
-                vector: make_vector(1.0),
-            },
-            ChunkRow {
-                file_path: "server.go".to_string(),
-                chunk_id: 1,
-                content: "func (s *Server) Start() {}".to_string(),
-                symbol_name: Some("Server.Start".to_string()),
-                symbol_kind: Some("method".to_string()),
-                language: "go".to_string(),
-                start_line: 3,
-                end_line: 3,
-                last_modified: 1700000000,
-                vector: make_vector(2.0),
-            },
-            ChunkRow {
-                file_path: "client.go".to_string(),
-                chunk_id: 0,
-                content: "func NewClient() {}".to_string(),
-                symbol_name: Some("NewClient".to_string()),
-                symbol_kind: Some("func".to_string()),
-                language: "go".to_string(),
+        let results = store
+            .search(&make_vector(1.0), 5, Filters::default(), None)
+            .await
+            .unwrap();
+        assert_eq!(results.len(), 1, "the alias's own chunks are removed");
+        assert_eq!(results[0].file_path, "packages/ui/button.ts");
+        assert_eq!(
+            results[0].duplicates,
+            [Location {
+                file_path: alias.clone(),
                 start_line: 1,
-                end_line: 1,
-                last_modified: 1700000000,
-                vector: make_vector(3.0),
-            },
-        ];
-        store.insert(rows).await.unwrap();
+                end_line: 9,
+            }]
+        );
+        let at = store
+            .chunk_at("packages/ui/button.ts", 1)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(at.duplicates.len(), 1);
+        assert_eq!(
+            store.alias_target(&alias).await.as_deref(),
+            Some("packages/ui/button.ts")
+        );
+        assert_eq!(store.alias_count().await, 1);
+        drop(store);
 
-        let results = store.find_by_symbol("Server", None, 10).await.unwrap();
-        assert_eq!(results.len(), 2);
-        let names: Vec<_> = results
-            .iter()
-            .filter_map(|r| r.symbol_name.as_deref())
-            .collect();
-        assert!(names.contains(&"NewServer"));
-        assert!(names.contains(&"Server.Start"));
+        let ro = VectorStore::open_read_only(path, &Default::default())
+            .await
+            .unwrap();
+        assert_eq!(ro.alias_count().await, 1);
+        assert!(ro.set_aliases(BTreeMap::new()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn notes_are_searchable_filterable_and_deletable() {
+        let tmp = TempDir::new().unwrap();
+        let path = tmp.path().to_str().unwrap();
+        let store = VectorStore::new(path, &Default::default()).await.unwrap();
+        store
+            .insert(vec![sample_row(
+                "a.go",
+                0,
+                "func alpha() {}",
+                "go",
+                make_vector(1.0),
+            )])
+            .await
+            .unwrap();
+        let key = store
+            .add_note("retries are capped at 3 on purpose", make_vector(2.0))
+            .await
+            .unwrap();
+
+        let results = store
+            .search(&make_vector(2.0), 5, Filters::default(), None)
+            .await
+            .unwrap();
+        assert_eq!(results[0].id, key);
+        let notes = store
+            .search(
+                &make_vector(1.0),
+                5,
+                Filters {
+                    language: Some(NOTES_LANGUAGE),
+                    ..Default::default()
+                },
+                None,
+            )
+            .await
+            .unwrap();
+        assert_eq!(notes.len(), 1);
+        assert_eq!(notes[0].content, "retries are capped at 3 on purpose");
+        assert_eq!(store.list_files(None).await.unwrap(), ["a.go"]);
+        drop(store);
+
+        // Persisted across restarts, and only notes can be deleted
+        let store = VectorStore::new(path, &Default::default()).await.unwrap();
+        assert_eq!(store.chunk_count().await.unwrap(), 2);
+        let code_key = store
+            .find_by_symbol(SymbolQuery::named("symbol_0"), 1)
+            .await
+            .unwrap()[0]
+            .id;
+        assert!(!store.delete_note(code_key).await.unwrap());
+        assert!(store.delete_note(key).await.unwrap());
+        assert!(!store.delete_note(key).await.unwrap());
+        assert_eq!(store.chunk_count().await.unwrap(), 1);
     }
 
     #[tokio::test]
-    async fn find_by_symbol_is_case_insensitive() {
+    async fn reopening_repairs_orphans() {
         let tmp = TempDir::new().unwrap();
-        let store = VectorStore::new(tmp.path().to_str().unwrap())
+        let path = tmp.path().to_str().unwrap();
+        let store = VectorStore::new(path, &Default::default()).await.unwrap();
+        store
+            .insert(vec![
+                sample_row("a.go", 0, "func alpha() {}", "go", make_vector(1.0)),
+                sample_row("b.go", 0, "func beta() {}", "go", make_vector(2.0)),
+            ])
             .await
             .unwrap();
+        assert!(store.repair_summary().is_clean());
+        drop(store);
 
-        let rows = vec![ChunkRow {
-            file_path: "handler.go".to_string(),
-            chunk_id: 0,
-            content: "func HandleRequest() {}".to_string(),
-            symbol_name: Some("HandleRequest".to_string()),
-            symbol_kind: Some("func".to_string()),
-            language: "go".to_string(),
-            start_line: 1,
-            end_line: 1,
-            last_modified: 1700000000,
-            vector: make_vector(1.0),
-        }];
-        store.insert(rows).await.unwrap();
+        // Simulate a crash that lost one chunk's metadata and another's vector
+        let meta_path = tmp.path().join(META_FILE);
+        let mut meta: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&meta_path).unwrap()).unwrap();
+        let chunks = meta["chunks"].as_object_mut().unwrap();
+        let beta = chunks.remove("1").unwrap();
+        chunks.insert("7".to_string(), beta);
+        std::fs::write(&meta_path, meta.to_string()).unwrap();
+
+        let ro = VectorStore::open_read_only(path, &Default::default())
+            .await
+            .unwrap();
+        assert_eq!(ro.chunk_count().await.unwrap(), 1);
+        drop(ro);
 
+        let store = VectorStore::new(path, &Default::default()).await.unwrap();
+        assert_eq!(
+            store.repair_summary(),
+            RepairSummary {
+                chunks_without_vectors: 1,
+                vectors_without_chunks: 1,
+            }
+        );
+        assert_eq!(store.chunk_count().await.unwrap(), 1);
         let results = store
-            .find_by_symbol("handlerequest", None, 10)
+            .search(&make_vector(2.0), 5, Filters::default(), None)
             .await
             .unwrap();
-        assert_eq!(results.len(), 1);
-        assert_eq!(results[0].symbol_name.as_deref(), Some("HandleRequest"));
+        assert!(results.iter().all(|r| r.file_path == "a.go"));
+        assert!(
+            store
+                .find_by_symbol(SymbolQuery::named("symbol_0"), 5)
+                .await
+                .unwrap()
+                .len()
+                == 1
+        );
+        drop(store);
+
+        let store = VectorStore::new(path, &Default::default()).await.unwrap();
+        assert!(store.repair_summary().is_clean());
     }
 
     #[tokio::test]
-    async fn find_by_symbol_with_kind_filter() {
+    async fn settings_follow_config() {
         let tmp = TempDir::new().unwrap();
-        let store = VectorStore::new(tmp.path().to_str().unwrap())
+        let config = IndexConfig {
+            metric: Metric::Ip,
+            connectivity: 32,
+            expansion_add: 200,
+            expansion_search: 100,
+            ..Default::default()
+        };
+        let store = VectorStore::new(tmp.path().to_str().unwrap(), &config)
             .await
             .unwrap();
+        let settings = store.settings().await;
+        assert_eq!(settings.backend, Backend::Usearch);
+        assert_eq!(settings.metric, Metric::Ip);
+        assert_eq!(settings.dimensions, EMBEDDING_DIM);
+        assert_eq!(settings.connectivity, 32);
+        assert_eq!(settings.expansion_search, 100);
+    }
 
-        let rows = vec![
-            ChunkRow {
-                file_path: "types.go".to_string(),
-                chunk_id: 0,
-                content: "type Server struct {}".to_string(),
-                symbol_name: Some("Server".to_string()),
-                symbol_kind: Some("type".to_string()),
-                language: "go".to_string(),
-                start_line: 1,
-                end_line: 1,
-                last_modified: 1700000000,
-                vector: make_vector(1.0),
-            },
-            ChunkRow {
-                file_path: "funcs.go".to_string(),
-                chunk_id: 0,
-                content: "func NewServer() {}".to_string(),
-                symbol_name: Some("NewServer".to_string()),
-                symbol_kind: Some("func".to_string()),
-                language: "go".to_string(),
-                start_line: 1,
-                end_line: 1,
-                last_modified: 1700000000,
-                vector: make_vector(2.0),
-            },
-        ];
-        store.insert(rows).await.unwrap();
+    #[tokio::test]
+    async fn truncated_dimensions_store_and_search() {
+        let tmp = TempDir::new().unwrap();
+        let path = tmp.path().to_str().unwrap();
+        let config = IndexConfig {
+            dimensions: 64,
+            ..Default::default()
+        };
+        let store = VectorStore::new(path, &config).await.unwrap();
+        assert_eq!(store.settings().await.dimensions, 64);
+        store
+            .insert(vec![
+                sample_row("a.go", 0, "func a() {}", "go", make_vector(1.0)),
+                sample_row("b.go", 0, "func b() {}", "go", make_vector(2.0)),
+            ])
+            .await
+            .unwrap();
 
+        // Queries arrive full-size and are truncated the same way
         let results = store
-            .find_by_symbol("Server", Some("type"), 10)
+            .search(&make_vector(2.0), 1, Filters::default(), None)
             .await
             .unwrap();
-        assert_eq!(results.len(), 1);
-        assert_eq!(results[0].symbol_kind.as_deref(), Some("type"));
+        assert_eq!(results[0].file_path, "b.go");
+        drop(store);
+
+        let fail = IndexConfig {
+            on_mismatch: OnMismatch::Fail,
+            ..Default::default()
+        };
+        let err = VectorStore::new(path, &fail).await.err().unwrap();
+        let msg = err.to_string();
+        assert!(msg.contains("stores 64-dimensional vectors"), "{msg}");
+        assert!(msg.contains("on_mismatch = \"rebuild\""), "{msg}");
+
+        let config = IndexConfig {
+            dimensions: EMBEDDING_DIM + 1,
+            ..Default::default()
+        };
+        assert!(VectorStore::new(path, &config).await.is_err());
     }
 
     #[tokio::test]
-    async fn find_by_symbol_empty_on_no_match() {
+    async fn reopening_with_another_metric_fails() {
         let tmp = TempDir::new().unwrap();
-        let store = VectorStore::new(tmp.path().to_str().unwrap())
+        let path = tmp.path().to_str().unwrap();
+        let store = VectorStore::new(path, &Default::default()).await.unwrap();
+        store
+            .insert(vec![sample_row(
+                "a.go",
+                0,
+                "func a() {}",
+                "go",
+                make_vector(1.0),
+            )])
             .await
             .unwrap();
+        drop(store);
 
-        let rows = vec![sample_row(
-            "a.go",
-            0,
-            "func alpha() {}",
-            "go",
-            make_vector(1.0),
-        )];
-        store.insert(rows).await.unwrap();
+        let config = IndexConfig {
+            metric: Metric::L2sq,
+            on_mismatch: OnMismatch::Fail,
+            ..Default::default()
+        };
+        let err = VectorStore::new(path, &config).await.err().unwrap();
+        let msg = err.to_string();
+        assert!(msg.contains("built with the cos metric"), "{msg}");
+        assert!(msg.contains("[index] metric is l2sq"), "{msg}");
 
-        let results = store.find_by_symbol("nonexistent", None, 10).await.unwrap();
-        assert!(results.is_empty());
+        // Nor is a read-only index rebuilt
+        let config = IndexConfig {
+            metric: Metric::L2sq,
+            ..Default::default()
+        };
+        let err = VectorStore::open_read_only(path, &config)
+            .await
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("without --read-only"), "{err}");
     }
 
     #[tokio::test]
-    async fn find_by_symbol_skips_null_names() {
+    async fn reopening_with_other_settings_rebuilds_from_stored_chunks() {
         let tmp = TempDir::new().unwrap();
-        let store = VectorStore::new(tmp.path().to_str().unwrap())
+        let path = tmp.path().to_str().unwrap();
+        let store = VectorStore::new(path, &Default::default()).await.unwrap();
+        store
+            .insert(vec![
+                sample_row("a.go", 0, "func a() {}", "go", make_vector(1.0)),
+                sample_row("b.go", 0, "func b() {}", "go", make_vector(2.0)),
+            ])
             .await
             .unwrap();
+        assert!(store.rebuild_status().await.is_none());
+        drop(store);
 
-        let rows = vec![ChunkRow {
-            file_path: "test.go".to_string(),
-            chunk_id: 0,
-            content: "package main".to_string(),
-            symbol_name: None,
-            symbol_kind: None,
-            language: "go".to_string(),
-            start_line: 1,
-            end_line: 1,
-            last_modified: 1700000000,
-            vector: make_vector(1.0),
-        }];
-        store.insert(rows).await.unwrap();
+        let config = IndexConfig {
+            metric: Metric::Ip,
+            dimensions: 64,
+            ..Default::default()
+        };
+        let store = VectorStore::new(path, &config).await.unwrap();
+        assert_eq!(store.settings().await.metric, Metric::Ip);
+        assert_eq!(store.chunk_count().await.unwrap(), 2);
+        let status = store.rebuild_status().await.unwrap();
+        assert_eq!(status.remaining, 2);
+        assert!(status.reason.contains("cos metric"), "{}", status.reason);
+        assert!(status.reason.contains("ip metric, 64"), "{}", status.reason);
 
-        let results = store.find_by_symbol("main", None, 10).await.unwrap();
-        assert!(results.is_empty());
-    }
+        // Survives a restart part way through
+        let batch = store.rebuild_batch(1).await;
+        assert_eq!(batch.len(), 1);
+        assert_eq!(batch[0].content, "func a() {}");
+        store
+            .replace_vectors(vec![(batch[0].key, make_vector(1.0))])
+            .await
+            .unwrap();
+        drop(store);
+        let store = VectorStore::new(path, &config).await.unwrap();
+        assert!(store.repair_summary().is_clean());
+        assert_eq!(store.rebuild_status().await.unwrap().remaining, 1);
+        let results = store
+            .search(&make_vector(2.0), 5, Filters::default(), None)
+            .await
+            .unwrap();
+        assert_eq!(results.len(), 1, "only rebuilt chunks are searchable");
 
-    // ---------------------------------------------------------------
-    // list_files tests
-    // ---------------------------------------------------------------
+        let batch = store.rebuild_batch(10).await;
+        let vectors = batch.iter().map(|c| (c.key, make_vector(2.0))).collect();
+        store.replace_vectors(vectors).await.unwrap();
+        assert!(store.rebuild_status().await.is_none());
+        let results = store
+            .search(&make_vector(2.0), 5, Filters::default(), None)
+            .await
+            .unwrap();
+        assert_eq!(results[0].file_path, "b.go");
+    }
 
     #[tokio::test]
-    async fn list_files_returns_unique_paths() {
+    async fn read_only_store_serves_searches() {
         let tmp = TempDir::new().unwrap();
-        let store = VectorStore::new(tmp.path().to_str().unwrap())
+        let path = tmp.path().to_str().unwrap();
+        let store = VectorStore::new(path, &Default::default()).await.unwrap();
+        store
+            .insert(vec![sample_row(
+                "a.go",
+                0,
+                "func a() {}",
+                "go",
+                make_vector(1.0),
+            )])
             .await
             .unwrap();
+        drop(store);
 
-        let rows = vec![
-            sample_row("main.go", 0, "package main", "go", make_vector(1.0)),
-            sample_row("main.go", 1, "func main() {}", "go", make_vector(2.0)),
-            sample_row("util.go", 0, "func util() {}", "go", make_vector(3.0)),
-            sample_row("server.go", 0, "func serve() {}", "go", make_vector(4.0)),
-        ];
-        store.insert(rows).await.unwrap();
-
-        let files = store.list_files(None).await.unwrap();
-        assert_eq!(files.len(), 3);
-        assert!(files.contains(&"main.go".to_string()));
-        assert!(files.contains(&"util.go".to_string()));
-        assert!(files.contains(&"server.go".to_string()));
+        let ro = VectorStore::open_read_only(path, &Default::default())
+            .await
+            .unwrap();
+        assert!(ro.is_read_only());
+        assert_eq!(ro.chunk_count().await.unwrap(), 1);
+        let results = ro
+            .search(&make_vector(1.0), 1, Filters::default(), None)
+            .await
+            .unwrap();
+        assert_eq!(results[0].file_path, "a.go");
     }
 
     #[tokio::test]
-    async fn list_files_sorted() {
+    async fn read_only_store_rejects_writes() {
         let tmp = TempDir::new().unwrap();
-        let store = VectorStore::new(tmp.path().to_str().unwrap())
+        let path = tmp.path().to_str().unwrap();
+        let store = VectorStore::new(path, &Default::default()).await.unwrap();
+        store
+            .insert(vec![sample_row(
+                "a.go",
+                0,
+                "func a() {}",
+                "go",
+                make_vector(1.0),
+            )])
             .await
             .unwrap();
+        let before = std::fs::read(tmp.path().join(META_FILE)).unwrap();
 
-        let rows = vec![
-            sample_row("z.go", 0, "func z() {}", "go", make_vector(1.0)),
-            sample_row("a.go", 0, "func a() {}", "go", make_vector(2.0)),
-            sample_row("m.go", 0, "func m() {}", "go", make_vector(3.0)),
-        ];
-        store.insert(rows).await.unwrap();
+        let ro = VectorStore::open_read_only(path, &Default::default())
+            .await
+            .unwrap();
+        let err = ro.delete_file("a.go").await.unwrap_err();
+        assert!(matches!(err, Error::ReadOnly(_)), "{err}");
+        let err = ro
+            .insert(vec![sample_row(
+                "b.go",
+                0,
+                "func b() {}",
+                "go",
+                make_vector(2.0),
+            )])
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::ReadOnly(_)), "{err}");
 
-        let files = store.list_files(None).await.unwrap();
-        assert_eq!(files, vec!["a.go", "m.go", "z.go"]);
+        assert_eq!(std::fs::read(tmp.path().join(META_FILE)).unwrap(), before);
+        assert_eq!(ro.chunk_count().await.unwrap(), 1);
     }
 
     #[tokio::test]
-    async fn list_files_with_language_filter() {
+    async fn read_only_store_reloads_the_writers_saves() {
         let tmp = TempDir::new().unwrap();
-        let store = VectorStore::new(tmp.path().to_str().unwrap())
+        let path = tmp.path().to_str().unwrap();
+        let store = VectorStore::new(path, &Default::default()).await.unwrap();
+        let row = |file: &str, seed| sample_row(file, 0, file, "go", make_vector(seed));
+        store.insert(vec![row("a.go", 1.0)]).await.unwrap();
+
+        let ro = VectorStore::open_read_only(path, &Default::default())
             .await
             .unwrap();
+        let saved = ro.saved_at();
+        assert!(saved.is_some());
+        store.insert(vec![row("b.go", 2.0)]).await.unwrap();
+        assert_eq!(ro.chunk_count().await.unwrap(), 1);
 
-        let rows = vec![
-            sample_row("main.go", 0, "func main() {}", "go", make_vector(1.0)),
-            sample_row("lib.rs", 0, "fn lib() {}", "rust", make_vector(2.0)),
-        ];
-        store.insert(rows).await.unwrap();
-
-        let go_files = store.list_files(Some("go")).await.unwrap();
-        assert_eq!(go_files, vec!["main.go"]);
+        ro.reload(&Default::default()).await.unwrap();
+        assert_eq!(ro.chunk_count().await.unwrap(), 2);
+        let results = ro
+            .search(&make_vector(2.0), 1, Filters::default(), None)
+            .await
+            .unwrap();
+        assert_eq!(results[0].file_path, "b.go");
+    }
 
-        let rust_files = store.list_files(Some("rust")).await.unwrap();
-        assert_eq!(rust_files, vec!["lib.rs"]);
+    #[tokio::test]
+    async fn read_only_store_requires_existing_index() {
+        let tmp = TempDir::new().unwrap();
+        let result =
+            VectorStore::open_read_only(tmp.path().to_str().unwrap(), &Default::default()).await;
+        assert!(result.is_err());
+        assert!(std::fs::read_dir(tmp.path()).unwrap().next().is_none());
     }
 
+    // ---------------------------------------------------------------
+    // token_stats tests
+    // ---------------------------------------------------------------
+
     #[tokio::test]
-    async fn list_files_empty_store() {
+    async fn token_stats_counts_truncated_chunks() {
         let tmp = TempDir::new().unwrap();
-        let store = VectorStore::new(tmp.path().to_str().unwrap())
+        let store = VectorStore::new(tmp.path().to_str().unwrap(), &Default::default())
             .await
             .unwrap();
 
-        let files = store.list_files(None).await.unwrap();
-        assert!(files.is_empty());
+        let counts = [Some(10), Some(MAX_SEQ_LEN), Some(MAX_SEQ_LEN + 1), None];
+        let rows: Vec<ChunkRow> = counts
+            .iter()
+            .enumerate()
+            .map(|(i, &count)| ChunkRow {
+                token_count: count,
+                comment_ratio: 0.0,
+                ..sample_row("a.go", i as i64, "func a() {}", "go", make_vector(i as f32))
+            })
+            .collect();
+        store.insert(rows).await.unwrap();
+
+        let stats = store.token_stats().await.unwrap();
+        assert_eq!(
+            stats,
+            TokenStats {
+                measured: 3,
+                truncated: 1
+            }
+        );
     }
 
     // ---------------------------------------------------------------
@@ -872,7 +6026,7 @@ mod tests {
         let path = tmp.path().to_str().unwrap();
 
         {
-            let store = VectorStore::new(path).await.unwrap();
+            let store = VectorStore::new(path, &Default::default()).await.unwrap();
             let rows = vec![
                 sample_row("a.go", 0, "func a() {}", "go", make_vector(1.0)),
                 sample_row("b.go", 0, "func b() {}", "go", make_vector(2.0)),
@@ -882,12 +6036,240 @@ mod tests {
         }
 
         // Reopen the store from the same path
-        let store = VectorStore::new(path).await.unwrap();
+        let store = VectorStore::new(path, &Default::default()).await.unwrap();
         assert_eq!(store.chunk_count().await.unwrap(), 2);
 
         // Search should still work
-        let results = store.search(&make_vector(1.0), 1, None).await.unwrap();
+        let results = store
+            .search(&make_vector(1.0), 1, Filters::default(), None)
+            .await
+            .unwrap();
         assert_eq!(results.len(), 1);
         assert_eq!(results[0].content, "func a() {}");
     }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn reads_and_writes_interleave_without_deadlock() {
+        let tmp = TempDir::new().unwrap();
+        let store = VectorStore::new(tmp.path().to_str().unwrap(), &Default::default())
+            .await
+            .unwrap();
+
+        let mut tasks = Vec::new();
+        for writer in 0..4 {
+            let store = store.clone();
+            tasks.push(tokio::spawn(async move {
+                for i in 0..25 {
+                    let path = format!("w{writer}/f{i}.go");
+                    let content = format!("func F{writer}x{i}() {{}}");
+                    let seed = (writer * 25 + i) as f32;
+                    let row = sample_row(&path, 0, &content, "go", make_vector(seed));
+                    store.replace_file(&path, vec![row]).await.unwrap();
+                    if i % 5 == 0 {
+                        store.delete_file(&path).await.unwrap();
+                    }
+                    if i % 10 == 0 {
+                        store.set_last_commit(&path).await.unwrap();
+                    }
+                }
+            }));
+        }
+        for reader in 0..4 {
+            let store = store.clone();
+            tasks.push(tokio::spawn(async move {
+                let query = make_vector(reader as f32);
+                for _ in 0..50 {
+                    store
+                        .search(&query, 5, Filters::default(), None)
+                        .await
+                        .unwrap();
+                    store.search_files(&query, 5, None).await.unwrap();
+                    store.chunk_count().await.unwrap();
+                    store.list_files(None).await.unwrap();
+                    store
+                        .find_by_symbol(SymbolQuery::named("F"), 5)
+                        .await
+                        .unwrap();
+                }
+            }));
+        }
+
+        let all = async {
+            for task in tasks {
+                task.await.unwrap();
+            }
+        };
+        tokio::time::timeout(std::time::Duration::from_secs(60), all)
+            .await
+            .expect("readers and writers deadlocked");
+        // Of each writer's 25 files, the 5 deleted right after are gone
+        assert_eq!(store.chunk_count().await.unwrap(), 80);
+    }
+
+    /// A backend whose saves wait until the test lets them go.
+    struct HeldSave {
+        inner: Box<dyn VectorStoreBackend>,
+        started: std::sync::mpsc::Sender<()>,
+        release: std::sync::Mutex<std::sync::mpsc::Receiver<()>>,
+    }
+
+    impl VectorStoreBackend for HeldSave {
+        fn insert(&mut self, points: &[(u64, &[f32], &ChunkMeta)]) -> Result<()> {
+            self.inner.insert(points)
+        }
+
+        fn search(
+            &self,
+            vector: &[f32],
+            limit: usize,
+            filter: &SearchFilter<'_>,
+        ) -> Result<Vec<(u64, f32)>> {
+            self.inner.search(vector, limit, filter)
+        }
+
+        fn search_exact(
+            &self,
+            vector: &[f32],
+            limit: usize,
+            filter: &SearchFilter<'_>,
+        ) -> Result<Vec<(u64, f32)>> {
+            self.inner.search_exact(vector, limit, filter)
+        }
+
+        fn hold_vectors(&mut self, keys: &[u64], max: usize) -> Result<()> {
+            self.inner.hold_vectors(keys, max)
+        }
+
+        fn delete(&mut self, keys: &[u64]) -> Result<()> {
+            self.inner.delete(keys)
+        }
+
+        fn vectors(&self, keys: &[u64]) -> Result<Vec<(u64, Vec<f32>)>> {
+            self.inner.vectors(keys)
+        }
+
+        fn list(&self) -> Result<Option<HashMap<u64, ChunkMeta>>> {
+            self.inner.list()
+        }
+
+        fn keys(&self, next_key: u64) -> Result<Option<HashSet<u64>>> {
+            self.inner.keys(next_key)
+        }
+
+        fn save(&self, dir: &Path) -> Result<()> {
+            self.started.send(()).unwrap();
+            self.release.lock().unwrap().recv().unwrap();
+            self.inner.save(dir)
+        }
+
+        fn settings(&self) -> IndexSettings {
+            self.inner.settings()
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn lookups_go_ahead_while_a_commit_saves() {
+        let tmp = TempDir::new().unwrap();
+        let config = IndexConfig::default();
+        let store = VectorStore::new(tmp.path().to_str().unwrap(), &config)
+            .await
+            .unwrap();
+        store
+            .insert(vec![sample_row(
+                "a.rs",
+                0,
+                "fn a() {}",
+                "rust",
+                make_vector(1.0),
+            )])
+            .await
+            .unwrap();
+        let (started, saving) = std::sync::mpsc::channel();
+        let (release, released) = std::sync::mpsc::channel();
+        {
+            let mut backend = store.backend.write().await;
+            let empty = hnsw::HnswBackend::empty(&config, EMBEDDING_DIM).unwrap();
+            let inner = std::mem::replace(&mut *backend, Box::new(empty));
+            *backend = Box::new(HeldSave {
+                inner,
+                started,
+                release: std::sync::Mutex::new(released),
+            });
+        }
+
+        let committing = tokio::spawn({
+            let store = store.clone();
+            async move {
+                let mut transaction = store.transaction();
+                transaction.insert(vec![sample_row(
+                    "b.rs",
+                    0,
+                    "fn b() {}",
+                    "rust",
+                    make_vector(2.0),
+                )]);
+                transaction.commit().await
+            }
+        });
+        tokio::task::spawn_blocking(move || saving.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        let count = tokio::time::timeout(std::time::Duration::from_secs(10), store.chunk_count())
+            .await
+            .expect("chunk_count waited for the commit")
+            .unwrap();
+        // The commit's writes are in; only its save is left
+        assert_eq!(count, 2);
+        assert!(!committing.is_finished());
+
+        release.send(()).unwrap();
+        committing.await.unwrap().unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn searches_wait_for_a_turn_holding_no_lock() {
+        let mut store = VectorStore::in_memory(&Default::default()).unwrap();
+        let searches = Arc::new(Semaphore::new(1));
+        store.searches = Some(Arc::clone(&searches));
+        store
+            .insert(vec![sample_row(
+                "a.rs",
+                0,
+                "fn a() {}",
+                "rust",
+                make_vector(1.0),
+            )])
+            .await
+            .unwrap();
+
+        let turn = searches.acquire().await.unwrap();
+        let waiting = tokio::spawn({
+            let store = store.clone();
+            async move {
+                store
+                    .search(&make_vector(1.0), 5, Filters::default(), None)
+                    .await
+            }
+        });
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        assert!(!waiting.is_finished());
+        // Writers get by the waiting search
+        let wrote = store.insert(vec![sample_row(
+            "b.rs",
+            0,
+            "fn b() {}",
+            "rust",
+            make_vector(2.0),
+        )]);
+        tokio::time::timeout(std::time::Duration::from_secs(10), wrote)
+            .await
+            .expect("a search waiting for a turn blocked a writer")
+            .unwrap();
+
+        drop(turn);
+        assert_eq!(waiting.await.unwrap().unwrap().len(), 2);
+        // The turn is given back
+        assert_eq!(searches.available_permits(), 1);
+    }
 }
@@ -0,0 +1,112 @@
+//! Chunks with identical text, stored once.
+//!
+//! Copied files, vendored packages, and generated code repeat chunks word
+//! for word. The first copy indexed keeps the vector; each later one keeps
+//! its own location but no vector, recorded as a duplicate of the first,
+//! and a search that finds the first lists every copy. When the copy
+//! holding the vector is removed, the vector passes to one that is left.
+
+use std::collections::{BTreeSet, HashMap};
+use std::hash::{Hash, Hasher};
+
+use super::ChunkMeta;
+use crate::config::Namespace;
+
+/// Fingerprint of what a chunk's vector is embedded from. The namespace,
+/// language, and doc are part of it, since each changes the text embedded
+/// or where the chunk may be searched. `DefaultHasher` may change between
+/// Rust releases; if it does, new chunks only stop matching older ones.
+pub(super) fn content_hash(
+    namespace: Namespace,
+    language: &str,
+    content: &str,
+    doc: Option<&str>,
+) -> u64 {
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    namespace.hash(&mut hasher);
+    language.hash(&mut hasher);
+    content.hash(&mut hasher);
+    doc.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Which chunk holds the vector for each text, and which chunks are its
+/// duplicates. Rebuilt from the chunks on load rather than persisted.
+#[derive(Default, Clone)]
+pub(super) struct Copies {
+    /// The chunk holding the vector, by content hash.
+    primaries: HashMap<u64, u64>,
+    /// The duplicates of each chunk that has any.
+    duplicates: HashMap<u64, BTreeSet<u64>>,
+}
+
+impl Copies {
+    pub(super) fn build(chunks: &HashMap<u64, ChunkMeta>) -> Self {
+        let mut copies = Self::default();
+        for (&key, chunk) in chunks {
+            copies.add(key, chunk);
+        }
+        copies
+    }
+
+    /// The chunk holding the vector for text with `hash`, if one does.
+    pub(super) fn primary(&self, hash: u64) -> Option<u64> {
+        self.primaries.get(&hash).copied()
+    }
+
+    /// The duplicates of the chunk stored under `key`, in key order.
+    pub(super) fn duplicates(&self, key: u64) -> impl Iterator<Item = u64> + '_ {
+        self.duplicates.get(&key).into_iter().flatten().copied()
+    }
+
+    /// Chunks stored without a vector of their own.
+    pub(super) fn duplicate_count(&self) -> usize {
+        self.duplicates.values().map(BTreeSet::len).sum()
+    }
+
+    /// Record the chunk stored under `key`.
+    pub(super) fn add(&mut self, key: u64, chunk: &ChunkMeta) {
+        match (chunk.duplicate_of, chunk.content_hash) {
+            (Some(primary), _) => {
+                self.duplicates.entry(primary).or_default().insert(key);
+            }
+            (None, Some(hash)) => {
+                self.primaries.entry(hash).or_insert(key);
+            }
+            (None, None) => {}
+        }
+    }
+
+    /// Forget the chunk stored under `key`. A chunk holding a vector
+    /// should have its duplicates handed to another with [`Self::promote`]
+    /// first.
+    pub(super) fn remove(&mut self, key: u64, chunk: &ChunkMeta) {
+        if let Some(primary) = chunk.duplicate_of {
+            if let Some(duplicates) = self.duplicates.get_mut(&primary) {
+                duplicates.remove(&key);
+                if duplicates.is_empty() {
+                    self.duplicates.remove(&primary);
+                }
+            }
+            return;
+        }
+        if let Some(hash) = chunk.content_hash
+            && self.primaries.get(&hash) == Some(&key)
+        {
+            self.primaries.remove(&hash);
+        }
+        self.duplicates.remove(&key);
+    }
+
+    /// Make `new`, a duplicate of `old`, the chunk holding the vector for
+    /// text with `hash`, with `duplicates`, those of `old`'s being kept,
+    /// now its own.
+    pub(super) fn promote(&mut self, old: u64, new: u64, hash: u64, duplicates: &[u64]) {
+        self.duplicates.remove(&old);
+        if !duplicates.is_empty() {
+            self.duplicates
+                .insert(new, duplicates.iter().copied().collect());
+        }
+        self.primaries.insert(hash, new);
+    }
+}
@@ -0,0 +1,310 @@
+//! The default backend: a usearch HNSW index saved next to the metadata.
+
+use std::collections::{HashMap, HashSet};
+use std::num::NonZero;
+use std::path::Path;
+
+use tokio::sync::Semaphore;
+use usearch::{Index, IndexOptions, MetricKind, ScalarKind};
+
+use super::{
+    ChunkMeta, INDEX_FILE, IndexSettings, META_FILE, SearchFilter, VectorStoreBackend, distance,
+};
+use crate::config::{Backend, IndexConfig, Metric};
+use crate::error::{Error, Result};
+
+/// A usearch index. Chunk metadata lives only in the local sidecar.
+pub(super) struct HnswBackend {
+    index: Index,
+    metric: Metric,
+    /// Copies of the vectors for exact search, when asked to hold them.
+    held: Option<Held>,
+}
+
+/// Turns to search a usearch index: it keeps one search context per
+/// hardware thread and fails a search that finds them all taken, so
+/// further searches wait for a permit.
+pub(super) fn search_slots() -> Semaphore {
+    Semaphore::new(std::thread::available_parallelism().map_or(1, NonZero::get))
+}
+
+/// Vectors kept in one contiguous block, row `i` belonging to `keys[i]`.
+struct Held {
+    dimensions: usize,
+    /// Dropped once there are more keys than this.
+    max: usize,
+    keys: Vec<u64>,
+    rows: HashMap<u64, usize>,
+    values: Vec<f32>,
+}
+
+impl Held {
+    fn push(&mut self, key: u64, vector: &[f32]) {
+        if let Some(&row) = self.rows.get(&key) {
+            let start = row * self.dimensions;
+            self.values[start..start + self.dimensions].copy_from_slice(vector);
+            return;
+        }
+        self.rows.insert(key, self.keys.len());
+        self.keys.push(key);
+        self.values.extend_from_slice(vector);
+    }
+
+    /// Remove `key`'s row, moving the last row into its place.
+    fn remove(&mut self, key: u64) {
+        let Some(row) = self.rows.remove(&key) else {
+            return;
+        };
+        let last = self.keys.len() - 1;
+        if row != last {
+            let moved = self.keys[last];
+            self.keys[row] = moved;
+            self.rows.insert(moved, row);
+            self.values
+                .copy_within(last * self.dimensions.., row * self.dimensions);
+        }
+        self.keys.truncate(last);
+        self.values.truncate(last * self.dimensions);
+    }
+
+    fn iter(&self) -> impl Iterator<Item = (u64, &[f32])> {
+        self.keys
+            .iter()
+            .copied()
+            .zip(self.values.chunks_exact(self.dimensions))
+    }
+}
+
+// usearch::Index contains raw C++ pointers that aren't Send/Sync in Rust,
+// but the underlying C++ library is thread-safe for concurrent reads and
+// exclusive writes -- which VectorStore enforces via RwLock.
+unsafe impl Send for HnswBackend {}
+unsafe impl Sync for HnswBackend {}
+
+impl HnswBackend {
+    /// Open the index of `dimensions`-sized vectors in `dir`, creating an
+    /// empty one if there is none. A read-only index is memory-mapped rather
+    /// than loaded and must exist.
+    pub(super) fn open(
+        dir: &Path,
+        config: &IndexConfig,
+        dimensions: usize,
+        read_only: bool,
+    ) -> Result<Self> {
+        let index_path = dir.join(INDEX_FILE);
+        let backend = Self::empty(config, dimensions)?;
+        let index = &backend.index;
+
+        // Map a read-only index in place; otherwise load it if present
+        if read_only {
+            if !index_path.exists() || !dir.join(META_FILE).exists() {
+                return Err(Error::StoreIndex(format!(
+                    "no index found at {} -- build one without --read-only or install one with `claudevil import`",
+                    dir.display()
+                )));
+            }
+            index
+                .view(index_path.to_str().unwrap_or_default())
+                .map_err(|e| Error::StoreIndex(e.to_string()))?;
+        } else if index_path.exists() {
+            index
+                .load(index_path.to_str().unwrap_or_default())
+                .map_err(|e| Error::StoreIndex(e.to_string()))?;
+        }
+
+        // Loading restores the saved expansion factors; configured ones win
+        if config.expansion_add > 0 {
+            index.change_expansion_add(config.expansion_add);
+        }
+        if config.expansion_search > 0 {
+            index.change_expansion_search(config.expansion_search);
+        }
+        Ok(backend)
+    }
+
+    /// An empty index of `dimensions`-sized vectors, held in memory until
+    /// saved.
+    pub(super) fn empty(config: &IndexConfig, dimensions: usize) -> Result<Self> {
+        let opts = IndexOptions {
+            dimensions,
+            metric: match config.metric {
+                Metric::Cos => MetricKind::Cos,
+                Metric::Ip => MetricKind::IP,
+                Metric::L2sq => MetricKind::L2sq,
+            },
+            quantization: ScalarKind::F32,
+            connectivity: config.connectivity,
+            expansion_add: config.expansion_add,
+            expansion_search: config.expansion_search,
+            ..Default::default()
+        };
+
+        let index = Index::new(&opts).map_err(|e| Error::StoreIndex(e.to_string()))?;
+        Ok(Self {
+            index,
+            metric: config.metric,
+            held: None,
+        })
+    }
+}
+
+impl VectorStoreBackend for HnswBackend {
+    fn insert(&mut self, points: &[(u64, &[f32], &ChunkMeta)]) -> Result<()> {
+        // Reserve space in the index for the new points
+        self.index
+            .reserve(self.index.size() + points.len())
+            .map_err(|e| Error::StoreIndex(e.to_string()))?;
+        for &(key, vector, _) in points {
+            self.index
+                .add(key, vector)
+                .map_err(|e| Error::StoreIndex(e.to_string()))?;
+            if let Some(held) = &mut self.held {
+                held.push(key, vector);
+            }
+        }
+        if let Some(held) = self.held.take_if(|held| held.keys.len() > held.max) {
+            tracing::info!(
+                "the index has grown past {} chunks; searching it approximately from now on",
+                held.max
+            );
+        }
+        Ok(())
+    }
+
+    fn search(
+        &self,
+        vector: &[f32],
+        limit: usize,
+        filter: &SearchFilter<'_>,
+    ) -> Result<Vec<(u64, f32)>> {
+        let matches = if filter.is_empty() {
+            self.index.search(vector, limit)
+        } else {
+            self.index
+                .filtered_search(vector, limit, |key| filter.allows(key))
+        }
+        .map_err(|e| Error::StoreIndex(e.to_string()))?;
+        Ok(matches.keys.into_iter().zip(matches.distances).collect())
+    }
+
+    fn search_exact(
+        &self,
+        vector: &[f32],
+        limit: usize,
+        filter: &SearchFilter<'_>,
+    ) -> Result<Vec<(u64, f32)>> {
+        let allowed = |key: u64| filter.is_empty() || filter.allows(key);
+        let mut hits: Vec<(u64, f32)> = match &self.held {
+            Some(held) => held
+                .iter()
+                .filter(|&(key, _)| allowed(key))
+                .map(|(key, stored)| (key, distance(self.metric, vector, stored)))
+                .collect(),
+            // Duplicates have no vectors of their own to score
+            None => {
+                let keys: Vec<u64> = filter
+                    .chunks
+                    .iter()
+                    .filter(|&(&key, chunk)| chunk.duplicate_of.is_none() && allowed(key))
+                    .map(|(&key, _)| key)
+                    .collect();
+                self.vectors(&keys)?
+                    .into_iter()
+                    .map(|(key, stored)| (key, distance(self.metric, vector, &stored)))
+                    .collect()
+            }
+        };
+        hits.sort_by(|a, b| a.1.total_cmp(&b.1));
+        hits.truncate(limit);
+        Ok(hits)
+    }
+
+    fn hold_vectors(&mut self, keys: &[u64], max: usize) -> Result<()> {
+        let dimensions = self.index.dimensions();
+        let mut held = Held {
+            dimensions,
+            max,
+            keys: Vec::with_capacity(keys.len()),
+            rows: HashMap::with_capacity(keys.len()),
+            values: Vec::with_capacity(keys.len() * dimensions),
+        };
+        for (key, vector) in self.vectors(keys)? {
+            held.push(key, &vector);
+        }
+        self.held = Some(held);
+        Ok(())
+    }
+
+    fn delete(&mut self, keys: &[u64]) -> Result<()> {
+        for &key in keys {
+            self.index
+                .remove(key)
+                .map_err(|e| Error::StoreIndex(e.to_string()))?;
+            if let Some(held) = &mut self.held {
+                held.remove(key);
+            }
+        }
+        Ok(())
+    }
+
+    fn vectors(&self, keys: &[u64]) -> Result<Vec<(u64, Vec<f32>)>> {
+        let mut vectors = Vec::with_capacity(keys.len());
+        for &key in keys {
+            let mut vector = vec![0.0f32; self.index.dimensions()];
+            let found = self
+                .index
+                .get(key, &mut vector)
+                .map_err(|e| Error::StoreIndex(e.to_string()))?;
+            if found > 0 {
+                vectors.push((key, vector));
+            }
+        }
+        Ok(vectors)
+    }
+
+    fn list(&self) -> Result<Option<HashMap<u64, ChunkMeta>>> {
+        Ok(None)
+    }
+
+    fn keys(&self, next_key: u64) -> Result<Option<HashSet<u64>>> {
+        // usearch can't enumerate its keys, so probe the range they were
+        // handed out from. Vectors added just before a crash may sit past
+        // `next_key` if the metadata recording them was never saved.
+        let size = self.index.size();
+        let limit = next_key.saturating_add(size as u64);
+        let mut keys = HashSet::with_capacity(size);
+        let mut key = 0;
+        while keys.len() < size && key < limit {
+            if self.index.contains(key) {
+                keys.insert(key);
+            }
+            key += 1;
+        }
+        Ok(Some(keys))
+    }
+
+    fn save(&self, dir: &Path) -> Result<()> {
+        // Saved beside the index and renamed over it, since a read-only
+        // server for the same directory may have it memory-mapped
+        let index_path = dir.join(INDEX_FILE);
+        let temp = crate::atomicfile::temp_path(&index_path);
+        self.index
+            .save(temp.to_str().unwrap_or_default())
+            .map_err(|e| Error::StoreIndex(e.to_string()))?;
+        std::fs::rename(&temp, &index_path).map_err(|e| Error::StoreIo {
+            context: format!("replacing {}", index_path.display()),
+            source: e,
+        })
+    }
+
+    fn settings(&self) -> IndexSettings {
+        IndexSettings {
+            backend: Backend::Usearch,
+            metric: self.metric,
+            dimensions: self.index.dimensions(),
+            connectivity: self.index.connectivity(),
+            expansion_add: self.index.expansion_add(),
+            expansion_search: self.index.expansion_search(),
+        }
+    }
+}
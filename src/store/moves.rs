@@ -0,0 +1,115 @@
+//! Files recently removed from the index, kept a while in case they moved.
+//!
+//! Moving a file, or a directory of them, looks to the indexer like
+//! removing each file and adding another with the same text. Embedding the
+//! new ones would take as long as indexing them afresh, so each removed
+//! file's chunks are kept with their vectors for [`MOVE_WINDOW`], and a new
+//! file whose chunks have the same text and lines takes them over, keys and
+//! vectors included.
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use super::dedup::content_hash;
+use super::{ChunkMeta, ChunkRow};
+
+/// How long a removed file waits to turn up under another path.
+const MOVE_WINDOW: Duration = Duration::from_secs(10 * 60);
+
+/// Most chunks kept, over all removed files; the oldest files go first.
+/// At 384 dimensions this holds about 30 MB of vectors.
+const MAX_KEPT_CHUNKS: usize = 20_000;
+
+/// A file's chunks as they were when it was removed, in chunk order, each
+/// with its key and vector.
+pub(super) struct RemovedFile {
+    pub file_path: String,
+    pub chunks: Vec<(u64, ChunkMeta, Vec<f32>)>,
+    removed_at: Instant,
+}
+
+impl RemovedFile {
+    pub fn new(file_path: &str, mut chunks: Vec<(u64, ChunkMeta, Vec<f32>)>) -> Self {
+        chunks.sort_by_key(|(_, chunk, _)| chunk.chunk_id);
+        Self {
+            file_path: file_path.to_string(),
+            chunks,
+            removed_at: Instant::now(),
+        }
+    }
+
+    /// Whether `rows`, the chunks of a new file, are this file's.
+    fn matches(&self, rows: &[ChunkRow]) -> bool {
+        self.chunks.len() == rows.len()
+            && self
+                .chunks
+                .iter()
+                .zip(rows)
+                .all(|((_, old, _), row)| same_chunk(old, row))
+    }
+}
+
+/// Whether `row` has the text and place in its file that `old` had. Chunks
+/// stored as offsets keep only a hash of their text, and without
+/// deduplication not even that; those never match.
+fn same_chunk(old: &ChunkMeta, row: &ChunkRow) -> bool {
+    let same_text = if !old.content.is_empty() {
+        old.content == row.content && old.doc == row.doc
+    } else {
+        old.content_hash.is_some_and(|hash| {
+            hash == content_hash(
+                old.namespace,
+                &old.language,
+                &row.content,
+                row.doc.as_deref(),
+            )
+        })
+    };
+    same_text
+        && old.chunk_id == row.chunk_id
+        && old.start_line == row.start_line
+        && old.end_line == row.end_line
+        && old.language == row.language
+}
+
+/// Removed files, oldest first.
+#[derive(Default)]
+pub(super) struct RemovedFiles {
+    files: VecDeque<RemovedFile>,
+    chunks: usize,
+}
+
+impl RemovedFiles {
+    pub fn push(&mut self, file: RemovedFile) {
+        if file.chunks.is_empty() || file.chunks.len() > MAX_KEPT_CHUNKS {
+            return;
+        }
+        self.chunks += file.chunks.len();
+        self.files.push_back(file);
+        while self.chunks > MAX_KEPT_CHUNKS {
+            self.pop_oldest();
+        }
+    }
+
+    /// The removed file whose chunks `rows` are, taken out, if one was
+    /// removed in the last [`MOVE_WINDOW`].
+    pub fn take(&mut self, rows: &[ChunkRow]) -> Option<RemovedFile> {
+        while self
+            .files
+            .front()
+            .is_some_and(|file| file.removed_at.elapsed() > MOVE_WINDOW)
+        {
+            self.pop_oldest();
+        }
+        let i = self.files.iter().position(|file| file.matches(rows))?;
+        let file = self.files.remove(i)?;
+        self.chunks -= file.chunks.len();
+        Some(file)
+    }
+
+    fn pop_oldest(&mut self) {
+        if let Some(file) = self.files.pop_front() {
+            self.chunks -= file.chunks.len();
+        }
+    }
+}
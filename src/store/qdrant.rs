@@ -0,0 +1,547 @@
+//! A backend that keeps chunks in a Qdrant collection over its REST API.
+//!
+//! Each point carries the chunk's metadata as its payload, so every server
+//! pointed at the same collection sees the same index; a team can run one
+//! indexer and any number of `--read-only` servers against it.
+
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::time::Duration;
+
+use serde_json::{Value, json};
+
+use super::{ChunkMeta, IndexSettings, Modified, SearchFilter, Space, VectorStoreBackend};
+use crate::config::{Backend, IndexConfig, Metric, Namespace};
+use crate::error::{Error, Result};
+
+/// Environment variable holding the Qdrant API key, if the server needs one.
+const API_KEY_ENV: &str = "QDRANT_API_KEY";
+
+/// How long to wait for any one Qdrant request.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Points fetched per page when listing the collection.
+const SCROLL_PAGE: usize = 1000;
+
+/// A Qdrant collection holding vectors and chunk metadata.
+pub(super) struct QdrantBackend {
+    agent: ureq::Agent,
+    /// `<url>/collections/<name>`.
+    collection_url: String,
+    api_key: Option<String>,
+    metric: Metric,
+    dimensions: usize,
+    connectivity: usize,
+    expansion_add: usize,
+    expansion_search: usize,
+}
+
+/// Qdrant's name for a metric.
+fn distance_name(metric: Metric) -> &'static str {
+    match metric {
+        Metric::Cos => "Cosine",
+        Metric::Ip => "Dot",
+        Metric::L2sq => "Euclid",
+    }
+}
+
+/// Convert a Qdrant score to the distance usearch would report, so ranking
+/// boosts behave the same on either backend. Cosine and dot scores are
+/// similarities; Euclid scores are unsquared distances.
+fn score_to_distance(metric: Metric, score: f32) -> f32 {
+    match metric {
+        Metric::Cos | Metric::Ip => 1.0 - score,
+        Metric::L2sq => score * score,
+    }
+}
+
+/// Qdrant filter for `filter`, or `None` if it allows everything.
+fn filter_json(filter: &SearchFilter<'_>) -> Option<Value> {
+    let mut must = Vec::new();
+    if let Some(keys) = filter.keys {
+        must.push(json!({ "has_id": keys }));
+    }
+    if let Some(language) = filter.language {
+        must.push(json!({ "key": "language", "match": { "value": language } }));
+    }
+    if let Some(kind) = filter.kind {
+        must.push(json!({ "key": "symbol_kind", "match": { "value": kind } }));
+    }
+    if let Some(owner) = filter.owner {
+        must.push(json!({ "key": "owners", "match": { "value": owner } }));
+    }
+    if let Some(module) = filter.module {
+        must.push(json!({ "key": "module", "match": { "value": module } }));
+    }
+    match filter.space {
+        // Points without a model predate it and count as the current one's
+        Some(Space::Current(model)) => must.push(json!({ "should": [
+            { "key": "model", "match": { "value": model } },
+            { "is_empty": { "key": "model" } },
+        ] })),
+        Some(Space::Earlier(model)) => {
+            must.push(json!({ "key": "model", "match": { "value": model } }));
+        }
+        None => {}
+    }
+    let Modified { after, before } = filter.modified;
+    if after.is_some() || before.is_some() {
+        let mut range = serde_json::Map::new();
+        if let Some(after) = after {
+            range.insert("gte".to_string(), json!(after));
+        }
+        if let Some(before) = before {
+            range.insert("lt".to_string(), json!(before));
+        }
+        must.push(json!({ "key": "last_modified", "range": range }));
+    }
+    let mut must_not = Vec::new();
+    if let Some(platform) = filter.platform {
+        must_not.push(json!({ "key": "excluded_platforms", "match": { "value": platform } }));
+    }
+    // Points without the field predate namespaces and are mostly code, so
+    // a filter that lets code through excludes the other namespaces rather
+    // than requiring the allowed ones
+    if filter.namespaces.len() < Namespace::ALL.len() {
+        if filter.namespaces.contains(&Namespace::Code) {
+            let excluded: Vec<Namespace> = Namespace::ALL
+                .into_iter()
+                .filter(|n| !filter.namespaces.contains(n))
+                .collect();
+            must_not.push(json!({ "key": "namespace", "match": { "any": excluded } }));
+        } else {
+            must.push(json!({ "key": "namespace", "match": { "any": filter.namespaces } }));
+        }
+    }
+    match (must.is_empty(), must_not.is_empty()) {
+        (true, true) => None,
+        (false, true) => Some(json!({ "must": must })),
+        (true, false) => Some(json!({ "must_not": must_not })),
+        (false, false) => Some(json!({ "must": must, "must_not": must_not })),
+    }
+}
+
+impl QdrantBackend {
+    /// Connect to the configured collection of `dimensions`-sized vectors,
+    /// creating it unless `read_only`. An existing collection must use the
+    /// configured metric and size.
+    pub(super) fn connect(
+        config: &IndexConfig,
+        dimensions: usize,
+        read_only: bool,
+    ) -> Result<Self> {
+        let qdrant = &config.qdrant;
+        let mut backend = Self {
+            agent: ureq::AgentBuilder::new().timeout(REQUEST_TIMEOUT).build(),
+            collection_url: format!(
+                "{}/collections/{}",
+                qdrant.url.trim_end_matches('/'),
+                qdrant.collection
+            ),
+            api_key: std::env::var(API_KEY_ENV).ok(),
+            metric: config.metric,
+            dimensions,
+            connectivity: config.connectivity,
+            expansion_add: config.expansion_add,
+            expansion_search: config.expansion_search,
+        };
+
+        match backend.send("GET", "", None) {
+            Ok(response) => {
+                let info = backend.read_json("GET", "", response)?;
+                backend.check_collection(&info)?;
+            }
+            Err(e) if matches!(*e, ureq::Error::Status(404, _)) => {
+                if read_only {
+                    return Err(Error::StoreIndex(format!(
+                        "Qdrant collection {} does not exist -- build it with a server \
+                         running without --read-only",
+                        backend.collection_url
+                    )));
+                }
+                backend.create_collection()?;
+            }
+            Err(e) => return Err(backend.request_error("GET", "", e)),
+        }
+        Ok(backend)
+    }
+
+    /// Verify an existing collection matches the config and adopt its
+    /// HNSW parameters.
+    fn check_collection(&mut self, info: &Value) -> Result<()> {
+        let config = &info["result"]["config"];
+        let distance = config["params"]["vectors"]["distance"]
+            .as_str()
+            .unwrap_or_default();
+        if distance != distance_name(self.metric) {
+            return Err(Error::StoreIndex(format!(
+                "Qdrant collection {} uses {distance} distance, but [index] metric is {} -- \
+                 change the metric or point [index.qdrant] collection at a new collection",
+                self.collection_url, self.metric
+            )));
+        }
+        let size = config["params"]["vectors"]["size"].as_u64();
+        if size != Some(self.dimensions as u64) {
+            return Err(Error::StoreIndex(format!(
+                "Qdrant collection {} holds {}-dimensional vectors, but claudevil stores \
+                 {} dimensions -- set [index] dimensions to match or point [index.qdrant] \
+                 collection at a new collection",
+                self.collection_url,
+                size.unwrap_or_default(),
+                self.dimensions
+            )));
+        }
+        let hnsw = &config["hnsw_config"];
+        if let Some(m) = hnsw["m"].as_u64() {
+            self.connectivity = m as usize;
+        }
+        if let Some(ef) = hnsw["ef_construct"].as_u64() {
+            self.expansion_add = ef as usize;
+        }
+        Ok(())
+    }
+
+    fn create_collection(&self) -> Result<()> {
+        let mut hnsw = serde_json::Map::new();
+        if self.connectivity > 0 {
+            hnsw.insert("m".to_string(), json!(self.connectivity));
+        }
+        if self.expansion_add > 0 {
+            hnsw.insert("ef_construct".to_string(), json!(self.expansion_add));
+        }
+        let body = json!({
+            "vectors": { "size": self.dimensions, "distance": distance_name(self.metric) },
+            "hnsw_config": hnsw,
+        });
+        self.request("PUT", "", Some(body))?;
+        tracing::info!("created Qdrant collection {}", self.collection_url);
+        Ok(())
+    }
+
+    /// Send a request to `<collection_url><path>` and return the JSON reply.
+    fn request(&self, method: &str, path: &str, body: Option<Value>) -> Result<Value> {
+        match self.send(method, path, body) {
+            Ok(response) => self.read_json(method, path, response),
+            Err(e) => Err(self.request_error(method, path, e)),
+        }
+    }
+
+    fn send(
+        &self,
+        method: &str,
+        path: &str,
+        body: Option<Value>,
+    ) -> std::result::Result<ureq::Response, Box<ureq::Error>> {
+        let mut request = self
+            .agent
+            .request(method, &format!("{}{path}", self.collection_url));
+        if let Some(key) = &self.api_key {
+            request = request.set("api-key", key);
+        }
+        match body {
+            Some(body) => request.send_json(body),
+            None => request.call(),
+        }
+        .map_err(Box::new)
+    }
+
+    fn read_json(&self, method: &str, path: &str, response: ureq::Response) -> Result<Value> {
+        response.into_json().map_err(|e| {
+            Error::StoreIndex(format!(
+                "unreadable reply from Qdrant to {method} {}{path}: {e}",
+                self.collection_url
+            ))
+        })
+    }
+
+    /// The points nearest `vector` that `filter` allows, searched with
+    /// these search `params`.
+    fn search_points(
+        &self,
+        vector: &[f32],
+        limit: usize,
+        filter: &SearchFilter<'_>,
+        params: Option<Value>,
+    ) -> Result<Vec<(u64, f32)>> {
+        let mut body = json!({ "vector": vector, "limit": limit, "with_payload": false });
+        if let Some(filter) = filter_json(filter) {
+            body["filter"] = filter;
+        }
+        if let Some(params) = params {
+            body["params"] = params;
+        }
+        let reply = self.request("POST", "/points/search", Some(body))?;
+        Ok(reply["result"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|hit| {
+                let key = hit["id"].as_u64()?;
+                let score = hit["score"].as_f64()? as f32;
+                Some((key, score_to_distance(self.metric, score)))
+            })
+            .collect())
+    }
+
+    fn request_error(&self, method: &str, path: &str, err: Box<ureq::Error>) -> Error {
+        let url = format!("{}{path}", self.collection_url);
+        match *err {
+            ureq::Error::Status(code, response) => {
+                let detail = response.into_string().unwrap_or_default();
+                let hint = if code == 401 || code == 403 {
+                    format!(" -- set {API_KEY_ENV} to the server's API key")
+                } else {
+                    String::new()
+                };
+                Error::StoreIndex(format!(
+                    "Qdrant request {method} {url} failed with status {code}: {detail}{hint}"
+                ))
+            }
+            ureq::Error::Transport(e) => Error::StoreIndex(format!(
+                "could not reach Qdrant at {url} ({e}) -- check [index.qdrant] url \
+                 and that the server is running"
+            )),
+        }
+    }
+}
+
+impl VectorStoreBackend for QdrantBackend {
+    fn insert(&mut self, points: &[(u64, &[f32], &ChunkMeta)]) -> Result<()> {
+        let points: Vec<Value> = points
+            .iter()
+            .map(|&(key, vector, chunk)| json!({ "id": key, "vector": vector, "payload": chunk }))
+            .collect();
+        self.request(
+            "PUT",
+            "/points?wait=true",
+            Some(json!({ "points": points })),
+        )?;
+        Ok(())
+    }
+
+    fn search(
+        &self,
+        vector: &[f32],
+        limit: usize,
+        filter: &SearchFilter<'_>,
+    ) -> Result<Vec<(u64, f32)>> {
+        let params =
+            (self.expansion_search > 0).then(|| json!({ "hnsw_ef": self.expansion_search }));
+        self.search_points(vector, limit, filter, params)
+    }
+
+    fn search_exact(
+        &self,
+        vector: &[f32],
+        limit: usize,
+        filter: &SearchFilter<'_>,
+    ) -> Result<Vec<(u64, f32)>> {
+        self.search_points(vector, limit, filter, Some(json!({ "exact": true })))
+    }
+
+    fn hold_vectors(&mut self, _keys: &[u64], _max: usize) -> Result<()> {
+        Ok(())
+    }
+
+    fn delete(&mut self, keys: &[u64]) -> Result<()> {
+        self.request(
+            "POST",
+            "/points/delete?wait=true",
+            Some(json!({ "points": keys })),
+        )?;
+        Ok(())
+    }
+
+    fn vectors(&self, keys: &[u64]) -> Result<Vec<(u64, Vec<f32>)>> {
+        let mut vectors = Vec::with_capacity(keys.len());
+        for page in keys.chunks(SCROLL_PAGE) {
+            let body = json!({ "ids": page, "with_payload": false, "with_vector": true });
+            let reply = self.request("POST", "/points", Some(body))?;
+            for point in reply["result"].as_array().into_iter().flatten() {
+                let Some(key) = point["id"].as_u64() else {
+                    continue;
+                };
+                let vector: Vec<f32> =
+                    serde_json::from_value(point["vector"].clone()).map_err(Error::StoreSerde)?;
+                vectors.push((key, vector));
+            }
+        }
+        Ok(vectors)
+    }
+
+    fn list(&self) -> Result<Option<HashMap<u64, ChunkMeta>>> {
+        let mut chunks = HashMap::new();
+        let mut offset = Value::Null;
+        loop {
+            let body = json!({
+                "limit": SCROLL_PAGE,
+                "offset": offset,
+                "with_payload": true,
+                "with_vector": false,
+            });
+            let reply = self.request("POST", "/points/scroll", Some(body))?;
+            for point in reply["result"]["points"].as_array().into_iter().flatten() {
+                let Some(key) = point["id"].as_u64() else {
+                    continue;
+                };
+                let chunk =
+                    serde_json::from_value(point["payload"].clone()).map_err(Error::StoreSerde)?;
+                chunks.insert(key, chunk);
+            }
+            offset = reply["result"]["next_page_offset"].clone();
+            if offset.is_null() {
+                return Ok(Some(chunks));
+            }
+        }
+    }
+
+    fn keys(&self, _next_key: u64) -> Result<Option<HashSet<u64>>> {
+        // Chunks are listed from the points themselves, so they always agree
+        Ok(None)
+    }
+
+    fn save(&self, _dir: &Path) -> Result<()> {
+        // Every write already waited for Qdrant to apply it
+        Ok(())
+    }
+
+    fn settings(&self) -> IndexSettings {
+        IndexSettings {
+            backend: Backend::Qdrant,
+            metric: self.metric,
+            dimensions: self.dimensions,
+            connectivity: self.connectivity,
+            expansion_add: self.expansion_add,
+            expansion_search: self.expansion_search,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::dedup::Copies;
+    use super::*;
+
+    #[test]
+    fn scores_become_usearch_distances() {
+        assert!((score_to_distance(Metric::Cos, 0.75) - 0.25).abs() < 1e-6);
+        assert!((score_to_distance(Metric::Ip, 1.0)).abs() < 1e-6);
+        assert!((score_to_distance(Metric::L2sq, 3.0) - 9.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn filters_map_to_qdrant_conditions() {
+        let chunks = HashMap::new();
+        let copies = Copies::default();
+        let unfiltered = SearchFilter {
+            keys: None,
+            language: None,
+            kind: None,
+            namespaces: &Namespace::ALL,
+            modified: Modified::default(),
+            platform: None,
+            owner: None,
+            module: None,
+            space: None,
+            chunks: &chunks,
+            copies: &copies,
+        };
+        assert_eq!(filter_json(&unfiltered), None);
+
+        let keys = HashSet::from([7]);
+        let filter = SearchFilter {
+            keys: Some(&keys),
+            language: Some("go"),
+            kind: Some("commit"),
+            namespaces: &Namespace::ALL,
+            modified: Modified::default(),
+            platform: None,
+            owner: None,
+            module: Some("acme.billing"),
+            space: Some(Space::Current("v2")),
+            chunks: &chunks,
+            copies: &copies,
+        };
+        assert_eq!(
+            filter_json(&filter),
+            Some(json!({ "must": [
+                { "has_id": [7] },
+                { "key": "language", "match": { "value": "go" } },
+                { "key": "symbol_kind", "match": { "value": "commit" } },
+                { "key": "module", "match": { "value": "acme.billing" } },
+                { "should": [
+                    { "key": "model", "match": { "value": "v2" } },
+                    { "is_empty": { "key": "model" } },
+                ] },
+            ] }))
+        );
+
+        let project = SearchFilter {
+            keys: None,
+            language: None,
+            kind: None,
+            namespaces: &[Namespace::Code, Namespace::Docs, Namespace::Notes],
+            modified: Modified {
+                after: Some(1_700_000_000),
+                before: None,
+            },
+            platform: Some("linux"),
+            owner: None,
+            module: None,
+            space: None,
+            chunks: &chunks,
+            copies: &copies,
+        };
+        assert_eq!(
+            filter_json(&project),
+            Some(json!({
+                "must": [
+                    { "key": "last_modified", "range": { "gte": 1_700_000_000 } },
+                ],
+                "must_not": [
+                    { "key": "excluded_platforms", "match": { "value": "linux" } },
+                    { "key": "namespace", "match": { "any": ["commits", "dependencies"] } },
+                ],
+            }))
+        );
+
+        let notes = SearchFilter {
+            namespaces: &[Namespace::Notes],
+            ..unfiltered
+        };
+        assert_eq!(
+            filter_json(&notes),
+            Some(json!({ "must": [
+                { "key": "namespace", "match": { "any": ["notes"] } },
+            ] }))
+        );
+
+        let earlier = SearchFilter {
+            space: Some(Space::Earlier("v1")),
+            ..unfiltered
+        };
+        assert_eq!(
+            filter_json(&earlier),
+            Some(json!({ "must": [
+                { "key": "model", "match": { "value": "v1" } },
+            ] }))
+        );
+    }
+
+    #[test]
+    fn unreachable_server_is_reported() {
+        let config = IndexConfig {
+            backend: Backend::Qdrant,
+            qdrant: crate::config::QdrantConfig {
+                // Port 9 (discard) is never a Qdrant server
+                url: "http://127.0.0.1:9".to_string(),
+                collection: "test".to_string(),
+            },
+            ..Default::default()
+        };
+        let Err(err) = QdrantBackend::connect(&config, crate::embed::EMBEDDING_DIM, false) else {
+            panic!("connected to a server that isn't there");
+        };
+        assert!(err.to_string().contains("could not reach Qdrant"), "{err}");
+    }
+}
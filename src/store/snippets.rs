@@ -0,0 +1,241 @@
+//! Chunk text read back from the indexed files.
+//!
+//! With `[index] content = "offsets"` the metadata keeps only where each
+//! chunk is in its file, so the text isn't held in memory twice. Results
+//! read it back from the file, and the most recently used chunks stay
+//! cached, since the same few tend to come up again and again. A file that
+//! isn't UTF-8 is decoded whole, since its chunks' ranges are in the text
+//! decoded from it.
+
+use std::collections::{BTreeMap, HashMap};
+use std::io::{Read, Seek, SeekFrom};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::SystemTime;
+
+use crate::encoding;
+
+/// Chunks cached when `[index] snippet_cache` is 0.
+const DEFAULT_CAPACITY: usize = 1024;
+
+/// Reads chunk text from files under a root, caching recent chunks.
+pub(super) struct Snippets {
+    root: PathBuf,
+    cache: Mutex<Lru>,
+}
+
+impl Snippets {
+    pub(super) fn new(root: PathBuf, capacity: usize) -> Self {
+        let capacity = if capacity == 0 {
+            DEFAULT_CAPACITY
+        } else {
+            capacity
+        };
+        Self {
+            root,
+            cache: Mutex::new(Lru::new(capacity)),
+        }
+    }
+
+    /// The text of chunk `key` at `range` of `file_path`, which is in
+    /// `encoding` if not UTF-8 and was last modified at `last_modified`
+    /// when indexed. Empty if the file can't be read, e.g. because it was
+    /// deleted since it was indexed.
+    pub(super) fn get(
+        &self,
+        key: u64,
+        file_path: &str,
+        range: &Range<usize>,
+        encoding: Option<&str>,
+        last_modified: i64,
+    ) -> String {
+        if let Some(text) = self.lock().get(key) {
+            return text.to_string();
+        }
+        let path = self.root.join(file_path);
+        // The range may no longer hold the chunk once its file was edited
+        let modified = std::fs::metadata(&path)
+            .and_then(|m| m.modified())
+            .ok()
+            .and_then(|m| m.duration_since(SystemTime::UNIX_EPOCH).ok())
+            .map(|m| m.as_secs() as i64);
+        if modified.is_some_and(|modified| modified > last_modified) {
+            tracing::warn!(
+                "{file_path} changed since it was indexed, so its chunk text may be out of date \
+                 -- wait for it to be reindexed, or run `reindex`"
+            );
+        }
+        let text = match encoding {
+            None => read_range(&path, range),
+            Some(_) => std::fs::read(&path).map(|bytes| {
+                let text = encoding::decode_as(bytes, encoding);
+                text.get(range.clone())
+                    .unwrap_or_default()
+                    .replace("\r\n", "\n")
+            }),
+        };
+        let text = match text {
+            Ok(text) => text,
+            Err(e) => {
+                tracing::warn!("can't read chunk text from {file_path} ({e}) -- reindex it");
+                return String::new();
+            }
+        };
+        self.lock().insert(key, Arc::from(text.as_str()));
+        text
+    }
+
+    /// The whole of `file_path`, in `encoding` if not UTF-8, uncached, or
+    /// `None` if it can't be read.
+    pub(super) fn read_file(&self, file_path: &str, encoding: Option<&str>) -> Option<String> {
+        let bytes = std::fs::read(self.root.join(file_path)).ok()?;
+        match encoding {
+            None => String::from_utf8(bytes).ok(),
+            Some(_) => Some(encoding::decode_as(bytes, encoding)),
+        }
+    }
+
+    /// The directory the indexed files are read from.
+    pub(super) fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Drop all cached text, for an index reloaded from disk.
+    pub(super) fn clear(&self) {
+        let mut cache = self.lock();
+        *cache = Lru::new(cache.capacity);
+    }
+
+    /// Drop any cached text for `key`, whose chunk changed or is gone.
+    pub(super) fn forget(&self, key: u64) {
+        self.lock().remove(key);
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Lru> {
+        self.cache.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Bytes `range` of the file at `path`, as text with CRLF line endings as
+/// LF, the way the chunker saw it.
+fn read_range(path: &std::path::Path, range: &Range<usize>) -> std::io::Result<String> {
+    let mut file = std::fs::File::open(path)?;
+    file.seek(SeekFrom::Start(range.start as u64))?;
+    let mut bytes = Vec::with_capacity(range.len());
+    file.take(range.len() as u64).read_to_end(&mut bytes)?;
+    Ok(String::from_utf8_lossy(&bytes).replace("\r\n", "\n"))
+}
+
+/// Least-recently-used cache of chunk text by key.
+struct Lru {
+    capacity: usize,
+    /// Incremented on every use; a larger tick is more recent.
+    tick: u64,
+    entries: HashMap<u64, (u64, Arc<str>)>,
+    /// Keys by the tick of their last use, oldest first.
+    order: BTreeMap<u64, u64>,
+}
+
+impl Lru {
+    fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            tick: 0,
+            entries: HashMap::new(),
+            order: BTreeMap::new(),
+        }
+    }
+
+    fn get(&mut self, key: u64) -> Option<Arc<str>> {
+        self.tick += 1;
+        let (tick, text) = self.entries.get_mut(&key)?;
+        self.order.remove(tick);
+        *tick = self.tick;
+        self.order.insert(self.tick, key);
+        Some(text.clone())
+    }
+
+    fn insert(&mut self, key: u64, text: Arc<str>) {
+        self.remove(key);
+        while self.entries.len() >= self.capacity {
+            let Some((_, oldest)) = self.order.pop_first() else {
+                break;
+            };
+            self.entries.remove(&oldest);
+        }
+        self.tick += 1;
+        self.order.insert(self.tick, key);
+        self.entries.insert(key, (self.tick, text));
+    }
+
+    fn remove(&mut self, key: u64) {
+        if let Some((tick, _)) = self.entries.remove(&key) {
+            self.order.remove(&tick);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn least_recently_used_is_evicted() {
+        let mut lru = Lru::new(2);
+        lru.insert(1, Arc::from("one"));
+        lru.insert(2, Arc::from("two"));
+        assert_eq!(lru.get(1).as_deref(), Some("one"));
+        lru.insert(3, Arc::from("three"));
+        assert_eq!(lru.get(2), None);
+        assert_eq!(lru.get(1).as_deref(), Some("one"));
+        assert_eq!(lru.get(3).as_deref(), Some("three"));
+
+        lru.remove(1);
+        assert_eq!(lru.get(1), None);
+        assert_eq!(lru.entries.len(), lru.order.len());
+    }
+
+    #[test]
+    fn reads_ranges_and_caches_them() {
+        let root = TempDir::new().unwrap();
+        std::fs::write(root.path().join("a.go"), "package a\n\nfunc A() {}\n").unwrap();
+        let snippets = Snippets::new(root.path().to_path_buf(), 0);
+        assert_eq!(
+            snippets.get(7, "a.go", &(11..22), None, i64::MAX),
+            "func A() {}"
+        );
+
+        // Served from the cache until forgotten
+        std::fs::write(root.path().join("a.go"), "package a\n\nfunc B() {}\n").unwrap();
+        assert_eq!(
+            snippets.get(7, "a.go", &(11..22), None, i64::MAX),
+            "func A() {}"
+        );
+        snippets.forget(7);
+        assert_eq!(
+            snippets.get(7, "a.go", &(11..22), None, i64::MAX),
+            "func B() {}"
+        );
+
+        assert_eq!(snippets.get(8, "gone.go", &(0..4), None, 0), "");
+    }
+
+    #[test]
+    fn ranges_of_decoded_files_index_the_decoded_text() {
+        let root = TempDir::new().unwrap();
+        // "é" is one byte in Latin-1 but two in the decoded text
+        std::fs::write(root.path().join("a.py"), b"# caf\xe9\r\ndef a(): pass\r\n").unwrap();
+        let snippets = Snippets::new(root.path().to_path_buf(), 0);
+        assert_eq!(
+            snippets.get(1, "a.py", &(0..16), Some("windows-1252"), i64::MAX),
+            "# café\ndef a()"
+        );
+        assert_eq!(
+            snippets.read_file("a.py", Some("windows-1252")).as_deref(),
+            Some("# café\r\ndef a(): pass\r\n")
+        );
+        assert_eq!(snippets.read_file("a.py", None), None);
+    }
+}
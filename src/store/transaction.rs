@@ -0,0 +1,330 @@
+//! Changes to the store buffered and applied together.
+//!
+//! Each write to the store takes its locks and saves the index and the
+//! metadata to disk. A [`Transaction`] collects any number of inserts,
+//! file replacements, and deletions and applies them under one taking of
+//! the locks, so a search sees none of them or all of them, followed by a
+//! single save, during which searches and lookups may already read the
+//! new state. If any write fails, the ones before it are undone and the
+//! store is left as it was. Dropping a transaction without committing it
+//! discards it.
+//!
+//! Metadata lookups such as [`VectorStore::chunk_count`] wait while the
+//! writes are applied, since they share the metadata lock searches take
+//! before the backend's; they go ahead during the save.
+
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+use super::moves::RemovedFile;
+use super::{
+    ChunkMeta, ChunkRow, IndexSettings, Metadata, SearchFilter, VectorStore, VectorStoreBackend,
+    lock_wait,
+};
+use crate::error::{Error, Result};
+
+enum Op {
+    Insert(Vec<ChunkRow>),
+    ReplaceFile(String, Vec<ChunkRow>),
+    DeleteFile(String),
+    DeleteChunks(Vec<u64>),
+}
+
+impl Op {
+    /// What the operation does, for the error a read-only store gives.
+    fn action(&self) -> &'static str {
+        match self {
+            Op::Insert(_) => "inserting chunks",
+            Op::ReplaceFile(..) => "replacing chunks",
+            Op::DeleteFile(_) | Op::DeleteChunks(_) => "deleting chunks",
+        }
+    }
+}
+
+/// Writes to a [`VectorStore`], applied in order by
+/// [`Transaction::commit`].
+#[must_use = "a transaction does nothing until committed"]
+pub struct Transaction<'a> {
+    store: &'a VectorStore,
+    ops: Vec<Op>,
+}
+
+impl<'a> Transaction<'a> {
+    pub(super) fn new(store: &'a VectorStore) -> Self {
+        Self {
+            store,
+            ops: Vec::new(),
+        }
+    }
+
+    /// Insert `rows`, as [`VectorStore::insert`] does.
+    pub fn insert(&mut self, rows: Vec<ChunkRow>) {
+        if !rows.is_empty() {
+            self.ops.push(Op::Insert(rows));
+        }
+    }
+
+    /// Replace the chunks of `file_path` with `rows`, as
+    /// [`VectorStore::replace_file`] does.
+    pub fn replace_file(&mut self, file_path: &str, rows: Vec<ChunkRow>) {
+        self.ops.push(Op::ReplaceFile(file_path.to_string(), rows));
+    }
+
+    /// Remove the chunks of `file_path`, as [`VectorStore::delete_file`]
+    /// does, keeping them a while in case the file moved.
+    pub fn delete_file(&mut self, file_path: &str) {
+        self.ops.push(Op::DeleteFile(file_path.to_string()));
+    }
+
+    /// Remove the chunks stored under `keys`, as
+    /// [`VectorStore::delete_chunks`] does.
+    pub fn delete_chunks(&mut self, keys: &[u64]) {
+        self.ops.push(Op::DeleteChunks(keys.to_vec()));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+
+    /// Apply the buffered writes in order and save the store once, if any
+    /// changed it. Returns the keys inserted rows were stored under, in
+    /// the order they were given. If a write fails, none are kept.
+    pub async fn commit(self) -> Result<Vec<u64>> {
+        let store = self.store;
+        let Some(first) = self.ops.first() else {
+            return Ok(Vec::new());
+        };
+        if store.read_only {
+            return Err(Error::ReadOnly(first.action()));
+        }
+        // Deletions are let through, since they free space
+        let adds = self.ops.iter().any(|op| match op {
+            Op::Insert(rows) | Op::ReplaceFile(_, rows) => !rows.is_empty(),
+            Op::DeleteFile(_) | Op::DeleteChunks(_) => false,
+        });
+        if adds {
+            store.check_space()?;
+        }
+
+        let mut meta = lock_wait("meta", store.meta.write()).await;
+        let mut backend = lock_wait("backend", store.backend.write()).await;
+        let mut file_vectors = lock_wait("file_vectors", store.file_vectors.write()).await;
+        let snapshot = meta.clone();
+        let mut journal = Journal::new(backend.as_mut());
+        let applied = apply(store, self.ops, &mut meta, &mut journal, &mut file_vectors);
+        // The space left was checked before, but the batch may not fit in it
+        let applied = applied.and_then(|applied| {
+            if adds {
+                store.check_space_after(&snapshot, &meta, &journal)?;
+            }
+            Ok(applied)
+        });
+        let Applied {
+            keys,
+            removed_files,
+            changed,
+        } = match applied {
+            Ok(applied) => applied,
+            Err(e) => {
+                journal.roll_back(&snapshot.chunks);
+                *meta = snapshot;
+                // Means of the vectors rolled back; recomputed when needed
+                file_vectors.clear();
+                return Err(e);
+            }
+        };
+        if !removed_files.is_empty() {
+            let mut removed = store.removed.lock().await;
+            for file in removed_files {
+                removed.push(file);
+            }
+        }
+
+        drop(file_vectors);
+        if changed {
+            store.persist_written(backend, meta).await?;
+        }
+        Ok(keys)
+    }
+}
+
+/// What [`apply`] did.
+struct Applied {
+    /// The keys inserted rows were stored under, in order.
+    keys: Vec<u64>,
+    /// Files deleted, to keep a while in case they moved.
+    removed_files: Vec<RemovedFile>,
+    /// Whether any write changed the store.
+    changed: bool,
+}
+
+/// Apply `ops` in order with the store's locks held, stopping at the
+/// first that fails.
+fn apply(
+    store: &VectorStore,
+    ops: Vec<Op>,
+    meta: &mut Metadata,
+    backend: &mut dyn VectorStoreBackend,
+    file_vectors: &mut HashMap<String, Vec<f32>>,
+) -> Result<Applied> {
+    let mut keys = Vec::new();
+    let mut removed_files: Vec<RemovedFile> = Vec::new();
+    let mut changed = false;
+    for op in ops {
+        match op {
+            Op::Insert(rows) => {
+                changed = true;
+                keys.extend(store.insert_locked(meta, backend, file_vectors, rows)?);
+            }
+            Op::ReplaceFile(file_path, rows) => {
+                let removed = store.remove_file_locked(meta, backend, &file_path)?;
+                if !removed && rows.is_empty() {
+                    continue;
+                }
+                changed = true;
+                file_vectors.remove(&file_path);
+                keys.extend(store.insert_locked(meta, backend, file_vectors, rows)?);
+            }
+            Op::DeleteFile(file_path) => {
+                let kept = store.removed_file_locked(meta, backend, &file_path)?;
+                if store.remove_file_locked(meta, backend, &file_path)? {
+                    changed = true;
+                    file_vectors.remove(&file_path);
+                    removed_files.extend(kept);
+                }
+            }
+            Op::DeleteChunks(keys) => {
+                let keys: Vec<u64> = keys
+                    .into_iter()
+                    .filter(|key| meta.chunks.contains_key(key))
+                    .collect();
+                if keys.is_empty() {
+                    continue;
+                }
+                changed = true;
+                for chunk in store.remove_keys_locked(meta, backend, &keys)? {
+                    file_vectors.remove(&chunk.file_path);
+                }
+            }
+        }
+    }
+    Ok(Applied {
+        keys,
+        removed_files,
+        changed,
+    })
+}
+
+/// A write undone by [`Journal::roll_back`].
+enum Undo {
+    /// Points inserted under these keys, or tried to be.
+    Inserted(Vec<u64>),
+    /// Points deleted, with the vectors they had.
+    Deleted(Vec<(u64, Vec<f32>)>),
+}
+
+/// A backend that records the writes made through it, so a commit that
+/// fails partway can undo them.
+struct Journal<'b> {
+    backend: &'b mut dyn VectorStoreBackend,
+    undo: Vec<Undo>,
+}
+
+impl<'b> Journal<'b> {
+    fn new(backend: &'b mut dyn VectorStoreBackend) -> Self {
+        Self {
+            backend,
+            undo: Vec::new(),
+        }
+    }
+
+    /// Undo the recorded writes, latest first, restoring deleted points
+    /// with their metadata from `chunks` as it was before the commit. A
+    /// failure here is logged: the vectors and the metadata then disagree
+    /// until the store is next opened, which reconciles them.
+    fn roll_back(self, chunks: &HashMap<u64, ChunkMeta>) {
+        for undo in self.undo.into_iter().rev() {
+            let undone = match undo {
+                Undo::Inserted(keys) => self.backend.delete(&keys),
+                Undo::Deleted(vectors) => {
+                    let keys: Vec<u64> = vectors.iter().map(|(key, _)| *key).collect();
+                    // Inserted and deleted again within the commit: gone before
+                    let points: Vec<(u64, &[f32], &ChunkMeta)> = vectors
+                        .iter()
+                        .filter_map(|(key, vector)| {
+                            Some((*key, vector.as_slice(), chunks.get(key)?))
+                        })
+                        .collect();
+                    // A delete that failed partway left some of them
+                    self.backend
+                        .delete(&keys)
+                        .and_then(|()| self.backend.insert(&points))
+                }
+            };
+            if let Err(e) = undone {
+                tracing::error!(
+                    "undoing a failed write to the index failed too ({e}); searches may miss \
+                     or mismatch chunks until the index is reopened, which repairs it"
+                );
+                return;
+            }
+        }
+    }
+}
+
+impl VectorStoreBackend for Journal<'_> {
+    fn insert(&mut self, points: &[(u64, &[f32], &ChunkMeta)]) -> Result<()> {
+        self.undo.push(Undo::Inserted(
+            points.iter().map(|(key, ..)| *key).collect(),
+        ));
+        self.backend.insert(points)
+    }
+
+    fn search(
+        &self,
+        vector: &[f32],
+        limit: usize,
+        filter: &SearchFilter<'_>,
+    ) -> Result<Vec<(u64, f32)>> {
+        self.backend.search(vector, limit, filter)
+    }
+
+    fn search_exact(
+        &self,
+        vector: &[f32],
+        limit: usize,
+        filter: &SearchFilter<'_>,
+    ) -> Result<Vec<(u64, f32)>> {
+        self.backend.search_exact(vector, limit, filter)
+    }
+
+    fn hold_vectors(&mut self, keys: &[u64], max: usize) -> Result<()> {
+        self.backend.hold_vectors(keys, max)
+    }
+
+    fn delete(&mut self, keys: &[u64]) -> Result<()> {
+        let vectors = self.backend.vectors(keys)?;
+        self.undo.push(Undo::Deleted(vectors));
+        self.backend.delete(keys)
+    }
+
+    fn vectors(&self, keys: &[u64]) -> Result<Vec<(u64, Vec<f32>)>> {
+        self.backend.vectors(keys)
+    }
+
+    fn list(&self) -> Result<Option<HashMap<u64, ChunkMeta>>> {
+        self.backend.list()
+    }
+
+    fn keys(&self, next_key: u64) -> Result<Option<HashSet<u64>>> {
+        self.backend.keys(next_key)
+    }
+
+    fn save(&self, dir: &Path) -> Result<()> {
+        self.backend.save(dir)
+    }
+
+    fn settings(&self) -> IndexSettings {
+        self.backend.settings()
+    }
+}
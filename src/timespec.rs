@@ -0,0 +1,120 @@
+//! Points in time given to search filters: a calendar date, or an age such
+//! as `7d` for "a week ago", which is what "recently added" questions need.
+
+const HOUR: i64 = 60 * 60;
+const DAY: i64 = 24 * HOUR;
+
+/// Unix seconds for `spec`: a `YYYY-MM-DD` date (midnight UTC), an age in
+/// hours, days, or weeks before `now` (`12h`, `7d`, `2w`), or plain Unix
+/// seconds. `None` if it is none of these.
+pub fn parse(spec: &str, now: i64) -> Option<i64> {
+    let spec = spec.trim();
+    if let Ok(secs) = spec.parse::<i64>() {
+        return Some(secs);
+    }
+    if let Some(date) = parse_date(spec) {
+        return Some(date);
+    }
+    let unit = match spec.chars().last()? {
+        'h' => HOUR,
+        'd' => DAY,
+        'w' => 7 * DAY,
+        _ => return None,
+    };
+    let count: i64 = spec[..spec.len() - 1].parse().ok()?;
+    Some(now - count.checked_mul(unit)?)
+}
+
+/// Midnight UTC of a `YYYY-MM-DD` date, in Unix seconds.
+fn parse_date(spec: &str) -> Option<i64> {
+    let mut parts = spec.splitn(3, '-');
+    let year: i64 = parts.next()?.parse().ok()?;
+    let month: i64 = parts.next()?.parse().ok()?;
+    let day: i64 = parts.next()?.parse().ok()?;
+    if !(1..=12).contains(&month) || !(1..=days_in_month(year, month)).contains(&day) {
+        return None;
+    }
+    Some(days_from_civil(year, month, day) * DAY)
+}
+
+fn days_in_month(year: i64, month: i64) -> i64 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Days from 1970-01-01 to a proleptic Gregorian date (Howard Hinnant's
+/// `days_from_civil`).
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// Unix seconds `secs` as a UTC timestamp, `YYYY-MM-DDTHH:MM:SSZ`.
+pub fn format_utc(secs: i64) -> String {
+    let (days, time) = (secs.div_euclid(DAY), secs.rem_euclid(DAY));
+    let (year, month, day) = civil_from_days(days);
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}Z",
+        time / HOUR,
+        time % HOUR / 60,
+        time % 60
+    )
+}
+
+/// The date `days` after 1970-01-01 (Howard Hinnant's `civil_from_days`).
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dates_ages_and_seconds() {
+        let now = 1_700_000_000;
+        assert_eq!(parse("1970-01-01", now), Some(0));
+        assert_eq!(parse("2000-03-01", now), Some(951_868_800));
+        assert_eq!(parse("2024-02-29", now), Some(1_709_164_800));
+        assert_eq!(parse(" 7d ", now), Some(now - 7 * DAY));
+        assert_eq!(parse("2w", now), Some(now - 14 * DAY));
+        assert_eq!(parse("36h", now), Some(now - 36 * HOUR));
+        assert_eq!(parse("1699999999", now), Some(1_699_999_999));
+
+        for bad in ["", "yesterday", "2023-02-29", "2024-13-01", "7m", "d"] {
+            assert_eq!(parse(bad, now), None, "{bad}");
+        }
+    }
+
+    #[test]
+    fn utc_timestamps_round_trip_dates() {
+        assert_eq!(format_utc(0), "1970-01-01T00:00:00Z");
+        assert_eq!(format_utc(1_709_164_800 + 3_723), "2024-02-29T01:02:03Z");
+        for date in ["2000-03-01", "1999-12-31", "2100-02-28"] {
+            let secs = parse(date, 0).unwrap();
+            assert_eq!(format_utc(secs), format!("{date}T00:00:00Z"));
+        }
+    }
+}
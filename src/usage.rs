@@ -0,0 +1,326 @@
+//! How often each chunk was returned, for ranking popular chunks higher.
+
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+
+use crate::atomicfile;
+use crate::error::{Error, Result};
+use crate::store::SearchResult;
+
+/// Counters kept next to the index, by chunk key.
+pub const USAGE_FILE: &str = "usage.json";
+
+/// Access counters for a single chunk.
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize)]
+struct UsageCounts {
+    /// Times the chunk appeared in search results.
+    returned: u64,
+    /// Times the chunk's file was read after the chunk was returned.
+    read: u64,
+}
+
+#[derive(Default)]
+struct UsageState {
+    counts: HashMap<String, UsageCounts>,
+    /// Chunks returned during this session that have not been read yet,
+    /// keyed by file path.
+    pending: HashMap<String, HashSet<String>>,
+}
+
+/// Tracks which chunks are returned and subsequently read, persisting
+/// lightweight counters next to the index so ranking can learn which parts
+/// of the repo are actually relevant.
+#[derive(Clone)]
+pub struct UsageTracker {
+    state: Arc<Mutex<UsageState>>,
+    path: PathBuf,
+    read_only: bool,
+}
+
+impl UsageTracker {
+    /// Load counters from `db_path`, starting empty if none were saved.
+    pub async fn load(db_path: &Path) -> Result<Self> {
+        Self::open(db_path, false).await
+    }
+
+    /// Load counters from `db_path` but keep updates in memory only, for
+    /// indexes opened with `--read-only`.
+    pub async fn load_read_only(db_path: &Path) -> Result<Self> {
+        Self::open(db_path, true).await
+    }
+
+    async fn open(db_path: &Path, read_only: bool) -> Result<Self> {
+        let path = db_path.join(USAGE_FILE);
+        let mut counts: HashMap<String, UsageCounts> = if path.exists() {
+            let data = tokio::fs::read_to_string(&path)
+                .await
+                .map_err(|e| Error::StoreIo {
+                    context: format!("reading {}", path.display()),
+                    source: e,
+                })?;
+            serde_json::from_str(&data).unwrap_or_else(|e| {
+                tracing::warn!(
+                    "ignoring unreadable usage counters in {} ({e}); starting over",
+                    path.display()
+                );
+                HashMap::new()
+            })
+        } else {
+            HashMap::new()
+        };
+        // Counters were once keyed by path#symbol, which can't be matched to
+        // a chunk id
+        let before = counts.len();
+        counts.retain(|key, _| key.parse::<u64>().is_ok());
+        if counts.len() < before {
+            tracing::info!(
+                "dropped {} usage counters in {} saved under an older key format",
+                before - counts.len(),
+                path.display()
+            );
+        }
+
+        Ok(Self {
+            state: Arc::new(Mutex::new(UsageState {
+                counts,
+                pending: HashMap::new(),
+            })),
+            path,
+            read_only,
+        })
+    }
+
+    /// Record that `results` were returned to the client.
+    pub async fn record_returned(&self, results: &[SearchResult]) -> Result<()> {
+        if results.is_empty() {
+            return Ok(());
+        }
+
+        let mut state = self.state.lock().await;
+        for result in results {
+            let key = usage_key(result);
+            state.counts.entry(key.clone()).or_default().returned += 1;
+            state
+                .pending
+                .entry(result.file_path.clone())
+                .or_default()
+                .insert(key);
+        }
+        self.persist_locked(&state).await
+    }
+
+    /// Record that `file_path` was read, crediting every chunk from that file
+    /// returned earlier in the session.
+    pub async fn record_read(&self, file_path: &str) -> Result<()> {
+        let mut state = self.state.lock().await;
+        let Some(keys) = state.pending.remove(file_path) else {
+            return Ok(());
+        };
+        for key in keys {
+            state.counts.entry(key).or_default().read += 1;
+        }
+        self.persist_locked(&state).await
+    }
+
+    /// Lower the distance of frequently-read chunks by up to `boost` and
+    /// re-sort. A `boost` of zero leaves the results untouched.
+    pub async fn apply_boost(&self, results: &mut [SearchResult], boost: f32) {
+        if boost <= 0.0 {
+            return;
+        }
+
+        let state = self.state.lock().await;
+        for result in results.iter_mut() {
+            let reads = state.counts.get(&usage_key(result)).map_or(0, |c| c.read) as f32;
+            // Saturates towards 1.0 so a handful of reads matters but a
+            // runaway favourite can't dominate every query.
+            let popularity = reads / (reads + 1.0);
+            result.distance -= boost * popularity;
+        }
+        results.sort_by(SearchResult::rank_cmp);
+    }
+
+    /// Persist counters to disk. Caller must hold the state lock.
+    async fn persist_locked(&self, state: &UsageState) -> Result<()> {
+        if self.read_only {
+            return Ok(());
+        }
+        let json = serde_json::to_string(&state.counts).map_err(Error::StoreSerde)?;
+        atomicfile::write(&self.path, json)
+            .await
+            .map_err(|e| Error::StoreIo {
+                context: format!("writing {}", self.path.display()),
+                source: e,
+            })
+    }
+}
+
+/// Counters are keyed by the chunk's store id, which carries over when a
+/// file is re-indexed, so edits and renames keep their history.
+fn usage_key(result: &SearchResult) -> String {
+    result.id.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn result(id: u64, file_path: &str, symbol: &str, distance: f32) -> SearchResult {
+        SearchResult {
+            id,
+            file_path: file_path.to_string(),
+            content: format!("fn {symbol}() {{}}"),
+            symbol_name: Some(symbol.to_string()),
+            symbol_kind: Some("function_item".to_string()),
+            doc: None,
+            signature: None,
+            build: None,
+            owners: Vec::new(),
+            module: None,
+            language: "rust".to_string(),
+            start_line: 1,
+            end_line: 1,
+            distance,
+            duplicates: Vec::new(),
+            approximate: false,
+        }
+    }
+
+    /// Return `hit` and then read its file, `times` times over.
+    async fn return_and_read(usage: &UsageTracker, hit: &SearchResult, times: usize) {
+        for _ in 0..times {
+            usage
+                .record_returned(std::slice::from_ref(hit))
+                .await
+                .unwrap();
+            usage.record_read(&hit.file_path).await.unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn boost_promotes_frequently_read_chunks() {
+        let tmp = TempDir::new().unwrap();
+        let usage = UsageTracker::load(tmp.path()).await.unwrap();
+        let popular = result(1, "src/popular.rs", "hot", 0.30);
+        return_and_read(&usage, &popular, 5).await;
+
+        let mut results = vec![result(2, "src/other.rs", "cold", 0.25), popular];
+        usage.apply_boost(&mut results, 0.1).await;
+        assert_eq!(results[0].symbol_name.as_deref(), Some("hot"));
+    }
+
+    #[tokio::test]
+    async fn read_without_return_is_ignored() {
+        let tmp = TempDir::new().unwrap();
+        let usage = UsageTracker::load(tmp.path()).await.unwrap();
+        usage.record_read("src/popular.rs").await.unwrap();
+
+        let mut results = vec![
+            result(2, "src/other.rs", "cold", 0.25),
+            result(1, "src/popular.rs", "hot", 0.30),
+        ];
+        usage.apply_boost(&mut results, 0.1).await;
+        assert_eq!(results[0].symbol_name.as_deref(), Some("cold"));
+        assert_eq!(results[1].distance, 0.30);
+    }
+
+    #[tokio::test]
+    async fn repeated_read_is_credited_once() {
+        let tmp = TempDir::new().unwrap();
+        let usage = UsageTracker::load(tmp.path()).await.unwrap();
+        let hit = result(3, "src/auth.rs", "login", 0.30);
+        return_and_read(&usage, &hit, 1).await;
+        usage.record_read("src/auth.rs").await.unwrap();
+
+        // One credited read gives popularity 0.5
+        let mut results = vec![hit];
+        usage.apply_boost(&mut results, 0.1).await;
+        assert!((results[0].distance - 0.25).abs() < 1e-6);
+    }
+
+    #[tokio::test]
+    async fn counters_survive_reload() {
+        let tmp = TempDir::new().unwrap();
+        let popular = result(1, "src/popular.rs", "hot", 0.30);
+        {
+            let usage = UsageTracker::load(tmp.path()).await.unwrap();
+            return_and_read(&usage, &popular, 5).await;
+        }
+
+        let usage = UsageTracker::load(tmp.path()).await.unwrap();
+        let mut results = vec![result(2, "src/other.rs", "cold", 0.25), popular];
+        usage.apply_boost(&mut results, 0.1).await;
+        assert_eq!(results[0].symbol_name.as_deref(), Some("hot"));
+    }
+
+    #[tokio::test]
+    async fn truncated_counters_start_over() {
+        let tmp = TempDir::new().unwrap();
+        std::fs::write(tmp.path().join(USAGE_FILE), r#"{"1":{"returned":3,"re"#).unwrap();
+        let usage = UsageTracker::load(tmp.path()).await.unwrap();
+        let popular = result(1, "src/popular.rs", "hot", 0.30);
+        return_and_read(&usage, &popular, 1).await;
+
+        let saved = std::fs::read_to_string(tmp.path().join(USAGE_FILE)).unwrap();
+        assert_eq!(saved, r#"{"1":{"returned":1,"read":1}}"#);
+    }
+
+    #[tokio::test]
+    async fn counters_under_old_keys_are_dropped() {
+        let tmp = TempDir::new().unwrap();
+        std::fs::write(
+            tmp.path().join(USAGE_FILE),
+            r#"{"src/popular.rs#hot":{"returned":9,"read":9},"1":{"returned":2,"read":2}}"#,
+        )
+        .unwrap();
+        let usage = UsageTracker::load(tmp.path()).await.unwrap();
+        return_and_read(&usage, &result(1, "src/popular.rs", "hot", 0.30), 1).await;
+
+        let saved = std::fs::read_to_string(tmp.path().join(USAGE_FILE)).unwrap();
+        assert_eq!(saved, r#"{"1":{"returned":3,"read":3}}"#);
+    }
+
+    #[tokio::test]
+    async fn renamed_chunk_keeps_counters() {
+        let tmp = TempDir::new().unwrap();
+        let usage = UsageTracker::load(tmp.path()).await.unwrap();
+        return_and_read(&usage, &result(1, "src/popular.rs", "hot", 0.30), 5).await;
+
+        // Same chunk id after a reindex that renamed the symbol
+        let renamed = result(1, "src/popular.rs", "warm", 0.30);
+        let mut results = vec![result(2, "src/other.rs", "cold", 0.25), renamed];
+        usage.apply_boost(&mut results, 0.1).await;
+        assert_eq!(results[0].symbol_name.as_deref(), Some("warm"));
+    }
+
+    #[tokio::test]
+    async fn zero_boost_keeps_order() {
+        let tmp = TempDir::new().unwrap();
+        let usage = UsageTracker::load(tmp.path()).await.unwrap();
+        let popular = result(1, "src/popular.rs", "hot", 0.30);
+        return_and_read(&usage, &popular, 5).await;
+
+        let mut results = vec![result(2, "src/other.rs", "cold", 0.25), popular];
+        usage.apply_boost(&mut results, 0.0).await;
+        assert_eq!(results[0].symbol_name.as_deref(), Some("cold"));
+        assert_eq!(results[1].distance, 0.30);
+    }
+
+    #[tokio::test]
+    async fn read_only_tracker_never_writes() {
+        let tmp = TempDir::new().unwrap();
+        let usage = UsageTracker::load_read_only(tmp.path()).await.unwrap();
+        let hit = result(3, "src/auth.rs", "login", 0.30);
+        return_and_read(&usage, &hit, 3).await;
+
+        assert!(!tmp.path().join(USAGE_FILE).exists());
+        let mut results = vec![hit];
+        usage.apply_boost(&mut results, 0.1).await;
+        assert!(results[0].distance < 0.30);
+    }
+}